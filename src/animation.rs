@@ -0,0 +1,299 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use exr::prelude::{read_first_rgba_layer_from_file, Vec2};
+use log::error;
+
+/// Playback speeds offered in the GUI, relative to the sequence frame rate
+pub(crate) const PLAYBACK_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// Playback controls of an EXR sequence, set from the GUI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PlaybackSettings {
+    pub playing: bool,
+    // Start over after the last frame instead of stopping
+    pub looping: bool,
+    pub speed: f32,
+    // Frames per second at 1x speed
+    pub fps: f32,
+    // Memory used by the decoded frames, the oldest are dropped past it
+    pub max_cache_mb: u32,
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        Self {
+            playing: false,
+            looping: true,
+            speed: 1.0,
+            fps: 24.0,
+            max_cache_mb: 512,
+        }
+    }
+}
+
+/// State of the sequence player, shown in the GUI
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PlaybackStatus {
+    pub frame_count: usize,
+    pub current_frame: usize,
+    pub cached_frames: usize,
+    pub playing: bool,
+    // Waiting for the current frame to be read from disk
+    pub loading: bool,
+}
+
+/// Plays back the EXR files of a directory, sorted by frame number.
+/// Decoded frames are kept in a cache of limited size, the missing ones are read
+/// on a background thread while playback waits for them.
+pub(crate) struct ExrSequencePlayer {
+    frames: Vec<PathBuf>,
+    // Size of the framebuffer the frames are resized to
+    width: usize,
+    height: usize,
+    // Linear RGBA pixels of each frame, if they are loaded
+    cache: Vec<Option<Vec<f32>>>,
+    // Cached frames, least recently loaded first
+    cache_order: VecDeque<usize>,
+    settings: PlaybackSettings,
+    current_frame: usize,
+    // Frame last written to the framebuffer
+    shown_frame: Option<usize>,
+    // Seconds spent on the current frame, at the playback speed
+    frame_time: f32,
+    last_tick: Option<Instant>,
+    loading: Option<(usize, JoinHandle<anyhow::Result<Vec<f32>>>)>,
+}
+
+impl ExrSequencePlayer {
+    /// Find the EXR files of `directory`. Frames are resized to `width` x `height`.
+    pub(crate) fn open(
+        directory: impl AsRef<Path>,
+        width: usize,
+        height: usize,
+        settings: PlaybackSettings,
+    ) -> anyhow::Result<Self> {
+        let frames = find_sequence_frames(directory.as_ref())?;
+        if frames.is_empty() {
+            anyhow::bail!("No EXR files in {}", directory.as_ref().display());
+        }
+        Ok(Self {
+            cache: vec![None; frames.len()],
+            frames,
+            width,
+            height,
+            cache_order: VecDeque::new(),
+            settings,
+            current_frame: 0,
+            shown_frame: None,
+            frame_time: 0.0,
+            last_tick: None,
+            loading: None,
+        })
+    }
+
+    pub(crate) fn status(&self) -> PlaybackStatus {
+        PlaybackStatus {
+            frame_count: self.frames.len(),
+            current_frame: self.current_frame,
+            cached_frames: self.cache_order.len(),
+            playing: self.settings.playing,
+            loading: self.cache[self.current_frame].is_none(),
+        }
+    }
+
+    pub(crate) fn set_settings(&mut self, settings: PlaybackSettings) {
+        if settings.playing && !self.settings.playing {
+            // Don't count the time spent paused
+            self.last_tick = None;
+            // Playing from the last frame without looping starts over
+            if !settings.looping && self.current_frame + 1 == self.frames.len() {
+                self.seek(0);
+            }
+        }
+        self.settings = settings;
+        self.evict();
+    }
+
+    /// Jump to a frame, clamped to the sequence
+    pub(crate) fn seek(&mut self, frame: usize) {
+        self.current_frame = frame.min(self.frames.len() - 1);
+        self.frame_time = 0.0;
+    }
+
+    /// Advance playback by the time elapsed since the last call and collect frames read
+    /// from disk. Returns the pixels of the current frame when it needs to be displayed.
+    pub(crate) fn tick(&mut self) -> Option<&[f32]> {
+        let now = Instant::now();
+        let elapsed = self
+            .last_tick
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.last_tick = Some(now);
+
+        // Playback waits on frames that are still being read
+        if self.settings.playing && self.cache[self.current_frame].is_some() {
+            self.advance(elapsed);
+        }
+
+        if let Some((frame, handle)) = self.loading.take() {
+            if handle.is_finished() {
+                let pixels = match handle.join() {
+                    Ok(Ok(pixels)) => pixels,
+                    Ok(Err(e)) => {
+                        error!("Failed to read {}: {e:?}", self.frames[frame].display());
+                        // Shown black rather than retried on every tick
+                        vec![0.0; self.width * self.height * 4]
+                    }
+                    Err(_) => {
+                        error!(
+                            "The thread reading {} panicked",
+                            self.frames[frame].display()
+                        );
+                        vec![0.0; self.width * self.height * 4]
+                    }
+                };
+                self.insert(frame, pixels);
+            } else {
+                self.loading = Some((frame, handle));
+            }
+        }
+
+        if self.loading.is_none() {
+            // Read the current frame, or the next one ahead of time
+            let next = (self.current_frame + 1) % self.frames.len();
+            let missing = [self.current_frame, next]
+                .into_iter()
+                .find(|&frame| self.cache[frame].is_none());
+            if let Some(frame) = missing {
+                self.load(frame);
+            }
+        }
+
+        if self.shown_frame == Some(self.current_frame) {
+            return None;
+        }
+        let pixels = self.cache[self.current_frame].as_deref()?;
+        self.shown_frame = Some(self.current_frame);
+        Some(pixels)
+    }
+
+    fn advance(&mut self, elapsed: f32) {
+        let frame_duration = 1.0 / self.settings.fps.max(1.0);
+        self.frame_time += elapsed * self.settings.speed;
+        while self.frame_time >= frame_duration {
+            self.frame_time -= frame_duration;
+            if self.current_frame + 1 < self.frames.len() {
+                self.current_frame += 1;
+            } else if self.settings.looping {
+                self.current_frame = 0;
+            } else {
+                self.settings.playing = false;
+                self.frame_time = 0.0;
+                break;
+            }
+        }
+    }
+
+    /// Start reading a frame on a background thread
+    fn load(&mut self, frame: usize) {
+        let path = self.frames[frame].clone();
+        let (width, height) = (self.width, self.height);
+        let handle = thread::Builder::new()
+            .name(format!("exr-sequence-{frame}"))
+            .spawn(move || load_exr_frame(&path, width, height));
+        match handle {
+            Ok(handle) => self.loading = Some((frame, handle)),
+            Err(e) => error!("Failed to start the frame loader: {e:?}"),
+        }
+    }
+
+    fn insert(&mut self, frame: usize, pixels: Vec<f32>) {
+        self.cache[frame] = Some(pixels);
+        self.cache_order.push_back(frame);
+        self.evict();
+    }
+
+    /// Drop the oldest frames over the cache size, always keeping the current one
+    fn evict(&mut self) {
+        let frame_bytes = self.width * self.height * 4 * std::mem::size_of::<f32>();
+        let max_frames =
+            (self.settings.max_cache_mb as usize * 1024 * 1024 / frame_bytes.max(1)).max(1);
+        while self.cache_order.len() > max_frames {
+            let Some(position) = self
+                .cache_order
+                .iter()
+                .position(|&frame| frame != self.current_frame)
+            else {
+                break;
+            };
+            if let Some(frame) = self.cache_order.remove(position) {
+                self.cache[frame] = None;
+                if self.shown_frame == Some(frame) {
+                    self.shown_frame = None;
+                }
+            }
+        }
+    }
+}
+
+/// EXR files of a directory, ordered by the number at the end of their name
+/// (`frame_0001.exr`, `frame_0002.exr`, ...), then by name
+fn find_sequence_frames(directory: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut frames: Vec<PathBuf> = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"))
+        })
+        .collect();
+    frames.sort_by_cached_key(|path| {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        let number = stem[stem.len() - digits..].parse::<u64>().ok();
+        (number, stem)
+    });
+    Ok(frames)
+}
+
+/// Read the first RGBA layer of an EXR file, resized to `width` x `height`
+/// with the nearest pixel
+pub(crate) fn load_exr_frame(path: &Path, width: usize, height: usize) -> anyhow::Result<Vec<f32>> {
+    let image = read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| {
+            (
+                resolution.width(),
+                vec![0.0_f32; resolution.width() * resolution.height() * 4],
+            )
+        },
+        |(image_width, pixels): &mut (usize, Vec<f32>),
+         position: Vec2<usize>,
+         (r, g, b, a): (f32, f32, f32, f32)| {
+            let index = (position.y() * *image_width + position.x()) * 4;
+            pixels[index..index + 4].copy_from_slice(&[r, g, b, a]);
+        },
+    )?;
+    let (image_width, pixels) = image.layer_data.channel_data.pixels;
+    let image_height = pixels.len() / 4 / image_width.max(1);
+    if image_width == width && image_height == height {
+        return Ok(pixels);
+    }
+
+    let mut resized = vec![0.0; width * height * 4];
+    for (y, row) in resized.chunks_exact_mut(width * 4).enumerate() {
+        let source_y = y * image_height / height;
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let source_x = x * image_width / width;
+            let index = (source_y * image_width + source_x) * 4;
+            pixel.copy_from_slice(&pixels[index..index + 4]);
+        }
+    }
+    Ok(resized)
+}
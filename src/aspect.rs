@@ -0,0 +1,272 @@
+use pixels::{wgpu, PixelsContext};
+
+use crate::constants::{RENDER_BUFFER_HEIGHT, RENDER_BUFFER_WIDTH};
+
+/// Width over height of a single framebuffer pixel, as shown on screen.
+/// Anamorphic formats store a squeezed image, stretched back when displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelAspectRatio {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl Default for PixelAspectRatio {
+    fn default() -> Self {
+        Self::SQUARE
+    }
+}
+
+impl PixelAspectRatio {
+    pub const SQUARE: Self = Self { num: 1, den: 1 };
+
+    /// Common formats, with the label shown in the Format panel
+    pub const PRESETS: [(&'static str, Self); 4] = [
+        ("Square (1:1)", Self::SQUARE),
+        // NTSC DV, a 720x480 frame shown as 4:3
+        ("4:3 SD (10:11)", Self { num: 10, den: 11 }),
+        ("CinemaScope (2:1)", Self { num: 2, den: 1 }),
+        ("Anamorphic (1.33)", Self { num: 4, den: 3 }),
+    ];
+
+    pub fn ratio(self) -> f32 {
+        self.num.max(1) as f32 / self.den.max(1) as f32
+    }
+
+    pub fn is_square(self) -> bool {
+        self.num.max(1) == self.den.max(1)
+    }
+
+    /// Label of the matching preset, `None` for a ratio set by hand or read from a header
+    pub fn preset_label(self) -> Option<&'static str> {
+        Self::PRESETS
+            .iter()
+            .find(|(_, preset)| preset.num * self.den == self.num * preset.den)
+            .map(|(label, _)| *label)
+    }
+}
+
+/// Size of the framebuffer on a target of `target` physical pixels.
+/// Like the `Pixels` scaling renderer, the scale is an integer, applied to the height,
+/// and the width is stretched by the pixel aspect ratio.
+pub(crate) fn viewport_size(target: [f32; 2], pixel_aspect: f32) -> [f32; 2] {
+    let width = RENDER_BUFFER_WIDTH as f32 * pixel_aspect;
+    let height = RENDER_BUFFER_HEIGHT as f32;
+    let scale = (target[0] / width).min(target[1] / height).max(1.0).floor();
+    [width * scale, height * scale]
+}
+
+/// Framebuffer pixel under a window position, in physical pixels.
+/// `None` outside of the framebuffer.
+pub(crate) fn window_pos_to_pixel(
+    target: [f32; 2],
+    position: (f32, f32),
+    pixel_aspect: f32,
+) -> Option<[u32; 2]> {
+    let size = viewport_size(target, pixel_aspect);
+    let x = (position.0 - (target[0] - size[0]) * 0.5) / size[0];
+    let y = (position.1 - (target[1] - size[1]) * 0.5) / size[1];
+    if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+        return None;
+    }
+    Some([
+        (x * RENDER_BUFFER_WIDTH as f32) as u32,
+        (y * RENDER_BUFFER_HEIGHT as f32) as u32,
+    ])
+}
+
+/// Draws the framebuffer texture on a quad stretched by the pixel aspect ratio
+const QUAD_SHADER: &str = "\
+struct Quad {
+    // Half extents in clip space, the other two components are padding
+    scale: vec4<f32>,
+}
+
+@group(0) @binding(0) var framebuffer: texture_2d<f32>;
+@group(0) @binding(1) var framebuffer_sampler: sampler;
+@group(0) @binding(2) var<uniform> quad: Quad;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Four corners, drawn as a triangle strip
+    let uv = vec2<f32>(f32(index & 1u), f32((index >> 1u) & 1u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(
+        (uv.x * 2.0 - 1.0) * quad.scale.x,
+        (1.0 - uv.y * 2.0) * quad.scale.y,
+        0.0,
+        1.0,
+    );
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(framebuffer, framebuffer_sampler, in.uv);
+}
+";
+
+/// Replaces the `Pixels` scaling renderer when the pixels aren't square,
+/// which can only scale both axes by the same amount
+pub(crate) struct PixelAspectRenderer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniforms: wgpu::Buffer,
+    // Created on the first frame, the framebuffer texture is never resized
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl PixelAspectRenderer {
+    /// `format` is the one of the frame
+    pub(crate) fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("pixel_aspect_quad"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pixel_aspect_quad"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("pixel_aspect_quad"),
+            source: wgpu::ShaderSource::Wgsl(QUAD_SHADER.into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("pixel_aspect_quad"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+        // Nearest, so the pixels stay crisp
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("pixel_aspect_quad"),
+            ..Default::default()
+        });
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pixel_aspect_quad"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            uniforms,
+            bind_group: None,
+        }
+    }
+
+    /// Draw the framebuffer centered on a target of `target_size` physical pixels,
+    /// clearing the rest to black
+    pub(crate) fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        render_target: &wgpu::TextureView,
+        context: &PixelsContext,
+        target_size: [u32; 2],
+        pixel_aspect: f32,
+    ) {
+        let target = [target_size[0].max(1) as f32, target_size[1].max(1) as f32];
+        let size = viewport_size(target, pixel_aspect);
+        let scale = [size[0] / target[0], size[1] / target[1], 0.0, 0.0];
+        let bytes: Vec<u8> = scale.iter().flat_map(|value| value.to_ne_bytes()).collect();
+        context.queue.write_buffer(&self.uniforms, 0, &bytes);
+
+        let bind_group = self.bind_group.get_or_insert_with(|| {
+            let view = context
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            context
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("pixel_aspect_quad"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: self.uniforms.as_entire_binding(),
+                        },
+                    ],
+                })
+        });
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("pixel_aspect_quad"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.draw(0..4, 0..1);
+    }
+}
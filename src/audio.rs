@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::{error, info};
+
+use crate::constants::OSCILLOSCOPE_SAMPLES;
+
+/// Records the default input device on cpal's audio thread,
+/// keeping the most recent samples mixed down to mono
+pub(crate) struct AudioCapture {
+    samples: Arc<Mutex<Vec<f32>>>,
+    // Capture stops when the stream is dropped
+    _stream: cpal::Stream,
+}
+
+impl AudioCapture {
+    pub(crate) fn new() -> anyhow::Result<Self> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| anyhow!("No audio input device"))?;
+        let config = device.default_input_config()?;
+        if config.sample_format() != cpal::SampleFormat::F32 {
+            bail!(
+                "Unsupported audio sample format {:?}",
+                config.sample_format()
+            );
+        }
+        info!(
+            "Capturing audio from {}",
+            device.name().unwrap_or_else(|_| "unknown device".into())
+        );
+
+        let channels = config.channels() as usize;
+        let samples = Arc::new(Mutex::new(Vec::with_capacity(OSCILLOSCOPE_SAMPLES * 2)));
+        let shared = Arc::clone(&samples);
+        let stream = device.build_input_stream(
+            &config.into(),
+            move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                let Ok(mut samples) = shared.lock() else {
+                    return;
+                };
+                samples.extend(
+                    data.chunks_exact(channels)
+                        .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+                );
+                let excess = samples.len().saturating_sub(OSCILLOSCOPE_SAMPLES);
+                samples.drain(..excess);
+            },
+            |e| error!("Audio capture failed: {e}"),
+            None,
+        )?;
+        stream.play()?;
+
+        Ok(Self {
+            samples,
+            _stream: stream,
+        })
+    }
+
+    /// Copy of the most recent samples, in [-1, 1]
+    pub(crate) fn latest(&self) -> Vec<f32> {
+        self.samples
+            .lock()
+            .map(|samples| samples.clone())
+            .unwrap_or_default()
+    }
+}
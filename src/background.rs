@@ -0,0 +1,113 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Render run on the background thread, drawing linear RGBA into the back buffer
+pub(crate) type RenderJob = Box<dyn FnOnce(&mut [f32]) + Send>;
+
+/// Renders too slow for the event loop, on a dedicated thread.
+/// The thread draws into the back buffer and signals when it's done, then the event loop
+/// swaps it with the front buffer, so a frame is never read while it's being drawn.
+pub(crate) struct BackgroundRenderer {
+    start: Sender<RenderJob>,
+    ready: Receiver<()>,
+    back: Arc<Mutex<Arc<Vec<f32>>>>,
+    front: Arc<Vec<f32>>,
+    // Only the frame of the last job is shown, older ones were replaced while drawing
+    jobs_sent: usize,
+    frames_ready: usize,
+    // Off once something else drew into the framebuffer, the frame in progress is dropped
+    wanted: bool,
+}
+
+impl BackgroundRenderer {
+    /// Buffers of `len` floats, the size of the framebuffer
+    pub(crate) fn new(len: usize) -> Self {
+        let (start, jobs) = mpsc::channel::<RenderJob>();
+        let (done, ready) = mpsc::channel();
+        let back = Arc::new(Mutex::new(Arc::new(vec![0.0; len])));
+        let thread_back = Arc::clone(&back);
+        thread::Builder::new()
+            .name("background-render".to_owned())
+            .spawn(move || {
+                // Stops once the renderer, and with it the sender, is dropped
+                while let Ok(mut job) = jobs.recv() {
+                    // Jobs queued up meanwhile replace each other, only the last one is drawn
+                    let mut skipped = 0;
+                    while let Ok(newer) = jobs.try_recv() {
+                        job = newer;
+                        skipped += 1;
+                    }
+                    if let Ok(mut back) = thread_back.lock() {
+                        job(&mut Arc::make_mut(&mut back)[..]);
+                    }
+                    for _ in 0..=skipped {
+                        if done.send(()).is_err() {
+                            return;
+                        }
+                    }
+                }
+            })
+            .expect("Failed to start the background render thread");
+        Self {
+            start,
+            ready,
+            back,
+            front: Arc::new(vec![0.0; len]),
+            jobs_sent: 0,
+            frames_ready: 0,
+            wanted: false,
+        }
+    }
+
+    /// Whether a frame is being drawn
+    pub(crate) fn is_busy(&self) -> bool {
+        self.frames_ready < self.jobs_sent
+    }
+
+    /// Draw a new frame, the one in progress is thrown away once done
+    pub(crate) fn start(&mut self, job: RenderJob) {
+        if self.start.send(job).is_ok() {
+            self.jobs_sent += 1;
+            self.wanted = true;
+        }
+    }
+
+    /// Forget about the frame in progress, for when something else is about to draw
+    /// into the framebuffer
+    pub(crate) fn reset(&mut self) {
+        self.wanted = false;
+    }
+
+    /// The new frame, if the last job finished since the previous call
+    pub(crate) fn receive(&mut self) -> Option<Arc<Vec<f32>>> {
+        self.frames_ready += self.ready.try_iter().count();
+        self.swap()
+    }
+
+    /// Block until the last job is finished, returning its frame if not received yet
+    pub(crate) fn wait(&mut self) -> Option<Arc<Vec<f32>>> {
+        while self.is_busy() {
+            if self.ready.recv().is_err() {
+                break;
+            }
+            self.frames_ready += 1;
+        }
+        self.swap()
+    }
+
+    fn swap(&mut self) -> Option<Arc<Vec<f32>>> {
+        if self.jobs_sent == 0 || self.is_busy() {
+            return None;
+        }
+        // Every frame sent was received, so the thread is idle and the lock free
+        self.jobs_sent = 0;
+        self.frames_ready = 0;
+        if !std::mem::take(&mut self.wanted) {
+            return None;
+        }
+        let mut back = self.back.lock().ok()?;
+        std::mem::swap(&mut *back, &mut self.front);
+        Some(Arc::clone(&self.front))
+    }
+}
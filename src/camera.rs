@@ -0,0 +1,58 @@
+use anyhow::Context;
+use log::info;
+use nokhwa::pixel_format::RgbFormat;
+use nokhwa::utils::{ApiBackend, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType};
+use nokhwa::CallbackCamera;
+
+use crate::image::{CameraFrame, CameraPixelFormat};
+
+/// Names of the cameras found, in the order `CameraCapture::new` indexes them
+pub(crate) fn list_devices() -> Vec<String> {
+    nokhwa::query(ApiBackend::Auto)
+        .map(|cameras| cameras.iter().map(|camera| camera.human_name()).collect())
+        .unwrap_or_default()
+}
+
+/// Streams a camera on nokhwa's capture thread, keeping the latest frame
+pub(crate) struct CameraCapture {
+    camera: CallbackCamera,
+}
+
+impl CameraCapture {
+    pub(crate) fn new(device: u32) -> anyhow::Result<Self> {
+        let format =
+            RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
+        let mut camera = CallbackCamera::new(CameraIndex::Index(device), format, |_| {})
+            .with_context(|| format!("Can't open camera {device}"))?;
+        camera.open_stream()?;
+        info!(
+            "Capturing camera {}",
+            camera
+                .info()
+                .map(|info| info.human_name())
+                .unwrap_or_else(|_| "unknown device".into())
+        );
+        Ok(Self { camera })
+    }
+
+    /// The most recent frame in ACEScg, `None` until the camera delivered one
+    pub(crate) fn latest_frame(&self) -> Option<CameraFrame> {
+        let buffer = self.camera.last_frame().ok()?;
+        let resolution = buffer.resolution();
+        let (width, height) = (resolution.width() as usize, resolution.height() as usize);
+        let frame = match buffer.source_frame_format() {
+            FrameFormat::YUYV => {
+                CameraFrame::from_raw(buffer.buffer(), width, height, CameraPixelFormat::Yuyv)
+            }
+            FrameFormat::RAWRGB => {
+                CameraFrame::from_raw(buffer.buffer(), width, height, CameraPixelFormat::Rgb)
+            }
+            // MJPEG, NV12 and grayscale are decoded by nokhwa
+            _ => {
+                let image = buffer.decode_image::<RgbFormat>().ok()?;
+                CameraFrame::from_raw(image.as_raw(), width, height, CameraPixelFormat::Rgb)
+            }
+        };
+        frame.ok()
+    }
+}
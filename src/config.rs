@@ -0,0 +1,409 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::{debug, error, warn};
+use pixels::wgpu;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{Framebuffer, RENDER_BUFFER_HEIGHT, RENDER_BUFFER_WIDTH};
+use crate::image::{
+    convert_storage_encoding, write_as_exr_image, AlphaMode, MetadataMap, StorageEncoding,
+};
+use crate::scene_desc::SceneDescription;
+
+/// Settings read once at startup.
+/// Changing any of them requires restarting the application.
+/// The session state (window layout) is written back on exit.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    pub surface_format: SurfaceFormat,
+    /// Antialiasing of the egui widgets and text
+    pub msaa: MsaaConfig,
+    /// Physical pixels per inch of the monitor, to show prints at their real size
+    pub window_dpi: f32,
+    /// Cap on the redraws per second while something is animating
+    pub target_fps: TargetFps,
+    /// ACEScg RGBA the framebuffer is filled with before each render
+    pub clear_color: [f32; 4],
+    /// Top-left corner of the egui windows, by title, in points
+    pub window_positions: HashMap<String, [f32; 2]>,
+    /// Periodic backup of the framebuffer and the scene
+    pub autosave: AutosaveConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            surface_format: SurfaceFormat::default(),
+            msaa: MsaaConfig::default(),
+            window_dpi: 96.0,
+            target_fps: TargetFps::default(),
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            window_positions: HashMap::new(),
+            autosave: AutosaveConfig::default(),
+        }
+    }
+}
+
+/// Where and how often the session is autosaved, see `Autosave`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct AutosaveConfig {
+    pub enabled: bool,
+    pub interval_minutes: u64,
+    /// `$XDG_CACHE_HOME/pixels-egui-framebuffer`, or `~/.cache/...`, when not set
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: 5,
+            directory: None,
+        }
+    }
+}
+
+/// Autosaves older than this are deleted, and not offered for recovery
+const AUTOSAVE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Autosaves kept, the older ones are deleted
+const AUTOSAVE_KEEP: usize = 5;
+/// Written next to the autosaves while the application runs, and deleted when it quits.
+/// Still there on startup, the previous session crashed.
+const SESSION_MARKER: &str = "session.lock";
+
+/// Writes the framebuffer as EXR and the scene as JSON every `autosave_interval`,
+/// so a crash loses at most a few minutes of work
+pub(crate) struct Autosave {
+    pub autosave_interval: Duration,
+    pub last_save: Instant,
+    enabled: bool,
+    directory: PathBuf,
+    // Of the framebuffer last written, an unchanged image isn't saved again
+    last_saved_hash: Option<u64>,
+}
+
+impl Autosave {
+    pub(crate) fn new(config: &AutosaveConfig) -> Self {
+        Self {
+            autosave_interval: Duration::from_secs(config.interval_minutes.max(1) * 60),
+            last_save: Instant::now(),
+            enabled: config.enabled,
+            directory: config
+                .directory
+                .clone()
+                .unwrap_or_else(default_autosave_directory),
+            last_saved_hash: None,
+        }
+    }
+
+    pub(crate) fn is_due(&self) -> bool {
+        self.enabled && self.last_save.elapsed() > self.autosave_interval
+    }
+
+    /// Write the files on a background thread, then delete the old autosaves.
+    /// `framebuffer` is stored with `storage_encoding`, the EXR is linear.
+    /// Nothing is written when the framebuffer is the same as at the last autosave.
+    pub(crate) fn save(
+        &mut self,
+        mut framebuffer: Framebuffer,
+        storage_encoding: StorageEncoding,
+        alpha_mode: AlphaMode,
+        pixel_aspect: f32,
+        scene: Option<SceneDescription>,
+    ) {
+        self.last_save = Instant::now();
+        let hash = framebuffer_hash(&framebuffer[..]);
+        if self.last_saved_hash == Some(hash) {
+            debug!("Skipping the autosave, the image didn't change");
+            return;
+        }
+        self.last_saved_hash = Some(hash);
+        let directory = self.directory.clone();
+        let stem = format!("autosave_{}", chrono::Local::now().format("%Y%m%d_%H%M%S"));
+        let handle = std::thread::Builder::new()
+            .name("autosave".to_owned())
+            .spawn(move || {
+                if let Err(e) = std::fs::create_dir_all(&directory) {
+                    error!("Failed to create {}: {e:?}", directory.display());
+                    return;
+                }
+                convert_storage_encoding(
+                    &mut framebuffer,
+                    storage_encoding,
+                    StorageEncoding::Linear,
+                );
+                let image_path = directory.join(format!("{stem}.exr"));
+                if let Err(e) = write_as_exr_image(
+                    &image_path,
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                    &framebuffer[..],
+                    &MetadataMap::default(),
+                    pixel_aspect,
+                    false,
+                    alpha_mode,
+                ) {
+                    error!("Failed to autosave {}: {e:?}", image_path.display());
+                    return;
+                }
+                if let Some(scene) = scene {
+                    let scene_path = directory.join(format!("{stem}.json"));
+                    if let Err(e) = scene.save(&scene_path) {
+                        error!("Failed to autosave {}: {e:?}", scene_path.display());
+                    }
+                }
+                remove_old_autosaves(&directory);
+            });
+        if let Err(e) = handle {
+            error!("Failed to start the autosave: {e:?}");
+        }
+    }
+
+    /// Mark the session as running until `end_session`. Returns the image of the newest
+    /// autosave younger than a day, if the previous session never got to remove its mark.
+    pub(crate) fn begin_session(&self) -> Option<PathBuf> {
+        let marker = self.directory.join(SESSION_MARKER);
+        let crashed = marker.exists();
+        let written = std::fs::create_dir_all(&self.directory)
+            .and_then(|()| std::fs::write(&marker, std::process::id().to_string()));
+        if let Err(e) = written {
+            warn!("Failed to write {}: {e:?}", marker.display());
+        }
+        if !crashed {
+            return None;
+        }
+        let newest = list_autosaves(&self.directory).into_iter().next()?;
+        (!is_expired(&newest)).then_some(newest)
+    }
+
+    /// Remove the mark of `begin_session`, on a clean exit
+    pub(crate) fn end_session(&self) {
+        let marker = self.directory.join(SESSION_MARKER);
+        if let Err(e) = std::fs::remove_file(&marker) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to delete {}: {e:?}", marker.display());
+            }
+        }
+    }
+}
+
+/// Fingerprint of the framebuffer contents, to tell whether it changed
+fn framebuffer_hash(framebuffer: &[f32]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for value in framebuffer {
+        value.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn default_autosave_directory() -> PathBuf {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    cache.join("pixels-egui-framebuffer")
+}
+
+/// Autosaved images of `directory`, newest first
+fn list_autosaves(directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut images: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|extension| extension == "exr")
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("autosave_"))
+        })
+        .collect();
+    // The timestamps in the names sort in time order
+    images.sort_unstable_by(|a, b| b.cmp(a));
+    images
+}
+
+fn is_expired(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_none_or(|age| age > AUTOSAVE_MAX_AGE)
+}
+
+/// Delete the autosaves older than a day, and all but the newest few
+fn remove_old_autosaves(directory: &Path) {
+    for (index, image_path) in list_autosaves(directory).into_iter().enumerate() {
+        if index < AUTOSAVE_KEEP && !is_expired(&image_path) {
+            continue;
+        }
+        for path in [image_path.clone(), image_path.with_extension("json")] {
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to delete {}: {e:?}", path.display());
+                }
+            }
+        }
+    }
+}
+
+/// Texture format of the `Pixels` framebuffer texture
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) enum SurfaceFormat {
+    #[default]
+    Rgba8UnormSrgb,
+    /// Preferred by Metal on macOS
+    Bgra8UnormSrgb,
+    /// Linear half float, written through the `half` crate
+    Rgba16Float,
+}
+
+/// Multisample antialiasing of the egui render pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct MsaaConfig {
+    /// Samples per pixel, 1 turns MSAA off
+    pub sample_count: u32,
+}
+
+impl Default for MsaaConfig {
+    fn default() -> Self {
+        Self { sample_count: 1 }
+    }
+}
+
+impl MsaaConfig {
+    /// Sample counts offered in the settings
+    pub(crate) const SAMPLE_COUNTS: [u32; 3] = [1, 2, 4];
+
+    /// Whether the adapter can render to and resolve `format` with this many samples.
+    /// wgpu 0.14 reports multisampling per format rather than per sample count,
+    /// so 2 and 4 samples are offered on the same flags.
+    pub(crate) fn is_supported(self, adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> bool {
+        if self.sample_count == 1 {
+            return true;
+        }
+        Self::SAMPLE_COUNTS.contains(&self.sample_count)
+            && adapter.get_texture_format_features(format).flags.contains(
+                wgpu::TextureFormatFeatureFlags::MULTISAMPLE
+                    | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE,
+            )
+    }
+}
+
+/// Redraws per second while animating, written as a string in the config
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) enum TargetFps {
+    #[serde(rename = "15")]
+    Fps15,
+    #[serde(rename = "24")]
+    Fps24,
+    #[serde(rename = "30")]
+    Fps30,
+    #[default]
+    #[serde(rename = "60")]
+    Fps60,
+    #[serde(rename = "120")]
+    Fps120,
+    /// Redraw as fast as possible, keeping a core busy
+    Unlimited,
+}
+
+impl TargetFps {
+    pub(crate) const ALL: [TargetFps; 6] = [
+        TargetFps::Fps15,
+        TargetFps::Fps24,
+        TargetFps::Fps30,
+        TargetFps::Fps60,
+        TargetFps::Fps120,
+        TargetFps::Unlimited,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            TargetFps::Fps15 => "15",
+            TargetFps::Fps24 => "24",
+            TargetFps::Fps30 => "30",
+            TargetFps::Fps60 => "60",
+            TargetFps::Fps120 => "120",
+            TargetFps::Unlimited => "Unlimited",
+        }
+    }
+
+    /// Shortest time between two redraws, `None` when unlimited
+    pub(crate) fn frame_interval(self) -> Option<Duration> {
+        let fps = match self {
+            TargetFps::Fps15 => 15.0,
+            TargetFps::Fps24 => 24.0,
+            TargetFps::Fps30 => 30.0,
+            TargetFps::Fps60 => 60.0,
+            TargetFps::Fps120 => 120.0,
+            TargetFps::Unlimited => return None,
+        };
+        Some(Duration::from_secs_f64(1.0 / fps))
+    }
+}
+
+impl Config {
+    /// Read the config from a TOML file
+    pub(crate) fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Write the config to a TOML file.
+    /// Comments in an existing file are not preserved.
+    pub(crate) fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Read the config from a TOML file, falling back to the defaults when
+    /// the file is missing or invalid
+    pub(crate) fn load_or_default(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::default();
+        }
+        match Self::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Ignoring invalid config {}: {e:?}", path.display());
+                Self::default()
+            }
+        }
+    }
+}
+
+impl SurfaceFormat {
+    pub(crate) fn to_wgpu(self) -> wgpu::TextureFormat {
+        match self {
+            SurfaceFormat::Rgba8UnormSrgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            SurfaceFormat::Bgra8UnormSrgb => wgpu::TextureFormat::Bgra8UnormSrgb,
+            SurfaceFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+
+    /// Size of a single pixel in the `Pixels` frame
+    pub(crate) fn bytes_per_pixel(self) -> usize {
+        match self {
+            SurfaceFormat::Rgba8UnormSrgb | SurfaceFormat::Bgra8UnormSrgb => 4,
+            SurfaceFormat::Rgba16Float => 8,
+        }
+    }
+
+    /// Whether the adapter can upload to and sample a texture of this format,
+    /// which is all `Pixels` does with it
+    pub(crate) fn is_supported(self, adapter: &wgpu::Adapter) -> bool {
+        adapter
+            .get_texture_format_features(self.to_wgpu())
+            .allowed_usages
+            .contains(wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST)
+    }
+}
@@ -1,6 +1,70 @@
+//! Sizes and limits shared by the renderers and the GUI.
+//!
+//! The framebuffer is `RENDER_BUFFER_WIDTH` x `RENDER_BUFFER_HEIGHT` pixels of four
+//! interleaved `f32` channels, in R, G, B, A order. Rows go from top to bottom, so the
+//! channel `c` of pixel `(x, y)` is at `(y * RENDER_BUFFER_WIDTH + x) * 4 + c`.
+//! Colors are scene linear ACEScg, or ACEScct when the working color space says so.
+
 pub const WINDOW_WIDTH: u32 = 1500;
 pub const WINDOW_HEIGHT: u32 = 720;
 
 pub const RENDER_BUFFER_WIDTH: u32 = 200;
 pub const RENDER_BUFFER_HEIGHT: u32 = 200;
 pub const RENDER_BUFFER_SIZE: usize = (RENDER_BUFFER_WIDTH * RENDER_BUFFER_HEIGHT * 4) as usize;
+const _: () = assert!(
+    RENDER_BUFFER_SIZE == RENDER_BUFFER_WIDTH as usize * RENDER_BUFFER_HEIGHT as usize * 4,
+    "RENDER_BUFFER_SIZE mismatch"
+);
+
+// The whole framebuffer as a region, `[x0, y0, x1, y1]` with the end exclusive
+pub const RENDER_BUFFER_REGION: [usize; 4] = [
+    0,
+    0,
+    RENDER_BUFFER_WIDTH as usize,
+    RENDER_BUFFER_HEIGHT as usize,
+];
+
+// RGBA pixels of the framebuffer, boxed since it's too large for the stack
+pub type Framebuffer = Box<[f32; RENDER_BUFFER_SIZE]>;
+
+// Side of the square blocks the tile renderer hands to each thread, in pixels
+pub const RENDER_TILE_SIZE: usize = 64;
+
+// How often the event loop checks for a finished background render, in milliseconds
+pub const BACKGROUND_POLL_MS: u64 = 16;
+
+// The raymarcher preview shown during its background render is this many times smaller
+pub const RAYMARCHER_PREVIEW_SCALE: usize = 4;
+
+// Radians the Lissajous phase advances each frame while animating
+pub const LISSAJOUS_PHASE_STEP: f32 = 0.02;
+
+// Radians the spiral turns each frame while animating
+pub const SPIRAL_PHASE_STEP: f32 = 0.03;
+
+// Samples shown by the oscilloscope, about 20 ms of audio at 48 kHz
+pub const OSCILLOSCOPE_SAMPLES: usize = 1024;
+
+// Seconds the oscilloscope test signal advances each frame
+pub const OSCILLOSCOPE_TIME_STEP: f32 = 1.0 / 60.0;
+
+// Paint strokes kept for undo, the oldest are dropped first
+pub const MAX_UNDO_STROKES: usize = 32;
+
+// Characters of the ASCII art export, darkest first
+pub const ASCII_ART_CHARSET: &str = " .:-=+*#@";
+
+// Ordered dither thresholds, out of 64, for the 8 bit output
+pub const BAYER_MATRIX_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+// Optional, read from the current working directory at startup
+pub const CONFIG_PATH: &str = "config.toml";
@@ -0,0 +1,337 @@
+use crate::math::smoothstep;
+
+/// What a brush stroke does to the framebuffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushTool {
+    /// Paint with the brush color
+    Paint,
+    /// Copy pixels from a source point, see `clone_stamp_paint`
+    Clone,
+}
+
+/// Round brush painting straight into the linear framebuffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brush {
+    pub tool: BrushTool,
+    // In pixels
+    pub radius: f32,
+    // 0.0 fades out from the center, 1.0 is a hard edged disk
+    pub hardness: f32,
+    pub opacity: f32,
+    // ACEScg RGBA
+    pub color: [f32; 4],
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            tool: BrushTool::Paint,
+            radius: 10.0,
+            hardness: 0.5,
+            opacity: 1.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl Brush {
+    /// Composite a single dab centered on (`cx`, `cy`) over the buffer.
+    /// Coordinates are in pixels, rows stored top first.
+    pub fn paint_at(&self, buffer: &mut [f32], width: usize, height: usize, cx: f32, cy: f32) {
+        let x_min = (cx - self.radius).floor().max(0.0) as usize;
+        let y_min = (cy - self.radius).floor().max(0.0) as usize;
+        let x_max = ((cx + self.radius).ceil().max(0.0) as usize).min(width);
+        let y_max = ((cy + self.radius).ceil().max(0.0) as usize).min(height);
+        // Full weight inside, falling off to 0 at the radius
+        let inner_radius = self.radius * self.hardness.clamp(0.0, 1.0);
+
+        for y in y_min..y_max {
+            for x in x_min..x_max {
+                let distance = (x as f32 + 0.5 - cx).hypot(y as f32 + 0.5 - cy);
+                if distance >= self.radius {
+                    continue;
+                }
+                // A fully hard brush has both edges on the radius, smoothstep would divide by 0
+                let weight = if inner_radius >= self.radius {
+                    1.0
+                } else {
+                    smoothstep(self.radius, inner_radius, distance)
+                };
+                let alpha = weight * self.opacity * self.color[3];
+
+                // Porter-Duff "over"
+                let index = (y * width + x) * 4;
+                let pixel = &mut buffer[index..index + 4];
+                for (value, brush) in pixel[..3].iter_mut().zip(&self.color[..3]) {
+                    *value = brush * alpha + *value * (1.0 - alpha);
+                }
+                pixel[3] = alpha + pixel[3] * (1.0 - alpha);
+            }
+        }
+    }
+
+    /// Paint dabs along the segment from `from` to `to`, so fast strokes don't leave gaps.
+    /// The dab at `from` is skipped, it was painted by the previous segment.
+    pub fn paint_segment(
+        &self,
+        buffer: &mut [f32],
+        width: usize,
+        height: usize,
+        from: [f32; 2],
+        to: [f32; 2],
+    ) {
+        for [cx, cy] in self.segment_dabs(from, to) {
+            self.paint_at(buffer, width, height, cx, cy);
+        }
+    }
+
+    /// Clone dabs along the segment from `from` to `to`, reading `offset` pixels away.
+    /// The dab at `from` is skipped, it was painted by the previous segment.
+    pub fn clone_segment(
+        &self,
+        buffer: &mut [f32],
+        width: usize,
+        height: usize,
+        from: [f32; 2],
+        to: [f32; 2],
+        offset: [f32; 2],
+    ) {
+        for [cx, cy] in self.segment_dabs(from, to) {
+            clone_stamp_paint(
+                buffer,
+                width,
+                height,
+                (cx + offset[0]).floor() as i32,
+                (cy + offset[1]).floor() as i32,
+                cx.floor() as i32,
+                cy.floor() as i32,
+                self.radius,
+                self.opacity,
+            );
+        }
+    }
+
+    /// Pixels the dabs from `from` to `to`, moved by `offset`, can reach, as (x0, y0, x1, y1)
+    /// clamped to the buffer. Never empty, the clone stamp reads the edge pixels for those
+    /// outside of it.
+    pub fn segment_bounds(
+        &self,
+        from: [f32; 2],
+        to: [f32; 2],
+        offset: [f32; 2],
+        width: usize,
+        height: usize,
+    ) -> [usize; 4] {
+        // Dabs are centered on whole pixels by the clone stamp
+        let reach = self.radius.ceil() + 2.0;
+        let low = |a: f32, b: f32, offset: f32, size: usize| {
+            ((a.min(b) + offset - reach).floor().max(0.0) as usize).min(size - 1)
+        };
+        let high = |a: f32, b: f32, offset: f32, size: usize| {
+            ((a.max(b) + offset + reach).ceil().max(1.0) as usize).min(size)
+        };
+        [
+            low(from[0], to[0], offset[0], width),
+            low(from[1], to[1], offset[1], height),
+            high(from[0], to[0], offset[0], width),
+            high(from[1], to[1], offset[1], height),
+        ]
+    }
+
+    /// Centers of the dabs after `from` up to `to`, a quarter of the radius apart
+    fn segment_dabs(&self, from: [f32; 2], to: [f32; 2]) -> impl Iterator<Item = [f32; 2]> {
+        let spacing = (self.radius * 0.25).max(1.0);
+        let length = (to[0] - from[0]).hypot(to[1] - from[1]);
+        let steps = (length / spacing).ceil().max(1.0) as usize;
+        (1..=steps).map(move |step| {
+            let t = step as f32 / steps as f32;
+            [
+                from[0] + (to[0] - from[0]) * t,
+                from[1] + (to[1] - from[1]) * t,
+            ]
+        })
+    }
+}
+
+/// Composite the pixels around (`src_x`, `src_y`) over the disk of `radius` pixels around
+/// (`dst_x`, `dst_y`), fading out towards the edge of the disk.
+/// Source pixels outside of the buffer are clamped to its edges. The source is read
+/// before anything is written, so overlapping areas copy the original pixels.
+#[allow(clippy::too_many_arguments)]
+pub fn clone_stamp_paint(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    src_x: i32,
+    src_y: i32,
+    dst_x: i32,
+    dst_y: i32,
+    radius: f32,
+    opacity: f32,
+) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let reach = radius.ceil() as i32;
+    let x_min = (dst_x - reach).max(0);
+    let y_min = (dst_y - reach).max(0);
+    let x_max = (dst_x + reach + 1).min(width as i32);
+    let y_max = (dst_y + reach + 1).min(height as i32);
+    if x_min >= x_max || y_min >= y_max {
+        return;
+    }
+
+    let source_pixel = |x: i32, y: i32| {
+        let x = (src_x + x - dst_x).clamp(0, width as i32 - 1) as usize;
+        let y = (src_y + y - dst_y).clamp(0, height as i32 - 1) as usize;
+        let index = (y * width + x) * 4;
+        [
+            buffer[index],
+            buffer[index + 1],
+            buffer[index + 2],
+            buffer[index + 3],
+        ]
+    };
+    let source: Vec<[f32; 4]> = (y_min..y_max)
+        .flat_map(|y| (x_min..x_max).map(move |x| (x, y)))
+        .map(|(x, y)| source_pixel(x, y))
+        .collect();
+
+    let area_width = (x_max - x_min) as usize;
+    for (offset, source) in source.into_iter().enumerate() {
+        let x = x_min + (offset % area_width) as i32;
+        let y = y_min + (offset / area_width) as i32;
+        let distance = (x - dst_x) as f32;
+        let distance = distance.hypot((y - dst_y) as f32);
+        if distance >= radius {
+            continue;
+        }
+        let alpha = smoothstep(radius, 0.0, distance) * opacity * source[3];
+
+        // Porter-Duff "over"
+        let index = (y as usize * width + x as usize) * 4;
+        let pixel = &mut buffer[index..index + 4];
+        for (value, source) in pixel[..3].iter_mut().zip(&source[..3]) {
+            *value = source * alpha + *value * (1.0 - alpha);
+        }
+        pixel[3] = alpha + pixel[3] * (1.0 - alpha);
+    }
+}
+
+/// Width and height in pixels of a glyph, before scaling
+pub const GLYPH_SIZE: usize = 8;
+
+/// Monospaced font of 8x8 pixel glyphs, indexed by code page 437 character.
+/// Each glyph is 8 rows of one byte, top first, the least significant bit on the left.
+pub struct BitmapFont {
+    glyphs: &'static [u8; 256 * GLYPH_SIZE],
+}
+
+impl BitmapFont {
+    /// The IBM PC font. Only the printable ASCII half of the code page is drawn,
+    /// the other glyphs are blank.
+    pub const CP437: Self = Self {
+        glyphs: include_bytes!("../assets/cp437_8x8.bin"),
+    };
+
+    /// Rows of the glyph for `c`, characters outside of ASCII are drawn as '?'
+    fn glyph(&self, c: char) -> &[u8] {
+        let code = if c.is_ascii() {
+            c as usize
+        } else {
+            '?' as usize
+        };
+        &self.glyphs[code * GLYPH_SIZE..(code + 1) * GLYPH_SIZE]
+    }
+}
+
+/// Size in pixels of `text` drawn by `draw_text`
+pub fn text_size(text: &str, scale: u32) -> [usize; 2] {
+    let columns = text.split('\n').map(|line| line.chars().count()).max();
+    let rows = text.split('\n').count();
+    let glyph_size = GLYPH_SIZE * scale.max(1) as usize;
+    [columns.unwrap_or(0) * glyph_size, rows * glyph_size]
+}
+
+/// Composite `text` over the buffer, its top left corner at (`x`, `y`).
+/// `color` is ACEScg RGBA, lines are split on '\n' and each glyph pixel
+/// becomes a `scale` x `scale` block. Pixels outside of the buffer are skipped.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_text(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    text: &str,
+    font: &BitmapFont,
+    color: [f32; 4],
+    scale: u32,
+) {
+    let scale = scale.max(1) as usize;
+    let glyph_size = GLYPH_SIZE * scale;
+    let alpha = color[3];
+    for (line_index, line) in text.split('\n').enumerate() {
+        let top = y + line_index * glyph_size;
+        for (column, c) in line.chars().enumerate() {
+            let left = x + column * glyph_size;
+            for (row, bits) in font.glyph(c).iter().enumerate() {
+                for bit in 0..GLYPH_SIZE {
+                    if (bits >> bit) & 1 == 0 {
+                        continue;
+                    }
+                    for py in top + row * scale..(top + (row + 1) * scale).min(height) {
+                        for px in left + bit * scale..(left + (bit + 1) * scale).min(width) {
+                            // Porter-Duff "over"
+                            let index = (py * width + px) * 4;
+                            let pixel = &mut buffer[index..index + 4];
+                            for (value, text) in pixel[..3].iter_mut().zip(&color[..3]) {
+                                *value = text * alpha + *value * (1.0 - alpha);
+                            }
+                            pixel[3] = alpha + pixel[3] * (1.0 - alpha);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Text drawn over the exported images
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    pub text: String,
+    // Top left corner, in pixels
+    pub position: [usize; 2],
+    // 1x, 2x or 4x the 8 pixels of the font
+    pub scale: u32,
+    // ACEScg RGBA
+    pub color: [f32; 4],
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            position: [8, 8],
+            scale: 2,
+            color: [1.0, 1.0, 1.0, 0.75],
+        }
+    }
+}
+
+impl Watermark {
+    pub fn draw(&self, buffer: &mut [f32], width: usize, height: usize) {
+        draw_text(
+            buffer,
+            width,
+            height,
+            self.position[0],
+            self.position[1],
+            &self.text,
+            &BitmapFont::CP437,
+            self.color,
+            self.scale,
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use colstodian::{color, Scene};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{RENDER_BUFFER_HEIGHT, RENDER_BUFFER_WIDTH};
+use crate::image::render_ssaa;
+use crate::math::fit_range_clamped;
+
+/// Region of the complex plane shown by the Mandelbrot renderer
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MandelbrotView {
+    pub center: [f64; 2],
+    // Width of the visible region of the complex plane
+    pub scale: f64,
+    pub max_iterations: u32,
+}
+
+impl Default for MandelbrotView {
+    fn default() -> Self {
+        Self {
+            center: [-0.5, 0.0],
+            scale: 3.0,
+            max_iterations: 256,
+        }
+    }
+}
+
+/// Render a `width` x `height` block of the Mandelbrot view, whose top left corner is at
+/// `x`, `y` in the framebuffer (rows stored top first), with `ss_factor` x `ss_factor`
+/// samples per pixel.
+/// `cancel` is checked before each row, returns `None` if it was set before the tile finished.
+pub fn render_mandelbrot_tile(
+    view: &MandelbrotView,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    ss_factor: u32,
+    cancel: &AtomicBool,
+) -> Option<Vec<f32>> {
+    let mut pixels = vec![0.0; width * height * 4];
+    let samples_per_pixel = ss_factor.max(1) as f64;
+    // Framebuffer coordinate of the center of a sample, pixel centers land on integers
+    let sample_position = |origin: usize, sample: usize| {
+        origin as f64 + (sample as f64 + 0.5) / samples_per_pixel - 0.5
+    };
+    render_ssaa(
+        &mut pixels,
+        width,
+        height,
+        [0, 0, width, height],
+        ss_factor,
+        |samples, width, _, _| {
+            for (row, row_pixels) in samples.chunks_exact_mut(width * 4).enumerate() {
+                if cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+                // The framebuffer is stored top first, the complex plane grows upwards
+                let plane_y = (RENDER_BUFFER_HEIGHT - 1) as f64 - sample_position(y, row);
+                for (column, pixel) in row_pixels.chunks_exact_mut(4).enumerate() {
+                    let plane_x = sample_position(x, column);
+                    let rgb = mandelbrot_pixel(
+                        view.center,
+                        view.scale,
+                        view.max_iterations,
+                        plane_x,
+                        plane_y,
+                    );
+                    pixel[..3].copy_from_slice(&rgb);
+                    pixel[3] = 1.0;
+                }
+            }
+        },
+    );
+    if cancel.load(Ordering::Relaxed) {
+        return None;
+    }
+    Some(pixels)
+}
+
+/// ACEScg color of the pixel at `x`, `y` (counted from the bottom) of the Mandelbrot view
+fn mandelbrot_pixel(center: [f64; 2], scale: f64, max_iterations: u32, x: f64, y: f64) -> [f32; 3] {
+    let pixel_size = scale / RENDER_BUFFER_WIDTH as f64;
+    let half_width = RENDER_BUFFER_WIDTH as f64 * 0.5;
+    let half_height = RENDER_BUFFER_HEIGHT as f64 * 0.5;
+
+    let inside = color::acescg::<Scene>(0.0, 0.0, 0.0);
+    let dark = color::acescg::<Scene>(0.02, 0.02, 0.1);
+    let bright = color::acescg::<Scene>(1.0, 0.6, 0.1);
+
+    let c_re = center[0] + (x - half_width) * pixel_size;
+    let c_im = center[1] + (y - half_height) * pixel_size;
+
+    let (mut z_re, mut z_im) = (0.0_f64, 0.0_f64);
+    let mut iteration = 0;
+    while iteration < max_iterations && z_re * z_re + z_im * z_im <= 4.0 {
+        let next_re = z_re * z_re - z_im * z_im + c_re;
+        z_im = 2.0 * z_re * z_im + c_im;
+        z_re = next_re;
+        iteration += 1;
+    }
+
+    let rendered_color = if iteration >= max_iterations {
+        inside
+    } else {
+        // Smooth (fractional) iteration count to avoid visible bands
+        let log_z = (z_re * z_re + z_im * z_im).ln() * 0.5;
+        let smooth =
+            iteration as f64 + 1.0 - (log_z / std::f64::consts::LN_2).ln() / std::f64::consts::LN_2;
+        let t = fit_range_clamped(smooth, 0.0, max_iterations as f64, 0.0, 1.0).sqrt() as f32;
+        dark.blend(bright, t)
+    };
+    [rendered_color.r, rendered_color.g, rendered_color.b]
+}
+
+/// The Mandelbrot viewport at a given frame of an animation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MandelbrotKeyframe {
+    pub center: [f64; 2],
+    pub scale: f64,
+    pub frame_number: u32,
+}
+
+/// Plays back a zoom animation by interpolating between keyframes
+#[derive(Debug, Default)]
+pub struct MandelbrotAnimator {
+    // Always kept sorted by frame number
+    keyframes: Vec<MandelbrotKeyframe>,
+    current_frame: u32,
+}
+
+impl MandelbrotAnimator {
+    /// Insert a keyframe, replacing any existing keyframe on the same frame
+    pub fn insert_keyframe(&mut self, keyframe: MandelbrotKeyframe) {
+        match self
+            .keyframes
+            .binary_search_by_key(&keyframe.frame_number, |k| k.frame_number)
+        {
+            Ok(i) => self.keyframes[i] = keyframe,
+            Err(i) => self.keyframes.insert(i, keyframe),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.current_frame = 0;
+    }
+
+    /// First and last frame covered by the keyframes
+    pub fn frame_range(&self) -> Option<(u32, u32)> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        Some((first.frame_number, last.frame_number))
+    }
+
+    /// Jump to the first keyframe
+    pub fn rewind(&mut self) {
+        self.current_frame = self.frame_range().map_or(0, |(first, _)| first);
+    }
+
+    /// Viewport at `frame`, held constant before the first and after the last keyframe.
+    /// The center is interpolated linearly, the scale exponentially so that zooming
+    /// proceeds at a constant pace.
+    pub fn interpolate(&self, frame: u32) -> Option<([f64; 2], f64)> {
+        let next = self.keyframes.partition_point(|k| k.frame_number <= frame);
+        let (a, b) = match next {
+            0 => return self.keyframes.first().map(|k| (k.center, k.scale)),
+            n if n == self.keyframes.len() => {
+                return self.keyframes.last().map(|k| (k.center, k.scale))
+            }
+            n => (self.keyframes[n - 1], self.keyframes[n]),
+        };
+
+        let t = (frame - a.frame_number) as f64 / (b.frame_number - a.frame_number) as f64;
+        let center = [
+            a.center[0] + (b.center[0] - a.center[0]) * t,
+            a.center[1] + (b.center[1] - a.center[1]) * t,
+        ];
+        let scale = a.scale * (b.scale / a.scale).powf(t);
+
+        Some((center, scale))
+    }
+
+    /// Center and scale of the current frame, advancing to the next one.
+    /// Returns `None` once past the last keyframe.
+    pub fn step(&mut self) -> Option<([f64; 2], f64)> {
+        let (_, last) = self.frame_range()?;
+        if self.current_frame > last {
+            return None;
+        }
+        let view = self.interpolate(self.current_frame)?;
+        self.current_frame += 1;
+        Some(view)
+    }
+}
+
+/// Exponential moving average of the rendered frames, to smooth out the noise
+/// of animated renders
+#[derive(Debug)]
+pub struct TemporalDenoiser {
+    // Linear ACEScg RGBA average of the previous frames, empty after a reset
+    pub history_buffer: Vec<f32>,
+    // Weight of the new frame, 1 shows it as is
+    pub blend_factor: f32,
+}
+
+impl Default for TemporalDenoiser {
+    fn default() -> Self {
+        Self {
+            history_buffer: Vec::new(),
+            blend_factor: 0.1,
+        }
+    }
+}
+
+impl TemporalDenoiser {
+    /// Blend `current` into the history and write the result to `output`.
+    /// The first frame after a reset, or after the size changed, starts the history.
+    pub fn apply(&mut self, current: &[f32], output: &mut [f32]) {
+        if self.history_buffer.len() != current.len() {
+            self.history_buffer = current.to_vec();
+        } else {
+            for (history, &value) in self.history_buffer.iter_mut().zip(current) {
+                *history += (value - *history) * self.blend_factor;
+            }
+        }
+        output.copy_from_slice(&self.history_buffer);
+    }
+
+    /// Forget the previous frames, so a new image doesn't ghost over with the old one
+    pub fn reset(&mut self) {
+        self.history_buffer.clear();
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::io;
+
+/// Why an image couldn't be read or written
+#[derive(Debug)]
+pub enum ImageError {
+    /// The file couldn't be read or written, e.g. the disk is full or the path is invalid
+    Io { source: io::Error },
+    /// The file isn't a valid OpenEXR image
+    Exr { message: String },
+    /// The file isn't a valid PNG or JPEG image
+    Decode { message: String },
+    /// An image can't have a zero or negative size
+    InvalidDimensions { width: usize, height: usize },
+    /// Valid data that can't be handled, e.g. an EXR compression the exr crate doesn't know
+    UnsupportedFormat { format: String },
+    /// The pixels don't match the size of the image
+    BufferSizeMismatch { expected: usize, actual: usize },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io { source } => write!(f, "I/O error: {source}"),
+            ImageError::Exr { message } => write!(f, "Invalid EXR: {message}"),
+            ImageError::Decode { message } => write!(f, "Invalid image: {message}"),
+            ImageError::InvalidDimensions { width, height } => {
+                write!(f, "Invalid image size {width}x{height}")
+            }
+            ImageError::UnsupportedFormat { format } => write!(f, "Unsupported format: {format}"),
+            ImageError::BufferSizeMismatch { expected, actual } => {
+                write!(f, "Expected {expected} values for the image, got {actual}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io { source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(source: io::Error) -> Self {
+        ImageError::Io { source }
+    }
+}
+
+impl From<::image::ImageError> for ImageError {
+    fn from(error: ::image::ImageError) -> Self {
+        match error {
+            ::image::ImageError::IoError(source) => ImageError::Io { source },
+            ::image::ImageError::Unsupported(unsupported) => ImageError::UnsupportedFormat {
+                format: unsupported.to_string(),
+            },
+            other => ImageError::Decode {
+                message: other.to_string(),
+            },
+        }
+    }
+}
+
+impl From<exr::error::Error> for ImageError {
+    fn from(error: exr::error::Error) -> Self {
+        match error {
+            // Keep the I/O errors apart, so a full disk can be told from a broken file
+            exr::error::Error::Io(source) => ImageError::Io { source },
+            exr::error::Error::NotSupported(format) => ImageError::UnsupportedFormat {
+                format: format.into_owned(),
+            },
+            other => ImageError::Exr {
+                message: other.to_string(),
+            },
+        }
+    }
+}
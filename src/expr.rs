@@ -0,0 +1,459 @@
+//! Tiny expression language for per-pixel color operations, e.g.
+//! `r = r * 2; g = pow(g, 0.5); b = 1.0 - b`
+//!
+//! A program is a list of assignments to `r`, `g`, `b` or `a`, separated by `;` or new lines.
+//! Expressions support `+ - * /`, parentheses, numbers, the variables
+//! `r g b a u v luma` and the functions
+//! `pow sin cos sqrt abs min max clamp lerp`.
+
+use std::fmt;
+
+use crate::image::acescg_luminance;
+
+/// A syntax error, with the 1-based position where it was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    R,
+    G,
+    B,
+    A,
+    /// Horizontal pixel coordinate, 0 on the left and 1 on the right
+    U,
+    /// Vertical pixel coordinate, 0 at the bottom and 1 at the top
+    V,
+    /// ACEScg luminance of the pixel, before the program runs
+    Luma,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "r" => Variable::R,
+            "g" => Variable::G,
+            "b" => Variable::B,
+            "a" => Variable::A,
+            "u" => Variable::U,
+            "v" => Variable::V,
+            "luma" => Variable::Luma,
+            _ => return None,
+        })
+    }
+
+    /// Index of the channel in an RGBA pixel, for the variables that can be assigned
+    fn channel(&self) -> Option<usize> {
+        match self {
+            Variable::R => Some(0),
+            Variable::G => Some(1),
+            Variable::B => Some(2),
+            Variable::A => Some(3),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Pow,
+    Sin,
+    Cos,
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Clamp,
+    Lerp,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "pow" => Function::Pow,
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "clamp" => Function::Clamp,
+            "lerp" => Function::Lerp,
+            _ => return None,
+        })
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Function::Sin | Function::Cos | Function::Sqrt | Function::Abs => 1,
+            Function::Pow | Function::Min | Function::Max => 2,
+            Function::Clamp | Function::Lerp => 3,
+        }
+    }
+
+    fn call(&self, args: &[f32]) -> f32 {
+        match self {
+            Function::Pow => args[0].powf(args[1]),
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Abs => args[0].abs(),
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+            // Not `f32::clamp`, which panics when min > max
+            Function::Clamp => args[0].max(args[1]).min(args[2]),
+            Function::Lerp => args[0] + (args[1] - args[0]) * args[2],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    Variable(Variable),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+/// Values the variables take for the pixel being evaluated
+struct Scope {
+    pixel: [f32; 4],
+    uv: [f32; 2],
+    luma: f32,
+}
+
+impl Expr {
+    fn eval(&self, scope: &Scope) -> f32 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Variable(variable) => match variable {
+                Variable::U => scope.uv[0],
+                Variable::V => scope.uv[1],
+                Variable::Luma => scope.luma,
+                channel => scope.pixel[channel.channel().unwrap_or(0)],
+            },
+            Expr::Negate(expr) => -expr.eval(scope),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(scope), rhs.eval(scope));
+                match op {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Sub => lhs - rhs,
+                    BinaryOp::Mul => lhs * rhs,
+                    BinaryOp::Div => lhs / rhs,
+                }
+            }
+            Expr::Call(function, args) => {
+                let args: Vec<f32> = args.iter().map(|arg| arg.eval(scope)).collect();
+                function.call(&args)
+            }
+        }
+    }
+}
+
+/// A parsed list of channel assignments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    statements: Vec<(Variable, Expr)>,
+}
+
+impl Program {
+    /// Run the program on every pixel of a linear ACEScg RGBA buffer.
+    /// Statements run in order, so later ones see the channels assigned by earlier ones.
+    pub fn apply(&self, buffer: &mut [f32], width: usize, height: usize) {
+        for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+            // Rows are stored top first
+            let v = 1.0 - (y as f32 + 0.5) / height as f32;
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let mut scope = Scope {
+                    pixel: [pixel[0], pixel[1], pixel[2], pixel[3]],
+                    uv: [(x as f32 + 0.5) / width as f32, v],
+                    luma: acescg_luminance([pixel[0], pixel[1], pixel[2]]),
+                };
+                for (variable, expr) in &self.statements {
+                    let value = expr.eval(&scope);
+                    if let Some(channel) = variable.channel() {
+                        scope.pixel[channel] = value;
+                    }
+                }
+                pixel.copy_from_slice(&scope.pixel);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Number(f32),
+    Identifier(String),
+    Symbol(char),
+    /// `;` or a new line
+    Separator,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let (mut line, mut column) = (1, 1);
+
+    while let Some(&c) = chars.peek() {
+        let (start_line, start_column) = (line, column);
+        let kind = if c == '\n' || c == ';' {
+            chars.next();
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+            TokenKind::Separator
+        } else if c.is_whitespace() {
+            chars.next();
+            column += 1;
+            continue;
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+                column += 1;
+            }
+            let value = text.parse().map_err(|_| ParseError {
+                message: format!("Invalid number '{text}'"),
+                line: start_line,
+                column: start_column,
+            })?;
+            TokenKind::Number(value)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+                column += 1;
+            }
+            TokenKind::Identifier(text)
+        } else if "+-*/(),=".contains(c) {
+            chars.next();
+            column += 1;
+            TokenKind::Symbol(c)
+        } else {
+            return Err(ParseError {
+                message: format!("Unexpected character '{c}'"),
+                line,
+                column,
+            });
+        };
+        tokens.push(Token {
+            kind,
+            line: start_line,
+            column: start_column,
+        });
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        line,
+        column,
+    });
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the token list
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].clone();
+        if token.kind != TokenKind::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn error(token: &Token, message: impl Into<String>) -> ParseError {
+        ParseError {
+            message: message.into(),
+            line: token.line,
+            column: token.column,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), ParseError> {
+        let token = self.next();
+        if token.kind == TokenKind::Symbol(symbol) {
+            Ok(())
+        } else {
+            Err(Self::error(&token, format!("Expected '{symbol}'")))
+        }
+    }
+
+    /// program := (statement (separator statement)*)?
+    fn program(&mut self) -> Result<Program, ParseError> {
+        let mut statements = Vec::new();
+        loop {
+            while self.peek().kind == TokenKind::Separator {
+                self.next();
+            }
+            if self.peek().kind == TokenKind::End {
+                return Ok(Program { statements });
+            }
+            statements.push(self.statement()?);
+
+            let token = self.peek();
+            if !matches!(token.kind, TokenKind::Separator | TokenKind::End) {
+                return Err(Self::error(token, "Expected ';' or a new line"));
+            }
+        }
+    }
+
+    /// statement := channel '=' expression
+    fn statement(&mut self) -> Result<(Variable, Expr), ParseError> {
+        let token = self.next();
+        let TokenKind::Identifier(name) = &token.kind else {
+            return Err(Self::error(&token, "Expected a channel to assign to"));
+        };
+        let variable = Variable::from_name(name)
+            .filter(|variable| variable.channel().is_some())
+            .ok_or_else(|| {
+                Self::error(
+                    &token,
+                    format!("Can only assign to r, g, b or a, not '{name}'"),
+                )
+            })?;
+        self.expect_symbol('=')?;
+        Ok((variable, self.expression()?))
+    }
+
+    /// expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Symbol('+') => BinaryOp::Add,
+                TokenKind::Symbol('-') => BinaryOp::Sub,
+                _ => return Ok(expr),
+            };
+            self.next();
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+        }
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Symbol('*') => BinaryOp::Mul,
+                TokenKind::Symbol('/') => BinaryOp::Div,
+                _ => return Ok(expr),
+            };
+            self.next();
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    /// unary := '-' unary | primary
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.peek().kind == TokenKind::Symbol('-') {
+            self.next();
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    /// primary := number | variable | function '(' arguments ')' | '(' expression ')'
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Number(value) => Ok(Expr::Number(*value)),
+            TokenKind::Symbol('(') => {
+                let expr = self.expression()?;
+                self.expect_symbol(')')?;
+                Ok(expr)
+            }
+            TokenKind::Identifier(name) => {
+                if let Some(variable) = Variable::from_name(name) {
+                    return Ok(Expr::Variable(variable));
+                }
+                let Some(function) = Function::from_name(name) else {
+                    return Err(Self::error(&token, format!("Unknown name '{name}'")));
+                };
+
+                self.expect_symbol('(')?;
+                let mut args = Vec::new();
+                if self.peek().kind != TokenKind::Symbol(')') {
+                    args.push(self.expression()?);
+                    while self.peek().kind == TokenKind::Symbol(',') {
+                        self.next();
+                        args.push(self.expression()?);
+                    }
+                }
+                self.expect_symbol(')')?;
+
+                if args.len() != function.arity() {
+                    return Err(Self::error(
+                        &token,
+                        format!(
+                            "'{name}' takes {} argument(s), got {}",
+                            function.arity(),
+                            args.len()
+                        ),
+                    ));
+                }
+                Ok(Expr::Call(function, args))
+            }
+            TokenKind::End => Err(Self::error(&token, "Unexpected end of input")),
+            _ => Err(Self::error(&token, "Expected a value")),
+        }
+    }
+}
+
+/// Parse a program, reporting the first syntax error
+pub fn parse(source: &str) -> Result<Program, ParseError> {
+    Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    }
+    .program()
+}
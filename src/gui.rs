@@ -1,13 +1,55 @@
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+use egui::plot::{HLine, Line, Plot, PlotPoints};
 use egui::{ClippedPrimitive, Context, TexturesDelta};
 use egui_wgpu::renderer::{Renderer, ScreenDescriptor};
+use log::{error, info};
 use pixels::{wgpu, PixelsContext};
-use winit::event_loop::EventLoopWindowTarget;
+use winit::event_loop::{EventLoopProxy, EventLoopWindowTarget};
 use winit::window::Window;
 
-use crate::constants::{RENDER_BUFFER_HEIGHT, RENDER_BUFFER_SIZE, RENDER_BUFFER_WIDTH};
-use crate::image::write_as_exr_image;
+use crate::animation::{PlaybackSettings, PlaybackStatus, PLAYBACK_SPEEDS};
+use crate::aspect::{self, PixelAspectRatio};
+use crate::config::{Config, MsaaConfig, SurfaceFormat, TargetFps};
+use crate::constants::{ASCII_ART_CHARSET, CONFIG_PATH, RENDER_BUFFER_HEIGHT, RENDER_BUFFER_WIDTH};
+use crate::draw::{text_size, Brush, BrushTool, Watermark};
+use crate::effects::MandelbrotView;
+use crate::error::ImageError;
+use crate::expr::{self, ParseError};
+use crate::image::{
+    acescg_to_srgb_u8, acescg_to_xy, analyze_bit_depth, bake_sh_irradiance,
+    default_gradient_anchors, equirect_to_cubemap, generate_palette, generate_test_warp,
+    linear_srgb_to_oklab, linear_to_srgb, oklab_to_linear_srgb, read_cube_file, read_exr_depth,
+    read_exr_displacement, read_exr_metadata, spectral_locus, srgb_to_linear, srgb_u8_to_acescg,
+    write_cube_file, write_cubemap_faces, AffineTransform, AlphaMode, BitDepthAnalysis, BokehShape,
+    BorderStyle, BrdfGridSettings, BrickSettings, CameraSettings, CdlParams, CelShadeMode,
+    ChannelRemap, ChannelSource, ChromaticAberrationMode, ColorStop, ColorWheelMode, ContourMode,
+    CubemapFaces, DisplayMode, DitherMode, EqualizationMode, Exemplar, FisheyeMode, FlameTransform,
+    FlameVariation, FractalFlameSettings, FractalFlameSystem, GradientAnchor, GrungeParams,
+    HdriMap, InvertMode, KaleidoscopeMode, LevelsHistogram, LevelsParams, LevelsRange,
+    LissajousSettings, Lut3d, MetadataMap, MipPyramid, NoiseBlendMode, OscilloscopeSettings,
+    OutputColorSpace, PcbSettings, PoissonDiskSettings, PolarMode, PostProcessEffect,
+    PostProcessSettings, PrintSettings, RaymarcherSettings, SdfMaterial, SdfPrimitive, SkySettings,
+    SoftProofTarget, SphericalHarmonics9, SpiralSettings, SplitToneParams, StarfieldSettings,
+    StorageEncoding, TerrainParams, TextureQuiltingSettings, ThermalColormap, TruchetSettings,
+    TruchetTileSet, TurbulencePattern, TurbulenceSettings, ValueNoiseSettings, WoodGrainSettings,
+    WorleyMetric, WorleyOutput, WorleySettings, ACESCG_PRIMARIES_XY, DCI_P3_PRIMARIES_XY,
+    DISPLAY_P3_PRIMARIES_XY, KALEIDOSCOPE_SEGMENTS, LEVELS_HISTOGRAM_BINS, MAX_BRDF_GRID_SIZE,
+    MAX_GRADIENT_ANCHORS, MAX_SDF_PRIMITIVES, REC2020_PRIMARIES_XY, SRGB_PRIMARIES_XY,
+    SSAA_FACTORS,
+};
+use crate::msaa::MsaaLayer;
+use crate::nodes::{Node, NodeGraph, NodeId, NodeKind, DEFAULT_WGSL_SHADER};
+use crate::noise::NoiseBasis;
+use crate::passes::{default_pass_order, PassSlot};
+use crate::sampling::SamplerKind;
+use crate::scene_desc::{ProceduralParams, SceneDescription, SceneFiles};
+use crate::tiles::RenderStatus;
+use crate::wgsl::WGSL_PRELUDE;
+use crate::{RenderMode, UserEvent};
 
 /// Manages all state required for rendering egui over `Pixels`.
 pub(crate) struct Framework {
@@ -16,8 +58,12 @@ pub(crate) struct Framework {
     egui_state: egui_winit::State,
     screen_descriptor: ScreenDescriptor,
     renderer: Renderer,
+    // Multisampled layer egui is drawn to, `None` when MSAA is off
+    msaa_layer: Option<MsaaLayer>,
     paint_jobs: Vec<ClippedPrimitive>,
     textures: TexturesDelta,
+    // When egui wants to be drawn again, `None` if it only changes on input
+    repaint_deadline: Option<Instant>,
 
     // State for the GUI
     gui: Gui,
@@ -28,252 +74,6210 @@ struct Gui {
     scale_factor: f32,
     // UI options
     window_open: bool,
-    should_rerender: bool,
     window_width: u32,
     window_height: u32,
     file_path: String,
     color_a: [u8; 4],
     color_b: [u8; 4],
+    color_harmony: ColorHarmonyPanel,
     file_format_chosen: FileFormat,
-    // Pointers
-    render_buffer_pointer: Box<[f32; RENDER_BUFFER_SIZE]>,
+    // Width in pixels of the block turned into each ASCII character
+    ascii_block_size: usize,
+    ascii_charset: String,
+    // EXIF tags in the JPEG export
+    embed_metadata: bool,
+    // Write only the red channel of EXR files, for masks
+    single_channel: bool,
+    watermark_enabled: bool,
+    watermark: Watermark,
+    render_mode: RenderMode,
+    mandelbrot_view: MandelbrotView,
+    // Frame at which the next keyframe will be recorded
+    keyframe_frame: u32,
+    storage_encoding: StorageEncoding,
+    sampler: SamplerKind,
+    // Samples per pixel, per axis, of the procedural renderers
+    ssaa_factor: u32,
+    soft_proof_target: SoftProofTarget,
+    // Distance units per pixel of the generated SDF
+    sdf_scale: f32,
+    // Distances shown from black to white
+    sdf_range: [f32; 2],
+    display_mode: DisplayMode,
+    print: PrintSettings,
+    gamut_warning: bool,
+    output_color_space: OutputColorSpace,
+    dither_mode: DitherMode,
+    dither_scale: f32,
+    pixel_aspect: PixelAspectRatio,
+    // The ratio is typed in, rather than picked from the presets
+    pixel_aspect_custom: bool,
+    alpha_mode: AlphaMode,
+    // Format of the framebuffer texture, after falling back from an unsupported one
+    surface_format: SurfaceFormat,
+    performance_open: bool,
+    frame_timing: FrameTimingGraph,
+    reference_open: bool,
+    reference: ReferenceOverlay,
+    sh_open: bool,
+    // Coefficients baked from the last loaded HDRI
+    sh_irradiance: Option<SphericalHarmonics9>,
+    gradient_editor: GradientEditor,
+    cubemap_open: bool,
+    cubemap_tool: CubemapTool,
+    lissajous: LissajousSettings,
+    starfield: StarfieldSettings,
+    // Wavelengths in nm
+    spectral_range: [f32; 2],
+    flame_editor: FlameEditor,
+    value_noise: ValueNoiseSettings,
+    sky: SkySettings,
+    turbulence: TurbulenceSettings,
+    bricks: BrickSettings,
+    color_wheel_lightness: f32,
+    color_wheel_mode: ColorWheelMode,
+    wood_grain: WoodGrainSettings,
+    poisson_disk: PoissonDiskSettings,
+    oscilloscope: OscilloscopeSettings,
+    // Progress of the tiled render, updated every frame
+    render_status: RenderStatus,
+    worley: WorleySettings,
+    pcb: PcbSettings,
+    texture_quilting: TextureQuiltingSettings,
+    // File name of the loaded exemplar
+    exemplar_name: Option<String>,
+    terrain: TerrainParams,
+    raymarcher: RaymarcherSettings,
+    brdf_spheres: BrdfGridSettings,
+    truchet: TruchetSettings,
+    grunge: GrungeParams,
+    spiral: SpiralSettings,
+    camera: CameraSettings,
+    // Names of the cameras found at startup
+    camera_devices: Vec<String>,
+    clear_before_render: bool,
+    // ACEScg RGBA
+    clear_color: [f32; 4],
+    paint_mode: bool,
+    brush: Brush,
+    // Framebuffer pixel the clone stamp copies from, once picked
+    clone_source: Option<[f32; 2]>,
+    color_dropper: ColorDropper,
+    region_selector: RegionSelector,
+    post_process: PostProcessSettings,
+    pass_order: Vec<PassSlot>,
+    // Index in `pass_order` of the pass being dragged to a new place
+    dragged_pass: Option<usize>,
+    // File name of the depth buffer used by the depth of field
+    depth_name: Option<String>,
+    // File the grid warp displacement was read from, or "Test warp"
+    warp_map_name: Option<String>,
+    denoise_enabled: bool,
+    // Weight of each new frame in the temporal average
+    denoise_blend: f32,
+    color_op_open: bool,
+    color_op: ColorOpEditor,
+    clut_open: bool,
+    clut: ClutEditor,
+    metadata_open: bool,
+    metadata: MetadataEditor,
+    playback_open: bool,
+    print_preview_open: bool,
+    bit_depth_open: bool,
+    // Of the last frame drawn while the panel is open
+    bit_depth: Option<BitDepthAnalysis>,
+    // Of the image going into the levels
+    levels_histogram: LevelsHistogram,
+    print_preview: PrintPreview,
+    playback: PlaybackPanel,
+    node_graph_open: bool,
+    node_graph: NodeGraphEditor,
+    palette: PaletteOptions,
+    gamut_open: bool,
+    gamut_diagram: GamutDiagram,
+    device_info_open: bool,
+    device_info: DeviceInfoPanel,
+    settings_open: bool,
+    settings: SettingsPanel,
+    // Why the last scene couldn't be loaded
+    scene_error: Option<String>,
+    // Scene file last saved or loaded, described in the exported JPEGs
+    scene_path: Option<PathBuf>,
+    // Why the last image couldn't be saved
+    image_error: Option<String>,
+    // Autosaved image left by a session that didn't quit cleanly, until the user answers
+    recoverable_session: Option<PathBuf>,
+    continuous_render: bool,
+    // Top-left corner of the windows by title, restored from and saved to the config
+    window_positions: HashMap<String, [f32; 2]>,
+    // Used to send events back to the main thread
+    event_proxy: EventLoopProxy<UserEvent>,
 }
 
 #[derive(Debug, PartialEq)]
 enum FileFormat {
     OpenEXR,
+    AsciiArt,
+    ColoredAscii,
+    Jpeg,
 }
 
-impl Framework {
-    /// Create egui.
-    pub(crate) fn new<T>(
-        event_loop: &EventLoopWindowTarget<T>,
-        width: u32,
-        height: u32,
-        scale_factor: f32,
-        pixels: &pixels::Pixels,
-        render_buffer: Box<[f32; RENDER_BUFFER_SIZE]>,
-    ) -> Self {
-        let max_texture_size = pixels.device().limits().max_texture_dimension_2d as usize;
+impl FileFormat {
+    fn label(&self) -> &'static str {
+        match self {
+            FileFormat::OpenEXR => "OpenEXR",
+            FileFormat::AsciiArt => "ASCII Art (.txt)",
+            FileFormat::ColoredAscii => "Colored ASCII (.html)",
+            FileFormat::Jpeg => "JPEG",
+        }
+    }
 
-        let egui_ctx = Context::default();
-        let mut egui_state = egui_winit::State::new(event_loop);
-        egui_state.set_max_texture_side(max_texture_size);
-        egui_state.set_pixels_per_point(scale_factor);
-        let screen_descriptor = ScreenDescriptor {
-            size_in_pixels: [width, height],
-            pixels_per_point: scale_factor,
-        };
-        let renderer = Renderer::new(pixels.device(), pixels.render_texture_format(), None, 1);
-        let textures = TexturesDelta::default();
-        let gui = Gui::new(width, height, scale_factor, render_buffer);
+    fn extension(&self) -> &'static str {
+        match self {
+            FileFormat::OpenEXR => "exr",
+            FileFormat::AsciiArt => "txt",
+            FileFormat::ColoredAscii => "html",
+            FileFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+/// Points of a restored window that must stay inside the screen
+const WINDOW_VISIBLE_MARGIN: f32 = 40.0;
 
+/// Number of frames shown in the performance graph
+const FRAME_TIMING_HISTORY: usize = 300;
+
+/// Entry of the pixel aspect ratio selector for a ratio typed in by hand
+const PIXEL_ASPECT_CUSTOM: &str = "Custom (DPX header)";
+
+/// Rolling history of frame times, shown in the "Performance" window.
+/// GPU timings would need `wgpu::Features::TIMESTAMP_QUERY`,
+/// which `Pixels` doesn't request when creating the device.
+struct FrameTimingGraph {
+    // Milliseconds, oldest first
+    frame_times: VecDeque<f32>,
+    // When each of these frames was recorded
+    frame_ends: VecDeque<Instant>,
+}
+
+impl FrameTimingGraph {
+    fn new() -> Self {
         Self {
-            egui_ctx,
-            egui_state,
-            screen_descriptor,
-            renderer,
-            paint_jobs: Vec::new(),
-            textures,
-            gui,
+            frame_times: VecDeque::with_capacity(FRAME_TIMING_HISTORY),
+            frame_ends: VecDeque::with_capacity(FRAME_TIMING_HISTORY),
         }
     }
 
-    /// Handle input events from the window manager.
-    pub(crate) fn handle_event(&mut self, event: &winit::event::WindowEvent) {
-        let _ = self.egui_state.on_event(&self.egui_ctx, event);
+    fn push(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == FRAME_TIMING_HISTORY {
+            self.frame_times.pop_front();
+            self.frame_ends.pop_front();
+        }
+        self.frame_times
+            .push_back(frame_time.as_secs_f32() * 1000.0);
+        self.frame_ends.push_back(Instant::now());
     }
 
-    /// Resize egui.
-    pub(crate) fn resize(&mut self, width: u32, height: u32) {
-        if width > 0 && height > 0 {
-            self.screen_descriptor.size_in_pixels = [width, height];
-        }
+    /// Frames drawn per second over the history, which is lower than what the frame
+    /// times allow when the loop waits between frames
+    fn effective_fps(&self) -> Option<f32> {
+        let (first, last) = (self.frame_ends.front()?, self.frame_ends.back()?);
+        let elapsed = last.duration_since(*first).as_secs_f32();
+        (elapsed > 0.0).then(|| (self.frame_ends.len() - 1) as f32 / elapsed)
     }
 
-    /// Update scaling factor.
-    pub(crate) fn scale_factor(&mut self, scale_factor: f64) {
-        self.screen_descriptor.pixels_per_point = scale_factor as f32;
+    /// Average, min and max frame time in milliseconds
+    fn stats(&self) -> Option<(f32, f32, f32)> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+        let mut sum = 0.0;
+        let mut min = f32::MAX;
+        let mut max = f32::MIN;
+        for &frame_time in &self.frame_times {
+            sum += frame_time;
+            min = min.min(frame_time);
+            max = max.max(frame_time);
+        }
+        Some((sum / self.frame_times.len() as f32, min, max))
     }
 
-    /// Prepare egui.
-    pub(crate) fn prepare(&mut self, window: &Window) {
-        // Run the egui frame and create all paint jobs to prepare for rendering.
-        let raw_input = self.egui_state.take_egui_input(window);
-        let output = self.egui_ctx.run(raw_input, |egui_ctx| {
-            // Draw the demo application.
-            self.gui.ui(egui_ctx);
+    fn ui(&self, ui: &mut egui::Ui, target_fps: TargetFps) {
+        let Some((average, min, max)) = self.stats() else {
+            ui.label("No frames recorded yet");
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Average: {average:.2} ms"));
+            ui.label(format!("Min: {min:.2} ms"));
+            ui.label(format!("Max: {max:.2} ms"));
         });
+        if let Some(fps) = self.effective_fps() {
+            ui.label(format!(
+                "Effective: {fps:.1} fps, target {}",
+                target_fps.label()
+            ));
+        }
 
-        self.textures.append(output.textures_delta);
-        self.egui_state
-            .handle_platform_output(window, &self.egui_ctx, output.platform_output);
-        self.paint_jobs = self.egui_ctx.tessellate(output.shapes);
+        // Ease the top of the graph toward the current max, rather than jumping
+        let y_max = ui.ctx().animate_value_with_time(
+            egui::Id::new("frame_timing_y_max"),
+            max.max(33.33) * 1.1,
+            0.3,
+        );
+
+        let line = Line::new(
+            self.frame_times
+                .iter()
+                .enumerate()
+                .map(|(i, &frame_time)| [i as f64, frame_time as f64])
+                .collect::<PlotPoints>(),
+        )
+        .name("Frame time (ms)");
+
+        Plot::new("frame_timing_plot")
+            .height(150.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .include_x(0.0)
+            .include_x(FRAME_TIMING_HISTORY as f64)
+            .include_y(0.0)
+            .include_y(y_max)
+            .show(ui, |plot_ui| {
+                plot_ui.line(line);
+                plot_ui.hline(HLine::new(1000.0 / 60.0).name("60 fps"));
+                plot_ui.hline(HLine::new(1000.0 / 30.0).name("30 fps"));
+            });
     }
+}
 
-    /// Render egui.
-    pub(crate) fn render(
-        &mut self,
-        encoder: &mut wgpu::CommandEncoder,
-        render_target: &wgpu::TextureView,
-        context: &PixelsContext,
-    ) {
-        // Upload all resources to the GPU.
-        for (id, image_delta) in &self.textures.set {
-            self.renderer
-                .update_texture(&context.device, &context.queue, *id, image_delta);
+/// How the reference image is combined with the framebuffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReferenceBlend {
+    /// Semi-transparent on top of the render
+    Normal,
+    /// Absolute per pixel difference, computed by the app while drawing
+    Difference,
+    /// Alternate between the reference and the render
+    OnionSkin,
+}
+
+/// A reference image drawn over the framebuffer, e.g. concept art to match
+struct ReferenceOverlay {
+    texture: Option<egui::TextureHandle>,
+    // sRGB RGBA8 pixels resampled to the framebuffer resolution
+    pixels: Option<Vec<u8>>,
+    opacity: f32,
+    blend: ReferenceBlend,
+    // Flashes per second in onion skin mode
+    onion_skin_hz: f32,
+}
+
+impl ReferenceOverlay {
+    fn new() -> Self {
+        Self {
+            texture: None,
+            pixels: None,
+            opacity: 0.5,
+            blend: ReferenceBlend::Normal,
+            onion_skin_hz: 2.0,
         }
-        self.renderer.update_buffers(
-            &context.device,
-            &context.queue,
-            encoder,
-            &self.paint_jobs,
-            &self.screen_descriptor,
+    }
+
+    /// Load a PNG or JPG from disk
+    fn load(&mut self, ctx: &Context, path: &Path) -> anyhow::Result<()> {
+        let image = ::image::open(path)?.to_rgba8();
+
+        let size = [image.width() as usize, image.height() as usize];
+        let color_image = egui::ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+        self.texture = Some(ctx.load_texture(
+            "reference_overlay",
+            color_image,
+            egui::TextureOptions::LINEAR,
+        ));
+
+        let resized = ::image::imageops::resize(
+            &image,
+            RENDER_BUFFER_WIDTH,
+            RENDER_BUFFER_HEIGHT,
+            ::image::imageops::FilterType::Triangle,
         );
+        self.pixels = Some(resized.into_raw());
 
-        // Render egui with WGPU
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("egui"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: render_target,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.texture = None;
+        self.pixels = None;
+    }
+
+    /// Tell the app whether it should draw the difference against the reference
+    fn difference_event(&self) -> UserEvent {
+        let reference = match self.blend {
+            ReferenceBlend::Difference => self.pixels.clone(),
+            _ => None,
+        };
+        UserEvent::SetReferenceDifference(reference)
+    }
+
+    /// Contents of the "Reference" window
+    fn ui(&mut self, ui: &mut egui::Ui, event_proxy: &EventLoopProxy<UserEvent>) {
+        let previous_blend = self.blend;
+
+        ui.horizontal(|ui| {
+            if ui.button("Load Reference").clicked() {
+                let path = rfd::FileDialog::new()
+                    .add_filter("Images", &["png", "jpg", "jpeg"])
+                    .pick_file();
+                if let Some(path) = path {
+                    match self.load(ui.ctx(), &path) {
+                        Ok(_) => send_event(event_proxy, self.difference_event()),
+                        Err(e) => error!("Failed to load {}: {e:?}", path.display()),
+                    }
+                }
+            }
+            if ui.button("Clear Reference").clicked() {
+                self.clear();
+                send_event(event_proxy, self.difference_event());
+            }
+        });
+
+        egui::ComboBox::from_label("Blend")
+            .selected_text(format!("{:?}", self.blend))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.blend, ReferenceBlend::Normal, "Normal");
+                ui.selectable_value(&mut self.blend, ReferenceBlend::Difference, "Difference");
+                ui.selectable_value(&mut self.blend, ReferenceBlend::OnionSkin, "Onion Skin");
             });
+        if self.blend != previous_blend {
+            send_event(event_proxy, self.difference_event());
+        }
 
-            self.renderer
-                .render(&mut rpass, &self.paint_jobs, &self.screen_descriptor);
+        match self.blend {
+            ReferenceBlend::Normal => {
+                ui.add(egui::Slider::new(&mut self.opacity, 0.0..=1.0).text("Opacity"));
+            }
+            ReferenceBlend::OnionSkin => {
+                ui.add(egui::Slider::new(&mut self.onion_skin_hz, 0.5..=10.0).text("Hz"));
+            }
+            ReferenceBlend::Difference => {}
+        }
+    }
+
+    /// Draw the reference on top of the framebuffer, below every window
+    fn paint(&self, ctx: &Context, pixel_aspect: f32) {
+        let Some(texture) = &self.texture else {
+            return;
         };
-        // Cleanup
-        let textures = std::mem::take(&mut self.textures);
-        for id in &textures.free {
-            self.renderer.free_texture(id);
+
+        let opacity = match self.blend {
+            ReferenceBlend::Normal => self.opacity,
+            ReferenceBlend::Difference => return,
+            ReferenceBlend::OnionSkin => {
+                let phase = (ctx.input().time * self.onion_skin_hz as f64 * 2.0) as u64;
+                if phase % 2 == 0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        let rect = framebuffer_viewport(ctx, pixel_aspect);
+        egui::Area::new("reference_overlay")
+            .fixed_pos(rect.min)
+            .order(egui::Order::Background)
+            .interactable(false)
+            .show(ctx, |ui| {
+                let tint = egui::Color32::from_white_alpha((opacity * 255.0) as u8);
+                ui.add(egui::Image::new(texture, rect.size()).tint(tint));
+            });
+    }
+}
+
+/// What went wrong with an image, in terms of what the user can do about it
+fn image_error_message(error: &ImageError) -> String {
+    match error {
+        ImageError::Io { source } => match source.kind() {
+            std::io::ErrorKind::StorageFull => "No space left, please free disk space".to_owned(),
+            std::io::ErrorKind::PermissionDenied => {
+                "Permission denied, please pick a folder you can write to".to_owned()
+            }
+            std::io::ErrorKind::NotFound => "The file or its folder doesn't exist".to_owned(),
+            _ => format!("Couldn't access the file: {source}"),
+        },
+        ImageError::Exr { message } => format!("Not a valid OpenEXR file: {message}"),
+        ImageError::Decode { message } => format!("Not a valid image: {message}"),
+        ImageError::InvalidDimensions { width, height } => {
+            format!("Can't save an image of {width}x{height} pixels")
+        }
+        ImageError::UnsupportedFormat { format } => format!("Unsupported image: {format}"),
+        ImageError::BufferSizeMismatch { expected, actual } => {
+            format!("Internal error, the image has {actual} values instead of {expected}")
         }
     }
 }
 
-impl Gui {
-    /// Create a `Gui`.
-    fn new(
-        width: u32,
-        height: u32,
-        scale_factor: f32,
-        render_buf_p: Box<[f32; RENDER_BUFFER_SIZE]>,
-    ) -> Self {
-        Self {
-            window_open: true,
-            should_rerender: false,
-            window_width: width,
-            window_height: height,
-            file_path: String::new(),
-            color_a: [0x00, 0x00, 0x00, 0xff],
-            color_b: [0xff, 0xff, 0xff, 0xff],
-            scale_factor,
-            file_format_chosen: FileFormat::OpenEXR,
-            render_buffer_pointer: render_buf_p,
+/// Ask the user for an equirectangular OpenEXR and load it, returning its path too
+fn pick_hdri() -> Option<(PathBuf, HdriMap)> {
+    let path = rfd::FileDialog::new()
+        .add_filter("OpenEXR", &["exr"])
+        .pick_file()?;
+    match HdriMap::load(&path) {
+        Ok(hdri) => Some((path, hdri)),
+        Err(e) => {
+            error!("Failed to load {}: {e:?}", path.display());
+            None
         }
     }
+}
 
-    /// Create the UI using egui.
-    fn ui(&mut self, ctx: &Context) {
-        egui::TopBottomPanel::top("menubar_container").show(ctx, |ui| {
-            egui::menu::bar(ui, |ui| {
-                ui.menu_button("File", |ui| {
-                    if ui.button("About...").clicked() {
-                        self.window_open = true;
-                        ui.close_menu();
-                    }
-                })
-            });
-        });
+/// Iterated function system editor of the fractal flame, shown in the "Scene Options" window
+struct FlameEditor {
+    settings: FractalFlameSettings,
+}
 
-        egui::Window::new("Scene Options")
-            .open(&mut self.window_open)
-            .default_pos(egui::Pos2::new(
-                self.window_width as f32 * (1.0 / self.scale_factor) * 0.015,
-                self.window_height as f32 * (1.0 / self.scale_factor) * 0.10,
-            ))
-            .show(ctx, |ui| {
-                egui::Grid::new("grid_1").show(ui, |ui| {
-                    ui.label("First Color:");
-                    ui.color_edit_button_srgba_unmultiplied(&mut self.color_a);
-                    ui.label("Second Color:");
-                    ui.color_edit_button_srgba_unmultiplied(&mut self.color_b);
-                    ui.end_row();
-                });
+impl FlameEditor {
+    fn new() -> Self {
+        Self {
+            settings: FractalFlameSettings::default(),
+        }
+    }
 
-                ui.separator();
+    fn ui(&mut self, ui: &mut egui::Ui, event_proxy: &EventLoopProxy<UserEvent>) {
+        let previous = self.settings.clone();
+        let settings = &mut self.settings;
+
+        ui.horizontal(|ui| {
+            ui.label("Presets:");
+            if ui.button("Linear").clicked() {
+                settings.system = FractalFlameSystem::linear_preset();
+            }
+            if ui.button("Sinusoidal").clicked() {
+                settings.system = FractalFlameSystem::sinusoidal_preset();
+            }
+            if ui.button("Spherical").clicked() {
+                settings.system = FractalFlameSystem::spherical_preset();
+            }
+        });
 
-                if ui.button("Render").clicked() {
-                    self.should_rerender = true;
-                    eprintln!("Re-rendering...");
+        egui::ComboBox::from_label("Variation")
+            .selected_text(format!("{:?}", settings.system.variation))
+            .show_ui(ui, |ui| {
+                for variation in [
+                    FlameVariation::Linear,
+                    FlameVariation::Sinusoidal,
+                    FlameVariation::Spherical,
+                ] {
+                    ui.selectable_value(
+                        &mut settings.system.variation,
+                        variation,
+                        format!("{variation:?}"),
+                    );
                 }
+            });
+        ui.add(
+            egui::Slider::new(&mut settings.iterations, 10_000..=50_000_000)
+                .logarithmic(true)
+                .text("Iterations"),
+        );
+        ui.add(egui::Slider::new(&mut settings.supersample, 1..=4).text("Supersample"));
 
-                ui.separator();
+        let mut removed = None;
+        for (i, transform) in settings.system.transforms.iter_mut().enumerate() {
+            egui::CollapsingHeader::new(format!("Transform {}", i + 1))
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new(("flame_transform_grid", i)).show(ui, |ui| {
+                        let affine = &mut transform.affine;
+                        for (row, label) in [
+                            ([&mut affine.a, &mut affine.b, &mut affine.c], "x' ="),
+                            ([&mut affine.d, &mut affine.e, &mut affine.f], "y' ="),
+                        ] {
+                            ui.label(label);
+                            for value in row {
+                                ui.add(egui::DragValue::new(value).speed(0.01));
+                            }
+                            ui.end_row();
+                        }
 
-                ui.horizontal(|ui| {
-                    ui.spacing_mut().item_spacing.x /= 2.0;
-                    ui.label("Learn more about egui at");
-                    ui.hyperlink("https://docs.rs/egui");
+                        ui.label("Probability:");
+                        ui.add(
+                            egui::DragValue::new(&mut transform.probability)
+                                .speed(0.01)
+                                .clamp_range(0.0..=10.0),
+                        );
+                        ui.end_row();
+
+                        ui.label("Color:");
+                        ui.add(egui::Slider::new(&mut transform.color_index, 0.0..=1.0));
+                        ui.end_row();
+                    });
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(i);
+                    }
                 });
+        }
+        if let Some(i) = removed {
+            settings.system.transforms.remove(i);
+        }
+        if ui.button("Add Transform").clicked() {
+            settings.system.transforms.push(FlameTransform {
+                affine: AffineTransform {
+                    a: 0.5,
+                    b: 0.0,
+                    c: 0.0,
+                    d: 0.0,
+                    e: 0.5,
+                    f: 0.0,
+                },
+                probability: 1.0,
+                color_index: 0.5,
             });
+        }
 
-        egui::Window::new("Save Options")
-            .open(&mut self.window_open)
-            .default_pos(egui::Pos2::new(
-                self.window_width as f32 * (1.0 / self.scale_factor) * 0.75,
-                self.window_height as f32 * (1.0 / self.scale_factor) * 0.10,
-            ))
-            .show(ctx, |ui| {
-                egui::ComboBox::from_label("Output format")
-                    .selected_text(format!("{:?}", self.file_format_chosen))
-                    .show_ui(ui, |ui| {
-                        ui.selectable_value(
-                            &mut self.file_format_chosen,
-                            FileFormat::OpenEXR,
-                            "OpenEXR",
-                        );
-                    });
+        if self.settings != previous {
+            send_event(
+                event_proxy,
+                UserEvent::SetFractalFlame(Box::new(self.settings.clone())),
+            );
+        }
+    }
+}
 
-                ui.separator();
-                ui.spacing_mut().item_spacing.x /= 2.0;
+/// Per-pixel expression editor, shown in the "Custom Color Op" window
+struct ColorOpEditor {
+    source: String,
+    // Outcome of the last "Apply"
+    status: Option<Result<(), ParseError>>,
+}
 
-                ui.label("File name (without extension):");
-                ui.text_edit_singleline(&mut self.file_path);
+impl ColorOpEditor {
+    fn new() -> Self {
+        Self {
+            source: "r = r * 2; g = pow(g, 0.5); b = 1.0 - b".to_owned(),
+            status: None,
+        }
+    }
 
-                // Here goes the save logic
-                if ui.button("Save").clicked() {
-                    let root_dir = PathBuf::from("images");
-                    if !root_dir.exists() {
-                        match std::fs::create_dir_all(&root_dir) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                eprintln!("Failed to create images dir: {e:?}");
-                            }
+    fn ui(&mut self, ui: &mut egui::Ui, event_proxy: &EventLoopProxy<UserEvent>) {
+        ui.label("Assign to r, g, b, a. Also available: u, v, luma");
+        ui.add(
+            egui::TextEdit::multiline(&mut self.source)
+                .code_editor()
+                .desired_rows(4)
+                .desired_width(f32::INFINITY),
+        );
+
+        if ui.button("Apply").clicked() {
+            self.status = Some(expr::parse(&self.source).map(|program| {
+                send_event(event_proxy, UserEvent::ApplyColorOp(program));
+            }));
+        }
+
+        match &self.status {
+            Some(Ok(())) => {
+                ui.label("Applied");
+            }
+            Some(Err(e)) => {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("Line {}, column {}: {}", e.line, e.column, e.message),
+                );
+            }
+            None => {}
+        }
+    }
+}
+
+/// Nodes per axis of the LUT the CLUT editor starts from
+const CLUT_EDITOR_SIZE: usize = 17;
+
+/// Points per side of the slice views of the CLUT editor
+const CLUT_SLICE_SIZE: f32 = 136.0;
+
+const CHANNEL_NAMES: [&str; 3] = ["Red", "Green", "Blue"];
+
+/// Planes through the LUT shown by the CLUT editor: the channels along x and y, and the
+/// channel held at a fixed node
+const CLUT_SLICES: [([usize; 2], usize); 3] = [([0, 1], 2), ([1, 2], 0), ([0, 2], 1)];
+
+/// Paint a 3D LUT node by node, shown in the "CLUT Editor" window
+struct ClutEditor {
+    lut: Lut3d,
+    // Send the LUT to the display
+    enabled: bool,
+    // Node at which each channel is held, in the slice that doesn't show it
+    depth: [usize; 3],
+    selected: Option<[usize; 3]>,
+    // Of the three slices, dropped whenever the LUT or the depths change
+    textures: Option<[egui::TextureHandle; 3]>,
+    // Of the last load or save
+    error: Option<String>,
+}
+
+impl ClutEditor {
+    fn new() -> Self {
+        Self {
+            lut: Lut3d::identity(CLUT_EDITOR_SIZE),
+            enabled: false,
+            depth: [CLUT_EDITOR_SIZE / 2; 3],
+            selected: None,
+            textures: None,
+            error: None,
+        }
+    }
+
+    /// Node of `slice` shown at `(x, y)`, green and blue growing upwards
+    fn slice_node(&self, slice: usize, x: usize, y: usize) -> [usize; 3] {
+        let (axes, fixed) = CLUT_SLICES[slice];
+        let mut node = [0; 3];
+        node[axes[0]] = x;
+        node[axes[1]] = self.lut.size - 1 - y;
+        node[fixed] = self.depth[fixed];
+        node
+    }
+
+    fn slice_textures(&self, ctx: &Context) -> [egui::TextureHandle; 3] {
+        let size = self.lut.size;
+        std::array::from_fn(|slice| {
+            let pixels = (0..size * size)
+                .map(|i| {
+                    let [r, g, b] = self.lut.node(self.slice_node(slice, i % size, i / size));
+                    egui::Color32::from(egui::Rgba::from_rgb(r, g, b))
+                })
+                .collect();
+            ctx.load_texture(
+                format!("clut_slice_{slice}"),
+                egui::ColorImage {
+                    size: [size, size],
+                    pixels,
+                },
+                egui::TextureOptions::NEAREST,
+            )
+        })
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, event_proxy: &EventLoopProxy<UserEvent>) {
+        let mut changed = ui
+            .checkbox(&mut self.enabled, "Apply to the display")
+            .changed();
+
+        ui.horizontal(|ui| {
+            if ui.button("Reset to Identity").clicked() {
+                self.lut = Lut3d::identity(CLUT_EDITOR_SIZE);
+                self.depth = [CLUT_EDITOR_SIZE / 2; 3];
+                self.selected = None;
+                changed = true;
+            }
+            if ui.button("Load .cube").clicked() {
+                let path = rfd::FileDialog::new()
+                    .add_filter("Cube LUT", &["cube"])
+                    .pick_file();
+                if let Some(path) = path {
+                    match read_cube_file(&path) {
+                        Ok(lut) => {
+                            self.depth = [lut.size / 2; 3];
+                            self.lut = lut;
+                            self.selected = None;
+                            self.error = None;
+                            changed = true;
                         }
+                        Err(e) => self.error = Some(format!("{}: {e}", path.display())),
                     }
-                    // Assume exr, for now :)
-                    let image_path = root_dir.join(format!("{}.exr", self.file_path));
+                }
+            }
+            if ui.button("Save as .cube").clicked() {
+                let path = rfd::FileDialog::new()
+                    .add_filter("Cube LUT", &["cube"])
+                    .save_file();
+                if let Some(path) = path {
+                    self.error = write_cube_file(&path, &self.lut)
+                        .err()
+                        .map(|e| format!("{}: {e}", path.display()));
+                }
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
 
-                    match write_as_exr_image(
-                        &image_path,
-                        RENDER_BUFFER_WIDTH as usize,
-                        RENDER_BUFFER_HEIGHT as usize,
-                        &self.render_buffer_pointer,
-                    ) {
-                        Ok(_) => {
-                            eprintln!("Image saved to {}", image_path.display());
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to save image: {e:?}");
+        if changed {
+            self.textures = None;
+        }
+        let textures = match &self.textures {
+            Some(textures) => textures.clone(),
+            None => {
+                let textures = self.slice_textures(ui.ctx());
+                self.textures = Some(textures.clone());
+                textures
+            }
+        };
+        let last = self.lut.size - 1;
+        ui.horizontal(|ui| {
+            for (slice, texture) in textures.iter().enumerate() {
+                let (axes, fixed) = CLUT_SLICES[slice];
+                ui.vertical(|ui| {
+                    ui.label(format!(
+                        "{} / {}",
+                        CHANNEL_NAMES[axes[0]], CHANNEL_NAMES[axes[1]]
+                    ));
+                    let response = ui.add(
+                        egui::Image::new(texture, egui::vec2(CLUT_SLICE_SIZE, CLUT_SLICE_SIZE))
+                            .sense(egui::Sense::click()),
+                    );
+                    let node_size = response.rect.width() / self.lut.size as f32;
+                    if response.clicked() {
+                        if let Some(position) = response.interact_pointer_pos() {
+                            let cell = (position - response.rect.min) / node_size;
+                            let x = (cell.x.max(0.0) as usize).min(last);
+                            let y = (cell.y.max(0.0) as usize).min(last);
+                            self.selected = Some(self.slice_node(slice, x, y));
                         }
                     }
+                    // Outline the selected node when it's on this slice
+                    if let Some(node) = self
+                        .selected
+                        .filter(|node| node[fixed] == self.depth[fixed])
+                    {
+                        let min = response.rect.min
+                            + egui::vec2(
+                                node[axes[0]] as f32 * node_size,
+                                (last - node[axes[1]]) as f32 * node_size,
+                            );
+                        ui.painter().rect_stroke(
+                            egui::Rect::from_min_size(min, egui::vec2(node_size, node_size)),
+                            0.0,
+                            egui::Stroke::new(2.0, egui::Color32::WHITE),
+                        );
+                    }
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.depth[fixed], 0..=last)
+                                .text(CHANNEL_NAMES[fixed]),
+                        )
+                        .changed()
+                    {
+                        self.textures = None;
+                    }
+                });
+            }
+        });
+
+        match self.selected {
+            Some(node) => {
+                ui.horizontal(|ui| {
+                    let step = 1.0 / last as f32;
+                    let input = node.map(|index| index as f32 * step);
+                    ui.label(format!(
+                        "Node ({:.3}, {:.3}, {:.3})",
+                        input[0], input[1], input[2]
+                    ));
+                    let mut output = self.lut.node(node);
+                    if ui.color_edit_button_rgb(&mut output).changed() {
+                        self.lut.set_node(node, output);
+                        self.textures = None;
+                        changed = true;
+                    }
+                });
+            }
+            None => {
+                ui.label("Click a slice to pick a node");
+            }
+        }
+
+        if changed {
+            send_event(
+                event_proxy,
+                UserEvent::SetLut(self.enabled.then(|| self.lut.clone())),
+            );
+        }
+    }
+}
+
+/// Custom attributes written in the header of saved EXR files, shown in the "EXR Metadata" window
+/// Controls of the EXR sequence player
+struct PlaybackPanel {
+    settings: PlaybackSettings,
+    // None until a sequence is opened
+    status: Option<PlaybackStatus>,
+    directory: Option<PathBuf>,
+}
+
+impl PlaybackPanel {
+    fn new() -> Self {
+        Self {
+            settings: PlaybackSettings::default(),
+            status: None,
+            directory: None,
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, event_proxy: &EventLoopProxy<UserEvent>) {
+        ui.horizontal(|ui| {
+            if ui.button("Open Sequence...").clicked() {
+                if let Some(directory) = rfd::FileDialog::new().pick_folder() {
+                    self.directory = Some(directory.clone());
+                    send_event(event_proxy, UserEvent::OpenSequence(directory));
+                }
+            }
+            if let Some(directory) = &self.directory {
+                ui.label(directory.display().to_string());
+            }
+        });
+
+        let Some(status) = self.status else {
+            ui.label("No sequence loaded");
+            return;
+        };
+        // Playback stops by itself on the last frame
+        self.settings.playing = status.playing;
+        let previous = self.settings;
+
+        ui.horizontal(|ui| {
+            let label = if self.settings.playing {
+                "⏸ Pause"
+            } else {
+                "▶ Play"
+            };
+            if ui.button(label).clicked() {
+                self.settings.playing = !self.settings.playing;
+            }
+            ui.label(format!(
+                "Frame {} / {}",
+                status.current_frame + 1,
+                status.frame_count
+            ));
+            if status.loading {
+                ui.spinner();
+            }
+        });
+
+        let mut frame = status.current_frame;
+        if ui
+            .add(egui::Slider::new(&mut frame, 0..=status.frame_count - 1).show_value(false))
+            .changed()
+        {
+            send_event(event_proxy, UserEvent::SeekSequence(frame));
+        }
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.settings.looping, "Loop");
+            egui::ComboBox::from_label("Speed")
+                .selected_text(format!("{}×", self.settings.speed))
+                .show_ui(ui, |ui| {
+                    for speed in PLAYBACK_SPEEDS {
+                        ui.selectable_value(&mut self.settings.speed, speed, format!("{speed}×"));
+                    }
+                });
+        });
+        ui.add(egui::Slider::new(&mut self.settings.fps, 1.0..=60.0).text("FPS"));
+        ui.add(
+            egui::Slider::new(&mut self.settings.max_cache_mb, 64..=8192)
+                .logarithmic(true)
+                .text("Cache")
+                .suffix(" MB"),
+        );
+        ui.label(format!("{} frames cached", status.cached_frames));
+
+        if self.settings != previous {
+            send_event(event_proxy, UserEvent::SetPlayback(self.settings));
+        }
+    }
+}
+
+struct MetadataEditor {
+    entries: Vec<(String, String)>,
+    // Well-known attributes of the last file the metadata was loaded from
+    standard: Vec<(String, String)>,
+    warnings: Vec<String>,
+    load_error: Option<String>,
+}
+
+impl MetadataEditor {
+    fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            standard: Vec::new(),
+            warnings: Vec::new(),
+            load_error: None,
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, event_proxy: &EventLoopProxy<UserEvent>) {
+        let previous = self.entries.clone();
+
+        let mut removed = None;
+        egui::Grid::new("metadata_grid")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Key");
+                ui.strong("Value");
+                ui.end_row();
+                for (index, (key, value)) in self.entries.iter_mut().enumerate() {
+                    ui.text_edit_singleline(key);
+                    ui.text_edit_singleline(value);
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some(index) = removed {
+            self.entries.remove(index);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                self.entries.push((String::new(), String::new()));
+            }
+            if ui.button("Load from EXR...").clicked() {
+                self.load();
+            }
+        });
+
+        if self.entries != previous {
+            let (metadata, warnings) = MetadataMap::from_entries(&self.entries);
+            self.warnings = warnings;
+            send_event(event_proxy, UserEvent::SetExrMetadata(metadata));
+        }
+
+        for warning in &self.warnings {
+            ui.colored_label(egui::Color32::YELLOW, format!("⚠ {warning}"));
+        }
+        if let Some(error) = &self.load_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        if !self.standard.is_empty() {
+            ui.collapsing("Standard Attributes", |ui| {
+                egui::Grid::new("standard_attributes_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (name, value) in &self.standard {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+            });
+        }
+    }
+
+    /// Replace the entries with the custom attributes of an EXR file picked by the user
+    fn load(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("OpenEXR", &["exr"])
+            .set_directory(images_dir())
+            .pick_file()
+        else {
+            return;
+        };
+        match read_exr_metadata(&path) {
+            Ok(metadata) => {
+                // Sent by the caller, which sees the entries changed
+                self.entries = metadata.custom;
+                self.standard = metadata.standard;
+                self.load_error = None;
+            }
+            Err(e) => {
+                self.load_error = Some(format!(
+                    "Failed to read {}: {}",
+                    path.display(),
+                    image_error_message(&e)
+                ));
+            }
+        }
+    }
+}
+
+/// Optional adapter features worth knowing about when comparing machines
+const REPORTED_FEATURES: [(wgpu::Features, &str); 6] = [
+    (wgpu::Features::SHADER_FLOAT64, "64-bit floats in shaders"),
+    (
+        wgpu::Features::TEXTURE_FORMAT_16BIT_NORM,
+        "16-bit normalized textures",
+    ),
+    (
+        wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+        "Adapter specific format features",
+    ),
+    (
+        wgpu::Features::TEXTURE_COMPRESSION_BC,
+        "BC texture compression",
+    ),
+    (wgpu::Features::TIMESTAMP_QUERY, "Timestamp queries"),
+    (wgpu::Features::PUSH_CONSTANTS, "Push constants"),
+];
+
+/// Read-only report of the wgpu adapter capabilities, shown in the "Device Info" window
+struct DeviceInfoPanel {
+    report: String,
+    // Settings in use that the adapter doesn't support
+    warnings: Vec<String>,
+}
+
+impl DeviceInfoPanel {
+    /// Query the adapter once, it doesn't change while the application runs.
+    /// `surface_format` and `msaa` are the ones in use, which differ from the config
+    /// when unsupported.
+    fn new(
+        pixels: &pixels::Pixels,
+        config: &Config,
+        surface_format: SurfaceFormat,
+        msaa: MsaaConfig,
+    ) -> Self {
+        let adapter = pixels.adapter();
+        let info = adapter.get_info();
+        let limits = pixels.device().limits();
+        let features = adapter.features();
+        let half_float = adapter.get_texture_format_features(wgpu::TextureFormat::Rgba16Float);
+
+        let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+        let mut report = format!(
+            "Adapter: {} ({:?})\n\
+             Driver: {} {}\n\
+             Backend: {:?}\n\
+             Max texture size: {}\n\
+             Max compute workgroups per dimension: {}\n\
+             Max compute invocations per workgroup: {}\n\
+             Half float (Rgba16Float) textures: {}, filterable: {}\n\
+             \n\
+             Optional features:\n",
+            info.name,
+            info.device_type,
+            info.driver,
+            info.driver_info,
+            info.backend,
+            limits.max_texture_dimension_2d,
+            limits.max_compute_workgroups_per_dimension,
+            limits.max_compute_invocations_per_workgroup,
+            yes_no(
+                half_float
+                    .allowed_usages
+                    .contains(wgpu::TextureUsages::TEXTURE_BINDING)
+            ),
+            yes_no(
+                half_float
+                    .flags
+                    .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+            ),
+        );
+        for (feature, label) in REPORTED_FEATURES {
+            report.push_str(&format!(
+                "  {label}: {}\n",
+                yes_no(features.contains(feature))
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        if surface_format != config.surface_format {
+            warnings.push(format!(
+                "The surface format {:?} from {CONFIG_PATH} isn't supported by this device. \
+                 {surface_format:?} is used instead.",
+                config.surface_format,
+            ));
+        }
+        if msaa != config.msaa {
+            warnings.push(format!(
+                "{} MSAA samples from {CONFIG_PATH} aren't supported by this device. \
+                 MSAA is turned off instead.",
+                config.msaa.sample_count,
+            ));
+        }
+
+        Self { report, warnings }
+    }
+
+    fn ui(&self, ui: &mut egui::Ui) {
+        for warning in &self.warnings {
+            ui.colored_label(egui::Color32::YELLOW, format!("⚠ {warning}"));
+        }
+        // A `&str` makes the text selectable but not editable
+        ui.add(
+            egui::TextEdit::multiline(&mut self.report.as_str())
+                .font(egui::TextStyle::Monospace)
+                .desired_width(f32::INFINITY),
+        );
+    }
+}
+
+/// Startup settings edited in the "Settings" window, saved to the config on exit
+struct SettingsPanel {
+    msaa: MsaaConfig,
+    // Physical pixels per inch of the monitor
+    window_dpi: f32,
+    target_fps: TargetFps,
+    // Used by the renderer since startup
+    msaa_in_use: MsaaConfig,
+    // Sample counts the device can render with
+    supported_sample_counts: Vec<u32>,
+}
+
+impl SettingsPanel {
+    /// `format` is the one egui is drawn to
+    fn new(
+        adapter: &wgpu::Adapter,
+        format: wgpu::TextureFormat,
+        config: &Config,
+        msaa_in_use: MsaaConfig,
+    ) -> Self {
+        let supported_sample_counts = MsaaConfig::SAMPLE_COUNTS
+            .into_iter()
+            .filter(|&sample_count| MsaaConfig { sample_count }.is_supported(adapter, format))
+            .collect();
+
+        Self {
+            msaa: config.msaa,
+            window_dpi: config.window_dpi,
+            target_fps: config.target_fps,
+            msaa_in_use,
+            supported_sample_counts,
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("MSAA:");
+            for &sample_count in &self.supported_sample_counts {
+                let label = match sample_count {
+                    1 => "Off".to_owned(),
+                    _ => format!("{sample_count}x"),
+                };
+                ui.selectable_value(&mut self.msaa.sample_count, sample_count, label);
+            }
+        })
+        .response
+        .on_hover_text("Antialiasing of the widgets and text");
+        if self.msaa != self.msaa_in_use {
+            ui.colored_label(egui::Color32::YELLOW, "⚠ Takes effect after a restart");
+        }
+        ui.add(
+            egui::Slider::new(&mut self.window_dpi, 48.0..=400.0)
+                .text("Monitor DPI")
+                .suffix(" px/in"),
+        )
+        .on_hover_text("Physical pixels per inch, to show prints at their real size");
+        egui::ComboBox::from_label("Target FPS")
+            .selected_text(self.target_fps.label())
+            .show_ui(ui, |ui| {
+                for target_fps in TargetFps::ALL {
+                    ui.selectable_value(&mut self.target_fps, target_fps, target_fps.label());
+                }
+            })
+            .response
+            .on_hover_text("Cap on the redraws per second while animating");
+    }
+}
+
+/// Paper sizes offered by the print preview
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PaperSize {
+    A3,
+    A4,
+    A5,
+    Letter,
+    Legal,
+    Photo4x6,
+    Photo5x7,
+    Photo8x10,
+}
+
+impl PaperSize {
+    const ALL: [PaperSize; 8] = [
+        PaperSize::A3,
+        PaperSize::A4,
+        PaperSize::A5,
+        PaperSize::Letter,
+        PaperSize::Legal,
+        PaperSize::Photo4x6,
+        PaperSize::Photo5x7,
+        PaperSize::Photo8x10,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            PaperSize::A3 => "A3",
+            PaperSize::A4 => "A4",
+            PaperSize::A5 => "A5",
+            PaperSize::Letter => "Letter",
+            PaperSize::Legal => "Legal",
+            PaperSize::Photo4x6 => "4×6 in",
+            PaperSize::Photo5x7 => "5×7 in",
+            PaperSize::Photo8x10 => "8×10 in",
+        }
+    }
+
+    /// Portrait width and height in inches
+    fn inches(&self) -> [f32; 2] {
+        let mm = |w: f32, h: f32| [w / MM_PER_INCH, h / MM_PER_INCH];
+        match self {
+            PaperSize::A3 => mm(297.0, 420.0),
+            PaperSize::A4 => mm(210.0, 297.0),
+            PaperSize::A5 => mm(148.0, 210.0),
+            PaperSize::Letter => [8.5, 11.0],
+            PaperSize::Legal => [8.5, 14.0],
+            PaperSize::Photo4x6 => [4.0, 6.0],
+            PaperSize::Photo5x7 => [5.0, 7.0],
+            PaperSize::Photo8x10 => [8.0, 10.0],
+        }
+    }
+}
+
+const MM_PER_INCH: f32 = 25.4;
+
+/// Resolution under which prints look soft when viewed up close
+const MIN_PRINT_PPI: f32 = 150.0;
+
+/// Physical size of the framebuffer once printed, shown in the "Print Preview" window and
+/// as an overlay of its real size on the monitor
+struct PrintPreview {
+    // Pixels per inch of the print
+    dpi: f32,
+    paper: Option<PaperSize>,
+    show_overlay: bool,
+}
+
+impl PrintPreview {
+    fn new() -> Self {
+        Self {
+            dpi: 300.0,
+            paper: None,
+            show_overlay: true,
+        }
+    }
+
+    /// Width and height of the print in inches
+    fn print_inches(&self) -> [f32; 2] {
+        [
+            RENDER_BUFFER_WIDTH as f32 / self.dpi,
+            RENDER_BUFFER_HEIGHT as f32 / self.dpi,
+        ]
+    }
+
+    /// Paper in the orientation of the image, whether the print fits on it at its DPI,
+    /// and the resolution of the image scaled to fill the paper
+    fn paper_fit(&self) -> Option<([f32; 2], bool, f32)> {
+        let [short, long] = self.paper?.inches();
+        let paper = if RENDER_BUFFER_WIDTH >= RENDER_BUFFER_HEIGHT {
+            [long, short]
+        } else {
+            [short, long]
+        };
+        let print = self.print_inches();
+        let fits = print[0] <= paper[0] && print[1] <= paper[1];
+        let ppi =
+            (RENDER_BUFFER_WIDTH as f32 / paper[0]).min(RENDER_BUFFER_HEIGHT as f32 / paper[1]);
+        Some((paper, fits, ppi))
+    }
+
+    /// Resolution the image is printed at, scaled to the paper if one is picked
+    fn effective_ppi(&self) -> f32 {
+        self.paper_fit().map_or(self.dpi, |(_, _, ppi)| ppi)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::Slider::new(&mut self.dpi, 72.0..=1200.0)
+                .logarithmic(true)
+                .text("Print DPI"),
+        );
+        egui::ComboBox::from_label("Paper")
+            .selected_text(self.paper.map_or("None", |paper| paper.label()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.paper, None, "None");
+                for paper in PaperSize::ALL {
+                    ui.selectable_value(&mut self.paper, Some(paper), paper.label());
+                }
+            });
+        ui.checkbox(&mut self.show_overlay, "Show actual size");
+
+        let [width, height] = self.print_inches();
+        ui.label(format!(
+            "{RENDER_BUFFER_WIDTH} × {RENDER_BUFFER_HEIGHT} px\n\
+             {width:.2} × {height:.2} in\n\
+             {:.1} × {:.1} cm at {:.0} DPI",
+            width * 2.54,
+            height * 2.54,
+            self.dpi,
+        ));
+        if let Some((_, fits, ppi)) = self.paper_fit() {
+            if fits {
+                ui.label("Fits on the paper without scaling");
+            } else {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("Doesn't fit, scaled to the paper it prints at {ppi:.0} PPI"),
+                );
+            }
+        }
+        if self.effective_ppi() < MIN_PRINT_PPI {
+            ui.colored_label(
+                egui::Color32::RED,
+                format!("⚠ Below {MIN_PRINT_PPI:.0} PPI, the print will look soft"),
+            );
+        }
+    }
+
+    /// Shade the footprint of the print, and of the paper, at their size on a monitor of
+    /// `window_dpi` physical pixels per inch
+    fn paint(&self, ctx: &Context, window_dpi: f32) {
+        if !self.show_overlay {
+            return;
+        }
+        let points_per_inch = window_dpi / ctx.pixels_per_point();
+        let center = ctx.input().screen_rect().center();
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Background,
+            egui::Id::new("print_preview"),
+        ));
+
+        if let Some((paper, _, _)) = self.paper_fit() {
+            let paper = egui::Rect::from_center_size(
+                center,
+                egui::vec2(paper[0], paper[1]) * points_per_inch,
+            );
+            painter.rect_stroke(paper, 0.0, egui::Stroke::new(1.0, egui::Color32::GRAY));
+        }
+
+        let [width, height] = self.print_inches();
+        let print =
+            egui::Rect::from_center_size(center, egui::vec2(width, height) * points_per_inch);
+        let color = if self.effective_ppi() < MIN_PRINT_PPI {
+            egui::Color32::RED
+        } else {
+            egui::Color32::WHITE
+        };
+        painter.rect(
+            print,
+            0.0,
+            color.linear_multiply(0.15),
+            egui::Stroke::new(1.0, color),
+        );
+        painter.text(
+            print.left_top() + egui::vec2(4.0, 4.0),
+            egui::Align2::LEFT_TOP,
+            format!(
+                "{:.1} × {:.1} cm at {:.0} DPI",
+                width * 2.54,
+                height * 2.54,
+                self.dpi
+            ),
+            egui::FontId::proportional(14.0),
+            color,
+        );
+    }
+}
+
+/// Pixels skipped between two samples of the gamut diagram, by default
+const GAMUT_MIP_LEVEL: usize = 2;
+
+/// CIE 1931 xy chromaticity diagram of the displayed image, shown in the "Gamut Diagram" window
+struct GamutDiagram {
+    // Chromaticity and hue of the sampled pixels
+    samples: Vec<([f32; 2], egui::Color32)>,
+    // Generation of the display buffer the samples were taken from
+    generation: Option<u64>,
+    // Mip level of the display buffer the samples are taken from
+    lod: usize,
+    // Number of levels of the last sampled pyramid
+    num_levels: usize,
+    show_display_p3: bool,
+    show_rec2020: bool,
+    show_dci_p3: bool,
+}
+
+impl GamutDiagram {
+    fn new() -> Self {
+        Self {
+            samples: Vec::new(),
+            generation: None,
+            lod: GAMUT_MIP_LEVEL,
+            num_levels: 1,
+            show_display_p3: false,
+            show_rec2020: false,
+            show_dci_p3: false,
+        }
+    }
+
+    /// Sample every pixel of a mip level of the linear ACEScg display buffer,
+    /// unless this generation was already sampled
+    fn update(&mut self, generation: u64, mips: &MipPyramid) {
+        if self.generation == Some(generation) {
+            return;
+        }
+        self.generation = Some(generation);
+        self.num_levels = mips.num_levels();
+        self.samples = mips
+            .level(self.lod)
+            .chunks_exact(4)
+            .filter_map(|pixel| {
+                let rgb = [pixel[0], pixel[1], pixel[2]];
+                let xy = acescg_to_xy(rgb)?;
+                // Normalize the brightness so only the hue shows
+                let max = rgb.iter().copied().fold(0.0, f32::max);
+                let [r, g, b] = rgb.map(|c| (c / max).clamp(0.0, 1.0));
+                Some((xy, egui::Rgba::from_rgb(r, g, b).into()))
+            })
+            .collect();
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let previous_lod = self.lod;
+        ui.add(
+            egui::Slider::new(&mut self.lod, 0..=self.num_levels.saturating_sub(1))
+                .text("Mip level"),
+        )
+        .on_hover_text("Each level averages 2x2 pixels of the previous one");
+        if self.lod != previous_lod {
+            // Resample on the next frame
+            self.generation = None;
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_display_p3, "Display P3");
+            ui.checkbox(&mut self.show_rec2020, "Rec. 2020");
+            ui.checkbox(&mut self.show_dci_p3, "DCI-P3");
+        });
+
+        let (response, painter) =
+            ui.allocate_painter(egui::vec2(320.0, 360.0), egui::Sense::hover());
+        let rect = response.rect;
+        // Show x in [0, 0.8] and y in [0, 0.9]
+        let to_screen = |[x, y]: [f32; 2]| {
+            egui::pos2(
+                rect.left() + x / 0.8 * rect.width(),
+                rect.bottom() - y / 0.9 * rect.height(),
+            )
+        };
+        let outline = |points: &[[f32; 2]], color: egui::Color32| {
+            let mut points: Vec<egui::Pos2> = points.iter().copied().map(to_screen).collect();
+            // Close the shape
+            points.push(points[0]);
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
+        };
+
+        painter.rect_filled(rect, 0.0, egui::Color32::from_gray(16));
+        // The straight segment closing the horseshoe is the line of purples
+        outline(&spectral_locus(), egui::Color32::WHITE);
+        for (xy, color) in &self.samples {
+            painter.circle_filled(to_screen(*xy), 1.5, *color);
+        }
+
+        let mut gamuts = vec![
+            ("sRGB", SRGB_PRIMARIES_XY, egui::Color32::LIGHT_GRAY),
+            ("ACEScg", ACESCG_PRIMARIES_XY, egui::Color32::LIGHT_BLUE),
+        ];
+        if self.show_display_p3 {
+            gamuts.push(("Display P3", DISPLAY_P3_PRIMARIES_XY, egui::Color32::GOLD));
+        }
+        if self.show_rec2020 {
+            gamuts.push((
+                "Rec. 2020",
+                REC2020_PRIMARIES_XY,
+                egui::Color32::LIGHT_GREEN,
+            ));
+        }
+        if self.show_dci_p3 {
+            gamuts.push(("DCI-P3", DCI_P3_PRIMARIES_XY, egui::Color32::LIGHT_RED));
+        }
+        for (_, primaries, color) in &gamuts {
+            outline(primaries, *color);
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            for (name, _, color) in &gamuts {
+                ui.colored_label(*color, *name);
+            }
+        });
+    }
+}
+
+/// Converts an HDRI to cubemap faces, shown in the "Cubemap" window
+struct CubemapTool {
+    // HDRI and the file it was loaded from
+    source: Option<(PathBuf, HdriMap)>,
+    face_size: usize,
+    faces: Option<CubemapFaces>,
+}
+
+impl CubemapTool {
+    fn new() -> Self {
+        Self {
+            source: None,
+            face_size: 256,
+            faces: None,
+        }
+    }
+
+    /// Load the HDRI at `path` and preview its faces, for scenes that use it
+    fn load(
+        &mut self,
+        path: &Path,
+        event_proxy: &EventLoopProxy<UserEvent>,
+    ) -> Result<(), ImageError> {
+        self.source = Some((path.to_owned(), HdriMap::load(path)?));
+        self.convert(event_proxy);
+        Ok(())
+    }
+
+    /// Convert the source to faces of `face_size` pixels and preview them
+    fn convert(&mut self, event_proxy: &EventLoopProxy<UserEvent>) {
+        let Some((path, source)) = &self.source else {
+            return;
+        };
+        let faces = equirect_to_cubemap(source, self.face_size);
+        send_event(
+            event_proxy,
+            UserEvent::SetCubemap {
+                faces: Box::new(faces.clone()),
+                hdri: path.clone(),
+            },
+        );
+        self.faces = Some(faces);
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, file_path: &str, event_proxy: &EventLoopProxy<UserEvent>) {
+        if ui.button("Load HDRI").clicked() {
+            if let Some(source) = pick_hdri() {
+                self.source = Some(source);
+                self.faces = None;
+            }
+        }
+
+        let Some((_, source)) = &self.source else {
+            ui.label("Load an equirectangular HDRI to convert it");
+            return;
+        };
+        ui.label(format!("Source: {}x{}", source.width, source.height));
+
+        ui.add(egui::Slider::new(&mut self.face_size, 64..=2048).text("Face size"));
+
+        let mut convert = false;
+        ui.horizontal(|ui| {
+            convert = ui.button("Convert").clicked();
+
+            if let Some(faces) = &self.faces {
+                if ui.button("Export Faces").clicked() {
+                    let name = if file_path.is_empty() {
+                        "cubemap"
+                    } else {
+                        file_path
+                    };
+                    if let Err(e) = write_cubemap_faces(images_dir().join(name), faces) {
+                        error!("Failed to export cubemap: {e:?}");
+                    }
+                }
+            }
+        });
+        if convert {
+            self.convert(event_proxy);
+        }
+    }
+}
+
+/// Size of a node box in the graph editor, in points
+const NODE_SIZE: egui::Vec2 = egui::vec2(130.0, 56.0);
+/// Radius of the input and output ports of a node, in points
+const NODE_PORT_RADIUS: f32 = 6.0;
+
+fn node_rect(origin: egui::Pos2, node: &Node) -> egui::Rect {
+    egui::Rect::from_min_size(
+        origin + egui::vec2(node.position[0], node.position[1]),
+        NODE_SIZE,
+    )
+}
+
+/// Inputs are spread along the left side of the node
+fn node_input_port(origin: egui::Pos2, node: &Node, input: usize) -> egui::Pos2 {
+    let rect = node_rect(origin, node);
+    let spacing = rect.height() / (node.kind.input_count() + 1) as f32;
+    egui::pos2(rect.left(), rect.top() + spacing * (input + 1) as f32)
+}
+
+fn node_output_port(origin: egui::Pos2, node: &Node) -> egui::Pos2 {
+    let rect = node_rect(origin, node);
+    egui::pos2(rect.right(), rect.center().y)
+}
+
+/// What a drag started on in the graph editor
+#[derive(Clone, Copy)]
+enum NodeDrag {
+    Move(NodeId),
+    /// A new edge, out of this node
+    Wire(NodeId),
+}
+
+/// Drag-and-drop editor of the compositing graph, shown in the "Node Graph" window
+struct NodeGraphEditor {
+    graph: NodeGraph,
+    selected: Option<NodeId>,
+    drag: Option<NodeDrag>,
+    // WGSL node open in the shader editor, with the text being edited
+    shader_draft: Option<(NodeId, String)>,
+    // Why the shader of each WGSL node didn't compile
+    shader_errors: HashMap<NodeId, String>,
+}
+
+impl NodeGraphEditor {
+    fn new() -> Self {
+        Self {
+            graph: NodeGraph::default(),
+            selected: None,
+            drag: None,
+            shader_draft: None,
+            shader_errors: HashMap::new(),
+        }
+    }
+
+    /// "WGSL Shader" window, editing the shader of a WGSL node until it's closed
+    fn shader_editor_ui(&mut self, ctx: &Context, event_proxy: &EventLoopProxy<UserEvent>) {
+        let Some((id, draft)) = &mut self.shader_draft else {
+            return;
+        };
+        let id = *id;
+        let mut open = true;
+        let mut compile = false;
+        egui::Window::new("WGSL Shader")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                ui.collapsing("Prelude", |ui| {
+                    ui.label("Declared before the shader, error line numbers include it");
+                    ui.monospace(WGSL_PRELUDE);
+                });
+                let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+                    let mut job = highlight_wgsl(text);
+                    job.wrap.max_width = wrap_width;
+                    ui.fonts().layout_job(job)
+                };
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(draft)
+                                .code_editor()
+                                .desired_rows(16)
+                                .desired_width(f32::INFINITY)
+                                .layouter(&mut layouter),
+                        );
+                    });
+                compile = ui.button("Compile").clicked();
+                if let Some(error) = self.shader_errors.get(&id) {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+
+        if compile {
+            if let Some(NodeKind::Wgsl { source }) =
+                self.graph.node_mut(id).map(|node| &mut node.kind)
+            {
+                *source = draft.clone();
+                send_event(event_proxy, UserEvent::SetNodeGraph(self.graph.clone()));
+            }
+        }
+        if !open || self.graph.node(id).is_none() {
+            self.shader_draft = None;
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, event_proxy: &EventLoopProxy<UserEvent>) {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Add:");
+            let kinds = [
+                NodeKind::Gradient {
+                    color_a: [0.0; 3],
+                    color_b: [1.0; 3],
+                },
+                NodeKind::GaussianBlur { sigma: 2.0 },
+                NodeKind::Merge { factor: 0.5 },
+                NodeKind::Wgsl {
+                    source: DEFAULT_WGSL_SHADER.to_owned(),
+                },
+                NodeKind::Output,
+            ];
+            for kind in kinds {
+                if ui.button(kind.label()).clicked() {
+                    self.selected = Some(self.graph.add_node(kind, [20.0, 20.0]));
+                    changed = true;
+                }
+            }
+        });
+        ui.label("Drag from an output to an input to connect them. Right click an input to disconnect it, or a node to remove it.");
+
+        let (response, painter) = ui.allocate_painter(
+            egui::vec2(ui.available_width(), 320.0),
+            egui::Sense::click_and_drag(),
+        );
+        let origin = response.rect.min;
+        let pointer = ui.ctx().pointer_latest_pos();
+
+        let hit_node = |graph: &NodeGraph, pos: egui::Pos2| {
+            // Last drawn is on top
+            graph
+                .nodes
+                .iter()
+                .rev()
+                .find(|node| node_rect(origin, node).contains(pos))
+                .map(|node| node.id)
+        };
+        let hit_output = |graph: &NodeGraph, pos: egui::Pos2| {
+            graph
+                .nodes
+                .iter()
+                .filter(|node| node.kind.has_output())
+                .find(|node| node_output_port(origin, node).distance(pos) <= NODE_PORT_RADIUS * 1.5)
+                .map(|node| node.id)
+        };
+        let hit_input = |graph: &NodeGraph, pos: egui::Pos2| {
+            graph.nodes.iter().find_map(|node| {
+                (0..node.kind.input_count())
+                    .find(|&input| {
+                        node_input_port(origin, node, input).distance(pos) <= NODE_PORT_RADIUS * 1.5
+                    })
+                    .map(|input| (node.id, input))
+            })
+        };
+
+        if let Some(pos) = response.interact_pointer_pos() {
+            if response.drag_started() {
+                self.drag = match hit_output(&self.graph, pos) {
+                    Some(id) => Some(NodeDrag::Wire(id)),
+                    None => hit_node(&self.graph, pos).map(NodeDrag::Move),
+                };
+                if let Some(NodeDrag::Move(id)) = self.drag {
+                    self.selected = Some(id);
+                }
+            }
+            if response.clicked() {
+                self.selected = hit_node(&self.graph, pos);
+            }
+            if response.secondary_clicked() {
+                if let Some((id, input)) = hit_input(&self.graph, pos) {
+                    self.graph.disconnect(id, input);
+                    changed = true;
+                } else if let Some(id) = hit_node(&self.graph, pos) {
+                    self.graph.remove_node(id);
+                    if self.selected == Some(id) {
+                        self.selected = None;
+                    }
+                    changed = true;
+                }
+            }
+        }
+        if response.dragged() {
+            if let Some(NodeDrag::Move(id)) = self.drag {
+                if let Some(node) = self.graph.node_mut(id) {
+                    let delta = response.drag_delta();
+                    node.position[0] += delta.x;
+                    node.position[1] += delta.y;
+                }
+            }
+        }
+        if response.drag_released() {
+            match (self.drag, pointer) {
+                (Some(NodeDrag::Wire(from)), Some(pos)) => {
+                    if let Some((to, input)) = hit_input(&self.graph, pos) {
+                        changed |= self.graph.connect(from, to, input);
+                    }
+                }
+                // Sent once the node is dropped, so the scene keeps the layout
+                (Some(NodeDrag::Move(_)), _) => changed = true,
+                _ => {}
+            }
+            self.drag = None;
+        }
+
+        painter.rect_filled(response.rect, 0.0, egui::Color32::from_gray(24));
+        let edge_stroke = egui::Stroke::new(2.0, egui::Color32::from_gray(160));
+        for edge in &self.graph.edges {
+            if let (Some(from), Some(to)) = (self.graph.node(edge.from), self.graph.node(edge.to)) {
+                painter.line_segment(
+                    [
+                        node_output_port(origin, from),
+                        node_input_port(origin, to, edge.input),
+                    ],
+                    edge_stroke,
+                );
+            }
+        }
+        if let (Some(NodeDrag::Wire(from)), Some(pos)) = (self.drag, pointer) {
+            if let Some(from) = self.graph.node(from) {
+                painter.line_segment([node_output_port(origin, from), pos], edge_stroke);
+            }
+        }
+        for node in &self.graph.nodes {
+            let rect = node_rect(origin, node);
+            let outline = if self.selected == Some(node.id) {
+                egui::Color32::WHITE
+            } else {
+                egui::Color32::from_gray(90)
+            };
+            painter.rect(
+                rect,
+                4.0,
+                egui::Color32::from_gray(48),
+                egui::Stroke::new(1.5, outline),
+            );
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                node.kind.label(),
+                egui::FontId::proportional(14.0),
+                egui::Color32::WHITE,
+            );
+            let port_stroke = egui::Stroke::new(1.5, egui::Color32::BLACK);
+            for input in 0..node.kind.input_count() {
+                painter.circle(
+                    node_input_port(origin, node, input),
+                    NODE_PORT_RADIUS,
+                    egui::Color32::LIGHT_BLUE,
+                    port_stroke,
+                );
+            }
+            if node.kind.has_output() {
+                painter.circle(
+                    node_output_port(origin, node),
+                    NODE_PORT_RADIUS,
+                    egui::Color32::GOLD,
+                    port_stroke,
+                );
+            }
+        }
+
+        if let Some(node) = self.selected.and_then(|id| self.graph.node_mut(id)) {
+            ui.separator();
+            ui.strong(node.kind.label());
+            let previous = node.kind.clone();
+            match &mut node.kind {
+                NodeKind::Gradient { color_a, color_b } => {
+                    ui.horizontal(|ui| {
+                        ui.label("Colors:");
+                        ui.color_edit_button_rgb(color_a);
+                        ui.color_edit_button_rgb(color_b);
+                    });
+                }
+                NodeKind::GaussianBlur { sigma } => {
+                    ui.add(egui::Slider::new(sigma, 0.0..=20.0).text("Sigma"));
+                }
+                NodeKind::Merge { factor } => {
+                    ui.add(egui::Slider::new(factor, 0.0..=1.0).text("Factor"));
+                }
+                NodeKind::Wgsl { source } => {
+                    ui.horizontal(|ui| {
+                        if ui.button("Edit Shader").clicked() {
+                            self.shader_draft = Some((node.id, source.clone()));
+                        }
+                        if self.shader_errors.contains_key(&node.id) {
+                            ui.colored_label(egui::Color32::RED, "Doesn't compile");
+                        }
+                    });
+                }
+                NodeKind::Output => {
+                    ui.label("Written to the framebuffer in the Node Graph render mode");
+                }
+            }
+            changed |= node.kind != previous;
+        }
+
+        if changed {
+            send_event(event_proxy, UserEvent::SetNodeGraph(self.graph.clone()));
+        }
+    }
+}
+
+/// Words colored as keywords by the shader editor
+const WGSL_KEYWORDS: [&str; 20] = [
+    "fn", "let", "var", "const", "return", "if", "else", "for", "loop", "while", "break",
+    "continue", "switch", "case", "default", "struct", "true", "false", "discard", "override",
+];
+
+/// Words colored as types by the shader editor
+const WGSL_TYPES: [&str; 16] = [
+    "f32",
+    "i32",
+    "u32",
+    "bool",
+    "vec2",
+    "vec3",
+    "vec4",
+    "mat2x2",
+    "mat3x3",
+    "mat4x4",
+    "array",
+    "sampler",
+    "texture_2d",
+    "vec2f",
+    "vec3f",
+    "vec4f",
+];
+
+/// Keyword coloring of WGSL source, for the shader editor
+fn highlight_wgsl(text: &str) -> egui::text::LayoutJob {
+    let font = egui::FontId::monospace(13.0);
+    let mut job = egui::text::LayoutJob::default();
+    let mut rest = text;
+    while let Some(first) = rest.chars().next() {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let (length, color) = if rest.starts_with("//") {
+            let length = rest.find('\n').unwrap_or(rest.len());
+            (length, egui::Color32::from_gray(120))
+        } else if is_word(first) || first == '@' {
+            let length = rest
+                .char_indices()
+                .skip(1)
+                .find(|&(_, c)| !is_word(c))
+                .map_or(rest.len(), |(i, _)| i);
+            let word = &rest[..length];
+            let color = if first == '@' {
+                egui::Color32::from_rgb(200, 160, 255)
+            } else if WGSL_KEYWORDS.contains(&word) {
+                egui::Color32::from_rgb(255, 140, 90)
+            } else if WGSL_TYPES.contains(&word) {
+                egui::Color32::from_rgb(90, 200, 255)
+            } else if first.is_ascii_digit() {
+                egui::Color32::from_rgb(180, 230, 140)
+            } else {
+                egui::Color32::LIGHT_GRAY
+            };
+            (length, color)
+        } else {
+            (first.len_utf8(), egui::Color32::LIGHT_GRAY)
+        };
+        job.append(
+            &rest[..length],
+            0.0,
+            egui::TextFormat::simple(font.clone(), color),
+        );
+        rest = &rest[length..];
+    }
+    job
+}
+
+/// Radius of the anchor handles, in points
+const GRADIENT_HANDLE_RADIUS: f32 = 7.0;
+
+/// Options of the random palettes, used by "Randomize Colors" and the gradient editor
+struct PaletteOptions {
+    // Incremented for every new palette
+    seed: u64,
+    saturation: f32,
+    lightness: f32,
+}
+
+impl PaletteOptions {
+    fn new() -> Self {
+        Self {
+            seed: 0,
+            saturation: 0.65,
+            lightness: 0.55,
+        }
+    }
+
+    /// A new palette of `n` ACEScg colors
+    fn generate(&mut self, n: usize) -> Vec<[f32; 3]> {
+        self.seed = self.seed.wrapping_add(1);
+        generate_palette(n, self.seed, self.saturation, self.lightness)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.saturation, 0.0..=1.0).text("Palette saturation"));
+        ui.add(egui::Slider::new(&mut self.lightness, 0.0..=1.0).text("Palette lightness"));
+    }
+}
+
+/// Below this Oklab chroma a color has no meaningful hue to build harmonies on
+const HARMONY_MIN_CHROMA: f32 = 0.01;
+
+/// Steps of the gray ramp shown instead of the harmonies of an achromatic color
+const HARMONY_GRAY_STEPS: usize = 7;
+
+/// Colors in harmony with the first scene color, found by rotating its Oklab hue.
+/// Clicking one makes it the second scene color.
+struct ColorHarmonyPanel {
+    // sRGB color the harmonies were computed for
+    source: Option<[u8; 3]>,
+    // Name of each harmony and its sRGB colors
+    harmonies: Vec<(&'static str, Vec<[u8; 3]>)>,
+}
+
+impl ColorHarmonyPanel {
+    fn new() -> Self {
+        Self {
+            source: None,
+            harmonies: Vec::new(),
+        }
+    }
+
+    /// Recompute the harmonies when `color` changed since the last frame
+    fn update(&mut self, color: [u8; 3]) {
+        if self.source == Some(color) {
+            return;
+        }
+        self.source = Some(color);
+
+        let linear = color.map(|c| srgb_to_linear(c as f32 / 255.0));
+        let [l, a, b] = linear_srgb_to_oklab(linear);
+        let chroma = a.hypot(b);
+        let to_srgb = |lab: [f32; 3]| {
+            oklab_to_linear_srgb(lab)
+                .map(|c| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8)
+        };
+
+        if chroma < HARMONY_MIN_CHROMA {
+            let ramp = (0..HARMONY_GRAY_STEPS)
+                .map(|step| to_srgb([step as f32 / (HARMONY_GRAY_STEPS - 1) as f32, 0.0, 0.0]))
+                .collect();
+            self.harmonies = vec![("Grays", ramp)];
+            return;
+        }
+
+        // Same lightness and chroma, only the hue turns
+        let hue = b.atan2(a).to_degrees();
+        let rotated = |offsets: &[f32]| {
+            offsets
+                .iter()
+                .map(|offset| {
+                    let (sin, cos) = (hue + offset).to_radians().sin_cos();
+                    to_srgb([l, chroma * cos, chroma * sin])
+                })
+                .collect()
+        };
+        self.harmonies = vec![
+            ("Complementary", rotated(&[180.0])),
+            ("Split complementary", rotated(&[150.0, -150.0])),
+            ("Triadic", rotated(&[120.0, -120.0])),
+            ("Analogous", rotated(&[-30.0, 30.0])),
+            ("Tetradic", rotated(&[90.0, 180.0, -90.0])),
+        ];
+    }
+
+    /// Show the harmonies as swatches, returns the one clicked
+    fn ui(&mut self, ui: &mut egui::Ui, color: [u8; 3]) -> Option<[u8; 3]> {
+        self.update(color);
+        let mut clicked = None;
+        egui::Grid::new("color_harmony_grid").show(ui, |ui| {
+            for (name, colors) in &self.harmonies {
+                ui.label(*name);
+                ui.horizontal(|ui| {
+                    for &[r, g, b] in colors {
+                        let (rect, response) =
+                            ui.allocate_exact_size(egui::vec2(24.0, 16.0), egui::Sense::click());
+                        ui.painter()
+                            .rect_filled(rect, 2.0, egui::Color32::from_rgb(r, g, b));
+                        if response
+                            .on_hover_text(format!(
+                                "#{r:02X}{g:02X}{b:02X}, click to use as Color B"
+                            ))
+                            .clicked()
+                        {
+                            clicked = Some([r, g, b]);
+                        }
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        clicked
+    }
+}
+
+/// Anchors of the multi-point gradient, editable as handles on top of the image
+struct GradientEditor {
+    anchors: Vec<GradientAnchor>,
+    // Anchor whose color is being edited
+    selected: Option<usize>,
+    // Whether clicking on the image places and drags anchors
+    editing: bool,
+    // Number of anchors created by "Generate N-Color Palette"
+    palette_size: usize,
+}
+
+impl GradientEditor {
+    fn new() -> Self {
+        Self {
+            anchors: default_gradient_anchors(),
+            selected: None,
+            editing: false,
+            palette_size: 4,
+        }
+    }
+
+    fn send_anchors(&self, event_proxy: &EventLoopProxy<UserEvent>) {
+        send_event(
+            event_proxy,
+            UserEvent::SetGradientAnchors(self.anchors.clone()),
+        );
+    }
+
+    /// Anchor list, shown in the "Scene Options" window
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        palette: &mut PaletteOptions,
+        event_proxy: &EventLoopProxy<UserEvent>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.palette_size, 2..=MAX_GRADIENT_ANCHORS).text("N"));
+            if ui.button("Generate N-Color Palette").clicked() {
+                // Spread the anchors evenly on a circle around the center of the image
+                let n = self.palette_size;
+                self.anchors = palette
+                    .generate(n)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, color)| {
+                        let angle = i as f32 / n as f32 * std::f32::consts::TAU;
+                        ([0.5 + 0.35 * angle.cos(), 0.5 + 0.35 * angle.sin()], color)
+                    })
+                    .collect();
+                self.selected = None;
+                self.send_anchors(event_proxy);
+            }
+        });
+
+        ui.checkbox(&mut self.editing, "Gradient Edit")
+            .on_hover_text("Click on the image to place anchors, drag them to move them");
+
+        let mut changed = false;
+        let mut removed = None;
+        egui::Grid::new("gradient_anchors_grid").show(ui, |ui| {
+            for (i, (position, color)) in self.anchors.iter_mut().enumerate() {
+                let label = format!("Anchor {}: ({:.2}, {:.2})", i + 1, position[0], position[1]);
+                if ui
+                    .selectable_label(self.selected == Some(i), label)
+                    .clicked()
+                {
+                    self.selected = Some(i);
+                }
+                changed |= ui.color_edit_button_rgb(color).changed();
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(i) = removed {
+            self.anchors.remove(i);
+            self.selected = None;
+            changed = true;
+        }
+        if changed {
+            self.send_anchors(event_proxy);
+        }
+    }
+
+    /// Draw the anchors over the framebuffer and let the user place, select and drag them
+    fn handles_ui(
+        &mut self,
+        ctx: &Context,
+        pixel_aspect: f32,
+        event_proxy: &EventLoopProxy<UserEvent>,
+    ) {
+        let rect = framebuffer_viewport(ctx, pixel_aspect);
+        let to_screen = |uv: [f32; 2]| {
+            rect.min + egui::vec2(uv[0] * rect.width(), (1.0 - uv[1]) * rect.height())
+        };
+        let to_uv = |pos: egui::Pos2| {
+            [
+                ((pos.x - rect.min.x) / rect.width()).clamp(0.0, 1.0),
+                (1.0 - (pos.y - rect.min.y) / rect.height()).clamp(0.0, 1.0),
+            ]
+        };
+
+        egui::Area::new("gradient_handles")
+            .fixed_pos(rect.min)
+            .order(egui::Order::Background)
+            .show(ctx, |ui| {
+                let (response, painter) =
+                    ui.allocate_painter(rect.size(), egui::Sense::click_and_drag());
+
+                let mut changed = false;
+                if let Some(pos) = response.interact_pointer_pos() {
+                    if response.drag_started() || response.clicked() {
+                        let hit = self.anchors.iter().position(|(uv, _)| {
+                            to_screen(*uv).distance(pos) <= GRADIENT_HANDLE_RADIUS
+                        });
+                        match hit {
+                            Some(i) => self.selected = Some(i),
+                            None if response.clicked()
+                                && self.anchors.len() < MAX_GRADIENT_ANCHORS =>
+                            {
+                                self.anchors.push((to_uv(pos), [1.0, 1.0, 1.0]));
+                                self.selected = Some(self.anchors.len() - 1);
+                                changed = true;
+                            }
+                            None => {}
+                        }
+                    }
+                    if response.dragged() {
+                        if let Some(anchor) = self.selected.and_then(|i| self.anchors.get_mut(i)) {
+                            anchor.0 = to_uv(pos);
+                            changed = true;
+                        }
+                    }
+                }
+
+                for (i, (uv, color)) in self.anchors.iter().enumerate() {
+                    let fill =
+                        egui::Color32::from(egui::Rgba::from_rgb(color[0], color[1], color[2]));
+                    let stroke_color = if self.selected == Some(i) {
+                        egui::Color32::WHITE
+                    } else {
+                        egui::Color32::BLACK
+                    };
+                    painter.circle(
+                        to_screen(*uv),
+                        GRADIENT_HANDLE_RADIUS,
+                        fill,
+                        egui::Stroke::new(2.0, stroke_color),
+                    );
+                }
+
+                if changed {
+                    self.send_anchors(event_proxy);
+                }
+            });
+    }
+}
+
+/// Crosshair over the clone stamp source, with a circle of the brush radius
+fn paint_clone_source(ctx: &Context, pixel_aspect: f32, source: [f32; 2], radius: f32) {
+    let rect = framebuffer_viewport(ctx, pixel_aspect);
+    // Vertical scale, the width is stretched by the pixel aspect ratio
+    let scale = rect.height() / RENDER_BUFFER_HEIGHT as f32;
+    let center = viewport_point(rect, source);
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("clone_source"),
+    ));
+    let (horizontal, vertical) = (egui::vec2(8.0, 0.0), egui::vec2(0.0, 8.0));
+    // Dark under light, to be seen on any image
+    for (width, color) in [(3.0, egui::Color32::BLACK), (1.0, egui::Color32::WHITE)] {
+        let stroke = egui::Stroke::new(width, color);
+        painter.line_segment([center - horizontal, center + horizontal], stroke);
+        painter.line_segment([center - vertical, center + vertical], stroke);
+        painter.circle_stroke(center, radius * scale, stroke);
+    }
+}
+
+/// Scene color set by the color dropper
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DropperTarget {
+    ColorA,
+    ColorB,
+}
+
+impl DropperTarget {
+    fn label(&self) -> &'static str {
+        match self {
+            DropperTarget::ColorA => "Color A",
+            DropperTarget::ColorB => "Color B",
+        }
+    }
+}
+
+/// Tool sampling the color of a framebuffer pixel into one of the scene colors.
+/// A left click samples for the target, a right click for Color B, Escape cancels.
+struct ColorDropper {
+    // `None` when the dropper isn't in use
+    target: Option<DropperTarget>,
+}
+
+/// What the color dropper did this frame
+enum DropperAction {
+    Sample(DropperTarget, [u32; 2]),
+    Cancel,
+}
+
+impl ColorDropper {
+    fn new() -> Self {
+        Self { target: None }
+    }
+
+    /// Draw the crosshair under the pointer, and sample on click
+    fn overlay(&self, ctx: &Context, pixel_aspect: f32) -> Option<DropperAction> {
+        let target = self.target?;
+        if ctx.input().key_pressed(egui::Key::Escape) {
+            return Some(DropperAction::Cancel);
+        }
+        // Clicks on the egui windows are theirs
+        if ctx.is_pointer_over_area() {
+            return None;
+        }
+        let position = ctx.input().pointer.hover_pos()?;
+        let rect = framebuffer_viewport(ctx, pixel_aspect);
+        if !rect.contains(position) {
+            return None;
+        }
+        ctx.output().cursor_icon = egui::CursorIcon::Crosshair;
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("color_dropper"),
+        ));
+        let (horizontal, vertical) = (egui::vec2(10.0, 0.0), egui::vec2(0.0, 10.0));
+        // Dark under light, to be seen on any image
+        for (width, color) in [(3.0, egui::Color32::BLACK), (1.0, egui::Color32::WHITE)] {
+            let stroke = egui::Stroke::new(width, color);
+            painter.line_segment([position - horizontal, position + horizontal], stroke);
+            painter.line_segment([position - vertical, position + vertical], stroke);
+        }
+        let label_position = position + egui::vec2(12.0, 12.0);
+        let text = format!("Sampling for: {}", target.label());
+        let font = egui::FontId::proportional(14.0);
+        painter.text(
+            label_position + egui::vec2(1.0, 1.0),
+            egui::Align2::LEFT_TOP,
+            &text,
+            font.clone(),
+            egui::Color32::BLACK,
+        );
+        painter.text(
+            label_position,
+            egui::Align2::LEFT_TOP,
+            text,
+            font,
+            egui::Color32::WHITE,
+        );
+
+        let input = ctx.input();
+        let target = if input.pointer.primary_clicked() {
+            target
+        } else if input.pointer.secondary_clicked() {
+            DropperTarget::ColorB
+        } else {
+            return None;
+        };
+        Some(DropperAction::Sample(
+            target,
+            viewport_pixel(rect, position),
+        ))
+    }
+}
+
+/// Tool dragging the rectangle of the framebuffer re-rendered by "Render Region".
+/// Escape cancels the selection in progress.
+struct RegionSelector {
+    selecting: bool,
+    // Pixel where the drag started
+    drag_start: Option<[u32; 2]>,
+    // `[x0, y0, x1, y1]` in framebuffer pixels, the end exclusive
+    region: Option<[usize; 4]>,
+}
+
+impl RegionSelector {
+    fn new() -> Self {
+        Self {
+            selecting: false,
+            drag_start: None,
+            region: None,
+        }
+    }
+
+    /// Outline the region, and update it while dragging on the image.
+    /// Returns true once the selection is over.
+    fn overlay(&mut self, ctx: &Context, pixel_aspect: f32) -> bool {
+        let rect = framebuffer_viewport(ctx, pixel_aspect);
+        let finished = self.selecting && self.drag(ctx, rect);
+        if let Some([x0, y0, x1, y1]) = self.region {
+            let outline = egui::Rect::from_min_max(
+                viewport_point(rect, [x0 as f32, y0 as f32]),
+                viewport_point(rect, [x1 as f32, y1 as f32]),
+            );
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("render_region"),
+            ));
+            // Dark under light, to be seen on any image
+            for (width, color) in [(3.0, egui::Color32::BLACK), (1.0, egui::Color32::WHITE)] {
+                painter.rect_stroke(outline, 0.0, egui::Stroke::new(width, color));
+            }
+        }
+        finished
+    }
+
+    fn drag(&mut self, ctx: &Context, rect: egui::Rect) -> bool {
+        // Clicks on the egui windows are theirs
+        let over_window = ctx.is_pointer_over_area();
+        let input = ctx.input();
+        if input.key_pressed(egui::Key::Escape) {
+            self.drag_start = None;
+            return true;
+        }
+        let Some(position) = input.pointer.interact_pos() else {
+            return false;
+        };
+        if self.drag_start.is_none() {
+            if !(input.pointer.any_pressed() && input.pointer.primary_down())
+                || !rect.contains(position)
+                || over_window
+            {
+                return false;
+            }
+            self.drag_start = Some(viewport_pixel(rect, position));
+        }
+        let Some(start) = self.drag_start else {
+            return false;
+        };
+        let end = viewport_pixel(rect, position);
+        let [x0, x1] = [start[0].min(end[0]), start[0].max(end[0])];
+        let [y0, y1] = [start[1].min(end[1]), start[1].max(end[1])];
+        self.region = Some([x0, y0, x1 + 1, y1 + 1].map(|coord| coord as usize));
+
+        if input.pointer.primary_released() {
+            self.drag_start = None;
+            return true;
+        }
+        false
+    }
+}
+
+/// R, G and B histograms of the levels, one line per channel
+fn levels_histogram_plot(ui: &mut egui::Ui, id: &str, title: &str, histogram: &LevelsHistogram) {
+    ui.label(title);
+    let colors = [
+        egui::Color32::from_rgb(230, 80, 80),
+        egui::Color32::from_rgb(80, 200, 80),
+        egui::Color32::from_rgb(80, 120, 230),
+    ];
+    Plot::new(id)
+        .height(80.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .show_axes([true, false])
+        .include_x(0.0)
+        .include_x(1.0)
+        .include_y(0.0)
+        .show(ui, |plot_ui| {
+            for (bins, color) in histogram.bins.iter().zip(colors) {
+                let points: PlotPoints = bins
+                    .iter()
+                    .enumerate()
+                    .map(|(bin, &count)| {
+                        let center = (bin as f64 + 0.5) / LEVELS_HISTOGRAM_BINS as f64;
+                        [center, count as f64]
+                    })
+                    .collect();
+                plot_ui.line(Line::new(points).color(color));
+            }
+        });
+}
+
+/// Effective bit depth under which the output likely shows banding
+const BANDING_BIT_DEPTH: f32 = 6.0;
+
+/// Levels used by each channel of the displayed frame, and how much of it is clipped
+fn bit_depth_ui(ui: &mut egui::Ui, analysis: &BitDepthAnalysis) {
+    egui::Grid::new("bit_depth_grid")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Channel");
+            ui.strong("Unique values");
+            ui.strong("Effective bits");
+            ui.end_row();
+            for (index, channel) in ["R", "G", "B", "A"].into_iter().enumerate() {
+                ui.label(channel);
+                ui.label(analysis.unique_values_per_channel[index].to_string());
+                ui.label(format!("{:.2}", analysis.effective_bit_depth[index]));
+                ui.end_row();
+            }
+        });
+    ui.label(format!(
+        "Clipped to white: {:.2}%",
+        analysis.clipped_white_fraction * 100.0
+    ));
+    ui.label(format!(
+        "Clipped to black: {:.2}%",
+        analysis.clipped_black_fraction * 100.0
+    ));
+    // Alpha is usually a single opaque level, only the color channels can band
+    if analysis.effective_bit_depth[..3]
+        .iter()
+        .any(|&bits| bits < BANDING_BIT_DEPTH)
+    {
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!(
+                "Under {BANDING_BIT_DEPTH} bits in a color channel, expect banding. \
+                Check the exposure and the tonemapper."
+            ),
+        );
+    }
+}
+
+/// Edit the three components of a position or size side by side
+fn drag_vec3(ui: &mut egui::Ui, value: &mut [f32; 3]) {
+    ui.horizontal(|ui| {
+        for component in value {
+            ui.add(egui::DragValue::new(component).speed(0.05));
+        }
+    });
+}
+
+/// Edit the min and max of a range of pixels side by side, keeping min below max
+fn range_drag_values(ui: &mut egui::Ui, range: &mut [f32; 2], limits: RangeInclusive<f32>) {
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut range[0]).clamp_range(limits.clone()));
+        ui.label("to");
+        ui.add(
+            egui::DragValue::new(&mut range[1])
+                .clamp_range(limits)
+                .suffix(" px"),
+        );
+    });
+    range[1] = range[1].max(range[0]);
+}
+
+/// Framebuffer pixel under `position`, clamped to the framebuffer
+fn viewport_pixel(rect: egui::Rect, position: egui::Pos2) -> [u32; 2] {
+    let x = (position.x - rect.min.x) / rect.width() * RENDER_BUFFER_WIDTH as f32;
+    let y = (position.y - rect.min.y) / rect.height() * RENDER_BUFFER_HEIGHT as f32;
+    [
+        (x.max(0.0) as u32).min(RENDER_BUFFER_WIDTH - 1),
+        (y.max(0.0) as u32).min(RENDER_BUFFER_HEIGHT - 1),
+    ]
+}
+
+/// Position on screen of a point of the framebuffer, in pixels
+fn viewport_point(rect: egui::Rect, pixel: [f32; 2]) -> egui::Pos2 {
+    rect.min
+        + egui::vec2(
+            pixel[0] / RENDER_BUFFER_WIDTH as f32 * rect.width(),
+            pixel[1] / RENDER_BUFFER_HEIGHT as f32 * rect.height(),
+        )
+}
+
+/// Area of the window covered by the framebuffer, in points.
+/// Mirrors the integer scaling and centering done by the `Pixels` scaling renderer,
+/// or by the `PixelAspectRenderer` when the pixels aren't square.
+fn framebuffer_viewport(ctx: &Context, pixel_aspect: f32) -> egui::Rect {
+    let screen = ctx.input().screen_rect();
+    let pixels_per_point = ctx.pixels_per_point();
+    let [width, height] = aspect::viewport_size(
+        [
+            screen.width() * pixels_per_point,
+            screen.height() * pixels_per_point,
+        ],
+        pixel_aspect,
+    );
+    egui::Rect::from_center_size(
+        screen.center(),
+        egui::vec2(width, height) / pixels_per_point,
+    )
+}
+
+/// Directory where images are saved, created if missing
+fn images_dir() -> PathBuf {
+    let root_dir = PathBuf::from("images");
+    if !root_dir.exists() {
+        match std::fs::create_dir_all(&root_dir) {
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to create images dir: {e:?}");
+            }
+        }
+    }
+    root_dir
+}
+
+/// Send an event to the main thread.
+/// Takes the proxy rather than `&self` so it can be called while other `Gui` fields are borrowed.
+fn send_event(proxy: &EventLoopProxy<UserEvent>, event: UserEvent) {
+    if let Err(e) = proxy.send_event(event) {
+        error!("Failed to send event: {e:?}");
+    }
+}
+
+impl Framework {
+    /// Create egui. `surface_format` and `msaa` are the ones in use,
+    /// those of `config` if the device supports them.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new<T>(
+        event_loop: &EventLoopWindowTarget<T>,
+        width: u32,
+        height: u32,
+        scale_factor: f32,
+        pixels: &pixels::Pixels,
+        event_proxy: EventLoopProxy<UserEvent>,
+        config: &Config,
+        surface_format: SurfaceFormat,
+        msaa: MsaaConfig,
+    ) -> Self {
+        let max_texture_size = pixels.device().limits().max_texture_dimension_2d as usize;
+
+        let egui_ctx = Context::default();
+        let mut egui_state = egui_winit::State::new(event_loop);
+        egui_state.set_max_texture_side(max_texture_size);
+        egui_state.set_pixels_per_point(scale_factor);
+        let screen_descriptor = ScreenDescriptor {
+            size_in_pixels: [width, height],
+            pixels_per_point: scale_factor,
+        };
+        let renderer = Renderer::new(
+            pixels.device(),
+            pixels.render_texture_format(),
+            None,
+            msaa.sample_count,
+        );
+        let msaa_layer = (msaa.sample_count > 1).then(|| {
+            MsaaLayer::new(
+                pixels.device(),
+                pixels.render_texture_format(),
+                msaa.sample_count,
+            )
+        });
+        let textures = TexturesDelta::default();
+        let gui = Gui::new(
+            width,
+            height,
+            scale_factor,
+            event_proxy,
+            config.window_positions.clone(),
+            config.clear_color,
+            surface_format,
+            DeviceInfoPanel::new(pixels, config, surface_format, msaa),
+            SettingsPanel::new(
+                pixels.adapter(),
+                pixels.render_texture_format(),
+                config,
+                msaa,
+            ),
+        );
+
+        Self {
+            egui_ctx,
+            egui_state,
+            screen_descriptor,
+            renderer,
+            msaa_layer,
+            paint_jobs: Vec::new(),
+            textures,
+            repaint_deadline: Some(Instant::now()),
+            gui,
+        }
+    }
+
+    /// Handle input events from the window manager.
+    /// Returns whether egui needs to be drawn again.
+    pub(crate) fn handle_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        let response = self.egui_state.on_event(&self.egui_ctx, event);
+        match event {
+            // Over the image, the pointer only matters to egui when it enters or leaves a window
+            winit::event::WindowEvent::CursorMoved { position, .. } => {
+                let position = egui::pos2(position.x as f32, position.y as f32)
+                    / self.egui_ctx.pixels_per_point();
+                response.repaint
+                    && (self.egui_ctx.layer_id_at(position).is_some()
+                        || self.egui_ctx.is_pointer_over_area()
+                        || self.egui_ctx.is_using_pointer())
+            }
+            _ => response.repaint,
+        }
+    }
+
+    /// Resize egui.
+    pub(crate) fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.screen_descriptor.size_in_pixels = [width, height];
+        }
+    }
+
+    /// Whether egui is using the pointer, e.g. it's over one of its windows
+    pub(crate) fn wants_pointer_input(&self) -> bool {
+        self.egui_ctx.wants_pointer_input()
+    }
+
+    /// Whether egui is using the keyboard, e.g. a text field has focus
+    pub(crate) fn wants_keyboard_input(&self) -> bool {
+        self.egui_ctx.wants_keyboard_input()
+    }
+
+    /// MSAA chosen in the settings, to be saved in the config
+    pub(crate) fn msaa_config(&self) -> MsaaConfig {
+        self.gui.settings.msaa
+    }
+
+    /// Frame rate cap set in the settings
+    pub(crate) fn target_fps(&self) -> TargetFps {
+        self.gui.settings.target_fps
+    }
+
+    /// Pixels per inch of the monitor set in the settings, to be saved in the config
+    pub(crate) fn window_dpi(&self) -> f32 {
+        self.gui.settings.window_dpi
+    }
+
+    /// Last known position of the egui windows, to be saved in the config
+    pub(crate) fn window_positions(&self) -> HashMap<String, [f32; 2]> {
+        self.gui.window_positions.clone()
+    }
+
+    /// Refresh the gamut diagram from the linear ACEScg display buffer, if it's visible
+    pub(crate) fn update_gamut_diagram(&mut self, generation: u64, mips: &MipPyramid) {
+        if self.gui.gamut_open {
+            self.gui.gamut_diagram.update(generation, mips);
+        }
+    }
+
+    /// Measure the levels used by the frame just drawn, if the bit depth panel is open
+    pub(crate) fn update_bit_depth(&mut self, frame: &[u8]) {
+        if self.gui.bit_depth_open {
+            self.gui.bit_depth = Some(analyze_bit_depth(frame));
+        }
+    }
+
+    /// When egui needs to be drawn again without any input, e.g. to animate a spinner
+    pub(crate) fn repaint_deadline(&self) -> Option<Instant> {
+        self.repaint_deadline
+    }
+
+    /// Ask whether to restore the autosave of a previous session
+    pub(crate) fn offer_session_recovery(&mut self, image_path: PathBuf) {
+        self.gui.recoverable_session = Some(image_path);
+    }
+
+    /// Tell the user why an image couldn't be saved
+    pub(crate) fn show_image_error(&mut self, error: &ImageError) {
+        self.gui.image_error = Some(image_error_message(error));
+    }
+
+    /// Show the progress of the tiled render
+    pub(crate) fn set_render_status(&mut self, status: RenderStatus) {
+        self.gui.render_status = status;
+    }
+
+    /// Show why the shaders of the WGSL nodes didn't compile
+    pub(crate) fn set_shader_errors(&mut self, errors: HashMap<NodeId, String>) {
+        self.gui.node_graph.shader_errors = errors;
+    }
+
+    /// Show the state of the EXR sequence player, if a sequence is open
+    pub(crate) fn set_playback_status(&mut self, status: Option<PlaybackStatus>) {
+        self.gui.playback.status = status;
+    }
+
+    /// Assign the color sampled by the color dropper
+    pub(crate) fn set_sampled_color(&mut self, target: DropperTarget, rgba: [f32; 4]) {
+        self.gui.set_sampled_color(target, rgba);
+    }
+
+    /// Show the levels picked by "Auto Levels"
+    pub(crate) fn set_levels(&mut self, levels: LevelsParams) {
+        self.gui.post_process.levels = levels;
+    }
+
+    /// Show the render mode the application switched to on its own
+    pub(crate) fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.gui.render_mode = render_mode;
+    }
+
+    /// Show the Mandelbrot view set by an animation or an IPC message
+    pub(crate) fn set_mandelbrot_view(&mut self, view: MandelbrotView) {
+        self.gui.mandelbrot_view = view;
+    }
+
+    /// Show the histogram of the image going into the levels
+    pub(crate) fn set_levels_histogram(&mut self, histogram: &LevelsHistogram) {
+        if self.gui.levels_histogram != *histogram {
+            self.gui.levels_histogram = histogram.clone();
+        }
+    }
+
+    /// Show where the clone stamp copies from
+    pub(crate) fn set_clone_source(&mut self, source: Option<[f32; 2]>) {
+        self.gui.clone_source = source;
+    }
+
+    /// Record how long the last frame took to draw.
+    pub(crate) fn record_frame_time(&mut self, frame_time: Duration) {
+        self.gui.frame_timing.push(frame_time);
+    }
+
+    /// Update scaling factor.
+    pub(crate) fn scale_factor(&mut self, scale_factor: f64) {
+        self.screen_descriptor.pixels_per_point = scale_factor as f32;
+    }
+
+    /// Prepare egui.
+    pub(crate) fn prepare(&mut self, window: &Window) {
+        // Run the egui frame and create all paint jobs to prepare for rendering.
+        let raw_input = self.egui_state.take_egui_input(window);
+        let output = self.egui_ctx.run(raw_input, |egui_ctx| {
+            // Draw the demo application.
+            self.gui.ui(egui_ctx);
+        });
+
+        // `Duration::MAX` when egui doesn't need to be repainted
+        self.repaint_deadline = Instant::now().checked_add(output.repaint_after);
+        self.textures.append(output.textures_delta);
+        self.egui_state
+            .handle_platform_output(window, &self.egui_ctx, output.platform_output);
+        self.paint_jobs = self.egui_ctx.tessellate(output.shapes);
+    }
+
+    /// Render egui.
+    pub(crate) fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        render_target: &wgpu::TextureView,
+        context: &PixelsContext,
+    ) {
+        // Upload all resources to the GPU.
+        for (id, image_delta) in &self.textures.set {
+            self.renderer
+                .update_texture(&context.device, &context.queue, *id, image_delta);
+        }
+        self.renderer.update_buffers(
+            &context.device,
+            &context.queue,
+            encoder,
+            &self.paint_jobs,
+            &self.screen_descriptor,
+        );
+
+        // Render egui with WGPU, resolving the multisampled layer if MSAA is on
+        {
+            let color_attachment = match &mut self.msaa_layer {
+                Some(layer) => {
+                    layer.attachment(&context.device, self.screen_descriptor.size_in_pixels)
+                }
+                None => wgpu::RenderPassColorAttachment {
+                    view: render_target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                },
+            };
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("egui"),
+                color_attachments: &[Some(color_attachment)],
+                depth_stencil_attachment: None,
+            });
+
+            self.renderer
+                .render(&mut rpass, &self.paint_jobs, &self.screen_descriptor);
+        };
+        if let Some(layer) = &self.msaa_layer {
+            layer.composite(encoder, render_target);
+        }
+        // Cleanup
+        let textures = std::mem::take(&mut self.textures);
+        for id in &textures.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+impl Gui {
+    /// Create a `Gui`.
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        width: u32,
+        height: u32,
+        scale_factor: f32,
+        event_proxy: EventLoopProxy<UserEvent>,
+        window_positions: HashMap<String, [f32; 2]>,
+        clear_color: [f32; 4],
+        surface_format: SurfaceFormat,
+        device_info: DeviceInfoPanel,
+        settings: SettingsPanel,
+    ) -> Self {
+        Self {
+            window_open: true,
+            window_width: width,
+            window_height: height,
+            file_path: String::new(),
+            color_a: [0x00, 0x00, 0x00, 0xff],
+            color_b: [0xff, 0xff, 0xff, 0xff],
+            color_harmony: ColorHarmonyPanel::new(),
+            scale_factor,
+            file_format_chosen: FileFormat::OpenEXR,
+            ascii_block_size: 4,
+            ascii_charset: ASCII_ART_CHARSET.to_owned(),
+            embed_metadata: true,
+            single_channel: false,
+            watermark_enabled: false,
+            watermark: Watermark::default(),
+            render_mode: RenderMode::Gradient,
+            mandelbrot_view: MandelbrotView::default(),
+            keyframe_frame: 0,
+            storage_encoding: StorageEncoding::Linear,
+            sampler: SamplerKind::Uniform,
+            ssaa_factor: 1,
+            soft_proof_target: SoftProofTarget::Off,
+            sdf_scale: 1.0,
+            sdf_range: [-16.0, 16.0],
+            display_mode: DisplayMode::Normal,
+            print: PrintSettings::default(),
+            gamut_warning: false,
+            output_color_space: OutputColorSpace::Srgb,
+            dither_mode: DitherMode::None,
+            dither_scale: 1.0,
+            pixel_aspect: PixelAspectRatio::default(),
+            pixel_aspect_custom: false,
+            alpha_mode: AlphaMode::Straight,
+            surface_format,
+            performance_open: false,
+            frame_timing: FrameTimingGraph::new(),
+            reference_open: false,
+            reference: ReferenceOverlay::new(),
+            sh_open: false,
+            sh_irradiance: None,
+            gradient_editor: GradientEditor::new(),
+            cubemap_open: false,
+            cubemap_tool: CubemapTool::new(),
+            lissajous: LissajousSettings::default(),
+            starfield: StarfieldSettings::default(),
+            spectral_range: [380.0, 700.0],
+            flame_editor: FlameEditor::new(),
+            value_noise: ValueNoiseSettings::default(),
+            sky: SkySettings::default(),
+            turbulence: TurbulenceSettings::default(),
+            bricks: BrickSettings::default(),
+            color_wheel_lightness: 0.5,
+            color_wheel_mode: ColorWheelMode::Hsl,
+            wood_grain: WoodGrainSettings::default(),
+            poisson_disk: PoissonDiskSettings::default(),
+            oscilloscope: OscilloscopeSettings::default(),
+            render_status: RenderStatus::Idle,
+            worley: WorleySettings::default(),
+            pcb: PcbSettings::default(),
+            texture_quilting: TextureQuiltingSettings::default(),
+            exemplar_name: None,
+            terrain: TerrainParams::default(),
+            raymarcher: RaymarcherSettings::default(),
+            brdf_spheres: BrdfGridSettings::default(),
+            truchet: TruchetSettings::default(),
+            grunge: GrungeParams::default(),
+            spiral: SpiralSettings::default(),
+            camera: CameraSettings::default(),
+            #[cfg(feature = "camera-input")]
+            camera_devices: crate::camera::list_devices(),
+            #[cfg(not(feature = "camera-input"))]
+            camera_devices: Vec::new(),
+            clear_before_render: true,
+            clear_color,
+            paint_mode: false,
+            brush: Brush::default(),
+            clone_source: None,
+            color_dropper: ColorDropper::new(),
+            region_selector: RegionSelector::new(),
+            post_process: PostProcessSettings::default(),
+            pass_order: default_pass_order(),
+            dragged_pass: None,
+            depth_name: None,
+            warp_map_name: None,
+            denoise_enabled: false,
+            denoise_blend: 0.1,
+            color_op_open: false,
+            color_op: ColorOpEditor::new(),
+            clut_open: false,
+            clut: ClutEditor::new(),
+            metadata_open: false,
+            metadata: MetadataEditor::new(),
+            playback_open: false,
+            print_preview_open: false,
+            bit_depth_open: false,
+            bit_depth: None,
+            levels_histogram: LevelsHistogram::default(),
+            print_preview: PrintPreview::new(),
+            playback: PlaybackPanel::new(),
+            node_graph_open: false,
+            node_graph: NodeGraphEditor::new(),
+            palette: PaletteOptions::new(),
+            gamut_open: false,
+            gamut_diagram: GamutDiagram::new(),
+            device_info_open: false,
+            device_info,
+            settings_open: false,
+            settings,
+            scene_error: None,
+            scene_path: None,
+            image_error: None,
+            recoverable_session: None,
+            continuous_render: false,
+            window_positions,
+            event_proxy,
+        }
+    }
+
+    /// Where to open a window: its saved position, pulled back on screen if needed,
+    /// or `default` when there is none
+    fn window_pos(&self, ctx: &Context, title: &str, default: egui::Pos2) -> egui::Pos2 {
+        let Some(&[x, y]) = self.window_positions.get(title) else {
+            return default;
+        };
+        // Keep enough of the title bar visible to grab it
+        let screen = ctx.input().screen_rect();
+        let max =
+            (screen.max - egui::vec2(WINDOW_VISIBLE_MARGIN, WINDOW_VISIBLE_MARGIN)).max(screen.min);
+        egui::pos2(x.clamp(screen.min.x, max.x), y.clamp(screen.min.y, max.y))
+    }
+
+    /// Remember where a window was shown this frame
+    fn remember_window_pos<R>(&mut self, title: &str, response: Option<egui::InnerResponse<R>>) {
+        if let Some(response) = response {
+            let pos = response.response.rect.min;
+            self.window_positions
+                .insert(title.to_owned(), [pos.x, pos.y]);
+        }
+    }
+
+    /// Ask for a JSON file and send the scene in it to the application,
+    /// updating the widgets to match
+    fn load_scene(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Scene", &["json"])
+            .pick_file()
+        else {
+            return;
+        };
+        if self.open_scene(&path) {
+            self.scene_path = Some(path);
+        }
+    }
+
+    /// Show the settings of the scene saved at `path` and render it.
+    /// Returns false when the file couldn't be read.
+    fn open_scene(&mut self, path: &Path) -> bool {
+        let (scene, params) = match SceneDescription::load(path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!("Failed to load the scene: {e:#}");
+                self.scene_error = Some(format!("{e:#}"));
+                return false;
+            }
+        };
+        self.scene_error = None;
+
+        self.render_mode = scene.render_mode;
+        self.sampler = scene.sampler;
+        for (color, acescg) in [
+            (&mut self.color_a, scene.color_a),
+            (&mut self.color_b, scene.color_b),
+        ] {
+            let [r, g, b] = acescg_to_srgb_u8(acescg);
+            *color = [r, g, b, 0xff];
+        }
+        self.post_process = scene.post_process();
+        self.pass_order = scene.pass_order.clone();
+        match &params {
+            ProceduralParams::None => {}
+            ProceduralParams::Mandelbrot(view) => self.mandelbrot_view = *view,
+            ProceduralParams::ShSphere(sh) => self.sh_irradiance = Some(*sh),
+            ProceduralParams::MultipointGradient(anchors) => {
+                self.gradient_editor.anchors = anchors.clone();
+                self.gradient_editor.selected = None;
+            }
+            ProceduralParams::Lissajous(lissajous) => self.lissajous = *lissajous,
+            ProceduralParams::Starfield(starfield) => self.starfield = *starfield,
+            ProceduralParams::SpectralGradient(range) => self.spectral_range = *range,
+            ProceduralParams::FractalFlame(settings) => {
+                self.flame_editor.settings = settings.clone();
+            }
+            ProceduralParams::ValueNoise(value_noise) => self.value_noise = *value_noise,
+            ProceduralParams::Sky(sky) => self.sky = *sky,
+            ProceduralParams::Turbulence(turbulence) => self.turbulence = *turbulence,
+            ProceduralParams::Bricks(bricks) => self.bricks = *bricks,
+            ProceduralParams::ColorWheel(color_wheel) => {
+                self.color_wheel_lightness = color_wheel.lightness;
+                self.color_wheel_mode = color_wheel.mode;
+            }
+            ProceduralParams::WoodGrain(wood_grain) => self.wood_grain = *wood_grain,
+            ProceduralParams::PoissonDisk(poisson_disk) => self.poisson_disk = *poisson_disk,
+            ProceduralParams::Oscilloscope(oscilloscope) => self.oscilloscope = *oscilloscope,
+            ProceduralParams::Camera(camera) => self.camera = *camera,
+            ProceduralParams::Worley(worley) => self.worley = *worley,
+            ProceduralParams::Pcb(pcb) => self.pcb = *pcb,
+            ProceduralParams::TextureQuilting(texture_quilting) => {
+                self.texture_quilting = *texture_quilting
+            }
+            ProceduralParams::Terrain(terrain) => self.terrain = terrain.clone(),
+            ProceduralParams::Raymarcher(raymarcher) => self.raymarcher = raymarcher.clone(),
+            ProceduralParams::BrdfSpheres(brdf_spheres) => self.brdf_spheres = *brdf_spheres,
+            ProceduralParams::Truchet(truchet) => self.truchet = *truchet,
+            ProceduralParams::GrungeMap(grunge) => self.grunge = *grunge,
+            ProceduralParams::Spiral(spiral) => self.spiral = *spiral,
+            ProceduralParams::NodeGraph(graph) => {
+                self.node_graph.graph = graph.clone();
+                self.node_graph.selected = None;
+            }
+        }
+
+        // Sent first, so the render mode of the scene replaces the one a cubemap switches to
+        self.load_scene_files(&scene.file_paths);
+        send_event(
+            &self.event_proxy,
+            UserEvent::ApplyScene(Box::new((scene, params))),
+        );
+        true
+    }
+
+    /// Load the images of `files`, picked in the GUI or listed by a scene.
+    /// Files that fail to load are reported, the others still load.
+    fn load_scene_files(&mut self, files: &SceneFiles) {
+        let loaded = [
+            files
+                .cubemap_hdri
+                .as_ref()
+                .map(|path| (path, self.cubemap_tool.load(path, &self.event_proxy))),
+            files
+                .exemplar
+                .as_ref()
+                .map(|path| (path, self.load_exemplar(path))),
+            files
+                .depth_buffer
+                .as_ref()
+                .map(|path| (path, self.load_depth_buffer(path))),
+            files
+                .warp_map
+                .as_ref()
+                .map(|path| (path, self.load_warp_map(path))),
+        ];
+        for (path, result) in loaded.into_iter().flatten() {
+            if let Err(e) = result {
+                error!("Failed to load {}: {e:?}", path.display());
+                self.image_error = Some(format!(
+                    "Can't load {}: {}",
+                    path.display(),
+                    image_error_message(&e)
+                ));
+            }
+        }
+    }
+
+    /// Load the exemplar of the texture synthesis from `path`
+    fn load_exemplar(&mut self, path: &Path) -> Result<(), ImageError> {
+        let exemplar = Exemplar::load(path)?;
+        self.exemplar_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        send_event(
+            &self.event_proxy,
+            UserEvent::SetExemplar {
+                exemplar: Box::new(exemplar),
+                path: path.to_owned(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Load the depth buffer of the depth of field from the OpenEXR at `path`
+    fn load_depth_buffer(&mut self, path: &Path) -> Result<(), ImageError> {
+        let depth = read_exr_depth(
+            path,
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+        )?;
+        self.depth_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        send_event(
+            &self.event_proxy,
+            UserEvent::SetDepthBuffer {
+                depth,
+                path: path.to_owned(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Load the displacement of the grid warp from the OpenEXR at `path`
+    fn load_warp_map(&mut self, path: &Path) -> Result<(), ImageError> {
+        let displacement = read_exr_displacement(
+            path,
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+        )?;
+        self.warp_map_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        send_event(
+            &self.event_proxy,
+            UserEvent::SetWarpMap {
+                displacement,
+                path: Some(path.to_owned()),
+            },
+        );
+        Ok(())
+    }
+
+    /// Pick the next clicked pixel for a scene color, pausing the brush meanwhile
+    fn start_color_dropper(&mut self, target: DropperTarget) {
+        if self.color_dropper.target.is_none() && self.paint_mode {
+            send_event(&self.event_proxy, UserEvent::SetBrush(None));
+        }
+        self.color_dropper.target = Some(target);
+    }
+
+    /// Go back to the tool used before the dropper
+    fn stop_color_dropper(&mut self) {
+        if self.color_dropper.target.take().is_some() && self.paint_mode {
+            send_event(&self.event_proxy, UserEvent::SetBrush(Some(self.brush)));
+        }
+    }
+
+    /// Drag a render region on the image, pausing the brush meanwhile
+    fn start_region_selection(&mut self) {
+        if !self.region_selector.selecting && self.paint_mode {
+            send_event(&self.event_proxy, UserEvent::SetBrush(None));
+        }
+        self.region_selector.selecting = true;
+    }
+
+    fn stop_region_selection(&mut self) {
+        if std::mem::take(&mut self.region_selector.selecting) && self.paint_mode {
+            send_event(&self.event_proxy, UserEvent::SetBrush(Some(self.brush)));
+        }
+    }
+
+    /// Set a scene color to a sampled ACEScg RGBA color
+    fn set_sampled_color(&mut self, target: DropperTarget, rgba: [f32; 4]) {
+        let [r, g, b] = acescg_to_srgb_u8([rgba[0], rgba[1], rgba[2]]);
+        let color = [r, g, b, (rgba[3].clamp(0.0, 1.0) * 255.0).round() as u8];
+        match target {
+            DropperTarget::ColorA => self.color_a = color,
+            DropperTarget::ColorB => self.color_b = color,
+        }
+        let [a, b] = [self.color_a, self.color_b].map(|[r, g, b, _]| srgb_u8_to_acescg([r, g, b]));
+        send_event(&self.event_proxy, UserEvent::SetSceneColors([a, b]));
+    }
+
+    /// Create the UI using egui.
+    fn ui(&mut self, ctx: &Context) {
+        egui::TopBottomPanel::top("menubar_container").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Save Scene...").clicked() {
+                        ui.close_menu();
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Scene", &["json"])
+                            .set_file_name("scene.json")
+                            .save_file()
+                        {
+                            self.scene_path = Some(path.clone());
+                            send_event(&self.event_proxy, UserEvent::SaveScene(path));
+                        }
+                    }
+                    if ui.button("Load Scene...").clicked() {
+                        ui.close_menu();
+                        self.load_scene();
+                    }
+                    ui.separator();
+                    if ui.button("Settings...").clicked() {
+                        self.settings_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("About...").clicked() {
+                        self.window_open = true;
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    if ui
+                        .checkbox(&mut self.performance_open, "Performance")
+                        .clicked()
+                    {
+                        ui.close_menu();
+                    }
+                    if ui.checkbox(&mut self.reference_open, "Reference").clicked() {
+                        ui.close_menu();
+                    }
+                    if ui.checkbox(&mut self.sh_open, "SH Irradiance").clicked() {
+                        ui.close_menu();
+                    }
+                    if ui.checkbox(&mut self.cubemap_open, "Cubemap").clicked() {
+                        ui.close_menu();
+                    }
+                    if ui
+                        .checkbox(&mut self.color_op_open, "Custom Color Op")
+                        .clicked()
+                    {
+                        ui.close_menu();
+                    }
+                    if ui.checkbox(&mut self.clut_open, "CLUT Editor").clicked() {
+                        ui.close_menu();
+                    }
+                    if ui.checkbox(&mut self.gamut_open, "Gamut Diagram").clicked() {
+                        ui.close_menu();
+                    }
+                    if ui
+                        .checkbox(&mut self.metadata_open, "EXR Metadata")
+                        .clicked()
+                    {
+                        ui.close_menu();
+                    }
+                    if ui
+                        .checkbox(&mut self.node_graph_open, "Node Graph")
+                        .clicked()
+                    {
+                        ui.close_menu();
+                    }
+                    if ui.checkbox(&mut self.playback_open, "Playback").clicked() {
+                        ui.close_menu();
+                    }
+                    if ui
+                        .checkbox(&mut self.print_preview_open, "Print Preview")
+                        .clicked()
+                    {
+                        ui.close_menu();
+                    }
+                    if ui.checkbox(&mut self.bit_depth_open, "Bit Depth").clicked() {
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Help", |ui| {
+                    if ui.button("Device Info").clicked() {
+                        self.device_info_open = true;
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+
+        let pixel_aspect = self.pixel_aspect.ratio();
+        self.reference.paint(ctx, pixel_aspect);
+
+        match self.color_dropper.overlay(ctx, pixel_aspect) {
+            Some(DropperAction::Sample(target, pixel)) => {
+                send_event(&self.event_proxy, UserEvent::SampleColor { pixel, target });
+                self.stop_color_dropper();
+            }
+            Some(DropperAction::Cancel) => self.stop_color_dropper(),
+            None => {}
+        }
+
+        if self.region_selector.overlay(ctx, pixel_aspect) {
+            self.stop_region_selection();
+        }
+
+        if self.print_preview_open {
+            self.print_preview.paint(ctx, self.settings.window_dpi);
+        }
+
+        if self.paint_mode && self.brush.tool == BrushTool::Clone {
+            if let Some(source) = self.clone_source {
+                paint_clone_source(ctx, pixel_aspect, source, self.brush.radius);
+            }
+        }
+
+        if self.render_mode == RenderMode::MultipointGradient && self.gradient_editor.editing {
+            self.gradient_editor
+                .handles_ui(ctx, pixel_aspect, &self.event_proxy);
+        }
+
+        egui::Window::new("Reference")
+            .open(&mut self.reference_open)
+            .show(ctx, |ui| {
+                self.reference.ui(ui, &self.event_proxy);
+            });
+
+        egui::Window::new("Gamut Diagram")
+            .open(&mut self.gamut_open)
+            .show(ctx, |ui| {
+                self.gamut_diagram.ui(ui);
+            });
+
+        if let Some(message) = &self.scene_error {
+            let mut open = true;
+            egui::Window::new("Scene Error")
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.colored_label(egui::Color32::RED, message);
+                });
+            if !open {
+                self.scene_error = None;
+            }
+        }
+
+        if let Some(message) = &self.image_error {
+            let mut open = true;
+            egui::Window::new("Image Error")
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.colored_label(egui::Color32::RED, message);
+                });
+            if !open {
+                self.image_error = None;
+            }
+        }
+
+        if let Some(image_path) = self.recoverable_session.clone() {
+            let mut answered = false;
+            egui::Window::new("Recover session?")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("An autosave of a previous session was found:");
+                    ui.monospace(image_path.display().to_string());
+                    ui.horizontal(|ui| {
+                        if ui.button("Recover").clicked() {
+                            // Render the scene first, then put back what was on top of it
+                            let scene_path = image_path.with_extension("json");
+                            if scene_path.exists() {
+                                self.open_scene(&scene_path);
+                            }
+                            send_event(
+                                &self.event_proxy,
+                                UserEvent::RestoreFramebuffer(image_path.clone()),
+                            );
+                            answered = true;
+                        }
+                        if ui.button("Discard").clicked() {
+                            answered = true;
+                        }
+                    });
+                });
+            if answered {
+                self.recoverable_session = None;
+            }
+        }
+
+        egui::Window::new("Device Info")
+            .open(&mut self.device_info_open)
+            .show(ctx, |ui| {
+                self.device_info.ui(ui);
+            });
+
+        egui::Window::new("Print Preview")
+            .open(&mut self.print_preview_open)
+            .show(ctx, |ui| {
+                self.print_preview.ui(ui);
+            });
+
+        egui::Window::new("Bit Depth")
+            .open(&mut self.bit_depth_open)
+            .show(ctx, |ui| match &self.bit_depth {
+                Some(analysis) => bit_depth_ui(ui, analysis),
+                None => {
+                    ui.label("Waiting for the next frame...");
+                }
+            });
+
+        egui::Window::new("Settings")
+            .open(&mut self.settings_open)
+            .show(ctx, |ui| {
+                self.settings.ui(ui);
+            });
+
+        egui::Window::new("Node Graph")
+            .open(&mut self.node_graph_open)
+            .default_width(720.0)
+            .show(ctx, |ui| {
+                self.node_graph.ui(ui, &self.event_proxy);
+            });
+        self.node_graph.shader_editor_ui(ctx, &self.event_proxy);
+
+        egui::Window::new("Playback")
+            .open(&mut self.playback_open)
+            .show(ctx, |ui| {
+                self.playback.ui(ui, &self.event_proxy);
+            });
+
+        egui::Window::new("EXR Metadata")
+            .open(&mut self.metadata_open)
+            .show(ctx, |ui| {
+                self.metadata.ui(ui, &self.event_proxy);
+            });
+
+        egui::Window::new("Custom Color Op")
+            .open(&mut self.color_op_open)
+            .show(ctx, |ui| {
+                self.color_op.ui(ui, &self.event_proxy);
+            });
+
+        egui::Window::new("CLUT Editor")
+            .open(&mut self.clut_open)
+            .show(ctx, |ui| {
+                self.clut.ui(ui, &self.event_proxy);
+            });
+
+        egui::Window::new("Cubemap")
+            .open(&mut self.cubemap_open)
+            .show(ctx, |ui| {
+                self.cubemap_tool.ui(ui, &self.file_path, &self.event_proxy);
+            });
+
+        egui::Window::new("SH Irradiance")
+            .open(&mut self.sh_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Load HDRI").clicked() {
+                        if let Some((_, hdri)) = pick_hdri() {
+                            let sh = bake_sh_irradiance(&hdri);
+                            self.sh_irradiance = Some(sh);
+                            self.render_mode = RenderMode::ShSphere;
+                            send_event(&self.event_proxy, UserEvent::SetShIrradiance(sh));
+                        }
+                    }
+
+                    if let Some(sh) = &self.sh_irradiance {
+                        if ui.button("Export JSON").clicked() {
+                            let name = if self.file_path.is_empty() {
+                                "sh_irradiance"
+                            } else {
+                                self.file_path.as_str()
+                            };
+                            let json_path = images_dir().join(format!("{name}.json"));
+                            let result = serde_json::to_string_pretty(sh)
+                                .map_err(anyhow::Error::from)
+                                .and_then(|json| Ok(std::fs::write(&json_path, json)?));
+                            match result {
+                                Ok(_) => info!("SH saved to {}", json_path.display()),
+                                Err(e) => error!("Failed to save SH: {e:?}"),
+                            }
+                        }
+                    }
+                });
+
+                let Some(sh) = &self.sh_irradiance else {
+                    ui.label("Load an equirectangular HDRI to bake its irradiance");
+                    return;
+                };
+
+                egui::Grid::new("sh_grid").striped(true).show(ui, |ui| {
+                    ui.label("l, m");
+                    ui.label("R");
+                    ui.label("G");
+                    ui.label("B");
+                    ui.end_row();
+
+                    let indices = [
+                        (0, 0),
+                        (1, -1),
+                        (1, 0),
+                        (1, 1),
+                        (2, -2),
+                        (2, -1),
+                        (2, 0),
+                        (2, 1),
+                        (2, 2),
+                    ];
+                    for ((l, m), coefficient) in indices.iter().zip(&sh.coefficients) {
+                        ui.label(format!("{l}, {m}"));
+                        for value in coefficient {
+                            ui.monospace(format!("{value:+.4}"));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        egui::Window::new("Performance")
+            .open(&mut self.performance_open)
+            .show(ctx, |ui| {
+                self.frame_timing.ui(ui, self.settings.target_fps);
+            });
+
+        let scene_options_pos = self.window_pos(
+            ctx,
+            "Scene Options",
+            egui::Pos2::new(
+                self.window_width as f32 * (1.0 / self.scale_factor) * 0.015,
+                self.window_height as f32 * (1.0 / self.scale_factor) * 0.10,
+            ),
+        );
+        let mut dropper_clicked = None;
+        let mut region_clicked = false;
+        // Loaded once the window has been drawn
+        let mut picked_files = SceneFiles::default();
+        let scene_options = egui::Window::new("Scene Options")
+            .open(&mut self.window_open)
+            .default_pos(scene_options_pos)
+            .show(ctx, |ui| {
+                let previous_colors = (self.color_a, self.color_b);
+                egui::Grid::new("grid_1").show(ui, |ui| {
+                    ui.label("First Color:");
+                    ui.color_edit_button_srgba_unmultiplied(&mut self.color_a);
+                    ui.label("Second Color:");
+                    ui.color_edit_button_srgba_unmultiplied(&mut self.color_b);
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    for target in [DropperTarget::ColorA, DropperTarget::ColorB] {
+                        let active = self.color_dropper.target == Some(target);
+                        if ui
+                            .selectable_label(active, format!("Dropper {}", target.label()))
+                            .on_hover_text(
+                                "Click the image to sample a color, \
+                                 right click always samples Color B",
+                            )
+                            .clicked()
+                        {
+                            // Toggled once the window no longer borrows `self`
+                            dropper_clicked = Some(target);
+                        }
+                    }
+                });
+                ui.collapsing("Color Harmony", |ui| {
+                    let [r, g, b, _] = self.color_a;
+                    if let Some([r, g, b]) = self.color_harmony.ui(ui, [r, g, b]) {
+                        self.color_b = [r, g, b, 0xff];
+                    }
+                });
+                if (self.color_a, self.color_b) != previous_colors {
+                    let [a, b] = [self.color_a, self.color_b]
+                        .map(|[r, g, b, _]| srgb_u8_to_acescg([r, g, b]));
+                    send_event(&self.event_proxy, UserEvent::SetSceneColors([a, b]));
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Randomize Colors").clicked() {
+                        let palette = self.palette.generate(2);
+                        let [r, g, b] = acescg_to_srgb_u8(palette[0]);
+                        self.color_a = [r, g, b, 0xff];
+                        let [r, g, b] = acescg_to_srgb_u8(palette[1]);
+                        self.color_b = [r, g, b, 0xff];
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetSceneColors([palette[0], palette[1]]),
+                        );
+                        send_event(&self.event_proxy, UserEvent::Render);
+                    }
+                });
+                self.palette.ui(ui);
+
+                ui.separator();
+
+                let previous_mode = self.render_mode;
+                egui::ComboBox::from_label("Render mode")
+                    .selected_text(self.render_mode.label())
+                    .show_ui(ui, |ui| {
+                        for &mode in RenderMode::ALL {
+                            ui.selectable_value(&mut self.render_mode, mode, mode.label());
+                        }
+                    });
+                if self.render_mode != previous_mode {
+                    send_event(
+                        &self.event_proxy,
+                        UserEvent::SetRenderMode(self.render_mode),
+                    );
+                }
+
+                match self.render_status {
+                    RenderStatus::Idle | RenderStatus::Finished => {}
+                    RenderStatus::Rendering { completed, total } => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(format!("Rendering tile {completed}/{total}"));
+                            if ui.button("Cancel").clicked() {
+                                send_event(&self.event_proxy, UserEvent::CancelRender);
+                            }
+                        });
+                    }
+                    RenderStatus::Cancelled => {
+                        ui.colored_label(egui::Color32::YELLOW, "Render cancelled");
+                    }
+                }
+
+                let previous_sampler = self.sampler;
+                egui::ComboBox::from_label("Sampler")
+                    .selected_text(format!("{:?}", self.sampler))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.sampler, SamplerKind::Uniform, "Uniform");
+                        ui.selectable_value(&mut self.sampler, SamplerKind::Halton, "Halton");
+                        ui.selectable_value(
+                            &mut self.sampler,
+                            SamplerKind::BlueNoise,
+                            "Blue Noise",
+                        );
+                    });
+                if self.sampler != previous_sampler {
+                    send_event(&self.event_proxy, UserEvent::SetSampler(self.sampler));
+                }
+
+                let previous_ssaa_factor = self.ssaa_factor;
+                let ssaa_label = |factor: u32| match factor {
+                    1 => "Off".to_string(),
+                    _ => format!("{}x", factor * factor),
+                };
+                egui::ComboBox::from_label("Supersampling")
+                    .selected_text(ssaa_label(self.ssaa_factor))
+                    .show_ui(ui, |ui| {
+                        for factor in SSAA_FACTORS {
+                            ui.selectable_value(&mut self.ssaa_factor, factor, ssaa_label(factor));
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Anti-aliasing of the procedural modes, by rendering at a higher \
+                        resolution and averaging it down",
+                    );
+                if self.ssaa_factor > 1 {
+                    // Linear RGBA f32 pixels of the supersampled image
+                    let megabytes = (RENDER_BUFFER_WIDTH * RENDER_BUFFER_HEIGHT) as f32
+                        * (self.ssaa_factor * self.ssaa_factor) as f32
+                        * 16.0
+                        / (1024.0 * 1024.0);
+                    ui.label(format!(
+                        "{}x the memory and render time ({megabytes:.0} MB)",
+                        self.ssaa_factor * self.ssaa_factor
+                    ));
+                }
+                if self.ssaa_factor != previous_ssaa_factor {
+                    send_event(
+                        &self.event_proxy,
+                        UserEvent::SetSsaaFactor(self.ssaa_factor),
+                    );
+                }
+
+                if self.render_mode == RenderMode::MultipointGradient {
+                    self.gradient_editor
+                        .ui(ui, &mut self.palette, &self.event_proxy);
+                }
+
+                if self.render_mode == RenderMode::Lissajous {
+                    let previous = self.lissajous;
+                    let lissajous = &mut self.lissajous;
+                    egui::Grid::new("lissajous_grid").show(ui, |ui| {
+                        ui.label("Frequency X:");
+                        ui.add(egui::Slider::new(&mut lissajous.freq_x, 1..=20));
+                        ui.end_row();
+
+                        ui.label("Frequency Y:");
+                        ui.add(egui::Slider::new(&mut lissajous.freq_y, 1..=20));
+                        ui.end_row();
+
+                        ui.label("Phase:");
+                        ui.add(egui::Slider::new(
+                            &mut lissajous.phase,
+                            0.0..=std::f32::consts::TAU,
+                        ));
+                        ui.end_row();
+
+                        ui.label("Thickness:");
+                        ui.add(egui::Slider::new(&mut lissajous.thickness, 0.5..=5.0));
+                        ui.end_row();
+
+                        ui.label("Color:");
+                        ui.color_edit_button_rgb(&mut lissajous.color);
+                        ui.end_row();
+                    });
+                    ui.checkbox(&mut lissajous.animate, "Animate phase");
+                    if *lissajous != previous {
+                        send_event(&self.event_proxy, UserEvent::SetLissajous(*lissajous));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Starfield {
+                    let previous = self.starfield;
+                    let starfield = &mut self.starfield;
+                    egui::Grid::new("starfield_grid").show(ui, |ui| {
+                        ui.label("Stars:");
+                        ui.add(
+                            egui::Slider::new(&mut starfield.num_stars, 100..=50000)
+                                .logarithmic(true),
+                        );
+                        ui.end_row();
+
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut starfield.seed));
+                        ui.end_row();
+
+                        ui.label("Magnitude exponent:");
+                        ui.add(egui::Slider::new(
+                            &mut starfield.magnitude_exponent,
+                            1.0..=32.0,
+                        ));
+                        ui.end_row();
+
+                        ui.label("Bloom threshold:");
+                        ui.add(
+                            egui::Slider::new(&mut starfield.bloom_threshold, 0.5..=50.0)
+                                .logarithmic(true),
+                        );
+                        ui.end_row();
+                    });
+                    if *starfield != previous {
+                        send_event(&self.event_proxy, UserEvent::SetStarfield(*starfield));
+                    }
+                }
+
+                if self.render_mode == RenderMode::SpectralGradient {
+                    let previous = self.spectral_range;
+                    let [lambda_lo, lambda_hi] = &mut self.spectral_range;
+                    ui.add(egui::Slider::new(lambda_lo, 360.0..=830.0).text("Lambda low (nm)"));
+                    ui.add(egui::Slider::new(lambda_hi, 360.0..=830.0).text("Lambda high (nm)"));
+                    if self.spectral_range != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetSpectralRange(self.spectral_range),
+                        );
+                    }
+                }
+
+                if self.render_mode == RenderMode::FractalFlame {
+                    self.flame_editor.ui(ui, &self.event_proxy);
+                }
+
+                if self.render_mode == RenderMode::ValueNoise {
+                    let previous = self.value_noise;
+                    let noise = &mut self.value_noise;
+                    egui::Grid::new("value_noise_grid").show(ui, |ui| {
+                        ui.label("Frequency:");
+                        ui.add(
+                            egui::Slider::new(&mut noise.frequency, 0.5..=64.0).logarithmic(true),
+                        );
+                        ui.end_row();
+
+                        ui.label("Octaves:");
+                        ui.add(egui::Slider::new(&mut noise.octaves, 1..=8));
+                        ui.end_row();
+
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut noise.seed));
+                        ui.end_row();
+
+                        ui.label("Blend:");
+                        egui::ComboBox::from_id_source("noise_blend_mode")
+                            .selected_text(format!("{:?}", noise.blend_mode))
+                            .show_ui(ui, |ui| {
+                                for mode in [
+                                    NoiseBlendMode::Replace,
+                                    NoiseBlendMode::Add,
+                                    NoiseBlendMode::Multiply,
+                                    NoiseBlendMode::Screen,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut noise.blend_mode,
+                                        mode,
+                                        format!("{mode:?}"),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    });
+                    if *noise != previous {
+                        send_event(&self.event_proxy, UserEvent::SetValueNoise(*noise));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Sky {
+                    let previous = self.sky;
+                    let sky = &mut self.sky;
+                    egui::Grid::new("sky_grid").show(ui, |ui| {
+                        ui.label("Sun elevation:");
+                        ui.add(egui::Slider::new(&mut sky.sun_elevation, 0.0..=90.0).suffix("°"));
+                        ui.end_row();
+
+                        ui.label("Sun azimuth:");
+                        ui.add(egui::Slider::new(&mut sky.sun_azimuth, 0.0..=360.0).suffix("°"));
+                        ui.end_row();
+
+                        ui.label("Turbidity:");
+                        ui.add(egui::Slider::new(&mut sky.turbidity, 1.0..=10.0));
+                        ui.end_row();
+
+                        ui.label("Ground albedo:");
+                        ui.color_edit_button_rgb(&mut sky.ground_albedo);
+                        ui.end_row();
+                    });
+                    if *sky != previous {
+                        send_event(&self.event_proxy, UserEvent::SetSky(*sky));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Turbulence {
+                    let previous = self.turbulence;
+                    let turbulence = &mut self.turbulence;
+                    egui::Grid::new("turbulence_grid").show(ui, |ui| {
+                        ui.label("Frequency:");
+                        ui.add(
+                            egui::Slider::new(&mut turbulence.frequency, 0.5..=64.0)
+                                .logarithmic(true),
+                        );
+                        ui.end_row();
+
+                        ui.label("Octaves:");
+                        ui.add(egui::Slider::new(&mut turbulence.octaves, 1..=8));
+                        ui.end_row();
+
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut turbulence.seed));
+                        ui.end_row();
+
+                        ui.label("Contrast:");
+                        ui.add(egui::Slider::new(&mut turbulence.contrast, 0.1..=4.0));
+                        ui.end_row();
+
+                        ui.label("Pattern:");
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(
+                                &mut turbulence.pattern,
+                                TurbulencePattern::Noise,
+                                "Noise",
+                            );
+                            ui.selectable_value(
+                                &mut turbulence.pattern,
+                                TurbulencePattern::Marble,
+                                "Marble",
+                            );
+                        });
+                        ui.end_row();
+
+                        ui.label("Basis:");
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(
+                                &mut turbulence.basis,
+                                NoiseBasis::Perlin,
+                                "Perlin",
+                            )
+                            .on_hover_text(
+                                "Square lattice. Its cells can show as horizontal \
+                                 and vertical streaks",
+                            );
+                            ui.selectable_value(
+                                &mut turbulence.basis,
+                                NoiseBasis::Simplex,
+                                "Simplex",
+                            )
+                            .on_hover_text(
+                                "Triangle lattice. Fewer directional artifacts, a softer \
+                                 and blobbier look, and cheaper in 3D and above",
+                            );
+                        });
+                        ui.end_row();
+                    });
+                    ui.checkbox(&mut turbulence.turbulent, "Turbulent (abs per octave)");
+                    if *turbulence != previous {
+                        send_event(&self.event_proxy, UserEvent::SetTurbulence(*turbulence));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Bricks {
+                    let previous = self.bricks;
+                    let bricks = &mut self.bricks;
+                    egui::Grid::new("bricks_grid").show(ui, |ui| {
+                        ui.label("Brick size:");
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut bricks.brick_w)
+                                    .clamp_range(2.0..=200.0)
+                                    .suffix(" px"),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut bricks.brick_h)
+                                    .clamp_range(2.0..=200.0)
+                                    .suffix(" px"),
+                            );
+                        });
+                        ui.end_row();
+
+                        ui.label("Mortar:");
+                        ui.add(egui::Slider::new(&mut bricks.mortar, 0.0..=10.0).suffix(" px"));
+                        ui.end_row();
+
+                        ui.label("Row offset:");
+                        ui.add(egui::Slider::new(&mut bricks.offset, 0.0..=1.0));
+                        ui.end_row();
+
+                        ui.label("Variation:");
+                        ui.add(egui::Slider::new(&mut bricks.variation, 0.0..=0.3));
+                        ui.end_row();
+
+                        ui.label("Brick color:");
+                        ui.color_edit_button_rgb(&mut bricks.brick_color);
+                        ui.end_row();
+
+                        ui.label("Mortar color:");
+                        ui.color_edit_button_rgb(&mut bricks.mortar_color);
+                        ui.end_row();
+                    });
+                    if *bricks != previous {
+                        send_event(&self.event_proxy, UserEvent::SetBricks(*bricks));
+                    }
+                }
+
+                if self.render_mode == RenderMode::ColorWheel {
+                    let previous = (self.color_wheel_lightness, self.color_wheel_mode);
+                    egui::ComboBox::from_label("Color model")
+                        .selected_text(format!("{:?}", self.color_wheel_mode))
+                        .show_ui(ui, |ui| {
+                            for (mode, label) in [
+                                (ColorWheelMode::Hsl, "HSL"),
+                                (ColorWheelMode::Oklab, "Oklab"),
+                                (ColorWheelMode::AcesCg, "ACEScg"),
+                            ] {
+                                ui.selectable_value(&mut self.color_wheel_mode, mode, label);
+                            }
+                        });
+                    ui.add(
+                        egui::Slider::new(&mut self.color_wheel_lightness, 0.0..=1.0)
+                            .text("Lightness"),
+                    );
+                    if (self.color_wheel_lightness, self.color_wheel_mode) != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetColorWheel {
+                                lightness: self.color_wheel_lightness,
+                                mode: self.color_wheel_mode,
+                            },
+                        );
+                    }
+                }
+
+                if self.render_mode == RenderMode::WoodGrain {
+                    let previous = self.wood_grain;
+                    let wood = &mut self.wood_grain;
+                    egui::Grid::new("wood_grain_grid").show(ui, |ui| {
+                        ui.label("Ring frequency:");
+                        ui.add(egui::Slider::new(&mut wood.ring_frequency, 0.5..=10.0));
+                        ui.end_row();
+
+                        ui.label("Turbulence:");
+                        ui.add(egui::Slider::new(&mut wood.turbulence_scale, 0.0..=5.0));
+                        ui.end_row();
+
+                        ui.label("Grain angle:");
+                        ui.add(egui::Slider::new(&mut wood.grain_angle, 0.0..=180.0).suffix("°"));
+                        ui.end_row();
+
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut wood.seed));
+                        ui.end_row();
+
+                        ui.label("Dark grain:");
+                        ui.color_edit_button_rgb(&mut wood.grain_color_a);
+                        ui.end_row();
+
+                        ui.label("Light grain:");
+                        ui.color_edit_button_rgb(&mut wood.grain_color_b);
+                        ui.end_row();
+                    });
+                    if *wood != previous {
+                        send_event(&self.event_proxy, UserEvent::SetWoodGrain(*wood));
+                    }
+                }
+
+                if self.render_mode == RenderMode::PoissonDisk {
+                    let previous = self.poisson_disk;
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::Slider::new(&mut self.poisson_disk.min_distance, 1.0..=50.0)
+                                .text("Min distance")
+                                .suffix(" px"),
+                        );
+                        if ui
+                            .button("Generate")
+                            .on_hover_text("New random pattern")
+                            .clicked()
+                        {
+                            self.poisson_disk.seed = self.poisson_disk.seed.wrapping_add(1);
+                        }
+                    });
+                    if self.poisson_disk != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetPoissonDisk(self.poisson_disk),
+                        );
+                    }
+                }
+
+                if self.render_mode == RenderMode::Oscilloscope {
+                    let previous = self.oscilloscope;
+                    let oscilloscope = &mut self.oscilloscope;
+                    egui::Grid::new("oscilloscope_grid").show(ui, |ui| {
+                        ui.label("Persistence:");
+                        ui.add(egui::Slider::new(&mut oscilloscope.persistence, 0.8..=1.0));
+                        ui.end_row();
+
+                        ui.label("Trace color:");
+                        ui.color_edit_button_rgb(&mut oscilloscope.color);
+                        ui.end_row();
+                    });
+                    if *oscilloscope != previous {
+                        send_event(&self.event_proxy, UserEvent::SetOscilloscope(*oscilloscope));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Camera {
+                    if self.camera_devices.is_empty() {
+                        ui.label(if cfg!(feature = "camera-input") {
+                            "No camera detected"
+                        } else {
+                            "Built without the camera-input feature"
+                        });
+                    } else {
+                        let previous = self.camera;
+                        let camera = &mut self.camera;
+                        let devices = &self.camera_devices;
+                        egui::Grid::new("camera_grid").show(ui, |ui| {
+                            ui.label("Device:");
+                            egui::ComboBox::from_id_source("camera_device")
+                                .selected_text(
+                                    devices
+                                        .get(camera.device as usize)
+                                        .map_or("None", String::as_str),
+                                )
+                                .show_ui(ui, |ui| {
+                                    for (index, name) in devices.iter().enumerate() {
+                                        ui.selectable_value(&mut camera.device, index as u32, name);
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.checkbox(&mut camera.flip_horizontal, "Flip Camera H");
+                            ui.end_row();
+                        });
+                        if *camera != previous {
+                            send_event(&self.event_proxy, UserEvent::SetCamera(*camera));
+                        }
+                    }
+                }
+
+                if self.render_mode == RenderMode::NodeGraph && ui.button("Edit Graph...").clicked()
+                {
+                    self.node_graph_open = true;
+                }
+
+                if self.render_mode == RenderMode::Terrain {
+                    let previous = self.terrain.clone();
+                    let terrain = &mut self.terrain;
+                    egui::Grid::new("terrain_grid").show(ui, |ui| {
+                        ui.label("Frequency:");
+                        ui.add(
+                            egui::Slider::new(&mut terrain.frequency, 0.5..=16.0).logarithmic(true),
+                        );
+                        ui.end_row();
+
+                        ui.label("Octaves:");
+                        ui.add(egui::Slider::new(&mut terrain.octaves, 1..=10));
+                        ui.end_row();
+
+                        ui.label("Persistence:");
+                        ui.add(egui::Slider::new(&mut terrain.persistence, 0.1..=0.9));
+                        ui.end_row();
+
+                        ui.label("Lacunarity:");
+                        ui.add(egui::Slider::new(&mut terrain.lacunarity, 1.5..=3.0));
+                        ui.end_row();
+
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut terrain.seed));
+                        ui.end_row();
+
+                        ui.label("Sea level:");
+                        ui.add(egui::Slider::new(&mut terrain.sea_level, 0.0..=1.0));
+                        ui.end_row();
+
+                        ui.label("Snow level:");
+                        ui.add(egui::Slider::new(&mut terrain.snow_level, 0.0..=1.0));
+                        ui.end_row();
+
+                        ui.label("Relief:");
+                        ui.add(egui::Slider::new(&mut terrain.relief, 0.0..=5.0));
+                        ui.end_row();
+
+                        ui.label("Sun azimuth:");
+                        ui.add(
+                            egui::Slider::new(&mut terrain.sun_azimuth, 0.0..=360.0).suffix("°"),
+                        );
+                        ui.end_row();
+
+                        ui.label("Sun elevation:");
+                        ui.add(
+                            egui::Slider::new(&mut terrain.sun_elevation, 0.0..=90.0).suffix("°"),
+                        );
+                        ui.end_row();
+                    });
+
+                    ui.label("Color ramp, by height:");
+                    let mut removed = None;
+                    for (index, stop) in terrain.ramp.stops.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::Slider::new(&mut stop.position, 0.0..=1.0));
+                            ui.color_edit_button_rgb(&mut stop.color);
+                            if ui.small_button("Remove").clicked() {
+                                removed = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = removed {
+                        terrain.ramp.stops.remove(index);
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Add Stop").clicked() {
+                            terrain.ramp.stops.push(ColorStop {
+                                position: 0.5,
+                                color: [0.5; 3],
+                            });
+                        }
+                        if ui.button("Reset Ramp").clicked() {
+                            terrain.ramp = TerrainParams::default().ramp;
+                        }
+                    });
+
+                    if *terrain != previous {
+                        send_event(&self.event_proxy, UserEvent::SetTerrain(terrain.clone()));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Truchet {
+                    let previous = self.truchet;
+                    let truchet = &mut self.truchet;
+                    egui::Grid::new("truchet_grid").show(ui, |ui| {
+                        ui.label("Tile set:");
+                        egui::ComboBox::from_id_source("truchet_tile_set")
+                            .selected_text(truchet.tile_set.label())
+                            .show_ui(ui, |ui| {
+                                for tile_set in TruchetTileSet::ALL {
+                                    ui.selectable_value(
+                                        &mut truchet.tile_set,
+                                        tile_set,
+                                        tile_set.label(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Tile size:");
+                        ui.add(egui::Slider::new(&mut truchet.tile_size, 8..=128).suffix(" px"));
+                        ui.end_row();
+
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut truchet.seed));
+                        ui.end_row();
+
+                        ui.label("Colors:");
+                        ui.horizontal(|ui| {
+                            ui.color_edit_button_rgb(&mut truchet.color_a);
+                            ui.color_edit_button_rgb(&mut truchet.color_b);
+                        });
+                        ui.end_row();
+                    });
+                    if *truchet != previous {
+                        send_event(&self.event_proxy, UserEvent::SetTruchet(*truchet));
+                    }
+                }
+
+                if self.render_mode == RenderMode::GrungeMap {
+                    let previous = self.grunge;
+                    let grunge = &mut self.grunge;
+                    egui::Grid::new("grunge_grid").show(ui, |ui| {
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut grunge.seed));
+                        ui.end_row();
+
+                        ui.label("Noise scale:");
+                        ui.add(
+                            egui::Slider::new(&mut grunge.noise_scale, 4.0..=200.0).suffix(" px"),
+                        );
+                        ui.end_row();
+
+                        ui.label("Scratches:");
+                        ui.add(egui::Slider::new(&mut grunge.scratch_count, 0..=200));
+                        ui.end_row();
+
+                        ui.label("Scratch length:");
+                        range_drag_values(ui, &mut grunge.scratch_length_range, 1.0..=200.0);
+                        ui.end_row();
+
+                        ui.label("Stains:");
+                        ui.add(egui::Slider::new(&mut grunge.stain_count, 0..=50));
+                        ui.end_row();
+
+                        ui.label("Stain radius:");
+                        range_drag_values(ui, &mut grunge.stain_radius_range, 1.0..=100.0);
+                        ui.end_row();
+                    });
+                    if *grunge != previous {
+                        send_event(&self.event_proxy, UserEvent::SetGrunge(*grunge));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Spiral {
+                    let previous = self.spiral;
+                    let spiral = &mut self.spiral;
+                    egui::Grid::new("spiral_grid").show(ui, |ui| {
+                        ui.label("Start radius (a):");
+                        ui.add(egui::Slider::new(&mut spiral.a, 0.5..=50.0).suffix(" px"));
+                        ui.end_row();
+
+                        ui.label("Growth rate (b):");
+                        ui.add(egui::Slider::new(&mut spiral.b, -0.5..=0.5))
+                            .on_hover_text("0 draws an Archimedean spiral, turns a px apart");
+                        ui.end_row();
+
+                        ui.label("Turns:");
+                        ui.add(egui::Slider::new(&mut spiral.num_turns, 0.5..=20.0));
+                        ui.end_row();
+
+                        ui.label("Phase:");
+                        ui.add(egui::Slider::new(
+                            &mut spiral.phase,
+                            0.0..=std::f32::consts::TAU,
+                        ));
+                        ui.end_row();
+
+                        ui.label("Thickness:");
+                        ui.add(egui::Slider::new(&mut spiral.thickness, 0.5..=8.0));
+                        ui.end_row();
+
+                        ui.label("Gradient:");
+                        ui.horizontal(|ui| {
+                            ui.color_edit_button_rgb(&mut spiral.color_a);
+                            ui.color_edit_button_rgb(&mut spiral.color_b);
+                        });
+                        ui.end_row();
+                    });
+                    ui.checkbox(&mut spiral.animate, "Animate phase");
+                    if *spiral != previous {
+                        send_event(&self.event_proxy, UserEvent::SetSpiral(*spiral));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Pcb {
+                    let previous = self.pcb;
+                    let pcb = &mut self.pcb;
+                    egui::Grid::new("pcb_grid").show(ui, |ui| {
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut pcb.seed));
+                        ui.end_row();
+
+                        ui.label("Trace density:");
+                        ui.add(egui::Slider::new(&mut pcb.trace_density, 0.0..=1.0));
+                        ui.end_row();
+
+                        ui.label("Grid cell:");
+                        ui.add(egui::Slider::new(&mut pcb.cell_size, 8..=32).suffix(" px"));
+                        ui.end_row();
+
+                        ui.label("Traces:");
+                        ui.color_edit_button_rgb(&mut pcb.trace_color);
+                        ui.end_row();
+
+                        ui.label("Board:");
+                        ui.color_edit_button_rgb(&mut pcb.bg_color);
+                        ui.end_row();
+
+                        ui.label("Glow:");
+                        ui.add(egui::Slider::new(&mut pcb.glow_strength, 0.0..=4.0));
+                        ui.end_row();
+                    });
+                    if *pcb != previous {
+                        send_event(&self.event_proxy, UserEvent::SetPcb(*pcb));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Raymarcher {
+                    let previous = self.raymarcher.clone();
+                    let raymarcher = &mut self.raymarcher;
+                    egui::Grid::new("raymarcher_grid").show(ui, |ui| {
+                        ui.label("Camera:");
+                        drag_vec3(ui, &mut raymarcher.camera.position);
+                        ui.end_row();
+
+                        ui.label("Look at:");
+                        drag_vec3(ui, &mut raymarcher.camera.target);
+                        ui.end_row();
+
+                        ui.label("Field of view:");
+                        ui.add(
+                            egui::Slider::new(&mut raymarcher.camera.fov, 10.0..=120.0).suffix("°"),
+                        );
+                        ui.end_row();
+
+                        let light = &mut raymarcher.scene.light;
+                        ui.label("Light:");
+                        drag_vec3(ui, &mut light.position);
+                        ui.end_row();
+
+                        ui.label("Light color:");
+                        ui.horizontal(|ui| {
+                            ui.color_edit_button_rgb(&mut light.color);
+                            ui.add(
+                                egui::Slider::new(&mut light.intensity, 0.0..=1000.0)
+                                    .logarithmic(true),
+                            );
+                        });
+                        ui.end_row();
+
+                        ui.label("Ambient:");
+                        ui.color_edit_button_rgb(&mut raymarcher.scene.ambient);
+                        ui.end_row();
+
+                        ui.label("Smoothness:");
+                        ui.add(egui::Slider::new(
+                            &mut raymarcher.scene.smoothness,
+                            0.0..=1.0,
+                        ))
+                        .on_hover_text("Distance over which the shapes blend together");
+                        ui.end_row();
+                    });
+
+                    let primitives = &mut raymarcher.scene.primitives;
+                    let mut removed = None;
+                    for (index, primitive) in primitives.iter_mut().enumerate() {
+                        ui.push_id(index, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.strong(primitive.label());
+                                if ui.small_button("Remove").clicked() {
+                                    removed = Some(index);
+                                }
+                            });
+                            egui::Grid::new("primitive_grid").show(ui, |ui| {
+                                let material = match primitive {
+                                    SdfPrimitive::Sphere {
+                                        center,
+                                        radius,
+                                        material,
+                                    } => {
+                                        ui.label("Center:");
+                                        drag_vec3(ui, center);
+                                        ui.end_row();
+                                        ui.label("Radius:");
+                                        ui.add(egui::Slider::new(radius, 0.05..=3.0));
+                                        ui.end_row();
+                                        material
+                                    }
+                                    SdfPrimitive::Box {
+                                        center,
+                                        half_extents,
+                                        material,
+                                    } => {
+                                        ui.label("Center:");
+                                        drag_vec3(ui, center);
+                                        ui.end_row();
+                                        ui.label("Half extents:");
+                                        drag_vec3(ui, half_extents);
+                                        ui.end_row();
+                                        material
+                                    }
+                                    SdfPrimitive::Torus {
+                                        center,
+                                        r_major,
+                                        r_minor,
+                                        material,
+                                    } => {
+                                        ui.label("Center:");
+                                        drag_vec3(ui, center);
+                                        ui.end_row();
+                                        ui.label("Radii:");
+                                        ui.horizontal(|ui| {
+                                            for radius in [r_major, r_minor] {
+                                                ui.add(
+                                                    egui::DragValue::new(radius)
+                                                        .speed(0.01)
+                                                        .clamp_range(0.0..=10.0),
+                                                );
+                                            }
+                                        });
+                                        ui.end_row();
+                                        material
+                                    }
+                                };
+                                ui.label("Albedo:");
+                                ui.color_edit_button_rgb(&mut material.albedo);
+                                ui.end_row();
+                            });
+                        });
+                    }
+                    if let Some(index) = removed {
+                        primitives.remove(index);
+                    }
+                    if primitives.len() < MAX_SDF_PRIMITIVES {
+                        ui.horizontal(|ui| {
+                            let material = SdfMaterial {
+                                albedo: [0.5, 0.5, 0.5],
+                            };
+                            let center = [0.0, 0.0, 0.0];
+                            ui.label("Add:");
+                            if ui.button("Sphere").clicked() {
+                                primitives.push(SdfPrimitive::Sphere {
+                                    center,
+                                    radius: 0.5,
+                                    material,
+                                });
+                            }
+                            if ui.button("Box").clicked() {
+                                primitives.push(SdfPrimitive::Box {
+                                    center,
+                                    half_extents: [0.5, 0.5, 0.5],
+                                    material,
+                                });
+                            }
+                            if ui.button("Torus").clicked() {
+                                primitives.push(SdfPrimitive::Torus {
+                                    center,
+                                    r_major: 1.0,
+                                    r_minor: 0.25,
+                                    material,
+                                });
+                            }
+                        });
+                    }
+
+                    if *raymarcher != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetRaymarcher(raymarcher.clone()),
+                        );
+                    }
+                }
+
+                if self.render_mode == RenderMode::BrdfSpheres {
+                    let previous = self.brdf_spheres;
+                    let brdf = &mut self.brdf_spheres;
+                    egui::Grid::new("brdf_spheres_grid").show(ui, |ui| {
+                        ui.label("Base color:");
+                        ui.color_edit_button_rgb(&mut brdf.base_color);
+                        ui.end_row();
+
+                        ui.label("Roughness rows:");
+                        ui.add(egui::Slider::new(
+                            &mut brdf.roughness_steps,
+                            1..=MAX_BRDF_GRID_SIZE,
+                        ))
+                        .on_hover_text("From smooth at the top to rough at the bottom");
+                        ui.end_row();
+
+                        ui.label("Metallic columns:");
+                        ui.add(egui::Slider::new(
+                            &mut brdf.metallic_steps,
+                            1..=MAX_BRDF_GRID_SIZE,
+                        ))
+                        .on_hover_text("From dielectric on the left to metal on the right");
+                        ui.end_row();
+
+                        ui.label("Light direction:");
+                        drag_vec3(ui, &mut brdf.light_dir);
+                        ui.end_row();
+
+                        ui.label("Light intensity:");
+                        ui.add(egui::Slider::new(&mut brdf.light_intensity, 0.0..=10.0));
+                        ui.end_row();
+                    });
+                    if *brdf != previous {
+                        send_event(&self.event_proxy, UserEvent::SetBrdfSpheres(*brdf));
+                    }
+                }
+
+                if self.render_mode == RenderMode::TextureSynthesis {
+                    ui.horizontal(|ui| {
+                        if ui.button("Load Exemplar").clicked() {
+                            picked_files.exemplar = rfd::FileDialog::new()
+                                .add_filter("Images", &["exr", "png", "jpg", "jpeg"])
+                                .pick_file();
+                        }
+                        ui.label(self.exemplar_name.as_deref().unwrap_or("No exemplar"));
+                    });
+
+                    let previous = self.texture_quilting;
+                    let texture_quilting = &mut self.texture_quilting;
+                    egui::Grid::new("texture_quilting_grid").show(ui, |ui| {
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut texture_quilting.seed));
+                        ui.end_row();
+
+                        ui.label("Patch size:");
+                        ui.add(
+                            egui::Slider::new(&mut texture_quilting.patch_size, 16..=64)
+                                .suffix(" px"),
+                        );
+                        ui.end_row();
+
+                        ui.label("Overlap:");
+                        ui.add(egui::Slider::new(
+                            &mut texture_quilting.overlap_fraction,
+                            0.1..=0.5,
+                        ));
+                        ui.end_row();
+                    });
+                    if *texture_quilting != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetTextureQuilting(*texture_quilting),
+                        );
+                    }
+                }
+
+                if self.render_mode == RenderMode::Worley {
+                    let previous = self.worley;
+                    let worley = &mut self.worley;
+                    egui::Grid::new("worley_grid").show(ui, |ui| {
+                        ui.label("Frequency:");
+                        ui.add(
+                            egui::Slider::new(&mut worley.frequency, 0.5..=20.0).logarithmic(true),
+                        );
+                        ui.end_row();
+
+                        ui.label("Jitter:");
+                        ui.add(egui::Slider::new(&mut worley.jitter, 0.0..=1.0));
+                        ui.end_row();
+
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut worley.seed));
+                        ui.end_row();
+
+                        ui.label("Metric:");
+                        let metric_label = match worley.metric {
+                            WorleyMetric::Euclidean => "Euclidean",
+                            WorleyMetric::Manhattan => "Manhattan",
+                            WorleyMetric::Chebyshev => "Chebyshev",
+                            WorleyMetric::Minkowski { .. } => "Minkowski",
+                        };
+                        egui::ComboBox::from_id_source("worley_metric")
+                            .selected_text(metric_label)
+                            .show_ui(ui, |ui| {
+                                let choices = [
+                                    (WorleyMetric::Euclidean, "Euclidean"),
+                                    (WorleyMetric::Manhattan, "Manhattan"),
+                                    (WorleyMetric::Chebyshev, "Chebyshev"),
+                                    (WorleyMetric::Minkowski { p: 3.0 }, "Minkowski"),
+                                ];
+                                for (choice, label) in choices {
+                                    // Keep the exponent when Minkowski is picked again
+                                    if ui.selectable_label(metric_label == label, label).clicked()
+                                        && metric_label != label
+                                    {
+                                        worley.metric = choice;
+                                    }
+                                }
+                            });
+                        ui.end_row();
+
+                        if let WorleyMetric::Minkowski { p } = &mut worley.metric {
+                            ui.label("Exponent:");
+                            ui.add(egui::Slider::new(p, 0.5..=8.0));
+                            ui.end_row();
+                        }
+
+                        ui.label("Output:");
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(&mut worley.output, WorleyOutput::F1, "F1");
+                            ui.selectable_value(&mut worley.output, WorleyOutput::F2, "F2");
+                            ui.selectable_value(
+                                &mut worley.output,
+                                WorleyOutput::F2MinusF1,
+                                "F2 - F1",
+                            );
+                            ui.selectable_value(
+                                &mut worley.output,
+                                WorleyOutput::Cracked,
+                                "Cracked",
+                            );
+                        });
+                        ui.end_row();
+                    });
+                    if *worley != previous {
+                        send_event(&self.event_proxy, UserEvent::SetWorley(*worley));
+                    }
+                }
+
+                if self.render_mode == RenderMode::Mandelbrot {
+                    let view = &mut self.mandelbrot_view;
+                    let mut changed = false;
+                    egui::Grid::new("mandelbrot_grid").show(ui, |ui| {
+                        ui.label("Center:");
+                        ui.horizontal(|ui| {
+                            let speed = view.scale * 0.005;
+                            changed |= ui
+                                .add(egui::DragValue::new(&mut view.center[0]).speed(speed))
+                                .changed();
+                            changed |= ui
+                                .add(egui::DragValue::new(&mut view.center[1]).speed(speed))
+                                .changed();
+                        });
+                        ui.end_row();
+
+                        ui.label("Scale:");
+                        let speed = view.scale * 0.01;
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut view.scale)
+                                    .speed(speed)
+                                    .clamp_range(1e-12..=10.0),
+                            )
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("Max iterations:");
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut view.max_iterations)
+                                    .clamp_range(1..=10000),
+                            )
+                            .changed();
+                        ui.end_row();
+                    });
+                    if changed {
+                        send_event(&self.event_proxy, UserEvent::SetMandelbrotView(*view));
+                    }
+
+                    egui::CollapsingHeader::new("Animation").show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Frame:");
+                            ui.add(egui::DragValue::new(&mut self.keyframe_frame));
+                            if ui.button("Record Keyframe").clicked() {
+                                send_event(
+                                    &self.event_proxy,
+                                    UserEvent::RecordKeyframe {
+                                        frame_number: self.keyframe_frame,
+                                    },
+                                );
+                                // Leave one second at 24 fps before the next keyframe
+                                self.keyframe_frame += 24;
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Preview Animation").clicked() {
+                                send_event(&self.event_proxy, UserEvent::PreviewAnimation);
+                            }
+                            if ui.button("Export Sequence").clicked() {
+                                let name = if self.file_path.is_empty() {
+                                    "mandelbrot"
+                                } else {
+                                    self.file_path.as_str()
+                                };
+                                let prefix = images_dir().join(name);
+                                send_event(&self.event_proxy, UserEvent::ExportSequence(prefix));
+                            }
+                            if ui.button("Clear Keyframes").clicked() {
+                                self.keyframe_frame = 0;
+                                send_event(&self.event_proxy, UserEvent::ClearKeyframes);
+                            }
+                        });
+                    });
+                }
+
+                ui.separator();
+
+                ui.collapsing("Working Color Space", |ui| {
+                    let previous_encoding = self.storage_encoding;
+                    ui.radio_value(
+                        &mut self.storage_encoding,
+                        StorageEncoding::Linear,
+                        "Linear (ACEScg)",
+                    );
+                    ui.radio_value(
+                        &mut self.storage_encoding,
+                        StorageEncoding::AcesCct,
+                        "Log (ACEScct)",
+                    );
+                    if self.storage_encoding != previous_encoding {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetStorageEncoding(self.storage_encoding),
+                        );
+                    }
+                });
+
+                ui.collapsing("Soft Proof", |ui| {
+                    let previous = (self.soft_proof_target, self.gamut_warning);
+                    egui::ComboBox::from_label("Target display")
+                        .selected_text(format!("{:?}", self.soft_proof_target))
+                        .show_ui(ui, |ui| {
+                            for (target, label) in [
+                                (SoftProofTarget::Off, "Off"),
+                                (SoftProofTarget::Srgb, "sRGB"),
+                                (SoftProofTarget::DisplayP3, "Display P3"),
+                                (SoftProofTarget::Rec2020, "Rec. 2020"),
+                                (SoftProofTarget::DciP3, "DCI-P3"),
+                            ] {
+                                ui.selectable_value(&mut self.soft_proof_target, target, label);
+                            }
+                        });
+                    ui.checkbox(&mut self.gamut_warning, "Gamut Warning");
+                    if (self.soft_proof_target, self.gamut_warning) != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetSoftProof {
+                                target: self.soft_proof_target,
+                                gamut_warning: self.gamut_warning,
+                            },
+                        );
+                    }
+                });
+
+                ui.collapsing("Print", |ui| {
+                    let previous = (self.display_mode, self.print);
+                    let mut cmyk = self.display_mode == DisplayMode::CmykSoft;
+                    ui.checkbox(&mut cmyk, "CMYK Preview")
+                        .on_hover_text("Separate to CMYK and show the inks on paper");
+                    self.display_mode = if cmyk {
+                        DisplayMode::CmykSoft
+                    } else {
+                        DisplayMode::Normal
+                    };
+                    ui.add_enabled_ui(cmyk, |ui| {
+                        let mut ink_limit = self.print.ink_limit * 100.0;
+                        ui.add(
+                            egui::Slider::new(&mut ink_limit, 200.0..=400.0)
+                                .text("Ink limit")
+                                .suffix("%"),
+                        );
+                        self.print.ink_limit = ink_limit / 100.0;
+                        ui.horizontal(|ui| {
+                            ui.label("Paper white:");
+                            ui.color_edit_button_rgb(&mut self.print.paper_white);
+                        });
+                    });
+                    if (self.display_mode, self.print) != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetDisplayMode {
+                                mode: self.display_mode,
+                                print: self.print,
+                            },
+                        );
+                    }
+                });
+
+                ui.collapsing("Format", |ui| {
+                    let previous = self.pixel_aspect;
+                    let selected = if self.pixel_aspect_custom {
+                        None
+                    } else {
+                        self.pixel_aspect.preset_label()
+                    };
+                    egui::ComboBox::from_label("Pixel aspect ratio")
+                        .selected_text(selected.unwrap_or(PIXEL_ASPECT_CUSTOM))
+                        .show_ui(ui, |ui| {
+                            for (label, preset) in PixelAspectRatio::PRESETS {
+                                if ui
+                                    .selectable_label(selected == Some(label), label)
+                                    .clicked()
+                                {
+                                    self.pixel_aspect = preset;
+                                    self.pixel_aspect_custom = false;
+                                }
+                            }
+                            ui.selectable_value(
+                                &mut self.pixel_aspect_custom,
+                                true,
+                                PIXEL_ASPECT_CUSTOM,
+                            )
+                            .on_hover_text("Ratio from the header of a DPX file, or any other");
+                        });
+                    if selected.is_none() {
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut self.pixel_aspect.num)
+                                    .clamp_range(1..=100),
+                            );
+                            ui.label(":");
+                            ui.add(
+                                egui::DragValue::new(&mut self.pixel_aspect.den)
+                                    .clamp_range(1..=100),
+                            );
+                        });
+                    }
+                    ui.label(format!(
+                        "Pixels are {:.3} times as wide as they are tall",
+                        self.pixel_aspect.ratio()
+                    ));
+                    if self.pixel_aspect != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetPixelAspectRatio(self.pixel_aspect),
+                        );
+                    }
+
+                    let previous = self.alpha_mode;
+                    ui.horizontal(|ui| {
+                        ui.label("Alpha:");
+                        for mode in AlphaMode::ALL {
+                            ui.radio_value(&mut self.alpha_mode, mode, mode.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Premultiplied colors composite without fringes");
+                    if self.alpha_mode != previous {
+                        send_event(&self.event_proxy, UserEvent::SetAlphaMode(self.alpha_mode));
+                    }
+                });
+
+                ui.collapsing("Output", |ui| {
+                    let previous = self.output_color_space;
+                    egui::ComboBox::from_label("Color space")
+                        .selected_text(self.output_color_space.label())
+                        .show_ui(ui, |ui| {
+                            for space in OutputColorSpace::ALL {
+                                ui.selectable_value(
+                                    &mut self.output_color_space,
+                                    space,
+                                    space.label(),
+                                );
+                            }
+                        });
+                    if self.output_color_space != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetOutputColorSpace(self.output_color_space),
+                        );
+                    }
+                    if self.output_color_space != OutputColorSpace::Srgb {
+                        let warning = if self.surface_format == SurfaceFormat::Rgba16Float {
+                            "The Rgba16Float surface is written in linear sRGB, \
+                             the output color space only applies to 8 bit formats"
+                                .to_owned()
+                        } else {
+                            format!(
+                                "The {:?} surface is shown as sRGB, colors only look right \
+                                 on a display set to {}",
+                                self.surface_format,
+                                self.output_color_space.label()
+                            )
+                        };
+                        ui.colored_label(egui::Color32::YELLOW, format!("⚠ {warning}"));
+                    }
+
+                    let previous = (self.dither_mode, self.dither_scale);
+                    ui.horizontal(|ui| {
+                        for mode in DitherMode::ALL {
+                            ui.radio_value(&mut self.dither_mode, mode, mode.label());
+                        }
+                    });
+                    ui.add_enabled(
+                        self.dither_mode != DitherMode::None,
+                        egui::Slider::new(&mut self.dither_scale, 0.0..=2.0).text("Dithering"),
+                    )
+                    .on_hover_text("Size of the dither pattern, in 8 bit code values");
+                    if (self.dither_mode, self.dither_scale) != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetDither {
+                                mode: self.dither_mode,
+                                scale: self.dither_scale,
+                            },
+                        );
+                    }
+                });
+
+                ui.collapsing("Clear", |ui| {
+                    let previous = (self.clear_before_render, self.clear_color);
+                    ui.checkbox(&mut self.clear_before_render, "Clear before render")
+                        .on_hover_text("Turn off to keep trails of the previous frames");
+                    ui.horizontal(|ui| {
+                        ui.label("Clear color");
+                        ui.color_edit_button_rgba_unmultiplied(&mut self.clear_color);
+                    });
+                    if (self.clear_before_render, self.clear_color) != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetClear {
+                                enabled: self.clear_before_render,
+                                color: self.clear_color,
+                            },
+                        );
+                    }
+                });
+
+                ui.collapsing("Paint", |ui| {
+                    let previous = (self.paint_mode, self.brush);
+                    ui.checkbox(&mut self.paint_mode, "Paint mode")
+                        .on_hover_text("Click and drag on the image to paint");
+                    ui.add_enabled_ui(self.paint_mode, |ui| {
+                        let brush = &mut self.brush;
+                        ui.horizontal(|ui| {
+                            ui.label("Tool");
+                            ui.selectable_value(&mut brush.tool, BrushTool::Paint, "Paint");
+                            ui.selectable_value(&mut brush.tool, BrushTool::Clone, "Clone")
+                                .on_hover_text(
+                                    "Click to pick the source, then drag to copy from it",
+                                );
+                        });
+                        ui.add(egui::Slider::new(&mut brush.radius, 1.0..=200.0).text("Radius"));
+                        ui.add_enabled(
+                            brush.tool == BrushTool::Paint,
+                            egui::Slider::new(&mut brush.hardness, 0.0..=1.0).text("Hardness"),
+                        );
+                        ui.add(egui::Slider::new(&mut brush.opacity, 0.0..=1.0).text("Opacity"));
+                        if brush.tool == BrushTool::Paint {
+                            ui.horizontal(|ui| {
+                                ui.label("Color");
+                                ui.color_edit_button_rgba_unmultiplied(&mut brush.color);
+                            });
+                        } else if self.clone_source.is_none() {
+                            ui.label("Click on the image to pick the source");
+                        } else if ui.button("Pick New Source").clicked() {
+                            send_event(&self.event_proxy, UserEvent::ResetCloneSource);
+                        }
+                        if ui.button("Undo Stroke").on_hover_text("Ctrl+Z").clicked() {
+                            send_event(&self.event_proxy, UserEvent::UndoStroke);
+                        }
+                    });
+                    if (self.paint_mode, self.brush) != previous {
+                        let brush = self.paint_mode.then_some(self.brush);
+                        send_event(&self.event_proxy, UserEvent::SetBrush(brush));
+                    }
+                });
+
+                ui.collapsing("Distance Field", |ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut self.sdf_scale)
+                            .clamp_range(0.001..=100.0)
+                            .speed(0.01)
+                            .prefix("Scale: ")
+                            .suffix(" per pixel"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Range:");
+                        ui.add(egui::DragValue::new(&mut self.sdf_range[0]).speed(0.1));
+                        ui.label("to");
+                        ui.add(egui::DragValue::new(&mut self.sdf_range[1]).speed(0.1));
+                    });
+                    self.sdf_range[1] = self.sdf_range[1].max(self.sdf_range[0]);
+                    if ui
+                        .button("Generate SDF from Luma")
+                        .on_hover_text(
+                            "Replace the image with the distance to the edge of its pixels \
+                             brighter than 0.5, save it with Single Channel (R) for engines",
+                        )
+                        .clicked()
+                    {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::GenerateSdf {
+                                scale: self.sdf_scale,
+                                range: self.sdf_range,
+                            },
+                        );
+                    }
+                });
+
+                ui.collapsing("Temporal Denoise", |ui| {
+                    let previous = (self.denoise_enabled, self.denoise_blend);
+                    ui.checkbox(&mut self.denoise_enabled, "Average animated frames");
+                    ui.add(
+                        egui::Slider::new(&mut self.denoise_blend, 0.0..=1.0)
+                            .text("New frame weight"),
+                    );
+                    if (self.denoise_enabled, self.denoise_blend) != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetTemporalDenoise {
+                                enabled: self.denoise_enabled,
+                                blend_factor: self.denoise_blend,
+                            },
+                        );
+                    }
+                    if ui.button("Reset History").clicked() {
+                        send_event(&self.event_proxy, UserEvent::ResetDenoiseHistory);
+                    }
+                });
+
+                ui.collapsing("Color Grading", |ui| {
+                    let previous = self.post_process;
+                    ui.collapsing("Levels", |ui| {
+                        let levels = &mut self.post_process.levels;
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut levels.enabled, "Enabled");
+                            ui.checkbox(&mut levels.per_channel, "Per Channel");
+                            if ui
+                                .button("Auto Levels")
+                                .on_hover_text("Clip 0.1% of the pixels to black and to white")
+                                .clicked()
+                            {
+                                send_event(&self.event_proxy, UserEvent::AutoLevels);
+                            }
+                        });
+                        ui.add_enabled_ui(levels.enabled, |ui| {
+                            egui::Grid::new("levels_grid").show(ui, |ui| {
+                                for header in [
+                                    "",
+                                    "Input black",
+                                    "Input white",
+                                    "Gamma",
+                                    "Output black",
+                                    "Output white",
+                                ] {
+                                    ui.label(header);
+                                }
+                                ui.end_row();
+                                let rows: Vec<(&str, &mut LevelsRange)> = if levels.per_channel {
+                                    ["R", "G", "B"]
+                                        .into_iter()
+                                        .zip(&mut levels.channels)
+                                        .collect()
+                                } else {
+                                    vec![("RGB", &mut levels.master)]
+                                };
+                                for (label, range) in rows {
+                                    ui.label(label);
+                                    for value in [&mut range.input_black, &mut range.input_white] {
+                                        ui.add(egui::DragValue::new(value).speed(0.005));
+                                    }
+                                    ui.add(
+                                        egui::DragValue::new(&mut range.gamma)
+                                            .speed(0.01)
+                                            .clamp_range(0.1..=10.0),
+                                    );
+                                    for value in [&mut range.output_black, &mut range.output_white]
+                                    {
+                                        ui.add(egui::DragValue::new(value).speed(0.005));
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                            if ui.button("Reset").clicked() {
+                                levels.master = LevelsRange::IDENTITY;
+                                levels.channels = [LevelsRange::IDENTITY; 3];
+                            }
+                        });
+                        let output = if levels.enabled {
+                            self.levels_histogram.remapped(&levels.ranges())
+                        } else {
+                            self.levels_histogram.clone()
+                        };
+                        levels_histogram_plot(
+                            ui,
+                            "levels_input_plot",
+                            "Input",
+                            &self.levels_histogram,
+                        );
+                        levels_histogram_plot(ui, "levels_output_plot", "Output", &output);
+                    });
+                    ui.collapsing("CDL", |ui| {
+                        let cdl = &mut self.post_process.cdl;
+                        ui.checkbox(&mut cdl.enabled, "Enabled");
+                        ui.add_enabled_ui(cdl.enabled, |ui| {
+                            ui.add(egui::Slider::new(&mut cdl.gain, 0.0..=4.0).text("Gain"));
+                            ui.add(egui::Slider::new(&mut cdl.offset, -0.2..=0.2).text("Offset"));
+                            ui.add(egui::Slider::new(&mut cdl.lift, -0.3..=0.3).text("Lift"))
+                                .on_hover_text("Raises the floor of the shadows");
+                            ui.add(egui::Slider::new(&mut cdl.pivot, 0.0..=0.5).text("Pivot"))
+                                .on_hover_text(
+                                    "Luminance up to which the lift is applied, \
+                                     it fades out up to twice this",
+                                );
+                            if ui.button("Reset").clicked() {
+                                *cdl = CdlParams {
+                                    enabled: true,
+                                    ..CdlParams::default()
+                                };
+                            }
+                        });
+                    });
+                    ui.collapsing("Split Toning", |ui| {
+                        let tone = &mut self.post_process.split_tone;
+                        ui.checkbox(&mut tone.enabled, "Enabled");
+                        ui.add_enabled_ui(tone.enabled, |ui| {
+                            egui::Grid::new("split_tone_grid").show(ui, |ui| {
+                                ui.label("Shadows:");
+                                ui.add(
+                                    egui::Slider::new(&mut tone.shadow_hue, 0.0..=360.0)
+                                        .text("Hue")
+                                        .suffix("°"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut tone.shadow_saturation, 0.0..=1.0)
+                                        .text("Saturation"),
+                                );
+                                ui.end_row();
+
+                                ui.label("Highlights:");
+                                ui.add(
+                                    egui::Slider::new(&mut tone.highlight_hue, 0.0..=360.0)
+                                        .text("Hue")
+                                        .suffix("°"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut tone.highlight_saturation, 0.0..=1.0)
+                                        .text("Saturation"),
+                                );
+                                ui.end_row();
+
+                                ui.label("Balance:");
+                                ui.add(egui::Slider::new(&mut tone.balance, -1.0..=1.0))
+                                    .on_hover_text(
+                                        "Higher values tint more of the image as shadows",
+                                    );
+                                ui.end_row();
+                            });
+                            ui.horizontal(|ui| {
+                                for (label, preset) in [
+                                    ("Teal & Orange", SplitToneParams::TEAL_AND_ORANGE),
+                                    ("Gold & Blue", SplitToneParams::GOLD_AND_BLUE),
+                                    ("Lavender & Peach", SplitToneParams::LAVENDER_AND_PEACH),
+                                ] {
+                                    if ui.button(label).clicked() {
+                                        *tone = preset;
+                                    }
+                                }
+                            });
+                        });
+                    });
+                    if self.post_process != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetPostProcess(self.post_process),
+                        );
+                    }
+                });
+
+                ui.collapsing("Post-Process", |ui| {
+                    let previous_order = self.pass_order.clone();
+                    ui.horizontal(|ui| {
+                        ui.label("Pass Order");
+                        if ui.button("Reset").clicked() {
+                            self.pass_order = default_pass_order();
+                        }
+                    });
+                    ui.label(format!("Render Mode: {}", self.render_mode.label()))
+                        .on_hover_text("Always the first pass, the effects run on its image");
+                    // Only the effects turned on below are listed
+                    let post_process = self.post_process;
+                    if !PostProcessEffect::ALL
+                        .iter()
+                        .any(|&effect| post_process.is_enabled(effect))
+                    {
+                        ui.weak("No effect turned on");
+                    }
+                    let pointer = ui.input().pointer.interact_pos();
+                    let mut hovered_slot = None;
+                    for (index, slot) in self.pass_order.iter_mut().enumerate() {
+                        if !post_process.is_enabled(slot.effect) {
+                            continue;
+                        }
+                        let row = ui.horizontal(|ui| {
+                            let handle = ui
+                                .add(egui::Label::new("☰").sense(egui::Sense::drag()))
+                                .on_hover_text("Drag to reorder");
+                            if handle.drag_started() {
+                                self.dragged_pass = Some(index);
+                            }
+                            ui.checkbox(&mut slot.enabled, slot.effect.label());
+                        });
+                        if pointer.is_some_and(|pointer| row.response.rect.contains(pointer)) {
+                            hovered_slot = Some(index);
+                        }
+                    }
+                    if let Some(dragged) = self.dragged_pass {
+                        if let Some(target) = hovered_slot.filter(|target| *target != dragged) {
+                            let slot = self.pass_order.remove(dragged);
+                            self.pass_order.insert(target, slot);
+                            self.dragged_pass = Some(target);
+                        }
+                        if !ui.input().pointer.any_down() {
+                            self.dragged_pass = None;
+                        }
+                    }
+                    if self.pass_order != previous_order {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetPassOrder(self.pass_order.clone()),
+                        );
+                    }
+                    ui.separator();
+
+                    let previous = self.post_process;
+                    ui.label("Channel Remap");
+                    let remap = &mut self.post_process.channel_remap;
+                    egui::Grid::new("channel_remap_grid").show(ui, |ui| {
+                        ui.label("");
+                        for source in ChannelSource::ALL {
+                            ui.label(format!("{source:?}"));
+                        }
+                        ui.end_row();
+                        for (name, channel) in [
+                            ("R", &mut remap.r_src),
+                            ("G", &mut remap.g_src),
+                            ("B", &mut remap.b_src),
+                            ("A", &mut remap.a_src),
+                        ] {
+                            ui.label(name);
+                            for source in ChannelSource::ALL {
+                                ui.radio_value(channel, source, "");
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    ui.horizontal_wrapped(|ui| {
+                        use ChannelSource::{A, B, G, R};
+                        let presets = [
+                            ("Reset", ChannelRemap::IDENTITY),
+                            (
+                                "Swap R/G",
+                                ChannelRemap {
+                                    r_src: G,
+                                    g_src: R,
+                                    b_src: B,
+                                    a_src: A,
+                                },
+                            ),
+                            (
+                                "Copy R to all",
+                                ChannelRemap {
+                                    r_src: R,
+                                    g_src: R,
+                                    b_src: R,
+                                    a_src: A,
+                                },
+                            ),
+                            (
+                                "B→R, R→G, G→B",
+                                ChannelRemap {
+                                    r_src: B,
+                                    g_src: R,
+                                    b_src: G,
+                                    a_src: A,
+                                },
+                            ),
+                        ];
+                        for (label, preset) in presets {
+                            if ui.button(label).clicked() {
+                                *remap = preset;
+                            }
+                        }
+                    });
+                    ui.separator();
+
+                    ui.add(
+                        egui::Slider::new(&mut self.post_process.highlight_recovery, 0.0..=1.0)
+                            .text("Highlight Recovery"),
+                    )
+                    .on_hover_text(
+                        "Rebuild the clipped channels of the highlights from the ones left",
+                    );
+                    ui.separator();
+
+                    let post_process = &mut self.post_process;
+                    ui.checkbox(&mut post_process.invert_enabled, "Invert");
+                    ui.add_enabled_ui(post_process.invert_enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.radio_value(
+                                &mut post_process.invert_mode,
+                                InvertMode::Color,
+                                "Invert Color",
+                            );
+                            ui.radio_value(
+                                &mut post_process.invert_mode,
+                                InvertMode::Luminance,
+                                "Invert Luminance",
+                            )
+                            .on_hover_text("Keeps the hue and saturation");
+                        });
+                        ui.add_enabled(
+                            post_process.invert_mode == InvertMode::Color,
+                            egui::Slider::new(&mut post_process.invert_max_value, 0.1..=16.0)
+                                .logarithmic(true)
+                                .text("Max scene value"),
+                        )
+                        .on_hover_text("Brighter values are inverted to black");
+                    });
+                    ui.separator();
+
+                    let post_process = &mut self.post_process;
+                    ui.add(
+                        egui::Slider::new(&mut post_process.hue_rotation, -180.0..=180.0)
+                            .text("Hue")
+                            .suffix("°"),
+                    )
+                    .on_hover_text("Rotates every hue in Oklab, keeping lightness and chroma");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut post_process.hue_cycle_enabled, "Hue Shift per Frame");
+                        ui.add_enabled(
+                            post_process.hue_cycle_enabled,
+                            egui::DragValue::new(&mut post_process.hue_cycle_step)
+                                .speed(0.1)
+                                .clamp_range(-45.0..=45.0)
+                                .suffix("°"),
+                        );
+                    });
+                    ui.separator();
+
+                    let post_process = &mut self.post_process;
+                    ui.checkbox(&mut post_process.kaleidoscope_enabled, "Kaleidoscope");
+                    ui.add_enabled_ui(post_process.kaleidoscope_enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            for (mode, label) in [
+                                (KaleidoscopeMode::Center, "From Center"),
+                                (KaleidoscopeMode::Corner, "From Corner"),
+                            ] {
+                                ui.radio_value(&mut post_process.kaleidoscope_mode, mode, label);
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Segments:");
+                            for segments in KALEIDOSCOPE_SEGMENTS {
+                                ui.selectable_value(
+                                    &mut post_process.kaleidoscope_segments,
+                                    segments,
+                                    segments.to_string(),
+                                );
+                            }
+                        });
+                        ui.add(
+                            egui::Slider::new(&mut post_process.kaleidoscope_angle, 0.0..=360.0)
+                                .text("Angle")
+                                .suffix("°"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut post_process.kaleidoscope_zoom, 0.25..=4.0)
+                                .logarithmic(true)
+                                .text("Zoom"),
+                        );
+                    });
+                    ui.separator();
+
+                    ui.checkbox(&mut post_process.polar_enabled, "Polar Coordinates");
+                    ui.add_enabled_ui(post_process.polar_enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            for (mode, label) in [
+                                (PolarMode::RectToPolar, "Rectangular to Polar"),
+                                (PolarMode::PolarToRect, "Polar to Rectangular"),
+                            ] {
+                                ui.radio_value(&mut post_process.polar_mode, mode, label);
+                            }
+                        });
+                        ui.add(
+                            egui::Slider::new(&mut post_process.polar_angle_offset, 0.0..=360.0)
+                                .text("Angle")
+                                .suffix("°"),
+                        );
+                        ui.horizontal(|ui| {
+                            ui.label("Center:");
+                            for component in &mut post_process.polar_center {
+                                ui.add(
+                                    egui::DragValue::new(component)
+                                        .speed(0.01)
+                                        .clamp_range(0.0..=1.0),
+                                );
+                            }
+                        });
+                    });
+                    ui.separator();
+
+                    ui.checkbox(&mut post_process.fisheye_enabled, "Lens Distortion");
+                    ui.add_enabled_ui(post_process.fisheye_enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            for (mode, label) in [
+                                (FisheyeMode::Barrel, "Barrel"),
+                                (FisheyeMode::Pincushion, "Pincushion"),
+                                (FisheyeMode::Equisolid, "Equisolid"),
+                            ] {
+                                ui.radio_value(&mut post_process.fisheye_mode, mode, label);
+                            }
+                        });
+                        ui.add(
+                            egui::Slider::new(&mut post_process.fisheye_fov, 90.0..=220.0)
+                                .text("Field of view")
+                                .suffix("°"),
+                        )
+                        .on_hover_text("Across the image width, for the equisolid fisheye");
+                        ui.add(
+                            egui::Slider::new(&mut post_process.fisheye_strength, 0.0..=1.0)
+                                .text("Strength"),
+                        );
+                    });
+                    ui.separator();
+
+                    ui.checkbox(&mut post_process.chromatic_enabled, "Chromatic Aberration");
+                    ui.add_enabled_ui(post_process.chromatic_enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            for (mode, label) in [
+                                (ChromaticAberrationMode::Transverse, "Transverse"),
+                                (ChromaticAberrationMode::Longitudinal, "Longitudinal"),
+                            ] {
+                                ui.radio_value(&mut post_process.chromatic_mode, mode, label);
+                            }
+                        });
+                        ui.add(
+                            egui::Slider::new(&mut post_process.chromatic_max_shift, 0.0..=20.0)
+                                .text("Max shift")
+                                .suffix(" px"),
+                        )
+                        .on_hover_text("Blur radius of red for the longitudinal aberration");
+                        if post_process.chromatic_mode == ChromaticAberrationMode::Transverse {
+                            ui.checkbox(&mut post_process.chromatic_radial, "Radial")
+                                .on_hover_text("Grow the shift away from the center, like a lens");
+                            ui.add_enabled(
+                                !post_process.chromatic_radial,
+                                egui::Slider::new(&mut post_process.chromatic_angle, 0.0..=360.0)
+                                    .text("Direction")
+                                    .suffix("°"),
+                            );
+                        }
+                    });
+                    ui.separator();
+
+                    ui.label("Smart Sharpen");
+                    ui.add(
+                        egui::Slider::new(&mut self.post_process.sharpen_strength, 0.0..=2.0)
+                            .text("Amount 0 = off"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.post_process.sharpen_radius, 0.5..=5.0)
+                            .text("Radius")
+                            .suffix(" px"),
+                    );
+                    ui.checkbox(&mut self.post_process.sharpen_reduce_halos, "Reduce halos");
+                    ui.separator();
+
+                    ui.label("Histogram Equalization");
+                    egui::ComboBox::from_label("Mode")
+                        .selected_text(format!("{:?}", self.post_process.equalization_mode))
+                        .show_ui(ui, |ui| {
+                            for (mode, label) in [
+                                (EqualizationMode::Global, "Global"),
+                                (EqualizationMode::AdaptiveHE, "Adaptive (CLAHE)"),
+                            ] {
+                                ui.selectable_value(
+                                    &mut self.post_process.equalization_mode,
+                                    mode,
+                                    label,
+                                );
+                            }
+                        });
+                    ui.add(
+                        egui::Slider::new(&mut self.post_process.equalization_strength, 0.0..=1.0)
+                            .text("Strength 0 = off"),
+                    );
+                    ui.separator();
+
+                    ui.label("Depth of Field");
+                    ui.horizontal(|ui| {
+                        if ui.button("Load Depth").clicked() {
+                            picked_files.depth_buffer = rfd::FileDialog::new()
+                                .add_filter("OpenEXR", &["exr"])
+                                .pick_file();
+                        }
+                        ui.label(self.depth_name.as_deref().unwrap_or("No depth buffer"));
+                    });
+                    let post_process = &mut self.post_process;
+                    ui.add_enabled_ui(self.depth_name.is_some(), |ui| {
+                        ui.checkbox(&mut post_process.bokeh_enabled, "Bokeh blur");
+                        ui.add(
+                            egui::Slider::new(&mut post_process.bokeh_focus_depth, 0.01..=1000.0)
+                                .logarithmic(true)
+                                .text("Focus depth"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut post_process.bokeh_aperture, 0.0..=50.0)
+                                .text("Aperture"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut post_process.bokeh_max_radius, 1.0..=32.0)
+                                .text("Max radius")
+                                .suffix(" px"),
+                        );
+                        ui.horizontal(|ui| {
+                            for (shape, label) in [
+                                (BokehShape::Disk, "Disk"),
+                                (BokehShape::Hexagon, "Hexagon"),
+                                (BokehShape::CatEye, "Cat eye"),
+                            ] {
+                                ui.radio_value(&mut post_process.bokeh_shape, shape, label);
+                            }
+                        });
+                    });
+                    ui.checkbox(&mut post_process.tilt_shift_enabled, "Tilt-shift")
+                        .on_hover_text(
+                            "Depth of field from the height in the image, no depth buffer needed",
+                        );
+                    ui.add_enabled_ui(post_process.tilt_shift_enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut post_process.tilt_shift_focus, 0.0..=1.0)
+                                .text("Focus height"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut post_process.tilt_shift_aperture, 0.0..=24.0)
+                                .text("Aperture")
+                                .suffix(" px"),
+                        );
+                    });
+                    ui.separator();
+
+                    ui.checkbox(&mut post_process.noise_reduction_enabled, "Noise Reduction")
+                        .on_hover_text(
+                            "Bilateral filter: smooths flat areas, keeps the edges.\n\
+                             Slow, every pixel reads (6 x spatial sigma)^2 neighbours",
+                        );
+                    ui.add_enabled_ui(post_process.noise_reduction_enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(
+                                &mut post_process.noise_reduction_spatial_sigma,
+                                0.5..=10.0,
+                            )
+                            .text("Spatial sigma")
+                            .suffix(" px"),
+                        );
+                        ui.add(
+                            egui::Slider::new(
+                                &mut post_process.noise_reduction_range_sigma,
+                                0.01..=0.5,
+                            )
+                            .text("Range sigma"),
+                        )
+                        .on_hover_text("Oklab color difference still considered the same area");
+                    });
+                    ui.separator();
+
+                    ui.label("Grid Warp");
+                    ui.horizontal(|ui| {
+                        if ui.button("Load Warp Map").clicked() {
+                            picked_files.warp_map = rfd::FileDialog::new()
+                                .add_filter("OpenEXR", &["exr"])
+                                .pick_file();
+                        }
+                        if ui.button("Generate Test Warp").clicked() {
+                            self.warp_map_name = Some("Test warp".to_owned());
+                            send_event(
+                                &self.event_proxy,
+                                UserEvent::SetWarpMap {
+                                    displacement: generate_test_warp(
+                                        RENDER_BUFFER_WIDTH as usize,
+                                        RENDER_BUFFER_HEIGHT as usize,
+                                    ),
+                                    path: None,
+                                },
+                            );
+                        }
+                    });
+                    ui.label(self.warp_map_name.as_deref().unwrap_or("No warp map"));
+                    let post_process = &mut self.post_process;
+                    ui.add_enabled_ui(self.warp_map_name.is_some(), |ui| {
+                        ui.checkbox(&mut post_process.grid_warp_enabled, "Warp");
+                        ui.add(
+                            egui::Slider::new(&mut post_process.grid_warp_scale, 0.0..=64.0)
+                                .text("Scale")
+                                .suffix(" px"),
+                        );
+                    });
+                    ui.separator();
+
+                    let post_process = &mut self.post_process;
+                    ui.checkbox(&mut post_process.cel_shade_enabled, "Cel Shade");
+                    ui.add_enabled_ui(post_process.cel_shade_enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut post_process.cel_shade_tones, 2..=16)
+                                .text("Tones"),
+                        );
+                        ui.add(
+                            egui::Slider::new(
+                                &mut post_process.cel_shade_edge_threshold,
+                                0.0..=1.0,
+                            )
+                            .text("Edge threshold"),
+                        );
+                        ui.horizontal(|ui| {
+                            ui.label("Edges");
+                            ui.color_edit_button_rgba_unmultiplied(
+                                &mut post_process.cel_shade_edge_color,
+                            );
+                            let mut dual =
+                                post_process.cel_shade_mode == CelShadeMode::DualThreshold;
+                            if ui
+                                .checkbox(&mut dual, "Dual threshold")
+                                .on_hover_text("Draw the weaker edges in gray")
+                                .changed()
+                            {
+                                post_process.cel_shade_mode = if dual {
+                                    CelShadeMode::DualThreshold
+                                } else {
+                                    CelShadeMode::Single
+                                };
+                            }
+                        });
+                    });
+                    ui.separator();
+
+                    let post_process = &mut self.post_process;
+                    ui.checkbox(&mut post_process.stained_glass_enabled, "Stained Glass");
+                    ui.add_enabled_ui(post_process.stained_glass_enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut post_process.stained_glass_regions, 2..=2000)
+                                .logarithmic(true)
+                                .text("Regions"),
+                        );
+                        ui.add(
+                            egui::Slider::new(
+                                &mut post_process.stained_glass_border_thickness,
+                                0..=8,
+                            )
+                            .text("Lead width")
+                            .suffix(" px"),
+                        );
+                        ui.horizontal(|ui| {
+                            ui.label("Lead");
+                            ui.color_edit_button_rgba_unmultiplied(
+                                &mut post_process.stained_glass_border_color,
+                            );
+                            ui.label("Seed");
+                            ui.add(egui::DragValue::new(&mut post_process.stained_glass_seed));
+                        });
+                    });
+                    ui.separator();
+
+                    let post_process = &mut self.post_process;
+                    ui.checkbox(&mut post_process.crosshatch_enabled, "Crosshatch");
+                    ui.add_enabled_ui(post_process.crosshatch_enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut post_process.crosshatch_spacing, 2..=32)
+                                .text("Spacing")
+                                .suffix(" px"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut post_process.crosshatch_line_width, 0.5..=4.0)
+                                .text("Line width")
+                                .suffix(" px"),
+                        );
+                        for (angle, label) in post_process
+                            .crosshatch_angles
+                            .iter_mut()
+                            .zip(["Angle 1", "Angle 2"])
+                        {
+                            ui.add(
+                                egui::Slider::new(angle, -90.0..=90.0)
+                                    .text(label)
+                                    .suffix("°"),
+                            );
+                        }
+                        ui.add(
+                            egui::Slider::new(
+                                &mut post_process.crosshatch_dark_threshold,
+                                0.0..=1.0,
+                            )
+                            .text("Dark threshold"),
+                        )
+                        .on_hover_text("Luminance below which the second set of lines is drawn");
+                    });
+                    ui.separator();
+
+                    let post_process = &mut self.post_process;
+                    ui.checkbox(&mut post_process.watercolor_enabled, "Watercolor");
+                    ui.add_enabled_ui(post_process.watercolor_enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut post_process.watercolor_steps, 0..=64)
+                                .text("Diffusion steps"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut post_process.watercolor_wetness, 0.0..=4.0)
+                                .text("Wetness"),
+                        )
+                        .on_hover_text("Multiplies the diffusion steps, wetter paint spreads more");
+                        ui.add(
+                            egui::Slider::new(&mut post_process.watercolor_paper, 0.0..=1.0)
+                                .text("Paper texture"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut post_process.watercolor_granulation, 0.0..=1.0)
+                                .text("Granulation"),
+                        );
+                    });
+                    ui.separator();
+
+                    let post_process = &mut self.post_process;
+                    ui.checkbox(&mut post_process.thermal_enabled, "Thermal Camera");
+                    ui.add_enabled_ui(post_process.thermal_enabled, |ui| {
+                        let colormap = &mut post_process.thermal_colormap;
+                        let colormap_label = match colormap {
+                            ThermalColormap::Ironbow => "Ironbow",
+                            ThermalColormap::Rainbow => "Rainbow",
+                            ThermalColormap::Grayscale => "Grayscale",
+                            ThermalColormap::Custom { .. } => "Custom",
+                        };
+                        egui::ComboBox::from_label("Colormap")
+                            .selected_text(colormap_label)
+                            .show_ui(ui, |ui| {
+                                let choices = [
+                                    ThermalColormap::Ironbow,
+                                    ThermalColormap::Rainbow,
+                                    ThermalColormap::Grayscale,
+                                    ThermalColormap::Custom {
+                                        cold: [0.0, 0.0, 0.2],
+                                        warm: [0.8, 0.1, 0.1],
+                                        hot: [1.0, 1.0, 0.6],
+                                    },
+                                ];
+                                for (choice, label) in choices.into_iter().zip([
+                                    "Ironbow",
+                                    "Rainbow",
+                                    "Grayscale",
+                                    "Custom",
+                                ]) {
+                                    // Keep the custom colors when it's picked again
+                                    if ui
+                                        .selectable_label(colormap_label == label, label)
+                                        .clicked()
+                                        && colormap_label != label
+                                    {
+                                        *colormap = choice;
+                                    }
+                                }
+                            });
+                        match colormap {
+                            ThermalColormap::Grayscale => {
+                                ui.horizontal(|ui| {
+                                    ui.label("Cold");
+                                    ui.color_edit_button_rgb(&mut post_process.thermal_cold_color);
+                                    ui.label("Hot");
+                                    ui.color_edit_button_rgb(&mut post_process.thermal_hot_color);
+                                });
+                            }
+                            ThermalColormap::Custom { cold, warm, hot } => {
+                                ui.horizontal(|ui| {
+                                    ui.label("Cold");
+                                    ui.color_edit_button_rgb(cold);
+                                    ui.label("Warm");
+                                    ui.color_edit_button_rgb(warm);
+                                    ui.label("Hot");
+                                    ui.color_edit_button_rgb(hot);
+                                });
+                            }
+                            ThermalColormap::Ironbow | ThermalColormap::Rainbow => {}
+                        }
+                        ui.add(
+                            egui::Slider::new(&mut post_process.thermal_noise, 0.0..=0.2)
+                                .text("Sensor noise"),
+                        );
+                    });
+                    ui.separator();
+
+                    let night_vision = &mut self.post_process.night_vision;
+                    ui.checkbox(&mut night_vision.enabled, "Night Vision");
+                    ui.add_enabled_ui(night_vision.enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut night_vision.amplification, 0.0..=20.0)
+                                .logarithmic(true)
+                                .text("Amplification"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut night_vision.noise_level, 0.0..=0.5)
+                                .text("Noise"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut night_vision.vignette_strength, 0.0..=1.0)
+                                .text("Vignette"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut night_vision.phosphor_persistence, 0.0..=0.95)
+                                .text("Phosphor persistence"),
+                        );
+                        ui.horizontal(|ui| {
+                            ui.label("Phosphor");
+                            ui.color_edit_button_rgb(&mut night_vision.green_tint);
+                            ui.checkbox(&mut night_vision.scanlines, "Scanlines");
+                        });
+                    });
+                    ui.separator();
+
+                    ui.label("Contour Lines");
+                    ui.add(
+                        egui::Slider::new(&mut self.post_process.contour_step, 0.01..=0.25)
+                            .text("Luminance step"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.post_process.contour_width, 0.0..=5.0)
+                            .text("Width 0 = off")
+                            .suffix(" px"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Color");
+                        ui.color_edit_button_rgba_unmultiplied(
+                            &mut self.post_process.contour_color,
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.radio_value(
+                            &mut self.post_process.contour_mode,
+                            ContourMode::All,
+                            "All transitions",
+                        );
+                        ui.radio_value(
+                            &mut self.post_process.contour_mode,
+                            ContourMode::Rising,
+                            "Brightness increases",
+                        );
+                    });
+                    ui.separator();
+
+                    ui.label("Border");
+                    ui.add(
+                        egui::Slider::new(&mut self.post_process.border_width, 0..=50)
+                            .text("Width 0 = off")
+                            .suffix(" px"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Color");
+                        ui.color_edit_button_rgba_unmultiplied(&mut self.post_process.border_color);
+                    });
+                    let style = &mut self.post_process.border_style;
+                    let style_label = match style {
+                        BorderStyle::Solid => "Solid",
+                        BorderStyle::Dashed { .. } => "Dashed",
+                        BorderStyle::Gradient { .. } => "Gradient",
+                    };
+                    egui::ComboBox::from_label("Style")
+                        .selected_text(style_label)
+                        .show_ui(ui, |ui| {
+                            let choices = [
+                                BorderStyle::Solid,
+                                BorderStyle::Dashed {
+                                    dash_len: 8,
+                                    gap_len: 4,
+                                },
+                                BorderStyle::Gradient {
+                                    inner_color: [1.0, 1.0, 1.0, 1.0],
+                                    outer_color: [0.0, 0.0, 0.0, 1.0],
+                                },
+                            ];
+                            for (choice, label) in
+                                choices.into_iter().zip(["Solid", "Dashed", "Gradient"])
+                            {
+                                // Keep the parameters when the current style is picked again
+                                if ui.selectable_label(style_label == label, label).clicked()
+                                    && style_label != label
+                                {
+                                    *style = choice;
+                                }
+                            }
+                        });
+                    match style {
+                        BorderStyle::Solid => {}
+                        BorderStyle::Dashed { dash_len, gap_len } => {
+                            ui.add(
+                                egui::Slider::new(dash_len, 1..=50)
+                                    .text("Dash")
+                                    .suffix(" px"),
+                            );
+                            ui.add(egui::Slider::new(gap_len, 1..=50).text("Gap").suffix(" px"));
+                        }
+                        BorderStyle::Gradient {
+                            inner_color,
+                            outer_color,
+                        } => {
+                            ui.horizontal(|ui| {
+                                ui.label("Inner");
+                                ui.color_edit_button_rgba_unmultiplied(inner_color);
+                                ui.label("Outer");
+                                ui.color_edit_button_rgba_unmultiplied(outer_color);
+                            });
+                        }
+                    }
+
+                    if self.post_process != previous {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetPostProcess(self.post_process),
+                        );
+                    }
+                });
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Render").clicked() {
+                        send_event(&self.event_proxy, UserEvent::Render);
+                    }
+                    if ui
+                        .checkbox(&mut self.continuous_render, "Continuous Render")
+                        .on_hover_text("Redraw every frame, even when nothing changed")
+                        .changed()
+                    {
+                        send_event(
+                            &self.event_proxy,
+                            UserEvent::SetContinuousRender(self.continuous_render),
+                        );
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let selecting = self.region_selector.selecting;
+                    if ui
+                        .selectable_label(selecting, "Region")
+                        .on_hover_text("Drag a rectangle on the image to re-render")
+                        .clicked()
+                    {
+                        // Toggled once the window no longer borrows `self`
+                        region_clicked = true;
+                    }
+                    let region = self.region_selector.region;
+                    if ui
+                        .add_enabled(region.is_some(), egui::Button::new("Render Region"))
+                        .on_hover_text("Re-render inside the region, keeping the rest")
+                        .clicked()
+                    {
+                        send_event(&self.event_proxy, UserEvent::SetRenderRegion(region));
+                        send_event(&self.event_proxy, UserEvent::Render);
+                    }
+                    if ui.button("Render Full").clicked() {
+                        self.region_selector.region = None;
+                        send_event(&self.event_proxy, UserEvent::SetRenderRegion(None));
+                        send_event(&self.event_proxy, UserEvent::Render);
+                    }
+                });
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x /= 2.0;
+                    ui.label("Learn more about egui at");
+                    ui.hyperlink("https://docs.rs/egui");
+                });
+            });
+
+        self.remember_window_pos("Scene Options", scene_options);
+        if let Some(target) = dropper_clicked {
+            if self.color_dropper.target == Some(target) {
+                self.stop_color_dropper();
+            } else {
+                self.start_color_dropper(target);
+            }
+        }
+        if region_clicked {
+            if self.region_selector.selecting {
+                self.stop_region_selection();
+            } else {
+                self.start_region_selection();
+            }
+        }
+        self.load_scene_files(&picked_files);
+
+        let save_options_pos = self.window_pos(
+            ctx,
+            "Save Options",
+            egui::Pos2::new(
+                self.window_width as f32 * (1.0 / self.scale_factor) * 0.75,
+                self.window_height as f32 * (1.0 / self.scale_factor) * 0.10,
+            ),
+        );
+        let save_options = egui::Window::new("Save Options")
+            .open(&mut self.window_open)
+            .default_pos(save_options_pos)
+            .show(ctx, |ui| {
+                egui::ComboBox::from_label("Output format")
+                    .selected_text(self.file_format_chosen.label())
+                    .show_ui(ui, |ui| {
+                        for format in [
+                            FileFormat::OpenEXR,
+                            FileFormat::AsciiArt,
+                            FileFormat::ColoredAscii,
+                            FileFormat::Jpeg,
+                        ] {
+                            let label = format.label();
+                            ui.selectable_value(&mut self.file_format_chosen, format, label);
+                        }
+                    });
+                if self.file_format_chosen == FileFormat::OpenEXR {
+                    ui.checkbox(&mut self.single_channel, "Single Channel (R)")
+                        .on_hover_text("Only write red, for masks like the grunge map");
+                }
+                if self.file_format_chosen == FileFormat::Jpeg {
+                    ui.checkbox(&mut self.embed_metadata, "Embed Metadata")
+                        .on_hover_text("EXIF tags with the display settings and the scene file");
+                }
+                if matches!(
+                    self.file_format_chosen,
+                    FileFormat::AsciiArt | FileFormat::ColoredAscii
+                ) {
+                    ui.horizontal(|ui| {
+                        ui.label("Character block:");
+                        ui.add(
+                            egui::DragValue::new(&mut self.ascii_block_size)
+                                .clamp_range(1..=32)
+                                .suffix(" px"),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Characters, dark to light:");
+                        ui.text_edit_singleline(&mut self.ascii_charset);
+                    });
+                }
+
+                ui.separator();
+                let previous_watermark = (self.watermark_enabled, self.watermark.clone());
+                ui.checkbox(&mut self.watermark_enabled, "Watermark Text")
+                    .on_hover_text("Drawn over the EXR and JPEG files, one line per row");
+                ui.add_enabled_ui(self.watermark_enabled, |ui| {
+                    let watermark = &mut self.watermark;
+                    ui.text_edit_multiline(&mut watermark.text);
+                    ui.horizontal(|ui| {
+                        ui.label("Scale:");
+                        for scale in [1, 2, 4] {
+                            ui.selectable_value(&mut watermark.scale, scale, format!("{scale}x"));
+                        }
+                    });
+                    // Keep the text on the image
+                    let [text_width, text_height] = text_size(&watermark.text, watermark.scale);
+                    let max_x = (RENDER_BUFFER_WIDTH as usize).saturating_sub(text_width);
+                    let max_y = (RENDER_BUFFER_HEIGHT as usize).saturating_sub(text_height);
+                    ui.horizontal(|ui| {
+                        ui.label("Position:");
+                        ui.add(
+                            egui::DragValue::new(&mut watermark.position[0])
+                                .clamp_range(0..=max_x)
+                                .prefix("x: "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut watermark.position[1])
+                                .clamp_range(0..=max_y)
+                                .prefix("y: "),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Color:");
+                        ui.color_edit_button_rgba_unmultiplied(&mut watermark.color);
+                    });
+                });
+                if (self.watermark_enabled, self.watermark.clone()) != previous_watermark {
+                    send_event(
+                        &self.event_proxy,
+                        UserEvent::SetWatermark(
+                            self.watermark_enabled.then(|| self.watermark.clone()),
+                        ),
+                    );
+                }
+                if ui
+                    .button("Burn Timestamp")
+                    .on_hover_text("Draw the current date and time into the framebuffer")
+                    .clicked()
+                {
+                    let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+                    send_event(
+                        &self.event_proxy,
+                        UserEvent::BurnText(Watermark {
+                            text: timestamp.to_string(),
+                            ..self.watermark.clone()
+                        }),
+                    );
+                }
+
+                ui.separator();
+                ui.spacing_mut().item_spacing.x /= 2.0;
+
+                ui.label("File name (without extension):");
+                ui.text_edit_singleline(&mut self.file_path);
+
+                // Here goes the save logic
+                if ui.button("Save").clicked() {
+                    let image_path = images_dir().join(format!(
+                        "{}.{}",
+                        self.file_path,
+                        self.file_format_chosen.extension()
+                    ));
+                    let event = match self.file_format_chosen {
+                        FileFormat::OpenEXR => UserEvent::Save {
+                            path: image_path,
+                            single_channel: self.single_channel,
+                        },
+                        // Characters are about twice as tall as they are wide
+                        FileFormat::AsciiArt | FileFormat::ColoredAscii => UserEvent::SaveAscii {
+                            path: image_path,
+                            char_w: self.ascii_block_size,
+                            char_h: self.ascii_block_size * 2,
+                            charset: self.ascii_charset.clone(),
+                            colored: self.file_format_chosen == FileFormat::ColoredAscii,
+                        },
+                        FileFormat::Jpeg => UserEvent::SaveJpeg {
+                            path: image_path,
+                            embed_metadata: self.embed_metadata,
+                            scene_path: self.scene_path.clone(),
+                        },
+                    };
+                    send_event(&self.event_proxy, event);
                 }
             });
+        self.remember_window_pos("Save Options", save_options);
     }
 }
@@ -1,27 +1,619 @@
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::OnceLock;
 
-use anyhow;
+use ::image::codecs::jpeg::JpegEncoder;
+use ::image::ColorType;
 use colstodian::spaces::{AcesCg, EncodedSrgb};
+use colstodian::tonemap::{PerceptualTonemapper, PerceptualTonemapperParams, Tonemapper};
 use colstodian::{color, Color, Display, Oklab, Scene};
+use exr::meta::attribute::{AttributeValue, IntegerBounds, Text};
+use exr::meta::MetaData;
 use exr::prelude::{
-    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, WritableImage,
+    read_first_flat_layer_from_file, read_first_rgba_layer_from_file, AnyChannel, AnyChannels,
+    Encoding, FlatSamples, Image, Layer, LayerAttributes, Vec2, WritableImage,
 };
+use little_exif::exif_tag::{ExifTag, ExifTagGroup};
+use little_exif::metadata::Metadata as ExifMetadata;
+use little_exif::rational::uR64;
+use log::info;
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use smallvec::smallvec;
 
-use crate::constants::{RENDER_BUFFER_HEIGHT, RENDER_BUFFER_SIZE, RENDER_BUFFER_WIDTH};
+use crate::constants::{
+    BAYER_MATRIX_8X8, RENDER_BUFFER_HEIGHT, RENDER_BUFFER_SIZE, RENDER_BUFFER_WIDTH,
+};
+use crate::draw::Brush;
+use crate::error::ImageError;
+use crate::math::{fit_range_t, smootherstep, smoothstep};
+use crate::noise::{fbm_simplex, simplex_noise_2d, NoiseBasis};
+use crate::sampling::{generate_poisson_disk_samples, BlueNoiseSampler, SamplerKind, SplitMix64};
 
 /// Linear remap a value in one range into another range (no clamping)
 pub fn fit_range(x: f32, imin: f32, imax: f32, omin: f32, omax: f32) -> f32 {
-    (omax - omin) * (x - imin) / (imax - imin) + omin
+    fit_range_t(x, imin, imax, omin, omax)
+}
+
+// ACEScct constants, from the official ACES CTL (S-2016-001)
+const ACESCCT_X_BRK: f32 = 0.0078125;
+const ACESCCT_Y_BRK: f32 = 0.155_251_1;
+const ACESCCT_A: f32 = 10.540_24;
+const ACESCCT_B: f32 = 0.072_905_53;
+/// Largest value of a half float, the most the ACEScct decode returns
+const HALF_MAX: f32 = 65504.0;
+
+/// Encode a linear ACEScg value with the ACEScct log curve
+/// (linear toe below the break point, pure log above it)
+pub fn acescg_to_acescct(linear: f32) -> f32 {
+    if linear <= ACESCCT_X_BRK {
+        ACESCCT_A * linear + ACESCCT_B
+    } else {
+        (linear.log2() + 9.72) / 17.52
+    }
+}
+
+/// Decode an ACEScct value back to linear ACEScg
+pub fn acescct_to_acescg(log: f32) -> f32 {
+    if log <= ACESCCT_Y_BRK {
+        (log - ACESCCT_B) / ACESCCT_A
+    } else {
+        (log * 17.52 - 9.72).exp2().min(HALF_MAX)
+    }
+}
+
+/// How the values in the framebuffer are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageEncoding {
+    /// Scene linear ACEScg
+    Linear,
+    /// ACEScg encoded with the ACEScct log curve
+    AcesCct,
+}
+
+impl StorageEncoding {
+    /// Convert an encoded channel value to linear ACEScg
+    pub fn decode(&self, value: f32) -> f32 {
+        match self {
+            StorageEncoding::Linear => value,
+            StorageEncoding::AcesCct => acescct_to_acescg(value),
+        }
+    }
+
+    /// Convert a linear ACEScg channel value to this encoding
+    pub fn encode(&self, value: f32) -> f32 {
+        match self {
+            StorageEncoding::Linear => value,
+            StorageEncoding::AcesCct => acescg_to_acescct(value),
+        }
+    }
+}
+
+/// Re-encode the RGB channels of the render buffer, leaving alpha untouched
+pub fn convert_storage_encoding(
+    render_buffer: &mut [f32; RENDER_BUFFER_SIZE],
+    from: StorageEncoding,
+    to: StorageEncoding,
+) {
+    if from == to {
+        return;
+    }
+    for pixel in render_buffer.chunks_exact_mut(4) {
+        for channel in &mut pixel[..3] {
+            *channel = to.encode(from.decode(*channel));
+        }
+    }
+}
+
+/// Decode the RGB channels of `region` (x0, y0, x1, y1) of the buffer to linear.
+/// Returns the encoded values of the region, for `encode_changed_region`.
+pub fn decode_region(
+    buffer: &mut [f32],
+    width: usize,
+    region: [usize; 4],
+    encoding: StorageEncoding,
+) -> Vec<f32> {
+    let [x0, y0, x1, y1] = region;
+    let mut stored = Vec::with_capacity((x1 - x0) * (y1 - y0) * 4);
+    for y in y0..y1 {
+        let row = &mut buffer[(y * width + x0) * 4..(y * width + x1) * 4];
+        stored.extend_from_slice(row);
+        for pixel in row.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = encoding.decode(*channel);
+            }
+        }
+    }
+    stored
+}
+
+/// Encode `region` of the buffer again after `decode_region`. Channels left as they were
+/// decoded get back their `stored` value, so they don't drift through the round trip.
+pub fn encode_changed_region(
+    buffer: &mut [f32],
+    width: usize,
+    region: [usize; 4],
+    encoding: StorageEncoding,
+    stored: &[f32],
+) {
+    let [x0, y0, x1, y1] = region;
+    let rows = stored.chunks_exact((x1 - x0) * 4);
+    for (y, stored_row) in (y0..y1).zip(rows) {
+        let row = &mut buffer[(y * width + x0) * 4..(y * width + x1) * 4];
+        for (pixel, stored_pixel) in row.chunks_exact_mut(4).zip(stored_row.chunks_exact(4)) {
+            for (channel, &stored) in pixel[..3].iter_mut().zip(&stored_pixel[..3]) {
+                *channel = if *channel == encoding.decode(stored) {
+                    stored
+                } else {
+                    encoding.encode(*channel)
+                };
+            }
+        }
+    }
+}
+
+/// How the colors of the framebuffer relate to its alpha
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Colors independent of alpha, as written by the renderers
+    Straight,
+    /// Colors already multiplied by alpha, as composited and as stored in OpenEXR
+    Premultiplied,
+}
+
+impl AlphaMode {
+    pub const ALL: [AlphaMode; 2] = [AlphaMode::Straight, AlphaMode::Premultiplied];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AlphaMode::Straight => "Straight",
+            AlphaMode::Premultiplied => "Premultiplied",
+        }
+    }
+}
+
+/// Multiply the RGB channels of every RGBA pixel by its alpha
+pub fn premultiply_alpha(buffer: &mut [f32]) {
+    for pixel in buffer.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        for channel in &mut pixel[..3] {
+            *channel *= alpha;
+        }
+    }
+}
+
+/// Divide the RGB channels of every RGBA pixel by its alpha. Fully transparent pixels
+/// have no color left to recover, they stay black.
+pub fn unpremultiply_alpha(buffer: &mut [f32]) {
+    for pixel in buffer.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        for channel in &mut pixel[..3] {
+            *channel = if alpha != 0.0 { *channel / alpha } else { 0.0 };
+        }
+    }
+}
+
+/// A point of a multi-point gradient: (UV position, ACEScg color)
+pub type GradientAnchor = ([f32; 2], [f32; 3]);
+/// Maximum number of anchors of the multi-point gradient
+pub const MAX_GRADIENT_ANCHORS: usize = 8;
+
+/// Red, green and blue anchors in the same corners as `render_bg_image`
+pub fn default_gradient_anchors() -> Vec<GradientAnchor> {
+    vec![
+        ([0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([1.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0], [0.0, 0.0, 1.0]),
+    ]
+}
+
+/// Fill the buffer with a smooth gradient passing through every anchor, using
+/// inverse distance weighting (power 2) of the anchor colors.
+/// UVs go from the bottom left (0, 0) to the top right (1, 1).
+/// Only the pixels in `region` are written, see `render_bg_image`.
+pub fn render_multipoint_gradient(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    anchors: &[GradientAnchor],
+) {
+    for (x, y, pixel) in region_pixels_mut(buffer, width, region) {
+        let u = (x as f32 + 0.5) / width as f32;
+        let v = 1.0 - (y as f32 + 0.5) / height as f32;
+
+        let mut color = [0.0; 3];
+        let mut total_weight = 0.0;
+        for (position, anchor_color) in anchors {
+            let du = u - position[0];
+            let dv = v - position[1];
+            let distance_sq = du * du + dv * dv;
+            // Right on top of an anchor: use its color exactly
+            if distance_sq < 1e-12 {
+                color = *anchor_color;
+                total_weight = 1.0;
+                break;
+            }
+            let weight = 1.0 / distance_sq;
+            for (c, a) in color.iter_mut().zip(anchor_color) {
+                *c += a * weight;
+            }
+            total_weight += weight;
+        }
+
+        if total_weight > 0.0 {
+            color = color.map(|c| c / total_weight);
+        }
+
+        pixel[0] = color[0];
+        pixel[1] = color[1];
+        pixel[2] = color[2];
+        pixel[3] = 1.0;
+    }
+}
+
+/// Decode an sRGB encoded value in [0, 1] to linear
+pub fn srgb_to_linear(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Luminance weights of the ACEScg (AP1) primaries
+pub const ACESCG_LUMINANCE: [f32; 3] = [0.272_228_7, 0.674_081_8, 0.053_689_52];
+
+/// Relative luminance of a linear ACEScg color
+pub fn acescg_luminance(rgb: [f32; 3]) -> f32 {
+    rgb[0] * ACESCG_LUMINANCE[0] + rgb[1] * ACESCG_LUMINANCE[1] + rgb[2] * ACESCG_LUMINANCE[2]
+}
+
+/// Multiply a 3x3 row-major matrix by a color
+pub fn mul_mat3(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+/// Displays that can be simulated by the soft proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftProofTarget {
+    Off,
+    Srgb,
+    DisplayP3,
+    Rec2020,
+    DciP3,
+}
+
+impl SoftProofTarget {
+    pub fn profile(&self) -> Option<SoftProofProfile> {
+        // Matrices include a Bradford adaptation from the ACES white point
+        // to the white point of the target display
+        match self {
+            SoftProofTarget::Off => None,
+            SoftProofTarget::Srgb => Some(SoftProofProfile {
+                to_target: ACESCG_TO_LINEAR_SRGB,
+                from_target: LINEAR_SRGB_TO_ACESCG,
+            }),
+            SoftProofTarget::DisplayP3 => Some(SoftProofProfile {
+                to_target: [
+                    [1.379_214, -0.308_864, -0.070_350],
+                    [-0.069_335, 1.082_297, -0.012_962],
+                    [-0.002_159, -0.045_459, 1.047_618],
+                ],
+                from_target: [
+                    [0.735_798, 0.212_166, 0.052_036],
+                    [0.047_180, 0.938_046, 0.014_774],
+                    [0.003_564, 0.041_142, 0.955_294],
+                ],
+            }),
+            SoftProofTarget::Rec2020 => Some(SoftProofProfile {
+                to_target: [
+                    [1.025_825, -0.020_053, -0.005_772],
+                    [-0.002_234, 1.004_587, -0.002_352],
+                    [-0.005_013, -0.025_290, 1.030_303],
+                ],
+                from_target: [
+                    [0.974_895, 0.019_599, 0.005_506],
+                    [0.002_180, 0.995_535, 0.002_285],
+                    [0.004_797, 0.024_532, 0.970_671],
+                ],
+            }),
+            SoftProofTarget::DciP3 => Some(SoftProofProfile {
+                to_target: [
+                    [1.464_120, -0.393_327, -0.070_793],
+                    [-0.066_477, 1.075_292, -0.008_815],
+                    [-0.002_553, -0.047_030, 1.049_582],
+                ],
+                from_target: [
+                    [0.694_725, 0.256_264, 0.049_011],
+                    [0.042_979, 0.946_176, 0.010_845],
+                    [0.003_616, 0.043_019, 0.953_365],
+                ],
+            }),
+        }
+    }
+}
+
+/// Conversion between ACEScg and the linear RGB of a target display
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftProofProfile {
+    pub to_target: [[f32; 3]; 3],
+    pub from_target: [[f32; 3]; 3],
+}
+
+impl SoftProofProfile {
+    /// Restrict a linear ACEScg color to the target gamut, returning the mapped
+    /// ACEScg color and whether it was out of gamut.
+    /// Out of gamut colors have their chroma scaled toward the neutral axis of
+    /// the same luminance until every channel is non negative (perceptual intent).
+    pub fn gamut_map(&self, rgb: [f32; 3]) -> ([f32; 3], bool) {
+        let target = mul_mat3(&self.to_target, rgb);
+        let min_channel = target[0].min(target[1]).min(target[2]);
+        if min_channel >= 0.0 {
+            return (rgb, false);
+        }
+
+        // White maps to white, so the neutral of the same luminance is (Y, Y, Y)
+        let gray = acescg_luminance(rgb);
+        if gray <= 0.0 {
+            return ([0.0; 3], true);
+        }
+        let t = gray / (gray - min_channel);
+        let mapped = target.map(|c| gray + t * (c - gray));
+
+        (mul_mat3(&self.from_target, mapped), true)
+    }
+}
+
+/// What `draw` shows of the display buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    /// The tonemapped image
+    Normal,
+    /// How the image would come out of a press, see `simulate_cmyk_preview`
+    CmykSoft,
+}
+
+/// Press and paper simulated by the CMYK preview
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrintSettings {
+    /// Most ink the press lays down, as the sum of C, M, Y and K: 3.0 is 300%
+    pub ink_limit: f32,
+    /// Color of the bare paper, sRGB encoded
+    pub paper_white: [f32; 3],
+}
+
+impl Default for PrintSettings {
+    fn default() -> Self {
+        Self {
+            ink_limit: 3.0,
+            // Uncoated stock, a little warmer and darker than the display white
+            paper_white: [0.96, 0.95, 0.91],
+        }
+    }
+}
+
+/// Approximate CMYK separation of a linear ACEScg buffer, one (C, M, Y, K) in [0, 1]
+/// per pixel, for a quick print check without an ICC profile.
+/// The colors are clipped to sRGB and taken relative to `paper_white`, so the image white
+/// is left unprinted. C, M and Y are the inverted sRGB, K is their minimum and is taken
+/// out of them, then C, M and Y are scaled down until the total ink fits `ink_limit`.
+pub fn simulate_cmyk_preview(
+    buffer: &[f32],
+    width: usize,
+    height: usize,
+    paper_white: [f32; 3],
+    ink_limit: f32,
+) -> Vec<[f32; 4]> {
+    buffer[..width * height * 4]
+        .chunks_exact(4)
+        .map(|pixel| {
+            let linear = mul_mat3(&ACESCG_TO_LINEAR_SRGB, [pixel[0], pixel[1], pixel[2]]);
+            let mut cmy = [0.0; 3];
+            for ((ink, value), paper) in cmy.iter_mut().zip(linear).zip(paper_white) {
+                let encoded = linear_to_srgb(value.clamp(0.0, 1.0));
+                *ink = 1.0 - (encoded / paper.max(1e-3)).min(1.0);
+            }
+            let k = cmy[0].min(cmy[1]).min(cmy[2]);
+            let cmy = cmy.map(|ink| ink - k);
+            let color_ink = cmy[0] + cmy[1] + cmy[2];
+            // Black carries the detail, the colored inks give way first
+            let scale = if color_ink + k > ink_limit && color_ink > 0.0 {
+                ((ink_limit - k) / color_ink).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+            [cmy[0] * scale, cmy[1] * scale, cmy[2] * scale, k]
+        })
+        .collect()
+}
+
+/// sRGB encoded color of CMYK inks printed on `paper_white`
+pub fn cmyk_preview_rgb(cmyk: [f32; 4], paper_white: [f32; 3]) -> [f32; 3] {
+    let [c, m, y, k] = cmyk;
+    [
+        paper_white[0] * (1.0 - c) * (1.0 - k),
+        paper_white[1] * (1.0 - m) * (1.0 - k),
+        paper_white[2] * (1.0 - y) * (1.0 - k),
+    ]
+}
+
+/// Encoding of the 8 bit frame written by `draw`. Unlike the soft proof, this changes
+/// the bytes sent to the display, which should be set to the same color space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputColorSpace {
+    Srgb,
+    /// P3 primaries with the sRGB transfer function
+    DisplayP3,
+    /// sRGB primaries with the BT.709 camera transfer function
+    Rec709,
+    Rec2020,
+    AdobeRgb1998,
+}
+
+impl OutputColorSpace {
+    pub const ALL: [OutputColorSpace; 5] = [
+        OutputColorSpace::Srgb,
+        OutputColorSpace::DisplayP3,
+        OutputColorSpace::Rec709,
+        OutputColorSpace::Rec2020,
+        OutputColorSpace::AdobeRgb1998,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutputColorSpace::Srgb => "sRGB",
+            OutputColorSpace::DisplayP3 => "Display P3",
+            OutputColorSpace::Rec709 => "Rec. 709",
+            OutputColorSpace::Rec2020 => "Rec. 2020",
+            OutputColorSpace::AdobeRgb1998 => "Adobe RGB (1998)",
+        }
+    }
+
+    /// Linear ACEScg to the linear RGB of the output, with a Bradford adaptation
+    /// from the ACES white point to D65
+    pub fn from_acescg(&self) -> [[f32; 3]; 3] {
+        match self {
+            OutputColorSpace::Srgb | OutputColorSpace::Rec709 => [
+                [1.705_051, -0.621_792, -0.083_259],
+                [-0.130_256, 1.140_805, -0.010_548],
+                [-0.024_003, -0.128_969, 1.152_972],
+            ],
+            OutputColorSpace::DisplayP3 => [
+                [1.379_214, -0.308_864, -0.070_350],
+                [-0.069_335, 1.082_297, -0.012_962],
+                [-0.002_159, -0.045_459, 1.047_618],
+            ],
+            OutputColorSpace::Rec2020 => [
+                [1.025_825, -0.020_053, -0.005_772],
+                [-0.002_234, 1.004_587, -0.002_352],
+                [-0.005_013, -0.025_290, 1.030_303],
+            ],
+            OutputColorSpace::AdobeRgb1998 => [
+                [1.182_219, -0.119_673, -0.062_545],
+                [-0.130_256, 1.140_805, -0.010_548],
+                [-0.028_377, -0.076_703, 1.105_080],
+            ],
+        }
+    }
+
+    /// Transfer function of the output, from linear to encoded values in [0, 1]
+    pub fn oetf(&self, linear: f32) -> f32 {
+        let linear = linear.clamp(0.0, 1.0);
+        match self {
+            OutputColorSpace::Srgb | OutputColorSpace::DisplayP3 => {
+                if linear <= 0.003_130_8 {
+                    linear * 12.92
+                } else {
+                    1.055 * linear.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            // BT.2020 uses the BT.709 curve, with more precise constants for 12 bits
+            OutputColorSpace::Rec709 | OutputColorSpace::Rec2020 => {
+                if linear < 0.018 {
+                    linear * 4.5
+                } else {
+                    1.099 * linear.powf(0.45) - 0.099
+                }
+            }
+            OutputColorSpace::AdobeRgb1998 => linear.powf(256.0 / 563.0),
+        }
+    }
+
+    /// Encode a display referred linear ACEScg color to 8 bits per channel, adding
+    /// `dither` code values before rounding
+    pub fn encode_u8(&self, rgb: [f32; 3], dither: f32) -> [u8; 3] {
+        mul_mat3(&self.from_acescg(), rgb).map(|c| quantize_u8(self.oetf(c), dither))
+    }
 }
 
-pub fn render_bg_image(render_buffer: &mut [f32; RENDER_BUFFER_SIZE]) {
-    let mut index: usize = 0;
-    for y in (0..RENDER_BUFFER_HEIGHT).rev() {
-        for x in 0..RENDER_BUFFER_WIDTH {
+/// Round an encoded value in [0, 1] to 8 bits, after adding `dither` code values
+pub fn quantize_u8(encoded: f32, dither: f32) -> u8 {
+    (encoded * 255.0 + dither).round().clamp(0.0, 255.0) as u8
+}
+
+/// Threshold pattern added to the 8 bit frame before rounding, to break up the banding
+/// of smooth gradients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DitherMode {
+    None,
+    /// 8x8 Bayer matrix
+    Ordered,
+    /// Tiled 64x64 blue noise texture
+    BlueNoise,
+}
+
+impl DitherMode {
+    pub const ALL: [DitherMode; 3] = [DitherMode::None, DitherMode::Ordered, DitherMode::BlueNoise];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DitherMode::None => "No Dither",
+            DitherMode::Ordered => "Ordered Dither",
+            DitherMode::BlueNoise => "Blue Noise Dither",
+        }
+    }
+
+    /// Code values to add to the pixel at `x`, `y`, in [-scale / 2, scale / 2).
+    /// The ordered dither is done on the whole frame by `apply_bayer_dither` instead.
+    pub fn offset(&self, x: usize, y: usize, scale: f32) -> f32 {
+        let threshold = match self {
+            DitherMode::None | DitherMode::Ordered => return 0.0,
+            DitherMode::BlueNoise => BlueNoiseSampler.sample(x as u32, y as u32)[0],
+        };
+        (threshold - 0.5) * scale
+    }
+}
+
+/// Ordered dither of the 8 bit RGBA `frame`, right after tonemapping.
+/// `(BAYER_MATRIX_8X8[y % 8][x % 8] / 64 - 0.5) * scale` code values are added to the color
+/// channels before rounding them again, alpha is left alone.
+pub fn apply_bayer_dither(frame: &mut [u8], width: usize, height: usize, scale: f32) {
+    for (i, pixel) in frame.chunks_exact_mut(4).take(width * height).enumerate() {
+        let (x, y) = (i % width, i / width);
+        let threshold = (BAYER_MATRIX_8X8[y % 8][x % 8] as f32 / 64.0 - 0.5) * scale;
+        for value in &mut pixel[..3] {
+            *value = (*value as f32 + threshold).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Render the gradient background, jittering each pixel's position with `sampler`
+/// to dither away banding. Only the pixels in `region`, `[x0, y0, x1, y1]` from the
+/// top left with the end exclusive, are written.
+pub fn render_bg_image(
+    render_buffer: &mut [f32; RENDER_BUFFER_SIZE],
+    sampler: SamplerKind,
+    region: [usize; 4],
+) {
+    let [x0, y0, x1, y1] = region.map(|v| v as u32);
+    for row in y0..y1 {
+        // The gradient starts from the bottom row
+        let y = RENDER_BUFFER_HEIGHT - 1 - row;
+        for x in x0..x1 {
+            let index = ((row * RENDER_BUFFER_WIDTH + x) * 4) as usize;
+            let jitter = sampler.sample_pixel(x, y, RENDER_BUFFER_WIDTH);
+
             // Get normalized U,V coordinates as we move through the image
-            let u = fit_range(x as f32, 0.0, RENDER_BUFFER_WIDTH as f32, 0.0, 1.0);
-            let v = fit_range(y as f32, 0.0, RENDER_BUFFER_HEIGHT as f32, 0.0, 1.0);
+            let u = fit_range(
+                x as f32 + jitter[0],
+                0.0,
+                RENDER_BUFFER_WIDTH as f32,
+                0.0,
+                1.0,
+            );
+            let v = fit_range(
+                y as f32 + jitter[1],
+                0.0,
+                RENDER_BUFFER_HEIGHT as f32,
+                0.0,
+                1.0,
+            );
 
             // Generate a gradient between two colors in AcesCG
             // TODO: Could we do this in LAB, and then convert to ACES CG ?
@@ -48,43 +640,206 @@ pub fn render_bg_image(render_buffer: &mut [f32; RENDER_BUFFER_SIZE]) {
             render_buffer[index + 1] = rendered_color.g;
             render_buffer[index + 2] = rendered_color.b;
             render_buffer[index + 3] = 1.0;
+        }
+    }
+}
+
+/// Header attributes the OpenEXR spec gives a meaning to. They can't be set as custom metadata
+pub const STANDARD_EXR_ATTRIBUTES: &[&str] = &[
+    "channels",
+    "compression",
+    "dataWindow",
+    "displayWindow",
+    "lineOrder",
+    "pixelAspectRatio",
+    "screenWindowCenter",
+    "screenWindowWidth",
+    "timeCode",
+    "chromaticities",
+    "owner",
+    "comments",
+    "capDate",
+    "software",
+    "name",
+];
+
+/// Custom string attributes written in the header of saved EXR files
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataMap(pub HashMap<String, String>);
+
+impl MetadataMap {
+    /// Build the map from editable key / value pairs, skipping the ones that can't be written.
+    /// Also returns a warning for each entry that was skipped or overwritten.
+    pub fn from_entries(entries: &[(String, String)]) -> (Self, Vec<String>) {
+        let mut map = HashMap::new();
+        let mut warnings = Vec::new();
+        for (index, (key, value)) in entries.iter().enumerate() {
+            let key = key.trim();
+            if key.is_empty() {
+                warnings.push(format!("Row {} has an empty key", index + 1));
+                continue;
+            }
+            if STANDARD_EXR_ATTRIBUTES.contains(&key) {
+                warnings.push(format!("\"{key}\" is a standard attribute"));
+                continue;
+            }
+            // The header stores Latin-1 text of limited length
+            if Text::new_or_none(key).is_none() || Text::new_or_none(value).is_none() {
+                warnings.push(format!("\"{key}\" can't be stored in an EXR header"));
+                continue;
+            }
+            if map.insert(key.to_owned(), value.clone()).is_some() {
+                warnings.push(format!("Duplicate key \"{key}\", the last one is used"));
+            }
+        }
+        (Self(map), warnings)
+    }
+}
+
+/// Attributes found in the header of an existing EXR file
+#[derive(Debug, Clone, Default)]
+pub struct ExrMetadata {
+    /// Attributes outside of the spec, non text values are converted to text
+    pub custom: Vec<(String, String)>,
+    /// Well-known attributes, for display only
+    pub standard: Vec<(String, String)>,
+}
+
+/// Read the header of the first layer of an EXR file, skipping the pixels
+pub fn read_exr_metadata(path: impl AsRef<Path>) -> Result<ExrMetadata, ImageError> {
+    let meta = MetaData::read_from_file(path, false)?;
+    let Some(header) = meta.headers.first() else {
+        return Err(ImageError::Exr {
+            message: "The file has no layers".to_owned(),
+        });
+    };
 
-            index += 4;
+    let format_bounds = |bounds: IntegerBounds| {
+        format!(
+            "{}x{} at ({}, {})",
+            bounds.size.width(),
+            bounds.size.height(),
+            bounds.position.x(),
+            bounds.position.y()
+        )
+    };
+    let mut standard = vec![
+        (
+            "displayWindow".to_owned(),
+            format_bounds(header.shared_attributes.display_window),
+        ),
+        ("dataWindow".to_owned(), format_bounds(header.data_window())),
+    ];
+    if let Some(time_code) = &header.shared_attributes.time_code {
+        standard.push((
+            "timeCode".to_owned(),
+            format!(
+                "{:02}:{:02}:{:02}:{:02}",
+                time_code.hours, time_code.minutes, time_code.seconds, time_code.frame
+            ),
+        ));
+    }
+    let layer = &header.own_attributes;
+    for (name, text) in [
+        ("owner", &layer.owner),
+        ("comments", &layer.comments),
+        ("software", &layer.software_name),
+    ] {
+        if let Some(text) = text {
+            standard.push((name.to_owned(), text.to_string()));
         }
     }
+
+    let mut custom: Vec<(String, String)> = header
+        .shared_attributes
+        .other
+        .iter()
+        .chain(&layer.other)
+        .map(|(name, value)| {
+            let value = match value {
+                AttributeValue::Text(text) => text.to_string(),
+                other => format!("{other:?}"),
+            };
+            (name.to_string(), value)
+        })
+        .collect();
+    // Hash map order isn't stable across reads
+    custom.sort();
+
+    Ok(ExrMetadata { custom, standard })
 }
 
+/// Save an RGBA buffer as an OpenEXR image, or only its red channel with `single_channel`.
+/// OpenEXR colors are premultiplied by alpha, `alpha_mode` tells whether the buffer ones
+/// still need to be.
+#[allow(clippy::too_many_arguments)]
 pub fn write_as_exr_image(
     image_path: impl AsRef<Path>,
     width: usize,
     height: usize,
-    render_buffer: &Box<[f32; RENDER_BUFFER_SIZE]>,
-) -> anyhow::Result<()> {
+    render_buffer: &[f32],
+    metadata: &MetadataMap,
+    pixel_aspect: f32,
+    single_channel: bool,
+    alpha_mode: AlphaMode,
+) -> Result<(), ImageError> {
+    if width == 0 || height == 0 {
+        return Err(ImageError::InvalidDimensions { width, height });
+    }
+    if render_buffer.len() != width * height * 4 {
+        return Err(ImageError::BufferSizeMismatch {
+            expected: width * height * 4,
+            actual: render_buffer.len(),
+        });
+    }
     let resolution = (width, height);
 
     // A vec for each channel
     let mut r_vec: Vec<f32> = Vec::new();
     let mut g_vec: Vec<f32> = Vec::new();
     let mut b_vec: Vec<f32> = Vec::new();
+    let mut a_vec: Vec<f32> = Vec::new();
 
     for f32_color in render_buffer.chunks_exact(4) {
-        r_vec.push(f32_color[0]);
-        g_vec.push(f32_color[1]);
-        b_vec.push(f32_color[2]);
+        let alpha = f32_color[3];
+        let scale = match alpha_mode {
+            AlphaMode::Straight => alpha,
+            AlphaMode::Premultiplied => 1.0,
+        };
+        r_vec.push(f32_color[0] * scale);
+        g_vec.push(f32_color[1] * scale);
+        b_vec.push(f32_color[2] * scale);
+        a_vec.push(alpha);
     }
 
     // Save the data into the channels
     let r_channel = AnyChannel::new("R", FlatSamples::F32(r_vec));
     let g_channel = AnyChannel::new("G", FlatSamples::F32(g_vec));
     let b_channel = AnyChannel::new("B", FlatSamples::F32(b_vec));
+    let a_channel = AnyChannel::new("A", FlatSamples::F32(a_vec));
 
-    let channels = AnyChannels::sort(smallvec![r_channel, g_channel, b_channel]);
+    // Masks only need one channel
+    let channels = if single_channel {
+        AnyChannels::sort(smallvec![r_channel])
+    } else {
+        AnyChannels::sort(smallvec![r_channel, g_channel, b_channel, a_channel])
+    };
 
     // The layer attributes can store additional metadata
     let mut layer_attributes = LayerAttributes::named("rgb");
     layer_attributes.comments = Some("Generated by vvzen from Rust".into());
     layer_attributes.owner = Some("vvzen".into());
     layer_attributes.software_name = Some("rust-tracer".into());
+    for (key, value) in &metadata.0 {
+        // Entries are validated by `MetadataMap::from_entries`, don't write a broken header
+        if let (Some(name), Some(text)) = (Text::new_or_none(key), Text::new_or_none(value)) {
+            if !STANDARD_EXR_ATTRIBUTES.contains(&key.as_str()) {
+                layer_attributes
+                    .other
+                    .insert(name, AttributeValue::Text(text));
+            }
+        }
+    }
 
     // The only layer in this image
     let layer = Layer::new(
@@ -95,18 +850,7622 @@ pub fn write_as_exr_image(
     );
 
     // Write the image to disk
-    let image = Image::from_layer(layer);
-    match image.write().to_file(&image_path) {
-        Ok(_) => {
-            eprintln!(
-                "Successfully saved image to {}",
-                image_path.as_ref().display()
-            );
+    let mut image = Image::from_layer(layer);
+    // Written as `pixelAspectRatio`, so viewers stretch anamorphic images back
+    image.attributes.pixel_aspect = pixel_aspect;
+    image.write().to_file(&image_path)?;
+    info!(
+        "Successfully saved image to {}",
+        image_path.as_ref().display()
+    );
+
+    Ok(())
+}
+
+/// Written in the `Software` EXIF tag of the exported JPEGs
+pub const EXIF_SOFTWARE: &str = "pixels-egui-framebuffer";
+
+// Nominal resolution of the exported JPEGs, EXIF requires one
+const JPEG_DPI: u32 = 72;
+
+const JPEG_QUALITY: u8 = 90;
+
+/// What the EXIF tags of an exported JPEG say about how it was made
+#[derive(Debug, Clone, PartialEq)]
+pub struct JpegMetadata {
+    pub tonemapper: String,
+    pub exposure_ev: f32,
+    // File name of the scene description, when the session was saved or loaded from one
+    pub document_name: Option<String>,
+    // The scene description itself, as JSON
+    pub description: Option<String>,
+}
+
+/// Write a linear ACEScg buffer as an sRGB JPEG, exposed by `exposure_ev` and tonemapped
+/// like the display. With `metadata`, the standard EXIF tags are embedded after encoding.
+pub fn write_as_jpeg_image(
+    image_path: impl AsRef<Path>,
+    width: usize,
+    height: usize,
+    render_buffer: &[f32],
+    exposure_ev: f32,
+    metadata: Option<&JpegMetadata>,
+) -> Result<(), ImageError> {
+    if width == 0 || height == 0 {
+        return Err(ImageError::InvalidDimensions { width, height });
+    }
+    if render_buffer.len() != width * height * 4 {
+        return Err(ImageError::BufferSizeMismatch {
+            expected: width * height * 4,
+            actual: render_buffer.len(),
+        });
+    }
+
+    let exposure = exposure_ev.exp2();
+    let params = PerceptualTonemapperParams::default();
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for pixel in render_buffer.chunks_exact(4) {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|c| c * exposure);
+        let tonemapped: Color<AcesCg, Display> =
+            PerceptualTonemapper::tonemap(color::acescg::<Scene>(r, g, b), params).convert();
+        rgb.extend(tonemapped.convert::<EncodedSrgb>().to_u8());
+    }
+
+    let file = std::io::BufWriter::new(std::fs::File::create(image_path.as_ref())?);
+    JpegEncoder::new_with_quality(file, JPEG_QUALITY).encode(
+        &rgb,
+        width as u32,
+        height as u32,
+        ColorType::Rgb8,
+    )?;
+
+    if let Some(metadata) = metadata {
+        write_jpeg_exif(image_path.as_ref(), width, height, metadata)?;
+    }
+    Ok(())
+}
+
+/// Embed the EXIF tags describing an exported image into the JPEG at `image_path`
+fn write_jpeg_exif(
+    image_path: &Path,
+    width: usize,
+    height: usize,
+    metadata: &JpegMetadata,
+) -> Result<(), ImageError> {
+    let resolution = uR64 {
+        nominator: JPEG_DPI,
+        denominator: 1,
+    };
+    // The first 8 bytes of a user comment name its character code
+    let mut user_comment = b"ASCII\0\0\0".to_vec();
+    user_comment.extend(
+        format!(
+            "Tonemapper: {}, Exposure: {:+.2} EV",
+            metadata.tonemapper, metadata.exposure_ev
+        )
+        .bytes(),
+    );
+
+    let mut exif = ExifMetadata::new();
+    exif.set_tag(ExifTag::Software(EXIF_SOFTWARE.to_owned()));
+    exif.set_tag(ExifTag::ImageWidth(vec![width as u32]));
+    exif.set_tag(ExifTag::ImageHeight(vec![height as u32]));
+    // 1 is sRGB
+    exif.set_tag(ExifTag::ColorSpace(vec![1]));
+    exif.set_tag(ExifTag::XResolution(vec![resolution.clone()]));
+    exif.set_tag(ExifTag::YResolution(vec![resolution]));
+    // 2 is inches
+    exif.set_tag(ExifTag::ResolutionUnit(vec![2]));
+    exif.set_tag(ExifTag::UserComment(user_comment));
+    if let Some(document_name) = &metadata.document_name {
+        // DocumentName, which little_exif has no variant for
+        exif.set_tag(ExifTag::UnknownSTRING(
+            document_name.clone(),
+            0x010d,
+            ExifTagGroup::IFD0,
+        ));
+    }
+    if let Some(description) = &metadata.description {
+        exif.set_tag(ExifTag::ImageDescription(description.clone()));
+    }
+    exif.write_to_file(image_path)?;
+    Ok(())
+}
+
+/// An equirectangular HDR environment map, in linear ACEScg
+pub struct HdriMap {
+    pub width: usize,
+    pub height: usize,
+    // RGB, row by row starting from the top (+Y)
+    pub pixels: Vec<[f32; 3]>,
+}
+
+impl HdriMap {
+    /// Read the first RGBA layer of an OpenEXR file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ImageError> {
+        let image = read_first_rgba_layer_from_file(
+            path,
+            |resolution, _| HdriMap {
+                width: resolution.width(),
+                height: resolution.height(),
+                pixels: vec![[0.0; 3]; resolution.width() * resolution.height()],
+            },
+            |map: &mut HdriMap, position: Vec2<usize>, (r, g, b, _a): (f32, f32, f32, f32)| {
+                map.pixels[position.y() * map.width + position.x()] = [r, g, b];
+            },
+        )?;
+        Ok(image.layer_data.channel_data.pixels)
+    }
+}
+
+/// Second order spherical harmonics (9 coefficients per RGB channel)
+/// representing the radiance of an environment
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SphericalHarmonics9 {
+    // Ordered as (l, m): (0, 0), (1, -1), (1, 0), (1, 1), (2, -2), (2, -1), (2, 0), (2, 1), (2, 2)
+    pub coefficients: [[f32; 3]; 9],
+}
+
+/// Real SH basis functions for a unit direction
+fn sh9_basis(dir: [f32; 3]) -> [f32; 9] {
+    let [x, y, z] = dir;
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
+
+impl SphericalHarmonics9 {
+    /// Irradiance reaching a surface with the given unit normal,
+    /// convolving the radiance with the clamped cosine lobe (Ramamoorthi & Hanrahan 2001)
+    pub fn irradiance(&self, normal: [f32; 3]) -> [f32; 3] {
+        use std::f32::consts::PI;
+        // Convolution weights for each band
+        const BAND_WEIGHTS: [f32; 3] = [PI, 2.0 * PI / 3.0, PI / 4.0];
+        const BANDS: [usize; 9] = [0, 1, 1, 1, 2, 2, 2, 2, 2];
+
+        let mut irradiance = [0.0; 3];
+        let terms = self.coefficients.iter().zip(sh9_basis(normal)).zip(BANDS);
+        for ((coefficient, basis), band) in terms {
+            let weight = BAND_WEIGHTS[band] * basis;
+            for (e, c) in irradiance.iter_mut().zip(coefficient) {
+                *e += c * weight;
+            }
         }
-        Err(e) => {
-            anyhow::bail!("Failed to write image: {e:?}");
+        irradiance
+    }
+}
+
+/// Project the radiance of an HDRI onto the first 9 SH basis functions
+pub fn bake_sh_irradiance(hdri: &HdriMap) -> SphericalHarmonics9 {
+    use std::f32::consts::PI;
+
+    let mut sh = SphericalHarmonics9::default();
+    let d_phi = 2.0 * PI / hdri.width as f32;
+    let d_theta = PI / hdri.height as f32;
+
+    for y in 0..hdri.height {
+        // Polar angle from +Y, sampled at the center of each row
+        let theta = (y as f32 + 0.5) * d_theta;
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        // Solid angle of the texels in this row
+        let d_omega = sin_theta * d_theta * d_phi;
+
+        for x in 0..hdri.width {
+            let phi = (x as f32 + 0.5) * d_phi;
+            let dir = [sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin()];
+            let radiance = hdri.pixels[y * hdri.width + x];
+
+            for (coefficient, basis) in sh.coefficients.iter_mut().zip(sh9_basis(dir)) {
+                for (c, r) in coefficient.iter_mut().zip(radiance) {
+                    *c += r * basis * d_omega;
+                }
+            }
+        }
+    }
+
+    sh
+}
+
+/// Render a white diffuse sphere lit only by the given SH environment, in the pixels of
+/// `region` (see `render_bg_image`)
+pub fn render_sh_sphere(
+    render_buffer: &mut [f32; RENDER_BUFFER_SIZE],
+    sh: &SphericalHarmonics9,
+    region: [usize; 4],
+) {
+    let radius = 0.8;
+    let [x0, y0, x1, y1] = region.map(|v| v as u32);
+    for row in y0..y1 {
+        let y = RENDER_BUFFER_HEIGHT - 1 - row;
+        for x in x0..x1 {
+            let index = ((row * RENDER_BUFFER_WIDTH + x) * 4) as usize;
+            let px = fit_range(x as f32 + 0.5, 0.0, RENDER_BUFFER_WIDTH as f32, -1.0, 1.0) / radius;
+            let py =
+                fit_range(y as f32 + 0.5, 0.0, RENDER_BUFFER_HEIGHT as f32, -1.0, 1.0) / radius;
+            let r2 = px * px + py * py;
+
+            let rgb = if r2 <= 1.0 {
+                // Normal of the sphere facing the camera, looking down -Z
+                let normal = [px, py, (1.0 - r2).sqrt()];
+                // Lambertian with albedo 1: radiance = irradiance / pi
+                sh.irradiance(normal)
+                    .map(|e| (e / std::f32::consts::PI).max(0.0))
+            } else {
+                [0.0; 3]
+            };
+
+            render_buffer[index] = rgb[0];
+            render_buffer[index + 1] = rgb[1];
+            render_buffer[index + 2] = rgb[2];
+            render_buffer[index + 3] = 1.0;
+        }
+    }
+}
+
+/// The six faces of a cubemap, in the order +X, -X, +Y, -Y, +Z, -Z.
+/// Each face is square, RGBA f32, row-major starting from the top.
+pub type CubemapFaces = [Vec<f32>; 6];
+
+/// File name suffixes of the cubemap faces, in the same order as `CubemapFaces`
+pub const CUBEMAP_FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+impl HdriMap {
+    /// Bilinearly interpolated radiance in the given unit direction
+    pub fn sample(&self, dir: [f32; 3]) -> [f32; 3] {
+        use std::f32::consts::PI;
+
+        // Inverse of the mapping used by `bake_sh_irradiance`
+        let theta = dir[1].clamp(-1.0, 1.0).acos();
+        let phi = dir[2].atan2(dir[0]).rem_euclid(2.0 * PI);
+
+        let x = phi / (2.0 * PI) * self.width as f32 - 0.5;
+        let y = theta / PI * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        // Wrap around horizontally, clamp at the poles
+        let column = |x: f32| (x as i64).rem_euclid(self.width as i64) as usize;
+        let row = |y: f32| (y.max(0.0) as usize).min(self.height - 1);
+        let texel = |x: f32, y: f32| self.pixels[row(y) * self.width + column(x)];
+
+        let top_left = texel(x0, y0);
+        let top_right = texel(x0 + 1.0, y0);
+        let bottom_left = texel(x0, y0 + 1.0);
+        let bottom_right = texel(x0 + 1.0, y0 + 1.0);
+
+        std::array::from_fn(|c| {
+            let top = top_left[c] + (top_right[c] - top_left[c]) * fx;
+            let bottom = bottom_left[c] + (bottom_right[c] - bottom_left[c]) * fx;
+            top + (bottom - top) * fy
+        })
+    }
+}
+
+/// Direction through a texel of a cubemap face, with `a` and `b` in [-1, 1]
+/// going right and down the face (OpenGL cubemap convention)
+fn cubemap_direction(face: usize, a: f32, b: f32) -> [f32; 3] {
+    let dir = match face {
+        0 => [1.0, -b, -a],
+        1 => [-1.0, -b, a],
+        2 => [a, 1.0, b],
+        3 => [a, -1.0, -b],
+        4 => [a, -b, 1.0],
+        _ => [-a, -b, -1.0],
+    };
+    let length = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+    dir.map(|d| d / length)
+}
+
+/// Reproject an equirectangular HDRI to the six faces of a cubemap
+pub fn equirect_to_cubemap(src: &HdriMap, face_size: usize) -> CubemapFaces {
+    std::array::from_fn(|face| {
+        let mut data = vec![0.0; face_size * face_size * 4];
+        for (i, pixel) in data.chunks_exact_mut(4).enumerate() {
+            let a = ((i % face_size) as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+            let b = ((i / face_size) as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+            let rgb = src.sample(cubemap_direction(face, a, b));
+            pixel[..3].copy_from_slice(&rgb);
+            pixel[3] = 1.0;
+        }
+        data
+    })
+}
+
+/// Lay the cubemap faces out as a horizontal cross for preview:
+///
+/// ```text
+///       +Y
+///   -X  +Z  +X  -Z
+///       -Y
+/// ```
+pub fn render_cubemap_crossview(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    faces: &CubemapFaces,
+) {
+    // Cell of each face in the 4x3 grid
+    const LAYOUT: [(usize, usize); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+
+    let face_size = ((faces[0].len() / 4) as f32).sqrt() as usize;
+    let cell = (width / 4).min(height / 3);
+    let offset_x = (width - cell * 4) / 2;
+    let offset_y = (height - cell * 3) / 2;
+
+    buffer.fill(0.0);
+    if cell == 0 || face_size == 0 {
+        return;
+    }
+
+    for (face, (column, row)) in faces.iter().zip(LAYOUT) {
+        for y in 0..cell {
+            for x in 0..cell {
+                // Nearest neighbour is plenty for a preview
+                let src_x = x * face_size / cell;
+                let src_y = y * face_size / cell;
+                let src = (src_y * face_size + src_x) * 4;
+
+                let dst_x = offset_x + column * cell + x;
+                let dst_y = offset_y + row * cell + y;
+                let dst = (dst_y * width + dst_x) * 4;
+
+                buffer[dst..dst + 4].copy_from_slice(&face[src..src + 4]);
+            }
         }
     }
+}
 
+/// Save every face of the cubemap as `<prefix>_px.exr`, `<prefix>_nx.exr`, etc.
+pub fn write_cubemap_faces(
+    prefix: impl AsRef<Path>,
+    faces: &CubemapFaces,
+) -> Result<(), ImageError> {
+    let prefix = prefix.as_ref();
+    let face_size = ((faces[0].len() / 4) as f32).sqrt() as usize;
+    for (face, name) in faces.iter().zip(CUBEMAP_FACE_NAMES) {
+        let mut image_path = prefix.as_os_str().to_owned();
+        image_path.push(format!("_{name}.exr"));
+        write_as_exr_image(
+            Path::new(&image_path),
+            face_size,
+            face_size,
+            face,
+            &MetadataMap::default(),
+            1.0,
+            false,
+            AlphaMode::Straight,
+        )?;
+    }
     Ok(())
 }
+
+/// Cells per side of the grid used by the adaptive equalization
+pub const CLAHE_GRID_SIZE: usize = 8;
+/// Maximum height of a cell histogram bin, relative to a flat histogram.
+/// Limits how much the noise in flat areas gets amplified.
+pub const CLAHE_CLIP_LIMIT: f32 = 3.0;
+
+/// How the histogram equalization finds its remapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum EqualizationMode {
+    /// One histogram for the whole image
+    Global,
+    /// Contrast limited, one histogram per cell of a grid (CLAHE)
+    AdaptiveHE,
+}
+
+/// Where an output channel of `apply_channel_remap` reads from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ChannelSource {
+    R,
+    G,
+    B,
+    A,
+    Zero,
+    One,
+    /// ACEScg luminance of the RGB channels
+    Luma,
+}
+
+impl ChannelSource {
+    /// Every source, in the order shown in the GUI
+    pub const ALL: [ChannelSource; 7] = [
+        ChannelSource::R,
+        ChannelSource::G,
+        ChannelSource::B,
+        ChannelSource::A,
+        ChannelSource::Zero,
+        ChannelSource::One,
+        ChannelSource::Luma,
+    ];
+
+    fn read(&self, pixel: [f32; 4]) -> f32 {
+        match self {
+            ChannelSource::R => pixel[0],
+            ChannelSource::G => pixel[1],
+            ChannelSource::B => pixel[2],
+            ChannelSource::A => pixel[3],
+            ChannelSource::Zero => 0.0,
+            ChannelSource::One => 1.0,
+            ChannelSource::Luma => acescg_luminance([pixel[0], pixel[1], pixel[2]]),
+        }
+    }
+}
+
+/// Source of each output channel, to swap or copy channels around
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ChannelRemap {
+    pub r_src: ChannelSource,
+    pub g_src: ChannelSource,
+    pub b_src: ChannelSource,
+    pub a_src: ChannelSource,
+}
+
+impl ChannelRemap {
+    /// Every channel reads from itself
+    pub const IDENTITY: ChannelRemap = ChannelRemap {
+        r_src: ChannelSource::R,
+        g_src: ChannelSource::G,
+        b_src: ChannelSource::B,
+        a_src: ChannelSource::A,
+    };
+}
+
+impl Default for ChannelRemap {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Rebuild every pixel from the sources picked in `remap`.
+/// All the sources are read before any channel is written, so channels can be swapped.
+pub fn apply_channel_remap(buffer: &mut [f32], width: usize, height: usize, remap: ChannelRemap) {
+    if remap == ChannelRemap::IDENTITY {
+        return;
+    }
+    for pixel in buffer.chunks_exact_mut(4).take(width * height) {
+        let source = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        pixel[0] = remap.r_src.read(source);
+        pixel[1] = remap.g_src.read(source);
+        pixel[2] = remap.b_src.read(source);
+        pixel[3] = remap.a_src.read(source);
+    }
+}
+
+/// Effects applied to the linear framebuffer before it's displayed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostProcessSettings {
+    pub channel_remap: ChannelRemap,
+    pub invert_enabled: bool,
+    pub invert_mode: InvertMode,
+    // ACEScg value inverted to 0 by `InvertMode::Color`
+    pub invert_max_value: f32,
+    // Blend towards the rebuilt clipped highlights, 0 disables it
+    pub highlight_recovery: f32,
+    pub levels: LevelsParams,
+    pub cdl: CdlParams,
+    pub split_tone: SplitToneParams,
+    // Degrees, from -180 to 180
+    pub hue_rotation: f32,
+    // Color cycling, adds `hue_cycle_step` degrees to the rotation on every frame
+    pub hue_cycle_enabled: bool,
+    pub hue_cycle_step: f32,
+    pub equalization_mode: EqualizationMode,
+    // 0 disables the equalization
+    pub equalization_strength: f32,
+    // 0 disables the sharpening
+    pub sharpen_strength: f32,
+    // Pixels
+    pub sharpen_radius: f32,
+    pub sharpen_reduce_halos: bool,
+    // Pixels, 0 disables the border
+    pub border_width: u32,
+    // ACEScg RGBA
+    pub border_color: [f32; 4],
+    pub border_style: BorderStyle,
+    // Luminance between two contour lines
+    pub contour_step: f32,
+    // ACEScg RGBA
+    pub contour_color: [f32; 4],
+    // Pixels, 0 disables the contour lines
+    pub contour_width: f32,
+    pub contour_mode: ContourMode,
+    pub night_vision: NightVisionParams,
+    pub cel_shade_enabled: bool,
+    // Lightness bands, from 2 to 16
+    pub cel_shade_tones: u32,
+    // Normalized Sobel gradient magnitude above which edges are drawn
+    pub cel_shade_edge_threshold: f32,
+    // ACEScg RGBA
+    pub cel_shade_edge_color: [f32; 4],
+    pub cel_shade_mode: CelShadeMode,
+    pub stained_glass_enabled: bool,
+    pub stained_glass_regions: usize,
+    // Pixels of lead between two regions
+    pub stained_glass_border_thickness: u32,
+    // ACEScg RGBA
+    pub stained_glass_border_color: [f32; 4],
+    pub stained_glass_seed: u64,
+    pub crosshatch_enabled: bool,
+    // Pixels between two lines of a set
+    pub crosshatch_spacing: u32,
+    // Pixels
+    pub crosshatch_line_width: f32,
+    // Degrees, of the first and the second set of lines
+    pub crosshatch_angles: [f32; 2],
+    // Luminance below which the second set of lines is drawn
+    pub crosshatch_dark_threshold: f32,
+    pub watercolor_enabled: bool,
+    pub watercolor_steps: u32,
+    // Multiplier of the diffusion steps, how far the pigment spreads
+    pub watercolor_wetness: f32,
+    pub watercolor_paper: f32,
+    pub watercolor_granulation: f32,
+    pub thermal_enabled: bool,
+    pub thermal_colormap: ThermalColormap,
+    // ACEScg, ends of the grayscale colormap
+    pub thermal_cold_color: [f32; 3],
+    pub thermal_hot_color: [f32; 3],
+    // Largest offset of the fixed pattern sensor noise, 0 disables it
+    pub thermal_noise: f32,
+    // Depth of field, only applied once a depth buffer is loaded
+    pub bokeh_enabled: bool,
+    // Depth in focus, in the units of the depth buffer
+    pub bokeh_focus_depth: f32,
+    // Circle of confusion in pixels per unit of depth away from the focus
+    pub bokeh_aperture: f32,
+    // Pixels
+    pub bokeh_max_radius: f32,
+    pub bokeh_shape: BokehShape,
+    // Fake depth of field of flat renders, see `apply_pseudo_dof`
+    pub tilt_shift_enabled: bool,
+    // Height of the focal plane, from 0 at the top to 1 at the bottom
+    pub tilt_shift_focus: f32,
+    // Pixels of blur on the row farthest from the focal plane
+    pub tilt_shift_aperture: f32,
+    // Bilateral filter, see `apply_bilateral_filter`
+    pub noise_reduction_enabled: bool,
+    // Pixels
+    pub noise_reduction_spatial_sigma: f32,
+    // Oklab distance
+    pub noise_reduction_range_sigma: f32,
+    pub kaleidoscope_enabled: bool,
+    pub kaleidoscope_mode: KaleidoscopeMode,
+    // One of `KALEIDOSCOPE_SEGMENTS`
+    pub kaleidoscope_segments: u32,
+    // Degrees
+    pub kaleidoscope_angle: f32,
+    pub kaleidoscope_zoom: f32,
+    pub polar_enabled: bool,
+    pub polar_mode: PolarMode,
+    // Degrees
+    pub polar_angle_offset: f32,
+    // Relative to the image size, from the top left
+    pub polar_center: [f32; 2],
+    pub fisheye_enabled: bool,
+    pub fisheye_mode: FisheyeMode,
+    // Degrees, from 90 to 220
+    pub fisheye_fov: f32,
+    pub fisheye_strength: f32,
+    pub chromatic_enabled: bool,
+    pub chromatic_mode: ChromaticAberrationMode,
+    // Pixels, from 0 to 20
+    pub chromatic_max_shift: f32,
+    // Transverse only, the shift grows away from the center instead of being the same everywhere
+    pub chromatic_radial: bool,
+    // Degrees, direction in which red moves when not radial
+    pub chromatic_angle: f32,
+    // Only applied once a displacement map is loaded
+    pub grid_warp_enabled: bool,
+    // Pixels of displacement for a value of 1 in the map
+    pub grid_warp_scale: f32,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            channel_remap: ChannelRemap::IDENTITY,
+            invert_enabled: false,
+            invert_mode: InvertMode::Color,
+            invert_max_value: 1.0,
+            highlight_recovery: 0.0,
+            levels: LevelsParams::default(),
+            cdl: CdlParams::default(),
+            split_tone: SplitToneParams::default(),
+            hue_rotation: 0.0,
+            hue_cycle_enabled: false,
+            hue_cycle_step: 2.0,
+            equalization_mode: EqualizationMode::Global,
+            equalization_strength: 0.0,
+            sharpen_strength: 0.0,
+            sharpen_radius: 1.0,
+            sharpen_reduce_halos: true,
+            border_width: 0,
+            border_color: [1.0, 1.0, 1.0, 1.0],
+            border_style: BorderStyle::Solid,
+            contour_step: 0.05,
+            contour_color: [0.0, 0.0, 0.0, 1.0],
+            contour_width: 0.0,
+            contour_mode: ContourMode::All,
+            night_vision: NightVisionParams::default(),
+            cel_shade_enabled: false,
+            cel_shade_tones: 4,
+            cel_shade_edge_threshold: 0.2,
+            cel_shade_edge_color: [0.0, 0.0, 0.0, 1.0],
+            cel_shade_mode: CelShadeMode::Single,
+            stained_glass_enabled: false,
+            stained_glass_regions: 200,
+            stained_glass_border_thickness: 2,
+            stained_glass_border_color: [0.02, 0.02, 0.02, 1.0],
+            stained_glass_seed: 0,
+            crosshatch_enabled: false,
+            crosshatch_spacing: 6,
+            crosshatch_line_width: 1.0,
+            crosshatch_angles: [45.0, -45.0],
+            crosshatch_dark_threshold: 0.5,
+            watercolor_enabled: false,
+            watercolor_steps: 8,
+            watercolor_wetness: 1.0,
+            watercolor_paper: 0.3,
+            watercolor_granulation: 0.5,
+            thermal_enabled: false,
+            thermal_colormap: ThermalColormap::Ironbow,
+            thermal_cold_color: [0.0, 0.0, 0.0],
+            thermal_hot_color: [1.0, 1.0, 1.0],
+            thermal_noise: 0.0,
+            bokeh_enabled: false,
+            bokeh_focus_depth: 1.0,
+            bokeh_aperture: 4.0,
+            bokeh_max_radius: 16.0,
+            bokeh_shape: BokehShape::Disk,
+            tilt_shift_enabled: false,
+            tilt_shift_focus: 0.5,
+            tilt_shift_aperture: 6.0,
+            noise_reduction_enabled: false,
+            noise_reduction_spatial_sigma: 2.0,
+            noise_reduction_range_sigma: 0.1,
+            kaleidoscope_enabled: false,
+            kaleidoscope_mode: KaleidoscopeMode::Center,
+            kaleidoscope_segments: 6,
+            kaleidoscope_angle: 0.0,
+            kaleidoscope_zoom: 1.0,
+            polar_enabled: false,
+            polar_mode: PolarMode::RectToPolar,
+            polar_angle_offset: 0.0,
+            polar_center: [0.5, 0.5],
+            fisheye_enabled: false,
+            fisheye_mode: FisheyeMode::Barrel,
+            fisheye_fov: 180.0,
+            fisheye_strength: 0.2,
+            chromatic_enabled: false,
+            chromatic_mode: ChromaticAberrationMode::Transverse,
+            chromatic_max_shift: 2.0,
+            chromatic_radial: true,
+            chromatic_angle: 0.0,
+            grid_warp_enabled: false,
+            grid_warp_scale: 8.0,
+        }
+    }
+}
+
+/// One of the effects of `PostProcessSettings`, each run by a pass of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum PostProcessEffect {
+    Bokeh,
+    GridWarp,
+    TiltShift,
+    NoiseReduction,
+    HighlightRecovery,
+    Levels,
+    Cdl,
+    ChannelRemap,
+    Invert,
+    SplitTone,
+    HueRotate,
+    Kaleidoscope,
+    PolarCoordinates,
+    Fisheye,
+    ChromaticAberration,
+    SmartSharpen,
+    HistogramEqualization,
+    CelShade,
+    StainedGlass,
+    Crosshatch,
+    Watercolor,
+    Thermal,
+    NightVision,
+    Contour,
+    Border,
+}
+
+impl PostProcessEffect {
+    /// Every effect, in the order the passes run by default
+    pub const ALL: [PostProcessEffect; 25] = [
+        // Lens effects, before anything is done to the picture they took
+        PostProcessEffect::Bokeh,
+        PostProcessEffect::GridWarp,
+        PostProcessEffect::TiltShift,
+        // Noise of the render itself, before any grading makes it stand out
+        PostProcessEffect::NoiseReduction,
+        // Still in scene linear light, before the highlights are graded
+        PostProcessEffect::HighlightRecovery,
+        // On the image as rendered, which is what the levels histograms show
+        PostProcessEffect::Levels,
+        PostProcessEffect::Cdl,
+        // Then, the other effects expect color in RGB
+        PostProcessEffect::ChannelRemap,
+        PostProcessEffect::Invert,
+        PostProcessEffect::SplitTone,
+        PostProcessEffect::HueRotate,
+        // Before the effects that look at neighbouring pixels, as if shot through the lens
+        PostProcessEffect::Kaleidoscope,
+        PostProcessEffect::PolarCoordinates,
+        PostProcessEffect::Fisheye,
+        PostProcessEffect::ChromaticAberration,
+        PostProcessEffect::SmartSharpen,
+        PostProcessEffect::HistogramEqualization,
+        PostProcessEffect::CelShade,
+        PostProcessEffect::StainedGlass,
+        PostProcessEffect::Crosshatch,
+        PostProcessEffect::Watercolor,
+        PostProcessEffect::Thermal,
+        PostProcessEffect::NightVision,
+        PostProcessEffect::Contour,
+        // Last, so the border isn't equalized or sharpened with the image
+        PostProcessEffect::Border,
+    ];
+
+    /// Name shown in the pass list of the GUI
+    pub fn label(&self) -> &'static str {
+        match self {
+            PostProcessEffect::Bokeh => "Bokeh",
+            PostProcessEffect::GridWarp => "Grid Warp",
+            PostProcessEffect::TiltShift => "Tilt-Shift",
+            PostProcessEffect::NoiseReduction => "Noise Reduction",
+            PostProcessEffect::HighlightRecovery => "Highlight Recovery",
+            PostProcessEffect::Levels => "Levels",
+            PostProcessEffect::Cdl => "CDL",
+            PostProcessEffect::ChannelRemap => "Channel Remap",
+            PostProcessEffect::Invert => "Invert",
+            PostProcessEffect::SplitTone => "Split Tone",
+            PostProcessEffect::HueRotate => "Hue Rotate",
+            PostProcessEffect::Kaleidoscope => "Kaleidoscope",
+            PostProcessEffect::PolarCoordinates => "Polar Coordinates",
+            PostProcessEffect::Fisheye => "Fisheye",
+            PostProcessEffect::ChromaticAberration => "Chromatic Aberration",
+            PostProcessEffect::SmartSharpen => "Smart Sharpen",
+            PostProcessEffect::HistogramEqualization => "Histogram Equalization",
+            PostProcessEffect::CelShade => "Cel Shade",
+            PostProcessEffect::StainedGlass => "Stained Glass",
+            PostProcessEffect::Crosshatch => "Crosshatch",
+            PostProcessEffect::Watercolor => "Watercolor",
+            PostProcessEffect::Thermal => "Thermal",
+            PostProcessEffect::NightVision => "Night Vision",
+            PostProcessEffect::Contour => "Contour",
+            PostProcessEffect::Border => "Border",
+        }
+    }
+}
+
+impl PostProcessSettings {
+    /// Whether `effect` changes the image with these settings
+    pub fn is_enabled(&self, effect: PostProcessEffect) -> bool {
+        match effect {
+            PostProcessEffect::Bokeh => self.bokeh_enabled,
+            PostProcessEffect::GridWarp => self.grid_warp_enabled,
+            PostProcessEffect::TiltShift => self.tilt_shift_enabled,
+            PostProcessEffect::NoiseReduction => self.noise_reduction_enabled,
+            PostProcessEffect::HighlightRecovery => self.highlight_recovery > 0.0,
+            PostProcessEffect::Levels => self.levels.enabled,
+            PostProcessEffect::Cdl => self.cdl.enabled,
+            PostProcessEffect::ChannelRemap => self.channel_remap != ChannelRemap::IDENTITY,
+            PostProcessEffect::Invert => self.invert_enabled,
+            PostProcessEffect::SplitTone => self.split_tone.enabled,
+            PostProcessEffect::HueRotate => self.hue_rotation != 0.0 || self.hue_cycle_enabled,
+            PostProcessEffect::Kaleidoscope => self.kaleidoscope_enabled,
+            PostProcessEffect::PolarCoordinates => self.polar_enabled,
+            PostProcessEffect::Fisheye => self.fisheye_enabled,
+            PostProcessEffect::ChromaticAberration => self.chromatic_enabled,
+            PostProcessEffect::SmartSharpen => self.sharpen_strength > 0.0,
+            PostProcessEffect::HistogramEqualization => self.equalization_strength > 0.0,
+            PostProcessEffect::CelShade => self.cel_shade_enabled,
+            PostProcessEffect::StainedGlass => self.stained_glass_enabled,
+            PostProcessEffect::Crosshatch => self.crosshatch_enabled,
+            PostProcessEffect::Watercolor => self.watercolor_enabled,
+            PostProcessEffect::Thermal => self.thermal_enabled,
+            PostProcessEffect::NightVision => self.night_vision.enabled,
+            PostProcessEffect::Contour => self.contour_width > 0.0,
+            PostProcessEffect::Border => self.border_width > 0,
+        }
+    }
+}
+
+/// Tonemapped, sRGB encoded value in [0, 1] of a gray with the given scene luminance
+fn display_gray(luminance: f32) -> f32 {
+    let gray = color::acescg::<Scene>(luminance, luminance, luminance);
+    let params = PerceptualTonemapperParams::default();
+    let tonemapped: Color<AcesCg, Display> = PerceptualTonemapper::tonemap(gray, params).convert();
+    tonemapped.convert::<EncodedSrgb>().g
+}
+
+/// Scene luminance shown as each of the 256 levels of an 8 bit display.
+/// The tonemapper is monotonic, so it's inverted with a bisection in log2 space.
+fn display_level_luminances() -> &'static [f32; 256] {
+    static LEVELS: OnceLock<[f32; 256]> = OnceLock::new();
+    LEVELS.get_or_init(|| {
+        std::array::from_fn(|level| {
+            if level == 0 {
+                return 0.0;
+            }
+            let target = level as f32 / 255.0;
+            let (mut low, mut high) = (-20.0_f32, 20.0_f32);
+            for _ in 0..32 {
+                let mid = 0.5 * (low + high);
+                if display_gray(mid.exp2()) < target {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            (0.5 * (low + high)).exp2()
+        })
+    })
+}
+
+/// 8 bit display level of a scene luminance
+fn display_level(levels: &[f32; 256], luminance: f32) -> usize {
+    levels.partition_point(|&level| level < luminance).min(255)
+}
+
+/// Scene luminance of a fractional display level
+fn level_luminance(levels: &[f32; 256], level: f32) -> f32 {
+    let low = (level.floor() as usize).min(255);
+    let high = (low + 1).min(255);
+    let t = level - low as f32;
+    levels[low] + (levels[high] - levels[low]) * t
+}
+
+/// Remapping of each level that makes the histogram as flat as possible,
+/// built from its cumulative distribution function
+fn equalization_table(histogram: &[u32; 256]) -> [f32; 256] {
+    let total: u32 = histogram.iter().sum();
+    let cdf_min = histogram
+        .iter()
+        .copied()
+        .find(|&count| count > 0)
+        .unwrap_or(0);
+    let mut cdf = 0;
+    std::array::from_fn(|level| {
+        cdf += histogram[level];
+        if total == cdf_min {
+            // A single populated level, nothing to spread
+            level as f32
+        } else {
+            cdf.saturating_sub(cdf_min) as f32 / (total - cdf_min) as f32 * 255.0
+        }
+    })
+}
+
+/// Blend a pixel's luminance towards the equalized one,
+/// scaling RGB so hue and saturation are preserved
+fn equalize_pixel(pixel: &mut [f32], levels: &[f32; 256], target_level: f32, strength: f32) {
+    let luminance = acescg_luminance([pixel[0], pixel[1], pixel[2]]);
+    if luminance <= 0.0 {
+        return;
+    }
+    let equalized = level_luminance(levels, target_level);
+    let scale = (luminance + (equalized - luminance) * strength) / luminance;
+    for channel in &mut pixel[..3] {
+        *channel *= scale;
+    }
+}
+
+/// Increase the global contrast by flattening the histogram of the displayed luminance.
+/// `strength` blends between the original (0) and the fully equalized (1) luminance.
+pub fn apply_histogram_equalization(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    strength: f32,
+) {
+    if strength <= 0.0 {
+        return;
+    }
+    let levels = display_level_luminances();
+    let pixels = &mut buffer[..width * height * 4];
+
+    let mut histogram = [0_u32; 256];
+    for pixel in pixels.chunks_exact(4) {
+        let luminance = acescg_luminance([pixel[0], pixel[1], pixel[2]]);
+        histogram[display_level(levels, luminance)] += 1;
+    }
+    let table = equalization_table(&histogram);
+
+    for pixel in pixels.chunks_exact_mut(4) {
+        let luminance = acescg_luminance([pixel[0], pixel[1], pixel[2]]);
+        let target_level = table[display_level(levels, luminance)];
+        equalize_pixel(pixel, levels, target_level, strength);
+    }
+}
+
+/// Contrast limited adaptive histogram equalization (CLAHE).
+/// The image is split in a `grid_size` x `grid_size` grid, each cell gets its own remapping
+/// with the histogram bins clipped at `clip_limit` times the average bin height.
+/// Pixels bilinearly interpolate the remappings of the four closest cell centers,
+/// so there are no seams at the cell boundaries.
+pub fn apply_adaptive_histogram_equalization(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    strength: f32,
+    grid_size: usize,
+    clip_limit: f32,
+) {
+    if strength <= 0.0 || width == 0 || height == 0 {
+        return;
+    }
+    let levels = display_level_luminances();
+    let cells_x = grid_size.clamp(1, width);
+    let cells_y = grid_size.clamp(1, height);
+    let pixel_level =
+        |pixel: &[f32]| display_level(levels, acescg_luminance([pixel[0], pixel[1], pixel[2]]));
+
+    let mut histograms = vec![[0_u32; 256]; cells_x * cells_y];
+    for (y, row) in buffer.chunks_exact(width * 4).take(height).enumerate() {
+        let cell_y = y * cells_y / height;
+        for (x, pixel) in row.chunks_exact(4).enumerate() {
+            let cell_x = x * cells_x / width;
+            histograms[cell_y * cells_x + cell_x][pixel_level(pixel)] += 1;
+        }
+    }
+
+    let tables: Vec<[f32; 256]> = histograms
+        .iter_mut()
+        .map(|histogram| {
+            // Clip the tallest bins and spread what was cut off evenly over all the levels
+            let total: u32 = histogram.iter().sum();
+            let limit = ((clip_limit * total as f32 / 256.0) as u32).max(1);
+            let mut excess = 0;
+            for count in histogram.iter_mut() {
+                excess += count.saturating_sub(limit);
+                *count = (*count).min(limit);
+            }
+            let remainder = (excess % 256) as usize;
+            for (level, count) in histogram.iter_mut().enumerate() {
+                *count += excess / 256 + u32::from(level < remainder);
+            }
+            equalization_table(histogram)
+        })
+        .collect();
+
+    // Position of a pixel between the two closest cell centers along one axis
+    let cell_coordinate = |position: usize, size: usize, cells: usize| {
+        let t = (position as f32 + 0.5) * cells as f32 / size as f32 - 0.5;
+        let low = t.floor().clamp(0.0, (cells - 1) as f32);
+        let high = (low as usize + 1).min(cells - 1);
+        (low as usize, high, (t - low).clamp(0.0, 1.0))
+    };
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        let (y0, y1, ty) = cell_coordinate(y, height, cells_y);
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let (x0, x1, tx) = cell_coordinate(x, width, cells_x);
+            let level = pixel_level(pixel);
+            let remap = |cell_x: usize, cell_y: usize| tables[cell_y * cells_x + cell_x][level];
+            let top = remap(x0, y0) + (remap(x1, y0) - remap(x0, y0)) * tx;
+            let bottom = remap(x0, y1) + (remap(x1, y1) - remap(x0, y1)) * tx;
+            let target_level = top + (bottom - top) * ty;
+            equalize_pixel(pixel, levels, target_level, strength);
+        }
+    }
+}
+
+/// Add the anti-aliased coverage of a thick segment from `a` to `b` (in pixels) to a
+/// single channel coverage buffer. Overlapping segments keep the maximum coverage,
+/// so joints in a polyline don't get brighter.
+pub fn rasterize_segment_coverage(
+    coverage: &mut [f32],
+    width: usize,
+    height: usize,
+    a: [f32; 2],
+    b: [f32; 2],
+    thickness: f32,
+) {
+    for_each_segment_pixel(width, height, a, b, thickness, |index, value| {
+        let covered = &mut coverage[index];
+        *covered = covered.max(value);
+    });
+}
+
+/// Call `visit` with the index and anti-aliased coverage of every pixel near the thick
+/// segment from `a` to `b`, including those it doesn't cover
+fn for_each_segment_pixel(
+    width: usize,
+    height: usize,
+    a: [f32; 2],
+    b: [f32; 2],
+    thickness: f32,
+    mut visit: impl FnMut(usize, f32),
+) {
+    let radius = thickness * 0.5;
+    // Pixels within half a pixel of the edge are partially covered
+    let reach = radius + 0.5;
+    let min_x = (a[0].min(b[0]) - reach).floor().max(0.0) as usize;
+    let min_y = (a[1].min(b[1]) - reach).floor().max(0.0) as usize;
+    let max_x = ((a[0].max(b[0]) + reach).ceil().max(0.0) as usize).min(width);
+    let max_y = ((a[1].max(b[1]) + reach).ceil().max(0.0) as usize).min(height);
+
+    let direction = [b[0] - a[0], b[1] - a[1]];
+    let length_squared = direction[0] * direction[0] + direction[1] * direction[1];
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let p = [x as f32 + 0.5 - a[0], y as f32 + 0.5 - a[1]];
+            let t = if length_squared > 0.0 {
+                ((p[0] * direction[0] + p[1] * direction[1]) / length_squared).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let distance = (p[0] - direction[0] * t).hypot(p[1] - direction[1] * t);
+            visit(y * width + x, (reach - distance).clamp(0.0, 1.0));
+        }
+    }
+}
+
+/// Same as `rasterize_segment_coverage` for the arc of the circle of `radius` around
+/// `center`, from `start_angle` to `end_angle` in radians, clockwise on screen since y
+/// goes down. The ends are rounded like the segment ones.
+#[allow(clippy::too_many_arguments)]
+pub fn rasterize_arc_coverage(
+    coverage: &mut [f32],
+    width: usize,
+    height: usize,
+    center: [f32; 2],
+    radius: f32,
+    start_angle: f32,
+    end_angle: f32,
+    thickness: f32,
+) {
+    let reach = thickness * 0.5 + 0.5;
+    let outer = radius + reach;
+    let min_x = (center[0] - outer).floor().max(0.0) as usize;
+    let min_y = (center[1] - outer).floor().max(0.0) as usize;
+    let max_x = ((center[0] + outer).ceil().max(0.0) as usize).min(width);
+    let max_y = ((center[1] + outer).ceil().max(0.0) as usize).min(height);
+
+    let span = (end_angle - start_angle).rem_euclid(std::f32::consts::TAU);
+    let point_at = |angle: f32| {
+        let (sin, cos) = angle.sin_cos();
+        [center[0] + radius * cos, center[1] + radius * sin]
+    };
+    let ends = [point_at(start_angle), point_at(start_angle + span)];
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let p = [x as f32 + 0.5 - center[0], y as f32 + 0.5 - center[1]];
+            let angle = p[1].atan2(p[0]);
+            let distance = if (angle - start_angle).rem_euclid(std::f32::consts::TAU) <= span {
+                (p[0].hypot(p[1]) - radius).abs()
+            } else {
+                // Past the ends, the distance to the closest one
+                ends.iter()
+                    .map(|end| (x as f32 + 0.5 - end[0]).hypot(y as f32 + 0.5 - end[1]))
+                    .fold(f32::MAX, f32::min)
+            };
+            let value = (reach - distance).clamp(0.0, 1.0);
+            let covered = &mut coverage[y * width + x];
+            *covered = covered.max(value);
+        }
+    }
+}
+
+/// Parameters of the Lissajous figure render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LissajousSettings {
+    pub freq_x: u32,
+    pub freq_y: u32,
+    // Radians
+    pub phase: f32,
+    // Pixels
+    pub thickness: f32,
+    pub color: [f32; 3],
+    // Advance the phase every frame
+    pub animate: bool,
+}
+
+impl Default for LissajousSettings {
+    fn default() -> Self {
+        Self {
+            freq_x: 3,
+            freq_y: 2,
+            phase: std::f32::consts::FRAC_PI_2,
+            thickness: 1.5,
+            color: [1.0, 0.6, 0.1],
+            animate: false,
+        }
+    }
+}
+
+/// Trace the Lissajous curve `x = sin(freq_x * t + phase)`, `y = sin(freq_y * t)` and add
+/// it on top of what's already in `buffer`, so several calls build up overlapping curves
+#[allow(clippy::too_many_arguments)]
+pub fn render_lissajous(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    freq_x: f32,
+    freq_y: f32,
+    phase: f32,
+    thickness: f32,
+    color: [f32; 3],
+) {
+    // Leave a small margin around the curve
+    let extent = [width as f32 * 0.45, height as f32 * 0.45];
+    let center = [width as f32 * 0.5, height as f32 * 0.5];
+    let point = |t: f32| {
+        [
+            center[0] + (freq_x * t + phase).sin() * extent[0],
+            // Rows are stored top first
+            center[1] - (freq_y * t).sin() * extent[1],
+        ]
+    };
+
+    // A point moves at most `freq * extent` pixels per radian,
+    // keep the segments around a pixel long so the curve stays smooth
+    let speed = freq_x.abs() * extent[0] + freq_y.abs() * extent[1];
+    let segments = ((std::f32::consts::TAU * speed).ceil() as usize).max(16);
+
+    let mut coverage = vec![0.0_f32; width * height];
+    let mut previous = point(0.0);
+    for i in 1..=segments {
+        let current = point(i as f32 / segments as f32 * std::f32::consts::TAU);
+        rasterize_segment_coverage(&mut coverage, width, height, previous, current, thickness);
+        previous = current;
+    }
+
+    for (pixel, covered) in buffer.chunks_exact_mut(4).zip(coverage) {
+        for (channel, value) in pixel.iter_mut().zip(color) {
+            *channel += value * covered;
+        }
+        pixel[3] = pixel[3].max(covered);
+    }
+}
+
+/// Parameters of the spiral render mode, see `render_logarithmic_spiral`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpiralSettings {
+    // Pixels, radius where the spiral starts
+    pub a: f32,
+    // Growth rate, 0 switches to an Archimedean spiral
+    pub b: f32,
+    pub num_turns: f32,
+    // Pixels
+    pub thickness: f32,
+    // Colors at the start and the end of the spiral
+    pub color_a: [f32; 3],
+    pub color_b: [f32; 3],
+    // Radians
+    pub phase: f32,
+    // Turn the spiral every frame
+    pub animate: bool,
+}
+
+impl Default for SpiralSettings {
+    fn default() -> Self {
+        Self {
+            a: 2.0,
+            b: 0.15,
+            num_turns: 4.0,
+            thickness: 2.0,
+            color_a: [0.1, 0.3, 1.0],
+            color_b: [1.0, 0.5, 0.1],
+            phase: 0.0,
+            animate: false,
+        }
+    }
+}
+
+/// Trace the logarithmic spiral `r = a * exp(b * theta)` around the image center for
+/// `theta` in `[phase, phase + num_turns * 2π]`, and add it on top of what's already in
+/// `buffer`. The radius is measured from the start of the curve, so changing `phase` turns
+/// the spiral rather than growing it. The color goes from `color_a` to `color_b` along it.
+///
+/// With `b = 0` the curve would be a circle of radius `a` drawn over itself, so it's the
+/// Archimedean spiral `r = a * (1 + theta / 2π)` instead, whose turns are `a` pixels apart.
+/// As `b` gets close to 0 the spiral stays equiangular, but its constant angle to the radius
+/// tends to 90°: every turn is only `exp(2π * b)` times wider than the previous one, so
+/// the turns get tighter and tighter.
+#[allow(clippy::too_many_arguments)]
+pub fn render_logarithmic_spiral(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    a: f32,
+    b: f32,
+    num_turns: f32,
+    thickness: f32,
+    color_a: [f32; 3],
+    color_b: [f32; 3],
+    phase: f32,
+) {
+    if width == 0 || height == 0 || num_turns <= 0.0 {
+        return;
+    }
+    let center = [width as f32 * 0.5, height as f32 * 0.5];
+    // Past this radius, a growing spiral never comes back into the image
+    let outside = (width as f32).hypot(height as f32) + thickness;
+    let max_theta = num_turns * std::f32::consts::TAU;
+    let radius = |theta: f32| {
+        if b == 0.0 {
+            a * (1.0 + theta / std::f32::consts::TAU)
+        } else {
+            a * (b * theta).exp()
+        }
+    };
+    // Pixels moved per radian, `r * sqrt(1 + b^2)` for the logarithmic spiral
+    let speed = |theta: f32| {
+        if b == 0.0 {
+            radius(theta).hypot(a / std::f32::consts::TAU)
+        } else {
+            radius(theta) * (1.0 + b * b).sqrt()
+        }
+    };
+    let point = |theta: f32| {
+        let r = radius(theta);
+        let angle = theta + phase;
+        // Rows are stored top first
+        [center[0] + r * angle.cos(), center[1] - r * angle.sin()]
+    };
+
+    // The segment last drawn over each pixel picks its color, where it covers it most
+    let mut coverage = vec![0.0_f32; width * height];
+    let mut position = vec![0.0_f32; width * height];
+    let mut theta = 0.0;
+    let mut previous = point(0.0);
+    while theta < max_theta && radius(theta) < outside {
+        // Segments around a pixel long, and never more than a few degrees of a turn
+        let step = (1.0 / speed(theta).max(f32::EPSILON)).min(0.05);
+        let next = (theta + step).min(max_theta);
+        let current = point(next);
+        let t = (theta + next) * 0.5 / max_theta;
+        for_each_segment_pixel(
+            width,
+            height,
+            previous,
+            current,
+            thickness,
+            |index, value| {
+                if value > coverage[index] {
+                    coverage[index] = value;
+                    position[index] = t;
+                }
+            },
+        );
+        previous = current;
+        theta = next;
+    }
+
+    for ((pixel, covered), t) in buffer.chunks_exact_mut(4).zip(coverage).zip(position) {
+        for (channel, (start, end)) in pixel.iter_mut().zip(color_a.iter().zip(color_b)) {
+            *channel += (start + (end - start) * t) * covered;
+        }
+        pixel[3] = pixel[3].max(covered);
+    }
+}
+
+/// Blur an RGBA buffer with a Gaussian kernel of standard deviation `sigma` pixels.
+/// The kernel is separable, so it's applied as a horizontal then a vertical pass.
+/// Edges are clamped.
+pub fn apply_gaussian_blur(buffer: &mut [f32], width: usize, height: usize, sigma: f32) {
+    if sigma <= 0.0 || width == 0 || height == 0 {
+        return;
+    }
+    let radius = (sigma * 3.0).ceil() as isize;
+    let weights: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    let weights: Vec<f32> = weights.iter().map(|w| w / total).collect();
+
+    let source = buffer[..width * height * 4].to_vec();
+    // Horizontal pass, from the copy into the buffer
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let mut sum = [0.0_f32; 4];
+            for (i, weight) in (-radius..=radius).zip(&weights) {
+                let sx = (x as isize + i).clamp(0, width as isize - 1) as usize;
+                let offset = (y * width + sx) * 4;
+                for (s, v) in sum.iter_mut().zip(&source[offset..offset + 4]) {
+                    *s += v * weight;
+                }
+            }
+            pixel.copy_from_slice(&sum);
+        }
+    }
+
+    let source = buffer[..width * height * 4].to_vec();
+    // Vertical pass
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let mut sum = [0.0_f32; 4];
+            for (i, weight) in (-radius..=radius).zip(&weights) {
+                let sy = (y as isize + i).clamp(0, height as isize - 1) as usize;
+                let offset = (sy * width + x) * 4;
+                for (s, v) in sum.iter_mut().zip(&source[offset..offset + 4]) {
+                    *s += v * weight;
+                }
+            }
+            pixel.copy_from_slice(&sum);
+        }
+    }
+}
+
+/// Sharpen by subtracting the Laplacian of Gaussian (LoG) of the image at `radius` pixels.
+/// Responses weaker than `strength` times the local standard deviation are ignored,
+/// so noise in flat areas isn't amplified. With `reduce_halos`, darkening is limited to
+/// the darkest neighbouring pixel, which avoids dark rings around bright edges.
+/// Works on linear values, alpha is left as is.
+pub fn apply_smart_sharpen(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    strength: f32,
+    radius: f32,
+    reduce_halos: bool,
+) {
+    if strength <= 0.0 || radius <= 0.0 || width == 0 || height == 0 {
+        return;
+    }
+    let size = width * height * 4;
+    let original = buffer[..size].to_vec();
+
+    let mut blurred = original.clone();
+    apply_gaussian_blur(&mut blurred, width, height, radius);
+    // E[x^2] - E[x]^2 over the same window gives the local variance
+    let mut blurred_squares: Vec<f32> = original.iter().map(|v| v * v).collect();
+    apply_gaussian_blur(&mut blurred_squares, width, height, radius);
+
+    let at = |x: isize, y: isize| {
+        let x = x.clamp(0, width as isize - 1) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        (y * width + x) * 4
+    };
+    // The LoG falls off as 1 / radius^2, scale it back so `strength` means the same at any radius
+    let normalization = radius * radius;
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let (x, y) = (x as isize, y as isize);
+            let center = at(x, y);
+            let neighbours = [at(x - 1, y), at(x + 1, y), at(x, y - 1), at(x, y + 1)];
+
+            for (channel, value) in pixel[..3].iter_mut().enumerate() {
+                let laplacian = neighbours
+                    .iter()
+                    .map(|&n| blurred[n + channel])
+                    .sum::<f32>()
+                    - 4.0 * blurred[center + channel];
+                let log = laplacian * normalization;
+
+                let mean = blurred[center + channel];
+                let std_dev = (blurred_squares[center + channel] - mean * mean)
+                    .max(0.0)
+                    .sqrt();
+                if log.abs() < strength * std_dev {
+                    continue;
+                }
+
+                let mut delta = -log * strength;
+                if reduce_halos && delta < 0.0 {
+                    let darkest = neighbours
+                        .iter()
+                        .map(|&n| original[n + channel])
+                        .fold(original[center + channel], f32::min);
+                    delta = delta.max(darkest - original[center + channel]);
+                }
+                *value = original[center + channel] + delta;
+            }
+        }
+    }
+}
+
+/// Peak ACEScg intensity of the brightest stars
+const STAR_MAX_BRIGHTNESS: f32 = 50.0;
+/// Stars dimmer than this are a single pixel, brighter ones an anti-aliased disk
+const STAR_DISK_BRIGHTNESS: f32 = 2.0;
+/// Standard deviation, in pixels, of the glow around bright stars
+const STAR_BLOOM_SIGMA: f32 = 2.5;
+
+/// Parameters of the star field render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StarfieldSettings {
+    pub num_stars: usize,
+    pub seed: u64,
+    // Higher values give fewer bright stars
+    pub magnitude_exponent: f32,
+    // Luminance above which stars get a bloom
+    pub bloom_threshold: f32,
+}
+
+impl Default for StarfieldSettings {
+    fn default() -> Self {
+        Self {
+            num_stars: 2000,
+            seed: 0,
+            magnitude_exponent: 8.0,
+            bloom_threshold: 5.0,
+        }
+    }
+}
+
+/// Fill the buffer with a black sky and `num_stars` randomly placed point lights.
+/// Brightness follows a power law (`(1 - u) ^ magnitude_exponent`), so most stars are dim.
+/// Stars brighter than `bloom_threshold` get a Gaussian glow.
+pub fn render_starfield(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    num_stars: usize,
+    seed: u64,
+    magnitude_exponent: f32,
+    bloom_threshold: f32,
+) {
+    let pixels = &mut buffer[..width * height * 4];
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&[0.0, 0.0, 0.0, 1.0]);
+    }
+
+    let mut rng = SplitMix64::new(seed);
+    for _ in 0..num_stars {
+        let position = [
+            rng.next_f32() * width as f32,
+            rng.next_f32() * height as f32,
+        ];
+        let brightness = (1.0 - rng.next_f32()).powf(magnitude_exponent) * STAR_MAX_BRIGHTNESS;
+        // Slight temperature variation, from orange to blue
+        let tint = color::acescg::<Scene>(1.0, 0.75, 0.5)
+            .blend(color::acescg::<Scene>(0.7, 0.8, 1.0), rng.next_f32());
+        let star = [
+            tint.r * brightness,
+            tint.g * brightness,
+            tint.b * brightness,
+        ];
+
+        if brightness < STAR_DISK_BRIGHTNESS {
+            let index = (position[1] as usize * width + position[0] as usize) * 4;
+            for (channel, value) in pixels[index..index + 3].iter_mut().zip(star) {
+                *channel += value;
+            }
+            continue;
+        }
+
+        // Anti-aliased disk, only touching the pixels around the star
+        let reach = (brightness / STAR_DISK_BRIGHTNESS).sqrt() * 0.5 + 0.5;
+        let min_x = (position[0] - reach).floor().max(0.0) as usize;
+        let min_y = (position[1] - reach).floor().max(0.0) as usize;
+        let max_x = ((position[0] + reach).ceil() as usize).min(width);
+        let max_y = ((position[1] + reach).ceil() as usize).min(height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let distance = (x as f32 + 0.5 - position[0]).hypot(y as f32 + 0.5 - position[1]);
+                let covered = (reach - distance).clamp(0.0, 1.0);
+                let index = (y * width + x) * 4;
+                for (channel, value) in pixels[index..index + 3].iter_mut().zip(star) {
+                    *channel += value * covered;
+                }
+            }
+        }
+    }
+
+    // Blur a copy holding only the brightest pixels, then add it back as glow
+    let mut bloom: Vec<f32> = pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            if acescg_luminance([pixel[0], pixel[1], pixel[2]]) > bloom_threshold {
+                [pixel[0], pixel[1], pixel[2], 0.0]
+            } else {
+                [0.0; 4]
+            }
+        })
+        .collect();
+    apply_gaussian_blur(&mut bloom, width, height, STAR_BLOOM_SIGMA);
+    for (pixel, glow) in pixels.chunks_exact_mut(4).zip(bloom.chunks_exact(4)) {
+        for (channel, value) in pixel.iter_mut().zip(&glow[..3]) {
+            *channel += value;
+        }
+    }
+}
+
+/// First wavelength, in nm, of the color matching function tables
+const CIE_LAMBDA_START: f32 = 380.0;
+/// Spacing, in nm, of the color matching function tables
+const CIE_LAMBDA_STEP: f32 = 10.0;
+/// CIE 1931 2° standard observer color matching functions `(x_bar, y_bar, z_bar)`,
+/// from 380 to 780 nm every 10 nm (CIE 15:2004)
+const CIE_1931_CMF: [[f32; 3]; 41] = [
+    [0.001368, 0.000039, 0.006450],
+    [0.004243, 0.000120, 0.020050],
+    [0.014310, 0.000396, 0.067850],
+    [0.043510, 0.001210, 0.207400],
+    [0.134380, 0.004000, 0.645600],
+    [0.283900, 0.011600, 1.385600],
+    [0.348280, 0.023000, 1.747060],
+    [0.336200, 0.038000, 1.772110],
+    [0.290800, 0.060000, 1.669200],
+    [0.195360, 0.090980, 1.287640],
+    [0.095640, 0.139020, 0.812950],
+    [0.032010, 0.208020, 0.465180],
+    [0.004900, 0.323000, 0.272000],
+    [0.009300, 0.503000, 0.158200],
+    [0.063270, 0.710000, 0.078250],
+    [0.165500, 0.862000, 0.042160],
+    [0.290400, 0.954000, 0.020300],
+    [0.433450, 0.994950, 0.008750],
+    [0.594500, 0.995000, 0.003900],
+    [0.762100, 0.952000, 0.002100],
+    [0.916300, 0.870000, 0.001650],
+    [1.026300, 0.757000, 0.001100],
+    [1.062200, 0.631000, 0.000800],
+    [1.002600, 0.503000, 0.000340],
+    [0.854450, 0.381000, 0.000190],
+    [0.642400, 0.265000, 0.000050],
+    [0.447900, 0.175000, 0.000020],
+    [0.283500, 0.107000, 0.000000],
+    [0.164900, 0.061000, 0.000000],
+    [0.087400, 0.032000, 0.000000],
+    [0.046770, 0.017000, 0.000000],
+    [0.022700, 0.008210, 0.000000],
+    [0.011359, 0.004102, 0.000000],
+    [0.005790, 0.002091, 0.000000],
+    [0.002899, 0.001047, 0.000000],
+    [0.001440, 0.000520, 0.000000],
+    [0.000690, 0.000249, 0.000000],
+    [0.000332, 0.000120, 0.000000],
+    [0.000166, 0.000060, 0.000000],
+    [0.000083, 0.000030, 0.000000],
+    [0.000042, 0.000015, 0.000000],
+];
+
+/// CIE XYZ (D65) to ACEScg, with a Bradford chromatic adaptation to the ACES white point
+const XYZ_TO_ACESCG: [[f32; 3]; 3] = [
+    [1.641_023, -0.324_803_3, -0.236_424_7],
+    [-0.663_663, 1.615_332, 0.016_756_3],
+    [0.011_721_9, -0.008_284_4, 0.988_394_9],
+];
+
+/// Number of wavelengths integrated over the band covered by each pixel
+const SPECTRAL_SAMPLES_PER_PIXEL: usize = 4;
+
+/// Color matching functions at `lambda` nm, linearly interpolated between the table entries
+/// and zero outside of the table
+pub fn cie_1931_cmf(lambda: f32) -> [f32; 3] {
+    let position = (lambda - CIE_LAMBDA_START) / CIE_LAMBDA_STEP;
+    if !(0.0..=(CIE_1931_CMF.len() - 1) as f32).contains(&position) {
+        return [0.0; 3];
+    }
+    let low = position.floor() as usize;
+    let high = (low + 1).min(CIE_1931_CMF.len() - 1);
+    let t = position - low as f32;
+    std::array::from_fn(|i| {
+        CIE_1931_CMF[low][i] + (CIE_1931_CMF[high][i] - CIE_1931_CMF[low][i]) * t
+    })
+}
+
+/// Render the visible spectrum, from `lambda_lo` nm on the left to `lambda_hi` nm on the right.
+/// Each column integrates the color matching functions over the band of wavelengths it covers.
+/// Only the pixels in `region` are written, see `render_bg_image`.
+pub fn render_spectral_gradient(
+    buffer: &mut [f32],
+    width: usize,
+    region: [usize; 4],
+    lambda_lo: f32,
+    lambda_hi: f32,
+) {
+    let band = (lambda_hi - lambda_lo) / width as f32;
+    let columns: Vec<[f32; 3]> = (0..width)
+        .map(|x| {
+            let mut xyz = [0.0_f32; 3];
+            for sample in 0..SPECTRAL_SAMPLES_PER_PIXEL {
+                let offset = (sample as f32 + 0.5) / SPECTRAL_SAMPLES_PER_PIXEL as f32;
+                let cmf = cie_1931_cmf(lambda_lo + (x as f32 + offset) * band);
+                for (total, value) in xyz.iter_mut().zip(cmf) {
+                    *total += value / SPECTRAL_SAMPLES_PER_PIXEL as f32;
+                }
+            }
+            // The most saturated wavelengths fall outside of AP1, clip them to the gamut
+            mul_mat3(&XYZ_TO_ACESCG, xyz).map(|c| c.max(0.0))
+        })
+        .collect();
+
+    for (x, _, pixel) in region_pixels_mut(buffer, width, region) {
+        let rgb = columns[x];
+        pixel.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 1.0]);
+    }
+}
+
+/// 2D affine map: `x' = a * x + b * y + c`, `y' = d * x + e * y + f`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AffineTransform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl AffineTransform {
+    pub fn apply(&self, p: [f32; 2]) -> [f32; 2] {
+        [
+            self.a * p[0] + self.b * p[1] + self.c,
+            self.d * p[0] + self.e * p[1] + self.f,
+        ]
+    }
+}
+
+/// Non-linear function applied after the affine part of every transform
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FlameVariation {
+    Linear,
+    Sinusoidal,
+    Spherical,
+}
+
+impl FlameVariation {
+    pub fn apply(&self, p: [f32; 2]) -> [f32; 2] {
+        match self {
+            FlameVariation::Linear => p,
+            FlameVariation::Sinusoidal => [p[0].sin(), p[1].sin()],
+            FlameVariation::Spherical => {
+                let r2 = (p[0] * p[0] + p[1] * p[1]).max(1e-6);
+                [p[0] / r2, p[1] / r2]
+            }
+        }
+    }
+}
+
+/// One function of an iterated function system
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FlameTransform {
+    pub affine: AffineTransform,
+    // Relative chance of this transform being picked, normalized over the system
+    pub probability: f32,
+    // Position in the palette, in [0, 1]
+    pub color_index: f32,
+}
+
+/// Iterated function system rendered by the fractal flame mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FractalFlameSystem {
+    pub transforms: Vec<FlameTransform>,
+    pub variation: FlameVariation,
+}
+
+impl FractalFlameSystem {
+    /// Sierpinski triangle
+    pub fn linear_preset() -> Self {
+        let corner = |c: f32, f: f32, color_index: f32| FlameTransform {
+            affine: AffineTransform {
+                a: 0.5,
+                b: 0.0,
+                c,
+                d: 0.0,
+                e: 0.5,
+                f,
+            },
+            probability: 1.0,
+            color_index,
+        };
+        Self {
+            transforms: vec![
+                corner(0.0, 0.5, 0.0),
+                corner(-0.5, -0.5, 0.5),
+                corner(0.5, -0.5, 1.0),
+            ],
+            variation: FlameVariation::Linear,
+        }
+    }
+
+    pub fn sinusoidal_preset() -> Self {
+        Self {
+            transforms: vec![
+                FlameTransform {
+                    affine: AffineTransform {
+                        a: 0.8,
+                        b: -0.6,
+                        c: 0.3,
+                        d: 0.6,
+                        e: 0.8,
+                        f: -0.2,
+                    },
+                    probability: 2.0,
+                    color_index: 0.1,
+                },
+                FlameTransform {
+                    affine: AffineTransform {
+                        a: -1.2,
+                        b: 0.4,
+                        c: 0.5,
+                        d: -0.3,
+                        e: -1.1,
+                        f: 0.4,
+                    },
+                    probability: 1.0,
+                    color_index: 0.9,
+                },
+            ],
+            variation: FlameVariation::Sinusoidal,
+        }
+    }
+
+    pub fn spherical_preset() -> Self {
+        Self {
+            transforms: vec![
+                FlameTransform {
+                    affine: AffineTransform {
+                        a: 0.56,
+                        b: -0.32,
+                        c: 0.6,
+                        d: 0.32,
+                        e: 0.56,
+                        f: 0.0,
+                    },
+                    probability: 1.0,
+                    color_index: 0.0,
+                },
+                FlameTransform {
+                    affine: AffineTransform {
+                        a: 0.56,
+                        b: 0.32,
+                        c: -0.6,
+                        d: -0.32,
+                        e: 0.56,
+                        f: 0.0,
+                    },
+                    probability: 1.0,
+                    color_index: 0.5,
+                },
+                FlameTransform {
+                    affine: AffineTransform {
+                        a: -0.4,
+                        b: 0.0,
+                        c: 0.0,
+                        d: 0.0,
+                        e: -0.4,
+                        f: 0.5,
+                    },
+                    probability: 0.5,
+                    color_index: 1.0,
+                },
+            ],
+            variation: FlameVariation::Spherical,
+        }
+    }
+}
+
+/// Parameters of the fractal flame render mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FractalFlameSettings {
+    pub system: FractalFlameSystem,
+    pub iterations: u64,
+    // Density samples per pixel side
+    pub supersample: u32,
+}
+
+impl Default for FractalFlameSettings {
+    fn default() -> Self {
+        Self {
+            system: FractalFlameSystem::linear_preset(),
+            iterations: 1_000_000,
+            supersample: 2,
+        }
+    }
+}
+
+/// Iterations run before plotting, so the point has settled on the attractor
+const FLAME_SETTLE_ITERATIONS: u64 = 20;
+/// Gamma applied to the log-density
+const FLAME_GAMMA: f32 = 2.2;
+
+/// Palette of the fractal flame, `t` in [0, 1]
+fn flame_palette(t: f32) -> [f32; 3] {
+    let cold = color::acescg::<Scene>(0.05, 0.2, 1.0);
+    let warm = color::acescg::<Scene>(1.0, 0.35, 0.05);
+    let hot = color::acescg::<Scene>(1.0, 0.9, 0.5);
+    let rgb = if t < 0.5 {
+        cold.blend(warm, t * 2.0)
+    } else {
+        warm.blend(hot, t * 2.0 - 1.0)
+    };
+    [rgb.r, rgb.g, rgb.b]
+}
+
+/// Render an iterated function system with the fractal flame algorithm (Draves & Reckase).
+/// The chaos game accumulates hit counts and average colors in a supersampled density
+/// buffer, which is then tonemapped by the log of the density and gamma corrected.
+/// The visible region of the plane is [-1, 1] on both axes.
+pub fn render_fractal_flame(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    system: &FractalFlameSystem,
+    iterations: u64,
+    supersample: u32,
+) {
+    let supersample = supersample.max(1) as usize;
+    let (density_width, density_height) = (width * supersample, height * supersample);
+    let mut density_buffer: Vec<(u32, [f32; 3])> =
+        vec![(0, [0.0; 3]); density_width * density_height];
+
+    let total_probability: f32 = system
+        .transforms
+        .iter()
+        .map(|t| t.probability.max(0.0))
+        .sum();
+    if total_probability > 0.0 {
+        let mut rng = SplitMix64::new(0);
+        let mut point = [rng.next_f32() * 2.0 - 1.0, rng.next_f32() * 2.0 - 1.0];
+        let mut color_index = 0.5;
+        for i in 0..iterations + FLAME_SETTLE_ITERATIONS {
+            // Pick a transform according to its probability
+            let mut choice = rng.next_f32() * total_probability;
+            let transform = system
+                .transforms
+                .iter()
+                .find(|t| {
+                    choice -= t.probability.max(0.0);
+                    choice < 0.0
+                })
+                .unwrap_or(&system.transforms[system.transforms.len() - 1]);
+
+            point = system.variation.apply(transform.affine.apply(point));
+            color_index = (color_index + transform.color_index) * 0.5;
+            if !point[0].is_finite() || !point[1].is_finite() {
+                point = [rng.next_f32() * 2.0 - 1.0, rng.next_f32() * 2.0 - 1.0];
+                continue;
+            }
+            if i < FLAME_SETTLE_ITERATIONS {
+                continue;
+            }
+
+            let x = (point[0] * 0.5 + 0.5) * density_width as f32;
+            // Rows are stored top first
+            let y = (0.5 - point[1] * 0.5) * density_height as f32;
+            if x < 0.0 || y < 0.0 || x >= density_width as f32 || y >= density_height as f32 {
+                continue;
+            }
+            let (count, color) = &mut density_buffer[y as usize * density_width + x as usize];
+            *count += 1;
+            for (channel, value) in color.iter_mut().zip(flame_palette(color_index)) {
+                *channel += value;
+            }
+        }
+    }
+
+    // Average each block of supersamples down to a pixel
+    let pixels: Vec<(u32, [f32; 3])> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let mut total = (0, [0.0_f32; 3]);
+            for sy in 0..supersample {
+                let row = (y * supersample + sy) * density_width;
+                for (count, color) in
+                    &density_buffer[row + x * supersample..row + (x + 1) * supersample]
+                {
+                    total.0 += count;
+                    for (channel, value) in total.1.iter_mut().zip(color) {
+                        *channel += value;
+                    }
+                }
+            }
+            total
+        })
+        .collect();
+
+    let max_log_density = pixels
+        .iter()
+        .map(|(count, _)| (*count as f32).ln_1p())
+        .fold(0.0_f32, f32::max);
+    for (pixel, (count, color)) in buffer.chunks_exact_mut(4).zip(pixels) {
+        if count == 0 || max_log_density <= 0.0 {
+            pixel.copy_from_slice(&[0.0, 0.0, 0.0, 1.0]);
+            continue;
+        }
+        let alpha = (count as f32).ln_1p() / max_log_density;
+        let brightness = alpha.powf(1.0 / FLAME_GAMMA) / count as f32;
+        pixel.copy_from_slice(&[
+            color[0] * brightness,
+            color[1] * brightness,
+            color[2] * brightness,
+            1.0,
+        ]);
+    }
+}
+
+/// How a noise layer is combined with what's already in the framebuffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoiseBlendMode {
+    /// Clear and write
+    Replace,
+    Add,
+    Multiply,
+    Screen,
+}
+
+impl NoiseBlendMode {
+    pub fn blend(&self, base: f32, noise: f32) -> f32 {
+        match self {
+            NoiseBlendMode::Replace => noise,
+            NoiseBlendMode::Add => base + noise,
+            NoiseBlendMode::Multiply => base * noise,
+            NoiseBlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - noise),
+        }
+    }
+}
+
+/// Parameters of the value noise render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ValueNoiseSettings {
+    // Lattice cells across the width of the image, for the first octave
+    pub frequency: f32,
+    pub octaves: u32,
+    pub seed: u64,
+    pub blend_mode: NoiseBlendMode,
+}
+
+impl Default for ValueNoiseSettings {
+    fn default() -> Self {
+        Self {
+            frequency: 8.0,
+            octaves: 4,
+            seed: 0,
+            blend_mode: NoiseBlendMode::Replace,
+        }
+    }
+}
+
+/// Random value in [0, 1) attached to a lattice point
+fn lattice_value(x: i32, y: i32, seed: u64) -> f32 {
+    let coordinates = ((x as u32 as u64) << 32) | y as u32 as u64;
+    SplitMix64::new(seed ^ coordinates.wrapping_mul(0x9E37_79B9_7F4A_7C15)).next_f32()
+}
+
+/// Single octave of value noise at `p`, in lattice units
+fn value_noise(p: [f32; 2], seed: u64) -> f32 {
+    let cell = [p[0].floor(), p[1].floor()];
+    let (x, y) = (cell[0] as i32, cell[1] as i32);
+    // Smoothed weights hide the lattice grid
+    let tx = smoothstep(0.0, 1.0, p[0] - cell[0]);
+    let ty = smoothstep(0.0, 1.0, p[1] - cell[1]);
+
+    let top = lattice_value(x, y, seed)
+        + (lattice_value(x + 1, y, seed) - lattice_value(x, y, seed)) * tx;
+    let bottom = lattice_value(x, y + 1, seed)
+        + (lattice_value(x + 1, y + 1, seed) - lattice_value(x, y + 1, seed)) * tx;
+    top + (bottom - top) * ty
+}
+
+/// Fractal value noise: random values on a lattice, interpolated bilinearly.
+/// Every octave doubles the frequency and halves the amplitude, the sum stays in [0, 1].
+/// The noise is composited on top of the existing content of `buffer` with `blend_mode`,
+/// only in `region` (see `render_bg_image`).
+pub fn render_value_noise(
+    buffer: &mut [f32],
+    width: usize,
+    region: [usize; 4],
+    frequency: f32,
+    octaves: u32,
+    seed: u64,
+    blend_mode: NoiseBlendMode,
+) {
+    let octaves = octaves.max(1);
+    let normalization: f32 = (0..octaves).map(|octave| 0.5_f32.powi(octave as i32)).sum();
+    let cell_size = width as f32 / frequency.max(f32::EPSILON);
+
+    for (x, y, pixel) in region_pixels_mut(buffer, width, region) {
+        let p = [x as f32 / cell_size, y as f32 / cell_size];
+        let noise = (0..octaves)
+            .map(|octave| {
+                let scale = (1 << octave) as f32;
+                value_noise(
+                    [p[0] * scale, p[1] * scale],
+                    seed.wrapping_add(octave as u64),
+                ) / scale
+            })
+            .sum::<f32>()
+            / normalization;
+
+        for channel in &mut pixel[..3] {
+            *channel = blend_mode.blend(*channel, noise);
+        }
+        if blend_mode == NoiseBlendMode::Replace {
+            pixel[3] = 1.0;
+        }
+    }
+}
+
+/// Preetham et al. "A Practical Analytic Model for Daylight" (1999).
+/// Perez distribution coefficients `A` to `E` for the luminance `Y` and the chromaticity
+/// `x` and `y`, each a linear function of the turbidity: `[slope, intercept]`
+const PREETHAM_PEREZ_COEFFICIENTS: [[[f32; 2]; 5]; 3] = [
+    // Y
+    [
+        [0.1787, -1.4630],
+        [-0.3554, 0.4275],
+        [-0.0227, 5.3251],
+        [0.1206, -2.5771],
+        [-0.0670, 0.3703],
+    ],
+    // x
+    [
+        [-0.0193, -0.2592],
+        [-0.0665, 0.0008],
+        [-0.0004, 0.2125],
+        [-0.0641, -0.8989],
+        [-0.0033, 0.0452],
+    ],
+    // y
+    [
+        [-0.0167, -0.2608],
+        [-0.0950, 0.0092],
+        [-0.0079, 0.2102],
+        [-0.0441, -1.6537],
+        [-0.0109, 0.0529],
+    ],
+];
+
+/// Zenith chromaticity polynomials, `T^2`, `T` and constant rows,
+/// each multiplied by `[theta_s^3, theta_s^2, theta_s, 1]`
+const PREETHAM_ZENITH_X: [[f32; 4]; 3] = [
+    [0.00166, -0.00375, 0.00209, 0.0],
+    [-0.02903, 0.06377, -0.03202, 0.00394],
+    [0.11693, -0.21196, 0.06052, 0.25886],
+];
+const PREETHAM_ZENITH_Y: [[f32; 4]; 3] = [
+    [0.00275, -0.00610, 0.00317, 0.0],
+    [-0.04214, 0.08970, -0.04153, 0.00516],
+    [0.15346, -0.26756, 0.06670, 0.26688],
+];
+
+/// Converts the model luminance (kcd/m^2) to framebuffer values
+const SKY_LUMINANCE_SCALE: f32 = 0.1;
+/// Luminance of the sun disk, before `SKY_LUMINANCE_SCALE`
+const SUN_LUMINANCE: f32 = 20000.0;
+/// Angular radius of the sun disk. The real sun is about 0.27°,
+/// it's enlarged so it covers a few pixels at the framebuffer resolution.
+const SUN_ANGULAR_RADIUS: f32 = 2.0 * std::f32::consts::PI / 180.0;
+
+/// Parameters of the sky render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SkySettings {
+    // Degrees above the horizon
+    pub sun_elevation: f32,
+    // Degrees
+    pub sun_azimuth: f32,
+    // Haziness of the atmosphere, 1 is a perfectly clear sky
+    pub turbidity: f32,
+    pub ground_albedo: [f32; 3],
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            sun_elevation: 30.0,
+            sun_azimuth: 180.0,
+            turbidity: 3.0,
+            ground_albedo: [0.2, 0.2, 0.2],
+        }
+    }
+}
+
+/// Perez sky distribution for a view at `theta` from the zenith and `gamma` from the sun
+fn perez(coefficients: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / theta.cos().max(0.01)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+/// Evaluate one of the zenith chromaticity polynomials
+fn zenith_chromaticity(polynomial: &[[f32; 4]; 3], turbidity: f32, theta_s: f32) -> f32 {
+    let powers = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+    let row = |i: usize| -> f32 { polynomial[i].iter().zip(powers).map(|(k, p)| k * p).sum() };
+    turbidity * turbidity * row(0) + turbidity * row(1) + row(2)
+}
+
+/// Render an analytic daylight sky (Preetham model) as an equirectangular panorama.
+/// The upper half of the image is the sky, the lower half the ground, shown
+/// with a constant `ground_albedo` lit by the zenith luminance.
+/// Angles are in degrees. Only the pixels in `region` are written, see `render_bg_image`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_sky(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    sun_elevation: f32,
+    sun_azimuth: f32,
+    turbidity: f32,
+    ground_albedo: [f32; 3],
+) {
+    use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+    let theta_s = FRAC_PI_2 - sun_elevation.clamp(0.0, 90.0).to_radians();
+    let phi_s = sun_azimuth.to_radians();
+    let sun = [
+        theta_s.sin() * phi_s.cos(),
+        theta_s.cos(),
+        theta_s.sin() * phi_s.sin(),
+    ];
+
+    let coefficients: [[f32; 5]; 3] = PREETHAM_PEREZ_COEFFICIENTS
+        .map(|channel| channel.map(|[slope, intercept]| slope * turbidity + intercept));
+    let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * theta_s);
+    let zenith = [
+        ((4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192).max(0.0),
+        zenith_chromaticity(&PREETHAM_ZENITH_X, turbidity, theta_s),
+        zenith_chromaticity(&PREETHAM_ZENITH_Y, turbidity, theta_s),
+    ];
+    // The distribution is relative to its value at the zenith
+    let at_zenith: [f32; 3] = std::array::from_fn(|i| perez(&coefficients[i], 0.0, theta_s));
+    let ground = ground_albedo.map(|albedo| albedo * zenith[0] * SKY_LUMINANCE_SCALE);
+
+    let [x0, y0, x1, y1] = region;
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        // Rows are stored top first, from the zenith down to the nadir
+        let theta = (y as f32 + 0.5) / height as f32 * PI;
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            if theta > FRAC_PI_2 {
+                pixel.copy_from_slice(&[ground[0], ground[1], ground[2], 1.0]);
+                continue;
+            }
+
+            let phi = (x as f32 + 0.5) / width as f32 * TAU;
+            let direction = [
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            ];
+            let cos_gamma = direction[0] * sun[0] + direction[1] * sun[1] + direction[2] * sun[2];
+            let gamma = cos_gamma.clamp(-1.0, 1.0).acos();
+
+            let [luminance, chroma_x, chroma_y]: [f32; 3] = std::array::from_fn(|i| {
+                zenith[i] * perez(&coefficients[i], theta, gamma) / at_zenith[i]
+            });
+            let luminance = if gamma < SUN_ANGULAR_RADIUS {
+                SUN_LUMINANCE
+            } else {
+                luminance
+            } * SKY_LUMINANCE_SCALE;
+
+            // xyY to XYZ
+            let xyz = [
+                chroma_x / chroma_y * luminance,
+                luminance,
+                (1.0 - chroma_x - chroma_y) / chroma_y * luminance,
+            ];
+            let rgb = mul_mat3(&XYZ_TO_ACESCG, xyz).map(|c| c.max(0.0));
+            pixel.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 1.0]);
+        }
+    }
+}
+
+/// Linear sRGB (Rec. 709 primaries, D65) to ACEScg, Bradford adapted
+pub const LINEAR_SRGB_TO_ACESCG: [[f32; 3]; 3] = [
+    [0.613_097_4, 0.339_523_1, 0.047_379_5],
+    [0.070_193_7, 0.916_353_9, 0.013_452_4],
+    [0.020_615_6, 0.109_569_8, 0.869_814_7],
+];
+
+/// Convert an 8 bit sRGB color, as picked in the GUI, to ACEScg
+pub fn srgb_u8_to_acescg(rgb: [u8; 3]) -> [f32; 3] {
+    mul_mat3(
+        &LINEAR_SRGB_TO_ACESCG,
+        rgb.map(|c| srgb_to_linear(c as f32 / 255.0)),
+    )
+}
+
+/// Largest 3D LUT read from a `.cube` file, in nodes per axis
+pub const MAX_LUT_SIZE: usize = 129;
+
+/// 3D color lookup table, as stored in `.cube` files
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3d {
+    // Nodes per axis, at least 2
+    pub size: usize,
+    // Input colors mapped to the first and last nodes
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    // Output colors, red changing fastest, then green, then blue, like in `.cube` files
+    pub table: Vec<[f32; 3]>,
+}
+
+impl Lut3d {
+    /// LUT leaving the colors unchanged
+    pub fn identity(size: usize) -> Self {
+        let size = size.clamp(2, MAX_LUT_SIZE);
+        let step = 1.0 / (size - 1) as f32;
+        let table = (0..size * size * size)
+            .map(|i| {
+                [
+                    (i % size) as f32 * step,
+                    (i / size % size) as f32 * step,
+                    (i / (size * size)) as f32 * step,
+                ]
+            })
+            .collect();
+        Self {
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table,
+        }
+    }
+
+    /// Output color of the node at `index`, the node numbers along red, green and blue
+    pub fn node(&self, index: [usize; 3]) -> [f32; 3] {
+        self.table[(index[2] * self.size + index[1]) * self.size + index[0]]
+    }
+
+    pub fn set_node(&mut self, index: [usize; 3], value: [f32; 3]) {
+        self.table[(index[2] * self.size + index[1]) * self.size + index[0]] = value;
+    }
+
+    /// Look up `rgb`, interpolating the 8 closest nodes. Colors outside of the domain are
+    /// clamped to it.
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        let coordinates: [f32; 3] = std::array::from_fn(|c| {
+            let range = self.domain_max[c] - self.domain_min[c];
+            let t = if range > 0.0 {
+                (rgb[c] - self.domain_min[c]) / range
+            } else {
+                0.0
+            };
+            t.clamp(0.0, 1.0) * last
+        });
+        let base = coordinates.map(|x| (x.floor() as usize).min(self.size - 2));
+        let t: [f32; 3] = std::array::from_fn(|c| coordinates[c] - base[c] as f32);
+
+        let mut output = [0.0; 3];
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, corner >> 2];
+            let weight: f32 = (0..3)
+                .map(|c| if offset[c] == 1 { t[c] } else { 1.0 - t[c] })
+                .product();
+            let node = self.node(std::array::from_fn(|c| base[c] + offset[c]));
+            for (value, node) in output.iter_mut().zip(node) {
+                *value += node * weight;
+            }
+        }
+        output
+    }
+
+    /// Parse the text of a `.cube` file. Keywords other than the size and the domain are
+    /// skipped, 1D LUTs aren't supported.
+    pub fn from_cube(text: &str) -> Result<Self, ImageError> {
+        let invalid = |message: String| ImageError::Decode { message };
+        let parse_triplet = |words: &[&str]| -> Result<[f32; 3], ImageError> {
+            match words {
+                [r, g, b] => {
+                    let parse = |word: &str| {
+                        word.parse::<f32>()
+                            .map_err(|_| invalid(format!("Invalid number '{word}' in .cube file")))
+                    };
+                    Ok([parse(r)?, parse(g)?, parse(b)?])
+                }
+                _ => Err(invalid(format!(
+                    "Expected 3 values in .cube file, got '{}'",
+                    words.join(" ")
+                ))),
+            }
+        };
+
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if !words[0].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                table.push(parse_triplet(&words)?);
+                continue;
+            }
+            match words[0] {
+                "LUT_3D_SIZE" => {
+                    size = words.get(1).and_then(|word| word.parse::<usize>().ok());
+                }
+                "DOMAIN_MIN" => domain_min = parse_triplet(&words[1..])?,
+                "DOMAIN_MAX" => domain_max = parse_triplet(&words[1..])?,
+                "LUT_1D_SIZE" => {
+                    return Err(ImageError::UnsupportedFormat {
+                        format: "1D .cube LUT".to_owned(),
+                    })
+                }
+                // TITLE, and the keywords of other applications
+                _ => {}
+            }
+        }
+
+        let size = size
+            .filter(|size| (2..=MAX_LUT_SIZE).contains(size))
+            .ok_or_else(|| invalid("Missing or invalid LUT_3D_SIZE in .cube file".to_owned()))?;
+        if table.len() != size * size * size {
+            return Err(ImageError::BufferSizeMismatch {
+                expected: size * size * size,
+                actual: table.len(),
+            });
+        }
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// Text of the `.cube` file of this LUT
+    pub fn to_cube(&self) -> String {
+        let mut text = format!(
+            "TITLE \"{EXIF_SOFTWARE}\"\nLUT_3D_SIZE {}\nDOMAIN_MIN {} {} {}\nDOMAIN_MAX {} {} {}\n",
+            self.size,
+            self.domain_min[0],
+            self.domain_min[1],
+            self.domain_min[2],
+            self.domain_max[0],
+            self.domain_max[1],
+            self.domain_max[2],
+        );
+        for [r, g, b] in &self.table {
+            text.push_str(&format!("{r:.6} {g:.6} {b:.6}\n"));
+        }
+        text
+    }
+}
+
+pub fn read_cube_file(path: impl AsRef<Path>) -> Result<Lut3d, ImageError> {
+    Lut3d::from_cube(&std::fs::read_to_string(path)?)
+}
+
+pub fn write_cube_file(path: impl AsRef<Path>, lut: &Lut3d) -> Result<(), ImageError> {
+    std::fs::write(&path, lut.to_cube())?;
+    info!("Successfully saved LUT to {}", path.as_ref().display());
+    Ok(())
+}
+
+/// Gradient at a lattice point of the Perlin noise, a random unit vector
+fn lattice_gradient(x: i32, y: i32, seed: u64) -> [f32; 2] {
+    let angle = lattice_value(x, y, seed) * std::f32::consts::TAU;
+    [angle.cos(), angle.sin()]
+}
+
+/// 2D Perlin gradient noise at `p`, in lattice units. Returns values in about [-1, 1].
+pub fn perlin_noise(p: [f32; 2], seed: u64) -> f32 {
+    let cell = [p[0].floor(), p[1].floor()];
+    let (x, y) = (cell[0] as i32, cell[1] as i32);
+    let offset = [p[0] - cell[0], p[1] - cell[1]];
+
+    let corner = |cx: i32, cy: i32| {
+        let gradient = lattice_gradient(x + cx, y + cy, seed);
+        gradient[0] * (offset[0] - cx as f32) + gradient[1] * (offset[1] - cy as f32)
+    };
+    let tx = smootherstep(0.0, 1.0, offset[0]);
+    let ty = smootherstep(0.0, 1.0, offset[1]);
+    let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * tx;
+    let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * tx;
+    // The extrema of 2D Perlin noise are at +-sqrt(0.5)
+    (top + (bottom - top) * ty) * std::f32::consts::SQRT_2
+}
+
+/// How the turbulence value becomes a pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurbulencePattern {
+    /// The noise value itself
+    Noise,
+    /// Veins from `sin(x + turbulence)`
+    Marble,
+}
+
+/// How much the turbulence distorts the marble veins
+const MARBLE_DISTORTION: f32 = 6.0;
+
+/// Parameters of the turbulence render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TurbulenceSettings {
+    // Lattice cells across the width of the image, for the first octave
+    pub frequency: f32,
+    pub octaves: u32,
+    pub seed: u64,
+    // Sum the absolute value of each octave, instead of plain FBM
+    pub turbulent: bool,
+    pub pattern: TurbulencePattern,
+    // Exponent applied to the final value
+    pub contrast: f32,
+    pub basis: NoiseBasis,
+}
+
+impl Default for TurbulenceSettings {
+    fn default() -> Self {
+        Self {
+            frequency: 4.0,
+            octaves: 6,
+            seed: 0,
+            turbulent: true,
+            pattern: TurbulencePattern::Noise,
+            contrast: 1.0,
+            basis: NoiseBasis::Perlin,
+        }
+    }
+}
+
+/// Where the simplex noise of a seed is read, it has no seed of its own
+fn simplex_seed_offset(seed: u64) -> [f32; 2] {
+    let mut rng = SplitMix64::new(seed);
+    [rng.next_f32() * 1024.0, rng.next_f32() * 1024.0]
+}
+
+/// Noise summed over `octaves`, each one at twice the frequency and half the amplitude.
+/// With `turbulent` each octave contributes its absolute value, `T = sum(|noise(2^k p)| / 2^k)`,
+/// giving a value in [0, 1], otherwise it's a regular FBM in about [-1, 1].
+pub fn turbulence(p: [f32; 2], octaves: u32, seed: u64, turbulent: bool, basis: NoiseBasis) -> f32 {
+    let octaves = octaves.max(1);
+    if basis == NoiseBasis::Simplex && !turbulent {
+        let offset = simplex_seed_offset(seed);
+        return fbm_simplex(p[0] + offset[0], p[1] + offset[1], octaves, 0.5, 2.0);
+    }
+    let normalization: f32 = (0..octaves).map(|octave| 0.5_f32.powi(octave as i32)).sum();
+    let sum: f32 = (0..octaves)
+        .map(|octave| {
+            let scale = (1 << octave) as f32;
+            let octave_seed = seed.wrapping_add(octave as u64);
+            let noise = match basis {
+                NoiseBasis::Perlin => perlin_noise([p[0] * scale, p[1] * scale], octave_seed),
+                NoiseBasis::Simplex => {
+                    let offset = simplex_seed_offset(octave_seed);
+                    simplex_noise_2d(p[0] * scale + offset[0], p[1] * scale + offset[1])
+                }
+            };
+            if turbulent {
+                noise.abs() / scale
+            } else {
+                noise / scale
+            }
+        })
+        .sum();
+    sum / normalization
+}
+
+/// Render Perlin or simplex turbulence (or plain FBM when `turbulent` is false),
+/// shaped by `pattern` and `contrast`, and mapped from `colors[0]` to `colors[1]`.
+/// Only the pixels in `region` are written, see `render_bg_image`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_turbulence(
+    buffer: &mut [f32],
+    width: usize,
+    region: [usize; 4],
+    frequency: f32,
+    octaves: u32,
+    seed: u64,
+    turbulent: bool,
+    pattern: TurbulencePattern,
+    contrast: f32,
+    basis: NoiseBasis,
+    colors: [[f32; 3]; 2],
+) {
+    let cell_size = width as f32 / frequency.max(f32::EPSILON);
+
+    let [x0, y0, x1, y1] = region;
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            let p = [x as f32 / cell_size, y as f32 / cell_size];
+            let value = turbulence(p, octaves, seed, turbulent, basis);
+            let value = match pattern {
+                TurbulencePattern::Noise if turbulent => value,
+                // Plain FBM is signed
+                TurbulencePattern::Noise => value * 0.5 + 0.5,
+                TurbulencePattern::Marble => {
+                    let phase = (p[0] + value * MARBLE_DISTORTION) * std::f32::consts::PI;
+                    phase.sin() * 0.5 + 0.5
+                }
+            };
+            let t = value.clamp(0.0, 1.0).powf(contrast);
+
+            for ((channel, a), b) in pixel.iter_mut().zip(colors[0]).zip(colors[1]) {
+                *channel = a + (b - a) * t;
+            }
+            pixel[3] = 1.0;
+        }
+    }
+}
+
+/// ACEScg to CIE XYZ (ACES white point)
+pub const ACESCG_TO_XYZ: [[f32; 3]; 3] = [
+    [0.662_454_2, 0.134_004_2, 0.156_187_7],
+    [0.272_228_7, 0.674_081_8, 0.053_689_5],
+    [-0.005_574_6, 0.004_060_7, 1.010_339_1],
+];
+
+/// xy chromaticity of the red, green and blue primaries of common gamuts
+pub const SRGB_PRIMARIES_XY: [[f32; 2]; 3] = [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]];
+pub const ACESCG_PRIMARIES_XY: [[f32; 2]; 3] = [[0.713, 0.293], [0.165, 0.830], [0.128, 0.044]];
+pub const DISPLAY_P3_PRIMARIES_XY: [[f32; 2]; 3] = [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060]];
+pub const REC2020_PRIMARIES_XY: [[f32; 2]; 3] = [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]];
+/// Same primaries as Display P3, DCI-P3 only differs by its white point
+pub const DCI_P3_PRIMARIES_XY: [[f32; 2]; 3] = DISPLAY_P3_PRIMARIES_XY;
+
+/// xy chromaticity of an ACEScg color, None for black
+pub fn acescg_to_xy(rgb: [f32; 3]) -> Option<[f32; 2]> {
+    let [x, y, z] = mul_mat3(&ACESCG_TO_XYZ, rgb);
+    let sum = x + y + z;
+    (sum > 0.0).then(|| [x / sum, y / sum])
+}
+
+/// xy chromaticity of the monochromatic lights from 380 to 780 nm,
+/// the horseshoe outline of the chromaticity diagram
+pub fn spectral_locus() -> Vec<[f32; 2]> {
+    CIE_1931_CMF
+        .iter()
+        .filter_map(|[x, y, z]| {
+            let sum = x + y + z;
+            (sum > 0.0).then(|| [x / sum, y / sum])
+        })
+        .collect()
+}
+
+/// ACEScg to linear sRGB, the inverse of `LINEAR_SRGB_TO_ACESCG`
+pub const ACESCG_TO_LINEAR_SRGB: [[f32; 3]; 3] = [
+    [1.705_051, -0.621_792_1, -0.083_258_9],
+    [-0.130_256_4, 1.140_804_8, -0.010_548_5],
+    [-0.024_003_3, -0.128_969, 1.152_972_3],
+];
+
+/// Encode a linear value in [0, 1] with the sRGB transfer function
+pub fn linear_to_srgb(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Convert an ACEScg color to 8 bit sRGB, clipping what falls outside of the sRGB gamut
+pub fn acescg_to_srgb_u8(rgb: [f32; 3]) -> [u8; 3] {
+    mul_mat3(&ACESCG_TO_LINEAR_SRGB, rgb)
+        .map(|c| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8)
+}
+
+/// Mean color of each `char_w` x `char_h` block of a linear ACEScg buffer, row by row.
+/// Each block gives the tonemapped, sRGB encoded color and the encoded luminance of
+/// the tonemapped pixels. Blocks on the right and bottom edges may be smaller.
+fn ascii_art_blocks(
+    buffer: &[f32],
+    width: usize,
+    height: usize,
+    char_w: usize,
+    char_h: usize,
+) -> Vec<Vec<([f32; 3], f32)>> {
+    let (char_w, char_h) = (char_w.max(1), char_h.max(1));
+    let params = PerceptualTonemapperParams::default();
+    (0..height)
+        .step_by(char_h)
+        .map(|block_y| {
+            (0..width)
+                .step_by(char_w)
+                .map(|block_x| {
+                    let mut color = [0.0; 3];
+                    let mut luminance = 0.0;
+                    let mut count = 0;
+                    for y in block_y..(block_y + char_h).min(height) {
+                        for x in block_x..(block_x + char_w).min(width) {
+                            let pixel = &buffer[(y * width + x) * 4..];
+                            let scene = color::acescg::<Scene>(pixel[0], pixel[1], pixel[2]);
+                            let tonemapped: Color<AcesCg, Display> =
+                                PerceptualTonemapper::tonemap(scene, params).convert();
+                            let encoded = tonemapped.convert::<EncodedSrgb>();
+                            for (sum, channel) in
+                                color.iter_mut().zip([encoded.r, encoded.g, encoded.b])
+                            {
+                                *sum += channel;
+                            }
+                            luminance += linear_to_srgb(acescg_luminance([
+                                tonemapped.r,
+                                tonemapped.g,
+                                tonemapped.b,
+                            ]));
+                            count += 1;
+                        }
+                    }
+                    let count = count as f32;
+                    (color.map(|sum| sum / count), luminance / count)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Character of `charset` (ordered darkest to lightest) for an encoded luminance in [0, 1]
+fn ascii_art_char(charset: &[char], luminance: f32) -> char {
+    let last = charset.len() - 1;
+    charset[((luminance.clamp(0.0, 1.0) * last as f32).round() as usize).min(last)]
+}
+
+/// Convert a linear ACEScg buffer to text, one character per `char_w` x `char_h` block
+/// picked from `charset` by the mean tonemapped luminance of the block.
+/// `charset` goes from the darkest to the lightest character, e.g. `" .:-=+*#@"`.
+pub fn export_ascii_art(
+    buffer: &[f32],
+    width: usize,
+    height: usize,
+    char_w: usize,
+    char_h: usize,
+    charset: &str,
+) -> String {
+    let charset: Vec<char> = charset.chars().collect();
+    if charset.is_empty() {
+        return String::new();
+    }
+    let mut text = String::new();
+    for row in ascii_art_blocks(buffer, width, height, char_w, char_h) {
+        text.extend(
+            row.iter()
+                .map(|&(_, luminance)| ascii_art_char(&charset, luminance)),
+        );
+        text.push('\n');
+    }
+    text
+}
+
+/// Same as `export_ascii_art`, as an HTML page where each character has the
+/// mean tonemapped sRGB color of its block
+pub fn export_ascii_art_html(
+    buffer: &[f32],
+    width: usize,
+    height: usize,
+    char_w: usize,
+    char_h: usize,
+    charset: &str,
+) -> String {
+    let charset: Vec<char> = charset.chars().collect();
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<body style=\"background: #000000\">\n",
+        "<pre style=\"font-family: monospace; line-height: 1\">\n",
+    ));
+    if !charset.is_empty() {
+        for row in ascii_art_blocks(buffer, width, height, char_w, char_h) {
+            for (color, luminance) in row {
+                let [r, g, b] = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                let character = match ascii_art_char(&charset, luminance) {
+                    '&' => "&amp;".to_owned(),
+                    '<' => "&lt;".to_owned(),
+                    '>' => "&gt;".to_owned(),
+                    character => character.to_string(),
+                };
+                html.push_str(&format!(
+                    "<span style=\"color: #{r:02X}{g:02X}{b:02X}\">{character}</span>"
+                ));
+            }
+            html.push('\n');
+        }
+    }
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+/// Convert a hue, saturation, lightness triplet (all in [0, 1]) to sRGB encoded RGB
+pub fn hsl_to_srgb(hue: f32, saturation: f32, lightness: f32) -> [f32; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(1.0) * 6.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma * 0.5;
+    [r + m, g + m, b + m]
+}
+
+/// 1 / φ, stepping the hue by this much never repeats and keeps consecutive colors far apart
+const GOLDEN_RATIO_CONJUGATE: f32 = 0.618_034;
+
+/// Generate `n` ACEScg colors by stepping around the HSL hue wheel by the golden ratio
+/// conjugate, starting from a hue picked by `seed`
+pub fn generate_palette(n: usize, seed: u64, saturation: f32, lightness: f32) -> Vec<[f32; 3]> {
+    let start = SplitMix64::new(seed).next_f32();
+    (0..n)
+        .map(|i| {
+            let hue = (start + i as f32 * GOLDEN_RATIO_CONJUGATE).fract();
+            let encoded = hsl_to_srgb(hue, saturation, lightness);
+            mul_mat3(&LINEAR_SRGB_TO_ACESCG, encoded.map(srgb_to_linear))
+        })
+        .collect()
+}
+
+/// Parameters of the brick render mode. Sizes are in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BrickSettings {
+    pub brick_w: f32,
+    pub brick_h: f32,
+    // Width of the gap between bricks
+    pub mortar: f32,
+    pub brick_color: [f32; 3],
+    pub mortar_color: [f32; 3],
+    // Horizontal shift of every other row, as a fraction of the brick width
+    pub offset: f32,
+    // How much the brightness of each brick varies, 0.0 - 0.3
+    pub variation: f32,
+}
+
+impl Default for BrickSettings {
+    fn default() -> Self {
+        Self {
+            brick_w: 40.0,
+            brick_h: 16.0,
+            mortar: 2.0,
+            brick_color: [0.45, 0.12, 0.06],
+            mortar_color: [0.5, 0.5, 0.48],
+            offset: 0.5,
+            variation: 0.15,
+        }
+    }
+}
+
+/// Render a wall of bricks separated by mortar lines, every other row shifted by
+/// `offset` bricks. Each brick gets a random brightness change of up to `variation`.
+/// Only the pixels in `region` are written, see `render_bg_image`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_brick_pattern(
+    buffer: &mut [f32],
+    width: usize,
+    region: [usize; 4],
+    brick_w: f32,
+    brick_h: f32,
+    mortar: f32,
+    brick_color: [f32; 3],
+    mortar_color: [f32; 3],
+    offset: f32,
+    variation: f32,
+) {
+    let brick_w = brick_w.max(1.0);
+    let brick_h = brick_h.max(1.0);
+
+    let [x0, y0, x1, y1] = region;
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        let py = y as f32 + 0.5;
+        let brick_row = (py / brick_h).floor();
+        let shift = if brick_row as i32 % 2 != 0 {
+            offset * brick_w
+        } else {
+            0.0
+        };
+        let local_y = py - brick_row * brick_h;
+
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            let px = x as f32 + 0.5 + shift;
+            let brick_col = (px / brick_w).floor();
+            let local_x = px - brick_col * brick_w;
+
+            let color = if local_x < mortar || local_y < mortar {
+                mortar_color
+            } else {
+                let random = lattice_value(brick_col as i32, brick_row as i32, 0);
+                let brightness = 1.0 + (random * 2.0 - 1.0) * variation;
+                brick_color.map(|c| c * brightness)
+            };
+            pixel.copy_from_slice(&[color[0], color[1], color[2], 1.0]);
+        }
+    }
+}
+
+/// Color model swept by the color wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorWheelMode {
+    /// HSL on sRGB encoded values
+    Hsl,
+    /// Oklab `(a, b)` plane at constant `L`
+    Oklab,
+    /// HSV directly on ACEScg values
+    AcesCg,
+}
+
+/// Largest Oklab chroma, reached at the edge of the wheel
+const OKLAB_WHEEL_MAX_CHROMA: f32 = 0.4;
+/// Width in pixels of the stripes marking out of gamut colors
+const HATCH_WIDTH: usize = 4;
+
+/// Convert a linear sRGB color to Oklab (Björn Ottosson's reference matrices)
+pub fn linear_srgb_to_oklab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let l = (0.412_221_5 * r + 0.536_332_5 * g + 0.051_446 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_397 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_8 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_3 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_04 * l + 0.782_771_8 * m - 0.808_675_8 * s,
+    ]
+}
+
+/// Convert an Oklab color to linear sRGB (Björn Ottosson's reference matrices)
+pub fn oklab_to_linear_srgb(lab: [f32; 3]) -> [f32; 3] {
+    let [l, a, b] = lab;
+    let l_ = l + 0.396_337_8 * a + 0.215_803_8 * b;
+    let m_ = l - 0.105_561_3 * a - 0.063_854_17 * b;
+    let s_ = l - 0.089_484_18 * a - 1.291_485_5 * b;
+    let (l, m, s) = (l_.powi(3), m_.powi(3), s_.powi(3));
+    [
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_9 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_4 * s,
+        -0.004_196_086 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    ]
+}
+
+/// Convert hue, saturation and value (all in [0, 1]) to RGB
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    // HSV and HSL share the same hue sectors, only the lightness axis differs
+    let lightness = value * (1.0 - saturation * 0.5);
+    let hsl_saturation = if lightness > 0.0 && lightness < 1.0 {
+        (value - lightness) / lightness.min(1.0 - lightness)
+    } else {
+        0.0
+    };
+    hsl_to_srgb(hue, hsl_saturation, lightness)
+}
+
+/// Render a hue (angle) / saturation or chroma (radius) disc at a fixed `lightness`.
+/// Colors that can't be represented in ACEScg are shown with grey stripes.
+/// Only the pixels in `region` are written, see `render_bg_image`.
+pub fn render_color_wheel(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    lightness: f32,
+    mode: ColorWheelMode,
+) {
+    let radius = width.min(height) as f32 * 0.5;
+    let center = [width as f32 * 0.5, height as f32 * 0.5];
+
+    let [x0, y0, x1, y1] = region;
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            // Rows are stored top first, flip so hues go counter-clockwise
+            let dx = (x as f32 + 0.5 - center[0]) / radius;
+            let dy = (center[1] - y as f32 - 0.5) / radius;
+            let distance = dx.hypot(dy);
+            if distance > 1.0 {
+                pixel.copy_from_slice(&[0.0, 0.0, 0.0, 1.0]);
+                continue;
+            }
+            let hue = dy.atan2(dx) / std::f32::consts::TAU;
+
+            let rgb = match mode {
+                ColorWheelMode::Hsl => mul_mat3(
+                    &LINEAR_SRGB_TO_ACESCG,
+                    hsl_to_srgb(hue, distance, lightness).map(srgb_to_linear),
+                ),
+                ColorWheelMode::Oklab => {
+                    let chroma = distance * OKLAB_WHEEL_MAX_CHROMA;
+                    let (sin, cos) = (hue * std::f32::consts::TAU).sin_cos();
+                    let linear = oklab_to_linear_srgb([lightness, chroma * cos, chroma * sin]);
+                    mul_mat3(&LINEAR_SRGB_TO_ACESCG, linear)
+                }
+                ColorWheelMode::AcesCg => hsv_to_rgb(hue, distance, lightness),
+            };
+
+            let rgb = if rgb.iter().any(|&c| c < 0.0) {
+                let stripe = (x + y) / HATCH_WIDTH % 2 == 0;
+                if stripe {
+                    [0.18; 3]
+                } else {
+                    [0.09; 3]
+                }
+            } else {
+                rgb
+            };
+            pixel.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 1.0]);
+        }
+    }
+}
+
+/// Half the width of the image in wood texture units
+const WOOD_HALF_WIDTH: f32 = 4.0;
+/// How much faster the grain turbulence varies along the grain than across it
+const WOOD_GRAIN_ANISOTROPY: f32 = 4.0;
+const WOOD_TURBULENCE_OCTAVES: u32 = 4;
+
+/// Parameters of the wood grain render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WoodGrainSettings {
+    // Rings per texture unit
+    pub ring_frequency: f32,
+    pub turbulence_scale: f32,
+    // Direction of the grain in degrees, 0 is horizontal
+    pub grain_angle: f32,
+    pub grain_color_a: [f32; 3],
+    pub grain_color_b: [f32; 3],
+    pub seed: u64,
+}
+
+impl Default for WoodGrainSettings {
+    fn default() -> Self {
+        Self {
+            ring_frequency: 2.0,
+            turbulence_scale: 1.0,
+            grain_angle: 0.0,
+            grain_color_a: [0.25, 0.1, 0.03],
+            grain_color_b: [0.55, 0.3, 0.12],
+            seed: 0,
+        }
+    }
+}
+
+/// Render wood rings around the center of the image:
+/// `dist = sqrt(x^2 + y^2) * ring_frequency + turbulence(x, y) * turbulence_scale`,
+/// with `sin(dist * PI)` blending between the two grain colors.
+/// The turbulence is stretched so it varies faster along the grain, rotated by `grain_angle` degrees.
+/// Only the pixels in `region` are written, see `render_bg_image`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_wood_grain(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    ring_frequency: f32,
+    turbulence_scale: f32,
+    grain_angle: f32,
+    grain_color_a: [f32; 3],
+    grain_color_b: [f32; 3],
+    seed: u64,
+) {
+    let units_per_pixel = WOOD_HALF_WIDTH * 2.0 / width as f32;
+    let (sin, cos) = grain_angle.to_radians().sin_cos();
+
+    let [x0, y0, x1, y1] = region;
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            let px = (x as f32 + 0.5 - width as f32 * 0.5) * units_per_pixel;
+            let py = (height as f32 * 0.5 - y as f32 - 0.5) * units_per_pixel;
+            // Along and across the grain
+            let along = px * cos + py * sin;
+            let across = py * cos - px * sin;
+
+            let noise = turbulence(
+                [along * WOOD_GRAIN_ANISOTROPY, across],
+                WOOD_TURBULENCE_OCTAVES,
+                seed,
+                false,
+                NoiseBasis::Perlin,
+            );
+            let dist = along.hypot(across) * ring_frequency + noise * turbulence_scale;
+            let t = (dist * std::f32::consts::PI).sin() * 0.5 + 0.5;
+
+            for ((channel, a), b) in pixel.iter_mut().zip(grain_color_a).zip(grain_color_b) {
+                *channel = a + (b - a) * t;
+            }
+            pixel[3] = 1.0;
+        }
+    }
+}
+
+/// Successively halved copies of an RGBA buffer, for cheap downsampled previews
+#[derive(Debug, Clone)]
+pub struct MipPyramid {
+    // Level 0 is the full resolution buffer
+    levels: Vec<Vec<f32>>,
+}
+
+impl MipPyramid {
+    /// Build every level down to 1x1 from a linear RGBA buffer.
+    /// Each level averages 2x2 blocks of the previous one, odd rows and columns
+    /// are folded into the last block.
+    pub fn build_from_buffer(buffer: &[f32], width: usize, height: usize) -> MipPyramid {
+        let mut levels = vec![buffer[..width * height * 4].to_vec()];
+        let (mut width, mut height) = (width, height);
+
+        while width > 1 || height > 1 {
+            let previous = &levels[levels.len() - 1];
+            let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+            let mut next = vec![0.0; next_width * next_height * 4];
+
+            for (y, row) in next.chunks_exact_mut(next_width * 4).enumerate() {
+                // The last block also takes the leftover row or column
+                let rows = y * 2..if y + 1 == next_height {
+                    height
+                } else {
+                    y * 2 + 2
+                };
+                for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                    let columns = x * 2..if x + 1 == next_width {
+                        width
+                    } else {
+                        x * 2 + 2
+                    };
+                    let count = (rows.len() * columns.len()) as f32;
+                    for source_y in rows.clone() {
+                        let start = (source_y * width + columns.start) * 4;
+                        let end = (source_y * width + columns.end) * 4;
+                        for source in previous[start..end].chunks_exact(4) {
+                            for (channel, value) in pixel.iter_mut().zip(source) {
+                                *channel += value / count;
+                            }
+                        }
+                    }
+                }
+            }
+
+            levels.push(next);
+            (width, height) = (next_width, next_height);
+        }
+
+        MipPyramid { levels }
+    }
+
+    /// Number of levels, including the full resolution one
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Pixels of level `lod`, clamped to the smallest level
+    pub fn level(&self, lod: usize) -> &[f32] {
+        &self.levels[lod.min(self.levels.len() - 1)]
+    }
+}
+
+/// Candidates tried around each active sample before giving up on it
+const POISSON_MAX_ATTEMPTS: u32 = 30;
+
+/// Parameters of the Poisson disk render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PoissonDiskSettings {
+    // In pixels
+    pub min_distance: f32,
+    pub seed: u64,
+}
+
+impl Default for PoissonDiskSettings {
+    fn default() -> Self {
+        Self {
+            min_distance: 10.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Render Poisson disk samples as white dots of radius `min_distance / 4` on black
+pub fn render_poisson_disk(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    min_distance: f32,
+    seed: u64,
+) {
+    let samples = generate_poisson_disk_samples(
+        width as f32,
+        height as f32,
+        min_distance,
+        POISSON_MAX_ATTEMPTS,
+        seed,
+    );
+
+    for pixel in buffer.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&[0.0, 0.0, 0.0, 1.0]);
+    }
+    let dot = Brush {
+        radius: min_distance / 4.0,
+        hardness: 1.0,
+        ..Brush::default()
+    };
+    for [u, v] in samples {
+        dot.paint_at(buffer, width, height, u * width as f32, v * height as f32);
+    }
+}
+
+/// Width of the oscilloscope trace, in pixels
+const OSCILLOSCOPE_THICKNESS: f32 = 1.5;
+
+/// Parameters of the oscilloscope render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OscilloscopeSettings {
+    pub color: [f32; 3],
+    // How much of the previous frame is kept, 1.0 never fades
+    pub persistence: f32,
+}
+
+impl Default for OscilloscopeSettings {
+    fn default() -> Self {
+        Self {
+            color: [0.1, 1.0, 0.2],
+            persistence: 0.9,
+        }
+    }
+}
+
+/// Plot `samples` (in [-1, 1]) as an anti-aliased waveform across the width of the image.
+/// What's already in `buffer` is faded by `persistence` first, so older traces linger
+/// like on the phosphor of an analog oscilloscope.
+pub fn render_oscilloscope(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    samples: &[f32],
+    color: [f32; 3],
+    persistence: f32,
+) {
+    for pixel in buffer.chunks_exact_mut(4) {
+        for channel in &mut pixel[..3] {
+            *channel *= persistence;
+        }
+        pixel[3] = 1.0;
+    }
+    if samples.len() < 2 {
+        return;
+    }
+
+    // Leave a small margin above and below the trace
+    let amplitude = height as f32 * 0.45;
+    let center = height as f32 * 0.5;
+    let point = |i: usize| {
+        [
+            i as f32 / (samples.len() - 1) as f32 * width as f32,
+            // Rows are stored top first
+            center - samples[i].clamp(-1.0, 1.0) * amplitude,
+        ]
+    };
+
+    let mut coverage = vec![0.0_f32; width * height];
+    for i in 1..samples.len() {
+        rasterize_segment_coverage(
+            &mut coverage,
+            width,
+            height,
+            point(i - 1),
+            point(i),
+            OSCILLOSCOPE_THICKNESS,
+        );
+    }
+
+    for (pixel, covered) in buffer.chunks_exact_mut(4).zip(coverage) {
+        for (channel, value) in pixel.iter_mut().zip(color) {
+            *channel += (value - *channel) * covered;
+        }
+    }
+}
+
+/// Samples of a slowly drifting two tone chord, shown by the oscilloscope
+/// when there's no audio input. `time` is in seconds.
+pub fn oscilloscope_test_signal(time: f32, count: usize) -> Vec<f32> {
+    // Number of periods of the base tone across the image
+    let periods = 3.0;
+    (0..count)
+        .map(|i| {
+            let t = i as f32 / count as f32 * std::f32::consts::TAU * periods;
+            0.6 * (t + time * 2.0).sin() + 0.3 * (t * 1.5 - time * 3.0).sin()
+        })
+        .collect()
+}
+
+/// How `apply_border` fills the border
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum BorderStyle {
+    Solid,
+    /// Dashes of the border color along each side, the image shows through the gaps
+    Dashed {
+        dash_len: u32,
+        gap_len: u32,
+    },
+    /// Blend from `outer_color` at the edge of the image to `inner_color`,
+    /// the border color is not used
+    Gradient {
+        inner_color: [f32; 4],
+        outer_color: [f32; 4],
+    },
+}
+
+/// Overwrite the outermost `border_width` pixels on all four sides with `border_color`,
+/// in ACEScg, following `style`
+pub fn apply_border(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    border_width: u32,
+    border_color: [f32; 4],
+    style: BorderStyle,
+) {
+    let border_width = border_width as usize;
+    if border_width == 0 {
+        return;
+    }
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            // Distance to the closest edge, in pixels
+            let from_left_right = x.min(width - 1 - x);
+            let from_top_bottom = y.min(height - 1 - y);
+            let distance = from_left_right.min(from_top_bottom);
+            if distance >= border_width {
+                continue;
+            }
+
+            let color = match style {
+                BorderStyle::Solid => border_color,
+                BorderStyle::Dashed { dash_len, gap_len } => {
+                    // Dashes run along the side the pixel is on
+                    let along = if from_top_bottom <= from_left_right {
+                        x
+                    } else {
+                        y
+                    };
+                    let period = (dash_len + gap_len).max(1) as usize;
+                    if along % period >= dash_len as usize {
+                        continue;
+                    }
+                    border_color
+                }
+                BorderStyle::Gradient {
+                    inner_color,
+                    outer_color,
+                } => {
+                    let t = if border_width > 1 {
+                        distance as f32 / (border_width - 1) as f32
+                    } else {
+                        0.0
+                    };
+                    let mut color = outer_color;
+                    for (channel, inner) in color.iter_mut().zip(inner_color) {
+                        *channel += (inner - *channel) * t;
+                    }
+                    color
+                }
+            };
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
+/// ACEScg value from which a channel is considered clipped by `apply_highlight_recovery`
+const HIGHLIGHT_CLIP_THRESHOLD: f32 = 0.9;
+
+/// Pixels around a clipped pixel searched for the color of the highlight
+const HIGHLIGHT_RECOVERY_RADIUS: usize = 4;
+
+/// Rebuild the channels clipped at `HIGHLIGHT_CLIP_THRESHOLD` where at least one channel
+/// survived. Nearby unclipped pixels give the expected ratio of each clipped channel to the
+/// luminance of the surviving ones, which scales the surviving luminance of the pixel.
+/// A clipped value is a lower bound, so channels only get brighter.
+/// `recovery` blends from the clipped (0) to the rebuilt (1) values.
+pub fn apply_highlight_recovery(buffer: &mut [f32], width: usize, height: usize, recovery: f32) {
+    if recovery <= 0.0 {
+        return;
+    }
+    let recovery = recovery.min(1.0);
+    let source = buffer[..width * height * 4].to_vec();
+    let rgb_at = |x: usize, y: usize| {
+        let index = (y * width + x) * 4;
+        [source[index], source[index + 1], source[index + 2]]
+    };
+    let is_clipped = |rgb: [f32; 3]| rgb.map(|c| c >= HIGHLIGHT_CLIP_THRESHOLD);
+    // Luminance of the channels not in `clipped`
+    let surviving_luminance = |rgb: [f32; 3], clipped: [bool; 3]| {
+        (0..3)
+            .filter(|&c| !clipped[c])
+            .map(|c| rgb[c] * ACESCG_LUMINANCE[c])
+            .sum::<f32>()
+    };
+
+    for (index, pixel) in buffer.chunks_exact_mut(4).take(width * height).enumerate() {
+        let (x, y) = (index % width, index / width);
+        let rgb = rgb_at(x, y);
+        let clipped = is_clipped(rgb);
+        let clipped_count = clipped.iter().filter(|&&c| c).count();
+        if clipped_count == 0 || clipped_count == 3 {
+            continue;
+        }
+        let luminance = surviving_luminance(rgb, clipped);
+        if luminance <= f32::EPSILON {
+            continue;
+        }
+
+        // Mean ratio of each channel to the surviving luminance, around the pixel
+        let mut ratios = [0.0; 3];
+        let mut count = 0;
+        for neighbour_y in y.saturating_sub(HIGHLIGHT_RECOVERY_RADIUS)
+            ..(y + HIGHLIGHT_RECOVERY_RADIUS + 1).min(height)
+        {
+            for neighbour_x in x.saturating_sub(HIGHLIGHT_RECOVERY_RADIUS)
+                ..(x + HIGHLIGHT_RECOVERY_RADIUS + 1).min(width)
+            {
+                let neighbour = rgb_at(neighbour_x, neighbour_y);
+                if is_clipped(neighbour).contains(&true) {
+                    continue;
+                }
+                let neighbour_luminance = surviving_luminance(neighbour, clipped);
+                if neighbour_luminance <= f32::EPSILON {
+                    continue;
+                }
+                for (ratio, value) in ratios.iter_mut().zip(neighbour) {
+                    *ratio += value / neighbour_luminance;
+                }
+                count += 1;
+            }
+        }
+        if count == 0 {
+            continue;
+        }
+
+        for (c, channel) in pixel[..3].iter_mut().enumerate() {
+            if clipped[c] {
+                let rebuilt = (ratios[c] / count as f32 * luminance).max(rgb[c]);
+                *channel = rgb[c] + (rebuilt - rgb[c]) * recovery;
+            }
+        }
+    }
+}
+
+/// Input and output ranges of a levels adjustment, as in Photoshop's Levels dialog
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LevelsRange {
+    pub input_black: f32,
+    pub input_white: f32,
+    // Midpoint gamma, above 1 brightens the midtones
+    pub gamma: f32,
+    pub output_black: f32,
+    pub output_white: f32,
+}
+
+impl LevelsRange {
+    pub const IDENTITY: LevelsRange = LevelsRange {
+        input_black: 0.0,
+        input_white: 1.0,
+        gamma: 1.0,
+        output_black: 0.0,
+        output_white: 1.0,
+    };
+
+    /// Normalize `[input_black, input_white]` to `[0, 1]`, apply the midpoint gamma, then
+    /// remap to `[output_black, output_white]`. Values below the input black are clipped,
+    /// values above the input white keep going so HDR highlights aren't flattened.
+    pub fn apply(&self, value: f32) -> f32 {
+        let input_range = (self.input_white - self.input_black).max(f32::EPSILON);
+        let normalized = ((value - self.input_black) / input_range).max(0.0);
+        let curved = normalized.powf(1.0 / self.gamma.max(f32::EPSILON));
+        self.output_black + curved * (self.output_white - self.output_black)
+    }
+}
+
+impl Default for LevelsRange {
+    fn default() -> Self {
+        LevelsRange::IDENTITY
+    }
+}
+
+/// Levels of `apply_levels`, the same for the three channels or one per channel
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LevelsParams {
+    pub enabled: bool,
+    pub per_channel: bool,
+    // Used when `per_channel` is off
+    pub master: LevelsRange,
+    // R, G and B, used when `per_channel` is on
+    pub channels: [LevelsRange; 3],
+}
+
+impl LevelsParams {
+    /// Range applied to each of R, G and B
+    pub fn ranges(&self) -> [LevelsRange; 3] {
+        if self.per_channel {
+            self.channels
+        } else {
+            [self.master; 3]
+        }
+    }
+}
+
+impl Default for LevelsParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            per_channel: false,
+            master: LevelsRange::IDENTITY,
+            channels: [LevelsRange::IDENTITY; 3],
+        }
+    }
+}
+
+/// Photoshop style levels on R, G and B, see `LevelsRange::apply`
+#[allow(clippy::too_many_arguments)]
+pub fn apply_levels(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    input_black: f32,
+    input_white: f32,
+    gamma: f32,
+    output_black: f32,
+    output_white: f32,
+) {
+    let range = LevelsRange {
+        input_black,
+        input_white,
+        gamma,
+        output_black,
+        output_white,
+    };
+    apply_levels_per_channel(buffer, width, height, &[range; 3]);
+}
+
+/// Levels with their own range for each of R, G and B
+pub fn apply_levels_per_channel(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    ranges: &[LevelsRange; 3],
+) {
+    for pixel in buffer.chunks_exact_mut(4).take(width * height) {
+        for (channel, range) in pixel.iter_mut().zip(ranges) {
+            *channel = range.apply(*channel);
+        }
+    }
+}
+
+/// Fraction of the pixels left out below the black and above the white by `auto_levels`
+const AUTO_LEVELS_CLIP: f32 = 0.001;
+
+/// Set the input black and white of `levels` to the 0.1% and 99.9% percentiles of the
+/// luminance for the master range, and of each channel for the per channel ranges
+pub fn auto_levels(buffer: &[f32], width: usize, height: usize, levels: &mut LevelsParams) {
+    let pixels = &buffer[..width * height * 4];
+    if pixels.is_empty() {
+        return;
+    }
+    let percentiles = |mut values: Vec<f32>| {
+        let last = values.len() - 1;
+        let low = ((last as f32 * AUTO_LEVELS_CLIP) as usize).min(last);
+        let high = ((last as f32 * (1.0 - AUTO_LEVELS_CLIP)).round() as usize).min(last);
+        let black = *values.select_nth_unstable_by(low, f32::total_cmp).1;
+        let white = *values.select_nth_unstable_by(high, f32::total_cmp).1;
+        (black, white.max(black + f32::EPSILON))
+    };
+
+    let luminances = pixels
+        .chunks_exact(4)
+        .map(|pixel| acescg_luminance([pixel[0], pixel[1], pixel[2]]))
+        .collect();
+    (levels.master.input_black, levels.master.input_white) = percentiles(luminances);
+    for (channel, range) in levels.channels.iter_mut().enumerate() {
+        let values = pixels.chunks_exact(4).map(|pixel| pixel[channel]).collect();
+        (range.input_black, range.input_white) = percentiles(values);
+    }
+}
+
+pub const LEVELS_HISTOGRAM_BINS: usize = 64;
+
+/// Histograms of R, G and B over [0, 1], shown under the levels controls.
+/// Values above 1 are counted in the last bin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelsHistogram {
+    pub bins: [[u32; LEVELS_HISTOGRAM_BINS]; 3],
+}
+
+impl LevelsHistogram {
+    pub fn from_buffer(buffer: &[f32], width: usize, height: usize) -> Self {
+        let mut bins = [[0; LEVELS_HISTOGRAM_BINS]; 3];
+        for pixel in buffer.chunks_exact(4).take(width * height) {
+            for (channel_bins, &value) in bins.iter_mut().zip(pixel) {
+                channel_bins[Self::bin(value)] += 1;
+            }
+        }
+        Self { bins }
+    }
+
+    fn bin(value: f32) -> usize {
+        ((value.max(0.0) * LEVELS_HISTOGRAM_BINS as f32) as usize).min(LEVELS_HISTOGRAM_BINS - 1)
+    }
+
+    /// Histogram of the image once the levels are applied, moving the pixels of each bin
+    /// to where its center lands
+    pub fn remapped(&self, ranges: &[LevelsRange; 3]) -> Self {
+        let mut bins = [[0; LEVELS_HISTOGRAM_BINS]; 3];
+        for ((remapped, input), range) in bins.iter_mut().zip(&self.bins).zip(ranges) {
+            for (bin, &count) in input.iter().enumerate() {
+                let center = (bin as f32 + 0.5) / LEVELS_HISTOGRAM_BINS as f32;
+                remapped[Self::bin(range.apply(center))] += count;
+            }
+        }
+        Self { bins }
+    }
+}
+
+impl Default for LevelsHistogram {
+    fn default() -> Self {
+        Self {
+            bins: [[0; LEVELS_HISTOGRAM_BINS]; 3],
+        }
+    }
+}
+
+/// ASC CDL style grade: `gain` and `offset` on every value, then `lift` on the shadows
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CdlParams {
+    pub enabled: bool,
+    // Multiplier, the CDL slope
+    pub gain: f32,
+    // Added to every channel
+    pub offset: f32,
+    // From -0.3 to 0.3, raises (or lowers) the floor of the shadows
+    pub lift: f32,
+    // Luminance below which the lift is fully applied, fading out up to twice that
+    pub pivot: f32,
+}
+
+impl Default for CdlParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            gain: 1.0,
+            offset: 0.0,
+            lift: 0.0,
+            pivot: 0.25,
+        }
+    }
+}
+
+/// The CDL gain and offset, `out = in * gain + offset`, clamped at 0
+pub fn apply_gain_offset(buffer: &mut [f32], width: usize, height: usize, gain: f32, offset: f32) {
+    for pixel in buffer.chunks_exact_mut(4).take(width * height) {
+        for channel in &mut pixel[..3] {
+            *channel = (*channel * gain + offset).max(0.0);
+        }
+    }
+}
+
+/// The CDL lift, `out = in * (1 - lift) + lift` on each channel, for the pixels with a
+/// luminance below `pivot`. It smoothly fades out between `pivot` and twice `pivot`,
+/// so the highlights are left alone. Negative lifts don't go below 0.
+pub fn apply_shadow_lift(buffer: &mut [f32], width: usize, height: usize, lift: f32, pivot: f32) {
+    if lift == 0.0 || pivot <= 0.0 {
+        return;
+    }
+    for pixel in buffer.chunks_exact_mut(4).take(width * height) {
+        let luminance = acescg_luminance([pixel[0], pixel[1], pixel[2]]);
+        let weight = 1.0 - smoothstep(pivot, pivot * 2.0, luminance);
+        for channel in &mut pixel[..3] {
+            let lifted = (*channel * (1.0 - lift) + lift).max(0.0);
+            *channel += (lifted - *channel) * weight;
+        }
+    }
+}
+
+/// Shadows and highlights tints of `apply_split_toning`. Hues are Oklab hue angles in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SplitToneParams {
+    pub enabled: bool,
+    pub shadow_hue: f32,
+    // 0 leaves the shadows untouched
+    pub shadow_saturation: f32,
+    pub highlight_hue: f32,
+    pub highlight_saturation: f32,
+    // In [-1, 1], moves the split between shadows and highlights towards the highlights
+    pub balance: f32,
+}
+
+impl SplitToneParams {
+    pub const TEAL_AND_ORANGE: SplitToneParams = SplitToneParams {
+        enabled: true,
+        shadow_hue: 200.0,
+        shadow_saturation: 0.4,
+        highlight_hue: 55.0,
+        highlight_saturation: 0.3,
+        balance: 0.0,
+    };
+    pub const GOLD_AND_BLUE: SplitToneParams = SplitToneParams {
+        enabled: true,
+        shadow_hue: 265.0,
+        shadow_saturation: 0.35,
+        highlight_hue: 85.0,
+        highlight_saturation: 0.3,
+        balance: 0.0,
+    };
+    pub const LAVENDER_AND_PEACH: SplitToneParams = SplitToneParams {
+        enabled: true,
+        shadow_hue: 305.0,
+        shadow_saturation: 0.3,
+        highlight_hue: 50.0,
+        highlight_saturation: 0.25,
+        balance: 0.0,
+    };
+}
+
+impl Default for SplitToneParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ..Self::TEAL_AND_ORANGE
+        }
+    }
+}
+
+/// Oklab chroma added to neutral colors by a split tone saturation of 1
+const SPLIT_TONE_MAX_CHROMA: f32 = 0.1;
+
+/// Tint the shadows and the highlights with two different hues, in Oklab.
+/// Each tint pulls the hue of the pixel towards its own hue and adds chroma of that hue,
+/// both by its saturation. The two are blended by the lightness of the pixel,
+/// `w = smoothstep(0.3 + balance * 0.2, 0.7 + balance * 0.2, L)`.
+/// The lightness is kept, hues are in degrees.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_split_toning(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    shadow_hue: f32,
+    shadow_saturation: f32,
+    highlight_hue: f32,
+    highlight_saturation: f32,
+    balance: f32,
+) {
+    // Chroma of a pixel once tinted towards `hue` by `saturation`
+    let tint = |ab: [f32; 2], hue: f32, saturation: f32| {
+        let (sin, cos) = hue.to_radians().sin_cos();
+        let chroma = ab[0].hypot(ab[1]);
+        let saturation = saturation.clamp(0.0, 1.0);
+        let rotated = [chroma * cos, chroma * sin];
+        [0, 1].map(|i| {
+            let pulled = ab[i] + (rotated[i] - ab[i]) * saturation;
+            pulled + [cos, sin][i] * SPLIT_TONE_MAX_CHROMA * saturation
+        })
+    };
+    let (low, high) = (0.3 + balance * 0.2, 0.7 + balance * 0.2);
+
+    for pixel in buffer.chunks_exact_mut(4).take(width * height) {
+        let rgb = mul_mat3(&ACESCG_TO_LINEAR_SRGB, [pixel[0], pixel[1], pixel[2]]);
+        let [l, a, b] = linear_srgb_to_oklab(rgb);
+        let w = smoothstep(low, high, l);
+        let shadow = tint([a, b], shadow_hue, shadow_saturation);
+        let highlight = tint([a, b], highlight_hue, highlight_saturation);
+        let [a, b] = [0, 1].map(|i| shadow[i] + (highlight[i] - shadow[i]) * w);
+        let toned = oklab_to_linear_srgb([l, a, b]);
+        pixel[..3].copy_from_slice(&mul_mat3(&LINEAR_SRGB_TO_ACESCG, toned));
+    }
+}
+
+/// Rotate the hue of every pixel by `angle_degrees` around the Oklab `(a, b)` plane,
+/// keeping its lightness and chroma
+pub fn apply_hue_rotate(buffer: &mut [f32], width: usize, height: usize, angle_degrees: f32) {
+    let (sin, cos) = angle_degrees.to_radians().sin_cos();
+    for pixel in buffer.chunks_exact_mut(4).take(width * height) {
+        let rgb = mul_mat3(&ACESCG_TO_LINEAR_SRGB, [pixel[0], pixel[1], pixel[2]]);
+        let [l, a, b] = linear_srgb_to_oklab(rgb);
+        let rotated = oklab_to_linear_srgb([l, a * cos - b * sin, a * sin + b * cos]);
+        pixel[..3].copy_from_slice(&mul_mat3(&LINEAR_SRGB_TO_ACESCG, rotated));
+    }
+}
+
+/// Look of an image intensifier tube, applied by `apply_night_vision`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NightVisionParams {
+    pub enabled: bool,
+    // Gain applied to the luminance
+    pub amplification: f32,
+    // Standard deviation of the noise added to the amplified luminance
+    pub noise_level: f32,
+    // ACEScg color of the phosphor
+    pub green_tint: [f32; 3],
+    // 0 leaves the corners untouched, 1 makes them black
+    pub vignette_strength: f32,
+    // Fraction of the previous frame still glowing, only used by animated renders
+    pub phosphor_persistence: f32,
+    // Darken every other pair of rows
+    pub scanlines: bool,
+    // Seeds the noise, changed on every frame so the grain moves
+    #[serde(skip)]
+    pub noise_seed: u64,
+}
+
+impl Default for NightVisionParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            amplification: 4.0,
+            noise_level: 0.05,
+            green_tint: [0.2, 1.0, 0.2],
+            vignette_strength: 0.6,
+            phosphor_persistence: 0.3,
+            scanlines: true,
+            noise_seed: 0,
+        }
+    }
+}
+
+/// Brightness of the dark rows of the night vision scanlines
+const NIGHT_VISION_SCANLINE_GAIN: f32 = 0.6;
+
+/// Turn the image into the green monochrome view of a night vision device:
+/// amplified and noisy luminance, darker towards the corners, tinted by the phosphor color
+/// and crossed by scanlines. Stays in linear ACEScg, the phosphor persistence is left
+/// to the caller since it depends on the previous frames.
+pub fn apply_night_vision(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    params: &NightVisionParams,
+) {
+    if !params.enabled {
+        return;
+    }
+
+    let mut rng = SplitMix64::new(params.noise_seed);
+    let center = [width as f32 * 0.5, height as f32 * 0.5];
+    let corner_distance = center[0].hypot(center[1]).max(f32::EPSILON);
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        let scanline = if params.scanlines && (y / 2) % 2 == 1 {
+            NIGHT_VISION_SCANLINE_GAIN
+        } else {
+            1.0
+        };
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let luminance = acescg_luminance([pixel[0], pixel[1], pixel[2]]);
+            let noisy = luminance * params.amplification + rng.next_gaussian() * params.noise_level;
+
+            let distance =
+                (x as f32 + 0.5 - center[0]).hypot(y as f32 + 0.5 - center[1]) / corner_distance;
+            let vignette = (1.0 - params.vignette_strength * distance * distance).max(0.0);
+
+            let intensity = noisy.max(0.0) * vignette * scanline;
+            for (channel, tint) in pixel.iter_mut().zip(params.green_tint) {
+                *channel = intensity * tint;
+            }
+        }
+    }
+}
+
+/// Which luminance transitions `apply_contour_overlay` draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ContourMode {
+    /// Every contour line
+    All,
+    /// Only where the luminance increases left to right and top to bottom
+    Rising,
+}
+
+/// Draw topographic map style lines where the ACEScg luminance crosses a multiple of
+/// `luma_step`, blending `line_color` over the image. The rest of the image is untouched.
+/// The distance to the closest contour is estimated from the luminance gradient,
+/// so lines stay about `line_width` pixels wide on both gentle and steep slopes.
+pub fn apply_contour_overlay(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    luma_step: f32,
+    line_color: [f32; 4],
+    line_width: f32,
+    mode: ContourMode,
+) {
+    if line_width <= 0.0 || luma_step <= 0.0 || width < 2 || height < 2 {
+        return;
+    }
+
+    // Read from a copy, so the lines already drawn don't create new contours
+    let luminance: Vec<f32> = buffer
+        .chunks_exact(4)
+        .take(width * height)
+        .map(|pixel| acescg_luminance([pixel[0], pixel[1], pixel[2]]))
+        .collect();
+    let at = |x: usize, y: usize| luminance[y * width + x];
+    let half_width = line_width * 0.5;
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            // Central differences, one sided on the edges
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            let gradient_x = (at(right, y) - at(left, y)) / (right - left) as f32;
+            let gradient_y = (at(x, down) - at(x, up)) / (down - up) as f32;
+            if mode == ContourMode::Rising && gradient_x + gradient_y <= 0.0 {
+                continue;
+            }
+            // Contour levels crossed per pixel
+            let slope = gradient_x.hypot(gradient_y) / luma_step;
+            if slope <= f32::EPSILON {
+                continue;
+            }
+
+            let level = at(x, y) / luma_step;
+            let to_level = (level - level.round()).abs();
+            let distance = to_level / slope;
+            // One pixel of antialiasing on the edge of the line
+            let coverage = (half_width + 0.5 - distance).clamp(0.0, 1.0) * line_color[3];
+            if coverage <= 0.0 {
+                continue;
+            }
+            for (channel, line) in pixel.iter_mut().zip(line_color).take(3) {
+                *channel += (line - *channel) * coverage;
+            }
+        }
+    }
+}
+
+/// Share of the original saturation kept in the crosshatch ink
+const CROSSHATCH_INK_SATURATION: f32 = 0.3;
+/// ACEScg luminance of the crosshatch ink
+const CROSSHATCH_INK_LUMINANCE: f32 = 0.02;
+
+/// Redraw the image as stained glass: about `num_regions` Worley cells, each filled with the
+/// mean ACEScg color of its pixels, and leaded with `border_thickness` pixels of
+/// `border_color` where the cells meet.
+pub fn apply_stained_glass(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    num_regions: usize,
+    border_thickness: u32,
+    border_color: [f32; 4],
+    seed: u64,
+) {
+    if width == 0 || height == 0 || num_regions == 0 {
+        return;
+    }
+    // Square cells, one feature point each
+    let cell_size = ((width * height) as f32 / num_regions as f32)
+        .sqrt()
+        .max(1.0);
+    // The closest feature point can be in the ring of cells just outside the image
+    let columns = (width as f32 / cell_size).ceil() as usize + 2;
+    let rows = (height as f32 / cell_size).ceil() as usize + 2;
+
+    let mut labels = vec![0; width * height];
+    let mut sums = vec![[0.0f64; 4]; columns * rows];
+    let mut counts = vec![0u32; columns * rows];
+    for (y, row) in buffer.chunks_exact(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact(4).enumerate() {
+            let p = [(x as f32 + 0.5) / cell_size, (y as f32 + 0.5) / cell_size];
+            let ([cell_x, cell_y], _, _) =
+                worley_closest_points(p, 1.0, seed, WorleyMetric::Euclidean);
+            let label = (cell_y + 1).clamp(0, rows as i32 - 1) as usize * columns
+                + (cell_x + 1).clamp(0, columns as i32 - 1) as usize;
+            labels[y * width + x] = label;
+            for (sum, &channel) in sums[label].iter_mut().zip(pixel) {
+                *sum += channel as f64;
+            }
+            counts[label] += 1;
+        }
+    }
+    let means: Vec<[f32; 4]> = sums
+        .iter()
+        .zip(&counts)
+        .map(|(sum, &count)| sum.map(|channel| (channel / count.max(1) as f64) as f32))
+        .collect();
+
+    // A pixel is leaded when a pixel of another cell is in the window around it. The
+    // window leans toward the bottom right so a thickness of 1 marks one side only.
+    let before = (border_thickness / 2) as isize;
+    let after = ((border_thickness + 1) / 2) as isize;
+    for (index, pixel) in buffer.chunks_exact_mut(4).take(width * height).enumerate() {
+        let (x, y) = ((index % width) as isize, (index / width) as isize);
+        let label = labels[index];
+        let is_border = border_thickness > 0
+            && (y - before..=y + after).any(|neighbour_y| {
+                (x - before..=x + after).any(|neighbour_x| {
+                    (0..width as isize).contains(&neighbour_x)
+                        && (0..height as isize).contains(&neighbour_y)
+                        && labels[neighbour_y as usize * width + neighbour_x as usize] != label
+                })
+            });
+        pixel.copy_from_slice(if is_border {
+            &border_color
+        } else {
+            &means[label]
+        });
+    }
+}
+
+/// Redraw the image as pencil hatching on paper. The paper is the image bleached toward
+/// white by its own luminance. Lines `line_spacing` pixels apart at `angle1` degrees are
+/// darker where the image is darker, and a second set at `angle2` crosses them where the
+/// luminance is below `dark_threshold`. The ink has the hue of the pixel, desaturated.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_crosshatch(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    line_spacing: u32,
+    line_width: f32,
+    angle1: f32,
+    angle2: f32,
+    dark_threshold: f32,
+) {
+    if line_spacing == 0 || line_width <= 0.0 {
+        return;
+    }
+    let spacing = line_spacing as f32;
+    let half_width = line_width * 0.5;
+    let directions = [angle1, angle2].map(|angle| angle.to_radians().sin_cos());
+    // Antialiased coverage of the closest line of a set, from the distance to it
+    let coverage = |x: f32, y: f32, (sin, cos): (f32, f32)| {
+        let across = x * cos + y * sin;
+        let distance = (across - (across / spacing).round() * spacing).abs();
+        (half_width + 0.5 - distance).clamp(0.0, 1.0)
+    };
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let rgb = [pixel[0], pixel[1], pixel[2]];
+            let luminance = acescg_luminance(rgb).clamp(0.0, 1.0);
+            let ink = if luminance > f32::EPSILON {
+                rgb.map(|c| {
+                    let desaturated = luminance + (c - luminance) * CROSSHATCH_INK_SATURATION;
+                    desaturated / luminance * CROSSHATCH_INK_LUMINANCE
+                })
+            } else {
+                [CROSSHATCH_INK_LUMINANCE; 3]
+            };
+            let opacities = [
+                1.0 - luminance,
+                (1.0 - luminance / dark_threshold.max(f32::EPSILON)).clamp(0.0, 1.0),
+            ];
+
+            // Pixel centers, so lines at 45 degrees stay symmetric
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            for (channel, value) in pixel.iter_mut().zip(rgb) {
+                *channel = value + (1.0 - value) * luminance;
+            }
+            for (direction, opacity) in directions.into_iter().zip(opacities) {
+                let alpha = coverage(px, py, direction) * opacity;
+                for (channel, ink) in pixel.iter_mut().zip(ink) {
+                    *channel += (ink - *channel) * alpha;
+                }
+            }
+        }
+    }
+}
+
+/// Share of the difference with a neighbour moved by a diffusion step, stable for 4 neighbours
+const WATERCOLOR_DIFFUSION_RATE: f32 = 0.2;
+/// Diffusion along the edges, relative to the one across them
+const WATERCOLOR_ALONG_EDGE: f32 = 0.25;
+/// Pixels of a cell of the paper texture, and of the finer granulation noise
+const WATERCOLOR_PAPER_CELL: f32 = 24.0;
+const WATERCOLOR_GRAIN_CELL: f32 = 4.0;
+const WATERCOLOR_PAPER_SEED: u64 = 0x9a9e;
+const WATERCOLOR_GRAIN_SEED: u64 = 0x6a41;
+
+/// Paint the image as watercolor, in three passes:
+/// - `diffusion_steps` times `wetness` (rounded) steps of anisotropic diffusion, guided by the
+///   luminance gradient so the pigment bleeds across edges more than along them
+/// - a paper texture of Perlin turbulence, darkening its valleys by up to `paper_texture_strength`
+/// - pigment granulation, where the pigment of the darker areas settles between the paper
+///   fibers, darkening the valleys of the paper and lightening its ridges by up to
+///   `pigment_granulation`
+pub fn apply_watercolor(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    diffusion_steps: u32,
+    paper_texture_strength: f32,
+    pigment_granulation: f32,
+    wetness: f32,
+) {
+    if width < 2 || height < 2 {
+        return;
+    }
+    let steps = (diffusion_steps as f32 * wetness.max(0.0)).round() as u32;
+    let mut source = buffer[..width * height * 4].to_vec();
+    for _ in 0..steps {
+        let luminance: Vec<f32> = source
+            .chunks_exact(4)
+            .map(|pixel| acescg_luminance([pixel[0], pixel[1], pixel[2]]))
+            .collect();
+        let at = |x: usize, y: usize| luminance[y * width + x];
+        for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+                // Unit normal of the edge, the direction the pigment bleeds the most in
+                let gradient = [at(right, y) - at(left, y), at(x, down) - at(x, up)];
+                let magnitude = gradient[0].hypot(gradient[1]);
+                let across_x = if magnitude > f32::EPSILON {
+                    (gradient[0] / magnitude).powi(2)
+                } else {
+                    // Flat areas spread evenly
+                    0.5
+                };
+                let weight_x = WATERCOLOR_ALONG_EDGE + (1.0 - WATERCOLOR_ALONG_EDGE) * across_x;
+                let weight_y =
+                    WATERCOLOR_ALONG_EDGE + (1.0 - WATERCOLOR_ALONG_EDGE) * (1.0 - across_x);
+
+                let index = (y * width + x) * 4;
+                for (channel, value) in pixel.iter_mut().enumerate() {
+                    let center = source[index + channel];
+                    let neighbour =
+                        |nx: usize, ny: usize| source[(ny * width + nx) * 4 + channel] - center;
+                    let flux = weight_x * (neighbour(left, y) + neighbour(right, y))
+                        + weight_y * (neighbour(x, up) + neighbour(x, down));
+                    *value = center + WATERCOLOR_DIFFUSION_RATE * flux;
+                }
+            }
+        }
+        source.copy_from_slice(&buffer[..width * height * 4]);
+    }
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let (px, py) = (x as f32, y as f32);
+            // Both in [0, 1], the paper is high on its ridges
+            let paper = turbulence(
+                [px / WATERCOLOR_PAPER_CELL, py / WATERCOLOR_PAPER_CELL],
+                4,
+                WATERCOLOR_PAPER_SEED,
+                true,
+                NoiseBasis::Perlin,
+            );
+            let grain = turbulence(
+                [px / WATERCOLOR_GRAIN_CELL, py / WATERCOLOR_GRAIN_CELL],
+                2,
+                WATERCOLOR_GRAIN_SEED,
+                true,
+                NoiseBasis::Perlin,
+            );
+            let pigment = 1.0 - acescg_luminance([pixel[0], pixel[1], pixel[2]]).clamp(0.0, 1.0);
+            // In [-1, 1], positive in the valleys of the paper
+            let valley = 1.0 - (paper + grain);
+            let factor = (1.0 - paper_texture_strength * (1.0 - paper))
+                * (1.0 - pigment_granulation * pigment * valley);
+            for channel in &mut pixel[..3] {
+                *channel *= factor.max(0.0);
+            }
+        }
+    }
+}
+
+/// Which edges `apply_cel_shade` draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum CelShadeMode {
+    /// Edges above the threshold, in the edge color
+    Single,
+    /// Weak edges, above half the threshold, in gray and strong edges in the edge color
+    DualThreshold,
+}
+
+/// Gray of the weak edges of `CelShadeMode::DualThreshold`, ACEScg
+const CEL_WEAK_EDGE_GRAY: f32 = 0.18;
+
+/// Oklab lightness of an ACEScg color (Björn Ottosson's reference matrices)
+pub fn oklab_lightness(rgb: [f32; 3]) -> f32 {
+    linear_srgb_to_oklab(mul_mat3(&ACESCG_TO_LINEAR_SRGB, rgb))[0]
+}
+
+/// What `apply_invert` and `apply_invert_luminance` flip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum InvertMode {
+    /// Every RGB channel, like a film negative
+    Color,
+    /// Only the Oklab lightness, keeping the hue and saturation
+    Luminance,
+}
+
+/// Invert the ACEScg RGB channels, keeping alpha: `max_scene_value - rgb`.
+/// Values above `max_scene_value`, which only an HDR framebuffer holds, become 0.
+pub fn apply_invert(buffer: &mut [f32], width: usize, height: usize, max_scene_value: f32) {
+    for pixel in buffer.chunks_exact_mut(4).take(width * height) {
+        for channel in &mut pixel[..3] {
+            *channel = (max_scene_value - *channel).max(0.0);
+        }
+    }
+}
+
+/// Invert the Oklab lightness of every pixel, `L` becoming `1 - L`, keeping its `a` and `b`.
+/// Lightnesses above 1 become 0.
+pub fn apply_invert_luminance(buffer: &mut [f32], width: usize, height: usize) {
+    for pixel in buffer.chunks_exact_mut(4).take(width * height) {
+        let rgb = mul_mat3(&ACESCG_TO_LINEAR_SRGB, [pixel[0], pixel[1], pixel[2]]);
+        let [l, a, b] = linear_srgb_to_oklab(rgb);
+        let inverted = oklab_to_linear_srgb([(1.0 - l).max(0.0), a, b]);
+        pixel[..3].copy_from_slice(&mul_mat3(&LINEAR_SRGB_TO_ACESCG, inverted));
+    }
+}
+
+/// Toon shading: quantize the Oklab lightness to `num_tones` flat bands, then draw the
+/// edges found by a Sobel filter on the original lightness where its gradient magnitude,
+/// normalized to [0, 1] on a black to white step, exceeds `edge_threshold`.
+/// RGB is scaled to reach the quantized lightness, which keeps the hue and saturation.
+pub fn apply_cel_shade(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    num_tones: u32,
+    edge_threshold: f32,
+    edge_color: [f32; 4],
+    mode: CelShadeMode,
+) {
+    if num_tones == 0 || width == 0 || height == 0 {
+        return;
+    }
+
+    // Read from a copy, so the quantized bands don't become edges
+    let lightness: Vec<f32> = buffer
+        .chunks_exact(4)
+        .take(width * height)
+        .map(|pixel| oklab_lightness([pixel[0], pixel[1], pixel[2]]))
+        .collect();
+    let at = |x: usize, y: usize| lightness[y * width + x];
+    let tones = num_tones as f32;
+    let weak_color = [
+        CEL_WEAK_EDGE_GRAY,
+        CEL_WEAK_EDGE_GRAY,
+        CEL_WEAK_EDGE_GRAY,
+        edge_color[3],
+    ];
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let current = at(x, y);
+            if current > 0.0 {
+                // Middle of the band, so the darkest one isn't black.
+                // Linear RGB scaled by k scales the Oklab lightness by the cube root of k.
+                let quantized = ((current * tones).floor() + 0.5) / tones;
+                let scale = (quantized / current).powi(3);
+                for channel in &mut pixel[..3] {
+                    *channel *= scale;
+                }
+            }
+
+            // Sobel, the edges are clamped
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let gradient_x = at(right, up) + 2.0 * at(right, y) + at(right, down)
+                - at(left, up)
+                - 2.0 * at(left, y)
+                - at(left, down);
+            let gradient_y = at(left, down) + 2.0 * at(x, down) + at(right, down)
+                - at(left, up)
+                - 2.0 * at(x, up)
+                - at(right, up);
+            let magnitude = gradient_x.hypot(gradient_y) / 4.0;
+
+            let color = if magnitude > edge_threshold {
+                edge_color
+            } else if mode == CelShadeMode::DualThreshold && magnitude > edge_threshold * 0.5 {
+                weak_color
+            } else {
+                continue;
+            };
+            for (channel, edge) in pixel.iter_mut().zip(color).take(3) {
+                *channel += (edge - *channel) * color[3];
+            }
+        }
+    }
+}
+
+/// Color scale of `apply_thermal`, from cold to hot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum ThermalColormap {
+    /// Black through purple, red and yellow to white, as on most thermal cameras
+    Ironbow,
+    /// Blue through green and yellow to red
+    Rainbow,
+    /// From the cold color to the hot color
+    Grayscale,
+    /// Three ACEScg colors, blended from `cold` to `warm` then to `hot`
+    Custom {
+        cold: [f32; 3],
+        warm: [f32; 3],
+        hot: [f32; 3],
+    },
+}
+
+/// 8 bit sRGB Ironbow palette, from the coldest to the hottest level
+#[rustfmt::skip]
+const IRONBOW_LUT: [[u8; 3]; 256] = [
+    [0, 0, 0], [1, 0, 4], [2, 0, 7], [4, 0, 11], [5, 0, 15], [6, 0, 19],
+    [7, 0, 22], [9, 0, 26], [10, 0, 30], [11, 0, 34], [12, 0, 37], [13, 0, 41],
+    [15, 0, 45], [16, 0, 48], [17, 0, 52], [18, 0, 56], [19, 0, 60], [21, 0, 63],
+    [22, 0, 67], [23, 0, 71], [24, 0, 75], [26, 0, 78], [27, 0, 82], [28, 0, 86],
+    [29, 0, 89], [30, 0, 93], [32, 0, 96], [34, 0, 97], [36, 0, 99], [37, 0, 100],
+    [39, 0, 101], [41, 0, 103], [43, 0, 104], [45, 0, 106], [46, 0, 107], [48, 0, 109],
+    [50, 0, 110], [52, 0, 112], [54, 0, 113], [55, 0, 114], [57, 0, 116], [59, 0, 117],
+    [61, 0, 119], [63, 0, 120], [64, 0, 122], [66, 0, 123], [68, 0, 124], [70, 0, 126],
+    [72, 0, 127], [73, 0, 129], [75, 0, 130], [77, 0, 132], [79, 0, 133], [81, 0, 135],
+    [82, 0, 136], [84, 0, 137], [86, 0, 139], [88, 0, 140], [90, 0, 142], [91, 0, 143],
+    [93, 0, 145], [95, 0, 146], [97, 0, 147], [99, 0, 149], [101, 0, 150], [103, 1, 149],
+    [105, 1, 149], [107, 2, 148], [109, 2, 148], [111, 3, 147], [113, 3, 147], [115, 4, 146],
+    [117, 4, 146], [119, 5, 145], [121, 5, 145], [124, 6, 144], [126, 6, 144], [128, 7, 143],
+    [130, 7, 143], [132, 8, 142], [134, 8, 142], [136, 9, 141], [138, 10, 140], [140, 10, 140],
+    [142, 11, 139], [144, 11, 139], [147, 12, 138], [149, 12, 138], [151, 13, 137], [153, 13, 137],
+    [155, 14, 136], [157, 14, 136], [159, 15, 135], [161, 15, 135], [163, 16, 134], [165, 16, 134],
+    [167, 17, 133], [170, 17, 133], [172, 18, 132], [174, 18, 132], [176, 19, 131], [178, 19, 131],
+    [180, 20, 130], [181, 21, 128], [183, 23, 126], [184, 24, 124], [185, 25, 122], [187, 27, 120],
+    [188, 28, 117], [189, 29, 115], [190, 30, 113], [192, 32, 111], [193, 33, 109], [194, 34, 107],
+    [196, 36, 105], [197, 37, 103], [198, 38, 101], [200, 40, 99], [201, 41, 97], [202, 42, 94],
+    [204, 44, 92], [205, 45, 90], [206, 46, 88], [207, 47, 86], [209, 49, 84], [210, 50, 82],
+    [211, 51, 80], [213, 53, 78], [214, 54, 76], [215, 55, 74], [217, 57, 71], [218, 58, 69],
+    [219, 59, 67], [221, 61, 65], [222, 62, 63], [223, 63, 61], [224, 64, 59], [226, 66, 57],
+    [227, 67, 55], [228, 68, 53], [230, 70, 51], [230, 71, 49], [231, 73, 48], [231, 74, 46],
+    [232, 76, 45], [232, 77, 44], [233, 79, 42], [234, 81, 41], [234, 82, 40], [235, 84, 39],
+    [235, 85, 37], [236, 87, 36], [236, 88, 35], [237, 90, 33], [237, 92, 32], [238, 93, 31],
+    [238, 95, 29], [239, 96, 28], [239, 98, 27], [240, 99, 25], [240, 101, 24], [241, 103, 23],
+    [241, 104, 22], [242, 106, 20], [242, 107, 19], [243, 109, 18], [243, 110, 16], [244, 112, 15],
+    [245, 114, 14], [245, 115, 12], [246, 117, 11], [246, 118, 10], [247, 120, 8], [247, 121, 7],
+    [248, 123, 6], [248, 125, 5], [249, 126, 3], [249, 128, 2], [250, 129, 1], [250, 131, 0],
+    [250, 133, 1], [250, 135, 1], [250, 136, 2], [251, 138, 2], [251, 140, 3], [251, 142, 3],
+    [251, 144, 4], [251, 146, 4], [251, 147, 5], [251, 149, 5], [252, 151, 6], [252, 153, 7],
+    [252, 155, 7], [252, 157, 8], [252, 158, 8], [252, 160, 9], [252, 162, 9], [252, 164, 10],
+    [253, 166, 10], [253, 168, 11], [253, 169, 11], [253, 171, 12], [253, 173, 12], [253, 175, 13],
+    [253, 177, 13], [253, 178, 14], [254, 180, 14], [254, 182, 15], [254, 184, 15], [254, 186, 16],
+    [254, 188, 16], [254, 189, 17], [254, 191, 18], [255, 193, 18], [255, 195, 19], [255, 197, 19],
+    [255, 199, 20], [255, 200, 21], [255, 202, 27], [255, 203, 33], [255, 205, 39], [255, 206, 44],
+    [255, 208, 50], [255, 209, 56], [255, 210, 62], [255, 212, 67], [255, 213, 73], [255, 215, 79],
+    [255, 216, 85], [255, 218, 90], [255, 219, 96], [255, 220, 102], [255, 222, 108], [255, 223, 113],
+    [255, 225, 119], [255, 226, 125], [255, 228, 131], [255, 229, 136], [255, 231, 142], [255, 232, 148],
+    [255, 233, 154], [255, 235, 159], [255, 236, 165], [255, 238, 171], [255, 239, 177], [255, 241, 182],
+    [255, 242, 188], [255, 243, 194], [255, 245, 200], [255, 246, 205], [255, 248, 211], [255, 249, 217],
+    [255, 251, 223], [255, 252, 228], [255, 254, 234], [255, 255, 240],
+];
+
+/// Map the ACEScg luminance of each pixel to a thermal camera color scale, in ACEScg.
+/// Luminances above 1 saturate to the hottest color. `sensor_noise` adds a fixed pattern
+/// of per-pixel offsets to the measured value, like the uneven response of a real sensor.
+/// `cold_color` and `hot_color` are the ends of the `Grayscale` colormap.
+pub fn apply_thermal(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    cold_color: [f32; 3],
+    hot_color: [f32; 3],
+    colormap: ThermalColormap,
+    sensor_noise: f32,
+) {
+    let lerp = |a: [f32; 3], b: [f32; 3], t: f32| -> [f32; 3] {
+        std::array::from_fn(|channel| a[channel] + (b[channel] - a[channel]) * t)
+    };
+    let ironbow: Vec<[f32; 3]> = match colormap {
+        ThermalColormap::Ironbow => IRONBOW_LUT
+            .iter()
+            .map(|&rgb| srgb_u8_to_acescg(rgb))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let mut t = acescg_luminance([pixel[0], pixel[1], pixel[2]]);
+            if sensor_noise > 0.0 {
+                t += (lattice_value(x as i32, y as i32, 0) - 0.5) * 2.0 * sensor_noise;
+            }
+            let t = t.clamp(0.0, 1.0);
+
+            let rgb = match colormap {
+                ThermalColormap::Ironbow => {
+                    let level = t * 255.0;
+                    let low = level.floor() as usize;
+                    let high = (low + 1).min(255);
+                    lerp(ironbow[low], ironbow[high], level - low as f32)
+                }
+                ThermalColormap::Rainbow => {
+                    // Hue from blue (2/3) down to red (0)
+                    let encoded = hsv_to_rgb((1.0 - t) * 2.0 / 3.0, 1.0, 1.0);
+                    mul_mat3(&LINEAR_SRGB_TO_ACESCG, encoded.map(srgb_to_linear))
+                }
+                ThermalColormap::Grayscale => lerp(cold_color, hot_color, t),
+                ThermalColormap::Custom { cold, warm, hot } => {
+                    if t < 0.5 {
+                        lerp(cold, warm, t * 2.0)
+                    } else {
+                        lerp(warm, hot, t * 2.0 - 1.0)
+                    }
+                }
+            };
+            pixel[..3].copy_from_slice(&rgb);
+        }
+    }
+}
+
+/// Shape of the out of focus highlights of `apply_bokeh_blur`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum BokehShape {
+    /// Round aperture
+    Disk,
+    /// Six aperture blades
+    Hexagon,
+    /// Disks clipped towards the center of the image, like the vignetting of a fast lens
+    CatEye,
+}
+
+/// Read the depth channel of an OpenEXR file, `Z` or else the first one,
+/// resized to `width` x `height` with the nearest pixel
+pub fn read_exr_depth(
+    path: impl AsRef<Path>,
+    width: usize,
+    height: usize,
+) -> Result<Vec<f32>, ImageError> {
+    let image = read_first_flat_layer_from_file(path)?;
+    let layer = image.layer_data;
+    let (depth_width, depth_height) = (layer.size.width(), layer.size.height());
+    if depth_width == 0 || depth_height == 0 {
+        return Err(ImageError::InvalidDimensions {
+            width: depth_width,
+            height: depth_height,
+        });
+    }
+    let channels = &layer.channel_data.list;
+    let channel = channels
+        .iter()
+        .find(|channel| channel.name.to_string() == "Z")
+        .or_else(|| channels.first())
+        .ok_or_else(|| ImageError::UnsupportedFormat {
+            format: "OpenEXR without channels".to_owned(),
+        })?;
+    let depth: Vec<f32> = channel.sample_data.values_as_f32().collect();
+
+    let mut resized = vec![0.0; width * height];
+    for (y, row) in resized.chunks_exact_mut(width.max(1)).enumerate() {
+        let source_y = y * depth_height / height;
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = depth[source_y * depth_width + x * depth_width / width];
+        }
+    }
+    Ok(resized)
+}
+
+/// How far the second disk of the cat eye bokeh is moved at the corners,
+/// relative to the circle of confusion
+const CAT_EYE_OFFSET: f32 = 0.6;
+
+/// Depth of field: blur each pixel by its circle of confusion, `aperture` pixels per unit
+/// of distance between its depth and `focus_depth`, up to `max_radius` pixels.
+/// Each pixel is scattered over the kernel of its own circle of confusion, divided by
+/// the kernel area so bright highlights spread into visible bokeh shapes, then every
+/// pixel is normalized by the weight it received.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_bokeh_blur(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    depth_buffer: &[f32],
+    focus_depth: f32,
+    aperture: f32,
+    max_radius: f32,
+    shape: BokehShape,
+) {
+    let source = buffer.to_vec();
+    apply_bokeh_blur_into(
+        &source,
+        buffer,
+        width,
+        height,
+        depth_buffer,
+        focus_depth,
+        aperture,
+        max_radius,
+        shape,
+    );
+}
+
+/// `apply_bokeh_blur` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+#[allow(clippy::too_many_arguments)]
+pub fn apply_bokeh_blur_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    depth_buffer: &[f32],
+    focus_depth: f32,
+    aperture: f32,
+    max_radius: f32,
+    shape: BokehShape,
+) {
+    if aperture <= 0.0 || max_radius <= 0.0 || depth_buffer.len() < width * height {
+        output.copy_from_slice(input);
+        return;
+    }
+
+    let mut accumulated = vec![0.0_f32; width * height * 4];
+    let mut weights = vec![0.0_f32; width * height];
+    let center = [width as f32 * 0.5, height as f32 * 0.5];
+    let half_diagonal = center[0].hypot(center[1]).max(1.0);
+    let half_sqrt3 = 3.0_f32.sqrt() * 0.5;
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let radius = ((depth_buffer[index] - focus_depth).abs() * aperture).min(max_radius);
+            let color = &input[index * 4..index * 4 + 4];
+            // Direction of the cat eye clipping, towards the center of the image
+            let clip = [
+                (center[0] - x as f32) / half_diagonal * CAT_EYE_OFFSET * radius,
+                (center[1] - y as f32) / half_diagonal * CAT_EYE_OFFSET * radius,
+            ];
+            let inside = |dx: f32, dy: f32| match shape {
+                BokehShape::Disk => dx.hypot(dy) <= radius,
+                BokehShape::Hexagon => {
+                    let (ax, ay) = (dx.abs(), dy.abs());
+                    ay <= half_sqrt3 * radius && half_sqrt3 * ax + 0.5 * ay <= half_sqrt3 * radius
+                }
+                BokehShape::CatEye => {
+                    dx.hypot(dy) <= radius && (dx - clip[0]).hypot(dy - clip[1]) <= radius
+                }
+            };
+
+            let reach = radius.floor() as i32;
+            let weight = 1.0 / (std::f32::consts::PI * radius * radius).max(1.0);
+            for dy in -reach..=reach {
+                let target_y = y as i32 + dy;
+                if target_y < 0 || target_y >= height as i32 {
+                    continue;
+                }
+                for dx in -reach..=reach {
+                    let target_x = x as i32 + dx;
+                    if target_x < 0 || target_x >= width as i32 || !inside(dx as f32, dy as f32) {
+                        continue;
+                    }
+                    let target = target_y as usize * width + target_x as usize;
+                    for (sum, value) in accumulated[target * 4..target * 4 + 4]
+                        .iter_mut()
+                        .zip(color)
+                    {
+                        *sum += value * weight;
+                    }
+                    weights[target] += weight;
+                }
+            }
+        }
+    }
+
+    // Every pixel is covered at least by its own kernel
+    for ((pixel, sum), weight) in output
+        .chunks_exact_mut(4)
+        .zip(accumulated.chunks_exact(4))
+        .zip(&weights)
+    {
+        for (value, sum) in pixel.iter_mut().zip(sum) {
+            *value = sum / weight;
+        }
+    }
+}
+
+/// Tilt-shift: a fake depth of field for flat renders, with a horizontal focal plane at
+/// `focus_y`, from 0 at the top to 1 at the bottom. With no depth buffer, the circle of
+/// confusion of a row grows with its distance to the focal plane, up to `aperture` pixels
+/// on the row farthest from it. Rows with the same whole radius share one disk kernel.
+pub fn apply_pseudo_dof(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    focus_y: f32,
+    aperture: f32,
+) {
+    let source = buffer.to_vec();
+    apply_pseudo_dof_into(&source, buffer, width, height, focus_y, aperture);
+}
+
+/// `apply_pseudo_dof` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+pub fn apply_pseudo_dof_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    focus_y: f32,
+    aperture: f32,
+) {
+    output.copy_from_slice(input);
+    if aperture <= 0.0 || height == 0 {
+        return;
+    }
+    let focus_y = focus_y.clamp(0.0, 1.0);
+    let farthest = focus_y.max(1.0 - focus_y).max(f32::EPSILON);
+    let coc: Vec<usize> = (0..height)
+        .map(|y| {
+            let row = (y as f32 + 0.5) / height as f32;
+            ((row - focus_y).abs() / farthest * aperture).round() as usize
+        })
+        .collect();
+
+    let max_radius = coc.iter().copied().max().unwrap_or(0);
+    for radius in 1..=max_radius {
+        let rows: Vec<usize> = (0..height).filter(|&y| coc[y] == radius).collect();
+        if rows.is_empty() {
+            continue;
+        }
+        let reach = radius as i32;
+        let kernel: Vec<[i32; 2]> = (-reach..=reach)
+            .flat_map(|dy| (-reach..=reach).map(move |dx| [dx, dy]))
+            .filter(|[dx, dy]| (*dx as f32).hypot(*dy as f32) <= radius as f32)
+            .collect();
+        for y in rows {
+            for x in 0..width {
+                let mut sum = [0.0; 4];
+                let mut count = 0.0;
+                for [dx, dy] in &kernel {
+                    let (sx, sy) = (x as i32 + dx, y as i32 + dy);
+                    if sx < 0 || sy < 0 || sx >= width as i32 || sy >= height as i32 {
+                        continue;
+                    }
+                    let index = (sy as usize * width + sx as usize) * 4;
+                    for (sum, value) in sum.iter_mut().zip(&input[index..index + 4]) {
+                        *sum += value;
+                    }
+                    count += 1.0;
+                }
+                let index = (y * width + x) * 4;
+                for (value, sum) in output[index..index + 4].iter_mut().zip(sum) {
+                    *value = sum / count;
+                }
+            }
+        }
+    }
+}
+
+/// Edge preserving noise reduction: every pixel becomes an average of its neighbours within
+/// `3 * spatial_sigma` pixels, weighted by a Gaussian of their distance (`spatial_sigma`)
+/// times a Gaussian of their Oklab color difference (`range_sigma`), so flat areas are
+/// smoothed while neighbours across an edge barely count. Alpha is averaged with the same
+/// weights.
+///
+/// Performance: this is O(n * r^2) for n pixels and a radius r, so at a radius of 15
+/// (`spatial_sigma` of 5) every pixel reads ~900 neighbours. Rows are spread across the
+/// rayon thread pool, but large buffers still take seconds.
+pub fn apply_bilateral_filter(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    spatial_sigma: f32,
+    range_sigma: f32,
+) {
+    let source = buffer[..width * height * 4].to_vec();
+    apply_bilateral_filter_into(
+        &source,
+        &mut buffer[..width * height * 4],
+        width,
+        height,
+        spatial_sigma,
+        range_sigma,
+    );
+}
+
+/// `apply_bilateral_filter` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+pub fn apply_bilateral_filter_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    spatial_sigma: f32,
+    range_sigma: f32,
+) {
+    if spatial_sigma <= 0.0 || range_sigma <= 0.0 || width == 0 || height == 0 {
+        output.copy_from_slice(input);
+        return;
+    }
+    let lab: Vec<[f32; 3]> = input
+        .chunks_exact(4)
+        .map(|pixel| {
+            linear_srgb_to_oklab(mul_mat3(
+                &ACESCG_TO_LINEAR_SRGB,
+                [pixel[0], pixel[1], pixel[2]],
+            ))
+        })
+        .collect();
+
+    let radius = (3.0 * spatial_sigma).ceil() as isize;
+    let spatial_denominator = 2.0 * spatial_sigma * spatial_sigma;
+    let range_denominator = 2.0 * range_sigma * range_sigma;
+    // Spatial weights only depend on the offset, computed once for the whole image
+    let side = (2 * radius + 1) as usize;
+    let spatial: Vec<f32> = (-radius..=radius)
+        .flat_map(|dy| (-radius..=radius).map(move |dx| (dx * dx + dy * dy) as f32))
+        .map(|distance_squared| (-distance_squared / spatial_denominator).exp())
+        .collect();
+
+    output
+        .par_chunks_exact_mut(width * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let center = lab[y * width + x];
+                let mut sum = [0.0; 4];
+                let mut total_weight = 0.0;
+                for dy in -radius..=radius {
+                    let sy = y as isize + dy;
+                    if sy < 0 || sy >= height as isize {
+                        continue;
+                    }
+                    for dx in -radius..=radius {
+                        let sx = x as isize + dx;
+                        if sx < 0 || sx >= width as isize {
+                            continue;
+                        }
+                        let index = sy as usize * width + sx as usize;
+                        let neighbour = lab[index];
+                        let color_distance_squared = (0..3)
+                            .map(|c| (neighbour[c] - center[c]).powi(2))
+                            .sum::<f32>();
+                        let weight = spatial
+                            [(dy + radius) as usize * side + (dx + radius) as usize]
+                            * (-color_distance_squared / range_denominator).exp();
+                        for (sum, value) in sum.iter_mut().zip(&input[index * 4..index * 4 + 4]) {
+                            *sum += value * weight;
+                        }
+                        total_weight += weight;
+                    }
+                }
+                // The center pixel always has a weight of 1, so this is never 0
+                for (value, sum) in pixel.iter_mut().zip(sum) {
+                    *value = sum / total_weight;
+                }
+            }
+        });
+}
+
+/// Choices of mirrored segments offered for the kaleidoscope
+pub const KALEIDOSCOPE_SEGMENTS: [u32; 6] = [2, 3, 4, 6, 8, 12];
+
+/// Where the mirrors of `apply_kaleidoscope` meet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum KaleidoscopeMode {
+    /// Segments all around the image center
+    Center,
+    /// Segments fanning out of the top left corner, across a quarter turn
+    Corner,
+}
+
+/// Fold the image like a kaleidoscope: every pixel is turned into polar coordinates around
+/// the center, or the corner, its angle folded back into the first of `segments` wedges,
+/// mirroring every other one, and the source sampled there. `angle_offset` in degrees turns
+/// the source wedge, `zoom` magnifies it.
+pub fn apply_kaleidoscope(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    segments: u32,
+    angle_offset: f32,
+    zoom: f32,
+    mode: KaleidoscopeMode,
+) {
+    let source = buffer[..width * height * 4].to_vec();
+    apply_kaleidoscope_into(
+        &source,
+        &mut buffer[..width * height * 4],
+        width,
+        height,
+        segments,
+        angle_offset,
+        zoom,
+        mode,
+    );
+}
+
+/// `apply_kaleidoscope` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+#[allow(clippy::too_many_arguments)]
+pub fn apply_kaleidoscope_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    segments: u32,
+    angle_offset: f32,
+    zoom: f32,
+    mode: KaleidoscopeMode,
+) {
+    if width == 0 || height == 0 || segments == 0 {
+        output.copy_from_slice(input);
+        return;
+    }
+
+    let (origin, turn) = match mode {
+        KaleidoscopeMode::Center => (
+            [width as f32 * 0.5, height as f32 * 0.5],
+            std::f32::consts::TAU,
+        ),
+        KaleidoscopeMode::Corner => ([0.0, 0.0], std::f32::consts::FRAC_PI_2),
+    };
+    let wedge = turn / segments as f32;
+    let offset = angle_offset.to_radians();
+    let zoom = zoom.max(1e-3);
+
+    for (index, pixel) in output.chunks_exact_mut(4).take(width * height).enumerate() {
+        let dx = (index % width) as f32 + 0.5 - origin[0];
+        let dy = (index / width) as f32 + 0.5 - origin[1];
+        let radius = dx.hypot(dy) / zoom;
+        // Every other wedge is a mirror image of the first one
+        let mut angle = (dy.atan2(dx) - offset).rem_euclid(2.0 * wedge);
+        if angle > wedge {
+            angle = 2.0 * wedge - angle;
+        }
+        let (sin, cos) = (angle + offset).sin_cos();
+        let x = origin[0] + radius * cos - 0.5;
+        let y = origin[1] + radius * sin - 0.5;
+        pixel.copy_from_slice(&apply_bilinear_sample(input, width, height, x, y));
+    }
+}
+
+/// Lens model of `apply_fisheye_distortion`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum FisheyeMode {
+    /// Magnification falls off away from the center, straight lines bulge outwards
+    Barrel,
+    /// Magnification grows away from the center, straight lines bend inwards
+    Pincushion,
+    /// Equi-solid angle fisheye, `r = 2 sin(θ / 2)`, covering `fov` across the image width
+    Equisolid,
+}
+
+/// RGBA of a buffer at continuous pixel coordinates, (0, 0) being the center of the first
+/// pixel. The four closest pixels are interpolated, coordinates are clamped to the edges.
+pub fn apply_bilinear_sample(
+    buffer: &[f32],
+    width: usize,
+    height: usize,
+    x: f32,
+    y: f32,
+) -> [f32; 4] {
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let pixel = |x: usize, y: usize| &buffer[(y * width + x) * 4..(y * width + x) * 4 + 4];
+    let mut rgba = [0.0; 4];
+    for (c, value) in rgba.iter_mut().enumerate() {
+        let top = lerp(pixel(x0, y0)[c], pixel(x1, y0)[c], tx);
+        let bottom = lerp(pixel(x0, y1)[c], pixel(x1, y1)[c], tx);
+        *value = lerp(top, bottom, ty);
+    }
+    rgba
+}
+
+/// Same as `apply_bilinear_sample`, with the x coordinates wrapping around instead of being
+/// clamped, for images whose left and right edges meet
+pub fn apply_bilinear_sample_wrap_x(
+    buffer: &[f32],
+    width: usize,
+    height: usize,
+    x: f32,
+    y: f32,
+) -> [f32; 4] {
+    let x = x.rem_euclid(width as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    // `rem_euclid` can round up to `width`
+    let x0 = (x.floor() as usize).min(width - 1);
+    let y0 = y.floor() as usize;
+    let (x1, y1) = ((x0 + 1) % width, (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let pixel = |x: usize, y: usize| &buffer[(y * width + x) * 4..(y * width + x) * 4 + 4];
+    let mut rgba = [0.0; 4];
+    for (c, value) in rgba.iter_mut().enumerate() {
+        let top = lerp(pixel(x0, y0)[c], pixel(x1, y0)[c], tx);
+        let bottom = lerp(pixel(x0, y1)[c], pixel(x1, y1)[c], tx);
+        *value = lerp(top, bottom, ty);
+    }
+    rgba
+}
+
+/// Direction of `apply_rect_to_polar` and `apply_polar_to_rect`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum PolarMode {
+    /// Rows become rings around the center, columns become rays
+    RectToPolar,
+    /// Rings around the center become rows, rays become columns
+    PolarToRect,
+}
+
+/// Center in pixels, from a center relative to the image size, and the distance from it to
+/// the farthest corner
+fn polar_frame(width: usize, height: usize, center: [f32; 2]) -> ([f32; 2], f32) {
+    let center = [center[0] * width as f32, center[1] * height as f32];
+    let max_radius = [0.0, width as f32]
+        .iter()
+        .flat_map(|&x| [0.0, height as f32].map(|y| (x - center[0]).hypot(y - center[1])))
+        .fold(0.0, f32::max)
+        .max(1.0);
+    (center, max_radius)
+}
+
+/// Wrap the image around `center`, relative to the image size: the width covers a full
+/// turn starting at `angle_offset` degrees, the height goes from the center to the
+/// farthest corner. A horizontal gradient becomes a sunburst.
+pub fn apply_rect_to_polar(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    center: [f32; 2],
+    angle_offset: f32,
+) {
+    let source = buffer[..width * height * 4].to_vec();
+    apply_rect_to_polar_into(
+        &source,
+        &mut buffer[..width * height * 4],
+        width,
+        height,
+        center,
+        angle_offset,
+    );
+}
+
+/// `apply_rect_to_polar` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+pub fn apply_rect_to_polar_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    center: [f32; 2],
+    angle_offset: f32,
+) {
+    if width == 0 || height == 0 {
+        output.copy_from_slice(input);
+        return;
+    }
+
+    let (center, max_radius) = polar_frame(width, height, center);
+    let offset = angle_offset.to_radians();
+    for (index, pixel) in output.chunks_exact_mut(4).take(width * height).enumerate() {
+        let dx = (index % width) as f32 + 0.5 - center[0];
+        let dy = (index / width) as f32 + 0.5 - center[1];
+        let theta = (dy.atan2(dx) - offset).rem_euclid(std::f32::consts::TAU);
+        // The first and last columns meet at the offset angle
+        let x = theta / std::f32::consts::TAU * width as f32 - 0.5;
+        let y = dx.hypot(dy) / max_radius * height as f32 - 0.5;
+        pixel.copy_from_slice(&apply_bilinear_sample_wrap_x(input, width, height, x, y));
+    }
+}
+
+/// Inverse of `apply_rect_to_polar`: unroll the rings around `center` into rows
+pub fn apply_polar_to_rect(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    center: [f32; 2],
+    angle_offset: f32,
+) {
+    let source = buffer[..width * height * 4].to_vec();
+    apply_polar_to_rect_into(
+        &source,
+        &mut buffer[..width * height * 4],
+        width,
+        height,
+        center,
+        angle_offset,
+    );
+}
+
+/// `apply_polar_to_rect` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+pub fn apply_polar_to_rect_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    center: [f32; 2],
+    angle_offset: f32,
+) {
+    if width == 0 || height == 0 {
+        output.copy_from_slice(input);
+        return;
+    }
+
+    let (center, max_radius) = polar_frame(width, height, center);
+    let offset = angle_offset.to_radians();
+    for (index, pixel) in output.chunks_exact_mut(4).take(width * height).enumerate() {
+        let theta = ((index % width) as f32 + 0.5) / width as f32 * std::f32::consts::TAU + offset;
+        let radius = ((index / width) as f32 + 0.5) / height as f32 * max_radius;
+        let (sin, cos) = theta.sin_cos();
+        let x = center[0] + radius * cos - 0.5;
+        let y = center[1] + radius * sin - 0.5;
+        pixel.copy_from_slice(&apply_bilinear_sample(input, width, height, x, y));
+    }
+}
+
+/// Distort the image as seen through a lens. Radii are relative to half the image width.
+/// `strength` is the coefficient of the radial polynomial of the barrel and pincushion
+/// models, and blends the undistorted image with the fisheye for `Equisolid`.
+/// `fov` is the horizontal field of view of the fisheye, in degrees. It is seen as a
+/// pinhole camera with the same magnification at the center as the source image.
+/// Pixels looking outside of the source, or behind it, are transparent black.
+pub fn apply_fisheye_distortion(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    fov: f32,
+    strength: f32,
+    mode: FisheyeMode,
+) {
+    let source = buffer[..width * height * 4].to_vec();
+    apply_fisheye_distortion_into(
+        &source,
+        &mut buffer[..width * height * 4],
+        width,
+        height,
+        fov,
+        strength,
+        mode,
+    );
+}
+
+/// `apply_fisheye_distortion` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+pub fn apply_fisheye_distortion_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    fov: f32,
+    strength: f32,
+    mode: FisheyeMode,
+) {
+    if width == 0 || height == 0 {
+        output.copy_from_slice(input);
+        return;
+    }
+
+    let half_width = width as f32 * 0.5;
+    let center = [half_width, height as f32 * 0.5];
+    // 2 sin(θ / 2) at the edge of the fisheye
+    let edge_chord = 2.0 * (fov.to_radians() * 0.25).sin();
+
+    for (index, pixel) in output.chunks_exact_mut(4).take(width * height).enumerate() {
+        let offset = [
+            ((index % width) as f32 + 0.5 - center[0]) / half_width,
+            ((index / width) as f32 + 0.5 - center[1]) / half_width,
+        ];
+        let radius = offset[0].hypot(offset[1]);
+
+        let source_radius = match mode {
+            FisheyeMode::Barrel => Some(radius * (1.0 + strength * radius * radius)),
+            FisheyeMode::Pincushion => Some(radius / (1.0 + strength * radius * radius)),
+            FisheyeMode::Equisolid => {
+                let chord = radius * edge_chord;
+                // Outside of the image circle, or 90° or more off axis where a pinhole sees nothing
+                if radius > 1.0 || chord >= std::f32::consts::SQRT_2 {
+                    None
+                } else {
+                    let theta = 2.0 * (chord * 0.5).asin();
+                    let pinhole = theta.tan() / edge_chord;
+                    Some(radius + (pinhole - radius) * strength)
+                }
+            }
+        };
+        let scale = match source_radius {
+            Some(source_radius) if radius > 0.0 => source_radius / radius,
+            Some(_) => 1.0,
+            None => {
+                pixel.fill(0.0);
+                continue;
+            }
+        };
+
+        let x = center[0] + offset[0] * scale * half_width - 0.5;
+        let y = center[1] + offset[1] * scale * half_width - 0.5;
+        if x < -0.5 || y < -0.5 || x > width as f32 - 0.5 || y > height as f32 - 0.5 {
+            pixel.fill(0.0);
+        } else {
+            pixel.copy_from_slice(&apply_bilinear_sample(input, width, height, x, y));
+        }
+    }
+}
+
+/// Kind of lens chromatic aberration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ChromaticAberrationMode {
+    /// Lateral: the channels are magnified differently, and land next to each other
+    Transverse,
+    /// Axial: the channels are focused at different depths, and blur by different amounts
+    Longitudinal,
+}
+
+/// Move the red channel by `shift_r` pixels and the blue channel by `shift_b`, sampled
+/// bilinearly, green staying in place. With `radial_mode`, the shifts are the ones at the
+/// image corners, going outwards for positive values, and shrink to nothing at the center,
+/// like the lateral aberration of a lens.
+pub fn apply_chromatic_aberration(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    shift_r: [f32; 2],
+    shift_b: [f32; 2],
+    radial_mode: bool,
+) {
+    let source = buffer[..width * height * 4].to_vec();
+    apply_chromatic_aberration_into(
+        &source,
+        &mut buffer[..width * height * 4],
+        width,
+        height,
+        shift_r,
+        shift_b,
+        radial_mode,
+    );
+}
+
+/// `apply_chromatic_aberration` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+pub fn apply_chromatic_aberration_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    shift_r: [f32; 2],
+    shift_b: [f32; 2],
+    radial_mode: bool,
+) {
+    output.copy_from_slice(input);
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let center = [width as f32 * 0.5, height as f32 * 0.5];
+    let corner_distance = center[0].hypot(center[1]);
+    for (index, pixel) in output.chunks_exact_mut(4).take(width * height).enumerate() {
+        let (x, y) = ((index % width) as f32, (index / width) as f32);
+        let scale = if radial_mode {
+            [
+                (x + 0.5 - center[0]) / corner_distance,
+                (y + 0.5 - center[1]) / corner_distance,
+            ]
+        } else {
+            [1.0, 1.0]
+        };
+        // A channel moved by `shift` shows what was `shift` pixels behind
+        let sample = |shift: [f32; 2]| {
+            apply_bilinear_sample(
+                input,
+                width,
+                height,
+                x - shift[0] * scale[0],
+                y - shift[1] * scale[1],
+            )
+        };
+        pixel[0] = sample(shift_r)[0];
+        pixel[2] = sample(shift_b)[2];
+    }
+}
+
+/// Axial chromatic aberration, with green in focus: red and blue are spread over blur
+/// circles of different sizes, red the widest with a radius of `max_blur` pixels
+pub fn apply_longitudinal_aberration(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    max_blur: f32,
+) {
+    if width == 0 || height == 0 || max_blur <= 0.0 {
+        return;
+    }
+
+    // A blur circle of radius r is about a Gaussian of standard deviation r / 2
+    for (channel, sigma) in [(0, max_blur * 0.5), (2, max_blur * 0.25)] {
+        let mut blurred = buffer[..width * height * 4].to_vec();
+        apply_gaussian_blur(&mut blurred, width, height, sigma);
+        for (pixel, blurred) in buffer.chunks_exact_mut(4).zip(blurred.chunks_exact(4)) {
+            pixel[channel] = blurred[channel];
+        }
+    }
+}
+
+/// Read a displacement map from the `R` and `G` channels of an OpenEXR file, or else its
+/// first two, into an RGBA buffer resized to `width` x `height` with the nearest pixel
+pub fn read_exr_displacement(
+    path: impl AsRef<Path>,
+    width: usize,
+    height: usize,
+) -> Result<Vec<f32>, ImageError> {
+    let image = read_first_flat_layer_from_file(path)?;
+    let layer = image.layer_data;
+    let (map_width, map_height) = (layer.size.width(), layer.size.height());
+    if map_width == 0 || map_height == 0 {
+        return Err(ImageError::InvalidDimensions {
+            width: map_width,
+            height: map_height,
+        });
+    }
+    let channels = &layer.channel_data.list;
+    let channel = |name: &str, index: usize| {
+        channels
+            .iter()
+            .find(|channel| channel.name.to_string() == name)
+            .or_else(|| channels.get(index))
+            .map(|channel| channel.sample_data.values_as_f32().collect::<Vec<f32>>())
+    };
+    let (Some(dx), Some(dy)) = (channel("R", 0), channel("G", 1)) else {
+        return Err(ImageError::UnsupportedFormat {
+            format: "OpenEXR with less than two channels".to_owned(),
+        });
+    };
+
+    let mut resized = vec![0.0; width * height * 4];
+    for (i, pixel) in resized.chunks_exact_mut(4).enumerate() {
+        let source_y = (i / width) * map_height / height;
+        let source = source_y * map_width + (i % width) * map_width / width;
+        pixel.copy_from_slice(&[dx[source], dy[source], 0.0, 1.0]);
+    }
+    Ok(resized)
+}
+
+/// Displacement map of crossing sine waves, to check `apply_grid_warp`
+pub fn generate_test_warp(width: usize, height: usize) -> Vec<f32> {
+    // Pixels between two crests
+    let wavelength = 64.0;
+    let mut displacement = vec![0.0; width * height * 4];
+    for (i, pixel) in displacement.chunks_exact_mut(4).enumerate() {
+        let (x, y) = ((i % width) as f32, (i / width) as f32);
+        pixel.copy_from_slice(&[
+            (y / wavelength * std::f32::consts::TAU).sin(),
+            (x / wavelength * std::f32::consts::TAU).sin(),
+            0.0,
+            1.0,
+        ]);
+    }
+    displacement
+}
+
+/// Move every pixel by a displacement map: each pixel reads the source at its position
+/// plus the `R` (x) and `G` (y) channels of `displacement`, in [-1, 1], times
+/// `disp_scale` pixels. Reads outside of the image are clamped to its edges.
+pub fn apply_grid_warp(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    displacement: &[f32],
+    disp_scale: f32,
+) {
+    let source = buffer[..width * height * 4].to_vec();
+    apply_grid_warp_into(
+        &source,
+        &mut buffer[..width * height * 4],
+        width,
+        height,
+        displacement,
+        disp_scale,
+    );
+}
+
+/// `apply_grid_warp` reading `input` and writing `output`, of the same size,
+/// without copying the image first
+pub fn apply_grid_warp_into(
+    input: &[f32],
+    output: &mut [f32],
+    width: usize,
+    height: usize,
+    displacement: &[f32],
+    disp_scale: f32,
+) {
+    if width == 0 || height == 0 || displacement.len() < width * height * 4 {
+        output.copy_from_slice(input);
+        return;
+    }
+
+    for (i, (pixel, offset)) in output
+        .chunks_exact_mut(4)
+        .zip(displacement.chunks_exact(4))
+        .take(width * height)
+        .enumerate()
+    {
+        let x = (i % width) as f32 + offset[0] * disp_scale;
+        let y = (i / width) as f32 + offset[1] * disp_scale;
+        pixel.copy_from_slice(&apply_bilinear_sample(input, width, height, x, y));
+    }
+}
+
+/// Parameters of the camera render mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CameraSettings {
+    // Index into the list of cameras found at startup
+    pub device: u32,
+    // Mirror the feed, as expected from a front facing camera
+    pub flip_horizontal: bool,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            device: 0,
+            flip_horizontal: true,
+        }
+    }
+}
+
+/// Byte layout of a raw camera frame
+#[cfg_attr(not(feature = "camera-input"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraPixelFormat {
+    /// 4:2:2 luma / chroma, two pixels in `Y0 U Y1 V`
+    Yuyv,
+    /// 8 bit sRGB, 3 bytes per pixel
+    Rgb,
+}
+
+/// Camera image converted to ACEScg RGBA
+#[derive(Debug, Clone)]
+pub struct CameraFrame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<f32>,
+}
+
+#[cfg_attr(not(feature = "camera-input"), allow(dead_code))]
+impl CameraFrame {
+    /// Convert raw camera bytes. YUYV goes through the BT.601 matrix cameras use,
+    /// then both formats are sRGB decoded and converted to ACEScg.
+    /// Fails when `bytes` is too short for the resolution.
+    pub fn from_raw(
+        bytes: &[u8],
+        width: usize,
+        height: usize,
+        format: CameraPixelFormat,
+    ) -> Result<Self, ImageError> {
+        let pixel_count = width * height;
+        let mut pixels = Vec::with_capacity(pixel_count * 4);
+        match format {
+            CameraPixelFormat::Yuyv => {
+                // Pixels come in pairs sharing their chroma
+                if width % 2 != 0 {
+                    return Err(ImageError::InvalidDimensions { width, height });
+                }
+                if bytes.len() < pixel_count * 2 {
+                    return Err(ImageError::BufferSizeMismatch {
+                        expected: pixel_count * 2,
+                        actual: bytes.len(),
+                    });
+                }
+                for chunk in bytes[..pixel_count * 2].chunks_exact(4) {
+                    let u = chunk[1] as f32 - 128.0;
+                    let v = chunk[3] as f32 - 128.0;
+                    for luma in [chunk[0], chunk[2]] {
+                        // Studio range BT.601
+                        let y = 1.164 * (luma as f32 - 16.0);
+                        let encoded = [y + 1.596 * v, y - 0.392 * u - 0.813 * v, y + 2.017 * u];
+                        let linear = encoded.map(|c| srgb_to_linear((c / 255.0).clamp(0.0, 1.0)));
+                        pixels.extend(mul_mat3(&LINEAR_SRGB_TO_ACESCG, linear));
+                        pixels.push(1.0);
+                    }
+                }
+            }
+            CameraPixelFormat::Rgb => {
+                if bytes.len() < pixel_count * 3 {
+                    return Err(ImageError::BufferSizeMismatch {
+                        expected: pixel_count * 3,
+                        actual: bytes.len(),
+                    });
+                }
+                for rgb in bytes[..pixel_count * 3].chunks_exact(3) {
+                    pixels.extend(srgb_u8_to_acescg([rgb[0], rgb[1], rgb[2]]));
+                    pixels.push(1.0);
+                }
+            }
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// Fit the camera frame in the buffer keeping its aspect ratio, centered,
+/// with nearest neighbour sampling. Pixels outside of the frame are left untouched.
+pub fn render_camera_frame(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    frame: &CameraFrame,
+    flip_horizontal: bool,
+) {
+    if frame.width == 0 || frame.height == 0 {
+        return;
+    }
+    let scale = (width as f32 / frame.width as f32).min(height as f32 / frame.height as f32);
+    let fitted_width = (frame.width as f32 * scale) as usize;
+    let fitted_height = (frame.height as f32 * scale) as usize;
+    let x_offset = (width - fitted_width) / 2;
+    let y_offset = (height - fitted_height) / 2;
+
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .skip(y_offset)
+        .take(fitted_height)
+        .enumerate()
+    {
+        let source_y = ((y as f32 + 0.5) / scale) as usize;
+        let source_row = source_y.min(frame.height - 1) * frame.width;
+        for (x, pixel) in row
+            .chunks_exact_mut(4)
+            .skip(x_offset)
+            .take(fitted_width)
+            .enumerate()
+        {
+            let source_x = (((x as f32 + 0.5) / scale) as usize).min(frame.width - 1);
+            let source_x = if flip_horizontal {
+                frame.width - 1 - source_x
+            } else {
+                source_x
+            };
+            let index = (source_row + source_x) * 4;
+            pixel.copy_from_slice(&frame.pixels[index..index + 4]);
+        }
+    }
+}
+
+/// Distance used between a pixel and the Worley feature points
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WorleyMetric {
+    Euclidean,
+    Manhattan,
+    Chebyshev,
+    /// Generalizes the others, p = 1 is Manhattan, p = 2 Euclidean, large p tends to Chebyshev
+    Minkowski {
+        p: f32,
+    },
+}
+
+impl WorleyMetric {
+    fn distance(&self, dx: f32, dy: f32) -> f32 {
+        let (dx, dy) = (dx.abs(), dy.abs());
+        match *self {
+            WorleyMetric::Euclidean => dx.hypot(dy),
+            WorleyMetric::Manhattan => dx + dy,
+            WorleyMetric::Chebyshev => dx.max(dy),
+            WorleyMetric::Minkowski { p } => {
+                let p = p.max(0.1);
+                (dx.powf(p) + dy.powf(p)).powf(1.0 / p)
+            }
+        }
+    }
+}
+
+/// Which of the distances to the feature points is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorleyOutput {
+    /// Distance to the closest point, round cells
+    F1,
+    /// Distance to the second closest point
+    F2,
+    /// Bright on the cell borders, dark at the center of the cells
+    F2MinusF1,
+    /// Flat cells separated by thin cracks
+    Cracked,
+}
+
+// Width of the cracks in cell units, as a difference between F2 and F1
+const WORLEY_CRACK_WIDTH: f32 = 0.05;
+
+/// Parameters of the Worley noise render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WorleySettings {
+    // Cells across the width of the image
+    pub frequency: f32,
+    pub metric: WorleyMetric,
+    pub output: WorleyOutput,
+    // 0.0 puts every feature point at the center of its cell, 1.0 anywhere in it
+    pub jitter: f32,
+    pub seed: u64,
+}
+
+impl Default for WorleySettings {
+    fn default() -> Self {
+        Self {
+            frequency: 8.0,
+            metric: WorleyMetric::Euclidean,
+            output: WorleyOutput::F1,
+            jitter: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Feature point of the cell at `x`, `y`, in cell units
+fn worley_feature_point(x: i32, y: i32, jitter: f32, seed: u64) -> [f32; 2] {
+    let coordinates = ((x as u32 as u64) << 32) | y as u32 as u64;
+    let mut rng = SplitMix64::new(seed ^ coordinates.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    let offset = [rng.next_f32() - 0.5, rng.next_f32() - 0.5];
+    [
+        x as f32 + 0.5 + offset[0] * jitter,
+        y as f32 + 0.5 + offset[1] * jitter,
+    ]
+}
+
+/// Cell of the feature point closest to `p`, in cell units, with the closest and the second
+/// closest distances. Only the 3x3 cells around `p` are searched.
+fn worley_closest_points(
+    p: [f32; 2],
+    jitter: f32,
+    seed: u64,
+    distance_metric: WorleyMetric,
+) -> ([i32; 2], f32, f32) {
+    let cell = [p[0].floor() as i32, p[1].floor() as i32];
+    let (mut closest, mut f1, mut f2) = (cell, f32::MAX, f32::MAX);
+    for cell_y in cell[1] - 1..=cell[1] + 1 {
+        for cell_x in cell[0] - 1..=cell[0] + 1 {
+            let point = worley_feature_point(cell_x, cell_y, jitter, seed);
+            let distance = distance_metric.distance(point[0] - p[0], point[1] - p[1]);
+            if distance < f1 {
+                f2 = f1;
+                f1 = distance;
+                closest = [cell_x, cell_y];
+            } else if distance < f2 {
+                f2 = distance;
+            }
+        }
+    }
+    (closest, f1, f2)
+}
+
+/// Render Worley (cellular) noise with one feature point per grid cell, `frequency` cells
+/// across the width. Only the 3x3 cells around a pixel are searched, so a `jitter`
+/// above 1 could miss closer points. The distance picked by `output` is mapped
+/// from `colors[0]` to `colors[1]`. Only the pixels in `region` are written, see
+/// `render_bg_image`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_worley_noise(
+    buffer: &mut [f32],
+    width: usize,
+    region: [usize; 4],
+    frequency: f32,
+    distance_metric: WorleyMetric,
+    output: WorleyOutput,
+    jitter: f32,
+    seed: u64,
+    colors: [[f32; 3]; 2],
+) {
+    let cell_size = width as f32 / frequency.max(f32::EPSILON);
+    let jitter = jitter.clamp(0.0, 1.0);
+
+    let [x0, y0, x1, y1] = region;
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            let p = [(x as f32 + 0.5) / cell_size, (y as f32 + 0.5) / cell_size];
+            let (_, f1, f2) = worley_closest_points(p, jitter, seed, distance_metric);
+
+            // Distances are in cell units, F1 stays below 1 and F2 below about 1.5
+            let t = match output {
+                WorleyOutput::F1 => f1,
+                WorleyOutput::F2 => f2 / 1.5,
+                WorleyOutput::F2MinusF1 => f2 - f1,
+                WorleyOutput::Cracked => smoothstep(0.0, WORLEY_CRACK_WIDTH, f2 - f1),
+            }
+            .clamp(0.0, 1.0);
+
+            for ((channel, a), b) in pixel.iter_mut().zip(colors[0]).zip(colors[1]) {
+                *channel = a + (b - a) * t;
+            }
+            pixel[3] = 1.0;
+        }
+    }
+}
+
+/// Random value in [0, 1) attached to a point of the 3D lattice
+fn lattice_value_3d(x: i32, y: i32, z: i32) -> f32 {
+    let coordinates = ((x as u32 as u64) << 42) ^ ((y as u32 as u64) << 21) ^ z as u32 as u64;
+    SplitMix64::new(coordinates.wrapping_mul(0x9E37_79B9_7F4A_7C15)).next_f32()
+}
+
+/// Single octave of 3D value noise at `p`, in lattice units, in [0, 1)
+fn value_noise_3d(p: [f32; 3]) -> f32 {
+    let cell = p.map(f32::floor);
+    let [x, y, z] = cell.map(|c| c as i32);
+    // Smoothed weights hide the lattice grid
+    let [tx, ty, tz] = [0, 1, 2].map(|axis| smoothstep(0.0, 1.0, p[axis] - cell[axis]));
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let layer = |z: i32| {
+        let top = lerp(lattice_value_3d(x, y, z), lattice_value_3d(x + 1, y, z), tx);
+        let bottom = lerp(
+            lattice_value_3d(x, y + 1, z),
+            lattice_value_3d(x + 1, y + 1, z),
+            tx,
+        );
+        lerp(top, bottom, ty)
+    };
+    lerp(layer(z), layer(z + 1), tz)
+}
+
+/// Color stop of a `ColorRamp`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub position: f32,
+    // ACEScg
+    pub color: [f32; 3],
+}
+
+/// Piecewise linear color gradient
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorRamp {
+    pub stops: Vec<ColorStop>,
+}
+
+impl ColorRamp {
+    /// Color at `t`, stops don't need to be sorted. Clamps to the first and last stops.
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+            return [0.0; 3];
+        };
+        if t <= first.position {
+            return first.color;
+        }
+        if t >= last.position {
+            return last.color;
+        }
+        for pair in stops.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if t <= to.position {
+                let span = (to.position - from.position).max(f32::EPSILON);
+                let weight = (t - from.position) / span;
+                let mut color = from.color;
+                for (channel, target) in color.iter_mut().zip(to.color) {
+                    *channel += (target - *channel) * weight;
+                }
+                return color;
+            }
+        }
+        last.color
+    }
+}
+
+/// Parameters of the terrain render mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainParams {
+    // Lattice cells across the width of the image, for the first octave
+    pub frequency: f32,
+    pub octaves: u32,
+    // Amplitude multiplier from one octave to the next
+    pub persistence: f32,
+    // Frequency multiplier from one octave to the next
+    pub lacunarity: f32,
+    pub seed: u64,
+    // Heights in [0, 1]. Water is flat below the sea level, snow covers what's above the snow level
+    pub sea_level: f32,
+    pub snow_level: f32,
+    // Stop positions are heights, the last stop is the color of the snow
+    pub ramp: ColorRamp,
+    // How steep the slopes look in the shading, 0 is flat
+    pub relief: f32,
+    // Degrees, clockwise from the top of the image
+    pub sun_azimuth: f32,
+    // Degrees above the horizon
+    pub sun_elevation: f32,
+}
+
+impl Default for TerrainParams {
+    fn default() -> Self {
+        let stop = |position, color| ColorStop { position, color };
+        Self {
+            frequency: 3.0,
+            octaves: 7,
+            persistence: 0.5,
+            lacunarity: 2.0,
+            seed: 0,
+            sea_level: 0.45,
+            snow_level: 0.78,
+            ramp: ColorRamp {
+                stops: vec![
+                    stop(0.2, [0.01, 0.03, 0.15]),
+                    stop(0.45, [0.05, 0.2, 0.45]),
+                    stop(0.47, [0.75, 0.65, 0.4]),
+                    stop(0.52, [0.12, 0.35, 0.08]),
+                    stop(0.68, [0.3, 0.25, 0.2]),
+                    stop(0.78, [0.95, 0.95, 1.0]),
+                ],
+            },
+            relief: 1.5,
+            sun_azimuth: 315.0,
+            sun_elevation: 35.0,
+        }
+    }
+}
+
+/// Ambient light of the terrain, so slopes facing away from the sun aren't black
+const TERRAIN_AMBIENT: f32 = 0.2;
+
+/// Render a landscape seen from above: a heightfield of 3D fractional Brownian motion
+/// (each seed slices the noise at a different depth), colored by height with the ramp
+/// and lit by the sun with Lambertian shading from finite difference normals.
+/// Only the pixels in `region` are written, see `render_bg_image`.
+pub fn render_terrain(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    params: &TerrainParams,
+) {
+    let cell_size = width as f32 / params.frequency.max(f32::EPSILON);
+    let depth = SplitMix64::new(params.seed).next_f32() * 1024.0;
+    let octaves = params.octaves.max(1);
+    let normalization: f32 = (0..octaves)
+        .map(|octave| params.persistence.powi(octave as i32))
+        .sum::<f32>()
+        .max(f32::EPSILON);
+
+    let [x0, y0, x1, y1] = region;
+    // The normals on the edges of the region read the heights one pixel outside of it
+    let mut heights = vec![0.0; width * height];
+    for y in y0.saturating_sub(1)..(y1 + 1).min(height) {
+        for x in x0.saturating_sub(1)..(x1 + 1).min(width) {
+            let p = [x as f32 / cell_size, y as f32 / cell_size, depth];
+            let (mut sum, mut amplitude, mut frequency) = (0.0, 1.0, 1.0);
+            for _ in 0..octaves {
+                sum += value_noise_3d(p.map(|c| c * frequency)) * amplitude;
+                amplitude *= params.persistence;
+                frequency *= params.lacunarity;
+            }
+            heights[y * width + x] = sum / normalization;
+        }
+    }
+
+    let (azimuth, elevation) = (
+        params.sun_azimuth.to_radians(),
+        params.sun_elevation.to_radians(),
+    );
+    // Image y grows downwards, so "up" on screen is -y
+    let sun = [
+        azimuth.sin() * elevation.cos(),
+        -azimuth.cos() * elevation.cos(),
+        elevation.sin(),
+    ];
+    // The water surface is flat
+    let surface = |x: usize, y: usize| heights[y * width + x].max(params.sea_level);
+
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            let h = heights[y * width + x];
+            let color = if h >= params.snow_level {
+                params.ramp.sample(f32::INFINITY)
+            } else {
+                params.ramp.sample(h)
+            };
+
+            // Central differences, one sided on the edges
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            let dx = (surface(right, y) - surface(left, y)) / (right - left).max(1) as f32;
+            let dy = (surface(x, down) - surface(x, up)) / (down - up).max(1) as f32;
+            // Slopes in height per lattice cell, exaggerated by the relief
+            let scale = params.relief * cell_size;
+            let normal = [-dx * scale, -dy * scale, 1.0];
+            let length = (normal[0] * normal[0] + normal[1] * normal[1] + 1.0).sqrt();
+            let lambert =
+                ((normal[0] * sun[0] + normal[1] * sun[1] + normal[2] * sun[2]) / length).max(0.0);
+            let light = TERRAIN_AMBIENT + (1.0 - TERRAIN_AMBIENT) * lambert;
+
+            for (channel, c) in pixel.iter_mut().zip(color) {
+                *channel = c * light;
+            }
+            pixel[3] = 1.0;
+        }
+    }
+}
+
+/// Parameters of the circuit board render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PcbSettings {
+    pub seed: u64,
+    // Rough fraction of the grid covered by traces, in [0, 1]
+    pub trace_density: f32,
+    // Pixels between two routing grid points
+    pub cell_size: u32,
+    // ACEScg
+    pub trace_color: [f32; 3],
+    pub bg_color: [f32; 3],
+    // Intensity of the bloom around traces and pads, 0 disables it
+    pub glow_strength: f32,
+}
+
+impl Default for PcbSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            trace_density: 0.5,
+            cell_size: 12,
+            trace_color: [0.1, 1.0, 0.35],
+            bg_color: [0.005, 0.03, 0.015],
+            glow_strength: 1.0,
+        }
+    }
+}
+
+/// Chance for a trace to turn by 90° at each grid point
+const PCB_TURN_PROBABILITY: f32 = 0.25;
+
+/// Set the mask to 1 in the rectangle between `min` and `max`, in pixels
+fn fill_mask_rect(mask: &mut [f32], width: usize, height: usize, min: [f32; 2], max: [f32; 2]) {
+    let x_end = (max[0].round().max(0.0) as usize).min(width);
+    let x_start = (min[0].round().max(0.0) as usize).min(x_end);
+    let y_end = (max[1].round().max(0.0) as usize).min(height);
+    let y_start = (min[1].round().max(0.0) as usize).min(y_end);
+    for row in mask.chunks_exact_mut(width).take(y_end).skip(y_start) {
+        row[x_start..x_end].fill(1.0);
+    }
+}
+
+/// Uniform random integer in `0..n`, 0 when `n` is 0
+fn random_below(rng: &mut SplitMix64, n: usize) -> usize {
+    (rng.next_u64() % n.max(1) as u64) as usize
+}
+
+/// Set the mask to 1 in a ring around `center`, a disc when `inner_radius` is 0
+fn fill_mask_ring(
+    mask: &mut [f32],
+    width: usize,
+    height: usize,
+    center: [f32; 2],
+    radius: f32,
+    inner_radius: f32,
+) {
+    let y_start = (center[1] - radius).floor().max(0.0) as usize;
+    let y_end = ((center[1] + radius).ceil().max(0.0) as usize).min(height);
+    let x_start = (center[0] - radius).floor().max(0.0) as usize;
+    let x_end = ((center[0] + radius).ceil().max(0.0) as usize).min(width);
+    for y in y_start..y_end {
+        for x in x_start..x_end {
+            let distance = (x as f32 + 0.5 - center[0]).hypot(y as f32 + 0.5 - center[1]);
+            if distance <= radius && distance >= inner_radius {
+                mask[y * width + x] = 1.0;
+            }
+        }
+    }
+}
+
+/// Render a circuit board: Manhattan routed traces grown by seeded random walks on a grid
+/// of `cell_size` pixels, with pads on their corners and vias on their ends, between
+/// rectangular component pads. `glow_strength` adds a blurred copy of the traces on top,
+/// as an emissive bloom that can go above 1.
+#[allow(clippy::too_many_arguments)]
+pub fn render_pcb_pattern(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    seed: u64,
+    trace_density: f32,
+    cell_size: u32,
+    trace_color: [f32; 3],
+    bg_color: [f32; 3],
+    glow_strength: f32,
+) {
+    let cell = cell_size.max(1) as f32;
+    let columns = (width as f32 / cell).ceil() as usize;
+    let rows = (height as f32 / cell).ceil() as usize;
+    let node_center = |(x, y): (usize, usize)| [(x as f32 + 0.5) * cell, (y as f32 + 0.5) * cell];
+    let trace_half_width = (cell * 0.125).max(0.5);
+
+    let mut rng = SplitMix64::new(seed);
+    let mut mask = vec![0.0_f32; width * height];
+    // Grid points already used by a trace or a component
+    let mut occupied = vec![false; columns * rows];
+
+    // Components first, so the traces route around them
+    let component_count = (columns * rows) as f32 * trace_density.clamp(0.0, 1.0) * 0.02;
+    for _ in 0..component_count.round() as usize {
+        let size = (2 + random_below(&mut rng, 3), 1 + random_below(&mut rng, 2));
+        let corner = (
+            random_below(&mut rng, columns),
+            random_below(&mut rng, rows),
+        );
+        let end = (
+            (corner.0 + size.0).min(columns),
+            (corner.1 + size.1).min(rows),
+        );
+        for y in corner.1..end.1 {
+            for x in corner.0..end.0 {
+                occupied[y * columns + x] = true;
+            }
+        }
+        let min = node_center(corner);
+        let max = node_center((end.0 - 1, end.1 - 1));
+        let margin = cell * 0.35;
+        fill_mask_rect(
+            &mut mask,
+            width,
+            height,
+            [min[0] - margin, min[1] - margin],
+            [max[0] + margin, max[1] + margin],
+        );
+    }
+
+    const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let trace_count = (columns * rows) as f32 * trace_density.clamp(0.0, 1.0) * 0.1;
+    for _ in 0..trace_count.round() as usize {
+        let start = (
+            random_below(&mut rng, columns),
+            random_below(&mut rng, rows),
+        );
+        if occupied[start.1 * columns + start.0] {
+            continue;
+        }
+        occupied[start.1 * columns + start.0] = true;
+        let mut direction = random_below(&mut rng, 4);
+        let mut node = start;
+        let max_length = 3 + random_below(&mut rng, columns.max(rows));
+        for _ in 0..max_length {
+            let mut turned = false;
+            if rng.next_f32() < PCB_TURN_PROBABILITY {
+                direction = (direction + if rng.next_f32() < 0.5 { 1 } else { 3 }) % 4;
+                turned = true;
+            }
+            let (dx, dy) = DIRECTIONS[direction];
+            let (Some(x), Some(y)) = (node.0.checked_add_signed(dx), node.1.checked_add_signed(dy))
+            else {
+                break;
+            };
+            if x >= columns || y >= rows || occupied[y * columns + x] {
+                break;
+            }
+            occupied[y * columns + x] = true;
+
+            let (from, to) = (node_center(node), node_center((x, y)));
+            fill_mask_rect(
+                &mut mask,
+                width,
+                height,
+                [
+                    from[0].min(to[0]) - trace_half_width,
+                    from[1].min(to[1]) - trace_half_width,
+                ],
+                [
+                    from[0].max(to[0]) + trace_half_width,
+                    from[1].max(to[1]) + trace_half_width,
+                ],
+            );
+            if turned && node != start {
+                let pad = cell * 0.2;
+                fill_mask_rect(
+                    &mut mask,
+                    width,
+                    height,
+                    [from[0] - pad, from[1] - pad],
+                    [from[0] + pad, from[1] + pad],
+                );
+            }
+            node = (x, y);
+        }
+        // Vias on both ends
+        for end in [start, node] {
+            fill_mask_ring(
+                &mut mask,
+                width,
+                height,
+                node_center(end),
+                cell * 0.3,
+                cell * 0.12,
+            );
+        }
+    }
+
+    let glow = (glow_strength > 0.0).then(|| {
+        let mut glow: Vec<f32> = mask
+            .iter()
+            .flat_map(|&m| {
+                [
+                    trace_color[0] * m,
+                    trace_color[1] * m,
+                    trace_color[2] * m,
+                    1.0,
+                ]
+            })
+            .collect();
+        apply_gaussian_blur(&mut glow, width, height, cell * 0.5);
+        glow
+    });
+
+    for (index, (pixel, &m)) in buffer.chunks_exact_mut(4).zip(&mask).enumerate() {
+        let glow = glow.as_ref().map_or([0.0; 3], |glow| {
+            [glow[index * 4], glow[index * 4 + 1], glow[index * 4 + 2]]
+        });
+        for (((channel, bg), trace), glow) in
+            pixel.iter_mut().zip(bg_color).zip(trace_color).zip(glow)
+        {
+            *channel = bg + (trace - bg) * m + glow * glow_strength;
+        }
+        pixel[3] = 1.0;
+    }
+}
+
+/// Parameters of the texture synthesis render mode. The exemplar isn't part of them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TextureQuiltingSettings {
+    pub seed: u64,
+    // Side of the square patches copied from the exemplar, in pixels
+    pub patch_size: u32,
+    // Fraction of each patch overlapping its left and top neighbours
+    pub overlap_fraction: f32,
+}
+
+impl Default for TextureQuiltingSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            patch_size: 32,
+            overlap_fraction: 0.25,
+        }
+    }
+}
+
+impl TextureQuiltingSettings {
+    /// Overlap between patches, in pixels
+    pub fn overlap(&self) -> usize {
+        (self.patch_size as f32 * self.overlap_fraction).round() as usize
+    }
+}
+
+/// Small image a texture is grown from, in linear ACEScg RGBA
+#[derive(Debug, Clone)]
+pub struct Exemplar {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<f32>,
+}
+
+impl Exemplar {
+    /// Read the first RGBA layer of an OpenEXR file, or an 8 bit sRGB image like a PNG
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ImageError> {
+        let path = path.as_ref();
+        let is_exr = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+        if is_exr {
+            let image = read_first_rgba_layer_from_file(
+                path,
+                |resolution, _| Exemplar {
+                    width: resolution.width(),
+                    height: resolution.height(),
+                    pixels: vec![0.0; resolution.width() * resolution.height() * 4],
+                },
+                |exemplar: &mut Exemplar,
+                 position: Vec2<usize>,
+                 (r, g, b, a): (f32, f32, f32, f32)| {
+                    let index = (position.y() * exemplar.width + position.x()) * 4;
+                    exemplar.pixels[index..index + 4].copy_from_slice(&[r, g, b, a]);
+                },
+            )?;
+            return Ok(image.layer_data.channel_data.pixels);
+        }
+
+        let image = ::image::open(path)?.to_rgba8();
+        let pixels = image
+            .pixels()
+            .flat_map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                let [r, g, b] = srgb_u8_to_acescg([r, g, b]);
+                [r, g, b, a as f32 / 255.0]
+            })
+            .collect();
+        Ok(Exemplar {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels,
+        })
+    }
+}
+
+/// Random exemplar positions compared for each patch of the texture quilting
+const QUILTING_CANDIDATES: usize = 256;
+/// Patches whose overlap error is within this fraction of the best one are picked at random
+const QUILTING_TOLERANCE: f32 = 0.1;
+
+/// Index of the smallest value, the first one on ties
+fn argmin(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .fold((0, f32::INFINITY), |best, (i, &value)| {
+            if value < best.1 {
+                (i, value)
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+/// Minimum error boundary cut through an overlap `length` pixels long and `thickness`
+/// pixels thick, where `error(along, across)` is the error of each pixel. Dynamic
+/// programming finds the connected path of least total error. Returns for each position
+/// along the overlap the first pixel across it taken from the new patch.
+fn min_error_cut(
+    length: usize,
+    thickness: usize,
+    error: impl Fn(usize, usize) -> f32,
+) -> Vec<usize> {
+    // Neighbours of a pixel on the previous row of the path
+    let neighbours = |across: usize| across.saturating_sub(1)..(across + 2).min(thickness);
+
+    // Least error of the paths from the start to each pixel
+    let mut cost = vec![0.0_f32; length * thickness];
+    for along in 0..length {
+        for across in 0..thickness {
+            let previous = match along {
+                0 => 0.0,
+                _ => cost[(along - 1) * thickness..along * thickness][neighbours(across)]
+                    .iter()
+                    .copied()
+                    .fold(f32::INFINITY, f32::min),
+            };
+            cost[along * thickness + across] = error(along, across) + previous;
+        }
+    }
+
+    // Walk the cheapest path back from its end
+    let mut cut = vec![0; length];
+    let mut across = argmin(&cost[(length - 1) * thickness..]);
+    for along in (0..length).rev() {
+        if along + 1 < length {
+            let range = neighbours(across);
+            let start = range.start;
+            across = start + argmin(&cost[along * thickness..(along + 1) * thickness][range]);
+        }
+        cut[along] = across;
+    }
+    cut
+}
+
+/// Grow a texture from an exemplar with the image quilting of Efros and Freeman.
+/// Square patches of `patch_size` pixels are laid out in a grid, overlapping their left
+/// and top neighbours by `overlap` pixels. Each one is taken from the exemplar where its
+/// overlap matches the patches already placed the best (sum of squared ACEScg
+/// differences), then stitched along the minimum error boundary cut of the overlap.
+/// `seed` picks the candidate patches, the same seed gives the same texture.
+#[allow(clippy::too_many_arguments)]
+pub fn render_texture_quilting(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    exemplar: &[f32],
+    ex_w: usize,
+    ex_h: usize,
+    patch_size: usize,
+    overlap: usize,
+    seed: u64,
+) {
+    if ex_w == 0 || ex_h == 0 || exemplar.len() < ex_w * ex_h * 4 {
+        buffer.fill(0.0);
+        return;
+    }
+    let patch = patch_size.min(ex_w).min(ex_h).max(1);
+    let overlap = overlap.min(patch - 1);
+    let step = patch - overlap;
+
+    // Whole patches are synthesized, then cropped to the buffer
+    let columns = width.saturating_sub(overlap).div_ceil(step).max(1);
+    let rows = height.saturating_sub(overlap).div_ceil(step).max(1);
+    let canvas_width = columns * step + overlap;
+    let canvas_height = rows * step + overlap;
+    let mut canvas = vec![0.0_f32; canvas_width * canvas_height * 4];
+
+    let mut rng = SplitMix64::new(seed);
+    let positions = (ex_w - patch + 1, ex_h - patch + 1);
+    for row in 0..rows {
+        for column in 0..columns {
+            let origin = (column * step, row * step);
+            // Squared difference between the exemplar at `source` and the canvas,
+            // at (x, y) in the patch
+            let pixel_error = |source: (usize, usize), x: usize, y: usize| -> f32 {
+                let exemplar_index = ((source.1 + y) * ex_w + source.0 + x) * 4;
+                let canvas_index = ((origin.1 + y) * canvas_width + origin.0 + x) * 4;
+                exemplar[exemplar_index..exemplar_index + 3]
+                    .iter()
+                    .zip(&canvas[canvas_index..canvas_index + 3])
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum()
+            };
+            let overlap_error = |source: (usize, usize)| -> f32 {
+                let mut error = 0.0;
+                for y in 0..patch {
+                    for x in 0..patch {
+                        let in_left = column > 0 && x < overlap;
+                        let in_top = row > 0 && y < overlap;
+                        if in_left || in_top {
+                            error += pixel_error(source, x, y);
+                        }
+                    }
+                }
+                error
+            };
+
+            let candidates: Vec<(usize, usize)> = (0..QUILTING_CANDIDATES)
+                .map(|_| {
+                    (
+                        random_below(&mut rng, positions.0),
+                        random_below(&mut rng, positions.1),
+                    )
+                })
+                .collect();
+            let errors: Vec<f32> = candidates.iter().map(|&c| overlap_error(c)).collect();
+            let best = errors[argmin(&errors)];
+            let good: Vec<(usize, usize)> = candidates
+                .iter()
+                .zip(&errors)
+                .filter(|(_, error)| **error <= best * (1.0 + QUILTING_TOLERANCE))
+                .map(|(&candidate, _)| candidate)
+                .collect();
+            let source = good[random_below(&mut rng, good.len())];
+
+            let left_cut = (column > 0 && overlap > 0)
+                .then(|| min_error_cut(patch, overlap, |y, x| pixel_error(source, x, y)));
+            let top_cut = (row > 0 && overlap > 0)
+                .then(|| min_error_cut(patch, overlap, |x, y| pixel_error(source, x, y)));
+
+            for y in 0..patch {
+                for x in 0..patch {
+                    // Pixels before a cut keep the patches already placed
+                    let kept = left_cut.as_ref().is_some_and(|cut| x < cut[y])
+                        || top_cut.as_ref().is_some_and(|cut| y < cut[x]);
+                    if kept {
+                        continue;
+                    }
+                    let exemplar_index = ((source.1 + y) * ex_w + source.0 + x) * 4;
+                    let canvas_index = ((origin.1 + y) * canvas_width + origin.0 + x) * 4;
+                    canvas[canvas_index..canvas_index + 4]
+                        .copy_from_slice(&exemplar[exemplar_index..exemplar_index + 4]);
+                }
+            }
+        }
+    }
+
+    for (y, out_row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        let start = y * canvas_width * 4;
+        out_row.copy_from_slice(&canvas[start..start + width * 4]);
+    }
+}
+
+/// Copy the pixels of `source` outside of `region` (`[x0, y0, x1, y1]`, end exclusive)
+/// into `buffer`, so only the pixels inside of it keep the changes made to `buffer`
+pub fn copy_outside_region(buffer: &mut [f32], source: &[f32], width: usize, region: [usize; 4]) {
+    let [x0, y0, x1, y1] = region;
+    let rows = buffer
+        .chunks_exact_mut(width * 4)
+        .zip(source.chunks_exact(width * 4));
+    for (y, (row, source_row)) in rows.enumerate() {
+        if y < y0 || y >= y1 {
+            row.copy_from_slice(source_row);
+            continue;
+        }
+        row[..x0 * 4].copy_from_slice(&source_row[..x0 * 4]);
+        row[x1 * 4..].copy_from_slice(&source_row[x1 * 4..]);
+    }
+}
+
+/// The RGBA pixels of `region`, `[x0, y0, x1, y1]` with the end exclusive, in a buffer
+/// `width` pixels wide, with their x and y
+pub fn region_pixels_mut(
+    buffer: &mut [f32],
+    width: usize,
+    region: [usize; 4],
+) -> impl Iterator<Item = (usize, usize, &mut [f32])> {
+    let [x0, y0, x1, y1] = region;
+    buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+        .flat_map(move |(y, row)| {
+            row.chunks_exact_mut(4)
+                .enumerate()
+                .take(x1)
+                .skip(x0)
+                .map(move |(x, pixel)| (x, y, pixel))
+        })
+}
+
+/// Supersampling factors offered in the GUI, per axis
+pub const SSAA_FACTORS: [u32; 4] = [1, 2, 4, 8];
+
+/// Render `width` x `height` pixels anti-aliased by supersampling: `render_fn` draws a
+/// `ss_factor` times wider and taller linear ACEScg image, which is box filtered down
+/// into `buffer`. The memory and time taken grow with the square of `ss_factor`.
+/// Only `region` is rendered, `render_fn` gets it scaled to the supersampled image.
+pub fn render_ssaa(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    ss_factor: u32,
+    render_fn: impl Fn(&mut [f32], usize, usize, [usize; 4]),
+) {
+    let ss_factor = ss_factor.max(1) as usize;
+    if ss_factor == 1 {
+        render_fn(buffer, width, height, region);
+        return;
+    }
+    let (ss_width, ss_height) = (width * ss_factor, height * ss_factor);
+    let mut supersampled = vec![0.0; ss_width * ss_height * 4];
+    render_fn(
+        &mut supersampled,
+        ss_width,
+        ss_height,
+        region.map(|v| v * ss_factor),
+    );
+    // Box filter of the samples under each pixel of the region
+    let weight = 1.0 / (ss_factor * ss_factor) as f32;
+    for (x, y, pixel) in region_pixels_mut(buffer, width, region) {
+        pixel.fill(0.0);
+        for sample_y in y * ss_factor..(y + 1) * ss_factor {
+            let start = (sample_y * ss_width + x * ss_factor) * 4;
+            for sample in supersampled[start..start + ss_factor * 4].chunks_exact(4) {
+                for (channel, value) in pixel.iter_mut().zip(sample) {
+                    *channel += value * weight;
+                }
+            }
+        }
+    }
+}
+
+/// Surface of a ray marched shape
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SdfMaterial {
+    // ACEScg diffuse reflectance
+    pub albedo: [f32; 3],
+}
+
+/// Shape of the ray marcher, described by its signed distance function
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SdfPrimitive {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        material: SdfMaterial,
+    },
+    Box {
+        center: [f32; 3],
+        half_extents: [f32; 3],
+        material: SdfMaterial,
+    },
+    /// Lying flat, around the Y axis
+    Torus {
+        center: [f32; 3],
+        r_major: f32,
+        r_minor: f32,
+        material: SdfMaterial,
+    },
+}
+
+impl SdfPrimitive {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SdfPrimitive::Sphere { .. } => "Sphere",
+            SdfPrimitive::Box { .. } => "Box",
+            SdfPrimitive::Torus { .. } => "Torus",
+        }
+    }
+
+    pub fn material(&self) -> SdfMaterial {
+        match self {
+            SdfPrimitive::Sphere { material, .. }
+            | SdfPrimitive::Box { material, .. }
+            | SdfPrimitive::Torus { material, .. } => *material,
+        }
+    }
+
+    /// Signed distance from `p` to the surface, negative inside
+    pub fn distance(&self, p: [f32; 3]) -> f32 {
+        match *self {
+            SdfPrimitive::Sphere { center, radius, .. } => length3(sub3(p, center)) - radius,
+            SdfPrimitive::Box {
+                center,
+                half_extents,
+                ..
+            } => {
+                let q = [0, 1, 2].map(|i| (p[i] - center[i]).abs() - half_extents[i]);
+                let outside = length3(q.map(|c| c.max(0.0)));
+                let inside = q[0].max(q[1]).max(q[2]).min(0.0);
+                outside + inside
+            }
+            SdfPrimitive::Torus {
+                center,
+                r_major,
+                r_minor,
+                ..
+            } => {
+                let q = sub3(p, center);
+                let ring = q[0].hypot(q[2]) - r_major;
+                ring.hypot(q[1]) - r_minor
+            }
+        }
+    }
+}
+
+/// Point light of the ray marcher, falling off with the square of the distance
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PointLight {
+    pub position: [f32; 3],
+    // ACEScg
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+/// Shapes of the ray marcher, merged by a smooth union
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SdfScene {
+    pub primitives: Vec<SdfPrimitive>,
+    // Distance over which the shapes blend into each other, 0 is a plain union
+    pub smoothness: f32,
+    pub light: PointLight,
+    // ACEScg radiance reaching every point, and of the background
+    pub ambient: [f32; 3],
+}
+
+/// Pinhole camera of the ray marcher
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    // Vertical field of view, degrees
+    pub fov: f32,
+}
+
+/// Parameters of the ray marcher render mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaymarcherSettings {
+    pub scene: SdfScene,
+    pub camera: Camera,
+}
+
+impl Default for RaymarcherSettings {
+    fn default() -> Self {
+        let material = |albedo| SdfMaterial { albedo };
+        Self {
+            scene: SdfScene {
+                primitives: vec![
+                    SdfPrimitive::Sphere {
+                        center: [-1.0, 0.0, 0.0],
+                        radius: 1.0,
+                        material: material([0.7, 0.1, 0.08]),
+                    },
+                    SdfPrimitive::Box {
+                        center: [1.0, 0.0, 0.0],
+                        half_extents: [0.7, 0.7, 0.7],
+                        material: material([0.1, 0.25, 0.7]),
+                    },
+                    SdfPrimitive::Torus {
+                        center: [0.0, -0.9, 0.0],
+                        r_major: 2.2,
+                        r_minor: 0.2,
+                        material: material([0.8, 0.7, 0.3]),
+                    },
+                ],
+                smoothness: 0.3,
+                light: PointLight {
+                    position: [4.0, 5.0, 4.0],
+                    color: [1.0, 1.0, 1.0],
+                    intensity: 150.0,
+                },
+                ambient: [0.02, 0.02, 0.03],
+            },
+            camera: Camera {
+                position: [0.0, 2.0, 6.0],
+                target: [0.0, 0.0, 0.0],
+                fov: 45.0,
+            },
+        }
+    }
+}
+
+/// Most primitives the ray marcher scene can hold in the GUI
+pub const MAX_SDF_PRIMITIVES: usize = 4;
+/// Sphere tracing steps before a ray is considered to miss
+const RAYMARCH_MAX_STEPS: u32 = 128;
+/// Distance to the surface under which a ray hits it
+const RAYMARCH_HIT_DISTANCE: f32 = 0.001;
+/// Distance along the ray after which it's considered to miss
+const RAYMARCH_MAX_DISTANCE: f32 = 100.0;
+/// Step of the finite differences of the normals
+const RAYMARCH_NORMAL_EPSILON: f32 = 0.0005;
+
+fn sub3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length3(v: [f32; 3]) -> f32 {
+    dot3(v, v).sqrt()
+}
+
+fn normalize3(v: [f32; 3]) -> [f32; 3] {
+    let length = length3(v).max(f32::EPSILON);
+    v.map(|c| c / length)
+}
+
+impl SdfScene {
+    /// Signed distance to the union of the primitives, with the albedo at that point.
+    /// Polynomial smooth minimum (Inigo Quilez), the albedos are blended by the same weight.
+    fn distance(&self, p: [f32; 3]) -> (f32, [f32; 3]) {
+        let mut primitives = self.primitives.iter();
+        let Some(first) = primitives.next() else {
+            return (f32::MAX, [0.0; 3]);
+        };
+        let k = self.smoothness.max(0.0);
+        primitives.fold(
+            (first.distance(p), first.material().albedo),
+            |(distance, albedo), primitive| {
+                let other = primitive.distance(p);
+                let other_albedo = primitive.material().albedo;
+                if k <= f32::EPSILON {
+                    return if other < distance {
+                        (other, other_albedo)
+                    } else {
+                        (distance, albedo)
+                    };
+                }
+                // Weight of the current union, 1 where it's the closest by more than `k`
+                let h = (0.5 + 0.5 * (other - distance) / k).clamp(0.0, 1.0);
+                let blended = other + (distance - other) * h - k * h * (1.0 - h);
+                let mut blended_albedo = [0.0; 3];
+                for ((c, a), b) in blended_albedo.iter_mut().zip(albedo).zip(other_albedo) {
+                    *c = b + (a - b) * h;
+                }
+                (blended, blended_albedo)
+            },
+        )
+    }
+
+    /// Gradient of the distance field by central differences
+    fn normal(&self, p: [f32; 3]) -> [f32; 3] {
+        let axis = |i: usize| {
+            let (mut forward, mut backward) = (p, p);
+            forward[i] += RAYMARCH_NORMAL_EPSILON;
+            backward[i] -= RAYMARCH_NORMAL_EPSILON;
+            self.distance(forward).0 - self.distance(backward).0
+        };
+        normalize3([axis(0), axis(1), axis(2)])
+    }
+
+    /// Sphere trace from `origin` along the unit `direction`, returning where the ray hit
+    fn march(&self, origin: [f32; 3], direction: [f32; 3]) -> Option<([f32; 3], [f32; 3])> {
+        let mut t = 0.0;
+        for _ in 0..RAYMARCH_MAX_STEPS {
+            let p = [0, 1, 2].map(|i| origin[i] + direction[i] * t);
+            let (distance, albedo) = self.distance(p);
+            if distance < RAYMARCH_HIT_DISTANCE {
+                return Some((p, albedo));
+            }
+            t += distance;
+            if t > RAYMARCH_MAX_DISTANCE {
+                break;
+            }
+        }
+        None
+    }
+}
+
+/// Ray march the signed distance field of `scene` seen through `camera`, into ACEScg radiance.
+/// Surfaces are Lambertian, lit by the point light and the ambient light.
+/// Only the pixels in `region` are written, see `render_bg_image`.
+pub fn render_raymarcher(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    scene: &SdfScene,
+    camera: &Camera,
+) {
+    use std::f32::consts::PI;
+
+    let forward = normalize3(sub3(camera.target, camera.position));
+    let right = normalize3(cross3(forward, [0.0, 1.0, 0.0]));
+    let up = cross3(right, forward);
+    let half_height = (camera.fov.clamp(1.0, 179.0).to_radians() * 0.5).tan();
+    let half_width = half_height * width as f32 / height.max(1) as f32;
+
+    let [x0, y0, x1, y1] = region;
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            let u = ((x as f32 + 0.5) / width as f32 * 2.0 - 1.0) * half_width;
+            // Rows are stored top first
+            let v = (1.0 - (y as f32 + 0.5) / height as f32 * 2.0) * half_height;
+            let direction = normalize3([0, 1, 2].map(|i| forward[i] + right[i] * u + up[i] * v));
+
+            let radiance = match scene.march(camera.position, direction) {
+                Some((p, albedo)) => {
+                    let normal = scene.normal(p);
+                    let to_light = sub3(scene.light.position, p);
+                    let distance_sq = dot3(to_light, to_light).max(f32::EPSILON);
+                    let cos_theta = dot3(normal, normalize3(to_light)).max(0.0);
+                    let irradiance = scene.light.intensity * cos_theta / distance_sq;
+                    [0, 1, 2].map(|i| {
+                        albedo[i] * (scene.light.color[i] * irradiance / PI + scene.ambient[i])
+                    })
+                }
+                None => scene.ambient,
+            };
+            pixel[..3].copy_from_slice(&radiance);
+            pixel[3] = 1.0;
+        }
+    }
+}
+
+/// Physically based material of the BRDF preview, metallic/roughness workflow
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BrdfMaterial {
+    // ACEScg albedo of dielectrics, specular color of metals
+    pub base_color: [f32; 3],
+    // Perceptual roughness in [0, 1], squared into the GGX alpha
+    pub roughness: f32,
+    pub metallic: f32,
+}
+
+/// Parameters of the BRDF spheres render mode: a grid of spheres of the same base color,
+/// rougher from top to bottom and more metallic from left to right
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BrdfGridSettings {
+    // ACEScg
+    pub base_color: [f32; 3],
+    // From 1 to `MAX_BRDF_GRID_SIZE`
+    pub roughness_steps: u32,
+    pub metallic_steps: u32,
+    // Towards the light, the camera looks down -Z
+    pub light_dir: [f32; 3],
+    pub light_intensity: f32,
+}
+
+impl Default for BrdfGridSettings {
+    fn default() -> Self {
+        Self {
+            base_color: [0.9, 0.45, 0.2],
+            roughness_steps: 4,
+            metallic_steps: 4,
+            light_dir: [-1.0, 1.0, 1.0],
+            light_intensity: 3.0,
+        }
+    }
+}
+
+/// Most rows and columns of spheres in the BRDF grid
+pub const MAX_BRDF_GRID_SIZE: u32 = 4;
+/// Smoothest material of the grid, a perfect mirror has no visible highlight from a point light
+const BRDF_MIN_ROUGHNESS: f32 = 0.05;
+/// Specular reflectance of dielectrics at normal incidence
+const BRDF_DIELECTRIC_F0: f32 = 0.04;
+/// ACEScg radiance of the sky, lighting the side of the spheres facing away from the light
+const BRDF_AMBIENT: f32 = 0.03;
+/// ACEScg radiance behind the spheres
+const BRDF_BACKGROUND: f32 = 0.01;
+/// Distance of the camera to the center of each sphere, in sphere radii
+const BRDF_CAMERA_DISTANCE: f32 = 4.0;
+
+impl BrdfGridSettings {
+    /// Materials of the grid, row by row
+    pub fn materials(&self) -> Vec<BrdfMaterial> {
+        let rows = self.roughness_steps.clamp(1, MAX_BRDF_GRID_SIZE);
+        let columns = self.metallic_steps.clamp(1, MAX_BRDF_GRID_SIZE);
+        // Position of a step in [0, 1], a single step sits in the middle
+        let t = |step: u32, steps: u32| match steps {
+            1 => 0.5,
+            _ => step as f32 / (steps - 1) as f32,
+        };
+        (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| BrdfMaterial {
+                    base_color: self.base_color,
+                    roughness: BRDF_MIN_ROUGHNESS + (1.0 - BRDF_MIN_ROUGHNESS) * t(row, rows),
+                    metallic: t(column, columns),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Cook-Torrance microfacet BRDF times the cosine of the light, with the GGX distribution,
+/// Smith-Schlick geometry term and Schlick Fresnel, plus a Lambertian diffuse lobe.
+/// `n`, `l` and `v` are the unit normal and the directions towards the light and the camera.
+fn cook_torrance_ggx(material: &BrdfMaterial, n: [f32; 3], l: [f32; 3], v: [f32; 3]) -> [f32; 3] {
+    use std::f32::consts::PI;
+
+    let n_dot_l = dot3(n, l);
+    let n_dot_v = dot3(n, v).max(1e-4);
+    if n_dot_l <= 0.0 {
+        return [0.0; 3];
+    }
+    let h = normalize3([l[0] + v[0], l[1] + v[1], l[2] + v[2]]);
+    let n_dot_h = dot3(n, h).max(0.0);
+    let v_dot_h = dot3(v, h).max(0.0);
+
+    let roughness = material.roughness.clamp(BRDF_MIN_ROUGHNESS, 1.0);
+    let alpha_sq = roughness.powi(4);
+    let denominator = n_dot_h * n_dot_h * (alpha_sq - 1.0) + 1.0;
+    let distribution = alpha_sq / (PI * denominator * denominator);
+    // Direct lighting remapping of the roughness
+    let k = (roughness + 1.0).powi(2) / 8.0;
+    let geometry = n_dot_l / (n_dot_l * (1.0 - k) + k) * n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let fresnel_weight = (1.0 - v_dot_h).powi(5);
+
+    let metallic = material.metallic.clamp(0.0, 1.0);
+    material.base_color.map(|base| {
+        let f0 = BRDF_DIELECTRIC_F0 + (base - BRDF_DIELECTRIC_F0) * metallic;
+        let fresnel = f0 + (1.0 - f0) * fresnel_weight;
+        let specular = distribution * geometry * fresnel / (4.0 * n_dot_l * n_dot_v);
+        // Metals have no diffuse lobe, and what's reflected isn't diffused
+        let diffuse = (1.0 - fresnel) * (1.0 - metallic) * base / PI;
+        (diffuse + specular) * n_dot_l
+    })
+}
+
+/// Render a grid of spheres, one per material, lit by a directional light towards
+/// `light_dir` (of intensity `light_intensity`) and shaded with the Cook-Torrance GGX BRDF.
+/// The materials fill the grid row by row, `columns` spheres per row.
+/// Each sphere is seen by a pinhole camera of its own, so they all look the same.
+/// Only the pixels in `region` are written, see `render_bg_image`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_brdf_sphere_grid(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    materials: &[BrdfMaterial],
+    columns: usize,
+    light_dir: [f32; 3],
+    light_intensity: f32,
+) {
+    let columns = columns.clamp(1, materials.len().max(1));
+    let rows = materials.len().div_ceil(columns).max(1);
+    let cell = (width as f32 / columns as f32).min(height as f32 / rows as f32);
+    // Cells centered in the image
+    let origin = [
+        (width as f32 - cell * columns as f32) * 0.5,
+        (height as f32 - cell * rows as f32) * 0.5,
+    ];
+    // The sphere covers 80% of its cell
+    let tan_half_fov = 1.0 / (0.8 * (BRDF_CAMERA_DISTANCE * BRDF_CAMERA_DISTANCE - 1.0).sqrt());
+    let light = normalize3(light_dir);
+
+    let [x0, y0, x1, y1] = region;
+    for (y, row) in buffer
+        .chunks_exact_mut(width * 4)
+        .enumerate()
+        .take(y1)
+        .skip(y0)
+    {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate().take(x1).skip(x0) {
+            pixel.copy_from_slice(&[BRDF_BACKGROUND, BRDF_BACKGROUND, BRDF_BACKGROUND, 1.0]);
+            let cell_x = (x as f32 + 0.5 - origin[0]) / cell;
+            let cell_y = (y as f32 + 0.5 - origin[1]) / cell;
+            if cell_x < 0.0 || cell_y < 0.0 {
+                continue;
+            }
+            let (column, row) = (cell_x as usize, cell_y as usize);
+            if column >= columns {
+                continue;
+            }
+            let Some(material) = materials.get(row * columns + column) else {
+                continue;
+            };
+
+            // Ray of the sphere's camera, rows are stored top first
+            let u = (cell_x.fract() * 2.0 - 1.0) * tan_half_fov;
+            let v = (1.0 - cell_y.fract() * 2.0) * tan_half_fov;
+            let direction = normalize3([u, v, -1.0]);
+            // Unit sphere at the origin, camera on +Z
+            let camera = [0.0, 0.0, BRDF_CAMERA_DISTANCE];
+            let b = dot3(camera, direction);
+            let c = dot3(camera, camera) - 1.0;
+            let discriminant = b * b - c;
+            if discriminant < 0.0 {
+                continue;
+            }
+            let t = -b - discriminant.sqrt();
+            let normal = [0, 1, 2].map(|i| camera[i] + direction[i] * t);
+            let view = direction.map(|c| -c);
+
+            let reflected = cook_torrance_ggx(material, normal, light, view);
+            let colors = reflected.into_iter().zip(material.base_color);
+            for (channel, (reflected, base)) in pixel.iter_mut().zip(colors) {
+                *channel = reflected * light_intensity + BRDF_AMBIENT * base;
+            }
+        }
+    }
+}
+
+/// How much of the 8 bit range a displayed frame uses, per RGBA channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitDepthAnalysis {
+    pub unique_values_per_channel: [usize; 4],
+    // log2 of the unique values
+    pub effective_bit_depth: [f32; 4],
+    // Share of the pixels with R, G and B all at 255
+    pub clipped_white_fraction: f32,
+    // Share of the pixels with R, G and B all at 0
+    pub clipped_black_fraction: f32,
+}
+
+/// Count the distinct levels of each channel of an RGBA8 frame, and the clipped pixels
+pub fn analyze_bit_depth(frame: &[u8]) -> BitDepthAnalysis {
+    let mut used = [[false; 256]; 4];
+    let (mut white, mut black) = (0, 0);
+    let pixels = frame.chunks_exact(4);
+    let pixel_count = pixels.len().max(1);
+    for pixel in pixels {
+        for (levels, &value) in used.iter_mut().zip(pixel) {
+            levels[value as usize] = true;
+        }
+        if pixel[..3] == [255; 3] {
+            white += 1;
+        } else if pixel[..3] == [0; 3] {
+            black += 1;
+        }
+    }
+
+    let unique_values_per_channel = used.map(|levels| levels.iter().filter(|&&used| used).count());
+    BitDepthAnalysis {
+        unique_values_per_channel,
+        effective_bit_depth: unique_values_per_channel.map(|unique| (unique.max(1) as f32).log2()),
+        clipped_white_fraction: white as f32 / pixel_count as f32,
+        clipped_black_fraction: black as f32 / pixel_count as f32,
+    }
+}
+
+/// Tiles of the Truchet pattern, each drawn in one of its orientations picked at random
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TruchetTileSet {
+    /// Two quarter circle arcs around opposite corners
+    Classic,
+    /// A single diagonal line
+    Lines,
+    /// The cell split along a diagonal, one half filled (Truchet's original tiles)
+    Triangles,
+    /// Filled quarter circles (Smith's tiles), two colored so no two touching regions match
+    Smith,
+}
+
+impl TruchetTileSet {
+    pub const ALL: [TruchetTileSet; 4] = [
+        TruchetTileSet::Classic,
+        TruchetTileSet::Lines,
+        TruchetTileSet::Triangles,
+        TruchetTileSet::Smith,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TruchetTileSet::Classic => "Classic",
+            TruchetTileSet::Lines => "Lines",
+            TruchetTileSet::Triangles => "Triangles",
+            TruchetTileSet::Smith => "Smith",
+        }
+    }
+}
+
+/// Parameters of the Truchet tiles render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TruchetSettings {
+    // Pixels, from 8 to 128
+    pub tile_size: u32,
+    pub seed: u64,
+    // ACEScg, of the background and of the lines or filled shapes
+    pub color_a: [f32; 3],
+    pub color_b: [f32; 3],
+    pub tile_set: TruchetTileSet,
+}
+
+impl Default for TruchetSettings {
+    fn default() -> Self {
+        Self {
+            tile_size: 24,
+            seed: 0,
+            color_a: [0.02, 0.03, 0.06],
+            color_b: [0.9, 0.6, 0.2],
+            tile_set: TruchetTileSet::Classic,
+        }
+    }
+}
+
+/// Render a grid of `tile_size` pixels Truchet tiles of `tile_set`, each turned by a random
+/// multiple of 90° picked from `seed`. Lines and filled shapes are drawn in `color_b` over
+/// `color_a`, with anti-aliased edges. Only the pixels in `region` are written, see
+/// `render_bg_image`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_truchet(
+    buffer: &mut [f32],
+    width: usize,
+    height: usize,
+    region: [usize; 4],
+    tile_size: usize,
+    seed: u64,
+    color_a: [f32; 3],
+    color_b: [f32; 3],
+    tile_set: TruchetTileSet,
+) {
+    let tile_size = tile_size.max(2);
+    let size = tile_size as f32;
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+    // Quarter turns of each tile, row by row
+    let orientations: Vec<u64> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (row, column)))
+        .map(|(row, column)| {
+            let coordinates = ((column as u64) << 32) | row as u64;
+            SplitMix64::new(seed ^ coordinates.wrapping_mul(0x9E37_79B9_7F4A_7C15)).next_u64() % 4
+        })
+        .collect();
+    let thickness = (size / 8.0).max(1.0);
+    let [x0, y0, x1, y1] = region;
+    // The lines spill out of their tile by up to half their thickness
+    let reach = thickness.ceil() as usize;
+
+    // How much of `color_b` covers each pixel
+    let mut coverage = vec![0.0; width * height];
+    for (index, &orientation) in orientations.iter().enumerate() {
+        let (column, row) = (index % columns, index / columns);
+        if column * tile_size >= x1 + reach
+            || (column + 1) * tile_size + reach <= x0
+            || row * tile_size >= y1 + reach
+            || (row + 1) * tile_size + reach <= y0
+        {
+            continue;
+        }
+        let origin = [column as f32 * size, row as f32 * size];
+        let corner = |x: f32, y: f32| [origin[0] + x * size, origin[1] + y * size];
+        // Lines and arcs look the same turned by 180°, only the parity matters
+        let flipped = orientation % 2 == 1;
+        match tile_set {
+            TruchetTileSet::Classic => {
+                use std::f32::consts::{FRAC_PI_2, PI};
+                let arcs = if flipped {
+                    [
+                        (corner(1.0, 0.0), FRAC_PI_2, PI),
+                        (corner(0.0, 1.0), -FRAC_PI_2, 0.0),
+                    ]
+                } else {
+                    [
+                        (corner(0.0, 0.0), 0.0, FRAC_PI_2),
+                        (corner(1.0, 1.0), PI, -FRAC_PI_2),
+                    ]
+                };
+                for (center, start, end) in arcs {
+                    rasterize_arc_coverage(
+                        &mut coverage,
+                        width,
+                        height,
+                        center,
+                        size * 0.5,
+                        start,
+                        end,
+                        thickness,
+                    );
+                }
+            }
+            TruchetTileSet::Lines => {
+                let (a, b) = if flipped {
+                    (corner(1.0, 0.0), corner(0.0, 1.0))
+                } else {
+                    (corner(0.0, 0.0), corner(1.0, 1.0))
+                };
+                rasterize_segment_coverage(&mut coverage, width, height, a, b, thickness);
+            }
+            TruchetTileSet::Triangles | TruchetTileSet::Smith => {
+                for y in row * tile_size..((row + 1) * tile_size).min(height) {
+                    for x in column * tile_size..((column + 1) * tile_size).min(width) {
+                        // Position in the tile, in pixels
+                        let u = x as f32 + 0.5 - origin[0];
+                        let v = y as f32 + 0.5 - origin[1];
+                        coverage[y * width + x] = if tile_set == TruchetTileSet::Triangles {
+                            triangle_tile_coverage(u, v, size, orientation)
+                        } else {
+                            smith_tile_coverage(u, v, size, flipped, (column + row) % 2 == 1)
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    for (x, y, pixel) in region_pixels_mut(buffer, width, region) {
+        let t = coverage[y * width + x];
+        for ((channel, a), b) in pixel.iter_mut().zip(color_a).zip(color_b) {
+            *channel = a + (b - a) * t;
+        }
+        pixel[3] = 1.0;
+    }
+}
+
+/// Coverage of the filled half of a Truchet triangle tile at `u`, `v` pixels in the tile.
+/// The filled corner turns with the quarter turns of `orientation`.
+fn triangle_tile_coverage(u: f32, v: f32, size: f32, orientation: u64) -> f32 {
+    // Signed distance to the diagonal, positive on the filled side
+    let distance = match orientation {
+        0 => size - u - v,
+        1 => u - v,
+        2 => u + v - size,
+        _ => v - u,
+    } * std::f32::consts::FRAC_1_SQRT_2;
+    (distance + 0.5).clamp(0.0, 1.0)
+}
+
+/// Coverage of `color_b` of a two colored Smith tile at `u`, `v` pixels in the tile.
+/// Grid corners are colored as a checkerboard, each region takes the color of the
+/// corners it touches, so the coloring carries on across the tiles. `odd` is the parity
+/// of the tile, whose top left corner then has `color_b`.
+fn smith_tile_coverage(u: f32, v: f32, size: f32, flipped: bool, odd: bool) -> f32 {
+    let radius = size * 0.5;
+    // Corners at the center of the quarter circles, the other two touch the band between
+    let discs = if flipped {
+        [[size, 0.0], [0.0, size]]
+    } else {
+        [[0.0, 0.0], [size, size]]
+    };
+    let distance = discs
+        .iter()
+        .map(|disc| (u - disc[0]).hypot(v - disc[1]) - radius)
+        .fold(f32::MAX, f32::min);
+    let in_disc = (0.5 - distance).clamp(0.0, 1.0);
+    // The top left corner is a disc center when not flipped
+    let disc_is_b = odd != flipped;
+    if disc_is_b {
+        in_disc
+    } else {
+        1.0 - in_disc
+    }
+}
+
+/// Octaves of Perlin noise in the base of the grunge map
+const GRUNGE_NOISE_OCTAVES: u32 = 4;
+
+// How much each layer adds to the grunge map
+const GRUNGE_NOISE_WEIGHT: f32 = 0.4;
+const GRUNGE_SCRATCH_WEIGHT: f32 = 0.6;
+const GRUNGE_STAIN_WEIGHT: f32 = 0.5;
+
+/// Parameters of the grunge map render mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GrungeParams {
+    pub scratch_count: u32,
+    // Pixels, min and max
+    pub scratch_length_range: [f32; 2],
+    pub stain_count: u32,
+    // Pixels, min and max
+    pub stain_radius_range: [f32; 2],
+    // Pixels, size of the largest features of the noise
+    pub noise_scale: f32,
+    pub seed: u64,
+}
+
+impl Default for GrungeParams {
+    fn default() -> Self {
+        Self {
+            scratch_count: 40,
+            scratch_length_range: [10.0, 60.0],
+            stain_count: 8,
+            stain_radius_range: [6.0, 24.0],
+            noise_scale: 48.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Render a grayscale wear mask, to be used as roughness or dirt in PBR materials.
+/// Layered Perlin noise gives the general variation, thin anti-aliased scratches and
+/// soft elliptical stains with a Gaussian falloff are added on top. The value, clamped
+/// to [0, 1], is written to the three color channels.
+pub fn render_grunge_map(buffer: &mut [f32], width: usize, height: usize, params: &GrungeParams) {
+    let mut rng = SplitMix64::new(params.seed);
+    let mut in_range = |range: [f32; 2]| {
+        let (min, max) = (range[0].min(range[1]), range[0].max(range[1]));
+        min + (max - min) * rng.next_f32()
+    };
+
+    let mut scratches = vec![0.0; width * height];
+    for _ in 0..params.scratch_count {
+        let center = [
+            in_range([0.0, width as f32]),
+            in_range([0.0, height as f32]),
+        ];
+        let angle = in_range([0.0, std::f32::consts::PI]);
+        let half_length = in_range(params.scratch_length_range) * 0.5;
+        let offset = [angle.cos() * half_length, angle.sin() * half_length];
+        rasterize_segment_coverage(
+            &mut scratches,
+            width,
+            height,
+            [center[0] - offset[0], center[1] - offset[1]],
+            [center[0] + offset[0], center[1] + offset[1]],
+            1.0,
+        );
+    }
+
+    let mut stains = vec![0.0; width * height];
+    for _ in 0..params.stain_count {
+        let center = [
+            in_range([0.0, width as f32]),
+            in_range([0.0, height as f32]),
+        ];
+        let radius = in_range(params.stain_radius_range).max(1.0);
+        // Elongated by up to 2:1, along a random direction
+        let radii = [radius, radius * in_range([0.5, 1.0])];
+        let (sin, cos) = in_range([0.0, std::f32::consts::PI]).sin_cos();
+        let intensity = in_range([0.3, 1.0]);
+        // The falloff is below 1% past 1.5 radii
+        let reach = radius * 1.5;
+        let min_x = (center[0] - reach).floor().max(0.0) as usize;
+        let min_y = (center[1] - reach).floor().max(0.0) as usize;
+        let max_x = ((center[0] + reach).ceil().max(0.0) as usize).min(width);
+        let max_y = ((center[1] + reach).ceil().max(0.0) as usize).min(height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let dx = x as f32 + 0.5 - center[0];
+                let dy = y as f32 + 0.5 - center[1];
+                // In units of the radii, 1 on the edge of the ellipse
+                let u = (dx * cos + dy * sin) / radii[0];
+                let v = (dy * cos - dx * sin) / radii[1];
+                stains[y * width + x] += intensity * (-2.0 * (u * u + v * v)).exp();
+            }
+        }
+    }
+
+    let frequency = 1.0 / params.noise_scale.max(1.0);
+    for (index, pixel) in buffer.chunks_exact_mut(4).enumerate() {
+        let p = [
+            (index % width) as f32 * frequency,
+            (index / width) as f32 * frequency,
+        ];
+        let (mut noise, mut amplitude, mut total) = (0.0, 1.0, 0.0);
+        for octave in 0..GRUNGE_NOISE_OCTAVES {
+            let scale = (1 << octave) as f32;
+            noise += perlin_noise(
+                [p[0] * scale, p[1] * scale],
+                params.seed.wrapping_add(octave as u64),
+            ) * amplitude;
+            total += amplitude;
+            amplitude *= 0.5;
+        }
+        let base = (noise / total) * 0.5 + 0.5;
+        let value = (base * GRUNGE_NOISE_WEIGHT
+            + scratches[index] * GRUNGE_SCRATCH_WEIGHT
+            + stains[index] * GRUNGE_STAIN_WEIGHT)
+            .clamp(0.0, 1.0);
+        pixel[..3].fill(value);
+        pixel[3] = 1.0;
+    }
+}
+
+/// Offset to the nearest seed pixel, a pixel with no seed in reach is this far away
+const SDF_FAR: [i32; 2] = [9999, 9999];
+
+/// Propagate the nearest seed offsets of the 8SSEDT over `grid`, in two passes:
+/// down the rows then back up, each row scanned one way then the other.
+fn ssedt_propagate(grid: &mut [[i32; 2]], width: usize, height: usize) {
+    let length_sq = |offset: [i32; 2]| {
+        let [dx, dy] = offset.map(i64::from);
+        dx * dx + dy * dy
+    };
+    let compare = |grid: &mut [[i32; 2]], x: usize, y: usize, ox: i32, oy: i32| {
+        let (nx, ny) = (x as i32 + ox, y as i32 + oy);
+        if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+            return;
+        }
+        let neighbor = grid[ny as usize * width + nx as usize];
+        let candidate = [neighbor[0] + ox, neighbor[1] + oy];
+        let current = &mut grid[y * width + x];
+        if length_sq(candidate) < length_sq(*current) {
+            *current = candidate;
+        }
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            compare(grid, x, y, -1, 0);
+            compare(grid, x, y, 0, -1);
+            compare(grid, x, y, -1, -1);
+            compare(grid, x, y, 1, -1);
+        }
+        for x in (0..width).rev() {
+            compare(grid, x, y, 1, 0);
+        }
+    }
+    for y in (0..height).rev() {
+        for x in (0..width).rev() {
+            compare(grid, x, y, 1, 0);
+            compare(grid, x, y, 0, 1);
+            compare(grid, x, y, -1, 1);
+            compare(grid, x, y, 1, 1);
+        }
+        for x in 0..width {
+            compare(grid, x, y, -1, 0);
+        }
+    }
+}
+
+/// Signed distance in pixels from each pixel of a binary `mask`, one value per pixel
+/// with inside above 0.5, to the edge of the mask: positive inside, negative outside.
+/// Uses the eight-point signed sequential Euclidean distance transform (8SSEDT).
+pub fn generate_sdf(mask: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let inside: Vec<bool> = mask[..width * height]
+        .iter()
+        .map(|value| *value > 0.5)
+        .collect();
+    // Offsets to the nearest inside pixel, and to the nearest outside one
+    let mut to_inside: Vec<[i32; 2]> = inside
+        .iter()
+        .map(|&inside| if inside { [0, 0] } else { SDF_FAR })
+        .collect();
+    let mut to_outside: Vec<[i32; 2]> = inside
+        .iter()
+        .map(|&inside| if inside { SDF_FAR } else { [0, 0] })
+        .collect();
+    ssedt_propagate(&mut to_inside, width, height);
+    ssedt_propagate(&mut to_outside, width, height);
+
+    let length = |[dx, dy]: [i32; 2]| (dx as f32).hypot(dy as f32);
+    to_outside
+        .iter()
+        .zip(&to_inside)
+        .map(|(to_outside, to_inside)| length(*to_outside) - length(*to_inside))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::RENDER_BUFFER_REGION;
+
+    #[test]
+    fn acescct_decode_stops_at_half_max() {
+        assert_eq!(acescct_to_acescg(2.0), HALF_MAX);
+        let linear = 100.0;
+        assert!((acescct_to_acescg(acescg_to_acescct(linear)) - linear).abs() < 1e-2);
+    }
+
+    #[test]
+    fn unchanged_pixels_keep_their_encoded_values() {
+        let (width, height) = (4, 3);
+        let encoding = StorageEncoding::AcesCct;
+        let original: Vec<f32> = (0..width * height * 4)
+            .map(|i| encoding.encode(i as f32 * 0.37))
+            .collect();
+        let mut buffer = original.clone();
+        let region = [1, 1, 3, 3];
+        let stored = decode_region(&mut buffer, width, region, encoding);
+        // Paint the red channel of a single pixel
+        let painted = (width + 2) * 4;
+        buffer[painted] = 0.5;
+        encode_changed_region(&mut buffer, width, region, encoding, &stored);
+
+        assert_eq!(buffer[painted], encoding.encode(0.5));
+        for (i, (value, original)) in buffer.iter().zip(&original).enumerate() {
+            if i != painted {
+                assert_eq!(value.to_bits(), original.to_bits(), "index {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn bayer_dither_follows_the_matrix() {
+        let (width, height) = (16, 8);
+        let mut frame = vec![128; width * height * 4];
+        apply_bayer_dither(&mut frame, width, height, 0.0);
+        assert!(frame.iter().all(|&value| value == 128));
+
+        apply_bayer_dither(&mut frame, width, height, 2.0);
+        let pixel = |x: usize, y: usize| &frame[(y * width + x) * 4..(y * width + x + 1) * 4];
+        // Thresholds 0, 32 and 60 out of 64
+        assert_eq!(pixel(0, 0), [127, 127, 127, 128]);
+        assert_eq!(pixel(1, 0), [128, 128, 128, 128]);
+        assert_eq!(pixel(0, 3), [129, 129, 129, 128]);
+        // The matrix tiles every 8 pixels
+        assert_eq!(pixel(8, 3), pixel(0, 3));
+    }
+
+    #[test]
+    fn uniform_environment_projects_onto_the_dc_term_only() {
+        let grey = 0.5;
+        let hdri = HdriMap {
+            width: 64,
+            height: 32,
+            pixels: vec![[grey; 3]; 64 * 32],
+        };
+        let sh = bake_sh_irradiance(&hdri);
+
+        // Integral of a constant over the sphere against Y00 = 0.282095
+        let expected_dc = grey * 0.282_095 * 4.0 * std::f32::consts::PI;
+        for c in sh.coefficients[0] {
+            assert!((c - expected_dc).abs() < expected_dc * 0.01, "DC {c}");
+        }
+        for coefficient in &sh.coefficients[1..] {
+            for c in coefficient {
+                assert!(c.abs() < 1e-2, "non-DC coefficient {c}");
+            }
+        }
+
+        // A constant environment lights every normal the same, E = pi * L
+        let irradiance = sh.irradiance([0.0, 0.0, 1.0]);
+        for e in irradiance {
+            assert!(
+                (e - std::f32::consts::PI * grey).abs() < 0.05,
+                "irradiance {e}"
+            );
+        }
+    }
+
+    /// HSL hue in [0, 1) of an ACEScg color, the inverse of what `generate_palette` does
+    fn palette_hue(color: [f32; 3]) -> f32 {
+        let [r, g, b] = mul_mat3(&ACESCG_TO_LINEAR_SRGB, color).map(linear_to_srgb);
+        let max = r.max(g).max(b);
+        let chroma = max - r.min(g).min(b);
+        let sector = if max == r {
+            ((g - b) / chroma).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / chroma + 2.0
+        } else {
+            (r - g) / chroma + 4.0
+        };
+        sector / 6.0
+    }
+
+    #[test]
+    fn palette_hues_are_spaced_about_evenly() {
+        for n in 2..=12 {
+            let mut hues: Vec<f32> = generate_palette(n, 7, 0.8, 0.5)
+                .into_iter()
+                .map(palette_hue)
+                .collect();
+            hues.sort_by(f32::total_cmp);
+            let mut gaps: Vec<f32> = hues.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            gaps.push(1.0 - hues[n - 1] + hues[0]);
+
+            // Golden ratio stepping leaves at most three distinct gap sizes,
+            // all within a small factor of an even 360 / n degrees split
+            let even = 360.0 / n as f32;
+            for gap in gaps {
+                let degrees = gap * 360.0;
+                assert!(
+                    degrees > even * 0.45 && degrees < even * 1.8,
+                    "{n} colors: gap of {degrees} degrees, expected about {even}"
+                );
+            }
+        }
+    }
+
+    fn mean_luminance(buffer: &[f32]) -> f32 {
+        let pixels = buffer.chunks_exact(4);
+        let count = pixels.len() as f32;
+        pixels
+            .map(|pixel| acescg_luminance([pixel[0], pixel[1], pixel[2]]))
+            .sum::<f32>()
+            / count
+    }
+
+    #[test]
+    fn mip_levels_keep_the_mean_luminance() {
+        let mut framebuffer = Box::new([0.0; RENDER_BUFFER_SIZE]);
+        render_bg_image(&mut framebuffer, SamplerKind::Uniform, RENDER_BUFFER_REGION);
+        let pyramid = MipPyramid::build_from_buffer(
+            &framebuffer[..],
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+        );
+
+        let base = mean_luminance(pyramid.level(0));
+        assert!(pyramid.num_levels() > 1);
+        for lod in 1..pyramid.num_levels() {
+            // Odd sizes fold the leftover row and column into the last block,
+            // which weights them a little differently
+            let mean = mean_luminance(pyramid.level(lod));
+            assert!(
+                (mean - base).abs() < base * 0.02,
+                "level {lod} mean {mean}, level 0 mean {base}"
+            );
+        }
+    }
+
+    const SPHERE_SIZE: usize = 64;
+    const SPHERE_ALBEDO: [f32; 3] = [0.8, 0.3, 0.1];
+
+    /// An orange Lambert shaded sphere on black, lit from the top right
+    fn gradient_sphere() -> Vec<f32> {
+        let light = [0.5, 0.5, 0.7].map(|c: f32| c / 0.99_f32.sqrt());
+        let mut buffer = vec![0.0; SPHERE_SIZE * SPHERE_SIZE * 4];
+        for (i, pixel) in buffer.chunks_exact_mut(4).enumerate() {
+            let [x, y] = [i % SPHERE_SIZE, i / SPHERE_SIZE]
+                .map(|c| fit_range(c as f32 + 0.5, 0.0, SPHERE_SIZE as f32, -1.0, 1.0) / 0.8);
+            pixel[3] = 1.0;
+            let r2 = x * x + y * y;
+            if r2 > 1.0 {
+                continue;
+            }
+            // Rows go down, so the normal's Y is flipped
+            let normal = [x, -y, (1.0 - r2).sqrt()];
+            let lambert = normal.iter().zip(light).map(|(n, l)| n * l).sum::<f32>();
+            let shade = lambert.max(0.0) * 0.9 + 0.05;
+            for (channel, albedo) in pixel.iter_mut().zip(SPHERE_ALBEDO) {
+                *channel = albedo * shade;
+            }
+        }
+        buffer
+    }
+
+    #[test]
+    fn cel_shade_quantizes_lightness_and_keeps_the_hue() {
+        let mut buffer = gradient_sphere();
+        let num_tones = 4;
+        // No edges
+        apply_cel_shade(
+            &mut buffer,
+            SPHERE_SIZE,
+            SPHERE_SIZE,
+            num_tones,
+            f32::INFINITY,
+            [0.0, 0.0, 0.0, 1.0],
+            CelShadeMode::Single,
+        );
+
+        let mut tones_used = Vec::new();
+        for pixel in buffer.chunks_exact(4).filter(|pixel| pixel[0] > 0.0) {
+            let lightness = oklab_lightness([pixel[0], pixel[1], pixel[2]]);
+            // Each band lands on its middle
+            let band = (lightness * num_tones as f32 - 0.5).round();
+            assert!(
+                (lightness - (band + 0.5) / num_tones as f32).abs() < 1e-3,
+                "lightness {lightness} is between bands"
+            );
+            if !tones_used.contains(&band) {
+                tones_used.push(band);
+            }
+            // Scaling RGB keeps the ratios between channels
+            let ratio = SPHERE_ALBEDO[0] / SPHERE_ALBEDO[1];
+            assert!((pixel[0] / pixel[1] - ratio).abs() < ratio * 1e-4);
+        }
+        assert!(tones_used.len() > 1 && tones_used.len() <= num_tones as usize);
+    }
+
+    #[test]
+    fn cel_shade_outlines_the_sphere() {
+        let original = gradient_sphere();
+        let edge_color = [0.0, 1.0, 0.0, 1.0];
+        let shade = |mode| {
+            let mut buffer = original.clone();
+            apply_cel_shade(
+                &mut buffer,
+                SPHERE_SIZE,
+                SPHERE_SIZE,
+                4,
+                0.1,
+                edge_color,
+                mode,
+            );
+            buffer
+        };
+        let single = shade(CelShadeMode::Single);
+        let dual = shade(CelShadeMode::DualThreshold);
+        let is_edge = |buffer: &[f32], x: usize, y: usize| {
+            let index = (y * SPHERE_SIZE + x) * 4;
+            buffer[index..index + 3] == edge_color[..3]
+        };
+
+        let middle = SPHERE_SIZE / 2;
+        let rim = (0..SPHERE_SIZE)
+            .find(|x| original[(middle * SPHERE_SIZE + x) * 4] > 0.0)
+            .unwrap();
+        assert!(is_edge(&single, rim, middle), "the silhouette is an edge");
+        assert!(
+            !is_edge(&single, middle, middle),
+            "the smooth shading is not"
+        );
+        assert!(!is_edge(&single, 0, 0), "the flat background is not");
+
+        // Dual threshold only adds weak edges, the strong ones stay
+        for y in 0..SPHERE_SIZE {
+            for x in 0..SPHERE_SIZE {
+                if is_edge(&single, x, y) {
+                    assert!(is_edge(&dual, x, y));
+                }
+            }
+        }
+    }
+
+    /// ACEScg RGBA pixels with every channel within [0, 1]
+    const IN_RANGE_PIXELS: [[f32; 4]; 6] = [
+        [0.0, 0.0, 0.0, 1.0],
+        [0.18, 0.18, 0.18, 1.0],
+        [0.8, 0.3, 0.1, 0.5],
+        [0.05, 0.4, 0.9, 1.0],
+        [0.6, 0.55, 0.02, 0.25],
+        [1.0, 1.0, 1.0, 1.0],
+    ];
+
+    fn assert_same_pixels(actual: &[f32], expected: &[f32]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-4, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn inverting_twice_is_identity() {
+        let original: Vec<f32> = IN_RANGE_PIXELS.concat();
+        let width = IN_RANGE_PIXELS.len();
+
+        let mut buffer = original.clone();
+        apply_invert(&mut buffer, width, 1, 1.0);
+        apply_invert(&mut buffer, width, 1, 1.0);
+        assert_same_pixels(&buffer, &original);
+
+        // Up to a brighter scene maximum
+        let bright: Vec<f32> = IN_RANGE_PIXELS
+            .map(|[r, g, b, a]| [r * 4.0, g * 4.0, b * 4.0, a])
+            .concat();
+        let mut buffer = bright.clone();
+        apply_invert(&mut buffer, width, 1, 4.0);
+        apply_invert(&mut buffer, width, 1, 4.0);
+        assert_same_pixels(&buffer, &bright);
+
+        let mut buffer = original.clone();
+        apply_invert_luminance(&mut buffer, width, 1);
+        apply_invert_luminance(&mut buffer, width, 1);
+        assert_same_pixels(&buffer, &original);
+    }
+
+    #[test]
+    fn rotating_the_hue_a_full_turn_is_identity() {
+        let original: Vec<f32> = IN_RANGE_PIXELS.concat();
+        let width = IN_RANGE_PIXELS.len();
+
+        let mut buffer = original.clone();
+        apply_hue_rotate(&mut buffer, width, 1, 360.0);
+        assert_same_pixels(&buffer, &original);
+
+        let mut buffer = original.clone();
+        apply_hue_rotate(&mut buffer, width, 1, 120.0);
+        apply_hue_rotate(&mut buffer, width, 1, 240.0);
+        assert_same_pixels(&buffer, &original);
+    }
+
+    #[test]
+    fn highlight_recovery_adds_no_blue_to_clipped_yellow() {
+        // Clipped yellow in the middle of an unclipped, slightly darker yellow
+        let mut buffer: Vec<f32> = [[0.7, 0.6, 0.05, 1.0]; 9].concat();
+        buffer[16..20].copy_from_slice(&[1.5, 1.5, 0.0, 1.0]);
+        apply_highlight_recovery(&mut buffer, 3, 3, 1.0);
+
+        let recovered = &buffer[16..20];
+        assert!(recovered[2].abs() < 1e-6, "blue became {}", recovered[2]);
+        // Clipped values are a lower bound, they never get darker
+        assert!(recovered[0] >= 1.5 && recovered[1] >= 1.5);
+        assert_eq!(recovered[3], 1.0);
+    }
+
+    #[test]
+    fn premultiplying_then_unpremultiplying_is_identity() {
+        // Scene linear, so colors above 1 too
+        let original: Vec<f32> = [
+            [0.8, 0.3, 0.1, 1.0],
+            [0.18, 0.18, 0.18, 0.5],
+            [4.0, 2.5, 0.0, 0.25],
+            [0.05, 0.4, 0.9, 0.01],
+        ]
+        .concat();
+
+        let mut buffer = original.clone();
+        premultiply_alpha(&mut buffer);
+        assert_eq!(buffer[12..16], [1.0, 0.625, 0.0, 0.25]);
+        unpremultiply_alpha(&mut buffer);
+        for (actual, expected) in buffer.iter().zip(&original) {
+            assert!(
+                (actual - expected).abs() <= expected.abs() * 1e-6,
+                "{buffer:?} != {original:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn jpeg_export_embeds_the_software_tag() {
+        let path = std::env::temp_dir().join("pixels-egui-framebuffer-exif-round-trip.jpg");
+        let (width, height) = (16, 8);
+        let buffer: Vec<f32> = [[0.18, 0.18, 0.18, 1.0]; 16 * 8].concat();
+        let metadata = JpegMetadata {
+            tonemapper: "Perceptual".to_owned(),
+            exposure_ev: 0.0,
+            document_name: None,
+            description: None,
+        };
+        write_as_jpeg_image(&path, width, height, &buffer, 0.0, Some(&metadata)).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(file))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let software = exif
+            .get_field(exif::Tag::Software, exif::In::PRIMARY)
+            .expect("no Software tag");
+        match &software.value {
+            exif::Value::Ascii(strings) => {
+                assert_eq!(strings, &[EXIF_SOFTWARE.as_bytes()]);
+            }
+            value => panic!("Software is not a string: {value:?}"),
+        }
+    }
+
+    #[test]
+    fn region_renders_match_the_full_frame_and_skip_the_rest() {
+        let (width, height) = (32, 24);
+        let region = [5, 3, 20, 17];
+        let params = TerrainParams::default();
+        let render = |buffer: &mut [f32], region| {
+            render_ssaa(buffer, width, height, region, 2, |buffer, w, h, region| {
+                render_terrain(buffer, w, h, region, &params)
+            })
+        };
+        let mut full = vec![0.0; width * height * 4];
+        render(&mut full[..], [0, 0, width, height]);
+        let mut partial = vec![-1.0; width * height * 4];
+        render(&mut partial[..], region);
+
+        let [x0, y0, x1, y1] = region;
+        for (index, (full, partial)) in full
+            .chunks_exact(4)
+            .zip(partial.chunks_exact(4))
+            .enumerate()
+        {
+            let (x, y) = (index % width, index / width);
+            if (x0..x1).contains(&x) && (y0..y1).contains(&y) {
+                assert_eq!(full, partial, "pixel ({x}, {y})");
+            } else {
+                assert_eq!(partial, [-1.0; 4], "pixel ({x}, {y})");
+            }
+        }
+    }
+}
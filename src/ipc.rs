@@ -0,0 +1,179 @@
+use std::io::{BufRead, BufReader, Read};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+
+use log::{error, info, warn};
+use serde::Deserialize;
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+
+// Source of the checked-in `docs/ipc.md`, see the test below
+#[cfg(test)]
+mod schema;
+
+/// Name of the named pipe we listen on, `\\.\pipe\pixels-egui-framebuffer`
+#[cfg(windows)]
+pub(crate) const PIPE_NAME: &str = "pixels-egui-framebuffer";
+
+/// A single newline-delimited JSON message, see `docs/ipc.md`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Message {
+    Render,
+    SetParam { key: String, value: f64 },
+    Save { path: PathBuf },
+}
+
+impl From<Message> for UserEvent {
+    fn from(message: Message) -> Self {
+        match message {
+            Message::Render => UserEvent::Render,
+            Message::SetParam { key, value } => UserEvent::SetParam { key, value },
+            Message::Save { path } => UserEvent::Save {
+                path,
+                single_channel: false,
+            },
+        }
+    }
+}
+
+/// Path of the Unix domain socket we listen on, in a directory only the user can enter
+#[cfg(unix)]
+pub(crate) fn socket_path() -> PathBuf {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    // SAFETY: getuid can't fail
+    let uid = unsafe { libc::getuid() };
+    runtime_dir
+        .join(format!("pixels-egui-framebuffer-{uid}"))
+        .join("ipc.sock")
+}
+
+/// Create the directory of the socket, or check that nobody else can get into it
+#[cfg(unix)]
+fn create_private_dir(dir: &std::path::Path) -> std::io::Result<()> {
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            let metadata = std::fs::symlink_metadata(dir)?;
+            // SAFETY: getuid can't fail
+            let uid = unsafe { libc::getuid() };
+            if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    format!("{} is not a private directory of this user", dir.display()),
+                ));
+            }
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Spawn the background thread accepting IPC connections.
+/// Every message received is forwarded to the main thread as a `UserEvent`.
+pub(crate) fn spawn(proxy: EventLoopProxy<UserEvent>) -> std::io::Result<JoinHandle<()>> {
+    #[cfg(unix)]
+    let listener = {
+        let path = socket_path();
+        if let Some(dir) = path.parent() {
+            create_private_dir(dir)?;
+        }
+        if path.exists() {
+            // Don't take the socket of an instance that is still running
+            if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AddrInUse,
+                    format!("Another instance is listening on {}", path.display()),
+                ));
+            }
+            // Left behind by a previous run
+            std::fs::remove_file(&path)?;
+        }
+        let listener = std::os::unix::net::UnixListener::bind(&path)?;
+        // The directory keeps other users out already, the socket doesn't depend on the umask
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        info!("Listening for IPC messages on {}", path.display());
+        listener
+    };
+
+    // The default security of a named pipe only lets the same user write to it
+    #[cfg(windows)]
+    let listener = {
+        let listener = interprocess::local_socket::LocalSocketListener::bind(PIPE_NAME)?;
+        info!("Listening for IPC messages on \\\\.\\pipe\\{PIPE_NAME}");
+        listener
+    };
+
+    let handle = std::thread::Builder::new()
+        .name("ipc".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let proxy = proxy.clone();
+                        std::thread::spawn(move || handle_connection(stream, proxy));
+                    }
+                    Err(e) => {
+                        error!("Failed to accept IPC connection: {e:?}");
+                    }
+                }
+            }
+        })?;
+
+    Ok(handle)
+}
+
+fn handle_connection(stream: impl Read, proxy: EventLoopProxy<UserEvent>) {
+    let reader = BufReader::new(stream);
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to read IPC message: {e:?}");
+                return;
+            }
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<Message>(&line) {
+            Ok(message) => {
+                // The event loop is gone, nothing left to drive
+                if proxy.send_event(message.into()).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                warn!("Ignoring invalid IPC message {line:?}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::schema::IPC_SCHEMA;
+
+    /// `docs/ipc.md` is checked in so it can be read without building.
+    /// Run with `UPDATE_GENERATED=1` to rewrite it after changing `IPC_SCHEMA`.
+    #[test]
+    fn docs_match_the_schema() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/ipc.md");
+        if std::env::var_os("UPDATE_GENERATED").is_some() {
+            std::fs::write(&path, IPC_SCHEMA).unwrap();
+        }
+        let docs = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            docs == IPC_SCHEMA,
+            "docs/ipc.md is out of date, run `UPDATE_GENERATED=1 cargo test --features ipc`"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+// `docs/ipc.md` is a copy of `IPC_SCHEMA`, kept in sync by a test of `ipc.rs`.
+
+/// Markdown description of the messages accepted on the IPC socket
+pub const IPC_SCHEMA: &str = r#"# IPC
+
+When built with `--features ipc`, the application listens for commands from
+external tools so that renders can be driven from shell scripts or Python.
+
+## Transport
+
+- Unix: a Unix domain socket at `pixels-egui-framebuffer-<uid>/ipc.sock` in
+  `$XDG_RUNTIME_DIR`, or in `$TMPDIR` when it isn't set. Starting fails if another
+  instance is already listening on it.
+- Windows: a named pipe at `\\.\pipe\pixels-egui-framebuffer`
+
+Only processes of the user running the application can connect.
+
+Each connection can send any number of messages.
+Messages are newline-delimited JSON objects, one per line.
+Lines that fail to parse are logged and ignored.
+
+## Messages
+
+### render
+
+Re-render the framebuffer with the current parameters.
+
+```json
+{"action": "render"}
+```
+
+### set_param
+
+Update a single numeric parameter. Values are read as 64 bit floats, so the
+Mandelbrot viewport keeps its precision on deep zooms. Unknown keys are logged and ignored.
+
+```json
+{"action": "set_param", "key": "exposure_ev", "value": 1.5}
+```
+
+| key | description |
+|-----|-------------|
+| `exposure_ev` | Exposure offset in stops, applied before tonemapping |
+| `mandelbrot_center_x` | Real part of the Mandelbrot viewport center |
+| `mandelbrot_center_y` | Imaginary part of the Mandelbrot viewport center |
+| `mandelbrot_scale` | Width of the Mandelbrot viewport |
+
+### save
+
+Write the current framebuffer as an OpenEXR image.
+
+```json
+{"action": "save", "path": "/tmp/out.exr"}
+```
+
+## Example
+
+```sh
+echo '{"action": "render"}' | nc -U "$XDG_RUNTIME_DIR/pixels-egui-framebuffer-$(id -u)/ipc.sock"
+```
+"#;
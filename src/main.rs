@@ -1,36 +1,590 @@
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
 
-use colstodian::spaces::{AcesCg, EncodedSrgb};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use colstodian::spaces::{AcesCg, EncodedSrgb, LinearSrgb};
 use colstodian::tonemap::{PerceptualTonemapper, PerceptualTonemapperParams, Tonemapper};
 use colstodian::{Color, Display};
-use log::error;
-use pixels::{wgpu, Error, PixelsBuilder, SurfaceTexture};
+use half::f16;
+use log::{debug, error, info, warn};
+use pixels::{Error, PixelsBuilder, SurfaceTexture};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
-use winit::event_loop::{ControlFlow, EventLoop};
+use winit::event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 
+mod animation;
+mod aspect;
+#[cfg(feature = "cpal")]
+mod audio;
+mod background;
+#[cfg(feature = "camera-input")]
+mod camera;
+mod config;
 mod constants;
+mod draw;
+mod effects;
+mod error;
+mod expr;
 mod gui;
 mod image;
+#[cfg(feature = "ipc")]
+mod ipc;
+mod math;
+mod msaa;
+mod nodes;
+mod noise;
+mod passes;
+mod sampling;
+mod scene_desc;
+mod tiles;
+mod wgsl;
 
+use crate::animation::{load_exr_frame, ExrSequencePlayer, PlaybackSettings};
+use crate::aspect::{PixelAspectRatio, PixelAspectRenderer};
+use crate::background::BackgroundRenderer;
+use crate::config::{Autosave, Config, MsaaConfig, SurfaceFormat, TargetFps};
 use crate::constants::{
-    RENDER_BUFFER_HEIGHT, RENDER_BUFFER_SIZE, RENDER_BUFFER_WIDTH, WINDOW_HEIGHT, WINDOW_WIDTH,
+    Framebuffer, BACKGROUND_POLL_MS, CONFIG_PATH, LISSAJOUS_PHASE_STEP, MAX_UNDO_STROKES,
+    OSCILLOSCOPE_SAMPLES, OSCILLOSCOPE_TIME_STEP, RAYMARCHER_PREVIEW_SCALE, RENDER_BUFFER_HEIGHT,
+    RENDER_BUFFER_REGION, RENDER_BUFFER_SIZE, RENDER_BUFFER_WIDTH, SPIRAL_PHASE_STEP,
+    WINDOW_HEIGHT, WINDOW_WIDTH,
+};
+use crate::draw::{Brush, BrushTool, Watermark};
+use crate::effects::{
+    render_mandelbrot_tile, MandelbrotAnimator, MandelbrotKeyframe, MandelbrotView,
+    TemporalDenoiser,
+};
+use crate::error::ImageError;
+use crate::gui::{DropperTarget, Framework};
+use crate::image::{
+    acescg_luminance, apply_bayer_dither, auto_levels, cmyk_preview_rgb, convert_storage_encoding,
+    copy_outside_region, decode_region, default_gradient_anchors, encode_changed_region,
+    export_ascii_art, export_ascii_art_html, generate_sdf, mul_mat3, oscilloscope_test_signal,
+    premultiply_alpha, quantize_u8, region_pixels_mut, render_bg_image, render_brdf_sphere_grid,
+    render_brick_pattern, render_camera_frame, render_color_wheel, render_cubemap_crossview,
+    render_fractal_flame, render_grunge_map, render_lissajous, render_logarithmic_spiral,
+    render_multipoint_gradient, render_oscilloscope, render_pcb_pattern, render_poisson_disk,
+    render_raymarcher, render_sh_sphere, render_sky, render_spectral_gradient, render_ssaa,
+    render_starfield, render_terrain, render_texture_quilting, render_truchet, render_turbulence,
+    render_value_noise, render_wood_grain, render_worley_noise, simulate_cmyk_preview,
+    srgb_to_linear, unpremultiply_alpha, write_as_exr_image, write_as_jpeg_image, AlphaMode,
+    BrdfGridSettings, BrickSettings, CameraFrame, CameraSettings, ColorWheelMode, CubemapFaces,
+    DisplayMode, DitherMode, Exemplar, FractalFlameSettings, GradientAnchor, GrungeParams,
+    JpegMetadata, LevelsHistogram, LevelsParams, LissajousSettings, Lut3d, MetadataMap, MipPyramid,
+    NoiseBlendMode, OscilloscopeSettings, OutputColorSpace, PcbSettings, PoissonDiskSettings,
+    PostProcessSettings, PrintSettings, RaymarcherSettings, SkySettings, SoftProofProfile,
+    SoftProofTarget, SphericalHarmonics9, SpiralSettings, StarfieldSettings, StorageEncoding,
+    TerrainParams, TextureQuiltingSettings, TruchetSettings, TurbulenceSettings,
+    ValueNoiseSettings, WoodGrainSettings, WorleySettings, LINEAR_SRGB_TO_ACESCG,
 };
-use crate::gui::Framework;
-use crate::image::render_bg_image;
+use crate::nodes::NodeGraph;
+use crate::passes::{default_pass_order, PassSlot, RenderModePass, RenderPassStack};
+use crate::sampling::SamplerKind;
+use crate::scene_desc::{
+    ColorWheelParams, PostProcessStep, ProceduralParams, SceneDescription, SceneFiles,
+    TonemapperKind,
+};
+use crate::tiles::{RenderStatus, Tile, TileRenderer};
+use crate::wgsl::WgslRunner;
+
+/// Bright orange (in ACEScg) used to flag out of gamut pixels
+const GAMUT_WARNING_COLOR: [f32; 3] = [1.0, 0.3, 0.0];
+
+/// The procedural image drawn into the framebuffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub(crate) enum RenderMode {
+    Gradient,
+    Mandelbrot,
+    /// Diffuse sphere lit by the baked SH irradiance
+    ShSphere,
+    /// Inverse distance weighted blend of user placed anchors
+    MultipointGradient,
+    /// Cubemap faces reprojected from an HDRI, laid out as a cross
+    CubemapCross,
+    /// Anti-aliased Lissajous curve, optionally animated
+    Lissajous,
+    /// Randomly scattered stars with a glow around the brightest ones
+    Starfield,
+    /// Visible spectrum, integrated from the CIE color matching functions
+    SpectralGradient,
+    /// Iterated function system rendered with the fractal flame algorithm
+    FractalFlame,
+    /// Fractal value noise, optionally layered over the previous image
+    ValueNoise,
+    /// Analytic daylight sky panorama
+    Sky,
+    /// Perlin turbulence or marble, colored with the two scene colors
+    Turbulence,
+    /// Tiling wall of bricks and mortar
+    Bricks,
+    /// Hue / saturation disc at a constant lightness
+    ColorWheel,
+    /// Turbulent rings of wood
+    WoodGrain,
+    /// Evenly spread random dots
+    PoissonDisk,
+    /// Waveform of the audio input, or of a test signal
+    Oscilloscope,
+    /// Live feed of a camera
+    Camera,
+    /// Cellular noise from the distances to random feature points
+    Worley,
+    /// Output of the compositing node graph
+    NodeGraph,
+    /// Shaded fractal landscape seen from above
+    Terrain,
+    /// Circuit board traces and pads, with an optional glow
+    Pcb,
+    /// Texture grown from an exemplar image by quilting patches of it
+    TextureSynthesis,
+    /// Shapes of signed distance functions, found by sphere tracing
+    Raymarcher,
+    /// Material preview spheres, from smooth to rough and from dielectric to metal
+    BrdfSpheres,
+    /// Randomly turned Truchet tiles
+    Truchet,
+    /// Grayscale mask of scratches, stains and noise, for texture artists
+    GrungeMap,
+    /// Logarithmic spiral with a color gradient, optionally turning
+    Spiral,
+}
+
+impl RenderMode {
+    /// Every mode, in the order shown in the GUI
+    pub(crate) const ALL: &'static [RenderMode] = &[
+        RenderMode::Gradient,
+        RenderMode::Mandelbrot,
+        RenderMode::ShSphere,
+        RenderMode::MultipointGradient,
+        RenderMode::CubemapCross,
+        RenderMode::Lissajous,
+        RenderMode::Starfield,
+        RenderMode::SpectralGradient,
+        RenderMode::FractalFlame,
+        RenderMode::ValueNoise,
+        RenderMode::Sky,
+        RenderMode::Turbulence,
+        RenderMode::Bricks,
+        RenderMode::ColorWheel,
+        RenderMode::WoodGrain,
+        RenderMode::PoissonDisk,
+        RenderMode::Oscilloscope,
+        RenderMode::Camera,
+        RenderMode::Worley,
+        RenderMode::NodeGraph,
+        RenderMode::Terrain,
+        RenderMode::Pcb,
+        RenderMode::TextureSynthesis,
+        RenderMode::Raymarcher,
+        RenderMode::BrdfSpheres,
+        RenderMode::Truchet,
+        RenderMode::GrungeMap,
+        RenderMode::Spiral,
+    ];
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            RenderMode::Gradient => "Gradient",
+            RenderMode::Mandelbrot => "Mandelbrot",
+            RenderMode::ShSphere => "SH Sphere",
+            RenderMode::MultipointGradient => "Multi-point Gradient",
+            RenderMode::CubemapCross => "Cubemap Cross",
+            RenderMode::Lissajous => "Lissajous",
+            RenderMode::Starfield => "Star Field",
+            RenderMode::SpectralGradient => "Spectral Gradient",
+            RenderMode::FractalFlame => "Fractal Flame",
+            RenderMode::ValueNoise => "Value Noise",
+            RenderMode::Sky => "Sky",
+            RenderMode::Turbulence => "Turbulence",
+            RenderMode::Bricks => "Bricks",
+            RenderMode::ColorWheel => "Color Wheel",
+            RenderMode::WoodGrain => "Wood Grain",
+            RenderMode::PoissonDisk => "Poisson Disk",
+            RenderMode::Oscilloscope => "Oscilloscope",
+            RenderMode::Camera => "Camera",
+            RenderMode::Worley => "Worley Noise",
+            RenderMode::NodeGraph => "Node Graph",
+            RenderMode::Terrain => "Terrain",
+            RenderMode::Pcb => "Circuit Board",
+            RenderMode::TextureSynthesis => "Texture Synthesis",
+            RenderMode::Raymarcher => "Ray Marcher",
+            RenderMode::BrdfSpheres => "BRDF Spheres",
+            RenderMode::Truchet => "Truchet Tiles",
+            RenderMode::GrungeMap => "Grunge Map",
+            RenderMode::Spiral => "Spiral",
+        }
+    }
+}
+
+/// Events sent to the main thread, either by the GUI or by external tools
+#[derive(Debug, Clone)]
+pub(crate) enum UserEvent {
+    /// Re-render the framebuffer
+    Render,
+    /// Update a single numeric parameter
+    SetParam { key: String, value: f64 },
+    /// Save the framebuffer to the given path. With `single_channel`, only red is written,
+    /// for masks.
+    Save { path: PathBuf, single_channel: bool },
+    /// Save the displayed image as ASCII art, as plain text or as colored HTML
+    SaveAscii {
+        path: PathBuf,
+        // Pixels covered by each character
+        char_w: usize,
+        char_h: usize,
+        charset: String,
+        colored: bool,
+    },
+    /// Save the displayed image as an sRGB JPEG. With `embed_metadata`, EXIF tags record
+    /// the display settings, and the scene when the session has a scene file.
+    SaveJpeg {
+        path: PathBuf,
+        embed_metadata: bool,
+        scene_path: Option<PathBuf>,
+    },
+    /// Switch the procedural image and re-render
+    SetRenderMode(RenderMode),
+    /// Move the Mandelbrot viewport and re-render
+    SetMandelbrotView(MandelbrotView),
+    /// Store the current Mandelbrot viewport as a keyframe
+    RecordKeyframe { frame_number: u32 },
+    /// Forget all the recorded keyframes
+    ClearKeyframes,
+    /// Play the keyframed animation in real time
+    PreviewAnimation,
+    /// Render every frame of the animation, saving them as `<prefix>.<frame>.exr`
+    ExportSequence(PathBuf),
+    /// Re-encode the framebuffer in a different working color space
+    SetStorageEncoding(StorageEncoding),
+    /// Change the sequence used to jitter samples and re-render
+    SetSampler(SamplerKind),
+    /// Simulate the output on another display
+    SetSoftProof {
+        target: SoftProofTarget,
+        gamut_warning: bool,
+    },
+    /// Encode the 8 bit frame for a display set to another color space
+    SetOutputColorSpace(OutputColorSpace),
+    /// Show the image as is or as printed
+    SetDisplayMode {
+        mode: DisplayMode,
+        print: PrintSettings,
+    },
+    /// Dither the 8 bit frame, `scale` is the size of the pattern in code values
+    SetDither { mode: DitherMode, scale: f32 },
+    /// Show the difference against a reference image, given as sRGB RGBA8
+    /// pixels at the framebuffer resolution, or go back to the normal display
+    SetReferenceDifference(Option<Vec<u8>>),
+    /// Light the SH sphere with new coefficients and show it
+    SetShIrradiance(SphericalHarmonics9),
+    /// Replace the anchors of the multi-point gradient and re-render
+    SetGradientAnchors(Vec<GradientAnchor>),
+    /// Preview new cubemap faces, converted from the HDRI at `hdri`
+    SetCubemap {
+        faces: Box<CubemapFaces>,
+        hdri: PathBuf,
+    },
+    /// Change the Lissajous curve and re-render
+    SetLissajous(LissajousSettings),
+    /// Change the star field and re-render
+    SetStarfield(StarfieldSettings),
+    /// Change the wavelengths, in nm, shown by the spectral gradient and re-render
+    SetSpectralRange([f32; 2]),
+    /// Change the fractal flame system and re-render
+    SetFractalFlame(Box<FractalFlameSettings>),
+    /// Change the value noise and re-render
+    SetValueNoise(ValueNoiseSettings),
+    /// Change the sun and atmosphere of the sky and re-render
+    SetSky(SkySettings),
+    /// Change the two ACEScg colors used by the procedural textures and re-render
+    SetSceneColors([[f32; 3]; 2]),
+    /// Change the turbulence texture and re-render
+    SetTurbulence(TurbulenceSettings),
+    /// Change the brick pattern and re-render
+    SetBricks(BrickSettings),
+    /// Change the lightness and model of the color wheel and re-render
+    SetColorWheel {
+        lightness: f32,
+        mode: ColorWheelMode,
+    },
+    /// Change the wood grain and re-render
+    SetWoodGrain(WoodGrainSettings),
+    /// Change the Poisson disk samples and re-render
+    SetPoissonDisk(PoissonDiskSettings),
+    /// Change the oscilloscope trace
+    SetOscilloscope(OscilloscopeSettings),
+    /// Switch camera or mirror its feed
+    SetCamera(CameraSettings),
+    /// Change the Worley noise and re-render
+    SetWorley(WorleySettings),
+    /// Replace the compositing graph and re-render
+    SetNodeGraph(NodeGraph),
+    /// Change the terrain and re-render
+    SetTerrain(TerrainParams),
+    /// Change the ray marched scene and re-render if it's shown
+    SetRaymarcher(RaymarcherSettings),
+    /// Change the material preview grid and re-render if it's shown
+    SetBrdfSpheres(BrdfGridSettings),
+    /// Change the Truchet tiles and re-render if they're shown
+    SetTruchet(TruchetSettings),
+    /// Change the grunge map and re-render if it's shown
+    SetGrunge(GrungeParams),
+    /// Change the spiral and re-render if it's shown
+    SetSpiral(SpiralSettings),
+    /// Change the circuit board and re-render
+    SetPcb(PcbSettings),
+    /// Change the texture synthesis and re-render
+    SetTextureQuilting(TextureQuiltingSettings),
+    /// Grow the synthesized texture from a new exemplar, loaded from `path`
+    SetExemplar {
+        exemplar: Box<Exemplar>,
+        path: PathBuf,
+    },
+    /// Redraw on every iteration of the event loop, instead of only after a change
+    SetContinuousRender(bool),
+    /// Average the frames of animated renders over time
+    SetTemporalDenoise { enabled: bool, blend_factor: f32 },
+    /// Restart the temporal average from the current frame
+    ResetDenoiseHistory,
+    /// Play back the EXR files of a directory
+    OpenSequence(PathBuf),
+    /// Change the playback controls of the EXR sequence
+    SetPlayback(PlaybackSettings),
+    /// Show a frame of the EXR sequence
+    SeekSequence(usize),
+    /// Set the custom attributes written in the header of saved EXR files
+    SetExrMetadata(MetadataMap),
+    /// Change the shape of the framebuffer pixels on screen, and in saved EXR files
+    SetPixelAspectRatio(PixelAspectRatio),
+    /// Collect the tiles the tile renderer finished
+    TilesReady,
+    /// Stop the tiled render in progress
+    CancelRender,
+    /// Run a per-pixel expression on the framebuffer
+    ApplyColorOp(expr::Program),
+    /// Look up the tonemapped colors in a 3D LUT, or stop when `None`
+    SetLut(Option<Lut3d>),
+    /// Text drawn over the saved images, none when `None`
+    SetWatermark(Option<Watermark>),
+    /// Draw text into the framebuffer
+    BurnText(Watermark),
+    /// Order of the post-process passes, and which ones are skipped
+    SetPassOrder(Vec<PassSlot>),
+    /// Convert the framebuffer to straight or premultiplied alpha
+    SetAlphaMode(AlphaMode),
+    /// Change how the framebuffer is cleared before rendering and re-render
+    SetClear { enabled: bool, color: [f32; 4] },
+    /// Enter paint mode with the given brush, or leave it with `None`
+    SetBrush(Option<Brush>),
+    /// Restore the framebuffer from before the last paint stroke
+    UndoStroke,
+    /// Forget the clone stamp source, the next click on the image picks a new one
+    ResetCloneSource,
+    /// Read the color of a framebuffer pixel for the color dropper
+    SampleColor {
+        pixel: [u32; 2],
+        target: DropperTarget,
+    },
+    /// Write the current render settings to a JSON file
+    SaveScene(PathBuf),
+    /// Restore the render settings read from a JSON file
+    ApplyScene(Box<(SceneDescription, ProceduralParams)>),
+    /// Change the effects applied before display
+    SetPostProcess(PostProcessSettings),
+    /// Fit the input black and white of the levels to the rendered image, and enable them
+    AutoLevels,
+    /// Replace the framebuffer with an autosaved EXR
+    RestoreFramebuffer(PathBuf),
+    /// Replace the framebuffer with the signed distance field of its bright pixels.
+    /// Distances are in pixels times `scale`, and `range` is mapped to black and white.
+    GenerateSdf { scale: f32, range: [f32; 2] },
+    /// Depth of each pixel of the framebuffer, used by the depth of field,
+    /// loaded from `path`
+    SetDepthBuffer { depth: Vec<f32>, path: PathBuf },
+    /// RGBA displacement of each pixel of the framebuffer, used by the grid warp.
+    /// `path` is the file it was loaded from, `None` for the generated test warp.
+    SetWarpMap {
+        displacement: Vec<f32>,
+        path: Option<PathBuf>,
+    },
+    /// Limit the next renders to `[x0, y0, x1, y1]` of the framebuffer, or render all of it
+    SetRenderRegion(Option<[usize; 4]>),
+    /// Change the samples per pixel, per axis, of the procedural renderers and re-render
+    SetSsaaFactor(u32),
+}
 
 /// Representation of the application state
 struct ApplicationState {
     // RGB 32 bit
-    framebuffer: [f32; RENDER_BUFFER_SIZE],
+    framebuffer: Framebuffer,
+    // Exposure offset in stops, applied before tonemapping
+    exposure_ev: f32,
+    render_mode: RenderMode,
+    mandelbrot_view: MandelbrotView,
+    animator: MandelbrotAnimator,
+    // Whether the animation is currently being previewed
+    animation_playing: bool,
+    denoise_enabled: bool,
+    denoiser: TemporalDenoiser,
+    // Last displayed night vision frame, faded into the next ones
+    phosphor_afterglow: Vec<f32>,
+    // Degrees added to the hue rotation by the color cycling so far
+    hue_cycle_phase: f32,
+    // The display buffer changed since the last redraw
+    dirty: bool,
+    // Redraw on every iteration of the event loop, even when nothing changed
+    continuous_render: bool,
+    // Why the last image couldn't be saved, until the GUI shows it
+    image_error: Option<ImageError>,
+    // Pre-rendered EXR sequence, played back into the framebuffer
+    sequence_player: Option<ExrSequencePlayer>,
+    playback: PlaybackSettings,
+    // Encoding of the values stored in the framebuffer
+    storage_encoding: StorageEncoding,
+    // Format of the `Pixels` texture we draw into, fixed at startup
+    surface_format: SurfaceFormat,
+    // Sequence used to jitter samples in the procedural renderers
+    sampler: SamplerKind,
+    // Gamut of the display being simulated, if any
+    soft_proof: Option<SoftProofProfile>,
+    display_mode: DisplayMode,
+    // Press simulated by the CMYK display mode
+    print: PrintSettings,
+    // Look applied to the tonemapped colors, from the CLUT editor
+    lut: Option<Lut3d>,
+    // Drawn over the EXR and JPEG exports, not the framebuffer
+    watermark: Option<Watermark>,
+    // Whether the framebuffer colors are multiplied by alpha
+    alpha_mode: AlphaMode,
+    // Flag out of gamut pixels while soft proofing
+    gamut_warning: bool,
+    // Encoding of the 8 bit frame
+    output_color_space: OutputColorSpace,
+    dither_mode: DitherMode,
+    // Code values, from 0 to 2
+    dither_scale: f32,
+    // sRGB RGBA8 reference image to diff the display against
+    reference_difference: Option<Vec<u8>>,
+    // Environment lighting of the SH sphere
+    sh_irradiance: SphericalHarmonics9,
+    gradient_anchors: Vec<GradientAnchor>,
+    cubemap: Option<Box<CubemapFaces>>,
+    lissajous: LissajousSettings,
+    starfield: StarfieldSettings,
+    // Wavelengths in nm at the left and right edges of the spectral gradient
+    spectral_range: [f32; 2],
+    fractal_flame: FractalFlameSettings,
+    value_noise: ValueNoiseSettings,
+    sky: SkySettings,
+    // ACEScg, picked in the GUI as "First Color" and "Second Color"
+    scene_colors: [[f32; 3]; 2],
+    turbulence: TurbulenceSettings,
+    bricks: BrickSettings,
+    color_wheel_lightness: f32,
+    color_wheel_mode: ColorWheelMode,
+    wood_grain: WoodGrainSettings,
+    poisson_disk: PoissonDiskSettings,
+    oscilloscope: OscilloscopeSettings,
+    // Waveform drawn by the next oscilloscope render
+    oscilloscope_samples: Vec<f32>,
+    // Seconds, drives the test signal
+    oscilloscope_time: f32,
+    #[cfg(feature = "cpal")]
+    audio: Option<audio::AudioCapture>,
+    camera: CameraSettings,
+    worley: WorleySettings,
+    pcb: PcbSettings,
+    texture_quilting: TextureQuiltingSettings,
+    // Image the texture synthesis copies patches from, once loaded
+    exemplar: Option<Box<Exemplar>>,
+    // Files the cubemap, exemplar, depth buffer and warp map were loaded from
+    scene_files: SceneFiles,
+    node_graph: NodeGraph,
+    // GPU pipelines of the WGSL nodes of the graph
+    shaders: WgslRunner,
+    terrain: TerrainParams,
+    raymarcher: RaymarcherSettings,
+    brdf_spheres: BrdfGridSettings,
+    truchet: TruchetSettings,
+    grunge: GrungeParams,
+    spiral: SpiralSettings,
+    // Last frame captured, nothing is drawn before the first one
+    camera_frame: Option<CameraFrame>,
+    #[cfg(feature = "camera-input")]
+    camera_capture: Option<camera::CameraCapture>,
+    // Linear copy of the image the value noise is layered on,
+    // taken when switching to the value noise mode
+    noise_base: Framebuffer,
+    // Fill the framebuffer with `clear_color` before each render.
+    // Turned off, sparse renderers leave trails of the previous frames.
+    clear_before_render: bool,
+    // ACEScg RGBA
+    clear_color: [f32; 4],
+    // Set while in paint mode
+    brush: Option<Brush>,
+    // Whether the mouse button went down on the framebuffer and is still held
+    stroke_active: bool,
+    // Pixel position of the last dab of the current stroke
+    last_dab: Option<[f32; 2]>,
+    // Where the clone stamp copies from, picked by the first click
+    clone_source: Option<[f32; 2]>,
+    // From the stroke to the pixels it copies, locked when the stroke begins
+    clone_offset: [f32; 2],
+    // Linear ACEScg RGBA read for the color dropper, until the GUI takes it
+    sampled_color: Option<(DropperTarget, [f32; 4])>,
+    // Levels picked by "Auto Levels", until the GUI takes them
+    auto_levels: Option<LevelsParams>,
+    // Render mode switched to without the GUI asking for it, until the GUI shows it
+    forced_render_mode: Option<RenderMode>,
+    // For the GUI, set when the Mandelbrot view changed without it asking
+    changed_mandelbrot_view: Option<MandelbrotView>,
+    // Of the display buffer before the post-process, shown by the levels controls
+    levels_histogram: LevelsHistogram,
+    // Framebuffer before each paint stroke, most recent last
+    undo_stack: Vec<Framebuffer>,
+    post_process: PostProcessSettings,
+    // Sequence the post-process effects run in, set from the GUI
+    pass_order: Vec<PassSlot>,
+    // Also holds the depth buffer of the bokeh and the warp map of the grid warp
+    render_passes: RenderPassStack,
+    // Part of the framebuffer re-rendered, `[x0, y0, x1, y1]` with the end exclusive.
+    // The per pixel renderers skip the pixels outside of it, the ones scattering shapes
+    // over the whole image draw everything and those pixels are put back afterwards.
+    render_region: Option<[usize; 4]>,
+    // Supersampling of the procedural renderers, `ssaa_factor` x `ssaa_factor` samples per pixel
+    ssaa_factor: u32,
+    // Linear copy of the framebuffer with the post-process effects applied, what gets drawn
+    display_buffer: Framebuffer,
+    // Downsampled copies of the display buffer
+    display_mips: MipPyramid,
+    // Incremented every time the display buffer changes
+    display_generation: u64,
+    // Renders the Mandelbrot set in the background
+    tile_renderer: TileRenderer,
+    // Draws the render modes too slow for the event loop
+    background_renderer: BackgroundRenderer,
+    // Framebuffer to restore outside of the render region once the background frame is done
+    background_outside_region: Option<([usize; 4], Framebuffer)>,
+    // Written in the header of every saved image
+    exr_metadata: MetadataMap,
+    // Shape of the framebuffer pixels, for anamorphic formats
+    pixel_aspect: PixelAspectRatio,
 }
 
 fn main() -> Result<(), Error> {
-    env_logger::init();
-    let event_loop = EventLoop::new();
+    // Saves and other results of this crate are reported at the info level,
+    // the dependencies stay quiet unless RUST_LOG asks for more
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("pixels_egui_framebuffer=info"),
+    )
+    .init();
+    let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build();
     let mut input = WinitInputHelper::new();
     let window = {
         let size = LogicalSize::new(WINDOW_WIDTH as f64, WINDOW_HEIGHT as f64);
@@ -42,24 +596,46 @@ fn main() -> Result<(), Error> {
             .unwrap()
     };
 
-    let mut app = ApplicationState::new();
-
-    let render_buffer_pointer = Box::new(app.framebuffer);
-    println!(
-        "Render buffer occupies {} bytes on the stack",
-        std::mem::size_of_val(&render_buffer_pointer)
-    );
+    let mut config = Config::load_or_default(CONFIG_PATH);
+    let mut autosave = Autosave::new(&config.autosave);
 
-    let (mut pixels, mut framework) = {
+    let (mut pixels, mut framework, surface_format) = {
         let window_size = window.inner_size();
         let scale_factor = window.scale_factor() as f32;
 
-        let surface_texture =
-            SurfaceTexture::new(RENDER_BUFFER_WIDTH, RENDER_BUFFER_HEIGHT, &window);
+        let build_pixels = |surface_format: SurfaceFormat| {
+            let surface_texture =
+                SurfaceTexture::new(RENDER_BUFFER_WIDTH, RENDER_BUFFER_HEIGHT, &window);
+            PixelsBuilder::new(RENDER_BUFFER_WIDTH, RENDER_BUFFER_WIDTH, surface_texture)
+                .texture_format(surface_format.to_wgpu())
+                .build()
+        };
+        // `Pixels` fails to build with a format its adapter can't use, so it starts with the
+        // default one, and is only built again with the configured one once the adapter has it
+        let mut surface_format = SurfaceFormat::default();
+        let mut pixels = build_pixels(surface_format)?;
+        if config.surface_format != surface_format {
+            if config.surface_format.is_supported(pixels.adapter()) {
+                surface_format = config.surface_format;
+                // The window can only have one surface at a time
+                drop(pixels);
+                pixels = build_pixels(surface_format)?;
+            } else {
+                warn!(
+                    "{:?} is not supported by this adapter, using the default format",
+                    config.surface_format
+                );
+            }
+        }
 
-        let pixels = PixelsBuilder::new(RENDER_BUFFER_WIDTH, RENDER_BUFFER_WIDTH, surface_texture)
-            .texture_format(wgpu::TextureFormat::Rgba8UnormSrgb)
-            .build()?;
+        let mut msaa = config.msaa;
+        if !msaa.is_supported(pixels.adapter(), pixels.render_texture_format()) {
+            warn!(
+                "{} MSAA samples are not supported by this adapter, turning MSAA off",
+                msaa.sample_count
+            );
+            msaa = MsaaConfig::default();
+        }
 
         let framework = Framework::new(
             &event_loop,
@@ -67,11 +643,34 @@ fn main() -> Result<(), Error> {
             window_size.height,
             scale_factor,
             &pixels,
-            render_buffer_pointer,
+            event_loop.create_proxy(),
+            &config,
+            surface_format,
+            msaa,
         );
 
-        (pixels, framework)
+        (pixels, framework, surface_format)
     };
+    let mut aspect_renderer =
+        PixelAspectRenderer::new(pixels.device(), pixels.render_texture_format());
+
+    if let Some(image_path) = autosave.begin_session() {
+        framework.offer_session_recovery(image_path);
+    }
+
+    let mut app = ApplicationState::new(
+        surface_format,
+        config.clear_color,
+        event_loop.create_proxy(),
+    );
+
+    #[cfg(feature = "ipc")]
+    if let Err(e) = ipc::spawn(event_loop.create_proxy()) {
+        error!("Failed to start the IPC listener: {e:?}");
+    }
+
+    // When the last redraw started, to cap the frame rate
+    let mut last_frame_start = Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
         // Handle input events
@@ -97,20 +696,121 @@ fn main() -> Result<(), Error> {
                 framework.resize(size.width, size.height);
             }
 
-            // Update internal state and request a redraw
+            // Paint strokes, unless the pointer is over an egui window
+            if app.brush.is_some() {
+                let mouse_pixel = input
+                    .mouse()
+                    .and_then(|position| {
+                        if app.pixel_aspect.is_square() {
+                            let (x, y) = pixels.window_pos_to_pixel(position).ok()?;
+                            Some([x as u32, y as u32])
+                        } else {
+                            let size = window.inner_size();
+                            aspect::window_pos_to_pixel(
+                                [size.width as f32, size.height as f32],
+                                position,
+                                app.pixel_aspect.ratio(),
+                            )
+                        }
+                    })
+                    .map(|[x, y]| [x as f32 + 0.5, y as f32 + 0.5]);
+                if let Some(position) = mouse_pixel {
+                    if input.mouse_pressed(0) && !framework.wants_pointer_input() {
+                        app.begin_stroke(position);
+                    }
+                    if input.mouse_held(0) {
+                        app.paint_to(position);
+                    }
+                }
+            }
+            if input.mouse_released(0) {
+                app.end_stroke();
+            }
+            if input.held_control()
+                && input.key_pressed(VirtualKeyCode::Z)
+                && !framework.wants_keyboard_input()
+            {
+                app.undo_stroke();
+            }
+
+            if autosave.is_due() {
+                let scene = app
+                    .scene_description()
+                    .map_err(|e| warn!("Autosaving the image without the scene: {e:#}"))
+                    .ok();
+                autosave.save(
+                    app.framebuffer.clone(),
+                    app.storage_encoding,
+                    app.alpha_mode,
+                    app.pixel_aspect.ratio(),
+                    scene,
+                );
+            }
+
+            // Update internal state, and redraw only when something changed
             app.update();
-            window.request_redraw();
+            // Animations move the Mandelbrot view
+            if let Some(view) = app.changed_mandelbrot_view.take() {
+                framework.set_mandelbrot_view(view);
+            }
+            let repaint_deadline = framework.repaint_deadline();
+            let gui_needs_repaint =
+                repaint_deadline.is_some_and(|deadline| deadline <= Instant::now());
+            let target_fps = framework.target_fps();
+            // Animations wait for their next frame, anything else is drawn right away
+            let frame_due = target_fps
+                .frame_interval()
+                .is_none_or(|interval| last_frame_start.elapsed() >= interval);
+            let throttled = app.is_animating() && !frame_due;
+            if ((app.dirty || app.continuous_render) && !throttled) || gui_needs_repaint {
+                window.request_redraw();
+            }
+            *control_flow = next_control_flow(&app, target_fps, last_frame_start, repaint_deadline);
         }
 
         match event {
             Event::WindowEvent { event, .. } => {
-                // Update egui inputs
-                framework.handle_event(&event);
+                // Update egui inputs, the image is redrawn on its own when it changes
+                if framework.handle_event(&event) {
+                    window.request_redraw();
+                }
+            }
+            // Events coming from the GUI or the IPC thread
+            Event::UserEvent(event) => {
+                app.handle_user_event(event);
+                if let Some(error) = app.image_error.take() {
+                    framework.show_image_error(&error);
+                }
+                if let Some((target, rgba)) = app.sampled_color.take() {
+                    framework.set_sampled_color(target, rgba);
+                }
+                if let Some(levels) = app.auto_levels.take() {
+                    framework.set_levels(levels);
+                }
+                if let Some(render_mode) = app.forced_render_mode.take() {
+                    framework.set_render_mode(render_mode);
+                }
+                if let Some(view) = app.changed_mandelbrot_view.take() {
+                    framework.set_mandelbrot_view(view);
+                }
+                window.request_redraw();
             }
             // Draw the current frame
             Event::RedrawRequested(_) => {
+                let frame_start = Instant::now();
+
                 // Draw the world
-                app.draw(pixels.get_frame_mut());
+                let frame = pixels.get_frame_mut();
+                app.draw(frame);
+                framework.update_bit_depth(frame);
+                framework.update_gamut_diagram(app.display_generation, &app.display_mips);
+                framework.set_render_status(app.tile_renderer.status());
+                framework.set_levels_histogram(&app.levels_histogram);
+                framework.set_playback_status(
+                    app.sequence_player.as_ref().map(|player| player.status()),
+                );
+                framework.set_clone_source(app.clone_source);
+                framework.set_shader_errors(app.shaders.errors());
 
                 // Prepare egui
                 framework.prepare(&window);
@@ -118,9 +818,20 @@ fn main() -> Result<(), Error> {
                 // Render everything together
                 // TODO: I really don't want the texture to alway scale
                 // up to the whole window, how can I achieve that?
+                let window_size = window.inner_size();
                 let render_result = pixels.render_with(|encoder, render_target, context| {
-                    // Render the world texture
-                    context.scaling_renderer.render(encoder, render_target);
+                    // Render the world texture, stretched when its pixels aren't square
+                    if app.pixel_aspect.is_square() {
+                        context.scaling_renderer.render(encoder, render_target);
+                    } else {
+                        aspect_renderer.render(
+                            encoder,
+                            render_target,
+                            context,
+                            [window_size.width, window_size.height],
+                            app.pixel_aspect.ratio(),
+                        );
+                    }
 
                     // Render egui
                     framework.render(encoder, render_target, context);
@@ -133,41 +844,1776 @@ fn main() -> Result<(), Error> {
                     error!("pixels.render() failed: {err}");
                     *control_flow = ControlFlow::Exit;
                 }
+
+                framework.record_frame_time(frame_start.elapsed());
+                app.dirty = false;
+                last_frame_start = frame_start;
+                *control_flow = next_control_flow(
+                    &app,
+                    framework.target_fps(),
+                    last_frame_start,
+                    framework.repaint_deadline(),
+                );
+            }
+            // Persist the session before quitting
+            Event::LoopDestroyed => {
+                config.window_positions = framework.window_positions();
+                config.msaa = framework.msaa_config();
+                config.window_dpi = framework.window_dpi();
+                config.target_fps = framework.target_fps();
+                if let Err(e) = config.save(CONFIG_PATH) {
+                    error!("Failed to save {CONFIG_PATH}: {e:?}");
+                }
+                // Nothing to recover on the next launch
+                autosave.end_session();
             }
             _ => (),
         }
     });
 }
 
+/// How long the event loop sleeps: until the next frame while animating, capped to the
+/// target frame rate, and until the next event otherwise
+fn next_control_flow(
+    app: &ApplicationState,
+    target_fps: TargetFps,
+    last_frame_start: Instant,
+    repaint_deadline: Option<Instant>,
+) -> ControlFlow {
+    // The render threads wake the loop up when their tiles are done
+    let rendering = matches!(app.tile_renderer.status(), RenderStatus::Rendering { .. });
+    // Nothing wakes the loop up when the background frame is done, it checks on it instead
+    if app.background_renderer.is_busy() {
+        let next_check = Instant::now() + Duration::from_millis(BACKGROUND_POLL_MS);
+        return ControlFlow::WaitUntil(
+            repaint_deadline.map_or(next_check, |deadline| deadline.min(next_check)),
+        );
+    }
+    if !app.is_animating() || rendering {
+        return repaint_deadline.map_or(ControlFlow::Wait, ControlFlow::WaitUntil);
+    }
+    match target_fps.frame_interval() {
+        None => ControlFlow::Poll,
+        Some(interval) => {
+            let next_frame = last_frame_start + interval;
+            ControlFlow::WaitUntil(
+                repaint_deadline.map_or(next_frame, |deadline| deadline.min(next_frame)),
+            )
+        }
+    }
+}
+
+/// Fill the framebuffer with `clear_color`, or leave the previous frame in place for
+/// sparse renderers to draw over when `clear_before_render` is off
+fn clear_framebuffer(framebuffer: &mut [f32], clear_before_render: bool, clear_color: [f32; 4]) {
+    if !clear_before_render {
+        return;
+    }
+    for pixel in framebuffer.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&clear_color);
+    }
+}
+
 impl ApplicationState {
     /// Create a new `ApplicationState` instance that can draw a moving box.
-    fn new() -> Self {
+    fn new(
+        surface_format: SurfaceFormat,
+        clear_color: [f32; 4],
+        event_proxy: EventLoopProxy<UserEvent>,
+    ) -> Self {
         // Start from black
         let black: f32 = 0.0;
-        let mut render_buffer: [f32; RENDER_BUFFER_SIZE] = [black; RENDER_BUFFER_SIZE];
-        eprintln!("Size of render buffer: {}", render_buffer.len());
-        render_bg_image(&mut render_buffer);
+        let mut render_buffer: Framebuffer = Box::new([black; RENDER_BUFFER_SIZE]);
+        debug!("Size of render buffer: {}", render_buffer.len());
+        render_bg_image(
+            &mut render_buffer,
+            SamplerKind::Uniform,
+            RENDER_BUFFER_REGION,
+        );
+        let display_buffer = render_buffer.clone();
+        let display_mips = MipPyramid::build_from_buffer(
+            &display_buffer[..],
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+        );
 
         Self {
             framebuffer: render_buffer,
+            exposure_ev: 0.0,
+            render_mode: RenderMode::Gradient,
+            mandelbrot_view: MandelbrotView::default(),
+            animator: MandelbrotAnimator::default(),
+            animation_playing: false,
+            denoise_enabled: false,
+            denoiser: TemporalDenoiser::default(),
+            phosphor_afterglow: Vec::new(),
+            hue_cycle_phase: 0.0,
+            dirty: true,
+            continuous_render: false,
+            image_error: None,
+            sequence_player: None,
+            playback: PlaybackSettings::default(),
+            storage_encoding: StorageEncoding::Linear,
+            surface_format,
+            sampler: SamplerKind::Uniform,
+            soft_proof: None,
+            display_mode: DisplayMode::Normal,
+            print: PrintSettings::default(),
+            lut: None,
+            watermark: None,
+            alpha_mode: AlphaMode::Straight,
+            gamut_warning: false,
+            output_color_space: OutputColorSpace::Srgb,
+            dither_mode: DitherMode::None,
+            dither_scale: 1.0,
+            reference_difference: None,
+            sh_irradiance: SphericalHarmonics9::default(),
+            gradient_anchors: default_gradient_anchors(),
+            cubemap: None,
+            lissajous: LissajousSettings::default(),
+            starfield: StarfieldSettings::default(),
+            spectral_range: [380.0, 700.0],
+            fractal_flame: FractalFlameSettings::default(),
+            value_noise: ValueNoiseSettings::default(),
+            sky: SkySettings::default(),
+            scene_colors: [[0.0; 3], [1.0; 3]],
+            turbulence: TurbulenceSettings::default(),
+            bricks: BrickSettings::default(),
+            color_wheel_lightness: 0.5,
+            color_wheel_mode: ColorWheelMode::Hsl,
+            wood_grain: WoodGrainSettings::default(),
+            poisson_disk: PoissonDiskSettings::default(),
+            oscilloscope: OscilloscopeSettings::default(),
+            oscilloscope_samples: Vec::new(),
+            oscilloscope_time: 0.0,
+            #[cfg(feature = "cpal")]
+            audio: None,
+            camera: CameraSettings::default(),
+            worley: WorleySettings::default(),
+            pcb: PcbSettings::default(),
+            texture_quilting: TextureQuiltingSettings::default(),
+            exemplar: None,
+            scene_files: SceneFiles::default(),
+            node_graph: NodeGraph::default(),
+            shaders: WgslRunner::default(),
+            terrain: TerrainParams::default(),
+            raymarcher: RaymarcherSettings::default(),
+            brdf_spheres: BrdfGridSettings::default(),
+            truchet: TruchetSettings::default(),
+            grunge: GrungeParams::default(),
+            spiral: SpiralSettings::default(),
+            camera_frame: None,
+            #[cfg(feature = "camera-input")]
+            camera_capture: None,
+            noise_base: Box::new([0.0; RENDER_BUFFER_SIZE]),
+            clear_before_render: true,
+            clear_color,
+            brush: None,
+            stroke_active: false,
+            last_dab: None,
+            clone_source: None,
+            sampled_color: None,
+            auto_levels: None,
+            forced_render_mode: None,
+            changed_mandelbrot_view: None,
+            levels_histogram: LevelsHistogram::default(),
+            clone_offset: [0.0, 0.0],
+            undo_stack: Vec::new(),
+            post_process: PostProcessSettings::default(),
+            pass_order: default_pass_order(),
+            render_passes: RenderPassStack::default(),
+            render_region: None,
+            ssaa_factor: 1,
+            display_buffer,
+            display_mips,
+            display_generation: 0,
+            tile_renderer: TileRenderer::new(event_proxy),
+            background_renderer: BackgroundRenderer::new(RENDER_BUFFER_SIZE),
+            background_outside_region: None,
+            exr_metadata: MetadataMap::default(),
+            pixel_aspect: PixelAspectRatio::default(),
         }
     }
 
     /// Update the Application internal state
     fn update(&mut self) {
-        // TODO: here goes any update logic
+        if let Some(frame) = self.background_renderer.receive() {
+            self.finish_background_frame(&frame);
+            self.update_display_buffer();
+        }
+        // The next frame starts once the tiles of the previous one are all in
+        if self.animation_playing
+            && !matches!(self.tile_renderer.status(), RenderStatus::Rendering { .. })
+        {
+            self.animation_playing = self.step_animation();
+        }
+        if let Some(frame) = self
+            .sequence_player
+            .as_mut()
+            .and_then(|player| player.tick())
+        {
+            self.framebuffer.copy_from_slice(frame);
+            self.encode_framebuffer();
+            self.advance_display_buffer();
+        }
+        if self.render_mode == RenderMode::Lissajous && self.lissajous.animate {
+            self.lissajous.phase =
+                (self.lissajous.phase + LISSAJOUS_PHASE_STEP) % std::f32::consts::TAU;
+            self.render();
+            self.advance_display_buffer();
+        }
+        if self.render_mode == RenderMode::Spiral && self.spiral.animate {
+            self.spiral.phase = (self.spiral.phase + SPIRAL_PHASE_STEP) % std::f32::consts::TAU;
+            self.render();
+            self.advance_display_buffer();
+        }
+        if self.render_mode == RenderMode::Oscilloscope {
+            self.oscilloscope_samples = self.next_waveform();
+            self.render();
+            self.advance_display_buffer();
+        }
+        if self.post_process.hue_cycle_enabled {
+            self.hue_cycle_phase =
+                (self.hue_cycle_phase + self.post_process.hue_cycle_step) % 360.0;
+            self.advance_display_buffer();
+        }
+        #[cfg(feature = "camera-input")]
+        if self.render_mode == RenderMode::Camera {
+            if let Some(frame) = self
+                .camera_capture
+                .as_ref()
+                .and_then(|capture| capture.latest_frame())
+            {
+                self.camera_frame = Some(frame);
+                self.render();
+                self.advance_display_buffer();
+            }
+        }
+    }
+
+    /// Start streaming the selected camera, leaving the mode blank if there is none
+    #[cfg(feature = "camera-input")]
+    fn open_camera(&mut self) {
+        self.camera_frame = None;
+        self.camera_capture = camera::CameraCapture::new(self.camera.device)
+            .map_err(|e| warn!("No camera feed: {e:?}"))
+            .ok();
+    }
+
+    /// Latest audio samples, or the next frame of the test signal without audio input
+    fn next_waveform(&mut self) -> Vec<f32> {
+        #[cfg(feature = "cpal")]
+        if let Some(audio) = &self.audio {
+            return audio.latest();
+        }
+        self.oscilloscope_time += OSCILLOSCOPE_TIME_STEP;
+        oscilloscope_test_signal(self.oscilloscope_time, OSCILLOSCOPE_SAMPLES)
+    }
+
+    /// Draw the current render mode into the framebuffer
+    fn render(&mut self) {
+        // Strokes painted over the previous image can't be undone on top of the new one
+        self.undo_stack.clear();
+        // Tiles of the previous render would land on top of this one
+        self.tile_renderer.reset();
+        self.background_renderer.reset();
+        // Put back once the render is done
+        let outside_region = self
+            .render_region
+            .map(|region| (region, self.framebuffer.clone()));
+        if self.render_mode == RenderMode::Oscilloscope {
+            // Fades out the previous frames itself, which must be linear too
+            convert_storage_encoding(
+                &mut self.framebuffer,
+                self.storage_encoding,
+                StorageEncoding::Linear,
+            );
+        } else {
+            clear_framebuffer(
+                &mut self.framebuffer[..],
+                self.clear_before_render,
+                self.clear_color,
+            );
+        }
+        // The input of the render mode pass
+        let cleared = self.framebuffer.to_vec();
+        let region = self.render_region.unwrap_or(RENDER_BUFFER_REGION);
+        let pass = match self.render_mode {
+            RenderMode::Gradient => RenderModePass(Box::new(|buffer, _, _| {
+                render_bg_image(
+                    buffer.try_into().expect("render buffer size"),
+                    self.sampler,
+                    region,
+                )
+            })),
+            RenderMode::Mandelbrot => {
+                let view = self.mandelbrot_view;
+                let ssaa_factor = self.ssaa_factor;
+                self.tile_renderer
+                    .start(region, move |x, y, width, height, cancel| {
+                        render_mandelbrot_tile(&view, x, y, width, height, ssaa_factor, cancel)
+                    });
+                // Tiles are filled in as they finish, those skipped by a cancel stay black
+                RenderModePass(Box::new(|buffer, _, _| {
+                    for pixel in buffer.chunks_exact_mut(4) {
+                        pixel.copy_from_slice(&[0.0, 0.0, 0.0, 1.0]);
+                    }
+                }))
+            }
+            RenderMode::ShSphere => RenderModePass(Box::new(|buffer, _, _| {
+                render_sh_sphere(
+                    buffer.try_into().expect("render buffer size"),
+                    &self.sh_irradiance,
+                    region,
+                )
+            })),
+            RenderMode::MultipointGradient => RenderModePass(Box::new(|buffer, width, height| {
+                render_ssaa(
+                    buffer,
+                    width,
+                    height,
+                    region,
+                    self.ssaa_factor,
+                    |buffer, width, height, region| {
+                        render_multipoint_gradient(
+                            buffer,
+                            width,
+                            height,
+                            region,
+                            &self.gradient_anchors,
+                        )
+                    },
+                )
+            })),
+            RenderMode::CubemapCross => {
+                RenderModePass(Box::new(|buffer, width, height| match &self.cubemap {
+                    Some(faces) => render_cubemap_crossview(buffer, width, height, faces),
+                    None => buffer.fill(0.0),
+                }))
+            }
+            RenderMode::Lissajous => RenderModePass(Box::new(|buffer, width, height| {
+                render_lissajous(
+                    buffer,
+                    width,
+                    height,
+                    self.lissajous.freq_x as f32,
+                    self.lissajous.freq_y as f32,
+                    self.lissajous.phase,
+                    self.lissajous.thickness,
+                    self.lissajous.color,
+                )
+            })),
+            RenderMode::Starfield => RenderModePass(Box::new(|buffer, width, height| {
+                render_starfield(
+                    buffer,
+                    width,
+                    height,
+                    self.starfield.num_stars,
+                    self.starfield.seed,
+                    self.starfield.magnitude_exponent,
+                    self.starfield.bloom_threshold,
+                )
+            })),
+            RenderMode::SpectralGradient => RenderModePass(Box::new(|buffer, width, height| {
+                render_ssaa(
+                    buffer,
+                    width,
+                    height,
+                    region,
+                    self.ssaa_factor,
+                    |buffer, width, _, region| {
+                        render_spectral_gradient(
+                            buffer,
+                            width,
+                            region,
+                            self.spectral_range[0],
+                            self.spectral_range[1],
+                        )
+                    },
+                )
+            })),
+            RenderMode::FractalFlame => RenderModePass(Box::new(|buffer, width, height| {
+                render_fractal_flame(
+                    buffer,
+                    width,
+                    height,
+                    &self.fractal_flame.system,
+                    self.fractal_flame.iterations,
+                    self.fractal_flame.supersample,
+                )
+            })),
+            RenderMode::ValueNoise => RenderModePass(Box::new(|buffer, width, _| {
+                // Start over from the base image, so tweaking the noise doesn't accumulate
+                buffer.copy_from_slice(&self.noise_base[..]);
+                render_value_noise(
+                    buffer,
+                    width,
+                    region,
+                    self.value_noise.frequency,
+                    self.value_noise.octaves,
+                    self.value_noise.seed,
+                    self.value_noise.blend_mode,
+                );
+            })),
+            RenderMode::Sky => RenderModePass(Box::new(|buffer, width, height| {
+                render_ssaa(
+                    buffer,
+                    width,
+                    height,
+                    region,
+                    self.ssaa_factor,
+                    |buffer, width, height, region| {
+                        render_sky(
+                            buffer,
+                            width,
+                            height,
+                            region,
+                            self.sky.sun_elevation,
+                            self.sky.sun_azimuth,
+                            self.sky.turbidity,
+                            self.sky.ground_albedo,
+                        )
+                    },
+                )
+            })),
+            RenderMode::Turbulence => RenderModePass(Box::new(|buffer, width, height| {
+                render_ssaa(
+                    buffer,
+                    width,
+                    height,
+                    region,
+                    self.ssaa_factor,
+                    |buffer, width, _, region| {
+                        render_turbulence(
+                            buffer,
+                            width,
+                            region,
+                            self.turbulence.frequency,
+                            self.turbulence.octaves,
+                            self.turbulence.seed,
+                            self.turbulence.turbulent,
+                            self.turbulence.pattern,
+                            self.turbulence.contrast,
+                            self.turbulence.basis,
+                            self.scene_colors,
+                        )
+                    },
+                )
+            })),
+            RenderMode::Bricks => RenderModePass(Box::new(|buffer, width, _| {
+                render_brick_pattern(
+                    buffer,
+                    width,
+                    region,
+                    self.bricks.brick_w,
+                    self.bricks.brick_h,
+                    self.bricks.mortar,
+                    self.bricks.brick_color,
+                    self.bricks.mortar_color,
+                    self.bricks.offset,
+                    self.bricks.variation,
+                )
+            })),
+            RenderMode::ColorWheel => RenderModePass(Box::new(|buffer, width, height| {
+                render_ssaa(
+                    buffer,
+                    width,
+                    height,
+                    region,
+                    self.ssaa_factor,
+                    |buffer, width, height, region| {
+                        render_color_wheel(
+                            buffer,
+                            width,
+                            height,
+                            region,
+                            self.color_wheel_lightness,
+                            self.color_wheel_mode,
+                        )
+                    },
+                )
+            })),
+            RenderMode::WoodGrain => RenderModePass(Box::new(|buffer, width, height| {
+                render_ssaa(
+                    buffer,
+                    width,
+                    height,
+                    region,
+                    self.ssaa_factor,
+                    |buffer, width, height, region| {
+                        render_wood_grain(
+                            buffer,
+                            width,
+                            height,
+                            region,
+                            self.wood_grain.ring_frequency,
+                            self.wood_grain.turbulence_scale,
+                            self.wood_grain.grain_angle,
+                            self.wood_grain.grain_color_a,
+                            self.wood_grain.grain_color_b,
+                            self.wood_grain.seed,
+                        )
+                    },
+                )
+            })),
+            RenderMode::PoissonDisk => RenderModePass(Box::new(|buffer, width, height| {
+                render_poisson_disk(
+                    buffer,
+                    width,
+                    height,
+                    self.poisson_disk.min_distance,
+                    self.poisson_disk.seed,
+                )
+            })),
+            RenderMode::Oscilloscope => RenderModePass(Box::new(|buffer, width, height| {
+                render_oscilloscope(
+                    buffer,
+                    width,
+                    height,
+                    &self.oscilloscope_samples,
+                    self.oscilloscope.color,
+                    self.oscilloscope.persistence,
+                )
+            })),
+            RenderMode::Camera => RenderModePass(Box::new(|buffer, width, height| {
+                if let Some(frame) = &self.camera_frame {
+                    render_camera_frame(buffer, width, height, frame, self.camera.flip_horizontal);
+                }
+            })),
+            RenderMode::Worley => RenderModePass(Box::new(|buffer, width, height| {
+                render_ssaa(
+                    buffer,
+                    width,
+                    height,
+                    region,
+                    self.ssaa_factor,
+                    |buffer, width, _, region| {
+                        render_worley_noise(
+                            buffer,
+                            width,
+                            region,
+                            self.worley.frequency,
+                            self.worley.metric,
+                            self.worley.output,
+                            self.worley.jitter,
+                            self.worley.seed,
+                            self.scene_colors,
+                        )
+                    },
+                )
+            })),
+            RenderMode::NodeGraph => {
+                // The shaders it compiles are kept, so it can't run from inside the pass
+                let image = self.node_graph.execute(
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                    &mut self.shaders,
+                );
+                RenderModePass(Box::new(move |buffer, _, _| buffer.copy_from_slice(&image)))
+            }
+            RenderMode::Terrain => RenderModePass(Box::new(|buffer, width, height| {
+                render_terrain(buffer, width, height, region, &self.terrain)
+            })),
+            RenderMode::TextureSynthesis => {
+                RenderModePass(Box::new(|buffer, width, height| match &self.exemplar {
+                    Some(exemplar) => render_texture_quilting(
+                        buffer,
+                        width,
+                        height,
+                        &exemplar.pixels,
+                        exemplar.width,
+                        exemplar.height,
+                        self.texture_quilting.patch_size as usize,
+                        self.texture_quilting.overlap(),
+                        self.texture_quilting.seed,
+                    ),
+                    None => buffer.fill(0.0),
+                }))
+            }
+            RenderMode::Raymarcher => {
+                // Too slow for the event loop, a low resolution preview stays up until it's done
+                let raymarcher = self.raymarcher.clone();
+                let ssaa_factor = self.ssaa_factor;
+                let cleared = cleared.clone();
+                self.background_outside_region = outside_region.clone();
+                self.background_renderer.start(Box::new(move |buffer| {
+                    buffer.copy_from_slice(&cleared);
+                    render_ssaa(
+                        buffer,
+                        RENDER_BUFFER_WIDTH as usize,
+                        RENDER_BUFFER_HEIGHT as usize,
+                        region,
+                        ssaa_factor,
+                        |buffer, width, height, region| {
+                            render_raymarcher(
+                                buffer,
+                                width,
+                                height,
+                                region,
+                                &raymarcher.scene,
+                                &raymarcher.camera,
+                            )
+                        },
+                    )
+                }));
+                RenderModePass(Box::new(|buffer, width, height| {
+                    let preview_width = (width / RAYMARCHER_PREVIEW_SCALE).max(1);
+                    let preview_height = (height / RAYMARCHER_PREVIEW_SCALE).max(1);
+                    let mut preview = vec![0.0; preview_width * preview_height * 4];
+                    let [x0, y0, x1, y1] = region;
+                    let preview_region = [
+                        x0 * preview_width / width,
+                        y0 * preview_height / height,
+                        (x1 * preview_width).div_ceil(width),
+                        (y1 * preview_height).div_ceil(height),
+                    ];
+                    render_raymarcher(
+                        &mut preview,
+                        preview_width,
+                        preview_height,
+                        preview_region,
+                        &self.raymarcher.scene,
+                        &self.raymarcher.camera,
+                    );
+                    for (x, y, pixel) in region_pixels_mut(buffer, width, region) {
+                        let x = x * preview_width / width;
+                        let y = y * preview_height / height;
+                        let source = (y * preview_width + x) * 4;
+                        pixel.copy_from_slice(&preview[source..source + 4]);
+                    }
+                }))
+            }
+            RenderMode::BrdfSpheres => {
+                let materials = self.brdf_spheres.materials();
+                let brdf_spheres = &self.brdf_spheres;
+                let ssaa_factor = self.ssaa_factor;
+                RenderModePass(Box::new(move |buffer, width, height| {
+                    render_ssaa(
+                        buffer,
+                        width,
+                        height,
+                        region,
+                        ssaa_factor,
+                        |buffer, width, height, region| {
+                            render_brdf_sphere_grid(
+                                buffer,
+                                width,
+                                height,
+                                region,
+                                &materials,
+                                brdf_spheres.metallic_steps as usize,
+                                brdf_spheres.light_dir,
+                                brdf_spheres.light_intensity,
+                            )
+                        },
+                    )
+                }))
+            }
+            RenderMode::Truchet => RenderModePass(Box::new(|buffer, width, height| {
+                render_truchet(
+                    buffer,
+                    width,
+                    height,
+                    region,
+                    self.truchet.tile_size as usize,
+                    self.truchet.seed,
+                    self.truchet.color_a,
+                    self.truchet.color_b,
+                    self.truchet.tile_set,
+                )
+            })),
+            RenderMode::GrungeMap => RenderModePass(Box::new(|buffer, width, height| {
+                render_grunge_map(buffer, width, height, &self.grunge)
+            })),
+            RenderMode::Spiral => RenderModePass(Box::new(|buffer, width, height| {
+                render_logarithmic_spiral(
+                    buffer,
+                    width,
+                    height,
+                    self.spiral.a,
+                    self.spiral.b,
+                    self.spiral.num_turns,
+                    self.spiral.thickness,
+                    self.spiral.color_a,
+                    self.spiral.color_b,
+                    self.spiral.phase,
+                )
+            })),
+            RenderMode::Pcb => RenderModePass(Box::new(|buffer, width, height| {
+                render_pcb_pattern(
+                    buffer,
+                    width,
+                    height,
+                    self.pcb.seed,
+                    self.pcb.trace_density,
+                    self.pcb.cell_size,
+                    self.pcb.trace_color,
+                    self.pcb.bg_color,
+                    self.pcb.glow_strength,
+                )
+            })),
+        };
+        self.render_passes.render(
+            pass,
+            &cleared,
+            &mut self.framebuffer[..],
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+        );
+        self.finish_render(outside_region);
+    }
+
+    /// Encode the linear image the render mode drew, and put back the framebuffer
+    /// outside of the render region
+    fn finish_render(&mut self, outside_region: Option<([usize; 4], Framebuffer)>) {
+        // The renderers write straight alpha
+        if self.alpha_mode == AlphaMode::Premultiplied {
+            premultiply_alpha(&mut self.framebuffer[..]);
+        }
+        self.encode_framebuffer();
+        if let Some((region, previous)) = outside_region {
+            copy_outside_region(
+                &mut self.framebuffer[..],
+                &previous[..],
+                RENDER_BUFFER_WIDTH as usize,
+                region,
+            );
+        }
+    }
+
+    /// Show the frame the background renderer finished
+    fn finish_background_frame(&mut self, frame: &[f32]) {
+        self.framebuffer.copy_from_slice(frame);
+        let outside_region = self.background_outside_region.take();
+        self.finish_render(outside_region);
+    }
+
+    /// Block until the tiled and background renders are done, before saving the framebuffer
+    fn wait_for_renders(&mut self) {
+        let tiles = self.tile_renderer.wait();
+        self.write_tiles(tiles);
+        if let Some(frame) = self.background_renderer.wait() {
+            self.finish_background_frame(&frame);
+        }
+    }
+
+    /// Remember the framebuffer so the stroke about to be painted at `position` can be undone.
+    /// With the clone stamp, the first click only picks the source.
+    fn begin_stroke(&mut self, position: [f32; 2]) {
+        if self
+            .brush
+            .is_some_and(|brush| brush.tool == BrushTool::Clone)
+        {
+            let Some(source) = self.clone_source else {
+                self.clone_source = Some(position);
+                self.dirty = true;
+                return;
+            };
+            self.clone_offset = [source[0] - position[0], source[1] - position[1]];
+        }
+        if self.undo_stack.len() == MAX_UNDO_STROKES {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(self.framebuffer.clone());
+        self.stroke_active = true;
+        self.last_dab = None;
+    }
+
+    /// Continue the current stroke up to `position`, in framebuffer pixels
+    fn paint_to(&mut self, position: [f32; 2]) {
+        let Some(brush) = self.brush else {
+            return;
+        };
+        if !self.stroke_active {
+            return;
+        }
+
+        let (width, height) = (RENDER_BUFFER_WIDTH as usize, RENDER_BUFFER_HEIGHT as usize);
+        // The pixels painted, and those the clone stamp copies
+        let from = self.last_dab.unwrap_or(position);
+        let mut region = brush.segment_bounds(from, position, [0.0, 0.0], width, height);
+        if brush.tool == BrushTool::Clone {
+            let source = brush.segment_bounds(from, position, self.clone_offset, width, height);
+            region = [
+                region[0].min(source[0]),
+                region[1].min(source[1]),
+                region[2].max(source[2]),
+                region[3].max(source[3]),
+            ];
+        }
+        // The brush composites in linear, only the pixels it can reach are decoded
+        let stored = decode_region(
+            &mut self.framebuffer[..],
+            width,
+            region,
+            self.storage_encoding,
+        );
+        match (brush.tool, self.last_dab) {
+            (BrushTool::Paint, Some(from)) => {
+                brush.paint_segment(&mut self.framebuffer[..], width, height, from, position)
+            }
+            (BrushTool::Paint, None) => brush.paint_at(
+                &mut self.framebuffer[..],
+                width,
+                height,
+                position[0],
+                position[1],
+            ),
+            // A first segment of zero length stamps a single dab
+            (BrushTool::Clone, from) => brush.clone_segment(
+                &mut self.framebuffer[..],
+                width,
+                height,
+                from.unwrap_or(position),
+                position,
+                self.clone_offset,
+            ),
+        }
+        encode_changed_region(
+            &mut self.framebuffer[..],
+            width,
+            region,
+            self.storage_encoding,
+            &stored,
+        );
+
+        self.last_dab = Some(position);
+        self.update_display_buffer();
+    }
+
+    fn end_stroke(&mut self) {
+        self.stroke_active = false;
+        self.last_dab = None;
+    }
+
+    /// Restore the framebuffer from before the last stroke
+    fn undo_stroke(&mut self) {
+        if let Some(previous) = self.undo_stack.pop() {
+            self.framebuffer = previous;
+            self.end_stroke();
+            self.update_display_buffer();
+        }
+    }
+
+    /// Renderers always write linear ACEScg, convert it to the storage encoding
+    fn encode_framebuffer(&mut self) {
+        convert_storage_encoding(
+            &mut self.framebuffer,
+            StorageEncoding::Linear,
+            self.storage_encoding,
+        );
+    }
+
+    /// Copy finished tiles of the tile renderer into the framebuffer
+    fn write_tiles(&mut self, tiles: Vec<Tile>) {
+        if tiles.is_empty() {
+            return;
+        }
+        // Tiles are rendered in linear
+        convert_storage_encoding(
+            &mut self.framebuffer,
+            self.storage_encoding,
+            StorageEncoding::Linear,
+        );
+        for tile in &tiles {
+            tile.write_to(&mut self.framebuffer[..], RENDER_BUFFER_WIDTH as usize);
+        }
+        self.encode_framebuffer();
+    }
+
+    /// Refresh the display buffer with an image replacing the previous one
+    fn update_display_buffer(&mut self) {
+        // The denoise history restarts from the new image, instead of fading into it
+        self.denoiser.reset();
+        self.phosphor_afterglow.clear();
+        self.advance_display_buffer();
+    }
+
+    /// Decode the framebuffer to linear and run the post-process effects on it.
+    /// Used for the frames of an animation, which are averaged with the previous ones
+    /// when the temporal denoise is on.
+    fn advance_display_buffer(&mut self) {
+        self.display_buffer.copy_from_slice(&self.framebuffer[..]);
+        convert_storage_encoding(
+            &mut self.display_buffer,
+            self.storage_encoding,
+            StorageEncoding::Linear,
+        );
+        if self.denoise_enabled {
+            let current = self.display_buffer.clone();
+            self.denoiser
+                .apply(&current[..], &mut self.display_buffer[..]);
+        }
+        self.levels_histogram = LevelsHistogram::from_buffer(
+            &self.display_buffer[..],
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+        );
+        {
+            let mut inputs = self.render_passes.inputs_mut();
+            inputs.settings = self.post_process;
+            // New grain on every frame
+            inputs.settings.night_vision.noise_seed = self.display_generation;
+            if inputs.settings.hue_cycle_enabled {
+                inputs.settings.hue_rotation += self.hue_cycle_phase;
+            }
+        }
+        self.render_passes.post_process(
+            &mut self.display_buffer[..],
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+        );
+        let night_vision = &self.post_process.night_vision;
+        if night_vision.enabled && night_vision.phosphor_persistence > 0.0 {
+            // The phosphor keeps glowing after the image moved on
+            if self.phosphor_afterglow.len() == self.display_buffer.len() {
+                for (value, glow) in self.display_buffer.iter_mut().zip(&self.phosphor_afterglow) {
+                    *value = value.max(glow * night_vision.phosphor_persistence);
+                }
+            }
+            self.phosphor_afterglow = self.display_buffer.to_vec();
+        }
+        self.display_mips = MipPyramid::build_from_buffer(
+            &self.display_buffer[..],
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+        );
+        self.display_generation = self.display_generation.wrapping_add(1);
+        self.dirty = true;
+    }
+
+    /// Whether `update` changes the image on its own, so the event loop can't sleep
+    fn is_animating(&self) -> bool {
+        self.continuous_render
+            || self.animation_playing
+            || (self.render_mode == RenderMode::Lissajous && self.lissajous.animate)
+            || (self.render_mode == RenderMode::Spiral && self.spiral.animate)
+            || self.render_mode == RenderMode::Oscilloscope
+            || self.render_mode == RenderMode::Camera
+            || self.post_process.hue_cycle_enabled
+            || self.sequence_player.as_ref().is_some_and(|player| {
+                let status = player.status();
+                status.playing || status.loading
+            })
+    }
+
+    /// Apply an event sent by the GUI or by an external tool
+    fn handle_user_event(&mut self, event: UserEvent) {
+        match event {
+            UserEvent::Render => {
+                debug!("Re-rendering...");
+                self.render();
+            }
+            UserEvent::SetParam { key, value } => {
+                if let Err(e) = self.set_param(&key, value) {
+                    warn!("{e}");
+                }
+            }
+            UserEvent::Save {
+                path,
+                single_channel,
+            } => {
+                // Don't save a half rendered image
+                self.wait_for_renders();
+                if let Err(e) = self.save(&path, single_channel) {
+                    error!("Failed to save image: {e:?}");
+                    self.image_error = Some(e);
+                }
+            }
+            UserEvent::SaveAscii {
+                path,
+                char_w,
+                char_h,
+                charset,
+                colored,
+            } => {
+                self.wait_for_renders();
+                let export = if colored {
+                    export_ascii_art_html
+                } else {
+                    export_ascii_art
+                };
+                let text = export(
+                    &self.display_buffer[..],
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                    char_w,
+                    char_h,
+                    &charset,
+                );
+                match std::fs::write(&path, text) {
+                    Ok(()) => info!("Successfully saved ASCII art to {}", path.display()),
+                    Err(e) => error!("Failed to save ASCII art: {e:?}"),
+                }
+            }
+            UserEvent::SaveJpeg {
+                path,
+                embed_metadata,
+                scene_path,
+            } => {
+                self.wait_for_renders();
+                let metadata = embed_metadata.then(|| self.jpeg_metadata(scene_path.as_deref()));
+                let mut pixels = self.display_buffer.clone();
+                if let Some(watermark) = &self.watermark {
+                    watermark.draw(
+                        &mut pixels[..],
+                        RENDER_BUFFER_WIDTH as usize,
+                        RENDER_BUFFER_HEIGHT as usize,
+                    );
+                }
+                if let Err(e) = write_as_jpeg_image(
+                    &path,
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                    &pixels[..],
+                    self.exposure_ev,
+                    metadata.as_ref(),
+                ) {
+                    error!("Failed to save JPEG: {e:?}");
+                    self.image_error = Some(e);
+                }
+            }
+            UserEvent::SetRenderMode(render_mode) => {
+                if render_mode == RenderMode::ValueNoise
+                    && self.render_mode != RenderMode::ValueNoise
+                {
+                    self.noise_base.copy_from_slice(&self.framebuffer[..]);
+                    convert_storage_encoding(
+                        &mut self.noise_base,
+                        self.storage_encoding,
+                        StorageEncoding::Linear,
+                    );
+                }
+                #[cfg(feature = "cpal")]
+                if render_mode == RenderMode::Oscilloscope && self.audio.is_none() {
+                    self.audio = audio::AudioCapture::new()
+                        .map_err(|e| {
+                            warn!("No audio for the oscilloscope, using a test signal: {e:?}")
+                        })
+                        .ok();
+                }
+                #[cfg(feature = "camera-input")]
+                if render_mode == RenderMode::Camera && self.camera_capture.is_none() {
+                    self.open_camera();
+                }
+                self.render_mode = render_mode;
+                self.render();
+            }
+            UserEvent::SetMandelbrotView(view) => {
+                self.mandelbrot_view = view;
+                if self.render_mode == RenderMode::Mandelbrot {
+                    self.render();
+                }
+            }
+            UserEvent::RecordKeyframe { frame_number } => {
+                self.animator.insert_keyframe(MandelbrotKeyframe {
+                    center: self.mandelbrot_view.center,
+                    scale: self.mandelbrot_view.scale,
+                    frame_number,
+                });
+            }
+            UserEvent::ClearKeyframes => {
+                self.animation_playing = false;
+                self.animator.clear();
+            }
+            UserEvent::PreviewAnimation => {
+                self.force_render_mode(RenderMode::Mandelbrot);
+                self.tile_renderer.reset();
+                self.background_renderer.reset();
+                self.animator.rewind();
+                self.animation_playing = true;
+            }
+            UserEvent::ExportSequence(prefix) => {
+                if let Err(e) = self.export_sequence(&prefix) {
+                    error!("Failed to export sequence: {e:?}");
+                    if let Ok(e) = e.downcast::<ImageError>() {
+                        self.image_error = Some(e);
+                    }
+                }
+            }
+            UserEvent::SetStorageEncoding(storage_encoding) => {
+                convert_storage_encoding(
+                    &mut self.framebuffer,
+                    self.storage_encoding,
+                    storage_encoding,
+                );
+                self.storage_encoding = storage_encoding;
+            }
+            UserEvent::SetSampler(sampler) => {
+                self.sampler = sampler;
+                self.render();
+            }
+            UserEvent::SetSoftProof {
+                target,
+                gamut_warning,
+            } => {
+                self.soft_proof = target.profile();
+                self.gamut_warning = gamut_warning;
+            }
+            UserEvent::SetDisplayMode { mode, print } => {
+                self.display_mode = mode;
+                self.print = print;
+            }
+            UserEvent::SetOutputColorSpace(output_color_space) => {
+                self.output_color_space = output_color_space;
+            }
+            UserEvent::SetDither { mode, scale } => {
+                self.dither_mode = mode;
+                self.dither_scale = scale;
+            }
+            UserEvent::SetReferenceDifference(reference) => {
+                self.reference_difference = reference;
+            }
+            UserEvent::SetShIrradiance(sh) => {
+                self.sh_irradiance = sh;
+                self.render_mode = RenderMode::ShSphere;
+                self.render();
+            }
+            UserEvent::SetCubemap { faces, hdri } => {
+                self.cubemap = Some(faces);
+                self.scene_files.cubemap_hdri = Some(hdri);
+                self.render_mode = RenderMode::CubemapCross;
+                self.render();
+            }
+            UserEvent::SetGradientAnchors(anchors) => {
+                self.gradient_anchors = anchors;
+                if self.render_mode == RenderMode::MultipointGradient {
+                    self.render();
+                }
+            }
+            UserEvent::SetLissajous(lissajous) => {
+                // While animating, the phase is driven by the application
+                let phase = self.lissajous.phase;
+                self.lissajous = lissajous;
+                if lissajous.animate {
+                    self.lissajous.phase = phase;
+                }
+                if self.render_mode == RenderMode::Lissajous {
+                    self.render();
+                }
+            }
+            UserEvent::SetStarfield(starfield) => {
+                self.starfield = starfield;
+                if self.render_mode == RenderMode::Starfield {
+                    self.render();
+                }
+            }
+            UserEvent::SetSpectralRange(range) => {
+                self.spectral_range = range;
+                if self.render_mode == RenderMode::SpectralGradient {
+                    self.render();
+                }
+            }
+            UserEvent::SetFractalFlame(fractal_flame) => {
+                self.fractal_flame = *fractal_flame;
+                if self.render_mode == RenderMode::FractalFlame {
+                    self.render();
+                }
+            }
+            UserEvent::SetValueNoise(value_noise) => {
+                self.value_noise = value_noise;
+                if self.render_mode == RenderMode::ValueNoise {
+                    self.render();
+                }
+            }
+            UserEvent::SetSky(sky) => {
+                self.sky = sky;
+                if self.render_mode == RenderMode::Sky {
+                    self.render();
+                }
+            }
+            UserEvent::SetSceneColors(colors) => {
+                self.scene_colors = colors;
+                if self.render_mode == RenderMode::Turbulence {
+                    self.render();
+                }
+            }
+            UserEvent::SetTurbulence(turbulence) => {
+                self.turbulence = turbulence;
+                if self.render_mode == RenderMode::Turbulence {
+                    self.render();
+                }
+            }
+            UserEvent::SetBricks(bricks) => {
+                self.bricks = bricks;
+                if self.render_mode == RenderMode::Bricks {
+                    self.render();
+                }
+            }
+            UserEvent::SetColorWheel { lightness, mode } => {
+                self.color_wheel_lightness = lightness;
+                self.color_wheel_mode = mode;
+                if self.render_mode == RenderMode::ColorWheel {
+                    self.render();
+                }
+            }
+            UserEvent::SetClear { enabled, color } => {
+                self.clear_before_render = enabled;
+                self.clear_color = color;
+                self.render();
+            }
+            UserEvent::SetBrush(brush) => {
+                self.brush = brush;
+                if brush.is_none() {
+                    self.end_stroke();
+                }
+            }
+            UserEvent::UndoStroke => self.undo_stroke(),
+            UserEvent::ResetCloneSource => {
+                self.clone_source = None;
+                self.end_stroke();
+            }
+            UserEvent::GenerateSdf { scale, range } => {
+                convert_storage_encoding(
+                    &mut self.framebuffer,
+                    self.storage_encoding,
+                    StorageEncoding::Linear,
+                );
+                let mask: Vec<f32> = self
+                    .framebuffer
+                    .chunks_exact(4)
+                    .map(|pixel| acescg_luminance([pixel[0], pixel[1], pixel[2]]))
+                    .collect();
+                let distances = generate_sdf(
+                    &mask,
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                );
+                let span = (range[1] - range[0]).max(f32::EPSILON);
+                for (pixel, distance) in self.framebuffer.chunks_exact_mut(4).zip(distances) {
+                    let value = ((distance * scale - range[0]) / span).clamp(0.0, 1.0);
+                    pixel.copy_from_slice(&[value, value, value, 1.0]);
+                }
+                self.encode_framebuffer();
+            }
+            UserEvent::RestoreFramebuffer(path) => {
+                match load_exr_frame(
+                    &path,
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                ) {
+                    Ok(pixels) => {
+                        // Strokes painted over the previous image can't be undone on top of this one
+                        self.undo_stack.clear();
+                        self.tile_renderer.reset();
+                        self.background_renderer.reset();
+                        self.framebuffer.copy_from_slice(&pixels);
+                        self.encode_framebuffer();
+                    }
+                    Err(e) => error!("Failed to restore {}: {e:?}", path.display()),
+                }
+            }
+            UserEvent::AutoLevels => {
+                let mut linear = self.framebuffer.clone();
+                convert_storage_encoding(
+                    &mut linear,
+                    self.storage_encoding,
+                    StorageEncoding::Linear,
+                );
+                let levels = &mut self.post_process.levels;
+                auto_levels(
+                    &linear[..],
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                    levels,
+                );
+                levels.enabled = true;
+                self.auto_levels = Some(*levels);
+                self.advance_display_buffer();
+            }
+            UserEvent::SampleColor { pixel, target } => {
+                let index = (pixel[1] * RENDER_BUFFER_WIDTH + pixel[0]) as usize * 4;
+                let mut rgba = [0.0; 4];
+                rgba.copy_from_slice(&self.framebuffer[index..index + 4]);
+                for channel in &mut rgba[..3] {
+                    *channel = self.storage_encoding.decode(*channel);
+                }
+                self.sampled_color = Some((target, rgba));
+            }
+            UserEvent::SetWoodGrain(wood_grain) => {
+                self.wood_grain = wood_grain;
+                if self.render_mode == RenderMode::WoodGrain {
+                    self.render();
+                }
+            }
+            UserEvent::SetPoissonDisk(poisson_disk) => {
+                self.poisson_disk = poisson_disk;
+                if self.render_mode == RenderMode::PoissonDisk {
+                    self.render();
+                }
+            }
+            UserEvent::SaveScene(path) => {
+                let saved = self.scene_description().and_then(|scene| scene.save(&path));
+                match saved {
+                    Ok(()) => info!("Saved the scene to {}", path.display()),
+                    Err(e) => error!("Failed to save the scene: {e:?}"),
+                }
+            }
+            UserEvent::SetWorley(worley) => {
+                self.worley = worley;
+                if self.render_mode == RenderMode::Worley {
+                    self.render();
+                }
+            }
+            UserEvent::SetNodeGraph(node_graph) => {
+                self.node_graph = node_graph;
+                if self.render_mode == RenderMode::NodeGraph {
+                    self.render();
+                }
+            }
+            UserEvent::SetContinuousRender(continuous_render) => {
+                self.continuous_render = continuous_render;
+            }
+            UserEvent::SetPcb(pcb) => {
+                self.pcb = pcb;
+                if self.render_mode == RenderMode::Pcb {
+                    self.render();
+                }
+            }
+            UserEvent::SetTextureQuilting(texture_quilting) => {
+                self.texture_quilting = texture_quilting;
+                if self.render_mode == RenderMode::TextureSynthesis {
+                    self.render();
+                }
+            }
+            UserEvent::SetExemplar { exemplar, path } => {
+                self.exemplar = Some(exemplar);
+                self.scene_files.exemplar = Some(path);
+                if self.render_mode == RenderMode::TextureSynthesis {
+                    self.render();
+                }
+            }
+            UserEvent::SetRaymarcher(raymarcher) => {
+                self.raymarcher = raymarcher;
+                if self.render_mode == RenderMode::Raymarcher {
+                    self.render();
+                }
+            }
+            UserEvent::SetBrdfSpheres(brdf_spheres) => {
+                self.brdf_spheres = brdf_spheres;
+                if self.render_mode == RenderMode::BrdfSpheres {
+                    self.render();
+                }
+            }
+            UserEvent::SetTruchet(truchet) => {
+                self.truchet = truchet;
+                if self.render_mode == RenderMode::Truchet {
+                    self.render();
+                }
+            }
+            UserEvent::SetGrunge(grunge) => {
+                self.grunge = grunge;
+                if self.render_mode == RenderMode::GrungeMap {
+                    self.render();
+                }
+            }
+            UserEvent::SetSpiral(spiral) => {
+                // While animating, the phase is driven by the application
+                let phase = self.spiral.phase;
+                self.spiral = spiral;
+                if spiral.animate {
+                    self.spiral.phase = phase;
+                }
+                if self.render_mode == RenderMode::Spiral {
+                    self.render();
+                }
+            }
+            UserEvent::SetTerrain(terrain) => {
+                self.terrain = terrain;
+                if self.render_mode == RenderMode::Terrain {
+                    self.render();
+                }
+            }
+            UserEvent::SetTemporalDenoise {
+                enabled,
+                blend_factor,
+            } => {
+                self.denoise_enabled = enabled;
+                self.denoiser.blend_factor = blend_factor;
+            }
+            // The display buffer is refreshed below, which restarts the history
+            UserEvent::ResetDenoiseHistory => {}
+            UserEvent::OpenSequence(directory) => {
+                match ExrSequencePlayer::open(
+                    &directory,
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                    self.playback,
+                ) {
+                    Ok(player) => {
+                        // The sequence replaces the render in progress
+                        self.tile_renderer.reset();
+                        self.background_renderer.reset();
+                        self.animation_playing = false;
+                        self.sequence_player = Some(player);
+                    }
+                    Err(e) => error!("Failed to open {}: {e:?}", directory.display()),
+                }
+            }
+            UserEvent::SetPlayback(playback) => {
+                self.playback = playback;
+                if let Some(player) = &mut self.sequence_player {
+                    player.set_settings(playback);
+                }
+            }
+            UserEvent::SeekSequence(frame) => {
+                if let Some(player) = &mut self.sequence_player {
+                    player.seek(frame);
+                }
+            }
+            UserEvent::SetExrMetadata(metadata) => self.exr_metadata = metadata,
+            UserEvent::SetPixelAspectRatio(pixel_aspect) => self.pixel_aspect = pixel_aspect,
+            UserEvent::TilesReady => {
+                let tiles = self.tile_renderer.receive();
+                self.write_tiles(tiles);
+            }
+            UserEvent::CancelRender => self.tile_renderer.cancel(),
+            UserEvent::ApplyScene(scene) => {
+                let (scene, params) = *scene;
+                self.apply_scene(scene, params);
+            }
+            UserEvent::SetOscilloscope(oscilloscope) => {
+                // Picked up by the next frame
+                self.oscilloscope = oscilloscope;
+            }
+            UserEvent::SetCamera(camera) => {
+                #[cfg(feature = "camera-input")]
+                let device_changed = camera.device != self.camera.device;
+                self.camera = camera;
+                #[cfg(feature = "camera-input")]
+                if device_changed && self.render_mode == RenderMode::Camera {
+                    self.open_camera();
+                }
+                if self.render_mode == RenderMode::Camera {
+                    self.render();
+                }
+            }
+            UserEvent::SetLut(lut) => self.lut = lut,
+            UserEvent::SetWatermark(watermark) => self.watermark = watermark,
+            UserEvent::BurnText(text) => {
+                convert_storage_encoding(
+                    &mut self.framebuffer,
+                    self.storage_encoding,
+                    StorageEncoding::Linear,
+                );
+                text.draw(
+                    &mut self.framebuffer[..],
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                );
+                self.encode_framebuffer();
+            }
+            UserEvent::SetPassOrder(pass_order) => {
+                self.render_passes.set_order(&pass_order);
+                self.pass_order = pass_order;
+                // Run the passes again in their new order, instead of the refresh below
+                self.update_display_buffer();
+                return;
+            }
+            UserEvent::SetAlphaMode(alpha_mode) => {
+                if alpha_mode != self.alpha_mode {
+                    convert_storage_encoding(
+                        &mut self.framebuffer,
+                        self.storage_encoding,
+                        StorageEncoding::Linear,
+                    );
+                    match alpha_mode {
+                        AlphaMode::Straight => unpremultiply_alpha(&mut self.framebuffer[..]),
+                        AlphaMode::Premultiplied => premultiply_alpha(&mut self.framebuffer[..]),
+                    }
+                    self.alpha_mode = alpha_mode;
+                    self.encode_framebuffer();
+                    self.update_display_buffer();
+                }
+            }
+            UserEvent::ApplyColorOp(program) => {
+                convert_storage_encoding(
+                    &mut self.framebuffer,
+                    self.storage_encoding,
+                    StorageEncoding::Linear,
+                );
+                program.apply(
+                    &mut self.framebuffer[..],
+                    RENDER_BUFFER_WIDTH as usize,
+                    RENDER_BUFFER_HEIGHT as usize,
+                );
+                self.encode_framebuffer();
+            }
+            UserEvent::SetDepthBuffer { depth, path } => {
+                self.render_passes.inputs_mut().depth_buffer = Some(depth);
+                self.scene_files.depth_buffer = Some(path);
+                self.update_display_buffer();
+            }
+            UserEvent::SetWarpMap { displacement, path } => {
+                self.render_passes.inputs_mut().warp_map = Some(displacement);
+                self.scene_files.warp_map = path;
+                self.update_display_buffer();
+            }
+            UserEvent::SetSsaaFactor(ssaa_factor) => {
+                self.ssaa_factor = ssaa_factor.max(1);
+                self.render();
+            }
+            UserEvent::SetRenderRegion(region) => {
+                let (width, height) = (RENDER_BUFFER_WIDTH as usize, RENDER_BUFFER_HEIGHT as usize);
+                self.render_region = region
+                    .map(|[x0, y0, x1, y1]| [x0, y0, x1.min(width), y1.min(height)])
+                    .filter(|[x0, y0, x1, y1]| x0 < x1 && y0 < y1);
+            }
+            UserEvent::SetPostProcess(post_process) => {
+                self.post_process = post_process;
+            }
+        }
+        // Most events touch either the framebuffer or the effects
+        self.update_display_buffer();
+    }
+
+    /// Set a numeric parameter by name. Taken as `f64`, so deep Mandelbrot zooms keep
+    /// their precision.
+    fn set_param(&mut self, key: &str, value: f64) -> anyhow::Result<()> {
+        match key {
+            "exposure_ev" => self.exposure_ev = value as f32,
+            "mandelbrot_center_x" => self.mandelbrot_view.center[0] = value,
+            "mandelbrot_center_y" => self.mandelbrot_view.center[1] = value,
+            "mandelbrot_scale" => self.mandelbrot_view.scale = value,
+            _ => anyhow::bail!("Unknown parameter: {key}"),
+        }
+        if key.starts_with("mandelbrot_") {
+            self.changed_mandelbrot_view = Some(self.mandelbrot_view);
+            if self.render_mode == RenderMode::Mandelbrot {
+                self.render();
+            }
+        }
+        Ok(())
+    }
+
+    /// Switch to `render_mode` without the GUI asking for it, and let the GUI know
+    fn force_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+        self.forced_render_mode = Some(render_mode);
+    }
+
+    /// Render the next frame of the keyframed animation through the tile renderer,
+    /// like any other Mandelbrot view. Returns false once past the last keyframe.
+    fn step_animation(&mut self) -> bool {
+        let Some((center, scale)) = self.animator.step() else {
+            return false;
+        };
+        self.mandelbrot_view.center = center;
+        self.mandelbrot_view.scale = scale;
+        self.changed_mandelbrot_view = Some(self.mandelbrot_view);
+        self.render();
+        true
+    }
+
+    /// Render and save every frame of the keyframed animation
+    fn export_sequence(&mut self, prefix: &Path) -> anyhow::Result<()> {
+        let Some((first, last)) = self.animator.frame_range() else {
+            anyhow::bail!("No keyframes recorded");
+        };
+
+        self.animation_playing = false;
+        self.force_render_mode(RenderMode::Mandelbrot);
+        self.tile_renderer.reset();
+        self.background_renderer.reset();
+        self.animator.rewind();
+        for frame in first..=last {
+            self.step_animation();
+            self.wait_for_renders();
+            let mut image_path = prefix.as_os_str().to_owned();
+            image_path.push(format!(".{frame:04}.exr"));
+            self.save(Path::new(&image_path), false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Describe the current render so it can be saved and restored later
+    fn scene_description(&self) -> anyhow::Result<SceneDescription> {
+        let params = match self.render_mode {
+            RenderMode::Gradient | RenderMode::CubemapCross => ProceduralParams::None,
+            RenderMode::Mandelbrot => ProceduralParams::Mandelbrot(self.mandelbrot_view),
+            RenderMode::ShSphere => ProceduralParams::ShSphere(self.sh_irradiance),
+            RenderMode::MultipointGradient => {
+                ProceduralParams::MultipointGradient(self.gradient_anchors.clone())
+            }
+            RenderMode::Lissajous => ProceduralParams::Lissajous(self.lissajous),
+            RenderMode::Starfield => ProceduralParams::Starfield(self.starfield),
+            RenderMode::SpectralGradient => ProceduralParams::SpectralGradient(self.spectral_range),
+            RenderMode::FractalFlame => ProceduralParams::FractalFlame(self.fractal_flame.clone()),
+            RenderMode::ValueNoise => ProceduralParams::ValueNoise(self.value_noise),
+            RenderMode::Sky => ProceduralParams::Sky(self.sky),
+            RenderMode::Turbulence => ProceduralParams::Turbulence(self.turbulence),
+            RenderMode::Bricks => ProceduralParams::Bricks(self.bricks),
+            RenderMode::ColorWheel => ProceduralParams::ColorWheel(ColorWheelParams {
+                lightness: self.color_wheel_lightness,
+                mode: self.color_wheel_mode,
+            }),
+            RenderMode::WoodGrain => ProceduralParams::WoodGrain(self.wood_grain),
+            RenderMode::PoissonDisk => ProceduralParams::PoissonDisk(self.poisson_disk),
+            RenderMode::Oscilloscope => ProceduralParams::Oscilloscope(self.oscilloscope),
+            RenderMode::Camera => ProceduralParams::Camera(self.camera),
+            RenderMode::Worley => ProceduralParams::Worley(self.worley),
+            RenderMode::NodeGraph => ProceduralParams::NodeGraph(self.node_graph.clone()),
+            RenderMode::Terrain => ProceduralParams::Terrain(self.terrain.clone()),
+            RenderMode::Pcb => ProceduralParams::Pcb(self.pcb),
+            RenderMode::TextureSynthesis => {
+                ProceduralParams::TextureQuilting(self.texture_quilting)
+            }
+            RenderMode::Raymarcher => ProceduralParams::Raymarcher(self.raymarcher.clone()),
+            RenderMode::BrdfSpheres => ProceduralParams::BrdfSpheres(self.brdf_spheres),
+            RenderMode::Truchet => ProceduralParams::Truchet(self.truchet),
+            RenderMode::GrungeMap => ProceduralParams::GrungeMap(self.grunge),
+            RenderMode::Spiral => ProceduralParams::Spiral(self.spiral),
+        };
+
+        let mut post_process_stack = PostProcessStep::stack(&self.post_process);
+        post_process_stack.sort_by_key(|step| {
+            self.pass_order
+                .iter()
+                .position(|slot| slot.effect == step.effect())
+        });
+
+        Ok(SceneDescription {
+            render_mode: self.render_mode,
+            tonemapper: TonemapperKind::Perceptual,
+            exposure_ev: self.exposure_ev,
+            sampler: self.sampler,
+            color_a: self.scene_colors[0],
+            color_b: self.scene_colors[1],
+            procedural_params: serde_json::to_value(params)?,
+            post_process_stack,
+            pass_order: self.pass_order.clone(),
+            file_paths: self.scene_files.clone(),
+        })
+    }
+
+    /// Restore a saved scene and render it
+    fn apply_scene(&mut self, scene: SceneDescription, params: ProceduralParams) {
+        self.exposure_ev = scene.exposure_ev;
+        self.sampler = scene.sampler;
+        self.scene_colors = [scene.color_a, scene.color_b];
+        self.post_process = scene.post_process();
+        self.render_passes.set_order(&scene.pass_order);
+        self.pass_order = scene.pass_order;
+        match params {
+            ProceduralParams::None => {}
+            ProceduralParams::Mandelbrot(view) => self.mandelbrot_view = view,
+            ProceduralParams::ShSphere(sh) => self.sh_irradiance = sh,
+            ProceduralParams::MultipointGradient(anchors) => self.gradient_anchors = anchors,
+            ProceduralParams::Lissajous(lissajous) => self.lissajous = lissajous,
+            ProceduralParams::Starfield(starfield) => self.starfield = starfield,
+            ProceduralParams::SpectralGradient(range) => self.spectral_range = range,
+            ProceduralParams::FractalFlame(fractal_flame) => self.fractal_flame = fractal_flame,
+            ProceduralParams::ValueNoise(value_noise) => self.value_noise = value_noise,
+            ProceduralParams::Sky(sky) => self.sky = sky,
+            ProceduralParams::Turbulence(turbulence) => self.turbulence = turbulence,
+            ProceduralParams::Bricks(bricks) => self.bricks = bricks,
+            ProceduralParams::ColorWheel(color_wheel) => {
+                self.color_wheel_lightness = color_wheel.lightness;
+                self.color_wheel_mode = color_wheel.mode;
+            }
+            ProceduralParams::WoodGrain(wood_grain) => self.wood_grain = wood_grain,
+            ProceduralParams::PoissonDisk(poisson_disk) => self.poisson_disk = poisson_disk,
+            ProceduralParams::Oscilloscope(oscilloscope) => self.oscilloscope = oscilloscope,
+            ProceduralParams::Camera(camera) => {
+                #[cfg(feature = "camera-input")]
+                if camera.device != self.camera.device {
+                    // Reopened on the new device by `SetRenderMode`
+                    self.camera_capture = None;
+                }
+                self.camera = camera;
+            }
+            ProceduralParams::Worley(worley) => self.worley = worley,
+            ProceduralParams::NodeGraph(node_graph) => self.node_graph = node_graph,
+            ProceduralParams::Terrain(terrain) => self.terrain = terrain,
+            ProceduralParams::Raymarcher(raymarcher) => self.raymarcher = raymarcher,
+            ProceduralParams::BrdfSpheres(brdf_spheres) => self.brdf_spheres = brdf_spheres,
+            ProceduralParams::Truchet(truchet) => self.truchet = truchet,
+            ProceduralParams::GrungeMap(grunge) => self.grunge = grunge,
+            ProceduralParams::Spiral(spiral) => self.spiral = spiral,
+            ProceduralParams::Pcb(pcb) => self.pcb = pcb,
+            ProceduralParams::TextureQuilting(texture_quilting) => {
+                self.texture_quilting = texture_quilting
+            }
+        }
+        // Same path as picking the mode in the GUI, which also renders
+        self.handle_user_event(UserEvent::SetRenderMode(scene.render_mode));
+    }
+
+    /// EXIF tags of a JPEG export, describing the scene only if it comes from `scene_path`
+    fn jpeg_metadata(&self, scene_path: Option<&Path>) -> JpegMetadata {
+        let scene = scene_path.and_then(|_| self.scene_description().ok());
+        JpegMetadata {
+            tonemapper: format!("{:?}", TonemapperKind::Perceptual),
+            exposure_ev: self.exposure_ev,
+            document_name: scene_path
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned()),
+            description: scene.and_then(|scene| serde_json::to_string(&scene).ok()),
+        }
+    }
+
+    /// Write the framebuffer to disk as an OpenEXR image.
+    /// Values are decoded to linear ACEScg first, whatever the storage encoding.
+    fn save(&self, image_path: &Path, single_channel: bool) -> Result<(), ImageError> {
+        let mut pixels = self.framebuffer.clone();
+        // Readers expect scene linear values, EXR has no attribute for a log encoding
+        convert_storage_encoding(&mut pixels, self.storage_encoding, StorageEncoding::Linear);
+        if let Some(watermark) = &self.watermark {
+            watermark.draw(
+                &mut pixels[..],
+                RENDER_BUFFER_WIDTH as usize,
+                RENDER_BUFFER_HEIGHT as usize,
+            );
+        }
+        write_as_exr_image(
+            image_path,
+            RENDER_BUFFER_WIDTH as usize,
+            RENDER_BUFFER_HEIGHT as usize,
+            &pixels[..],
+            &self.exr_metadata,
+            self.pixel_aspect.ratio(),
+            single_channel,
+            self.alpha_mode,
+        )
     }
 
     // Draw to the frame buffer
-    // Assumes the default texture format: `wgpu::TextureFormat::Rgba8UnormSrgb`
-    // This means:
-    //     Red, green, blue, and alpha channels.
-    //     8 bit integer per channel.
-    //     Srgb-color [0, 255] converted to/from linear-color float [0, 1] in shader
+    // The packing depends on the texture format chosen at startup:
+    //     `Rgba8UnormSrgb`: 8 bit integer per channel.
+    //         Srgb-color [0, 255] converted to/from linear-color float [0, 1] in shader
+    //     `Bgra8UnormSrgb`: same as above, with red and blue swapped
+    //     `Rgba16Float`: 16 bit float per channel, linear (not sRGB encoded)
     // See more formats here: https://docs.rs/wgpu/latest/wgpu/enum.TextureFormat.html
     fn draw(&self, frame: &mut [u8]) {
-        let it = std::iter::zip(frame.chunks_exact_mut(4), self.framebuffer.chunks_exact(4));
-        for (_, (pixel, render_pixel)) in it.enumerate() {
+        let exposure = 2.0_f32.powf(self.exposure_ev);
+        let bytes_per_pixel = self.surface_format.bytes_per_pixel();
+        let cmyk_preview = (self.display_mode == DisplayMode::CmykSoft).then(|| {
+            let exposed: Vec<f32> = self
+                .display_buffer
+                .chunks_exact(4)
+                .flat_map(|pixel| {
+                    [
+                        pixel[0] * exposure,
+                        pixel[1] * exposure,
+                        pixel[2] * exposure,
+                        pixel[3],
+                    ]
+                })
+                .collect();
+            simulate_cmyk_preview(
+                &exposed,
+                RENDER_BUFFER_WIDTH as usize,
+                RENDER_BUFFER_HEIGHT as usize,
+                self.print.paper_white,
+                self.print.ink_limit,
+            )
+        });
+        let it = std::iter::zip(
+            frame.chunks_exact_mut(bytes_per_pixel),
+            self.display_buffer.chunks_exact(4),
+        );
+        for (i, (pixel, render_pixel)) in it.enumerate() {
             // Here we draw the pixels!
             // In my case, I already drew them, so I can copy them around
             // and the bits of math to convert from scene referred to display referred
@@ -176,25 +2622,159 @@ impl ApplicationState {
             // For the sake of simplicity and saving memory, our array is composed of f32
             // instead of propert color structs. Here we recreate the colstodian color struct
             // on the fly so we can do the conversion to 8bit sRGB
-            let rendered_color =
-                colstodian::color::acescg(render_pixel[0], render_pixel[1], render_pixel[2]);
+            let mut rgb = [
+                render_pixel[0] * exposure,
+                render_pixel[1] * exposure,
+                render_pixel[2] * exposure,
+            ];
+
+            // Restrict the colors to what the simulated display can show
+            if let Some(profile) = &self.soft_proof {
+                let (mapped, out_of_gamut) = profile.gamut_map(rgb);
+                rgb = if out_of_gamut && self.gamut_warning {
+                    GAMUT_WARNING_COLOR
+                } else {
+                    mapped
+                };
+            }
+
+            let rendered_color = colstodian::color::acescg(rgb[0], rgb[1], rgb[2]);
             let alpha = render_pixel[3];
 
             // Use a standard Tonemap to go from ACEScg HDR to SDR
             let params = PerceptualTonemapperParams::default();
-            let tonemapped: Color<AcesCg, Display> =
+            let mut tonemapped: Color<AcesCg, Display> =
                 PerceptualTonemapper::tonemap(rendered_color, params).convert();
+            if let Some(lut) = &self.lut {
+                let [r, g, b] = lut.apply([tonemapped.r, tonemapped.g, tonemapped.b]);
+                tonemapped = Color::new(r, g, b);
+            }
+            // Print is display referred, the inks on paper replace the tonemapped colors
+            if let Some(cmyk_preview) = &cmyk_preview {
+                let preview = cmyk_preview_rgb(cmyk_preview[i], self.print.paper_white);
+                let [r, g, b] = mul_mat3(&LINEAR_SRGB_TO_ACESCG, preview.map(srgb_to_linear));
+                tonemapped = Color::new(r, g, b);
+            }
+
+            // sRGB encoded reference pixel, when showing the difference against it
+            let reference = self
+                .reference_difference
+                .as_ref()
+                .map(|reference| &reference[i * 4..i * 4 + 3]);
 
-            // Encode in sRGB so we're ready to display or write to an image
-            let encoded = tonemapped.convert::<EncodedSrgb>();
+            if self.surface_format == SurfaceFormat::Rgba16Float {
+                // Float textures aren't decoded in the shader, so they stay linear
+                let linear = tonemapped.convert::<LinearSrgb>();
+                let mut rgba = [linear.r, linear.g, linear.b, alpha];
+                if let Some(reference) = reference {
+                    for (value, reference) in rgba.iter_mut().zip(reference) {
+                        *value = (*value - srgb_to_linear(*reference as f32 / 255.0)).abs();
+                    }
+                }
+                for (bytes, value) in pixel.chunks_exact_mut(2).zip(rgba) {
+                    bytes.copy_from_slice(&f16::from_f32(value).to_le_bytes());
+                }
+                continue;
+            }
 
-            // Convert to 8bit
-            let rgb: [u8; 3] = encoded.to_u8();
+            // Encode for the display so we're ready to show or write to an image
+            let width = RENDER_BUFFER_WIDTH as usize;
+            let dither = self
+                .dither_mode
+                .offset(i % width, i / width, self.dither_scale);
+            let mut rgb: [u8; 3] = match self.output_color_space {
+                OutputColorSpace::Srgb => {
+                    let encoded = tonemapped.convert::<EncodedSrgb>();
+                    [encoded.r, encoded.g, encoded.b].map(|c| quantize_u8(c, dither))
+                }
+                space => space.encode_u8([tonemapped.r, tonemapped.g, tonemapped.b], dither),
+            };
+            if let Some(reference) = reference {
+                for (value, reference) in rgb.iter_mut().zip(reference) {
+                    *value = value.abs_diff(*reference);
+                }
+            }
 
             // Can I avoid doing a copy here ?
-            let rgba: [u8; 4] = [rgb[0], rgb[1], rgb[2], (255 as f32 * alpha) as u8];
+            let rgba: [u8; 4] = match self.surface_format {
+                SurfaceFormat::Bgra8UnormSrgb => {
+                    [rgb[2], rgb[1], rgb[0], (255 as f32 * alpha) as u8]
+                }
+                _ => [rgb[0], rgb[1], rgb[2], (255 as f32 * alpha) as u8],
+            };
 
             pixel.copy_from_slice(&rgba);
         }
+        // Float frames aren't rounded to 8 bits, so they have no banding to break up
+        if self.dither_mode == DitherMode::Ordered
+            && self.surface_format != SurfaceFormat::Rgba16Float
+        {
+            apply_bayer_dither(
+                frame,
+                RENDER_BUFFER_WIDTH as usize,
+                RENDER_BUFFER_HEIGHT as usize,
+                self.dither_scale,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 64;
+    const HEIGHT: usize = 48;
+    const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+    /// Two frames of the Lissajous curve with a different phase, like an animation would draw
+    fn render_two_frames(clear_before_render: bool) -> Vec<f32> {
+        let mut framebuffer = vec![0.0; WIDTH * HEIGHT * 4];
+        for phase in [0.0, 1.0] {
+            clear_framebuffer(&mut framebuffer, clear_before_render, CLEAR_COLOR);
+            render_lissajous(
+                &mut framebuffer,
+                WIDTH,
+                HEIGHT,
+                3.0,
+                2.0,
+                phase,
+                1.0,
+                [1.0, 1.0, 1.0],
+            );
+        }
+        framebuffer
+    }
+
+    #[test]
+    fn previous_frame_shows_through_without_clearing() {
+        let mut first_frame = vec![0.0; WIDTH * HEIGHT * 4];
+        clear_framebuffer(&mut first_frame, true, CLEAR_COLOR);
+        render_lissajous(
+            &mut first_frame,
+            WIDTH,
+            HEIGHT,
+            3.0,
+            2.0,
+            0.0,
+            1.0,
+            [1.0, 1.0, 1.0],
+        );
+        let cleared = render_two_frames(true);
+        let ghosted = render_two_frames(false);
+
+        // Pixels of the first curve that the second one doesn't cross
+        let mut trails = 0;
+        for ((first, cleared), ghosted) in first_frame
+            .chunks_exact(4)
+            .zip(cleared.chunks_exact(4))
+            .zip(ghosted.chunks_exact(4))
+        {
+            if first[0] > 0.0 && cleared[0] == 0.0 {
+                trails += 1;
+                assert_eq!(ghosted[..3], first[..3]);
+            }
+        }
+        assert!(trails > 0, "the two frames should not overlap entirely");
     }
 }
@@ -0,0 +1,135 @@
+use num_traits::Float;
+
+/// Linear remap a value in one range into another range (no clamping).
+/// A degenerate input range (`imin == imax`) maps everything to `omin`.
+pub fn fit_range_t<T: Float>(x: T, imin: T, imax: T, omin: T, omax: T) -> T {
+    if imin == imax {
+        return omin;
+    }
+    (omax - omin) * (x - imin) / (imax - imin) + omin
+}
+
+/// Same as `fit_range_t`, but the result is clamped to the output range.
+/// Works with reversed output ranges too (`omin > omax`).
+pub fn fit_range_clamped<T: Float>(x: T, imin: T, imax: T, omin: T, omax: T) -> T {
+    let value = fit_range_t(x, imin, imax, omin, omax);
+    let (low, high) = if omin <= omax {
+        (omin, omax)
+    } else {
+        (omax, omin)
+    };
+    value.max(low).min(high)
+}
+
+/// Component-wise `fit_range_t` for 2D vectors
+#[allow(dead_code)]
+pub fn fit_range_vec2<T: Float>(
+    x: [T; 2],
+    imin: [T; 2],
+    imax: [T; 2],
+    omin: [T; 2],
+    omax: [T; 2],
+) -> [T; 2] {
+    [
+        fit_range_t(x[0], imin[0], imax[0], omin[0], omax[0]),
+        fit_range_t(x[1], imin[1], imax[1], omin[1], omax[1]),
+    ]
+}
+
+/// Component-wise `fit_range_t` for 3D vectors
+#[allow(dead_code)]
+pub fn fit_range_vec3<T: Float>(
+    x: [T; 3],
+    imin: [T; 3],
+    imax: [T; 3],
+    omin: [T; 3],
+    omax: [T; 3],
+) -> [T; 3] {
+    [
+        fit_range_t(x[0], imin[0], imax[0], omin[0], omax[0]),
+        fit_range_t(x[1], imin[1], imax[1], omin[1], omax[1]),
+        fit_range_t(x[2], imin[2], imax[2], omin[2], omax[2]),
+    ]
+}
+
+/// Hermite interpolation between 0 and 1 as `x` goes from `edge0` to `edge1`
+pub fn smoothstep<T: Float>(edge0: T, edge1: T, x: T) -> T {
+    let t = fit_range_clamped(x, edge0, edge1, T::zero(), T::one());
+    let two = T::one() + T::one();
+    let three = two + T::one();
+    t * t * (three - two * t)
+}
+
+/// Ken Perlin's variant of `smoothstep`, with zero first and second derivatives at the edges
+pub fn smootherstep<T: Float>(edge0: T, edge1: T, x: T) -> T {
+    let t = fit_range_clamped(x, edge0, edge1, T::zero(), T::one());
+    let six = T::from(6.0).unwrap();
+    let ten = T::from(10.0).unwrap();
+    let fifteen = T::from(15.0).unwrap();
+    t * t * t * (t * (t * six - fifteen) + ten)
+}
+
+/// Schlick's bias function: remaps `x` in [0, 1] so that `bias(0.5, b) == b`
+#[allow(dead_code)]
+pub fn bias<T: Float>(x: T, b: T) -> T {
+    let two = T::one() + T::one();
+    x / ((T::one() / b - two) * (T::one() - x) + T::one())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degenerate_input_range_returns_omin() {
+        assert_eq!(fit_range_t(0.5_f32, 1.0, 1.0, 2.0, 4.0), 2.0);
+        assert_eq!(fit_range_t(-3.0_f64, 0.0, 0.0, -1.0, 1.0), -1.0);
+        assert_eq!(fit_range_clamped(7.0_f32, 2.0, 2.0, 5.0, 10.0), 5.0);
+        assert_eq!(
+            fit_range_vec2(
+                [0.0_f32, 1.0],
+                [1.0, 1.0],
+                [1.0, 1.0],
+                [3.0, 4.0],
+                [5.0, 6.0]
+            ),
+            [3.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn values_outside_the_input_range_extrapolate() {
+        assert_eq!(fit_range_t(2.0_f32, 0.0, 1.0, 0.0, 10.0), 20.0);
+        assert_eq!(fit_range_t(-1.0_f32, 0.0, 1.0, 0.0, 10.0), -10.0);
+        assert_eq!(fit_range_clamped(2.0_f32, 0.0, 1.0, 0.0, 10.0), 10.0);
+        assert_eq!(fit_range_clamped(-1.0_f32, 0.0, 1.0, 0.0, 10.0), 0.0);
+        assert_eq!(smoothstep(0.0_f32, 1.0, -0.5), 0.0);
+        assert_eq!(smootherstep(0.0_f32, 1.0, 1.5), 1.0);
+    }
+
+    #[test]
+    fn negative_and_reversed_ranges() {
+        assert_eq!(fit_range_t(-5.0_f32, -10.0, 0.0, 0.0, 1.0), 0.5);
+        assert_eq!(fit_range_t(0.25_f32, 0.0, 1.0, -1.0, -2.0), -1.25);
+        assert_eq!(fit_range_t(0.0_f32, 1.0, -1.0, 0.0, 10.0), 5.0);
+        assert_eq!(fit_range_clamped(2.0_f32, 0.0, 1.0, 0.0, -4.0), -4.0);
+        assert_eq!(fit_range_clamped(-1.0_f32, 0.0, 1.0, 0.0, -4.0), 0.0);
+        assert_eq!(
+            fit_range_vec3(
+                [-1.0_f32, 0.0, 1.0],
+                [-2.0; 3],
+                [2.0; 3],
+                [-1.0; 3],
+                [1.0; 3]
+            ),
+            [-0.5, 0.0, 0.5]
+        );
+    }
+
+    #[test]
+    fn bias_hits_its_anchor_points() {
+        assert_eq!(bias(0.0_f32, 0.3), 0.0);
+        assert_eq!(bias(1.0_f32, 0.3), 1.0);
+        assert!((bias(0.5_f32, 0.3) - 0.3).abs() < 1e-6);
+    }
+}
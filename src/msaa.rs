@@ -0,0 +1,321 @@
+use pixels::wgpu;
+
+/// Copies the resolved egui layer over the frame. Its colors are premultiplied.
+const COMPOSITE_SHADER: &str = "\
+@group(0) @binding(0) var egui_layer: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(egui_layer, vec2<i32>(position.xy), 0);
+}
+";
+
+/// Textures of the size of the window
+struct LayerTextures {
+    size: [u32; 2],
+    multisampled: wgpu::TextureView,
+    resolved: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Multisampled target egui is drawn to when MSAA is on.
+/// The frame already holds the framebuffer, which can't be drawn to a multisampled
+/// texture by `Pixels`, so egui is drawn to a layer of its own, resolved, then blended
+/// over the frame.
+pub(crate) struct MsaaLayer {
+    sample_count: u32,
+    format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    // Created on the first frame, and again when the window is resized
+    textures: Option<LayerTextures>,
+}
+
+impl MsaaLayer {
+    /// `format` is the one of the frame
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("egui_msaa_layer"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("egui_msaa_composite"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("egui_msaa_composite"),
+            source: wgpu::ShaderSource::Wgsl(COMPOSITE_SHADER.into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("egui_msaa_composite"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            sample_count,
+            format,
+            bind_group_layout,
+            pipeline,
+            textures: None,
+        }
+    }
+
+    /// Render pass attachment egui is drawn to, cleared to transparent.
+    /// The multisampled texture is resolved into the layer composited by `composite`.
+    pub(crate) fn attachment(
+        &mut self,
+        device: &wgpu::Device,
+        size: [u32; 2],
+    ) -> wgpu::RenderPassColorAttachment<'_> {
+        if self.textures.as_ref().map(|textures| textures.size) != Some(size) {
+            self.textures = Some(self.create_textures(device, size));
+        }
+        let textures = self.textures.as_ref().expect("created above");
+        wgpu::RenderPassColorAttachment {
+            view: &textures.multisampled,
+            resolve_target: Some(&textures.resolved),
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                // Only the resolved layer is read afterwards
+                store: false,
+            },
+        }
+    }
+
+    /// Blend the resolved egui layer over the frame
+    pub(crate) fn composite(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        render_target: &wgpu::TextureView,
+    ) {
+        let Some(textures) = &self.textures else {
+            return;
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("egui_msaa_composite"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &textures.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    fn create_textures(&self, device: &wgpu::Device, size: [u32; 2]) -> LayerTextures {
+        let texture = |label, sample_count, usage| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: size[0].max(1),
+                        height: size[1].max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: self.format,
+                    usage,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let multisampled = texture(
+            "egui_msaa",
+            self.sample_count,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let resolved = texture(
+            "egui_msaa_resolved",
+            1,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("egui_msaa_layer"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&resolved),
+            }],
+        });
+
+        LayerTextures {
+            size,
+            multisampled,
+            resolved,
+            bind_group,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui_wgpu::renderer::{Renderer, ScreenDescriptor};
+
+    use super::*;
+    use crate::config::MsaaConfig;
+
+    const SIZE: u32 = 64;
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Draw a panel with egui through a 4x multisampled layer, without a window
+    #[test]
+    fn egui_renders_with_4_samples() {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()));
+        let Some(adapter) = adapter else {
+            // Nothing to render with, not even a software adapter
+            return;
+        };
+        let msaa = MsaaConfig { sample_count: 4 };
+        assert!(msaa.is_supported(&adapter, FORMAT));
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                limits: adapter.limits(),
+                ..Default::default()
+            },
+            None,
+        ))
+        .unwrap();
+
+        let ctx = egui::Context::default();
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(SIZE as f32, SIZE as f32),
+            )),
+            ..Default::default()
+        };
+        let output = ctx.run(input, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| ui.label("MSAA"));
+        });
+        let paint_jobs = ctx.tessellate(output.shapes);
+        let screen_descriptor = ScreenDescriptor {
+            size_in_pixels: [SIZE, SIZE],
+            pixels_per_point: 1.0,
+        };
+
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut renderer = Renderer::new(&device, FORMAT, None, msaa.sample_count);
+        let mut layer = MsaaLayer::new(&device, FORMAT, msaa.sample_count);
+
+        // Same steps as `Framework::render`
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        for (id, image_delta) in &output.textures_delta.set {
+            renderer.update_texture(&device, &queue, *id, image_delta);
+        }
+        renderer.update_buffers(
+            &device,
+            &queue,
+            &mut encoder,
+            &paint_jobs,
+            &screen_descriptor,
+        );
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(layer.attachment(&device, [SIZE, SIZE]))],
+                depth_stencil_attachment: None,
+            });
+            renderer.render(&mut pass, &paint_jobs, &screen_descriptor);
+        }
+        layer.composite(&mut encoder, &target_view);
+
+        // 64 RGBA8 pixels per row is the 256 bytes alignment copies need
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (SIZE * SIZE * 4) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+
+        // The target starts out transparent, below the label is the opaque panel background
+        let pixels = slice.get_mapped_range();
+        let index = ((SIZE * 3 / 4 * SIZE + SIZE / 2) * 4) as usize;
+        let panel = &pixels[index..index + 4];
+        assert_eq!(panel[3], 255);
+        assert!(
+            panel[..3].iter().any(|&c| c > 0),
+            "panel is black: {panel:?}"
+        );
+    }
+}
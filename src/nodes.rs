@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::image::apply_gaussian_blur;
+use crate::wgsl::WgslRunner;
+
+/// Shader of a new WGSL node, which copies its input
+pub(crate) const DEFAULT_WGSL_SHADER: &str = "\
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(input_texture, input_sampler, in.uv);
+}
+";
+
+/// Stable identifier of a node, kept when other nodes are removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct NodeId(pub u32);
+
+/// Operation performed by a node. Every input and output is a linear ACEScg RGBA image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum NodeKind {
+    /// Horizontal ramp between two colors
+    Gradient {
+        color_a: [f32; 3],
+        color_b: [f32; 3],
+    },
+    /// Blur of the input, `sigma` in pixels
+    GaussianBlur { sigma: f32 },
+    /// `lerp(a, b, factor)` of the two inputs
+    Merge { factor: f32 },
+    /// User WGSL fragment shader run on the GPU, see `wgsl::WGSL_PRELUDE` for what it can use
+    Wgsl { source: String },
+    /// Image written to the framebuffer
+    Output,
+}
+
+impl NodeKind {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            NodeKind::Gradient { .. } => "Gradient",
+            NodeKind::GaussianBlur { .. } => "Gaussian Blur",
+            NodeKind::Merge { .. } => "Merge",
+            NodeKind::Wgsl { .. } => "WGSL Shader",
+            NodeKind::Output => "Output",
+        }
+    }
+
+    pub(crate) fn input_count(&self) -> usize {
+        match self {
+            NodeKind::Gradient { .. } => 0,
+            NodeKind::GaussianBlur { .. } | NodeKind::Wgsl { .. } | NodeKind::Output => 1,
+            NodeKind::Merge { .. } => 2,
+        }
+    }
+
+    pub(crate) fn has_output(&self) -> bool {
+        !matches!(self, NodeKind::Output)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Node {
+    pub id: NodeId,
+    pub kind: NodeKind,
+    // Top left corner in the graph editor, in points
+    pub position: [f32; 2],
+}
+
+/// Connection from the output of `from` to input number `input` of `to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Edge {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub input: usize,
+}
+
+/// Compositing graph, evaluated from the sources to the output node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct NodeGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    next_id: u32,
+}
+
+impl Default for NodeGraph {
+    /// Two gradients merged together, blurred and sent to the output
+    fn default() -> Self {
+        let mut graph = Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            next_id: 0,
+        };
+        let horizontal = graph.add_node(
+            NodeKind::Gradient {
+                color_a: [0.0, 0.0, 0.0],
+                color_b: [1.0, 0.5, 0.1],
+            },
+            [20.0, 20.0],
+        );
+        let reversed = graph.add_node(
+            NodeKind::Gradient {
+                color_a: [0.1, 0.3, 1.0],
+                color_b: [0.0, 0.0, 0.0],
+            },
+            [20.0, 140.0],
+        );
+        let merge = graph.add_node(NodeKind::Merge { factor: 0.5 }, [200.0, 80.0]);
+        let blur = graph.add_node(NodeKind::GaussianBlur { sigma: 2.0 }, [380.0, 80.0]);
+        let output = graph.add_node(NodeKind::Output, [560.0, 80.0]);
+        for (from, to, input) in [
+            (horizontal, merge, 0),
+            (reversed, merge, 1),
+            (merge, blur, 0),
+            (blur, output, 0),
+        ] {
+            graph.connect(from, to, input);
+        }
+        graph
+    }
+}
+
+impl NodeGraph {
+    pub(crate) fn add_node(&mut self, kind: NodeKind, position: [f32; 2]) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        self.nodes.push(Node { id, kind, position });
+        id
+    }
+
+    /// Remove a node and every edge touching it
+    pub(crate) fn remove_node(&mut self, id: NodeId) {
+        self.nodes.retain(|node| node.id != id);
+        self.edges.retain(|edge| edge.from != id && edge.to != id);
+    }
+
+    pub(crate) fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    pub(crate) fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.iter_mut().find(|node| node.id == id)
+    }
+
+    /// Connect the output of `from` to an input of `to`, replacing what was connected there.
+    /// Returns false, leaving the graph untouched, if the edge is invalid or would make a cycle.
+    pub(crate) fn connect(&mut self, from: NodeId, to: NodeId, input: usize) -> bool {
+        let (Some(source), Some(target)) = (self.node(from), self.node(to)) else {
+            return false;
+        };
+        if !source.kind.has_output() || input >= target.kind.input_count() {
+            return false;
+        }
+        if from == to || self.depends_on(from, to) {
+            return false;
+        }
+        self.disconnect(to, input);
+        self.edges.push(Edge { from, to, input });
+        true
+    }
+
+    /// Remove the edge going into an input, if any
+    pub(crate) fn disconnect(&mut self, to: NodeId, input: usize) {
+        self.edges
+            .retain(|edge| !(edge.to == to && edge.input == input));
+    }
+
+    /// Whether `node` reads, directly or not, the output of `upstream`
+    fn depends_on(&self, node: NodeId, upstream: NodeId) -> bool {
+        let mut stack = vec![node];
+        while let Some(current) = stack.pop() {
+            if current == upstream {
+                return true;
+            }
+            stack.extend(
+                self.edges
+                    .iter()
+                    .filter(|edge| edge.to == current)
+                    .map(|edge| edge.from),
+            );
+        }
+        false
+    }
+
+    /// Nodes ordered so each one comes after the nodes it reads from (Kahn's algorithm)
+    fn topological_order(&self) -> Vec<NodeId> {
+        let mut pending_inputs: HashMap<NodeId, usize> =
+            self.nodes.iter().map(|node| (node.id, 0)).collect();
+        for edge in &self.edges {
+            *pending_inputs.entry(edge.to).or_default() += 1;
+        }
+
+        let mut ready: Vec<NodeId> = self
+            .nodes
+            .iter()
+            .map(|node| node.id)
+            .filter(|id| pending_inputs[id] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(id) = ready.pop() {
+            order.push(id);
+            for edge in self.edges.iter().filter(|edge| edge.from == id) {
+                let pending = pending_inputs.entry(edge.to).or_default();
+                *pending -= 1;
+                if *pending == 0 {
+                    ready.push(edge.to);
+                }
+            }
+        }
+        order
+    }
+
+    /// Evaluate every node and return the image reaching the first output node.
+    /// Unconnected inputs read as transparent black, as does a graph without an output.
+    /// The WGSL nodes run on `shaders`.
+    pub(crate) fn execute(
+        &self,
+        width: usize,
+        height: usize,
+        shaders: &mut WgslRunner,
+    ) -> Vec<f32> {
+        shaders.retain(|id| self.node(id).is_some());
+        let size = width * height * 4;
+        let mut results: HashMap<NodeId, Vec<f32>> = HashMap::new();
+        let mut output = None;
+
+        for id in self.topological_order() {
+            let Some(node) = self.node(id) else {
+                continue;
+            };
+            let input = |index: usize| {
+                self.edges
+                    .iter()
+                    .find(|edge| edge.to == id && edge.input == index)
+                    .and_then(|edge| results.get(&edge.from))
+                    .cloned()
+                    .unwrap_or_else(|| vec![0.0; size])
+            };
+
+            let result = match node.kind {
+                NodeKind::Gradient { color_a, color_b } => {
+                    let mut buffer = vec![0.0; size];
+                    for row in buffer.chunks_exact_mut(width * 4) {
+                        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                            let t = x as f32 / (width.max(2) - 1) as f32;
+                            for ((channel, a), b) in pixel.iter_mut().zip(color_a).zip(color_b) {
+                                *channel = a + (b - a) * t;
+                            }
+                            pixel[3] = 1.0;
+                        }
+                    }
+                    buffer
+                }
+                NodeKind::Wgsl { ref source } => shaders.run(id, source, input(0), width, height),
+                NodeKind::GaussianBlur { sigma } => {
+                    let mut buffer = input(0);
+                    apply_gaussian_blur(&mut buffer, width, height, sigma);
+                    buffer
+                }
+                NodeKind::Merge { factor } => {
+                    let mut buffer = input(0);
+                    for (a, b) in buffer.iter_mut().zip(input(1)) {
+                        *a += (b - *a) * factor;
+                    }
+                    buffer
+                }
+                NodeKind::Output => {
+                    if output.is_none() {
+                        output = Some(input(0));
+                    }
+                    continue;
+                }
+            };
+            results.insert(id, result);
+        }
+
+        output.unwrap_or_else(|| vec![0.0; size])
+    }
+}
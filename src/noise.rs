@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+
+/// Gradient noise the fractal patterns are built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoiseBasis {
+    /// Gradients on a square lattice, interpolated across each cell
+    Perlin,
+    /// Gradients on a simplex (triangle) lattice, summed with a radial falloff
+    Simplex,
+}
+
+/// Ken Perlin's reference permutation of the bytes
+#[rustfmt::skip]
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225,
+    140, 36, 103, 30, 69, 142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148,
+    247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219, 203, 117, 35, 11, 32,
+    57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122,
+    60, 211, 133, 230, 220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54,
+    65, 25, 63, 161, 1, 216, 80, 73, 209, 76, 132, 187, 208, 89, 18, 169,
+    200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173, 186, 3, 64,
+    52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212,
+    207, 206, 59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213,
+    119, 248, 152, 2, 44, 154, 163, 70, 221, 153, 101, 155, 167, 43, 172, 9,
+    129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232, 178, 185, 112, 104,
+    218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162, 241,
+    81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157,
+    184, 84, 204, 176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93,
+    222, 114, 67, 29, 24, 72, 243, 141, 128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+/// Midpoints of the edges of a cube, the gradients of the 2D and 3D simplex noise
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// Pseudo random byte of a lattice coordinate
+fn hash(i: i32) -> usize {
+    PERMUTATION[(i & 255) as usize] as usize
+}
+
+/// Gradient of a lattice corner
+fn gradient(index: usize) -> [f32; 3] {
+    GRADIENTS[index % 12]
+}
+
+/// Contribution of a simplex corner at `offset` from the point, zero past its radius
+fn corner_2d(offset: [f32; 2], index: usize) -> f32 {
+    let t = 0.5 - offset[0] * offset[0] - offset[1] * offset[1];
+    if t <= 0.0 {
+        return 0.0;
+    }
+    let g = gradient(index);
+    t.powi(4) * (g[0] * offset[0] + g[1] * offset[1])
+}
+
+/// 2D simplex noise, in [-1, 1]. The plane is skewed so its triangles become half squares,
+/// the integer cell and the order of the offsets give the three corners around the point,
+/// then the contributions of their gradients are summed.
+pub fn simplex_noise_2d(x: f32, y: f32) -> f32 {
+    // Skew and unskew factors, (sqrt(3) - 1) / 2 and (3 - sqrt(3)) / 6
+    const F2: f32 = 0.366_025_4;
+    const G2: f32 = 0.211_324_87;
+
+    let skew = (x + y) * F2;
+    let (i, j) = ((x + skew).floor() as i32, (y + skew).floor() as i32);
+    let unskew = (i + j) as f32 * G2;
+    let p0 = [x - (i as f32 - unskew), y - (j as f32 - unskew)];
+
+    // Lower or upper triangle of the square
+    let (i1, j1) = if p0[0] > p0[1] { (1, 0) } else { (0, 1) };
+    let p1 = [p0[0] - i1 as f32 + G2, p0[1] - j1 as f32 + G2];
+    let p2 = [p0[0] - 1.0 + 2.0 * G2, p0[1] - 1.0 + 2.0 * G2];
+
+    let n0 = corner_2d(p0, hash(i + hash(j) as i32));
+    let n1 = corner_2d(p1, hash(i + i1 + hash(j + j1) as i32));
+    let n2 = corner_2d(p2, hash(i + 1 + hash(j + 1) as i32));
+    // Scales the extrema to about +-1
+    (70.0 * (n0 + n1 + n2)).clamp(-1.0, 1.0)
+}
+
+/// Contribution of a simplex corner at `offset` from the point, zero past its radius.
+/// The radius is 0.5 rather than the 0.6 of Gustavson's reference, whose kernels reach
+/// past the opposite face of the tetrahedron and leave small steps in the noise there.
+fn corner_3d(offset: [f32; 3], index: usize) -> f32 {
+    let t = 0.5 - offset[0] * offset[0] - offset[1] * offset[1] - offset[2] * offset[2];
+    if t <= 0.0 {
+        return 0.0;
+    }
+    let g = gradient(index);
+    t.powi(4) * (g[0] * offset[0] + g[1] * offset[1] + g[2] * offset[2])
+}
+
+/// 3D simplex noise, in [-1, 1]. Same as `simplex_noise_2d` with tetrahedra, of which
+/// there are six in each skewed cube, picked by the order of the offsets.
+// Nothing animates the noise through a third dimension yet
+#[allow(dead_code)]
+pub fn simplex_noise_3d(x: f32, y: f32, z: f32) -> f32 {
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 6.0;
+
+    let skew = (x + y + z) * F3;
+    let cell = [
+        (x + skew).floor() as i32,
+        (y + skew).floor() as i32,
+        (z + skew).floor() as i32,
+    ];
+    let unskew = (cell[0] + cell[1] + cell[2]) as f32 * G3;
+    let p0 = [
+        x - (cell[0] as f32 - unskew),
+        y - (cell[1] as f32 - unskew),
+        z - (cell[2] as f32 - unskew),
+    ];
+
+    // Second and third corners, stepping along the largest offsets first
+    let (second, third) = if p0[0] >= p0[1] {
+        if p0[1] >= p0[2] {
+            ([1, 0, 0], [1, 1, 0])
+        } else if p0[0] >= p0[2] {
+            ([1, 0, 0], [1, 0, 1])
+        } else {
+            ([0, 0, 1], [1, 0, 1])
+        }
+    } else if p0[1] < p0[2] {
+        ([0, 0, 1], [0, 1, 1])
+    } else if p0[0] < p0[2] {
+        ([0, 1, 0], [0, 1, 1])
+    } else {
+        ([0, 1, 0], [1, 1, 0])
+    };
+
+    let corner = |step: [i32; 3]| {
+        let corners = (step[0] + step[1] + step[2]) as f32;
+        let offset = [
+            p0[0] - step[0] as f32 + corners * G3,
+            p0[1] - step[1] as f32 + corners * G3,
+            p0[2] - step[2] as f32 + corners * G3,
+        ];
+        let index = hash(
+            cell[0] + step[0] + hash(cell[1] + step[1] + hash(cell[2] + step[2]) as i32) as i32,
+        );
+        corner_3d(offset, index)
+    };
+    let sum = corner([0, 0, 0]) + corner(second) + corner(third) + corner([1, 1, 1]);
+    // Scales the extrema to about +-1
+    (76.0 * sum).clamp(-1.0, 1.0)
+}
+
+/// Fractal Brownian motion of 2D simplex noise: `octaves` layers, each `lacunarity` times
+/// the frequency and `persistence` times the amplitude of the previous one.
+/// Normalized by the total amplitude, so it stays in [-1, 1].
+pub fn fbm_simplex(x: f32, y: f32, octaves: u32, persistence: f32, lacunarity: f32) -> f32 {
+    let mut sum = 0.0;
+    let mut total_amplitude = 0.0;
+    let (mut frequency, mut amplitude) = (1.0, 1.0);
+    for _ in 0..octaves.max(1) {
+        sum += simplex_noise_2d(x * frequency, y * frequency) * amplitude;
+        total_amplitude += amplitude;
+        frequency *= lacunarity;
+        amplitude *= persistence;
+    }
+    if total_amplitude > 0.0 {
+        sum / total_amplitude
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points along a line through a few dozen cells, not aligned with the lattice
+    fn line(step: f32) -> impl Iterator<Item = f32> {
+        (0..60_000).map(move |i| -3.0 + i as f32 * step)
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        for t in line(1e-3) {
+            let (x, y, z) = (t * 7.3 + 0.1, t * -4.1 + 2.9, t * 2.2 - 0.7);
+            assert!((-1.0..=1.0).contains(&simplex_noise_2d(x, y)));
+            assert!((-1.0..=1.0).contains(&simplex_noise_3d(x, y, z)));
+            assert!((-1.0..=1.0).contains(&fbm_simplex(x, y, 6, 0.5, 2.0)));
+        }
+    }
+
+    #[test]
+    fn noise_is_zero_at_the_origin() {
+        assert_eq!(simplex_noise_2d(0.0, 0.0), 0.0);
+        assert_eq!(simplex_noise_3d(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(fbm_simplex(0.0, 0.0, 4, 0.5, 2.0), 0.0);
+    }
+
+    /// Bounded first and second differences: no steps in the noise, and no kinks in its
+    /// gradient, which would show up as second differences of the order of `1 / h`
+    fn assert_smooth(noise: impl Fn(f32) -> f32) {
+        let h = 1e-3;
+        for t in line(1e-4) {
+            let (before, at, after) = (noise(t - h), noise(t), noise(t + h));
+            let slope = (after - at).abs() / h;
+            let curvature = (after - 2.0 * at + before).abs() / (h * h);
+            assert!(slope < 12.0, "slope of {slope} at {t}");
+            assert!(curvature < 100.0, "curvature of {curvature} at {t}");
+        }
+    }
+
+    #[test]
+    fn noise_gradient_is_continuous() {
+        assert_smooth(|t| simplex_noise_2d(0.9 * t + 0.1, 0.4 * t - 2.3));
+        assert_smooth(|t| simplex_noise_3d(0.9 * t + 0.1, 0.4 * t - 2.3, 0.3 * t + 0.7));
+    }
+}
@@ -0,0 +1,506 @@
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
+
+use anyhow::ensure;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::image::{
+    apply_adaptive_histogram_equalization, apply_bilateral_filter_into, apply_bokeh_blur_into,
+    apply_border, apply_cel_shade, apply_channel_remap, apply_chromatic_aberration_into,
+    apply_contour_overlay, apply_crosshatch, apply_fisheye_distortion_into, apply_gain_offset,
+    apply_grid_warp_into, apply_highlight_recovery, apply_histogram_equalization, apply_hue_rotate,
+    apply_invert, apply_invert_luminance, apply_kaleidoscope_into, apply_levels,
+    apply_levels_per_channel, apply_longitudinal_aberration, apply_night_vision,
+    apply_polar_to_rect_into, apply_pseudo_dof_into, apply_rect_to_polar_into, apply_shadow_lift,
+    apply_smart_sharpen, apply_split_toning, apply_stained_glass, apply_thermal, apply_watercolor,
+    ChromaticAberrationMode, EqualizationMode, InvertMode, PolarMode, PostProcessEffect,
+    PostProcessSettings, CLAHE_CLIP_LIMIT, CLAHE_GRID_SIZE,
+};
+
+/// One step of the frame, reading what the previous one wrote
+pub(crate) trait RenderPass {
+    /// Write the result of the pass on `input`, both linear RGBA, to `output`
+    fn execute(&self, input: &[f32], output: &mut [f32], width: usize, height: usize);
+
+    /// False when the pass would only copy its input, so it's skipped
+    fn is_active(&self) -> bool {
+        true
+    }
+}
+
+/// A render mode drawing its image over the cleared buffer it gets as input
+pub(crate) struct RenderModePass<'a>(pub Box<dyn Fn(&mut [f32], usize, usize) + 'a>);
+
+impl RenderPass for RenderModePass<'_> {
+    fn execute(&self, input: &[f32], output: &mut [f32], width: usize, height: usize) {
+        output.copy_from_slice(input);
+        (self.0)(output, width, height);
+    }
+}
+
+/// What the post-process passes read besides the image. Shared by all of them,
+/// so the passes don't need to be built again when a setting changes.
+#[derive(Default)]
+pub(crate) struct PassInputs {
+    pub settings: PostProcessSettings,
+    /// Depth of every pixel, for the bokeh
+    pub depth_buffer: Option<Vec<f32>>,
+    /// RGBA displacement of every pixel, for the grid warp
+    pub warp_map: Option<Vec<f32>>,
+}
+
+/// Runs one of the post-process effects, with the settings of the current frame
+struct PostProcessPass {
+    effect: PostProcessEffect,
+    inputs: Rc<RefCell<PassInputs>>,
+}
+
+impl PostProcessPass {
+    /// Run the effects that sample the image away from the pixel they write, straight from
+    /// `input` to `output`. Returns false for the other effects.
+    fn resample(
+        &self,
+        inputs: &PassInputs,
+        input: &[f32],
+        output: &mut [f32],
+        width: usize,
+        height: usize,
+    ) -> bool {
+        let settings = &inputs.settings;
+        match self.effect {
+            PostProcessEffect::Bokeh => match &inputs.depth_buffer {
+                Some(depth_buffer) => apply_bokeh_blur_into(
+                    input,
+                    output,
+                    width,
+                    height,
+                    depth_buffer,
+                    settings.bokeh_focus_depth,
+                    settings.bokeh_aperture,
+                    settings.bokeh_max_radius,
+                    settings.bokeh_shape,
+                ),
+                None => output.copy_from_slice(input),
+            },
+            PostProcessEffect::GridWarp => match &inputs.warp_map {
+                Some(warp_map) => apply_grid_warp_into(
+                    input,
+                    output,
+                    width,
+                    height,
+                    warp_map,
+                    settings.grid_warp_scale,
+                ),
+                None => output.copy_from_slice(input),
+            },
+            PostProcessEffect::TiltShift => apply_pseudo_dof_into(
+                input,
+                output,
+                width,
+                height,
+                settings.tilt_shift_focus,
+                settings.tilt_shift_aperture,
+            ),
+            PostProcessEffect::NoiseReduction => apply_bilateral_filter_into(
+                input,
+                output,
+                width,
+                height,
+                settings.noise_reduction_spatial_sigma,
+                settings.noise_reduction_range_sigma,
+            ),
+            PostProcessEffect::Kaleidoscope => apply_kaleidoscope_into(
+                input,
+                output,
+                width,
+                height,
+                settings.kaleidoscope_segments,
+                settings.kaleidoscope_angle,
+                settings.kaleidoscope_zoom,
+                settings.kaleidoscope_mode,
+            ),
+            PostProcessEffect::PolarCoordinates => {
+                let transform = match settings.polar_mode {
+                    PolarMode::RectToPolar => apply_rect_to_polar_into,
+                    PolarMode::PolarToRect => apply_polar_to_rect_into,
+                };
+                transform(
+                    input,
+                    output,
+                    width,
+                    height,
+                    settings.polar_center,
+                    settings.polar_angle_offset,
+                );
+            }
+            PostProcessEffect::Fisheye => apply_fisheye_distortion_into(
+                input,
+                output,
+                width,
+                height,
+                settings.fisheye_fov,
+                settings.fisheye_strength,
+                settings.fisheye_mode,
+            ),
+            PostProcessEffect::ChromaticAberration
+                if settings.chromatic_mode == ChromaticAberrationMode::Transverse =>
+            {
+                // Red and blue move apart, green stays in place
+                let shift = settings.chromatic_max_shift;
+                let shift_r = if settings.chromatic_radial {
+                    [shift, shift]
+                } else {
+                    let (sin, cos) = settings.chromatic_angle.to_radians().sin_cos();
+                    [cos * shift, sin * shift]
+                };
+                apply_chromatic_aberration_into(
+                    input,
+                    output,
+                    width,
+                    height,
+                    shift_r,
+                    shift_r.map(|s| -s),
+                    settings.chromatic_radial,
+                );
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Run the effects that only change the pixels of `buffer` in place
+    fn apply_in_place(
+        &self,
+        settings: &PostProcessSettings,
+        buffer: &mut [f32],
+        width: usize,
+        height: usize,
+    ) {
+        match self.effect {
+            PostProcessEffect::HighlightRecovery => {
+                apply_highlight_recovery(buffer, width, height, settings.highlight_recovery)
+            }
+            PostProcessEffect::Levels => {
+                let levels = &settings.levels;
+                if levels.per_channel {
+                    apply_levels_per_channel(buffer, width, height, &levels.channels);
+                } else {
+                    let master = &levels.master;
+                    apply_levels(
+                        buffer,
+                        width,
+                        height,
+                        master.input_black,
+                        master.input_white,
+                        master.gamma,
+                        master.output_black,
+                        master.output_white,
+                    );
+                }
+            }
+            PostProcessEffect::Cdl => {
+                let cdl = &settings.cdl;
+                apply_gain_offset(buffer, width, height, cdl.gain, cdl.offset);
+                apply_shadow_lift(buffer, width, height, cdl.lift, cdl.pivot);
+            }
+            PostProcessEffect::ChannelRemap => {
+                apply_channel_remap(buffer, width, height, settings.channel_remap)
+            }
+            PostProcessEffect::Invert => match settings.invert_mode {
+                InvertMode::Color => apply_invert(buffer, width, height, settings.invert_max_value),
+                InvertMode::Luminance => apply_invert_luminance(buffer, width, height),
+            },
+            PostProcessEffect::SplitTone => {
+                let tone = &settings.split_tone;
+                apply_split_toning(
+                    buffer,
+                    width,
+                    height,
+                    tone.shadow_hue,
+                    tone.shadow_saturation,
+                    tone.highlight_hue,
+                    tone.highlight_saturation,
+                    tone.balance,
+                );
+            }
+            PostProcessEffect::HueRotate => {
+                apply_hue_rotate(buffer, width, height, settings.hue_rotation)
+            }
+            PostProcessEffect::ChromaticAberration => {
+                apply_longitudinal_aberration(buffer, width, height, settings.chromatic_max_shift)
+            }
+            PostProcessEffect::SmartSharpen => apply_smart_sharpen(
+                buffer,
+                width,
+                height,
+                settings.sharpen_strength,
+                settings.sharpen_radius,
+                settings.sharpen_reduce_halos,
+            ),
+            PostProcessEffect::HistogramEqualization => match settings.equalization_mode {
+                EqualizationMode::Global => apply_histogram_equalization(
+                    buffer,
+                    width,
+                    height,
+                    settings.equalization_strength,
+                ),
+                EqualizationMode::AdaptiveHE => apply_adaptive_histogram_equalization(
+                    buffer,
+                    width,
+                    height,
+                    settings.equalization_strength,
+                    CLAHE_GRID_SIZE,
+                    CLAHE_CLIP_LIMIT,
+                ),
+            },
+            PostProcessEffect::CelShade => apply_cel_shade(
+                buffer,
+                width,
+                height,
+                settings.cel_shade_tones,
+                settings.cel_shade_edge_threshold,
+                settings.cel_shade_edge_color,
+                settings.cel_shade_mode,
+            ),
+            PostProcessEffect::StainedGlass => apply_stained_glass(
+                buffer,
+                width,
+                height,
+                settings.stained_glass_regions,
+                settings.stained_glass_border_thickness,
+                settings.stained_glass_border_color,
+                settings.stained_glass_seed,
+            ),
+            PostProcessEffect::Crosshatch => apply_crosshatch(
+                buffer,
+                width,
+                height,
+                settings.crosshatch_spacing,
+                settings.crosshatch_line_width,
+                settings.crosshatch_angles[0],
+                settings.crosshatch_angles[1],
+                settings.crosshatch_dark_threshold,
+            ),
+            PostProcessEffect::Watercolor => apply_watercolor(
+                buffer,
+                width,
+                height,
+                settings.watercolor_steps,
+                settings.watercolor_paper,
+                settings.watercolor_granulation,
+                settings.watercolor_wetness,
+            ),
+            PostProcessEffect::Thermal => apply_thermal(
+                buffer,
+                width,
+                height,
+                settings.thermal_cold_color,
+                settings.thermal_hot_color,
+                settings.thermal_colormap,
+                settings.thermal_noise,
+            ),
+            PostProcessEffect::NightVision => {
+                apply_night_vision(buffer, width, height, &settings.night_vision)
+            }
+            PostProcessEffect::Contour => apply_contour_overlay(
+                buffer,
+                width,
+                height,
+                settings.contour_step,
+                settings.contour_color,
+                settings.contour_width,
+                settings.contour_mode,
+            ),
+            PostProcessEffect::Border => apply_border(
+                buffer,
+                width,
+                height,
+                settings.border_width,
+                settings.border_color,
+                settings.border_style,
+            ),
+            // Run by `resample`
+            PostProcessEffect::Bokeh
+            | PostProcessEffect::GridWarp
+            | PostProcessEffect::TiltShift
+            | PostProcessEffect::NoiseReduction
+            | PostProcessEffect::Kaleidoscope
+            | PostProcessEffect::PolarCoordinates
+            | PostProcessEffect::Fisheye => {}
+        }
+    }
+}
+
+impl RenderPass for PostProcessPass {
+    fn execute(&self, input: &[f32], output: &mut [f32], width: usize, height: usize) {
+        let inputs = self.inputs.borrow();
+        if !self.resample(&inputs, input, output, width, height) {
+            output.copy_from_slice(input);
+            self.apply_in_place(&inputs.settings, output, width, height);
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        let inputs = self.inputs.borrow();
+        inputs.settings.is_enabled(self.effect)
+            && match self.effect {
+                PostProcessEffect::Bokeh => inputs.depth_buffer.is_some(),
+                PostProcessEffect::GridWarp => inputs.warp_map.is_some(),
+                _ => true,
+            }
+    }
+}
+
+/// Place of a post-process effect in the pass list of the GUI, saved with the scene
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub(crate) struct PassSlot {
+    pub effect: PostProcessEffect,
+    /// Off to skip the effect without losing its settings
+    pub enabled: bool,
+}
+
+pub(crate) fn default_pass_order() -> Vec<PassSlot> {
+    PostProcessEffect::ALL
+        .iter()
+        .map(|&effect| PassSlot {
+            effect,
+            enabled: true,
+        })
+        .collect()
+}
+
+/// Fail unless `order` has exactly one slot for every effect
+pub(crate) fn check_pass_order(order: &[PassSlot]) -> anyhow::Result<()> {
+    for effect in PostProcessEffect::ALL {
+        let count = order.iter().filter(|slot| slot.effect == effect).count();
+        ensure!(
+            count == 1,
+            "{} is in the pass order {count} times, expected once",
+            effect.label()
+        );
+    }
+    Ok(())
+}
+
+/// The passes of a frame. The render mode comes first, drawing over a cleared buffer into
+/// the framebuffer, where strokes are then painted. The post-process effects follow in the
+/// order of the pass list, and run again on every frame without drawing the image again.
+/// Whatever the number of effects, they alternate between the same two buffers.
+pub(crate) struct RenderPassStack {
+    inputs: Rc<RefCell<PassInputs>>,
+    /// Built once, and again only when the order changes
+    effects: Vec<Box<dyn RenderPass>>,
+    ping: Vec<f32>,
+    pong: Vec<f32>,
+}
+
+impl Default for RenderPassStack {
+    fn default() -> Self {
+        let mut stack = Self {
+            inputs: Rc::default(),
+            effects: Vec::new(),
+            ping: Vec::new(),
+            pong: Vec::new(),
+        };
+        stack.set_order(&default_pass_order());
+        stack
+    }
+}
+
+impl RenderPassStack {
+    /// Build the post-process passes in `order`, leaving out the disabled slots
+    pub(crate) fn set_order(&mut self, order: &[PassSlot]) {
+        self.effects = order
+            .iter()
+            .filter(|slot| slot.enabled)
+            .map(|slot| {
+                Box::new(PostProcessPass {
+                    effect: slot.effect,
+                    inputs: Rc::clone(&self.inputs),
+                }) as Box<dyn RenderPass>
+            })
+            .collect();
+    }
+
+    /// Settings, depth buffer and warp map the post-process passes read
+    pub(crate) fn inputs_mut(&self) -> RefMut<'_, PassInputs> {
+        self.inputs.borrow_mut()
+    }
+
+    /// First pass: draw the image of `render_mode` over `cleared`, into `framebuffer`.
+    /// The pass is dropped once done, along with whatever it borrows.
+    pub(crate) fn render(
+        &self,
+        render_mode: impl RenderPass,
+        cleared: &[f32],
+        framebuffer: &mut [f32],
+        width: usize,
+        height: usize,
+    ) {
+        render_mode.execute(cleared, framebuffer, width, height);
+    }
+
+    /// The other passes: run the active post-process effects on `buffer`, holding the image
+    /// of the render mode, and replace it with the output of the last one
+    pub(crate) fn post_process(&mut self, buffer: &mut [f32], width: usize, height: usize) {
+        let mut active = self.effects.iter().filter(|pass| pass.is_active());
+        let Some(first) = active.next() else {
+            return;
+        };
+        // Kept across frames, only allocated again if the buffer size changes
+        self.ping.resize(buffer.len(), 0.0);
+        self.pong.resize(buffer.len(), 0.0);
+        first.execute(buffer, &mut self.ping, width, height);
+        for pass in active {
+            pass.execute(&self.ping, &mut self.pong, width, height);
+            std::mem::swap(&mut self.ping, &mut self.pong);
+        }
+        buffer.copy_from_slice(&self.ping);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_run_in_the_pass_order() {
+        let (width, height) = (4, 2);
+        let image: Vec<f32> = (0..width * height * 4).map(|i| i as f32 / 32.0).collect();
+        let mut stack = RenderPassStack::default();
+        {
+            let mut inputs = stack.inputs_mut();
+            inputs.settings.invert_enabled = true;
+            inputs.settings.invert_max_value = 1.0;
+            inputs.settings.levels.enabled = true;
+            inputs.settings.levels.master.output_white = 0.5;
+        }
+        // Invert before the levels, against the default order
+        let mut order = default_pass_order();
+        let invert = order
+            .iter()
+            .position(|slot| slot.effect == PostProcessEffect::Invert)
+            .unwrap();
+        let slot = order.remove(invert);
+        order.insert(0, slot);
+        stack.set_order(&order);
+
+        let mut buffer = image.clone();
+        stack.post_process(&mut buffer, width, height);
+
+        let mut expected = image.clone();
+        apply_invert(&mut expected, width, height, 1.0);
+        apply_levels(&mut expected, width, height, 0.0, 1.0, 1.0, 0.0, 0.5);
+        assert_eq!(buffer, expected);
+
+        // A disabled slot is skipped, its settings stay on
+        order[0].enabled = false;
+        stack.set_order(&order);
+        let mut buffer = image.clone();
+        stack.post_process(&mut buffer, width, height);
+        let mut expected = image;
+        apply_levels(&mut expected, width, height, 0.0, 1.0, 1.0, 0.0, 0.5);
+        assert_eq!(buffer, expected);
+    }
+}
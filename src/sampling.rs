@@ -0,0 +1,311 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// 64x64 blue noise tile, one threshold byte per pixel, generated with void-and-cluster
+const BLUE_NOISE_TILE: &[u8; BLUE_NOISE_SIZE * BLUE_NOISE_SIZE] =
+    include_bytes!("../assets/blue_noise_64.bin");
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Which sequence to use when jittering samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum SamplerKind {
+    /// Pseudo random, white noise
+    Uniform,
+    /// Low discrepancy sequence
+    Halton,
+    /// Spatially decorrelated noise from a precomputed tile
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// Sub-pixel offset in [0, 1) for the pixel at `x`, `y`
+    pub fn sample_pixel(&self, x: u32, y: u32, width: u32) -> [f32; 2] {
+        match self {
+            SamplerKind::Uniform => {
+                let seed = y * width + x;
+                [hash_to_unit(seed, 0), hash_to_unit(seed, 1)]
+            }
+            SamplerKind::Halton => HaltonSampler::new(2, 3, 1).sample(y * width + x),
+            SamplerKind::BlueNoise => BlueNoiseSampler.sample(x, y),
+        }
+    }
+}
+
+/// Radical inverse of `index` in the given base, the building block of the Halton sequence
+pub fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut result = 0.0_f64;
+    while index > 0 {
+        result += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+    result as f32
+}
+
+/// 2D Halton sequence. The two bases must be coprime (e.g. 2 and 3),
+/// otherwise the two dimensions end up correlated.
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    bases: [u32; 2],
+    index: u32,
+}
+
+impl HaltonSampler {
+    /// Create a sampler, skipping the first `skip` points of the sequence
+    /// (the first point is always the origin)
+    pub fn new(base_a: u32, base_b: u32, skip: u32) -> Self {
+        Self {
+            bases: [base_a, base_b],
+            index: skip,
+        }
+    }
+
+    /// Point `index` of the sequence, relative to the skipped start
+    pub fn sample(&self, index: u32) -> [f32; 2] {
+        let index = self.index.wrapping_add(index);
+        [
+            radical_inverse(index, self.bases[0]),
+            radical_inverse(index, self.bases[1]),
+        ]
+    }
+}
+
+impl Iterator for HaltonSampler {
+    type Item = [f32; 2];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.sample(0);
+        self.index = self.index.wrapping_add(1);
+        Some(sample)
+    }
+}
+
+/// Blue noise sampler, tiling a precomputed 64x64 texture over the image
+#[derive(Debug, Clone, Copy)]
+pub struct BlueNoiseSampler;
+
+impl BlueNoiseSampler {
+    /// Sample for the pixel at `x`, `y`.
+    /// The second dimension reads the tile at a half-tile offset so the two are decorrelated.
+    pub fn sample(&self, x: u32, y: u32) -> [f32; 2] {
+        let half = BLUE_NOISE_SIZE / 2;
+        [
+            Self::threshold(x as usize, y as usize),
+            Self::threshold(x as usize + half, y as usize + half),
+        ]
+    }
+
+    fn threshold(x: usize, y: usize) -> f32 {
+        let index = (y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE;
+        // Center each of the 256 levels in its bucket
+        (BLUE_NOISE_TILE[index] as f32 + 0.5) / 256.0
+    }
+}
+
+/// Hash an integer into a float in [0, 1), used for cheap reproducible white noise
+pub fn hash_to_unit(value: u32, seed: u32) -> f32 {
+    // PCG output permutation
+    let state = value
+        .wrapping_mul(747_796_405)
+        .wrapping_add(2_891_336_453)
+        .wrapping_add(seed.wrapping_mul(0x9E37_79B9));
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+    let hash = (word >> 22) ^ word;
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Small seedable pseudo random generator (SplitMix64), for reproducible procedural content
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1_u64 << 24) as f32
+    }
+
+    /// Normally distributed float with a mean of 0 and a standard deviation of 1 (Box-Muller)
+    pub fn next_gaussian(&mut self) -> f32 {
+        // Avoid ln(0)
+        let u = 1.0 - self.next_f32();
+        let v = self.next_f32();
+        (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos()
+    }
+}
+
+/// Poisson disk samples covering a `width` x `height` area, no two closer than `min_distance`,
+/// using Bridson's algorithm. Each active sample tries `max_attempts` random candidates in the
+/// annulus between `min_distance` and twice that before it's retired.
+/// Returns UV coordinates in [0, 1]².
+pub fn generate_poisson_disk_samples(
+    width: f32,
+    height: f32,
+    min_distance: f32,
+    max_attempts: u32,
+    seed: u64,
+) -> Vec<[f32; 2]> {
+    let min_distance = min_distance.max(f32::EPSILON);
+    // Cells small enough to hold at most one sample
+    let cell_size = min_distance / std::f32::consts::SQRT_2;
+    let columns = (width / cell_size).ceil().max(1.0) as usize;
+    let rows = (height / cell_size).ceil().max(1.0) as usize;
+    let cell_of = |p: [f32; 2]| {
+        let column = ((p[0] / cell_size) as usize).min(columns - 1);
+        let row = ((p[1] / cell_size) as usize).min(rows - 1);
+        (column, row)
+    };
+
+    // Index into `samples` of the sample in each cell
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+    let mut samples: Vec<[f32; 2]> = Vec::new();
+    let mut active: Vec<usize> = Vec::new();
+    let mut rng = SplitMix64::new(seed);
+
+    let first = [rng.next_f32() * width, rng.next_f32() * height];
+    let (column, row) = cell_of(first);
+    grid[row * columns + column] = Some(0);
+    samples.push(first);
+    active.push(0);
+
+    while !active.is_empty() {
+        let active_index = (rng.next_u64() % active.len() as u64) as usize;
+        let center = samples[active[active_index]];
+
+        let mut found = false;
+        for _ in 0..max_attempts {
+            let angle = rng.next_f32() * std::f32::consts::TAU;
+            // Uniform over the annulus area
+            let radius = min_distance * (1.0 + 3.0 * rng.next_f32()).sqrt();
+            let candidate = [
+                center[0] + radius * angle.cos(),
+                center[1] + radius * angle.sin(),
+            ];
+            if !(0.0..width).contains(&candidate[0]) || !(0.0..height).contains(&candidate[1]) {
+                continue;
+            }
+
+            // Only the 5x5 neighbouring cells can hold samples closer than `min_distance`
+            let (column, row) = cell_of(candidate);
+            let too_close = (row.saturating_sub(2)..(row + 3).min(rows)).any(|neighbour_row| {
+                (column.saturating_sub(2)..(column + 3).min(columns)).any(|neighbour_column| {
+                    grid[neighbour_row * columns + neighbour_column].is_some_and(|index| {
+                        let other = samples[index];
+                        (other[0] - candidate[0]).hypot(other[1] - candidate[1]) < min_distance
+                    })
+                })
+            });
+            if too_close {
+                continue;
+            }
+
+            grid[row * columns + column] = Some(samples.len());
+            active.push(samples.len());
+            samples.push(candidate);
+            found = true;
+            break;
+        }
+
+        if !found {
+            active.swap_remove(active_index);
+        }
+    }
+
+    samples
+        .into_iter()
+        .map(|[x, y]| [x / width, y / height])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Star discrepancy of points in [0, 1)², estimated over the boxes anchored at the origin
+    /// whose far corner lies on a `resolution` x `resolution` grid: the largest difference
+    /// between the fraction of points inside a box and the box's area
+    fn star_discrepancy(points: &[[f32; 2]], resolution: usize) -> f32 {
+        let mut worst = 0.0_f32;
+        for i in 1..=resolution {
+            for j in 1..=resolution {
+                let corner = [i as f32 / resolution as f32, j as f32 / resolution as f32];
+                let inside = points
+                    .iter()
+                    .filter(|p| p[0] < corner[0] && p[1] < corner[1])
+                    .count();
+                let fraction = inside as f32 / points.len() as f32;
+                worst = worst.max((fraction - corner[0] * corner[1]).abs());
+            }
+        }
+        worst
+    }
+
+    /// White noise jitter for the pixels of a `width` x `height` image
+    fn uniform_samples(width: u32, height: u32) -> Vec<[f32; 2]> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| SamplerKind::Uniform.sample_pixel(x, y, width)))
+            .collect()
+    }
+
+    #[test]
+    fn halton_has_lower_discrepancy_than_white_noise() {
+        let halton: Vec<[f32; 2]> = HaltonSampler::new(2, 3, 1).take(1024).collect();
+        let halton = star_discrepancy(&halton, 32);
+        let uniform = star_discrepancy(&uniform_samples(32, 32), 32);
+        assert!(halton < 0.01, "Halton discrepancy {halton}");
+        assert!(halton < uniform, "Halton {halton}, uniform {uniform}");
+    }
+
+    #[test]
+    fn blue_noise_has_lower_discrepancy_than_white_noise() {
+        let size = BLUE_NOISE_SIZE as u32;
+        let blue_noise: Vec<[f32; 2]> = (0..size)
+            .flat_map(|y| (0..size).map(move |x| BlueNoiseSampler.sample(x, y)))
+            .collect();
+        let blue_noise = star_discrepancy(&blue_noise, 32);
+        let uniform = star_discrepancy(&uniform_samples(size, size), 32);
+        assert!(blue_noise < 0.012, "blue noise discrepancy {blue_noise}");
+        assert!(
+            blue_noise < uniform,
+            "blue noise {blue_noise}, uniform {uniform}"
+        );
+    }
+
+    #[test]
+    fn poisson_disk_samples_keep_their_distance() {
+        let (width, height, min_distance) = (200.0, 120.0, 7.5);
+        let samples: Vec<[f32; 2]> =
+            generate_poisson_disk_samples(width, height, min_distance, 30, 3)
+                .into_iter()
+                .map(|[u, v]| [u * width, v * height])
+                .collect();
+        assert!(samples.len() > 100, "only {} samples", samples.len());
+
+        for (i, a) in samples.iter().enumerate() {
+            assert!((0.0..=width).contains(&a[0]) && (0.0..=height).contains(&a[1]));
+            for b in &samples[i + 1..] {
+                let distance = (a[0] - b[0]).hypot(a[1] - b[1]);
+                // Leave room for the round trip through UV coordinates
+                assert!(
+                    distance >= min_distance * 0.9999,
+                    "{a:?} and {b:?} are {distance} apart"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,1487 @@
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::effects::MandelbrotView;
+use crate::image::{
+    BokehShape, BorderStyle, BrdfGridSettings, BrickSettings, CameraSettings, CdlParams,
+    CelShadeMode, ChannelRemap, ChromaticAberrationMode, ColorWheelMode, ContourMode,
+    EqualizationMode, FisheyeMode, FractalFlameSettings, GradientAnchor, GrungeParams, InvertMode,
+    KaleidoscopeMode, LevelsParams, LissajousSettings, NightVisionParams, OscilloscopeSettings,
+    PcbSettings, PoissonDiskSettings, PolarMode, PostProcessEffect, PostProcessSettings,
+    RaymarcherSettings, SdfPrimitive, SkySettings, SphericalHarmonics9, SpiralSettings,
+    SplitToneParams, StarfieldSettings, TerrainParams, TextureQuiltingSettings, ThermalColormap,
+    TruchetSettings, TurbulenceSettings, ValueNoiseSettings, WoodGrainSettings, WorleyMetric,
+    WorleySettings, KALEIDOSCOPE_SEGMENTS, MAX_BRDF_GRID_SIZE, MAX_GRADIENT_ANCHORS,
+    MAX_SDF_PRIMITIVES,
+};
+use crate::nodes::{NodeGraph, NodeKind};
+use crate::passes::{check_pass_order, default_pass_order, PassSlot};
+use crate::sampling::SamplerKind;
+use crate::RenderMode;
+
+/// Everything needed to render the current image again, saved as JSON.
+/// Images loaded from files are saved as their paths, see `SceneFiles`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub(crate) struct SceneDescription {
+    pub render_mode: RenderMode,
+    pub tonemapper: TonemapperKind,
+    pub exposure_ev: f32,
+    pub sampler: SamplerKind,
+    // ACEScg, the "First Color" and "Second Color" of the GUI
+    pub color_a: [f32; 3],
+    pub color_b: [f32; 3],
+    /// Settings of `render_mode`, see `ProceduralParams`
+    pub procedural_params: serde_json::Value,
+    /// Effects applied before display, in the order they run
+    pub post_process_stack: Vec<PostProcessStep>,
+    /// Order of the post-process passes, with those turned off in the pass list.
+    /// Scenes saved without it use the default order.
+    #[serde(default = "default_pass_order")]
+    pub pass_order: Vec<PassSlot>,
+    /// Files the loaded images come from, read again when the scene is opened.
+    /// Scenes saved without it have none.
+    #[serde(default)]
+    pub file_paths: SceneFiles,
+}
+
+/// Files the images used by a scene were loaded from. Images made in the application,
+/// like the test warp map, have none.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub(crate) struct SceneFiles {
+    /// Equirectangular HDRI converted to the faces shown by the cubemap cross
+    pub cubemap_hdri: Option<PathBuf>,
+    /// Exemplar of the texture synthesis
+    pub exemplar: Option<PathBuf>,
+    /// Depth buffer of the depth of field
+    pub depth_buffer: Option<PathBuf>,
+    /// Displacement of the grid warp
+    pub warp_map: Option<PathBuf>,
+}
+
+/// Display transform applied after the exposure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub(crate) enum TonemapperKind {
+    /// colstodian's `PerceptualTonemapper` with its default parameters
+    Perceptual,
+}
+
+/// One effect of the post-process stack
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "filter")]
+pub(crate) enum PostProcessStep {
+    ChannelRemap(ChannelRemap),
+    Invert {
+        mode: InvertMode,
+        max_scene_value: f32,
+    },
+    HighlightRecovery {
+        recovery: f32,
+    },
+    Levels(LevelsParams),
+    Cdl(CdlParams),
+    SplitTone(SplitToneParams),
+    HueRotate {
+        angle_degrees: f32,
+        cycle_enabled: bool,
+        cycle_step: f32,
+    },
+    SmartSharpen {
+        strength: f32,
+        radius: f32,
+        reduce_halos: bool,
+    },
+    HistogramEqualization {
+        mode: EqualizationMode,
+        strength: f32,
+    },
+    Border {
+        width: u32,
+        color: [f32; 4],
+        style: BorderStyle,
+    },
+    Contour {
+        luma_step: f32,
+        line_color: [f32; 4],
+        line_width: f32,
+        mode: ContourMode,
+    },
+    NightVision(NightVisionParams),
+    CelShade {
+        num_tones: u32,
+        edge_threshold: f32,
+        edge_color: [f32; 4],
+        mode: CelShadeMode,
+    },
+    StainedGlass {
+        num_regions: usize,
+        border_thickness: u32,
+        border_color: [f32; 4],
+        seed: u64,
+    },
+    Crosshatch {
+        line_spacing: u32,
+        line_width: f32,
+        angles: [f32; 2],
+        dark_threshold: f32,
+    },
+    Watercolor {
+        diffusion_steps: u32,
+        wetness: f32,
+        paper_texture_strength: f32,
+        pigment_granulation: f32,
+    },
+    Thermal {
+        colormap: ThermalColormap,
+        cold_color: [f32; 3],
+        hot_color: [f32; 3],
+        sensor_noise: f32,
+    },
+    Bokeh {
+        focus_depth: f32,
+        aperture: f32,
+        max_radius: f32,
+        shape: BokehShape,
+    },
+    TiltShift {
+        focus_y: f32,
+        aperture: f32,
+    },
+    NoiseReduction {
+        spatial_sigma: f32,
+        range_sigma: f32,
+    },
+    Kaleidoscope {
+        segments: u32,
+        angle_offset: f32,
+        zoom: f32,
+        mode: KaleidoscopeMode,
+    },
+    PolarCoordinates {
+        mode: PolarMode,
+        angle_offset: f32,
+        center: [f32; 2],
+    },
+    Fisheye {
+        fov: f32,
+        strength: f32,
+        mode: FisheyeMode,
+    },
+    ChromaticAberration {
+        mode: ChromaticAberrationMode,
+        max_shift: f32,
+        radial: bool,
+        angle: f32,
+    },
+    GridWarp {
+        scale: f32,
+    },
+}
+
+/// Color wheel settings, which are separate fields of the application state
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ColorWheelParams {
+    pub lightness: f32,
+    pub mode: ColorWheelMode,
+}
+
+/// Typed `procedural_params`. Which variant is stored depends on the render mode,
+/// so the JSON itself doesn't need a tag.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub(crate) enum ProceduralParams {
+    /// Modes without settings of their own
+    None,
+    Mandelbrot(MandelbrotView),
+    ShSphere(SphericalHarmonics9),
+    MultipointGradient(Vec<GradientAnchor>),
+    Lissajous(LissajousSettings),
+    Starfield(StarfieldSettings),
+    // Wavelengths in nm
+    SpectralGradient([f32; 2]),
+    FractalFlame(FractalFlameSettings),
+    ValueNoise(ValueNoiseSettings),
+    Sky(SkySettings),
+    Turbulence(TurbulenceSettings),
+    Bricks(BrickSettings),
+    ColorWheel(ColorWheelParams),
+    WoodGrain(WoodGrainSettings),
+    PoissonDisk(PoissonDiskSettings),
+    Oscilloscope(OscilloscopeSettings),
+    Camera(CameraSettings),
+    Worley(WorleySettings),
+    NodeGraph(NodeGraph),
+    Terrain(TerrainParams),
+    Pcb(PcbSettings),
+    TextureQuilting(TextureQuiltingSettings),
+    Raymarcher(RaymarcherSettings),
+    BrdfSpheres(BrdfGridSettings),
+    Truchet(TruchetSettings),
+    GrungeMap(GrungeParams),
+    Spiral(SpiralSettings),
+}
+
+impl ProceduralParams {
+    /// Read the settings of `render_mode` from its JSON value
+    pub(crate) fn from_value(
+        render_mode: RenderMode,
+        value: serde_json::Value,
+    ) -> serde_json::Result<Self> {
+        use serde_json::from_value;
+
+        Ok(match render_mode {
+            RenderMode::Gradient | RenderMode::CubemapCross => ProceduralParams::None,
+            RenderMode::Mandelbrot => ProceduralParams::Mandelbrot(from_value(value)?),
+            RenderMode::ShSphere => ProceduralParams::ShSphere(from_value(value)?),
+            RenderMode::MultipointGradient => {
+                ProceduralParams::MultipointGradient(from_value(value)?)
+            }
+            RenderMode::Lissajous => ProceduralParams::Lissajous(from_value(value)?),
+            RenderMode::Starfield => ProceduralParams::Starfield(from_value(value)?),
+            RenderMode::SpectralGradient => ProceduralParams::SpectralGradient(from_value(value)?),
+            RenderMode::FractalFlame => ProceduralParams::FractalFlame(from_value(value)?),
+            RenderMode::ValueNoise => ProceduralParams::ValueNoise(from_value(value)?),
+            RenderMode::Sky => ProceduralParams::Sky(from_value(value)?),
+            RenderMode::Turbulence => ProceduralParams::Turbulence(from_value(value)?),
+            RenderMode::Bricks => ProceduralParams::Bricks(from_value(value)?),
+            RenderMode::ColorWheel => ProceduralParams::ColorWheel(from_value(value)?),
+            RenderMode::WoodGrain => ProceduralParams::WoodGrain(from_value(value)?),
+            RenderMode::PoissonDisk => ProceduralParams::PoissonDisk(from_value(value)?),
+            RenderMode::Oscilloscope => ProceduralParams::Oscilloscope(from_value(value)?),
+            RenderMode::Camera => ProceduralParams::Camera(from_value(value)?),
+            RenderMode::Worley => ProceduralParams::Worley(from_value(value)?),
+            RenderMode::NodeGraph => ProceduralParams::NodeGraph(from_value(value)?),
+            RenderMode::Terrain => ProceduralParams::Terrain(from_value(value)?),
+            RenderMode::Pcb => ProceduralParams::Pcb(from_value(value)?),
+            RenderMode::TextureSynthesis => ProceduralParams::TextureQuilting(from_value(value)?),
+            RenderMode::Raymarcher => ProceduralParams::Raymarcher(from_value(value)?),
+            RenderMode::BrdfSpheres => ProceduralParams::BrdfSpheres(from_value(value)?),
+            RenderMode::Truchet => ProceduralParams::Truchet(from_value(value)?),
+            RenderMode::GrungeMap => ProceduralParams::GrungeMap(from_value(value)?),
+            RenderMode::Spiral => ProceduralParams::Spiral(from_value(value)?),
+        })
+    }
+
+    /// Check every setting against the range of the GUI control editing it.
+    /// Renders assume those ranges, a zero `min_distance` alone would try to allocate
+    /// a Poisson disk grid of about 10^18 cells.
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        use std::f32::consts::TAU;
+
+        match self {
+            ProceduralParams::None
+            | ProceduralParams::ShSphere(_)
+            | ProceduralParams::Camera(_) => {}
+            ProceduralParams::Mandelbrot(view) => {
+                ensure!(
+                    view.center.iter().all(|c| c.is_finite()),
+                    "center is {:?}, expected finite coordinates",
+                    view.center
+                );
+                check_range("scale", view.scale, 1e-12..=10.0)?;
+                check_range("max_iterations", view.max_iterations, 1..=10000)?;
+            }
+            ProceduralParams::MultipointGradient(anchors) => {
+                check_range("anchor count", anchors.len(), 0..=MAX_GRADIENT_ANCHORS)?;
+                for (uv, _) in anchors {
+                    check_range("anchor u", uv[0], 0.0..=1.0)?;
+                    check_range("anchor v", uv[1], 0.0..=1.0)?;
+                }
+            }
+            ProceduralParams::Lissajous(lissajous) => {
+                check_range("freq_x", lissajous.freq_x, 1..=20)?;
+                check_range("freq_y", lissajous.freq_y, 1..=20)?;
+                check_range("phase", lissajous.phase, 0.0..=TAU)?;
+                check_range("thickness", lissajous.thickness, 0.5..=5.0)?;
+            }
+            ProceduralParams::Starfield(starfield) => {
+                check_range("num_stars", starfield.num_stars, 100..=50000)?;
+                check_range(
+                    "magnitude_exponent",
+                    starfield.magnitude_exponent,
+                    1.0..=32.0,
+                )?;
+                check_range("bloom_threshold", starfield.bloom_threshold, 0.5..=50.0)?;
+            }
+            ProceduralParams::SpectralGradient([lambda_lo, lambda_hi]) => {
+                check_range("lambda low", *lambda_lo, 360.0..=830.0)?;
+                check_range("lambda high", *lambda_hi, 360.0..=830.0)?;
+            }
+            ProceduralParams::FractalFlame(flame) => {
+                check_range("iterations", flame.iterations, 10_000..=50_000_000)?;
+                check_range("supersample", flame.supersample, 1..=4)?;
+                for transform in &flame.system.transforms {
+                    check_range("probability", transform.probability, 0.0..=10.0)?;
+                    check_range("color_index", transform.color_index, 0.0..=1.0)?;
+                }
+            }
+            ProceduralParams::ValueNoise(noise) => {
+                check_range("frequency", noise.frequency, 0.5..=64.0)?;
+                check_range("octaves", noise.octaves, 1..=8)?;
+            }
+            ProceduralParams::Sky(sky) => {
+                check_range("sun_elevation", sky.sun_elevation, 0.0..=90.0)?;
+                check_range("sun_azimuth", sky.sun_azimuth, 0.0..=360.0)?;
+                check_range("turbidity", sky.turbidity, 1.0..=10.0)?;
+            }
+            ProceduralParams::Turbulence(turbulence) => {
+                check_range("frequency", turbulence.frequency, 0.5..=64.0)?;
+                check_range("octaves", turbulence.octaves, 1..=8)?;
+                check_range("contrast", turbulence.contrast, 0.1..=4.0)?;
+            }
+            ProceduralParams::Bricks(bricks) => {
+                check_range("brick_w", bricks.brick_w, 2.0..=200.0)?;
+                check_range("brick_h", bricks.brick_h, 2.0..=200.0)?;
+                check_range("mortar", bricks.mortar, 0.0..=10.0)?;
+                check_range("offset", bricks.offset, 0.0..=1.0)?;
+                check_range("variation", bricks.variation, 0.0..=0.3)?;
+            }
+            ProceduralParams::ColorWheel(color_wheel) => {
+                check_range("lightness", color_wheel.lightness, 0.0..=1.0)?;
+            }
+            ProceduralParams::WoodGrain(wood) => {
+                check_range("ring_frequency", wood.ring_frequency, 0.5..=10.0)?;
+                check_range("turbulence_scale", wood.turbulence_scale, 0.0..=5.0)?;
+                check_range("grain_angle", wood.grain_angle, 0.0..=180.0)?;
+            }
+            ProceduralParams::PoissonDisk(poisson_disk) => {
+                check_range("min_distance", poisson_disk.min_distance, 1.0..=50.0)?;
+            }
+            ProceduralParams::Oscilloscope(oscilloscope) => {
+                check_range("persistence", oscilloscope.persistence, 0.8..=1.0)?;
+            }
+            ProceduralParams::Worley(worley) => {
+                check_range("frequency", worley.frequency, 0.5..=20.0)?;
+                check_range("jitter", worley.jitter, 0.0..=1.0)?;
+                if let WorleyMetric::Minkowski { p } = worley.metric {
+                    check_range("p", p, 0.5..=8.0)?;
+                }
+            }
+            ProceduralParams::NodeGraph(graph) => {
+                for node in &graph.nodes {
+                    match node.kind {
+                        NodeKind::GaussianBlur { sigma } => {
+                            check_range("sigma", sigma, 0.0..=20.0)?
+                        }
+                        NodeKind::Merge { factor } => check_range("factor", factor, 0.0..=1.0)?,
+                        _ => {}
+                    }
+                }
+            }
+            ProceduralParams::Terrain(terrain) => {
+                check_range("frequency", terrain.frequency, 0.5..=16.0)?;
+                check_range("octaves", terrain.octaves, 1..=10)?;
+                check_range("persistence", terrain.persistence, 0.1..=0.9)?;
+                check_range("lacunarity", terrain.lacunarity, 1.5..=3.0)?;
+                check_range("sea_level", terrain.sea_level, 0.0..=1.0)?;
+                check_range("snow_level", terrain.snow_level, 0.0..=1.0)?;
+                check_range("relief", terrain.relief, 0.0..=5.0)?;
+                check_range("sun_azimuth", terrain.sun_azimuth, 0.0..=360.0)?;
+                check_range("sun_elevation", terrain.sun_elevation, 0.0..=90.0)?;
+                for stop in &terrain.ramp.stops {
+                    check_range("ramp stop position", stop.position, 0.0..=1.0)?;
+                }
+            }
+            ProceduralParams::Pcb(pcb) => {
+                check_range("trace_density", pcb.trace_density, 0.0..=1.0)?;
+                check_range("cell_size", pcb.cell_size, 8..=32)?;
+                check_range("glow_strength", pcb.glow_strength, 0.0..=4.0)?;
+            }
+            ProceduralParams::TextureQuilting(texture_quilting) => {
+                check_range("patch_size", texture_quilting.patch_size, 16..=64)?;
+                check_range(
+                    "overlap_fraction",
+                    texture_quilting.overlap_fraction,
+                    0.1..=0.5,
+                )?;
+            }
+            ProceduralParams::Raymarcher(raymarcher) => {
+                let scene = &raymarcher.scene;
+                check_range("fov", raymarcher.camera.fov, 10.0..=120.0)?;
+                check_range("light intensity", scene.light.intensity, 0.0..=1000.0)?;
+                check_range("smoothness", scene.smoothness, 0.0..=1.0)?;
+                check_range(
+                    "primitive count",
+                    scene.primitives.len(),
+                    0..=MAX_SDF_PRIMITIVES,
+                )?;
+                for primitive in &scene.primitives {
+                    match *primitive {
+                        SdfPrimitive::Sphere { radius, .. } => {
+                            check_range("radius", radius, 0.05..=3.0)?
+                        }
+                        SdfPrimitive::Box { .. } => {}
+                        SdfPrimitive::Torus {
+                            r_major, r_minor, ..
+                        } => {
+                            check_range("r_major", r_major, 0.0..=10.0)?;
+                            check_range("r_minor", r_minor, 0.0..=10.0)?;
+                        }
+                    }
+                }
+            }
+            ProceduralParams::BrdfSpheres(brdf) => {
+                check_range(
+                    "roughness_steps",
+                    brdf.roughness_steps,
+                    1..=MAX_BRDF_GRID_SIZE,
+                )?;
+                check_range(
+                    "metallic_steps",
+                    brdf.metallic_steps,
+                    1..=MAX_BRDF_GRID_SIZE,
+                )?;
+                check_range("light_intensity", brdf.light_intensity, 0.0..=10.0)?;
+            }
+            ProceduralParams::Truchet(truchet) => {
+                check_range("tile_size", truchet.tile_size, 8..=128)?;
+            }
+            ProceduralParams::GrungeMap(grunge) => {
+                check_range("noise_scale", grunge.noise_scale, 4.0..=200.0)?;
+                check_range("scratch_count", grunge.scratch_count, 0..=200)?;
+                check_range("stain_count", grunge.stain_count, 0..=50)?;
+                for length in grunge.scratch_length_range {
+                    check_range("scratch length", length, 1.0..=200.0)?;
+                }
+                for radius in grunge.stain_radius_range {
+                    check_range("stain radius", radius, 1.0..=100.0)?;
+                }
+            }
+            ProceduralParams::Spiral(spiral) => {
+                check_range("a", spiral.a, 0.5..=50.0)?;
+                check_range("b", spiral.b, -0.5..=0.5)?;
+                check_range("num_turns", spiral.num_turns, 0.5..=20.0)?;
+                check_range("phase", spiral.phase, 0.0..=TAU)?;
+                check_range("thickness", spiral.thickness, 0.5..=8.0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SceneDescription {
+    /// Read a scene from a JSON file. The procedural params are checked against the
+    /// render mode and every setting against the range of its GUI control,
+    /// so a scene that loads is also one that can be applied.
+    pub(crate) fn load(path: impl AsRef<Path>) -> anyhow::Result<(Self, ProceduralParams)> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Can't read {}", path.display()))?;
+        let scene: SceneDescription = serde_json::from_str(&contents)
+            .with_context(|| format!("{} is not a valid scene", path.display()))?;
+        let params_context = || {
+            format!(
+                "Invalid procedural_params for the {} render mode in {}",
+                scene.render_mode.label(),
+                path.display()
+            )
+        };
+        let params =
+            ProceduralParams::from_value(scene.render_mode, scene.procedural_params.clone())
+                .with_context(params_context)?;
+        params.validate().with_context(params_context)?;
+        for step in &scene.post_process_stack {
+            step.validate()
+                .with_context(|| format!("Invalid {} step in {}", step.label(), path.display()))?;
+        }
+        check_pass_order(&scene.pass_order)
+            .with_context(|| format!("Invalid pass_order in {}", path.display()))?;
+        Ok((scene, params))
+    }
+
+    /// Write the scene as pretty-printed JSON
+    pub(crate) fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Post-process settings described by the stack. Steps missing from it are turned off.
+    pub(crate) fn post_process(&self) -> PostProcessSettings {
+        PostProcessStep::settings(&self.post_process_stack)
+    }
+}
+
+impl PostProcessStep {
+    /// Post-process settings with only `steps` turned on
+    pub(crate) fn settings(steps: &[PostProcessStep]) -> PostProcessSettings {
+        let mut settings = PostProcessSettings::default();
+        for step in steps {
+            match *step {
+                PostProcessStep::ChannelRemap(remap) => settings.channel_remap = remap,
+                PostProcessStep::SmartSharpen {
+                    strength,
+                    radius,
+                    reduce_halos,
+                } => {
+                    settings.sharpen_strength = strength;
+                    settings.sharpen_radius = radius;
+                    settings.sharpen_reduce_halos = reduce_halos;
+                }
+                PostProcessStep::HistogramEqualization { mode, strength } => {
+                    settings.equalization_mode = mode;
+                    settings.equalization_strength = strength;
+                }
+                PostProcessStep::Border {
+                    width,
+                    color,
+                    style,
+                } => {
+                    settings.border_width = width;
+                    settings.border_color = color;
+                    settings.border_style = style;
+                }
+                PostProcessStep::Contour {
+                    luma_step,
+                    line_color,
+                    line_width,
+                    mode,
+                } => {
+                    settings.contour_step = luma_step;
+                    settings.contour_color = line_color;
+                    settings.contour_width = line_width;
+                    settings.contour_mode = mode;
+                }
+                PostProcessStep::NightVision(params) => settings.night_vision = params,
+                PostProcessStep::HighlightRecovery { recovery } => {
+                    settings.highlight_recovery = recovery
+                }
+                PostProcessStep::Levels(params) => settings.levels = params,
+                PostProcessStep::Cdl(params) => settings.cdl = params,
+                PostProcessStep::SplitTone(params) => settings.split_tone = params,
+                PostProcessStep::HueRotate {
+                    angle_degrees,
+                    cycle_enabled,
+                    cycle_step,
+                } => {
+                    settings.hue_rotation = angle_degrees;
+                    settings.hue_cycle_enabled = cycle_enabled;
+                    settings.hue_cycle_step = cycle_step;
+                }
+                PostProcessStep::CelShade {
+                    num_tones,
+                    edge_threshold,
+                    edge_color,
+                    mode,
+                } => {
+                    settings.cel_shade_enabled = true;
+                    settings.cel_shade_tones = num_tones;
+                    settings.cel_shade_edge_threshold = edge_threshold;
+                    settings.cel_shade_edge_color = edge_color;
+                    settings.cel_shade_mode = mode;
+                }
+                PostProcessStep::Crosshatch {
+                    line_spacing,
+                    line_width,
+                    angles,
+                    dark_threshold,
+                } => {
+                    settings.crosshatch_enabled = true;
+                    settings.crosshatch_spacing = line_spacing;
+                    settings.crosshatch_line_width = line_width;
+                    settings.crosshatch_angles = angles;
+                    settings.crosshatch_dark_threshold = dark_threshold;
+                }
+                PostProcessStep::StainedGlass {
+                    num_regions,
+                    border_thickness,
+                    border_color,
+                    seed,
+                } => {
+                    settings.stained_glass_enabled = true;
+                    settings.stained_glass_regions = num_regions;
+                    settings.stained_glass_border_thickness = border_thickness;
+                    settings.stained_glass_border_color = border_color;
+                    settings.stained_glass_seed = seed;
+                }
+                PostProcessStep::Watercolor {
+                    diffusion_steps,
+                    wetness,
+                    paper_texture_strength,
+                    pigment_granulation,
+                } => {
+                    settings.watercolor_enabled = true;
+                    settings.watercolor_steps = diffusion_steps;
+                    settings.watercolor_wetness = wetness;
+                    settings.watercolor_paper = paper_texture_strength;
+                    settings.watercolor_granulation = pigment_granulation;
+                }
+                PostProcessStep::Thermal {
+                    colormap,
+                    cold_color,
+                    hot_color,
+                    sensor_noise,
+                } => {
+                    settings.thermal_enabled = true;
+                    settings.thermal_colormap = colormap;
+                    settings.thermal_cold_color = cold_color;
+                    settings.thermal_hot_color = hot_color;
+                    settings.thermal_noise = sensor_noise;
+                }
+                PostProcessStep::Bokeh {
+                    focus_depth,
+                    aperture,
+                    max_radius,
+                    shape,
+                } => {
+                    settings.bokeh_enabled = true;
+                    settings.bokeh_focus_depth = focus_depth;
+                    settings.bokeh_aperture = aperture;
+                    settings.bokeh_max_radius = max_radius;
+                    settings.bokeh_shape = shape;
+                }
+                PostProcessStep::Invert {
+                    mode,
+                    max_scene_value,
+                } => {
+                    settings.invert_enabled = true;
+                    settings.invert_mode = mode;
+                    settings.invert_max_value = max_scene_value;
+                }
+                PostProcessStep::TiltShift { focus_y, aperture } => {
+                    settings.tilt_shift_enabled = true;
+                    settings.tilt_shift_focus = focus_y;
+                    settings.tilt_shift_aperture = aperture;
+                }
+                PostProcessStep::NoiseReduction {
+                    spatial_sigma,
+                    range_sigma,
+                } => {
+                    settings.noise_reduction_enabled = true;
+                    settings.noise_reduction_spatial_sigma = spatial_sigma;
+                    settings.noise_reduction_range_sigma = range_sigma;
+                }
+                PostProcessStep::Kaleidoscope {
+                    segments,
+                    angle_offset,
+                    zoom,
+                    mode,
+                } => {
+                    settings.kaleidoscope_enabled = true;
+                    settings.kaleidoscope_segments = segments;
+                    settings.kaleidoscope_angle = angle_offset;
+                    settings.kaleidoscope_zoom = zoom;
+                    settings.kaleidoscope_mode = mode;
+                }
+                PostProcessStep::PolarCoordinates {
+                    mode,
+                    angle_offset,
+                    center,
+                } => {
+                    settings.polar_enabled = true;
+                    settings.polar_mode = mode;
+                    settings.polar_angle_offset = angle_offset;
+                    settings.polar_center = center;
+                }
+                PostProcessStep::Fisheye {
+                    fov,
+                    strength,
+                    mode,
+                } => {
+                    settings.fisheye_enabled = true;
+                    settings.fisheye_fov = fov;
+                    settings.fisheye_strength = strength;
+                    settings.fisheye_mode = mode;
+                }
+                PostProcessStep::ChromaticAberration {
+                    mode,
+                    max_shift,
+                    radial,
+                    angle,
+                } => {
+                    settings.chromatic_enabled = true;
+                    settings.chromatic_mode = mode;
+                    settings.chromatic_max_shift = max_shift;
+                    settings.chromatic_radial = radial;
+                    settings.chromatic_angle = angle;
+                }
+                PostProcessStep::GridWarp { scale } => {
+                    settings.grid_warp_enabled = true;
+                    settings.grid_warp_scale = scale;
+                }
+            }
+        }
+        settings
+    }
+
+    /// Check every setting against the range of the GUI control editing it
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        match *self {
+            PostProcessStep::ChannelRemap(_) => {}
+            PostProcessStep::Invert {
+                max_scene_value, ..
+            } => check_range("max_scene_value", max_scene_value, 0.1..=16.0)?,
+            PostProcessStep::HighlightRecovery { recovery } => {
+                check_range("recovery", recovery, 0.0..=1.0)?
+            }
+            PostProcessStep::Levels(levels) => {
+                for range in std::iter::once(levels.master).chain(levels.channels) {
+                    check_range("gamma", range.gamma, 0.1..=10.0)?;
+                }
+            }
+            PostProcessStep::Cdl(cdl) => {
+                check_range("gain", cdl.gain, 0.0..=4.0)?;
+                check_range("offset", cdl.offset, -0.2..=0.2)?;
+                check_range("lift", cdl.lift, -0.3..=0.3)?;
+                check_range("pivot", cdl.pivot, 0.0..=0.5)?;
+            }
+            PostProcessStep::SplitTone(tone) => {
+                check_range("shadow_hue", tone.shadow_hue, 0.0..=360.0)?;
+                check_range("shadow_saturation", tone.shadow_saturation, 0.0..=1.0)?;
+                check_range("highlight_hue", tone.highlight_hue, 0.0..=360.0)?;
+                check_range("highlight_saturation", tone.highlight_saturation, 0.0..=1.0)?;
+                check_range("balance", tone.balance, -1.0..=1.0)?;
+            }
+            PostProcessStep::HueRotate {
+                angle_degrees,
+                cycle_step,
+                ..
+            } => {
+                check_range("angle_degrees", angle_degrees, -180.0..=180.0)?;
+                check_range("cycle_step", cycle_step, -45.0..=45.0)?;
+            }
+            PostProcessStep::SmartSharpen {
+                strength, radius, ..
+            } => {
+                check_range("strength", strength, 0.0..=2.0)?;
+                check_range("radius", radius, 0.5..=5.0)?;
+            }
+            PostProcessStep::HistogramEqualization { strength, .. } => {
+                check_range("strength", strength, 0.0..=1.0)?
+            }
+            PostProcessStep::Border { width, style, .. } => {
+                check_range("width", width, 0..=50)?;
+                if let BorderStyle::Dashed { dash_len, gap_len } = style {
+                    check_range("dash_len", dash_len, 1..=50)?;
+                    check_range("gap_len", gap_len, 1..=50)?;
+                }
+            }
+            PostProcessStep::Contour {
+                luma_step,
+                line_width,
+                ..
+            } => {
+                check_range("luma_step", luma_step, 0.01..=0.25)?;
+                check_range("line_width", line_width, 0.0..=5.0)?;
+            }
+            PostProcessStep::NightVision(night_vision) => {
+                check_range("amplification", night_vision.amplification, 0.0..=20.0)?;
+                check_range("noise_level", night_vision.noise_level, 0.0..=0.5)?;
+                check_range(
+                    "vignette_strength",
+                    night_vision.vignette_strength,
+                    0.0..=1.0,
+                )?;
+                check_range(
+                    "phosphor_persistence",
+                    night_vision.phosphor_persistence,
+                    0.0..=0.95,
+                )?;
+            }
+            PostProcessStep::CelShade {
+                num_tones,
+                edge_threshold,
+                ..
+            } => {
+                check_range("num_tones", num_tones, 2..=16)?;
+                check_range("edge_threshold", edge_threshold, 0.0..=1.0)?;
+            }
+            PostProcessStep::StainedGlass {
+                num_regions,
+                border_thickness,
+                ..
+            } => {
+                check_range("num_regions", num_regions, 2..=2000)?;
+                check_range("border_thickness", border_thickness, 0..=8)?;
+            }
+            PostProcessStep::Crosshatch {
+                line_spacing,
+                line_width,
+                angles,
+                dark_threshold,
+            } => {
+                check_range("line_spacing", line_spacing, 2..=32)?;
+                check_range("line_width", line_width, 0.5..=4.0)?;
+                for angle in angles {
+                    check_range("angle", angle, -90.0..=90.0)?;
+                }
+                check_range("dark_threshold", dark_threshold, 0.0..=1.0)?;
+            }
+            PostProcessStep::Watercolor {
+                diffusion_steps,
+                wetness,
+                paper_texture_strength,
+                pigment_granulation,
+            } => {
+                check_range("diffusion_steps", diffusion_steps, 0..=64)?;
+                check_range("wetness", wetness, 0.0..=4.0)?;
+                check_range("paper_texture_strength", paper_texture_strength, 0.0..=1.0)?;
+                check_range("pigment_granulation", pigment_granulation, 0.0..=1.0)?;
+            }
+            PostProcessStep::Thermal { sensor_noise, .. } => {
+                check_range("sensor_noise", sensor_noise, 0.0..=0.2)?
+            }
+            PostProcessStep::Bokeh {
+                focus_depth,
+                aperture,
+                max_radius,
+                ..
+            } => {
+                check_range("focus_depth", focus_depth, 0.01..=1000.0)?;
+                check_range("aperture", aperture, 0.0..=50.0)?;
+                check_range("max_radius", max_radius, 1.0..=32.0)?;
+            }
+            PostProcessStep::TiltShift { focus_y, aperture } => {
+                check_range("focus_y", focus_y, 0.0..=1.0)?;
+                check_range("aperture", aperture, 0.0..=24.0)?;
+            }
+            PostProcessStep::NoiseReduction {
+                spatial_sigma,
+                range_sigma,
+            } => {
+                check_range("spatial_sigma", spatial_sigma, 0.5..=10.0)?;
+                check_range("range_sigma", range_sigma, 0.01..=0.5)?;
+            }
+            PostProcessStep::Kaleidoscope {
+                segments,
+                angle_offset,
+                zoom,
+                ..
+            } => {
+                ensure!(
+                    KALEIDOSCOPE_SEGMENTS.contains(&segments),
+                    "segments is {segments}, expected one of {KALEIDOSCOPE_SEGMENTS:?}"
+                );
+                check_range("angle_offset", angle_offset, 0.0..=360.0)?;
+                check_range("zoom", zoom, 0.25..=4.0)?;
+            }
+            PostProcessStep::PolarCoordinates {
+                angle_offset,
+                center,
+                ..
+            } => {
+                check_range("angle_offset", angle_offset, 0.0..=360.0)?;
+                for component in center {
+                    check_range("center", component, 0.0..=1.0)?;
+                }
+            }
+            PostProcessStep::Fisheye { fov, strength, .. } => {
+                check_range("fov", fov, 90.0..=220.0)?;
+                check_range("strength", strength, 0.0..=1.0)?;
+            }
+            PostProcessStep::ChromaticAberration {
+                max_shift, angle, ..
+            } => {
+                check_range("max_shift", max_shift, 0.0..=20.0)?;
+                check_range("angle", angle, 0.0..=360.0)?;
+            }
+            PostProcessStep::GridWarp { scale } => check_range("scale", scale, 0.0..=64.0)?,
+        }
+        Ok(())
+    }
+
+    /// The effect the step turns on
+    pub(crate) fn effect(&self) -> PostProcessEffect {
+        match self {
+            PostProcessStep::ChannelRemap(_) => PostProcessEffect::ChannelRemap,
+            PostProcessStep::Invert { .. } => PostProcessEffect::Invert,
+            PostProcessStep::HighlightRecovery { .. } => PostProcessEffect::HighlightRecovery,
+            PostProcessStep::Levels(_) => PostProcessEffect::Levels,
+            PostProcessStep::Cdl(_) => PostProcessEffect::Cdl,
+            PostProcessStep::SplitTone(_) => PostProcessEffect::SplitTone,
+            PostProcessStep::HueRotate { .. } => PostProcessEffect::HueRotate,
+            PostProcessStep::SmartSharpen { .. } => PostProcessEffect::SmartSharpen,
+            PostProcessStep::HistogramEqualization { .. } => {
+                PostProcessEffect::HistogramEqualization
+            }
+            PostProcessStep::Border { .. } => PostProcessEffect::Border,
+            PostProcessStep::Contour { .. } => PostProcessEffect::Contour,
+            PostProcessStep::NightVision(_) => PostProcessEffect::NightVision,
+            PostProcessStep::CelShade { .. } => PostProcessEffect::CelShade,
+            PostProcessStep::StainedGlass { .. } => PostProcessEffect::StainedGlass,
+            PostProcessStep::Crosshatch { .. } => PostProcessEffect::Crosshatch,
+            PostProcessStep::Watercolor { .. } => PostProcessEffect::Watercolor,
+            PostProcessStep::Thermal { .. } => PostProcessEffect::Thermal,
+            PostProcessStep::Bokeh { .. } => PostProcessEffect::Bokeh,
+            PostProcessStep::TiltShift { .. } => PostProcessEffect::TiltShift,
+            PostProcessStep::NoiseReduction { .. } => PostProcessEffect::NoiseReduction,
+            PostProcessStep::Kaleidoscope { .. } => PostProcessEffect::Kaleidoscope,
+            PostProcessStep::PolarCoordinates { .. } => PostProcessEffect::PolarCoordinates,
+            PostProcessStep::Fisheye { .. } => PostProcessEffect::Fisheye,
+            PostProcessStep::ChromaticAberration { .. } => PostProcessEffect::ChromaticAberration,
+            PostProcessStep::GridWarp { .. } => PostProcessEffect::GridWarp,
+        }
+    }
+
+    /// Name shown in the pass list of the GUI
+    pub(crate) fn label(&self) -> &'static str {
+        self.effect().label()
+    }
+
+    /// Enabled steps of the post-process settings
+    pub(crate) fn stack(settings: &PostProcessSettings) -> Vec<PostProcessStep> {
+        let mut stack = Vec::new();
+        if settings.bokeh_enabled {
+            stack.push(PostProcessStep::Bokeh {
+                focus_depth: settings.bokeh_focus_depth,
+                aperture: settings.bokeh_aperture,
+                max_radius: settings.bokeh_max_radius,
+                shape: settings.bokeh_shape,
+            });
+        }
+        if settings.grid_warp_enabled {
+            stack.push(PostProcessStep::GridWarp {
+                scale: settings.grid_warp_scale,
+            });
+        }
+        if settings.tilt_shift_enabled {
+            stack.push(PostProcessStep::TiltShift {
+                focus_y: settings.tilt_shift_focus,
+                aperture: settings.tilt_shift_aperture,
+            });
+        }
+        if settings.noise_reduction_enabled {
+            stack.push(PostProcessStep::NoiseReduction {
+                spatial_sigma: settings.noise_reduction_spatial_sigma,
+                range_sigma: settings.noise_reduction_range_sigma,
+            });
+        }
+        if settings.highlight_recovery > 0.0 {
+            stack.push(PostProcessStep::HighlightRecovery {
+                recovery: settings.highlight_recovery,
+            });
+        }
+        if settings.levels.enabled {
+            stack.push(PostProcessStep::Levels(settings.levels));
+        }
+        if settings.cdl.enabled {
+            stack.push(PostProcessStep::Cdl(settings.cdl));
+        }
+        if settings.channel_remap != ChannelRemap::IDENTITY {
+            stack.push(PostProcessStep::ChannelRemap(settings.channel_remap));
+        }
+        if settings.invert_enabled {
+            stack.push(PostProcessStep::Invert {
+                mode: settings.invert_mode,
+                max_scene_value: settings.invert_max_value,
+            });
+        }
+        if settings.split_tone.enabled {
+            stack.push(PostProcessStep::SplitTone(settings.split_tone));
+        }
+        if settings.hue_rotation != 0.0 || settings.hue_cycle_enabled {
+            stack.push(PostProcessStep::HueRotate {
+                angle_degrees: settings.hue_rotation,
+                cycle_enabled: settings.hue_cycle_enabled,
+                cycle_step: settings.hue_cycle_step,
+            });
+        }
+        if settings.kaleidoscope_enabled {
+            stack.push(PostProcessStep::Kaleidoscope {
+                segments: settings.kaleidoscope_segments,
+                angle_offset: settings.kaleidoscope_angle,
+                zoom: settings.kaleidoscope_zoom,
+                mode: settings.kaleidoscope_mode,
+            });
+        }
+        if settings.polar_enabled {
+            stack.push(PostProcessStep::PolarCoordinates {
+                mode: settings.polar_mode,
+                angle_offset: settings.polar_angle_offset,
+                center: settings.polar_center,
+            });
+        }
+        if settings.fisheye_enabled {
+            stack.push(PostProcessStep::Fisheye {
+                fov: settings.fisheye_fov,
+                strength: settings.fisheye_strength,
+                mode: settings.fisheye_mode,
+            });
+        }
+        if settings.chromatic_enabled {
+            stack.push(PostProcessStep::ChromaticAberration {
+                mode: settings.chromatic_mode,
+                max_shift: settings.chromatic_max_shift,
+                radial: settings.chromatic_radial,
+                angle: settings.chromatic_angle,
+            });
+        }
+        if settings.sharpen_strength > 0.0 {
+            stack.push(PostProcessStep::SmartSharpen {
+                strength: settings.sharpen_strength,
+                radius: settings.sharpen_radius,
+                reduce_halos: settings.sharpen_reduce_halos,
+            });
+        }
+        if settings.equalization_strength > 0.0 {
+            stack.push(PostProcessStep::HistogramEqualization {
+                mode: settings.equalization_mode,
+                strength: settings.equalization_strength,
+            });
+        }
+        if settings.border_width > 0 {
+            stack.push(PostProcessStep::Border {
+                width: settings.border_width,
+                color: settings.border_color,
+                style: settings.border_style,
+            });
+        }
+        if settings.contour_width > 0.0 {
+            stack.push(PostProcessStep::Contour {
+                luma_step: settings.contour_step,
+                line_color: settings.contour_color,
+                line_width: settings.contour_width,
+                mode: settings.contour_mode,
+            });
+        }
+        if settings.night_vision.enabled {
+            stack.push(PostProcessStep::NightVision(settings.night_vision));
+        }
+        if settings.cel_shade_enabled {
+            stack.push(PostProcessStep::CelShade {
+                num_tones: settings.cel_shade_tones,
+                edge_threshold: settings.cel_shade_edge_threshold,
+                edge_color: settings.cel_shade_edge_color,
+                mode: settings.cel_shade_mode,
+            });
+        }
+        if settings.stained_glass_enabled {
+            stack.push(PostProcessStep::StainedGlass {
+                num_regions: settings.stained_glass_regions,
+                border_thickness: settings.stained_glass_border_thickness,
+                border_color: settings.stained_glass_border_color,
+                seed: settings.stained_glass_seed,
+            });
+        }
+        if settings.crosshatch_enabled {
+            stack.push(PostProcessStep::Crosshatch {
+                line_spacing: settings.crosshatch_spacing,
+                line_width: settings.crosshatch_line_width,
+                angles: settings.crosshatch_angles,
+                dark_threshold: settings.crosshatch_dark_threshold,
+            });
+        }
+        if settings.watercolor_enabled {
+            stack.push(PostProcessStep::Watercolor {
+                diffusion_steps: settings.watercolor_steps,
+                wetness: settings.watercolor_wetness,
+                paper_texture_strength: settings.watercolor_paper,
+                pigment_granulation: settings.watercolor_granulation,
+            });
+        }
+        if settings.thermal_enabled {
+            stack.push(PostProcessStep::Thermal {
+                colormap: settings.thermal_colormap,
+                cold_color: settings.thermal_cold_color,
+                hot_color: settings.thermal_hot_color,
+                sensor_noise: settings.thermal_noise,
+            });
+        }
+        stack
+    }
+}
+
+/// Fail with a message naming the setting when `value` is outside `range`, or NaN
+fn check_range<T: PartialOrd + Display>(
+    name: &str,
+    value: T,
+    range: RangeInclusive<T>,
+) -> anyhow::Result<()> {
+    ensure!(
+        range.contains(&value),
+        "{name} is {value}, expected {} to {}",
+        range.start(),
+        range.end()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::image::{default_gradient_anchors, ChannelSource};
+
+    /// JSON schema of the scene files, generated from the types
+    fn scene_schema() -> String {
+        let schema = schemars::schema_for!(SceneDescription);
+        serde_json::to_string_pretty(&schema).unwrap() + "\n"
+    }
+
+    /// `schema.json` is checked in for editors and validators to point at.
+    /// Run with `UPDATE_GENERATED=1` to rewrite it after changing the scene types.
+    #[test]
+    fn schema_file_matches_the_types() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema.json");
+        let generated = scene_schema();
+        if std::env::var_os("UPDATE_GENERATED").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+        }
+        let schema = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            schema == generated,
+            "schema.json is out of date, run `UPDATE_GENERATED=1 cargo test`"
+        );
+    }
+
+    /// Check `value` against `schema`, for the keywords `schema.json` uses.
+    /// Objects may only hold the properties the schema lists, so a field serde skips
+    /// or renames differently from schemars fails too.
+    fn check_schema(value: &Value, schema: &Value, root: &Value, path: &str) -> Result<(), String> {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference
+                .strip_prefix("#/definitions/")
+                .expect("Only references to definitions are supported");
+            return check_schema(value, &root["definitions"][name], root, path);
+        }
+        for part in schema
+            .get("allOf")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            check_schema(value, part, root, path)?;
+        }
+        if let Some(variants) = schema.get("enum").and_then(Value::as_array) {
+            if !variants.contains(value) {
+                return Err(format!("{path}: {value} is not one of {variants:?}"));
+            }
+        }
+        if let Some(kinds) = schema.get("type") {
+            // A single type or a list of them, `["string", "null"]` for an `Option`
+            let kinds: Vec<&str> = match kinds {
+                Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).collect(),
+                kind => kind.as_str().into_iter().collect(),
+            };
+            let matches = kinds.iter().any(|&kind| match kind {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "number" => value.is_number(),
+                "integer" => value.is_u64() || value.is_i64(),
+                "boolean" => value.is_boolean(),
+                "string" => value.is_string(),
+                "null" => value.is_null(),
+                _ => panic!("Unsupported type {kind}"),
+            });
+            if !matches {
+                return Err(format!("{path}: {value} is not of type {kinds:?}"));
+            }
+        }
+        if let Some(number) = value.as_f64() {
+            let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+            if bound("minimum").is_some_and(|minimum| number < minimum)
+                || bound("maximum").is_some_and(|maximum| number > maximum)
+            {
+                return Err(format!("{path}: {number} is out of range"));
+            }
+        }
+        if let Some(items) = value.as_array() {
+            let count = |keyword: &str| schema.get(keyword).and_then(Value::as_u64);
+            if count("minItems").is_some_and(|min| (items.len() as u64) < min)
+                || count("maxItems").is_some_and(|max| (items.len() as u64) > max)
+            {
+                return Err(format!("{path}: {} items", items.len()));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_schema(item, item_schema, root, &format!("{path}[{i}]"))?;
+                }
+            }
+        }
+        if let Some(object) = value.as_object() {
+            for key in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let key = key.as_str().unwrap();
+                if !object.contains_key(key) {
+                    return Err(format!("{path}: missing {key}"));
+                }
+            }
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                for (key, property) in object {
+                    let Some(property_schema) = properties.get(key) else {
+                        return Err(format!("{path}.{key} is not in the schema"));
+                    };
+                    check_schema(property, property_schema, root, &format!("{path}.{key}"))?;
+                }
+            }
+        }
+        if let Some(choices) = schema.get("oneOf").and_then(Value::as_array) {
+            let errors: Vec<String> = choices
+                .iter()
+                .filter_map(|choice| check_schema(value, choice, root, path).err())
+                .collect();
+            if choices.len() - errors.len() != 1 {
+                return Err(format!(
+                    "{path}: {value} matches {} of the oneOf schemas, {errors:?}",
+                    choices.len() - errors.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Catches serde attributes schemars reads differently, with one step of each kind
+    #[test]
+    fn saved_scenes_match_the_schema() {
+        let schema: Value = serde_json::from_str(&scene_schema()).unwrap();
+
+        // One step of each kind
+        let settings = PostProcessSettings {
+            channel_remap: ChannelRemap {
+                r_src: ChannelSource::B,
+                b_src: ChannelSource::R,
+                ..ChannelRemap::IDENTITY
+            },
+            invert_enabled: true,
+            highlight_recovery: 0.5,
+            levels: LevelsParams {
+                enabled: true,
+                ..LevelsParams::default()
+            },
+            cdl: CdlParams {
+                enabled: true,
+                ..CdlParams::default()
+            },
+            split_tone: SplitToneParams::TEAL_AND_ORANGE,
+            hue_rotation: 30.0,
+            sharpen_strength: 0.5,
+            equalization_strength: 0.5,
+            border_width: 4,
+            border_style: BorderStyle::Dashed {
+                dash_len: 8,
+                gap_len: 4,
+            },
+            contour_width: 1.0,
+            night_vision: NightVisionParams {
+                enabled: true,
+                ..NightVisionParams::default()
+            },
+            cel_shade_enabled: true,
+            stained_glass_enabled: true,
+            crosshatch_enabled: true,
+            watercolor_enabled: true,
+            thermal_enabled: true,
+            thermal_colormap: ThermalColormap::Custom {
+                cold: [0.0, 0.0, 0.5],
+                warm: [0.5, 0.0, 0.0],
+                hot: [1.0, 1.0, 0.0],
+            },
+            bokeh_enabled: true,
+            tilt_shift_enabled: true,
+            noise_reduction_enabled: true,
+            kaleidoscope_enabled: true,
+            polar_enabled: true,
+            fisheye_enabled: true,
+            chromatic_enabled: true,
+            grid_warp_enabled: true,
+            ..PostProcessSettings::default()
+        };
+        let mut stack = PostProcessStep::stack(&settings);
+        assert_eq!(stack.len(), 25, "Not every kind of step is covered");
+        // The variants the steps above don't use
+        stack.extend([
+            PostProcessStep::Border {
+                width: 2,
+                color: [1.0; 4],
+                style: BorderStyle::Solid,
+            },
+            PostProcessStep::Border {
+                width: 2,
+                color: [1.0; 4],
+                style: BorderStyle::Gradient {
+                    inner_color: [1.0; 4],
+                    outer_color: [0.0, 0.0, 0.0, 1.0],
+                },
+            },
+            PostProcessStep::Thermal {
+                colormap: ThermalColormap::Ironbow,
+                cold_color: [0.0; 3],
+                hot_color: [1.0; 3],
+                sensor_noise: 0.0,
+            },
+        ]);
+
+        let samplers = [
+            SamplerKind::Uniform,
+            SamplerKind::Halton,
+            SamplerKind::BlueNoise,
+        ];
+        for (&render_mode, sampler) in RenderMode::ALL.iter().zip(samplers.into_iter().cycle()) {
+            let scene = SceneDescription {
+                render_mode,
+                tonemapper: TonemapperKind::Perceptual,
+                exposure_ev: -1.5,
+                sampler,
+                color_a: [0.1, 0.2, 0.3],
+                color_b: [1.0, 0.5, 0.0],
+                procedural_params: Value::Null,
+                post_process_stack: stack.clone(),
+                pass_order: default_pass_order(),
+                file_paths: SceneFiles {
+                    exemplar: Some(PathBuf::from("exemplar.png")),
+                    ..SceneFiles::default()
+                },
+            };
+            let value = serde_json::to_value(&scene).unwrap();
+            if let Err(e) = check_schema(&value, &schema, &schema, "scene") {
+                panic!("{e}");
+            }
+        }
+    }
+
+    #[test]
+    fn default_settings_are_in_range() {
+        let defaults = [
+            ProceduralParams::Mandelbrot(MandelbrotView::default()),
+            ProceduralParams::MultipointGradient(default_gradient_anchors()),
+            ProceduralParams::Lissajous(LissajousSettings::default()),
+            ProceduralParams::Starfield(StarfieldSettings::default()),
+            ProceduralParams::FractalFlame(FractalFlameSettings::default()),
+            ProceduralParams::ValueNoise(ValueNoiseSettings::default()),
+            ProceduralParams::Sky(SkySettings::default()),
+            ProceduralParams::Turbulence(TurbulenceSettings::default()),
+            ProceduralParams::Bricks(BrickSettings::default()),
+            ProceduralParams::WoodGrain(WoodGrainSettings::default()),
+            ProceduralParams::PoissonDisk(PoissonDiskSettings::default()),
+            ProceduralParams::Oscilloscope(OscilloscopeSettings::default()),
+            ProceduralParams::Worley(WorleySettings::default()),
+            ProceduralParams::NodeGraph(NodeGraph::default()),
+            ProceduralParams::Terrain(TerrainParams::default()),
+            ProceduralParams::Pcb(PcbSettings::default()),
+            ProceduralParams::TextureQuilting(TextureQuiltingSettings::default()),
+            ProceduralParams::Raymarcher(RaymarcherSettings::default()),
+            ProceduralParams::BrdfSpheres(BrdfGridSettings::default()),
+            ProceduralParams::Truchet(TruchetSettings::default()),
+            ProceduralParams::GrungeMap(GrungeParams::default()),
+            ProceduralParams::Spiral(SpiralSettings::default()),
+        ];
+        for params in defaults {
+            if let Err(e) = params.validate() {
+                panic!("{params:?}: {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_settings_fail_to_load() {
+        let scene = SceneDescription {
+            render_mode: RenderMode::PoissonDisk,
+            tonemapper: TonemapperKind::Perceptual,
+            exposure_ev: 0.0,
+            sampler: SamplerKind::Uniform,
+            color_a: [0.0; 3],
+            color_b: [1.0; 3],
+            procedural_params: serde_json::json!({ "min_distance": 0.0, "seed": 0 }),
+            post_process_stack: Vec::new(),
+            pass_order: default_pass_order(),
+            file_paths: SceneFiles::default(),
+        };
+        let path = std::env::temp_dir().join("pixels-egui-framebuffer-invalid-scene.json");
+        scene.save(&path).unwrap();
+        let error = SceneDescription::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        let message = format!("{error:#}");
+        assert!(
+            message.contains("min_distance is 0, expected 1 to 50"),
+            "{message}"
+        );
+
+        let scene = SceneDescription {
+            procedural_params: serde_json::json!({ "min_distance": 10.0, "seed": 0 }),
+            post_process_stack: vec![PostProcessStep::NoiseReduction {
+                spatial_sigma: 100.0,
+                range_sigma: 0.1,
+            }],
+            ..scene
+        };
+        scene.save(&path).unwrap();
+        let error = SceneDescription::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        let message = format!("{error:#}");
+        assert!(
+            message.contains("Invalid Noise Reduction step")
+                && message.contains("spatial_sigma is 100, expected 0.5 to 10"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn file_paths_are_saved() {
+        let file_paths = SceneFiles {
+            cubemap_hdri: Some(PathBuf::from("studio.exr")),
+            warp_map: Some(PathBuf::from("warp.exr")),
+            ..SceneFiles::default()
+        };
+        let scene = SceneDescription {
+            render_mode: RenderMode::CubemapCross,
+            tonemapper: TonemapperKind::Perceptual,
+            exposure_ev: 0.0,
+            sampler: SamplerKind::Uniform,
+            color_a: [0.0; 3],
+            color_b: [1.0; 3],
+            procedural_params: Value::Null,
+            post_process_stack: Vec::new(),
+            pass_order: default_pass_order(),
+            file_paths: file_paths.clone(),
+        };
+        let path = std::env::temp_dir().join("pixels-egui-framebuffer-file-paths.json");
+        scene.save(&path).unwrap();
+        let (loaded, _) = SceneDescription::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.file_paths, file_paths);
+
+        // Scenes saved before the field was added
+        let mut value = serde_json::to_value(&scene).unwrap();
+        value.as_object_mut().unwrap().remove("file_paths");
+        let loaded: SceneDescription = serde_json::from_value(value).unwrap();
+        assert_eq!(loaded.file_paths, SceneFiles::default());
+    }
+
+    #[test]
+    fn pass_order_is_saved_and_checked() {
+        let mut pass_order = default_pass_order();
+        pass_order.reverse();
+        pass_order[0].enabled = false;
+        let scene = SceneDescription {
+            render_mode: RenderMode::Gradient,
+            tonemapper: TonemapperKind::Perceptual,
+            exposure_ev: 0.0,
+            sampler: SamplerKind::Uniform,
+            color_a: [0.0; 3],
+            color_b: [1.0; 3],
+            procedural_params: Value::Null,
+            post_process_stack: Vec::new(),
+            pass_order: pass_order.clone(),
+            file_paths: SceneFiles::default(),
+        };
+        let path = std::env::temp_dir().join("pixels-egui-framebuffer-pass-order.json");
+        scene.save(&path).unwrap();
+        let (loaded, _) = SceneDescription::load(&path).unwrap();
+        assert_eq!(loaded.pass_order, pass_order);
+
+        // Every effect must be listed once
+        pass_order[1] = pass_order[2];
+        let scene = SceneDescription {
+            pass_order,
+            ..scene
+        };
+        scene.save(&path).unwrap();
+        let error = SceneDescription::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        let message = format!("{error:#}");
+        assert!(
+            message.contains("Invalid pass_order") && message.contains("2 times"),
+            "{message}"
+        );
+    }
+}
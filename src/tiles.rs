@@ -0,0 +1,174 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+use winit::event_loop::EventLoopProxy;
+
+use crate::constants::{RENDER_BUFFER_HEIGHT, RENDER_BUFFER_WIDTH, RENDER_TILE_SIZE};
+use crate::UserEvent;
+
+/// Block of the framebuffer rendered as a single job, with its linear RGBA pixels
+pub(crate) struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    pixels: Vec<f32>,
+}
+
+impl Tile {
+    /// Copy the pixels to their place in `buffer`
+    pub(crate) fn write_to(&self, buffer: &mut [f32], buffer_width: usize) {
+        for (row, pixels) in self.pixels.chunks_exact(self.width * 4).enumerate() {
+            let start = ((self.y + row) * buffer_width + self.x) * 4;
+            buffer[start..start + pixels.len()].copy_from_slice(pixels);
+        }
+    }
+}
+
+/// Progress of the last tiled render, shown in the GUI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RenderStatus {
+    Idle,
+    Rendering {
+        completed: usize,
+        total: usize,
+    },
+    Finished,
+    /// Stopped from the GUI, the tiles that didn't finish are left black
+    Cancelled,
+}
+
+/// Renders the framebuffer tile by tile on a rayon thread pool.
+/// Finished tiles are sent back through a channel, and `UserEvent::TilesReady`
+/// wakes up the event loop to collect them.
+pub(crate) struct TileRenderer {
+    pool: rayon::ThreadPool,
+    event_proxy: EventLoopProxy<UserEvent>,
+    // Cancellation token of the render in progress
+    cancel: Arc<AtomicBool>,
+    receiver: Option<Receiver<Tile>>,
+    status: RenderStatus,
+}
+
+impl TileRenderer {
+    pub(crate) fn new(event_proxy: EventLoopProxy<UserEvent>) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .thread_name(|index| format!("tile-render-{index}"))
+            .build()
+            .expect("Failed to start the tile render threads");
+        Self {
+            pool,
+            event_proxy,
+            cancel: Arc::new(AtomicBool::new(false)),
+            receiver: None,
+            status: RenderStatus::Idle,
+        }
+    }
+
+    pub(crate) fn status(&self) -> RenderStatus {
+        self.status
+    }
+
+    /// Drop the render in progress and queue every tile of `region` of the framebuffer,
+    /// `[x0, y0, x1, y1]` with the end exclusive.
+    /// `render_tile(x, y, width, height, cancel)` returns the tile's pixels,
+    /// or `None` when it noticed `cancel` was set before it finished.
+    pub(crate) fn start<F>(&mut self, region: [usize; 4], render_tile: F)
+    where
+        F: Fn(usize, usize, usize, usize, &AtomicBool) -> Option<Vec<f32>> + Send + Sync + 'static,
+    {
+        self.reset();
+        // A channel per render, so tiles still in flight from an older one get dropped
+        let (sender, receiver) = mpsc::channel();
+        let render_tile = Arc::new(render_tile);
+        let [x0, y0] = [region[0], region[1]];
+        let x1 = region[2].min(RENDER_BUFFER_WIDTH as usize);
+        let y1 = region[3].min(RENDER_BUFFER_HEIGHT as usize);
+
+        let mut total = 0;
+        for y in (y0..y1).step_by(RENDER_TILE_SIZE) {
+            for x in (x0..x1).step_by(RENDER_TILE_SIZE) {
+                let width = RENDER_TILE_SIZE.min(x1 - x);
+                let height = RENDER_TILE_SIZE.min(y1 - y);
+                let cancel = Arc::clone(&self.cancel);
+                let render_tile = Arc::clone(&render_tile);
+                let sender = sender.clone();
+                let event_proxy = self.event_proxy.clone();
+                self.pool.spawn(move || {
+                    if cancel.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let Some(pixels) = render_tile(x, y, width, height, &cancel) else {
+                        return;
+                    };
+                    let tile = Tile {
+                        x,
+                        y,
+                        width,
+                        pixels,
+                    };
+                    if sender.send(tile).is_ok() {
+                        // Fails once the event loop is gone, nothing left to draw to
+                        let _ = event_proxy.send_event(UserEvent::TilesReady);
+                    }
+                });
+                total += 1;
+            }
+        }
+
+        self.receiver = Some(receiver);
+        self.status = RenderStatus::Rendering {
+            completed: 0,
+            total,
+        };
+    }
+
+    /// Stop the render in progress, keeping the tiles already done
+    pub(crate) fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        if matches!(self.status, RenderStatus::Rendering { .. }) {
+            self.status = RenderStatus::Cancelled;
+        }
+    }
+
+    /// Stop the render in progress and forget about it, for when something else
+    /// is about to draw into the framebuffer
+    pub(crate) fn reset(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.cancel = Arc::new(AtomicBool::new(false));
+        self.receiver = None;
+        self.status = RenderStatus::Idle;
+    }
+
+    /// Tiles finished since the last call
+    pub(crate) fn receive(&mut self) -> Vec<Tile> {
+        let tiles: Vec<Tile> = match &self.receiver {
+            Some(receiver) => receiver.try_iter().collect(),
+            None => Vec::new(),
+        };
+        self.count_completed(tiles.len());
+        tiles
+    }
+
+    /// Block until the render in progress is finished, returning the tiles not received yet
+    pub(crate) fn wait(&mut self) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        while matches!(self.status, RenderStatus::Rendering { .. }) {
+            let Some(Ok(tile)) = self.receiver.as_ref().map(Receiver::recv) else {
+                break;
+            };
+            tiles.push(tile);
+            self.count_completed(1);
+        }
+        tiles
+    }
+
+    fn count_completed(&mut self, count: usize) {
+        if let RenderStatus::Rendering { completed, total } = &mut self.status {
+            *completed += count;
+            if *completed >= *total {
+                self.status = RenderStatus::Finished;
+            }
+        }
+    }
+}
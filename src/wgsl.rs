@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use pixels::wgpu;
+
+use crate::nodes::NodeId;
+
+/// Declarations put in front of the shader of every WGSL node. The user shader provides
+/// `fs_main`, reading the upstream image from `input_texture`.
+pub(crate) const WGSL_PRELUDE: &str = "\
+@group(0) @binding(0) var input_texture: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // (0, 0) on the top left corner of the image
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // A single triangle covering the whole image
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+";
+
+/// Linear ACEScg RGBA, unfiltered so it keeps values above 1
+const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+const BYTES_PER_PIXEL: u32 = 16;
+
+/// Run a future to completion on this thread. wgpu resolves the adapter and device
+/// requests and the error scopes right away on native, so this rarely parks.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(Thread);
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl Gpu {
+    /// Device of its own, the one of `Pixels` can't be shared with the application state
+    fn new() -> Result<Self, String> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .ok_or_else(|| "No GPU adapter available".to_owned())?;
+        let (device, queue) = block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("wgsl_nodes"),
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None,
+        ))
+        .map_err(|e| format!("Can't open the GPU: {e}"))?;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("wgsl_node_input"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("wgsl_node"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("wgsl_node_input"),
+            ..Default::default()
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            bind_group_layout,
+            pipeline_layout,
+            sampler,
+        })
+    }
+
+    /// Build the pipeline of a user shader, returning the compile errors if it's invalid
+    fn compile(&self, source: &str) -> Result<wgpu::RenderPipeline, String> {
+        // Errors are reported to the scope instead of panicking
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("wgsl_node"),
+                source: wgpu::ShaderSource::Wgsl(format!("{WGSL_PRELUDE}{source}").into()),
+            });
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("wgsl_node"),
+                layout: Some(&self.pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: TEXTURE_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            });
+        match block_on(self.device.pop_error_scope()) {
+            Some(error) => Err(error.to_string()),
+            None => Ok(pipeline),
+        }
+    }
+
+    /// Draw `input` through a pipeline and read the result back
+    fn run(
+        &self,
+        pipeline: &wgpu::RenderPipeline,
+        input: &[f32],
+        width: usize,
+        height: usize,
+    ) -> Vec<f32> {
+        let size = wgpu::Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        };
+        let texture = |label, usage| {
+            self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: TEXTURE_FORMAT,
+                usage,
+            })
+        };
+        let input_texture = texture(
+            "wgsl_node_input",
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        );
+        let output_texture = texture(
+            "wgsl_node_output",
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
+
+        let input_bytes: Vec<u8> = input.iter().flat_map(|value| value.to_ne_bytes()).collect();
+        self.queue.write_texture(
+            input_texture.as_image_copy(),
+            &input_bytes,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(width as u32 * BYTES_PER_PIXEL),
+                rows_per_image: None,
+            },
+            size,
+        );
+
+        let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("wgsl_node_input"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        // Rows of a texture copy are aligned to 256 bytes
+        let row_bytes = width as u32 * BYTES_PER_PIXEL;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_bytes = row_bytes.div_ceil(alignment) * alignment;
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wgsl_node_readback"),
+            size: padded_row_bytes as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("wgsl_node"),
+            });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("wgsl_node"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &output_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        encoder.copy_texture_to_buffer(
+            output_texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let mut output = vec![0.0; width * height * 4];
+        {
+            let mapped = slice.get_mapped_range();
+            for (row, padded) in output
+                .chunks_exact_mut(width * 4)
+                .zip(mapped.chunks_exact(padded_row_bytes as usize))
+            {
+                for (value, bytes) in row.iter_mut().zip(padded.chunks_exact(4)) {
+                    *value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                }
+            }
+        }
+        readback.unmap();
+        output
+    }
+}
+
+/// Compiles and runs the shaders of the WGSL nodes, on a GPU device opened on first use.
+/// Pipelines are kept until the shader of their node changes.
+#[derive(Default)]
+pub(crate) struct WgslRunner {
+    gpu: Option<Result<Gpu, String>>,
+    // Shader source and its pipeline, or why it didn't compile, by node
+    pipelines: HashMap<NodeId, (String, Result<wgpu::RenderPipeline, String>)>,
+}
+
+impl WgslRunner {
+    /// Run the shader of a node on its input. A shader that doesn't compile passes the
+    /// input through, its errors are available from `errors`.
+    pub(crate) fn run(
+        &mut self,
+        node: NodeId,
+        source: &str,
+        input: Vec<f32>,
+        width: usize,
+        height: usize,
+    ) -> Vec<f32> {
+        let gpu = match self.gpu.get_or_insert_with(Gpu::new) {
+            Ok(gpu) => gpu,
+            Err(e) => {
+                self.pipelines
+                    .insert(node, (source.to_owned(), Err(e.clone())));
+                return input;
+            }
+        };
+        let stale = self
+            .pipelines
+            .get(&node)
+            .is_none_or(|(compiled, _)| compiled != source);
+        if stale {
+            self.pipelines
+                .insert(node, (source.to_owned(), gpu.compile(source)));
+        }
+        match &self.pipelines[&node].1 {
+            Ok(pipeline) => gpu.run(pipeline, &input, width, height),
+            Err(_) => input,
+        }
+    }
+
+    /// Forget the nodes that aren't in the graph anymore
+    pub(crate) fn retain(&mut self, nodes: impl Fn(NodeId) -> bool) {
+        self.pipelines.retain(|&id, _| nodes(id));
+    }
+
+    /// Why the shader of each node didn't compile
+    pub(crate) fn errors(&self) -> HashMap<NodeId, String> {
+        self.pipelines
+            .iter()
+            .filter_map(|(&id, (_, pipeline))| {
+                pipeline.as_ref().err().map(|error| (id, error.clone()))
+            })
+            .collect()
+    }
+}